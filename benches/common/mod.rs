@@ -0,0 +1,53 @@
+use std::env;
+use std::sync::{Once, ONCE_INIT};
+
+use futures_executor::block_on;
+use locustdb::colgen::{ColumnGenerator, GenTable};
+use locustdb::{LocustDB, Options};
+
+static mut DB: Option<LocustDB> = None;
+static INIT: Once = ONCE_INIT;
+
+/// Database shared by all benchmarks of the binary, populated by `create_tables` on first use.
+/// The number of worker threads can be set with `LOCUSTDB_THREADS`.
+pub fn db(create_tables: fn(&LocustDB)) -> &'static LocustDB {
+    unsafe {
+        INIT.call_once(|| {
+            let mut opts = Options::default();
+            if let Ok(threads) = env::var("LOCUSTDB_THREADS") {
+                opts.threads = threads.parse().expect("LOCUSTDB_THREADS must be a positive integer");
+            }
+            let locustdb = LocustDB::new(&opts);
+            eprintln!("Synthesizing tables");
+            create_tables(&locustdb);
+            eprintln!("Done");
+            DB = Some(locustdb);
+        });
+        DB.as_ref().unwrap()
+    }
+}
+
+pub fn gen_table(db: &LocustDB,
+                 name: &str,
+                 partitions: usize,
+                 partition_size: usize,
+                 columns: Vec<(&str, Box<ColumnGenerator>)>) {
+    let _ = block_on(db.gen_table(GenTable {
+        name: name.to_string(),
+        partitions,
+        partition_size,
+        columns: columns.into_iter().map(|(name, generator)| (name.to_string(), generator)).collect(),
+    }));
+}
+
+/// Runs `query` and returns the number of rows scanned, panics if the query fails.
+pub fn run(db: &LocustDB, query: &str) -> usize {
+    match block_on(db.run_query(query, false, vec![])).unwrap().0 {
+        Ok(output) => output.stats.rows_scanned,
+        Err(err) => panic!("{} failed: {}", query, err),
+    }
+}
+
+pub fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|s| s.to_string()).collect()
+}
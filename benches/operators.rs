@@ -0,0 +1,81 @@
+#[macro_use]
+extern crate criterion;
+extern crate futures_executor;
+extern crate locustdb;
+
+mod common;
+
+use criterion::Criterion;
+use locustdb::colgen::*;
+use locustdb::LocustDB;
+
+// Each benchmark runs a query whose runtime is dominated by a single kind of operator on 1M rows in 16 partitions.
+
+fn create_tables(db: &LocustDB) {
+    common::gen_table(db, "ops", 16, 1 << 16, vec![
+        ("id", incrementing_int()),
+        ("x", int_uniform(0, 1 << 32)),
+        ("low", int_uniform(0, 16)),
+        ("mid", int_uniform(0, 100_000)),
+        ("str_low", string_weighted(common::strings(&["a", "b", "c", "d", "e", "f", "g", "h"]), vec![1.0; 8])),
+        ("str_high", random_hex_string(8)),
+    ]);
+}
+
+fn bench_queries(c: &mut Criterion, queries: &[(&str, &'static str)]) {
+    let db = common::db(create_tables);
+    for &(name, query) in queries {
+        c.bench_function(name, move |b| b.iter(|| common::run(db, query)));
+    }
+}
+
+fn filter(c: &mut Criterion) {
+    bench_queries(c, &[
+        ("filter/int_less_than", "SELECT sum(low) FROM ops WHERE x < 429496729;"),
+        ("filter/string_equals", "SELECT sum(low) FROM ops WHERE str_low = 'c';"),
+        ("filter/and_or", "SELECT sum(low) FROM ops WHERE (x < 2147483648 AND low = 3) OR str_low = 'a';"),
+        ("filter/select_rows", "SELECT id, x FROM ops WHERE low = 3 LIMIT 1000000;"),
+    ]);
+}
+
+fn sort(c: &mut Criterion) {
+    bench_queries(c, &[
+        ("sort/int", "SELECT x FROM ops ORDER BY x LIMIT 2000000;"),
+        ("sort/string", "SELECT str_high FROM ops ORDER BY str_high LIMIT 2000000;"),
+        ("sort/multiple_columns", "SELECT low, x FROM ops ORDER BY low, x DESC LIMIT 2000000;"),
+    ]);
+}
+
+fn top_n(c: &mut Criterion) {
+    bench_queries(c, &[
+        ("top_n/int", "SELECT id, x FROM ops ORDER BY x DESC LIMIT 100;"),
+        ("top_n/string", "SELECT id, str_high FROM ops ORDER BY str_high LIMIT 100;"),
+        ("top_n/multiple_columns", "SELECT id, low, x FROM ops ORDER BY low DESC, x LIMIT 100;"),
+    ]);
+}
+
+fn grouping(c: &mut Criterion) {
+    bench_queries(c, &[
+        ("grouping/dense_int", "SELECT low, count(0) FROM ops;"),
+        ("grouping/hash_int", "SELECT mid, sum(x) FROM ops;"),
+        ("grouping/string", "SELECT str_low, count(0), sum(x) FROM ops;"),
+        ("grouping/multiple_columns", "SELECT low, str_low, mid / 1000, count(0) FROM ops;"),
+    ]);
+}
+
+fn merge(c: &mut Criterion) {
+    bench_queries(c, &[
+        // All 100k groups of every partition have to be merged since the result is ordered by the grouping key
+        ("merge/aggregate", "SELECT mid, count(0) FROM ops ORDER BY mid LIMIT 100;"),
+        // The limit exceeds half a partition, so partitions are sorted in full and then merged
+        ("merge/sorted", "SELECT id, x FROM ops ORDER BY x LIMIT 100000;"),
+        ("merge/concatenate", "SELECT id FROM ops WHERE low < 8 LIMIT 10000000;"),
+    ]);
+}
+
+criterion_group! {
+    name = operators;
+    config = Criterion::default().sample_size(20);
+    targets = filter, sort, top_n, grouping, merge
+}
+criterion_main!(operators);
@@ -0,0 +1,85 @@
+extern crate futures_executor;
+extern crate locustdb;
+
+mod common;
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use locustdb::colgen::*;
+use locustdb::LocustDB;
+
+// Runs queries modeled after ClickBench on a generated table of web analytics events and reports the fastest and median
+// runtime of each. Queries can be selected by passing substrings of their names, the number of runs is set with
+// `LOCUSTDB_BENCH_RUNS`.
+
+const QUERIES: &[(&str, &str)] = &[
+    ("q00_count", "SELECT count(0) FROM hits;"),
+    ("q01_count_filtered", "SELECT count(0) FROM hits WHERE adv_engine_id <> 0;"),
+    ("q02_sum_avg", "SELECT sum(adv_engine_id), count(0), avg(resolution_width) FROM hits;"),
+    ("q03_count_distinct", "SELECT approx_count_distinct(user_id) FROM hits;"),
+    ("q04_group_filtered", "SELECT adv_engine_id, count(0) FROM hits WHERE adv_engine_id <> 0 ORDER BY count(0) DESC;"),
+    ("q05_group_count_distinct", "SELECT region_id, approx_count_distinct(user_id) FROM hits ORDER BY approx_count_distinct(user_id) DESC LIMIT 10;"),
+    ("q06_group_multiple_aggregates", "SELECT region_id, sum(adv_engine_id), count(0), avg(resolution_width) FROM hits ORDER BY count(0) DESC LIMIT 10;"),
+    ("q07_group_string", "SELECT search_phrase, count(0) FROM hits WHERE search_phrase <> '' ORDER BY count(0) DESC LIMIT 10;"),
+    ("q08_group_high_cardinality", "SELECT user_id, count(0) FROM hits ORDER BY count(0) DESC LIMIT 10;"),
+    ("q09_group_multiple_columns", "SELECT user_id, search_phrase, count(0) FROM hits ORDER BY count(0) DESC LIMIT 10;"),
+    ("q10_like", "SELECT count(0) FROM hits WHERE url LIKE '%google%';"),
+    ("q11_top_n_filtered", "SELECT search_phrase, event_time FROM hits WHERE search_phrase <> '' ORDER BY event_time LIMIT 10;"),
+    ("q12_top_n", "SELECT watch_id, resolution_width FROM hits ORDER BY watch_id DESC LIMIT 10;"),
+    ("q13_group_expression", "SELECT event_time / 60, count(0) FROM hits WHERE counter_id = 62 ORDER BY count(0) DESC LIMIT 10;"),
+    ("q14_select_filtered", "SELECT watch_id, url FROM hits WHERE counter_id = 62 AND region_id < 100 LIMIT 100000;"),
+];
+
+fn create_tables(db: &LocustDB) {
+    common::gen_table(db, "hits", 32, 1 << 16, vec![
+        ("watch_id", int_uniform(0, 1 << 62)),
+        ("user_id", int_uniform(0, 1_000_000)),
+        ("counter_id", int_uniform(0, 100)),
+        ("region_id", int_uniform(0, 10_000)),
+        ("event_time", splayed(1_372_636_800, 1)),
+        ("adv_engine_id", int_weighted(vec![0, 2, 3, 27, 52], vec![95.0, 2.0, 1.0, 1.0, 1.0])),
+        ("resolution_width", int_weighted(vec![1024, 1280, 1366, 1920, 2560], vec![10.0, 20.0, 30.0, 35.0, 5.0])),
+        ("search_phrase", string_weighted(
+            common::strings(&["", "locustdb", "column store", "analytics database", "rust", "query planner"]),
+            vec![80.0, 4.0, 4.0, 4.0, 4.0, 4.0])),
+        ("url", string_weighted(
+            common::strings(&["https://example.com/", "https://www.google.com/search", "https://news.example.org/",
+                              "https://github.com/", "https://maps.google.com/"]),
+            vec![40.0, 20.0, 20.0, 15.0, 5.0])),
+    ]);
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e3 + f64::from(duration.subsec_nanos()) / 1e6
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`
+    let filters = env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect::<Vec<_>>();
+    let runs = match env::var("LOCUSTDB_BENCH_RUNS").map(|runs| runs.parse::<usize>()) {
+        Ok(Ok(runs)) if runs > 0 => runs,
+        Ok(_) => panic!("LOCUSTDB_BENCH_RUNS must be a positive integer"),
+        Err(_) => 5,
+    };
+    let db = common::db(create_tables);
+
+    println!("{:<32} {:>12} {:>12} {:>14}", "query", "min (ms)", "median (ms)", "rows/s");
+    for &(name, query) in QUERIES {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(&filter[..])) {
+            continue;
+        }
+        // The first run is not measured, so that all partitions are loaded
+        let rows_scanned = common::run(db, query);
+        let mut durations = (0..runs)
+            .map(|_| {
+                let start = Instant::now();
+                common::run(db, query);
+                millis(start.elapsed())
+            })
+            .collect::<Vec<_>>();
+        durations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median = durations[durations.len() / 2];
+        println!("{:<32} {:>12.2} {:>12.2} {:>14.0}", name, durations[0], median, rows_scanned as f64 / median * 1e3);
+    }
+}
@@ -140,6 +140,8 @@ fn create_buffer(field_ident: &Ident, field_type: &Type) -> Stmt {
         parse_quote!(let #field_ident = self.buffer_provider.buffer_usize(#field_name);)
     } else if *field_type == parse_quote!(BufferRef<i64>) {
         parse_quote!(let #field_ident = self.buffer_provider.buffer_i64(#field_name);)
+    } else if *field_type == parse_quote!(BufferRef<OrderedF64>) {
+        parse_quote!(let #field_ident = self.buffer_provider.buffer_f64(#field_name);)
     } else if *field_type == parse_quote!(BufferRef<u32>) {
         parse_quote!(let #field_ident = self.buffer_provider.buffer_u32(#field_name);)
     } else if *field_type == parse_quote!(BufferRef<MergeOp>) {
@@ -148,6 +150,8 @@ fn create_buffer(field_ident: &Ident, field_type: &Type) -> Stmt {
         parse_quote!(let #field_ident = self.buffer_provider.buffer_premerge(#field_name);)
     } else if *field_type == parse_quote!(BufferRef<Scalar<i64>>) {
         parse_quote!(let #field_ident = self.buffer_provider.buffer_scalar_i64(#field_name);)
+    } else if *field_type == parse_quote!(BufferRef<Scalar<OrderedF64>>) {
+        parse_quote!(let #field_ident = self.buffer_provider.buffer_scalar_f64(#field_name);)
     } else if *field_type == parse_quote!(BufferRef<Scalar<String>>) {
         parse_quote!(let #field_ident = self.buffer_provider.buffer_scalar_string(#field_name);)
     } else if *field_type == parse_quote!(BufferRef<Scalar<&'static str>>) {
@@ -167,6 +171,8 @@ fn convert(expr: Expr, field_type: &Type) -> Expr {
         parse_quote!(#expr.usize().unwrap())
     } else if *field_type == parse_quote!(BufferRef<i64>) {
         parse_quote!(#expr.i64().unwrap())
+    } else if *field_type == parse_quote!(BufferRef<OrderedF64>) {
+        parse_quote!(#expr.f64().unwrap())
     } else if *field_type == parse_quote!(BufferRef<u32>) {
         parse_quote!(#expr.u32().unwrap())
     } else if *field_type == parse_quote!(BufferRef<MergeOp>) {
@@ -175,6 +181,8 @@ fn convert(expr: Expr, field_type: &Type) -> Expr {
         parse_quote!(#expr.premerge().unwrap())
     } else if *field_type == parse_quote!(BufferRef<Scalar<i64>>) {
         parse_quote!(#expr.scalar_i64().unwrap())
+    } else if *field_type == parse_quote!(BufferRef<Scalar<OrderedF64>>) {
+        parse_quote!(#expr.scalar_f64().unwrap())
     } else if *field_type == parse_quote!(BufferRef<Scalar<String>>) {
         parse_quote!(#expr.scalar_string().unwrap())
     } else if *field_type == parse_quote!(BufferRef<Scalar<&'static str>>) {
@@ -189,14 +197,21 @@ fn hash(field_ident: &Ident, field_type: &Type) -> Stmt {
         parse_quote!(hasher.input_str(#field_ident);)
     } else if *field_type == parse_quote!(usize) || *field_type == parse_quote!(i64) {
         parse_quote!(hasher.input(&#field_ident.to_ne_bytes());)
+    } else if *field_type == parse_quote!(OrderedF64) {
+        parse_quote!(hasher.input(&#field_ident.normalized_bits().to_ne_bytes());)
     } else if *field_type == parse_quote!(u8) {
         parse_quote!(hasher.input(&[#field_ident]);)
     } else if *field_type == parse_quote!(bool) {
         parse_quote!(hasher.input(&[#field_ident as u8]);)
-    } else if *field_type == parse_quote!(Aggregator) {
+    } else if *field_type == parse_quote!(Aggregator) || *field_type == parse_quote!(TimeUnit)
+        || *field_type == parse_quote!(Func2Type) {
         parse_quote!(hasher.input(&[#field_ident as u8]);)
     } else if *field_type == parse_quote!(TypedBufferRef) {
         parse_quote!(hasher.input(&#field_ident.buffer.i.to_ne_bytes());)
+    } else if *field_type == parse_quote!(Vec<TypedBufferRef>) {
+        parse_quote!(for buffer in &#field_ident { hasher.input(&buffer.buffer.i.to_ne_bytes()); })
+    } else if *field_type == parse_quote!(Vec<bool>) {
+        parse_quote!(for &b in &#field_ident { hasher.input(&[b as u8]); })
     } else {
         parse_quote!(hasher.input(&#field_ident.i.to_ne_bytes());)
     }
@@ -224,6 +239,8 @@ fn parse_type(field_ident: &Ident, type_def: String) -> Option<(Expr, Option<FnA
             parse_quote!(#provided_type_ident)
         } else if base == "i64" {
             parse_quote!(EncodingType::I64)
+        } else if base == "f64" {
+            parse_quote!(EncodingType::F64)
         } else if base == "u8" {
             parse_quote!(EncodingType::U8)
         } else {
@@ -198,13 +198,14 @@ fn types(t: &Ident) -> Option<Vec<Type>> {
         "IntegerNoU64" => Some(vec![Type::U8, Type::U16, Type::U32, Type::I64]),
         "Integer" => Some(vec![Type::U8, Type::U16, Type::U32, Type::U64, Type::I64]),
         "NullableInteger" => Some(vec![Type::NullableU8, Type::NullableU16, Type::NullableU32, Type::NullableI64]),
-        "Primitive" => Some(vec![Type::U8, Type::U16, Type::U32, Type::U64, Type::I64, Type::Str]),
+        "Primitive" => Some(vec![Type::U8, Type::U16, Type::U32, Type::U64, Type::I64, Type::F64, Type::Str]),
         "NullablePrimitive" => Some(vec![Type::NullableU8, Type::NullableU16, Type::NullableU32, Type::NullableI64, Type::NullableStr]),
-        "PrimitiveUSize" => Some(vec![Type::U8, Type::U16, Type::U32, Type::U64, Type::I64, Type::Str, Type::USize]),
+        "PrimitiveUSize" => Some(vec![Type::U8, Type::U16, Type::U32, Type::U64, Type::I64, Type::F64, Type::Str, Type::USize]),
         "PrimitiveNoU64" => Some(vec![Type::U8, Type::U16, Type::U32, Type::I64, Type::Str]),
         "Const" => Some(vec![Type::ScalarI64, Type::ScalarStr]),
         "ScalarI64" => Some(vec![Type::ScalarI64]),
         "ScalarStr" => Some(vec![Type::ScalarStr]),
+        "KeyedVal" => Some(vec![Type::KeyedVal]),
         _ => None,
     }
 }
@@ -216,6 +217,7 @@ enum Type {
     U32,
     U64,
     I64,
+    F64,
     Str,
 
     NullableU8,
@@ -227,6 +229,7 @@ enum Type {
     ScalarI64,
     ScalarStr,
     USize,
+    KeyedVal,
 }
 
 impl Type {
@@ -237,6 +240,7 @@ impl Type {
             Type::U32 => parse_quote!(EncodingType::U32),
             Type::U64 => parse_quote!(EncodingType::U64),
             Type::I64 => parse_quote!(EncodingType::I64),
+            Type::F64 => parse_quote!(EncodingType::F64),
             Type::Str => parse_quote!(EncodingType::Str),
             Type::NullableU8 => parse_quote!(EncodingType::NullableU8),
             Type::NullableU16 => parse_quote!(EncodingType::NullableU16),
@@ -246,6 +250,7 @@ impl Type {
             Type::USize => parse_quote!(EncodingType::USize),
             Type::ScalarI64 => parse_quote!(EncodingType::ScalarI64),
             Type::ScalarStr => parse_quote!(EncodingType::ScalarStr),
+            Type::KeyedVal => parse_quote!(EncodingType::KeyedVal),
         }
     }
 
@@ -256,6 +261,7 @@ impl Type {
             Type::U32 => parse_quote!( let #variable = #variable.buffer.u32(); ),
             Type::U64 => parse_quote!( let #variable = #variable.buffer.u64(); ),
             Type::I64 => parse_quote!( let #variable = #variable.buffer.i64(); ),
+            Type::F64 => parse_quote!( let #variable = #variable.buffer.f64(); ),
             Type::Str => parse_quote!( let #variable = #variable.buffer.str(); ),
             Type::NullableU8 => parse_quote!( let #variable = #variable.buffer.nullable_u8(); ),
             Type::NullableU16 => parse_quote!( let #variable = #variable.buffer.nullable_u16(); ),
@@ -265,6 +271,7 @@ impl Type {
             Type::USize => parse_quote!( let #variable = #variable.buffer.usize(); ),
             Type::ScalarI64 => parse_quote!( let #variable = #variable.buffer.scalar_i64(); ),
             Type::ScalarStr => parse_quote!( let #variable = #variable.buffer.scalar_str(); ),
+            Type::KeyedVal => parse_quote!( let #variable = #variable.buffer.keyed_val(); ),
         }
     }
 }
@@ -0,0 +1,264 @@
+#![feature(specialization)]
+
+#[macro_use]
+extern crate pyo3;
+
+use futures_executor::block_on;
+use locustdb::{JsonLoadOptions, LoadOptions, LocustDB, QueryOutput, Value};
+use pyo3::exceptions;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyFloat, PyList};
+
+
+/// Opens an in-memory database, or the database stored at `path`.
+#[pyfunction]
+fn open(py: Python, path: Option<String>, threads: Option<usize>) -> PyResult<Py<Database>> {
+    let mut builder = LocustDB::builder();
+    if let Some(path) = path {
+        builder = builder.db_path(&path);
+    }
+    if let Some(threads) = threads {
+        builder = builder.threads(threads);
+    }
+    let db = builder.build().map_err(PyErr::new::<exceptions::ValueError, _>)?;
+    Py::new(py, |_| Database { db })
+}
+
+#[pyclass]
+struct Database {
+    db: LocustDB,
+}
+
+#[pymethods]
+impl Database {
+    /// Loads a CSV file whose first line contains the column names into `table`.
+    fn load_csv(&self, path: &str, table: &str) -> PyResult<()> {
+        let gil = Python::acquire_gil();
+        let db = &self.db;
+        let result = gil.python().allow_threads(|| block_on(db.load_csv(LoadOptions::new(path, table))));
+        ingestion_result(result)
+    }
+
+    /// Loads a file with one JSON object per line into `table`.
+    fn load_json(&self, path: &str, table: &str) -> PyResult<()> {
+        let gil = Python::acquire_gil();
+        let db = &self.db;
+        let result = gil.python().allow_threads(|| block_on(db.load_json(JsonLoadOptions::new(path, table))));
+        ingestion_result(result)
+    }
+
+    /// Appends `data` to `table`, which is either a dict of column lists, a pandas DataFrame or a pyarrow Table.
+    fn ingest(&self, table: &str, data: &PyObjectRef) -> PyResult<()> {
+        let columns = to_columns(data)?;
+        let len = columns.first().map_or(0, |(_, values)| values.len());
+        if let Some((colname, values)) = columns.iter().find(|(_, values)| values.len() != len) {
+            return Err(PyErr::new::<exceptions::ValueError, _>(
+                format!("Column {} has {} values, expected {}", colname, values.len(), len)));
+        }
+        let mut rows = vec![Vec::with_capacity(columns.len()); len];
+        for (colname, values) in columns {
+            for (row, value) in rows.iter_mut().zip(values) {
+                row.push((colname.clone(), value));
+            }
+        }
+        let gil = Python::acquire_gil();
+        let db = &self.db;
+        gil.python().allow_threads(|| {
+            let writer = db.table_writer(table);
+            let written = writer.write_all(rows);
+            writer.flush();
+            written
+        }).map_err(PyErr::new::<exceptions::ValueError, _>)
+    }
+
+    /// Runs `query` and returns a dict that maps each column name to a list of its values.
+    fn query(&self, query: &str) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let output = self.run(py, query)?;
+        Ok(to_dict(py, &output)?.to_object(py))
+    }
+
+    /// Runs `query` and returns the result as a pandas DataFrame.
+    fn query_df(&self, query: &str) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let output = self.run(py, query)?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("columns", output.colnames.clone())?;
+        let df = py.import("pandas")?.call("DataFrame", (to_dict(py, &output)?,), Some(kwargs))?;
+        Ok(df.to_object(py))
+    }
+
+    /// Runs `query` and returns the result as a pyarrow Table, whose arrays are built from buffers without creating a
+    /// Python object for each value.
+    fn query_arrow(&self, query: &str) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        let output = self.run(py, query)?;
+        let pyarrow = py.import("pyarrow")?;
+        let arrays = (0..output.colnames.len())
+            .map(|i| arrow_array(py, pyarrow, &output, i))
+            .collect::<PyResult<Vec<_>>>()?;
+        let table = pyarrow.get("Table")?.call_method("from_arrays", (arrays, output.colnames.clone()), None)?;
+        Ok(table.to_object(py))
+    }
+}
+
+impl Database {
+    /// Runs `query` without holding the GIL, so that other Python threads continue while it executes.
+    fn run(&self, py: Python, query: &str) -> PyResult<QueryOutput> {
+        let db = &self.db;
+        match py.allow_threads(|| block_on(db.run_query(query, false, vec![]))) {
+            Ok((Ok(output), _)) => Ok(output),
+            Ok((Err(err), _)) => Err(PyErr::new::<exceptions::RuntimeError, _>(err.to_string())),
+            Err(_) => Err(PyErr::new::<exceptions::RuntimeError, _>("Query was cancelled")),
+        }
+    }
+}
+
+fn ingestion_result<E>(result: Result<Result<(), String>, E>) -> PyResult<()> {
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(PyErr::new::<exceptions::IOError, _>(err)),
+        Err(_) => Err(PyErr::new::<exceptions::RuntimeError, _>("Ingestion was cancelled")),
+    }
+}
+
+fn to_dict<'p>(py: Python<'p>, output: &QueryOutput) -> PyResult<&'p PyDict> {
+    let dict = PyDict::new(py);
+    for (i, colname) in output.colnames.iter().enumerate() {
+        dict.set_item(colname, column(py, output, i))?;
+    }
+    Ok(dict)
+}
+
+fn column<'p>(py: Python<'p>, output: &QueryOutput, index: usize) -> &'p PyList {
+    let values = output.rows.iter().map(|row| to_object(py, &row[index])).collect::<Vec<_>>();
+    PyList::new(py, &values)
+}
+
+/// Converts a column of `output` into a pyarrow Array of 64-bit integers, doubles, strings or nulls.
+/// Columns that mix integers and floats are converted to doubles.
+fn arrow_array(py: Python, pyarrow: &PyModule, output: &QueryOutput, index: usize) -> PyResult<PyObject> {
+    let values = output.rows.iter().map(|row| &row[index]).collect::<Vec<_>>();
+    let mut validity = vec![0u8; (values.len() + 7) / 8];
+    let (mut ints, mut floats, mut strings) = (0, 0, 0);
+    for (i, value) in values.iter().enumerate() {
+        match value {
+            Value::Null => continue,
+            Value::Int(_) => ints += 1,
+            Value::Float(_) => floats += 1,
+            Value::Str(_) => strings += 1,
+        }
+        validity[i / 8] |= 1 << (i % 8);
+    }
+    let null_count = values.len() - ints - floats - strings;
+    let validity = if null_count == 0 { py.None() } else { arrow_buffer(py, pyarrow, &validity)? };
+    let mut data = Vec::new();
+    let (datatype, buffers) = if strings > 0 {
+        if ints + floats > 0 {
+            return Err(PyErr::new::<exceptions::TypeError, _>(
+                format!("Column {} mixes strings and numbers", output.colnames[index])));
+        }
+        let mut offsets = Vec::with_capacity(4 * (values.len() + 1));
+        push_le(&mut offsets, 0, 4);
+        for value in &values {
+            if let Value::Str(string) = value {
+                data.extend_from_slice(string.as_bytes());
+            }
+            push_le(&mut offsets, data.len() as u64, 4);
+        }
+        ("string", vec![validity, arrow_buffer(py, pyarrow, &offsets)?, arrow_buffer(py, pyarrow, &data)?])
+    } else if floats > 0 {
+        for value in &values {
+            let float = match value {
+                Value::Int(int) => *int as f64,
+                Value::Float(float) => float.0,
+                _ => 0.0,
+            };
+            push_le(&mut data, float.to_bits(), 8);
+        }
+        ("float64", vec![validity, arrow_buffer(py, pyarrow, &data)?])
+    } else if ints > 0 {
+        for value in &values {
+            let int = match value {
+                Value::Int(int) => *int,
+                _ => 0,
+            };
+            push_le(&mut data, int as u64, 8);
+        }
+        ("int64", vec![validity, arrow_buffer(py, pyarrow, &data)?])
+    } else {
+        ("null", vec![py.None()])
+    };
+    let datatype = pyarrow.call(datatype, (), None)?.to_object(py);
+    let array = pyarrow.get("Array")?
+        .call_method("from_buffers", (datatype, values.len(), buffers, null_count), None)?;
+    Ok(array.to_object(py))
+}
+
+fn arrow_buffer(py: Python, pyarrow: &PyModule, bytes: &[u8]) -> PyResult<PyObject> {
+    Ok(pyarrow.call("py_buffer", (PyBytes::new(py, bytes),), None)?.to_object(py))
+}
+
+/// Appends the lowest `width` bytes of `bits` in little-endian order, which is the byte order of Arrow buffers.
+fn push_le(bytes: &mut Vec<u8>, bits: u64, width: usize) {
+    bytes.extend((0..width).map(|i| (bits >> (8 * i)) as u8));
+}
+
+fn to_object(py: Python, value: &Value) -> PyObject {
+    match value {
+        Value::Null => py.None(),
+        Value::Int(int) => int.to_object(py),
+        Value::Float(float) => float.0.to_object(py),
+        Value::Str(string) => string.to_object(py),
+    }
+}
+
+fn to_columns(data: &PyObjectRef) -> PyResult<Vec<(String, Vec<Value>)>> {
+    let data = if data.hasattr("to_pydict")? {
+        // pyarrow.Table
+        data.call_method("to_pydict", (), None)?
+    } else if data.hasattr("to_dict")? {
+        // pandas.DataFrame
+        data.call_method("to_dict", ("list",), None)?
+    } else {
+        data
+    };
+    let dict = data.cast_as::<PyDict>()?;
+    let mut columns = Vec::with_capacity(dict.len());
+    for (colname, values) in dict.iter() {
+        let values = values.iter()?
+            .map(|value| value.and_then(to_value))
+            .collect::<PyResult<Vec<_>>>()?;
+        columns.push((colname.extract::<String>()?, values));
+    }
+    Ok(columns)
+}
+
+fn to_value(value: &PyObjectRef) -> PyResult<Value> {
+    if value.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(float) = value.cast_as::<PyFloat>() {
+        // pandas represents missing values as NaN
+        if float.value().is_nan() {
+            Ok(Value::Null)
+        } else {
+            Ok(Value::Float(float.value().into()))
+        }
+    } else if let Ok(string) = value.extract::<String>() {
+        Ok(Value::Str(string))
+    } else if let Ok(int) = value.extract::<i64>() {
+        Ok(Value::Int(int))
+    } else {
+        Err(PyErr::new::<exceptions::TypeError, _>(format!("Unsupported value {}", value)))
+    }
+}
+
+#[pymodinit]
+fn pylocustdb(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_function!(open))?;
+    m.add_class::<Database>()?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use QueryError;
+use engine::Query;
+use syntax::expression::*;
+
+
+/// Determines how a role may access a column.
+#[derive(Debug, Clone, Copy)]
+pub enum ColumnAccess {
+    Allow,
+    Deny,
+    Mask(Masking),
+}
+
+/// Transformation applied to every value of a masked column before it is used by a query.
+#[derive(Debug, Clone, Copy)]
+pub enum Masking {
+    /// Replaces each value with a 64 bit hash.
+    Hash,
+    /// Replaces each value with a fixed placeholder string.
+    Redact,
+    /// Truncates strings to at most the specified number of characters.
+    Truncate(usize),
+}
+
+impl Masking {
+    fn apply(self, expr: Expr) -> Expr {
+        let func = match self {
+            Masking::Hash => Func1Type::Hash,
+            Masking::Redact => Func1Type::Redact,
+            Masking::Truncate(len) => Func1Type::Truncate(len),
+        };
+        Expr::func1(func, expr)
+    }
+}
+
+#[derive(Default)]
+pub struct AccessPolicy {
+    rules: HashMap<String, HashMap<(String, String), ColumnAccess>>,
+}
+
+impl AccessPolicy {
+    pub fn set(&mut self, role: &str, table: &str, column: &str, access: ColumnAccess) {
+        self.rules
+            .entry(role.to_string())
+            .or_insert_with(HashMap::default)
+            .insert((table.to_string(), column.to_string()), access);
+    }
+
+    pub fn access(&self, role: &str, table: &str, column: &str) -> ColumnAccess {
+        self.rules.get(role)
+            .and_then(|rules| rules.get(&(table.to_string(), column.to_string())))
+            .cloned()
+            .unwrap_or(ColumnAccess::Allow)
+    }
+
+    /// Removes all columns from `columns` that `role` is not allowed to access.
+    pub fn visible_columns(&self, role: &str, table: &str, columns: Vec<String>) -> Vec<String> {
+        columns.into_iter()
+            .filter(|column| match self.access(role, table, column) {
+                ColumnAccess::Deny => false,
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Rewrites all column references in `query` according to the permissions of `role`.
+    /// Masked columns are masked everywhere (including filters and sort keys) so their raw values can't be inferred.
+    pub fn apply(&self, role: &str, query: Query) -> Result<Query, QueryError> {
+        let table = query.table.clone();
+        let mut rewrite = |name: String| match self.access(role, &table, &name) {
+            ColumnAccess::Allow => Ok(Expr::ColName(name)),
+            ColumnAccess::Mask(masking) => Ok(masking.apply(Expr::ColName(name))),
+            ColumnAccess::Deny => Err(QueryError::PermissionDenied(
+                format!("role `{}` may not access column `{}` of table `{}`", role, name, table))),
+        };
+        let select = query.select.into_iter()
+            .map(|expr| expr.map_colnames(&mut rewrite))
+            .collect::<Result<Vec<_>, _>>()?;
+        let filter = query.filter.map_colnames(&mut rewrite)?;
+        let order_by = query.order_by.into_iter()
+            .map(|(expr, desc)| expr.map_colnames(&mut rewrite).map(|expr| (expr, desc)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Query {
+            select,
+            table: query.table,
+            filter,
+            order_by,
+            limit: query.limit,
+            distinct: query.distinct,
+            aliases: query.aliases,
+            rollup: query.rollup,
+            sum_overflow: query.sum_overflow,
+            sample: query.sample,
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use futures_executor::block_on;
+use locustdb::{LocustDB, Value};
+use rustyline;
+use rustyline::completion::Completer;
+
+/// Completes the word before the cursor with the names of tables and columns.
+pub struct NameCompleter<'a> {
+    locustdb: &'a LocustDB,
+}
+
+impl<'a> NameCompleter<'a> {
+    pub fn new(locustdb: &'a LocustDB) -> NameCompleter<'a> {
+        NameCompleter { locustdb }
+    }
+
+    // Names are read from the system tables on every completion so that new tables and columns are included
+    fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for query in &["SELECT name FROM _tables LIMIT 1000000;", "SELECT column_name FROM _columns LIMIT 1000000;"] {
+            if let Ok((Ok(output), _)) = block_on(self.locustdb.run_query(query, false, vec![])) {
+                names.extend(output.rows.into_iter().filter_map(|mut row| match row.pop() {
+                    Some(Value::Str(name)) => Some(name),
+                    _ => None,
+                }));
+            }
+        }
+        names
+    }
+}
+
+impl<'a> Completer for NameCompleter<'a> {
+    fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].char_indices()
+            .rev()
+            .find(|&(_, c)| !(c.is_alphanumeric() || c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return Ok((start, vec![]));
+        }
+        let mut candidates = self.names().into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
@@ -17,14 +17,20 @@ use locustdb::unit_fmt::*;
 use locustdb::LocustDB;
 use time::precise_time_ns;
 use clap::{Arg, App};
+use rustyline::error::ReadlineError;
+use std::env;
 
+use completion::NameCompleter;
+use print_results::{Format, PrintOptions};
+
+mod completion;
 mod print_results;
 mod fmt_table;
 
 fn main() {
     env_logger::init();
 
-    let mut options = locustdb::Options::default();
+    let options = locustdb::Options::default();
     let default_mem_limit_tables = format!("{}", options.mem_size_limit_tables / 1024 / 1024 / 1024);
     let default_readahead = format!("{}", options.readahead / 1024 / 1024);
     let help_threads = format!("Number of worker threads. [default: number of cores ({})]", options.threads);
@@ -37,6 +43,16 @@ fn main() {
             .long("db-path")
             .value_name("PATH")
             .takes_value(true))
+        .arg(Arg::with_name("snapshot")
+            .help("Query the snapshot directory at PATH without modifying it")
+            .long("snapshot")
+            .value_name("PATH")
+            .takes_value(true)
+            .conflicts_with_all(&["db-path", "load"]))
+        .arg(Arg::with_name("mmap")
+            .help("Memory-map the data of --snapshot instead of loading it up front")
+            .long("mmap")
+            .requires("snapshot"))
         .arg(Arg::with_name("load")
             .help("Load .csv or .csv.gz files into the database")
             .long("load")
@@ -71,6 +87,9 @@ fn main() {
         .arg(Arg::with_name("seq-disk-read")
             .help("Improves performance on HDD, can hurt performance on SSD.")
             .long("seq-disk-read"))
+        .arg(Arg::with_name("deterministic")
+            .help("Produce identical results for identical data, queries run on a single thread.")
+            .long("deterministic"))
         .arg(Arg::with_name("threads")
             .help(&help_threads)
             .long("threads")
@@ -84,6 +103,22 @@ fn main() {
             .help("Set ingestion schema for nyc taxi ride dataset")
             .long("trips")
             .conflicts_with("reduced-trips"))
+        .arg(Arg::with_name("format")
+            .help("Format of query results")
+            .long("format")
+            .value_name("FORMAT")
+            .possible_values(&["table", "csv", "json", "vertical"])
+            .default_value("table"))
+        .arg(Arg::with_name("null")
+            .help("String that null values are displayed as")
+            .long("null")
+            .value_name("STRING")
+            .default_value("null"))
+        .arg(Arg::with_name("precision")
+            .help("Number of digits displayed after the decimal point of floats")
+            .long("precision")
+            .value_name("DIGITS")
+            .takes_value(true))
         .get_matches();
 
     let files = matches.values_of("load").unwrap_or_default();
@@ -93,37 +128,48 @@ fn main() {
     let full_nyc = matches.is_present("trips");
     let db_path = matches.value_of("db-path");
     let file_count = files.len();
+    let print_options = PrintOptions {
+        format: Format::parse(matches.value_of("format").unwrap()).unwrap(),
+        null: matches.value_of("null").unwrap().to_string(),
+        precision: matches.value_of("precision").map(|digits| digits.parse()
+            .expect("Argument --precision must be a positive integer!")),
+    };
 
-    if matches.is_present("db-path") && !cfg!(feature = "enable_rocksdb") {
-        println!("WARNING: --db-path option passed, but RocksDB storage backend is not enabled in this build of LocustDB.");
+    let mut builder = LocustDB::builder();
+    if let Some(path) = db_path {
+        builder = builder.db_path(path);
     }
-
-    options.db_path = db_path.map(|x| x.to_string());
     if let Some(t) = matches.value_of("threads") {
-        options.threads = t.parse()
-            .expect("Argument --threads must be a positive integer!");
-    }
-    options.mem_size_limit_tables = matches
-        .value_of("mem-limit-tables").unwrap()
-        .parse::<usize>()
-        .map(|x| x * 1024 * 1024 * 1024)
-        .expect("Argument --mem-limit-tables must be a positive integer!");
-    options.readahead = matches
-        .value_of("readahead").unwrap()
-        .parse::<usize>()
-        .map(|x| x * 1024 * 1024)
-        .expect("Argument --readahead must be a positive integer!");
-    options.mem_lz4 = matches.is_present("mem-lz4");
-    if matches.is_present("seq-disk-read") {
-        options.seq_disk_read = true;
-        options.read_threads = 1;
-    }
-
-    if options.readahead > options.mem_size_limit_tables {
-        println!("WARNING: `mem-limit-tables` should be at least as large as `readahead`");
+        builder = builder.threads(t.parse()
+            .expect("Argument --threads must be a positive integer!"));
     }
+    builder = builder
+        .mem_size_limit_tables(matches
+            .value_of("mem-limit-tables").unwrap()
+            .parse::<usize>()
+            .map(|x| x * 1024 * 1024 * 1024)
+            .expect("Argument --mem-limit-tables must be a positive integer!"))
+        .readahead(matches
+            .value_of("readahead").unwrap()
+            .parse::<usize>()
+            .map(|x| x * 1024 * 1024)
+            .expect("Argument --readahead must be a positive integer!"))
+        .mem_lz4(matches.is_present("mem-lz4"))
+        .mmap(matches.is_present("mmap"))
+        .deterministic(matches.is_present("deterministic"))
+        .seq_disk_read(matches.is_present("seq-disk-read"));
 
-    let locustdb = locustdb::LocustDB::new(&options);
+    let locustdb = match matches.value_of("snapshot") {
+        Some(path) => builder.open_read_only(path),
+        None => builder.build(),
+    };
+    let locustdb = match locustdb {
+        Ok(locustdb) => locustdb,
+        Err(err) => {
+            println!("ERROR: {}", err);
+            return;
+        }
+    };
 
     let start_time = precise_time_ns();
     let mut loads = Vec::new();
@@ -155,7 +201,7 @@ fn main() {
     }
 
     table_stats(&locustdb);
-    repl(&locustdb);
+    repl(&locustdb, print_options);
 }
 
 fn table_stats(locustdb: &LocustDB) {
@@ -169,101 +215,191 @@ fn table_stats(locustdb: &LocustDB) {
     }
 }
 
-fn repl(locustdb: &LocustDB) {
-    let mut rl = rustyline::Editor::<()>::new();
-    rl.load_history(".locustdb_history").ok();
-    while let Ok(mut s) = rl.readline("locustdb> ") {
-        if let Some('\n') = s.chars().next_back() {
-            s.pop();
+fn repl(locustdb: &LocustDB, mut print_options: PrintOptions) {
+    let mut rl = rustyline::Editor::new();
+    rl.set_completer(Some(NameCompleter::new(locustdb)));
+    let history = history_path();
+    rl.load_history(&history).ok();
+    let mut statement = String::new();
+    loop {
+        let prompt = if statement.is_empty() { "locustdb> " } else { "       -> " };
+        let line = match rl.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C discards the statement that is being entered
+            Err(ReadlineError::Interrupted) => {
+                statement.clear();
+                continue;
+            }
+            Err(_) => break,
+        };
+        let line = line.trim_right();
+        if statement.is_empty() {
+            if line.trim() == "" {
+                continue;
+            }
+            if line == "exit" || line == "\\q" {
+                break;
+            }
         }
-        if let Some('\r') = s.chars().next_back() {
-            s.pop();
+        if !statement.is_empty() {
+            statement.push(' ');
         }
-        if s.trim() == "" {
+        statement.push_str(line);
+        // Statements can span multiple lines and end with `;`, meta commands consist of a single line
+        if !(statement.ends_with(';') || is_meta_command(&statement)) {
             continue;
         }
-        if s == "exit" {
-            break;
-        }
-        rl.add_history_entry(&s);
-        if !s.ends_with(';') {
-            s.push(';');
-        }
+        rl.add_history_entry(&statement);
+        rl.save_history(&history).ok();
+        execute(locustdb, &statement, &mut print_options);
+        statement.clear();
+    }
+    rl.save_history(&history).ok();
+}
 
-        let mut print_trace = false;
-        let mut explain = false;
-        let mut show = vec![];
-        let mut s: &str = &s;
-        if s.starts_with(":memtree") {
-            let depth = if s.starts_with(":memtree(") {
-                let end = s.find(')').unwrap();
-                s[9..end].parse::<usize>().expect("must pass integer to :memtree(x) command")
-            } else { 2 };
-            match block_on(locustdb.mem_tree(depth)) {
-                Ok(trees) => for tree in trees {
-                    println!("{}\n", &tree)
-                },
-                _ => println!("Error: Query execution was canceled!"),
-            }
-            continue;
+fn history_path() -> String {
+    match env::var("HOME") {
+        Ok(home) => format!("{}/.locustdb_history", home),
+        Err(_) => ".locustdb_history".to_string(),
+    }
+}
+
+fn is_meta_command(line: &str) -> bool {
+    line.starts_with('\\')
+        || line.starts_with(":memtree")
+        || line.starts_with(":restore")
+        || line.starts_with(":recover")
+}
+
+fn execute(locustdb: &LocustDB, statement: &str, print_options: &mut PrintOptions) {
+    let mut s = statement.to_string();
+    if s.starts_with('\\') {
+        s = match meta_command(&s, print_options) {
+            Some(query) => query,
+            None => return,
+        };
+    }
+    if !s.ends_with(';') {
+        s.push(';');
+    }
+
+    let mut print_trace = false;
+    let mut explain = false;
+    let mut show = vec![];
+    let mut s: &str = &s;
+    if s.starts_with(":memtree") {
+        let depth = if s.starts_with(":memtree(") {
+            let end = s.find(')').unwrap();
+            s[9..end].parse::<usize>().expect("must pass integer to :memtree(x) command")
+        } else { 2 };
+        match block_on(locustdb.mem_tree(depth)) {
+            Ok(trees) => for tree in trees {
+                println!("{}\n", &tree)
+            },
+            _ => println!("Error: Query execution was canceled!"),
         }
-        if s.starts_with(":restore") {
-            let start = precise_time_ns();
-            match block_on(locustdb.bulk_load()) {
-                Ok(trees) => {
-                    println!("Restored DB from disk in {}",
-                             ns((precise_time_ns() - start) as usize));
-                    for tree in trees {
-                        println!("{}\n", &tree)
-                    }
+        return;
+    }
+    if s.starts_with(":restore") {
+        let start = precise_time_ns();
+        match block_on(locustdb.bulk_load()) {
+            Ok(trees) => {
+                println!("Restored DB from disk in {}",
+                         ns((precise_time_ns() - start) as usize));
+                for tree in trees {
+                    println!("{}\n", &tree)
                 }
-                _ => println!("Error: Query execution was canceled!"),
             }
-            continue;
-        }
-        if s.starts_with(":explain") {
-            explain = true;
-            s = &s[9..];
+            _ => println!("Error: Query execution was canceled!"),
         }
-        if s.starts_with(":trace") {
-            print_trace = true;
-            s = &s[7..];
+        return;
+    }
+    if s.starts_with(":explain") {
+        explain = true;
+        s = &s[9..];
+    }
+    if s.starts_with(":trace") {
+        print_trace = true;
+        s = &s[7..];
+    }
+    if s.starts_with(":show") {
+        show = if s.starts_with(":show(") {
+            let end = s.find(')').unwrap();
+            let partition = s[6..end].parse::<usize>().expect("must pass integer to :show(x) command");
+            s = &s[(end + 2)..];
+            vec![partition]
+        } else {
+            s = &s[6..];
+            vec![0]
         }
-        if s.starts_with(":show") {
-            show = if s.starts_with(":show(") {
-                let end = s.find(')').unwrap();
-                let partition = s[6..end].parse::<usize>().expect("must pass integer to :show(x) command");
-                s = &s[(end + 2)..];
-                vec![partition]
-            } else {
-                s = &s[6..];
-                vec![0]
+    }
+    if s.starts_with(":recover") {
+        locustdb.recover();
+        return;
+    }
+    if s.starts_with(":ast") {
+        println!("{}", locustdb.ast(&s[5..]));
+        return;
+    }
+
+    let query = locustdb.run_query(s, explain, show);
+    match block_on(query) {
+        Ok((result, trace)) => {
+            if print_trace {
+                trace.print();
+            }
+            match result {
+                Ok(output) => print_results::print_query_result(&output, print_options),
+                Err(mut fail) => print_error(&fail),
             }
         }
-        if s.starts_with(":recover") {
-            locustdb.recover();
-            continue;
+        _ => println!("Error: Query execution was canceled!"),
+    }
+}
+
+/// Translates `\d` to `SHOW TABLES` and `\d table` to `DESCRIBE table`, and changes the output format. Prints help
+/// for all other commands.
+fn meta_command(command: &str, print_options: &mut PrintOptions) -> Option<String> {
+    let words = command.trim_right_matches(';').split_whitespace().collect::<Vec<_>>();
+    match (words[0], words.get(1)) {
+        ("\\d", None) | ("\\dt", None) => return Some("SHOW TABLES;".to_string()),
+        ("\\d", Some(table)) => return Some(format!("DESCRIBE {};", table)),
+        ("\\x", None) => {
+            print_options.format = match print_options.format {
+                Format::Vertical => Format::Table,
+                _ => Format::Vertical,
+            };
+            println!("Output format is {:?}.", print_options.format);
+            return None;
         }
-        if s.starts_with(":ast") {
-            println!("{}", locustdb.ast(&s[5..]));
-            continue;
+        ("\\format", Some(format)) => if let Some(format) = Format::parse(format) {
+            print_options.format = format;
+            return None;
+        },
+        ("\\null", Some(null)) => {
+            print_options.null = null.to_string();
+            return None;
         }
-
-        let query = locustdb.run_query(s, explain, show);
-        match block_on(query) {
-            Ok((result, trace)) => {
-                if print_trace {
-                    trace.print();
-                }
-                match result {
-                    Ok(output) => print_results::print_query_result(&output),
-                    Err(mut fail) => print_error(&fail),
-                }
-            }
-            _ => println!("Error: Query execution was canceled!"),
+        ("\\precision", Some(&"off")) => {
+            print_options.precision = None;
+            return None;
         }
+        ("\\precision", Some(digits)) => if let Ok(digits) = digits.parse() {
+            print_options.precision = Some(digits);
+            return None;
+        },
+        (command, _) => if command != "\\?" {
+            println!("Unknown command {}", command);
+        },
     }
-    rl.save_history(".locustdb_history").ok();
+    println!("\\d                    list tables");
+    println!("\\d <table>            list columns of <table>");
+    println!("\\x                    toggle vertical output");
+    println!("\\format <format>      set output format (table, csv, json or vertical)");
+    println!("\\null <string>        set string displayed for null values");
+    println!("\\precision <digits>   set digits displayed after the decimal point of floats, or off");
+    println!("\\q                    quit");
+    None
 }
 
 fn print_error(fail: &locustdb::QueryError) {
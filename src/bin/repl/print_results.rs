@@ -2,27 +2,100 @@ use fmt_table::fmt_table;
 use locustdb::*;
 use locustdb::unit_fmt::*;
 
-pub fn print_query_result(results: &QueryOutput) {
-    let rt = results.stats.runtime_ns;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Aligned columns.
+    Table,
+    Csv,
+    /// One JSON object per row and line.
+    Json,
+    /// One line per column of each row, for results with many columns.
+    Vertical,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_ref() {
+            "table" => Some(Format::Table),
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "vertical" => Some(Format::Vertical),
+            _ => None,
+        }
+    }
+}
+
+pub struct PrintOptions {
+    pub format: Format,
+    /// Representation of null values, except in JSON.
+    pub null: String,
+    /// Number of digits after the decimal point of floats, all digits if `None`.
+    pub precision: Option<usize>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> PrintOptions {
+        PrintOptions {
+            format: Format::Table,
+            null: "null".to_string(),
+            precision: None,
+        }
+    }
+}
 
-    println!();
+pub fn print_query_result(results: &QueryOutput, options: &PrintOptions) {
+    let rt = results.stats.runtime_ns;
+    let mut stats = String::new();
     for (query_plan, count) in &results.query_plans {
-        println!("Query plan in {} batches{}", count, query_plan)
+        stats.push_str(&format!("Query plan in {} batches{}\n", count, query_plan));
+    }
+    stats.push_str(&format!("Scanned {} rows in {} ({:.2} rows/s)!",
+                            short_scale(results.stats.rows_scanned as f64),
+                            ns(rt as usize),
+                            billion(results.stats.rows_scanned as f64 / rt as f64)));
+    if results.stats.sampling_factor < 1.0 {
+        stats.push_str(&format!(" Sampled {:.2}% of the table.", results.stats.sampling_factor * 100.0));
+    }
+
+    match options.format {
+        Format::Table | Format::Vertical => {
+            println!();
+            println!("{}", stats);
+            if options.format == Format::Table {
+                println!("\n{}", format_results(&results.colnames, &results.rows, options));
+            } else {
+                println!("\n{}", format_vertical(&results.colnames, &results.rows, options));
+            }
+            println!();
+        }
+        // Only the result is written to stdout so that it can be piped into other programs
+        Format::Csv => {
+            eprintln!("{}", stats);
+            println!("{}", format_csv(&results.colnames, &results.rows, options));
+        }
+        Format::Json => {
+            eprintln!("{}", stats);
+            for row in &results.rows {
+                println!("{}", format_json(&results.colnames, row, options));
+            }
+        }
     }
-    println!("Scanned {} rows in {} ({:.2} rows/s)!",
-             short_scale(results.stats.rows_scanned as f64),
-             ns(rt as usize),
-             billion(results.stats.rows_scanned as f64 / rt as f64));
-    println!("\n{}", format_results(&results.colnames, &results.rows));
-    println!();
 }
 
-fn format_results(colnames: &[String], rows: &[Vec<Value>]) -> String {
+fn format_value(val: &Value, options: &PrintOptions) -> String {
+    match (val, options.precision) {
+        (Value::Null, _) => options.null.clone(),
+        (Value::Float(float), Some(precision)) => format!("{:.*}", precision, float.0),
+        _ => format!("{}", val),
+    }
+}
+
+fn format_results(colnames: &[String], rows: &[Vec<Value>], options: &PrintOptions) -> String {
     let strcolnames: Vec<&str> = colnames.iter().map(|s| s as &str).collect();
     let formattedrows: Vec<Vec<String>> = rows.iter()
         .map(|row| {
             row.iter()
-                .map(|val| format!("{}", val))
+                .map(|val| format_value(val, options))
                 .collect()
         })
         .collect();
@@ -32,3 +105,70 @@ fn format_results(colnames: &[String], rows: &[Vec<Value>]) -> String {
     fmt_table(&strcolnames, &strrows)
 }
 
+fn format_vertical(colnames: &[String], rows: &[Vec<Value>], options: &PrintOptions) -> String {
+    let width = colnames.iter().map(|colname| colname.chars().count()).max().unwrap_or(0);
+    let mut result = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        result.push(format!("-[ RECORD {} ]{}", i + 1, "-".repeat(width)));
+        for (colname, val) in colnames.iter().zip(row) {
+            result.push(format!("{:2$} | {}", colname, format_value(val, options), width));
+        }
+    }
+    result.join("\n")
+}
+
+fn format_csv(colnames: &[String], rows: &[Vec<Value>], options: &PrintOptions) -> String {
+    let mut lines = vec![colnames.iter().map(|colname| csv_field(colname)).collect::<Vec<_>>().join(",")];
+    for row in rows {
+        let fields = row.iter()
+            .map(|val| match val {
+                Value::Str(s) => csv_field(s),
+                _ => csv_field(&format_value(val, options)),
+            })
+            .collect::<Vec<_>>();
+        lines.push(fields.join(","));
+    }
+    lines.join("\n")
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn format_json(colnames: &[String], row: &[Value], options: &PrintOptions) -> String {
+    let fields = colnames.iter().zip(row)
+        .map(|(colname, val)| {
+            let val = match val {
+                Value::Null => "null".to_string(),
+                Value::Str(s) => json_string(s),
+                // JSON has no representation for infinity and NaN
+                Value::Float(float) if !float.0.is_finite() => "null".to_string(),
+                _ => format_value(val, options),
+            };
+            format!("{}:{}", json_string(colname), val)
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -0,0 +1,323 @@
+extern crate serde_json;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::TcpStream;
+use std::thread;
+
+use time::precise_time_ns;
+use self::serde_json::Value as Json;
+
+use QueryError;
+use QueryResult;
+use engine::{Aggregator, Query};
+use engine::query_task::{QueryOutput, QueryStats, row_types};
+use ingest::json_loader;
+use ingest::raw_val::RawVal;
+use syntax::expression::{Expr, Func2Type};
+use syntax::limit::LimitClause;
+use syntax::parser;
+
+
+/// Coordinator for a cluster of nodes that each hold a shard of the data and make it available with `server::Server`.
+/// Queries are run by all nodes on their own shard and the results are merged by the coordinator.
+///
+/// Supported are queries that select rows, `DISTINCT` queries and aggregations whose aggregates are `SUM`, `COUNT`,
+/// `MIN` or `MAX`. Results can only be ordered by selected expressions. Other queries are rejected before they are sent
+/// to the nodes, since their results cannot be merged from the results of the shards.
+pub struct Cluster {
+    nodes: Vec<String>,
+}
+
+impl Cluster {
+    /// Nodes are given by the address their server listens on, e.g. `10.0.0.1:8080`.
+    pub fn new(nodes: Vec<String>) -> Cluster {
+        Cluster { nodes }
+    }
+
+    pub fn run_query(&self, query: &str) -> QueryResult {
+        let start_time_ns = precise_time_ns();
+        let plan = MergePlan::new(&parser::parse_query(query)?)?;
+        let requests = self.nodes.iter()
+            .map(|node| {
+                let node = node.clone();
+                let query = query.to_string();
+                thread::spawn(move || run_partial(&node, &query))
+            })
+            .collect::<Vec<_>>();
+
+        let mut colnames: Option<Vec<String>> = None;
+        let mut rows = Vec::new();
+        let mut rows_scanned = 0;
+        let mut partitions_pruned = 0;
+        let mut sampling_factors = Vec::new();
+        for request in requests {
+            let output = request.join().map_err(|_| fatal!("Request thread panicked"))??;
+            if let Some(ref expected) = colnames {
+                if *expected != output.colnames {
+                    bail!(QueryError::RemoteError, "Nodes returned columns {:?} and {:?}", expected, output.colnames);
+                }
+            }
+            if colnames.is_none() {
+                colnames = Some(output.colnames);
+            }
+            rows.extend(output.rows);
+            rows_scanned += output.stats.rows_scanned;
+            partitions_pruned += output.stats.partitions_pruned;
+            sampling_factors.push(output.stats.sampling_factor);
+        }
+        // Assumes that all nodes store a similar number of rows
+        let sampling_factor = if sampling_factors.is_empty() {
+            1.0
+        } else {
+            sampling_factors.iter().sum::<f64>() / sampling_factors.len() as f64
+        };
+
+        let colnames = colnames.unwrap_or_default();
+        let rows = plan.merge(&colnames, rows)?;
+        Ok(QueryOutput {
+            coltypes: row_types(colnames.len(), &rows),
+            colnames,
+            rows,
+            query_plans: Default::default(),
+            plan_graphs: vec![],
+            stats: QueryStats {
+                runtime_ns: precise_time_ns() - start_time_ns,
+                rows_scanned,
+                partitions_pruned,
+                sampling_factor,
+            },
+        })
+    }
+}
+
+/// How the results of the nodes are combined into the result of the query.
+struct MergePlan {
+    /// Aggregator of each result column, `None` for grouping columns.
+    aggregators: Vec<Option<Aggregator>>,
+    distinct: bool,
+    /// Position of the result column and whether it is sorted in descending order.
+    order_by: Vec<(usize, bool)>,
+    limit: LimitClause,
+}
+
+impl MergePlan {
+    fn new(query: &Query) -> Result<MergePlan, QueryError> {
+        if query.rollup {
+            bail!(QueryError::NotImplemented, "GROUP BY ROLLUP is not supported by clusters");
+        }
+        let mut grouping = Vec::new();
+        let mut aggregated = Vec::new();
+        for (i, expr) in query.select.iter().enumerate() {
+            match merge_aggregator(expr)? {
+                Some(aggregator) => aggregated.push((i, Some(aggregator))),
+                None => grouping.push((i, None)),
+            }
+        }
+        // Aggregates follow the grouping columns in the result of aggregations
+        grouping.extend(aggregated);
+        let mut positions = vec![0; grouping.len()];
+        for (position, &(i, _)) in grouping.iter().enumerate() {
+            positions[i] = position;
+        }
+
+        let mut order_by = Vec::new();
+        for (expr, desc) in &query.order_by {
+            let expr_str = format!("{:?}", expr);
+            let selected = query.select.iter().position(|selected| format!("{:?}", selected) == expr_str)
+                .or_else(|| match expr {
+                    Expr::ColName(name) => query.aliases.iter().position(|alias| alias.as_ref() == Some(name)),
+                    _ => None,
+                });
+            match selected {
+                Some(i) => order_by.push((positions[i], *desc)),
+                // Nodes would return the rows they rank highest, which the coordinator cannot rank among each other
+                None => bail!(QueryError::NotImplemented, "ORDER BY {:?} must be selected when querying clusters", expr),
+            }
+        }
+
+        Ok(MergePlan {
+            aggregators: grouping.into_iter().map(|(_, aggregator)| aggregator).collect(),
+            distinct: query.distinct,
+            order_by,
+            limit: query.limit.clone(),
+        })
+    }
+
+    fn merge(&self, colnames: &[String], rows: Vec<Vec<RawVal>>) -> Result<Vec<Vec<RawVal>>, QueryError> {
+        let mut order_by = self.order_by.clone();
+        let mut rows = if self.distinct || self.aggregators.iter().any(Option::is_some) {
+            let merged = self.merge_groups(rows)?;
+            if order_by.is_empty() {
+                order_by = (0..colnames.len()).filter(|&i| self.aggregator(i).is_none()).map(|i| (i, false)).collect();
+            }
+            merged
+        } else {
+            rows
+        };
+        rows.sort_by(|left, right| {
+            order_by.iter()
+                .map(|&(i, desc)| if desc { right[i].cmp(&left[i]) } else { left[i].cmp(&right[i]) })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(rows.into_iter()
+            .skip(self.limit.offset as usize)
+            .take(self.limit.limit as usize)
+            .collect())
+    }
+
+    /// Combines rows that have the same values in all grouping columns.
+    fn merge_groups(&self, rows: Vec<Vec<RawVal>>) -> Result<Vec<Vec<RawVal>>, QueryError> {
+        let mut groups = HashMap::<Vec<RawVal>, usize>::default();
+        let mut merged = Vec::<Vec<RawVal>>::new();
+        for row in rows {
+            let key = row.iter().enumerate()
+                .filter(|&(i, _)| self.aggregator(i).is_none())
+                .map(|(_, value)| value.clone())
+                .collect::<Vec<_>>();
+            let existing = groups.get(&key).cloned();
+            match existing {
+                Some(index) => for (i, value) in row.into_iter().enumerate() {
+                    if let Some(aggregator) = self.aggregator(i) {
+                        let accumulator = mem::replace(&mut merged[index][i], RawVal::Null);
+                        merged[index][i] = combine(aggregator, accumulator, value)?;
+                    }
+                },
+                None => {
+                    groups.insert(key, merged.len());
+                    merged.push(row);
+                }
+            }
+        }
+        Ok(merged)
+    }
+
+    fn aggregator(&self, column: usize) -> Option<Aggregator> {
+        self.aggregators.get(column).cloned().unwrap_or(None)
+    }
+}
+
+/// Aggregator that combines the values of a selected expression returned by different nodes, `None` if the
+/// expression contains no aggregates.
+fn merge_aggregator(expr: &Expr) -> Result<Option<Aggregator>, QueryError> {
+    match expr {
+        // Sums and counts of shards add up to those of all rows
+        Expr::Aggregate(Aggregator::Sum, _) => Ok(Some(Aggregator::Sum)),
+        Expr::Aggregate(Aggregator::Count, _) => Ok(Some(Aggregator::Count)),
+        Expr::Aggregate(Aggregator::Min, _) => Ok(Some(Aggregator::Min)),
+        Expr::Aggregate(Aggregator::Max, _) => Ok(Some(Aggregator::Max)),
+        // `AVG` is parsed into the quotient of a sum and a count, which nodes return already divided
+        Expr::Func2(Func2Type::Divide, sum, count) if is_aggregate(sum, Aggregator::Sum)
+            && is_aggregate(count, Aggregator::Count) =>
+            Err(QueryError::NotImplemented("AVG is not supported by clusters".to_string())),
+        Expr::Window(_) => Err(QueryError::NotImplemented("Window functions are not supported by clusters".to_string())),
+        _ => if Query::extract_aggregators(expr, &mut vec![]).1.is_empty() {
+            Ok(None)
+        } else {
+            bail!(QueryError::NotImplemented, "Aggregate {:?} is not supported by clusters", expr)
+        },
+    }
+}
+
+fn is_aggregate(expr: &Expr, aggregator: Aggregator) -> bool {
+    match expr {
+        Expr::Aggregate(a, _) => *a == aggregator,
+        _ => false,
+    }
+}
+
+fn combine(aggregator: Aggregator, left: RawVal, right: RawVal) -> Result<RawVal, QueryError> {
+    match (aggregator, left, right) {
+        (_, RawVal::Null, value) | (_, value, RawVal::Null) => Ok(value),
+        (Aggregator::Min, left, right) => Ok(if right < left { right } else { left }),
+        (Aggregator::Max, left, right) => Ok(if right > left { right } else { left }),
+        (_, left, right) => add(left, right),
+    }
+}
+
+fn add(left: RawVal, right: RawVal) -> Result<RawVal, QueryError> {
+    match (left, right) {
+        (RawVal::Null, value) | (value, RawVal::Null) => Ok(value),
+        (RawVal::Int(left), RawVal::Int(right)) => match left.checked_add(right) {
+            Some(sum) => Ok(RawVal::Int(sum)),
+            None => Err(QueryError::Overflow("Sum of the results of all nodes exceeds the range of 64-bit integers".to_string())),
+        },
+        (RawVal::Int(left), RawVal::Float(right)) => Ok(RawVal::Float((left as f64 + right.0).into())),
+        (RawVal::Float(left), RawVal::Int(right)) => Ok(RawVal::Float((left.0 + right as f64).into())),
+        (RawVal::Float(left), RawVal::Float(right)) => Ok(RawVal::Float((left.0 + right.0).into())),
+        (left, right) => Err(fatal!("Cannot add {:?} and {:?}", left, right)),
+    }
+}
+
+/// Runs `query` on the shard of `node` with `POST /partial`.
+fn run_partial(node: &str, query: &str) -> QueryResult {
+    post(node, "/partial", query)
+        .map_err(|err| err.to_string())
+        .and_then(|response| parse_output(&response))
+        .map_err(|err| QueryError::RemoteError(format!("{}: {}", node, err)))
+}
+
+fn post(addr: &str, path: &str, body: &str) -> io::Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    // Responses to HTTP/1.0 requests are not chunked and end when the connection is closed
+    write!(stream, "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}", path, addr, body.len(), body)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+fn parse_output(response: &str) -> Result<QueryOutput, String> {
+    let body = match response.find("\r\n\r\n") {
+        Some(end_of_headers) => &response[end_of_headers + 4..],
+        None => return Err("Malformed response".to_string()),
+    };
+    let mut json = match serde_json::from_str(body) {
+        Ok(Json::Object(json)) => json,
+        Ok(_) => return Err("Response is not a JSON object".to_string()),
+        Err(err) => return Err(format!("Failed to parse response: {}", err)),
+    };
+    if let Some(Json::String(error)) = json.remove("error") {
+        return Err(error);
+    }
+    let colnames = match json.remove("colnames") {
+        Some(Json::Array(colnames)) => colnames.into_iter()
+            .map(|colname| match colname {
+                Json::String(colname) => Ok(colname),
+                _ => Err("Column names must be strings".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err("Response has no column names".to_string()),
+    };
+    let rows = match json.remove("rows") {
+        Some(Json::Array(rows)) => rows.into_iter()
+            .map(|row| match row {
+                Json::Array(row) => Ok(row.into_iter().map(json_loader::to_raw_val).collect()),
+                _ => Err("Rows must be arrays".to_string()),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err("Response has no rows".to_string()),
+    };
+    let stat = |name: &str| json.get("stats")
+        .and_then(|stats| stats.get(name))
+        .and_then(Json::as_u64)
+        .unwrap_or(0) as usize;
+    Ok(QueryOutput {
+        coltypes: row_types(colnames.len(), &rows),
+        colnames,
+        rows,
+        query_plans: Default::default(),
+        plan_graphs: vec![],
+        stats: QueryStats {
+            runtime_ns: 0,
+            rows_scanned: stat("rows_scanned"),
+            partitions_pruned: stat("partitions_pruned"),
+            sampling_factor: json.get("stats")
+                .and_then(|stats| stats.get("sampling_factor"))
+                .and_then(Json::as_f64)
+                .unwrap_or(1.0),
+        },
+    })
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use disk_store::interface::PartitionID;
+use ingest::schema::{ColumnSchema, ColumnType, EncodingHint};
+
+
+/// Definitions of all tables and views and manifests of the partitions of tables, which are stored in a text file
+/// separately from the column data. The catalog restores tables that have no partitions yet and views after a restart
+/// and can be inspected by tools.
+///
+/// Each line of the file is a tab separated record:
+///
+/// ```text
+/// table       <table>
+/// column      <table> <column> <type> <NULL|NOT NULL> <encoding>
+/// retention   <table> <column> <max age in seconds>
+/// generated   <table> <column> <VIRTUAL|STORED> <expression>
+/// partition   <table> <id> <rows> <column>,<column>,...
+/// view        <view>  <query>
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Catalog {
+    pub tables: BTreeMap<String, TableEntry>,
+    /// SQL of the query that defines each view.
+    pub views: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableEntry {
+    /// Column types declared with `CREATE TABLE`.
+    pub columns: Vec<(String, ColumnSchema)>,
+    /// Integer column that holds the timestamp of rows and the age in seconds at which rows are dropped.
+    pub retention: Option<(String, u64)>,
+    /// Generated columns in the order in which they were added.
+    pub generated: Vec<GeneratedEntry>,
+    pub partitions: Vec<PartitionEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedEntry {
+    pub name: String,
+    /// SQL expression that computes the column.
+    pub sql: String,
+    pub stored: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionEntry {
+    pub id: PartitionID,
+    pub len: usize,
+    pub columns: Vec<String>,
+}
+
+impl Catalog {
+    /// Reads the catalog file at `path`, which is empty if the file does not exist.
+    pub fn read(path: &Path) -> Result<Catalog, String> {
+        if !path.exists() {
+            return Ok(Catalog::default());
+        }
+        let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read catalog {:?}: {}", path, e))?;
+        Catalog::parse(&contents)
+    }
+
+    /// Replaces the catalog file at `path`. The catalog is written to a temporary file first, so that the file is
+    /// never left incomplete.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.serialize()).map_err(|e| format!("Failed to write catalog {:?}: {}", tmp, e))?;
+        fs::rename(&tmp, path).map_err(|e| format!("Failed to write catalog {:?}: {}", path, e))
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut lines = Vec::new();
+        for (name, table) in &self.tables {
+            lines.push(format!("table\t{}", name));
+            for &(ref column, schema) in &table.columns {
+                lines.push(format!("column\t{}\t{}\t{:?}\t{}\t{:?}",
+                                   name, column, schema.column_type,
+                                   if schema.nullable { "NULL" } else { "NOT NULL" },
+                                   schema.encoding));
+            }
+            if let Some((ref column, max_age)) = table.retention {
+                lines.push(format!("retention\t{}\t{}\t{}", name, column, max_age));
+            }
+            for generated in &table.generated {
+                lines.push(format!("generated\t{}\t{}\t{}\t{}",
+                                   name, generated.name, if generated.stored { "STORED" } else { "VIRTUAL" },
+                                   generated.sql));
+            }
+            for partition in &table.partitions {
+                lines.push(format!("partition\t{}\t{}\t{}\t{}",
+                                   name, partition.id, partition.len, partition.columns.join(",")));
+            }
+        }
+        for (name, sql) in &self.views {
+            lines.push(format!("view\t{}\t{}", name, sql));
+        }
+        let mut serialized = lines.join("\n");
+        serialized.push('\n');
+        serialized
+    }
+
+    pub fn parse(contents: &str) -> Result<Catalog, String> {
+        let mut catalog = Catalog::default();
+        for (line_num, line) in contents.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || format!("Invalid line {} of catalog: {}", line_num + 1, line);
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() < 2 {
+                return Err(invalid());
+            }
+            if fields[0] == "view" {
+                if fields.len() != 3 {
+                    return Err(invalid());
+                }
+                catalog.views.insert(fields[1].to_string(), fields[2].to_string());
+                continue;
+            }
+            let table = catalog.tables.entry(fields[1].to_string()).or_insert_with(TableEntry::default);
+            match (fields[0], fields.len()) {
+                ("table", 2) => {}
+                ("column", 6) => {
+                    let column_type = match fields[3] {
+                        "Integer" => ColumnType::Integer,
+                        "Float" => ColumnType::Float,
+                        "String" => ColumnType::String,
+                        "StringList" => ColumnType::StringList,
+                        _ => return Err(invalid()),
+                    };
+                    let nullable = match fields[4] {
+                        "NULL" => true,
+                        "NOT NULL" => false,
+                        _ => return Err(invalid()),
+                    };
+                    let encoding = match fields[5] {
+                        "Auto" => EncodingHint::Auto,
+                        "NoHex" => EncodingHint::NoHex,
+                        _ => return Err(invalid()),
+                    };
+                    let schema = ColumnSchema::new(column_type).nullable(nullable).encoding(encoding);
+                    table.columns.push((fields[2].to_string(), schema));
+                }
+                ("retention", 4) => {
+                    let max_age = fields[3].parse::<u64>().map_err(|_| invalid())?;
+                    table.retention = Some((fields[2].to_string(), max_age));
+                }
+                ("generated", 5) => {
+                    let stored = match fields[3] {
+                        "STORED" => true,
+                        "VIRTUAL" => false,
+                        _ => return Err(invalid()),
+                    };
+                    table.generated.push(GeneratedEntry { name: fields[2].to_string(), sql: fields[4].to_string(), stored });
+                }
+                ("partition", 5) => {
+                    let id = fields[2].parse::<PartitionID>().map_err(|_| invalid())?;
+                    let len = fields[3].parse::<usize>().map_err(|_| invalid())?;
+                    let columns = fields[4].split(',').filter(|column| !column.is_empty()).map(str::to_string).collect();
+                    table.partitions.push(PartitionEntry { id, len, columns });
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(catalog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_roundtrip() {
+        let mut catalog = Catalog::default();
+        catalog.tables.insert("empty".to_string(), TableEntry::default());
+        catalog.tables.insert("events".to_string(), TableEntry {
+            columns: vec![("ts".to_string(), ColumnSchema::new(ColumnType::Integer).nullable(false))],
+            retention: Some(("ts".to_string(), 86400)),
+            generated: vec![GeneratedEntry { name: "hour".to_string(), sql: "ts / 3600".to_string(), stored: true }],
+            partitions: vec![PartitionEntry { id: 3, len: 100, columns: vec!["hour".to_string(), "ts".to_string()] }],
+        });
+        catalog.views.insert("recent".to_string(), "SELECT ts FROM events WHERE ts > 0".to_string());
+        let serialized = catalog.serialize();
+        assert_eq!(serialized.lines().next(), Some("table\tempty"));
+        assert_eq!(Catalog::parse(&serialized), Ok(catalog));
+        assert!(Catalog::parse("partition\tevents\tx\t1\tts").is_err());
+        assert!(Catalog::parse("view\trecent").is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
+use ingest::raw_val::RawVal;
 use mem_store::column::Column;
+use mem_store::catalog::ColumnStats;
 use scheduler::inner_locustdb::InnerLocustDB;
 
 
@@ -10,10 +12,29 @@ pub trait DiskStore: Sync + Send + 'static {
     fn load_column_range(&self, start: PartitionID, end: PartitionID, column_name: &str, ldb: &InnerLocustDB);
     fn bulk_load(&self, ldb: &InnerLocustDB);
     fn store_partition(&self, partition: PartitionID, tablename: &str, columns: &[Arc<Column>]);
+    /// Appends a row of `tablename` to the write-ahead log and returns its sequence number.
+    fn append_wal(&self, tablename: &str, row: &[(String, RawVal)]) -> WalSeq;
+    /// All rows in the write-ahead log in the order they were appended.
+    fn load_wal(&self) -> Vec<WalEntry>;
+    /// Stores a partition created from rows of the write-ahead log and removes all rows of `tablename` up to
+    /// `wal_seq` from the log in the same write.
+    fn store_wal_partition(&self, partition: PartitionID, tablename: &str, columns: &[Arc<Column>], wal_seq: WalSeq);
+    /// Removes a partition and the columns with the given names.
+    fn delete_partition(&self, partition: PartitionID, column_names: &[String]);
+    /// Removes all rows of `tablename` from the write-ahead log.
+    fn delete_wal(&self, tablename: &str);
 }
 
 pub type PartitionID = u64;
 
+pub type WalSeq = u64;
+
+pub struct WalEntry {
+    pub seq: WalSeq,
+    pub tablename: String,
+    pub row: Vec<(String, RawVal)>,
+}
+
 pub struct PartitionMetadata {
     pub id: PartitionID,
     pub tablename: String,
@@ -24,4 +45,5 @@ pub struct PartitionMetadata {
 pub struct ColumnMetadata {
     pub name: String,
     pub size_bytes: usize,
+    pub stats: ColumnStats,
 }
@@ -1,5 +1,8 @@
+pub mod catalog;
 pub mod interface;
 pub mod noop_storage;
 
 #[cfg(feature = "enable_rocksdb")]
-pub mod rocksdb;
\ No newline at end of file
+pub mod rocksdb;
+#[cfg(feature = "enable_rocksdb")]
+pub mod snapshot;
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use ingest::raw_val::RawVal;
 use mem_store::column::Column;
 use disk_store::interface::*;
 use scheduler::inner_locustdb::InnerLocustDB;
@@ -14,4 +15,9 @@ impl DiskStore for NoopStorage {
     fn load_column_range(&self, _: PartitionID, _: PartitionID, _: &str, _: &InnerLocustDB) {}
     fn bulk_load(&self, _: &InnerLocustDB) {}
     fn store_partition(&self, _: PartitionID, _: &str, _: &[Arc<Column>]) {}
+    fn append_wal(&self, _: &str, _: &[(String, RawVal)]) -> WalSeq { 0 }
+    fn load_wal(&self) -> Vec<WalEntry> { Vec::new() }
+    fn store_wal_partition(&self, _: PartitionID, _: &str, _: &[Arc<Column>], _: WalSeq) {}
+    fn delete_partition(&self, _: PartitionID, _: &[String]) {}
+    fn delete_wal(&self, _: &str) {}
 }
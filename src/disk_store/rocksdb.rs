@@ -2,6 +2,7 @@ extern crate rocksdb;
 extern crate capnp;
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::str;
 
 use byteorder::{ByteOrder, BigEndian};
@@ -11,10 +12,14 @@ use self::rocksdb::*;
 use storage_format_capnp::*;
 
 use disk_store::interface::*;
+use ingest::raw_val::RawVal;
 use mem_store::column::{Column, DataSection, DataSource};
 use scheduler::inner_locustdb::InnerLocustDB;
 use mem_store::codec::CodecOp;
+use mem_store::catalog::ColumnStats;
+use mem_store::zone_map::ZoneMap;
 use engine::data_types::EncodingType as Type;
+use engine::data_types::OrderedF64;
 
 use time;
 use unit_fmt::*;
@@ -22,6 +27,7 @@ use unit_fmt::*;
 
 pub struct RocksDB {
     db: DB,
+    next_wal_seq: AtomicUsize,
 }
 
 impl RocksDB {
@@ -46,8 +52,14 @@ impl RocksDB {
         let db = DB::open_cf_descriptors(&options, path, vec![
             ColumnFamilyDescriptor::new("metadata", Options::default()),
             ColumnFamilyDescriptor::new("partitions", partitions_options),
+            ColumnFamilyDescriptor::new("wal", Options::default()),
         ]).unwrap();
-        RocksDB { db }
+        let next_wal_seq = db.iterator_cf(db.cf_handle("wal").unwrap(), IteratorMode::Start)
+            .unwrap()
+            .map(|(key, _)| deserialize_wal_key(&key).0 + 1)
+            .max()
+            .unwrap_or(0);
+        RocksDB { db, next_wal_seq: AtomicUsize::new(next_wal_seq as usize) }
     }
 
     fn metadata(&self) -> ColumnFamily {
@@ -57,6 +69,24 @@ impl RocksDB {
     fn partitions(&self) -> ColumnFamily {
         self.db.cf_handle("partitions").unwrap()
     }
+
+    fn wal(&self) -> ColumnFamily {
+        self.db.cf_handle("wal").unwrap()
+    }
+
+    fn partition_batch(&self, partition: PartitionID, tablename: &str, columns: &[Arc<Column>]) -> WriteBatch {
+        let mut tx = WriteBatch::default();
+        let mut key = [0; 8];
+        BigEndian::write_u64(&mut key, partition as u64);
+        let md = serialize_meta_data(tablename, columns);
+        tx.put_cf(self.metadata(), &key, &md).unwrap();
+        for column in columns {
+            let key = column_key(partition, column.name());
+            let data = serialize_column(column.as_ref());
+            tx.put_cf(self.partitions(), &key, &data).unwrap();
+        }
+        tx
+    }
 }
 
 impl DiskStore for RocksDB {
@@ -115,21 +145,131 @@ impl DiskStore for RocksDB {
     }
 
     fn store_partition(&self, partition: PartitionID, tablename: &str, columns: &[Arc<Column>]) {
+        let tx = self.partition_batch(partition, tablename, columns);
+        self.db.write(tx).unwrap();
+    }
+
+    fn append_wal(&self, tablename: &str, row: &[(String, RawVal)]) -> WalSeq {
+        let seq = self.next_wal_seq.fetch_add(1, Ordering::SeqCst) as WalSeq;
+        let entry = serialize_wal_entry(tablename, row);
+        self.db.put_cf(self.wal(), &wal_key(tablename, seq), &entry).unwrap();
+        seq
+    }
+
+    fn load_wal(&self) -> Vec<WalEntry> {
+        let iterator = self.db.iterator_cf(self.wal(), IteratorMode::Start).unwrap();
+        let mut entries = iterator
+            .map(|(key, value)| deserialize_wal_entry(&value, deserialize_wal_key(&key).0))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.seq);
+        entries
+    }
+
+    fn store_wal_partition(&self, partition: PartitionID, tablename: &str, columns: &[Arc<Column>], wal_seq: WalSeq) {
+        let mut tx = self.partition_batch(partition, tablename, columns);
+        let iterator = self.db
+            .iterator_cf(self.wal(), IteratorMode::From(&wal_key(tablename, 0), Direction::Forward))
+            .unwrap();
+        for (key, _) in iterator {
+            let (seq, name) = deserialize_wal_key(&key);
+            if name != tablename || seq > wal_seq { break; }
+            tx.delete_cf(self.wal(), &key).unwrap();
+        }
+        self.db.write(tx).unwrap();
+    }
+
+    fn delete_partition(&self, partition: PartitionID, column_names: &[String]) {
         let mut tx = WriteBatch::default();
         let mut key = [0; 8];
         BigEndian::write_u64(&mut key, partition as u64);
-        let md = serialize_meta_data(tablename, columns);
-        tx.put_cf(self.metadata(), &key, &md).unwrap();
-        for column in columns {
-            let key = column_key(partition, column.name());
-            let data = serialize_column(column.as_ref());
-            tx.put_cf(self.partitions(), &key, &data).unwrap();
+        tx.delete_cf(self.metadata(), &key).unwrap();
+        for name in column_names {
+            tx.delete_cf(self.partitions(), &column_key(partition, name)).unwrap();
         }
+        self.db.write(tx).unwrap();
+    }
 
+    fn delete_wal(&self, tablename: &str) {
+        let mut tx = WriteBatch::default();
+        let iterator = self.db
+            .iterator_cf(self.wal(), IteratorMode::From(&wal_key(tablename, 0), Direction::Forward))
+            .unwrap();
+        for (key, _) in iterator {
+            if deserialize_wal_key(&key).1 != tablename { break; }
+            tx.delete_cf(self.wal(), &key).unwrap();
+        }
         self.db.write(tx).unwrap();
     }
 }
 
+// Rows of the same table are stored next to each other in the order they were appended.
+fn wal_key(tablename: &str, seq: WalSeq) -> Vec<u8> {
+    let mut key = Vec::new();
+    key.extend(tablename.as_bytes());
+    key.push(0);
+    let mut seq_bytes = vec![0; 8];
+    BigEndian::write_u64(&mut seq_bytes, seq);
+    key.extend(seq_bytes);
+    key
+}
+
+fn deserialize_wal_key(key: &[u8]) -> (WalSeq, String) {
+    let i = key.len() - 8;
+    (BigEndian::read_u64(&key[i..]), str::from_utf8(&key[..(i - 1)]).unwrap().to_string())
+}
+
+fn serialize_wal_entry(tablename: &str, row: &[(String, RawVal)]) -> Vec<u8> {
+    let mut builder = capnp::message::Builder::new_default();
+    {
+        let mut entry = builder.init_root::<wal_entry::Builder>();
+        entry.set_tablename(tablename);
+        {
+            let mut columns = entry.reborrow().init_columns(row.len() as u32);
+            for (i, (name, _)) in row.iter().enumerate() {
+                columns.set(i as u32, name);
+            }
+        }
+        {
+            let mut values = entry.reborrow().init_values(row.len() as u32);
+            for (i, (_, val)) in row.iter().enumerate() {
+                let mut value = values.reborrow().get(i as u32);
+                match val {
+                    RawVal::Int(int) => value.set_int(*int),
+                    RawVal::Float(float) => value.set_float(float.0),
+                    RawVal::Str(string) => value.set_str(string),
+                    RawVal::Null => value.set_null(()),
+                }
+            }
+        }
+    }
+    let mut buffer = Vec::new();
+    capnp::serialize::write_message(&mut buffer, &builder).unwrap();
+    buffer
+}
+
+fn deserialize_wal_entry(data: &[u8], seq: WalSeq) -> WalEntry {
+    let message_reader = serialize::read_message_from_words(
+        Word::bytes_to_words(data),
+        message::ReaderOptions::new()).unwrap();
+    let entry = message_reader.get_root::<wal_entry::Reader>().unwrap();
+    let columns = entry.get_columns().unwrap();
+    let row = entry.get_values().unwrap().iter().enumerate().map(|(i, value)| {
+        use storage_format_capnp::value::Which::*;
+        let value = match value.which().unwrap() {
+            Int(int) => RawVal::Int(int),
+            Float(float) => RawVal::Float(OrderedF64(float)),
+            Str(string) => RawVal::Str(string.unwrap().to_string()),
+            Null(_) => RawVal::Null,
+        };
+        (columns.get(i as u32).unwrap().to_string(), value)
+    }).collect();
+    WalEntry {
+        seq,
+        tablename: entry.get_tablename().unwrap().to_string(),
+        row,
+    }
+}
+
 fn column_key(id: PartitionID, column_name: &str) -> Vec<u8> {
     let mut key = Vec::new();
     key.extend(column_name.as_bytes());
@@ -144,7 +284,7 @@ fn deserialize_column_key(key: &[u8]) -> (PartitionID, String) {
     (BigEndian::read_u64(&key[i..]), str::from_utf8(&key[..i]).unwrap().to_string())
 }
 
-fn deserialize_column(data: &[u8]) -> Column {
+pub fn deserialize_column(data: &[u8]) -> Column {
     let message_reader = serialize::read_message_from_words(
         Word::bytes_to_words(&data),
         message::ReaderOptions::new()).unwrap();
@@ -169,18 +309,25 @@ fn deserialize_column(data: &[u8]) -> Column {
                 CodecOp::Add(deserialize_type(add.get_type().unwrap()), add.get_amount())
             }
             Delta(delta) => CodecOp::Delta(deserialize_type(delta.unwrap())),
+            RunLength(t) => CodecOp::RunLength(deserialize_type(t.unwrap())),
             ToI64(toi64) => CodecOp::ToI64(deserialize_type(toi64.unwrap())),
             PushDataSection(section) => CodecOp::PushDataSection(section as usize),
             DictLookup(t) => CodecOp::DictLookup(deserialize_type(t.unwrap())),
+            SharedDictLookup(t) => CodecOp::SharedDictLookup(deserialize_type(t.unwrap())),
             Lz4(lz4) => {
                 let lz4 = lz4.unwrap();
                 CodecOp::LZ4(deserialize_type(lz4.get_type().unwrap()), lz4.get_len_decoded() as usize)
             }
+            Zstd(zstd) => {
+                let zstd = zstd.unwrap();
+                CodecOp::Zstd(deserialize_type(zstd.get_type().unwrap()), zstd.get_len_decoded() as usize)
+            }
             UnpackStrings(_) => CodecOp::UnpackStrings,
             UnhexpackStrings(uhps) => {
                 let uhps = uhps.unwrap();
                 CodecOp::UnhexpackStrings(uhps.get_uppercase(), uhps.get_total_bytes() as usize)
             }
+            List(_) => CodecOp::List,
         }
     }).collect::<Vec<_>>();
 
@@ -217,6 +364,12 @@ fn deserialize_column(data: &[u8]) -> Column {
                 buffer.extend(data);
                 DataSection::I64(buffer)
             }
+            F64(data) => {
+                let data = data.unwrap();
+                let mut buffer = Vec::with_capacity(data.len() as usize);
+                buffer.extend(data.into_iter().map(OrderedF64));
+                DataSection::F64(buffer)
+            }
             Null(count) => DataSection::Null(count as usize),
         }
     }).collect::<Vec<_>>();
@@ -236,7 +389,7 @@ fn deserialize_type(t: EncodingType) -> Type {
     }
 }
 
-fn deserialize_meta_data(data: &[u8], partition_id: PartitionID) -> PartitionMetadata {
+pub fn deserialize_meta_data(data: &[u8], partition_id: PartitionID) -> PartitionMetadata {
     let message_reader = serialize::read_message_from_words(
         Word::bytes_to_words(data),
         message::ReaderOptions::new()).unwrap();
@@ -246,15 +399,31 @@ fn deserialize_meta_data(data: &[u8], partition_id: PartitionID) -> PartitionMet
         len: meta_data.get_len() as usize,
         tablename: meta_data.get_tablename().unwrap().to_string(),
         columns: meta_data.get_columns().unwrap().iter().map(|c| {
+            let range = match c.get_range().which().unwrap() {
+                column_meta_data::range::Which::Unknown(_) => None,
+                column_meta_data::range::Which::Range(range) => {
+                    let range = range.unwrap();
+                    Some((range.get_start(), range.get_end()))
+                }
+            };
+            let null_count = match c.get_null_count().which().unwrap() {
+                column_meta_data::null_count::Which::Unknown(_) => None,
+                column_meta_data::null_count::Which::Count(count) => Some(count as usize),
+            };
+            let distinct_values = match c.get_distinct_values().which().unwrap() {
+                column_meta_data::distinct_values::Which::Unknown(_) => None,
+                column_meta_data::distinct_values::Which::Count(count) => Some(count as usize),
+            };
             ColumnMetadata {
                 name: c.get_name().unwrap().to_string(),
                 size_bytes: c.get_size_bytes() as usize,
+                stats: ColumnStats { zone_map: ZoneMap { range, null_count }, distinct_values },
             }
         }).collect(),
     }
 }
 
-fn serialize_meta_data(tablename: &str, columns: &[Arc<Column>]) -> Vec<u8> {
+pub fn serialize_meta_data(tablename: &str, columns: &[Arc<Column>]) -> Vec<u8> {
     let mut builder = capnp::message::Builder::new_default();
     {
         let mut meta_data = builder.init_root::<meta_data::Builder>();
@@ -266,6 +435,33 @@ fn serialize_meta_data(tablename: &str, columns: &[Arc<Column>]) -> Vec<u8> {
                 let mut col = cols.reborrow().get(i as u32);
                 col.set_name(column.name());
                 col.set_size_bytes(column.heap_size_of_children() as u64);
+                let stats = ColumnStats::of(column);
+                let zone_map = stats.zone_map;
+                {
+                    let mut range = col.reborrow().init_range();
+                    match zone_map.range {
+                        None => range.set_unknown(()),
+                        Some((start, end)) => {
+                            let mut range = range.reborrow().init_range();
+                            range.set_start(start);
+                            range.set_end(end);
+                        }
+                    }
+                }
+                {
+                    let mut null_count = col.reborrow().init_null_count();
+                    match zone_map.null_count {
+                        None => null_count.set_unknown(()),
+                        Some(count) => null_count.set_count(count as u64),
+                    }
+                }
+                {
+                    let mut distinct_values = col.reborrow().init_distinct_values();
+                    match stats.distinct_values {
+                        None => distinct_values.set_unknown(()),
+                        Some(count) => distinct_values.set_count(count as u64),
+                    }
+                }
             }
         }
     }
@@ -274,7 +470,16 @@ fn serialize_meta_data(tablename: &str, columns: &[Arc<Column>]) -> Vec<u8> {
     buffer
 }
 
-fn serialize_column(col: &Column) -> Vec<u8> {
+pub fn serialize_column(col: &Column) -> Vec<u8> {
+    serialize_column_sections(col, true)
+}
+
+/// Serializes `col` with empty data sections of the same types, for formats that store the data separately.
+pub fn serialize_column_header(col: &Column) -> Vec<u8> {
+    serialize_column_sections(col, false)
+}
+
+fn serialize_column_sections(col: &Column, with_data: bool) -> Vec<u8> {
     let mut builder = capnp::message::Builder::new_default();
     {
         let mut column = builder.init_root::<column::Builder>();
@@ -303,20 +508,28 @@ fn serialize_column(col: &Column) -> Vec<u8> {
                         add.set_amount(amount);
                     }
                     CodecOp::Delta(t) => capnp_op.set_delta(encoding_type_to_capnp(t)),
+                    CodecOp::RunLength(t) => capnp_op.set_run_length(encoding_type_to_capnp(t)),
                     CodecOp::ToI64(t) => capnp_op.set_to_i64(encoding_type_to_capnp(t)),
                     CodecOp::PushDataSection(section) => capnp_op.set_push_data_section(section as u64),
                     CodecOp::DictLookup(t) => capnp_op.set_dict_lookup(encoding_type_to_capnp(t)),
+                    CodecOp::SharedDictLookup(t) => capnp_op.set_shared_dict_lookup(encoding_type_to_capnp(t)),
                     CodecOp::LZ4(t, decoded_length) => {
                         let mut lz4 = capnp_op.init_lz4();
                         lz4.set_type(encoding_type_to_capnp(t));
                         lz4.set_len_decoded(decoded_length as u64);
                     }
+                    CodecOp::Zstd(t, decoded_length) => {
+                        let mut zstd = capnp_op.init_zstd();
+                        zstd.set_type(encoding_type_to_capnp(t));
+                        zstd.set_len_decoded(decoded_length as u64);
+                    }
                     CodecOp::UnpackStrings => capnp_op.set_unpack_strings(()),
                     CodecOp::UnhexpackStrings(uppercase, total_bytes) => {
                         let mut uhps = capnp_op.init_unhexpack_strings();
                         uhps.set_uppercase(uppercase);
                         uhps.set_total_bytes(total_bytes as u64);
                     }
+                    CodecOp::List => capnp_op.set_list(()),
                     CodecOp::Unknown => panic!("Trying to serialize CodecOp::Unkown"),
                 }
             }
@@ -324,30 +537,7 @@ fn serialize_column(col: &Column) -> Vec<u8> {
         {
             let mut data_sections = column.reborrow().init_data(col.data().len() as u32);
             for (i, section) in col.data().iter().enumerate() {
-                let mut ds = data_sections.reborrow().get(i as u32);
-                match section {
-                    DataSection::U8(x) => {
-                        let mut builder = ds.init_u8(x.len() as u32);
-                        populate_primitive_list(&mut builder, x);
-                    }
-                    DataSection::U16(x) => {
-                        let mut builder = ds.init_u16(x.len() as u32);
-                        populate_primitive_list(&mut builder, x);
-                    }
-                    DataSection::U32(x) => {
-                        let mut builder = ds.init_u32(x.len() as u32);
-                        populate_primitive_list(&mut builder, x);
-                    }
-                    DataSection::U64(x) => {
-                        let mut builder = ds.init_u64(x.len() as u32);
-                        populate_primitive_list(&mut builder, x);
-                    }
-                    DataSection::I64(x) => {
-                        let mut builder = ds.init_i64(x.len() as u32);
-                        populate_primitive_list(&mut builder, x);
-                    }
-                    DataSection::Null(count) => ds.set_null(*count as u64),
-                }
+                serialize_data_section(data_sections.reborrow().get(i as u32), section, with_data);
             }
         }
     }
@@ -356,6 +546,44 @@ fn serialize_column(col: &Column) -> Vec<u8> {
     buffer
 }
 
+fn serialize_data_section(mut ds: data_section::Builder, section: &DataSection, with_data: bool) {
+    let values = |x: usize| if with_data { x } else { 0 };
+    match section {
+        DataSection::U8(x) => {
+            let mut builder = ds.init_u8(values(x.len()) as u32);
+            populate_primitive_list(&mut builder, &x[..values(x.len())]);
+        }
+        DataSection::U16(x) => {
+            let mut builder = ds.init_u16(values(x.len()) as u32);
+            populate_primitive_list(&mut builder, &x[..values(x.len())]);
+        }
+        DataSection::U32(x) => {
+            let mut builder = ds.init_u32(values(x.len()) as u32);
+            populate_primitive_list(&mut builder, &x[..values(x.len())]);
+        }
+        DataSection::U64(x) => {
+            let mut builder = ds.init_u64(values(x.len()) as u32);
+            populate_primitive_list(&mut builder, &x[..values(x.len())]);
+        }
+        DataSection::I64(x) => {
+            let mut builder = ds.init_i64(values(x.len()) as u32);
+            populate_primitive_list(&mut builder, &x[..values(x.len())]);
+        }
+        DataSection::F64(x) => {
+            let mut builder = ds.init_f64(values(x.len()) as u32);
+            for (i, &x) in x[..values(x.len())].iter().enumerate() {
+                builder.set(i as u32, x.0);
+            }
+        }
+        DataSection::Null(count) => ds.set_null(*count as u64),
+        #[cfg(feature = "enable_mmap")]
+        DataSection::Mapped(x) => {
+            let section = if with_data { x.materialize() } else { DataSection::from_bytes(x.encoding_type(), &[]) };
+            serialize_data_section(ds, &section, with_data)
+        }
+    }
+}
+
 fn encoding_type_to_capnp(t: Type) -> EncodingType {
     match t {
         Type::U8 => EncodingType::U8,
@@ -0,0 +1,159 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "enable_mmap")]
+use memmap::Mmap;
+
+use disk_store::rocksdb::{deserialize_column, deserialize_meta_data, serialize_column_header, serialize_meta_data};
+use engine::data_types::EncodingType;
+use mem_store::column::{Column, DataSection};
+#[cfg(feature = "enable_mmap")]
+use mem_store::mapped_section::MappedSection;
+
+
+/// Start of partition files that store data sections separately from the column headers. Files without it store
+/// the data sections as part of the serialized columns.
+const MAGIC: &[u8; 8] = b"LDBSNAP2";
+
+/// Writes the columns of a partition in their compressed form to a file in the snapshot directory `dir`.
+/// The data sections of each column follow its header, aligned to 8 bytes, so they can be memory-mapped.
+/// The file is renamed into place once it is complete, so processes reading the directory never see partial files.
+pub fn write_partition(dir: &Path, index: usize, tablename: &str, columns: &[Arc<Column>]) -> Result<(), String> {
+    let path = dir.join(format!("{:08}.partition", index));
+    let tmp = path.with_extension("tmp");
+    {
+        let file = File::create(&tmp).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC).map_err(|e| e.to_string())?;
+        write_aligned_section(&mut writer, &serialize_meta_data(tablename, columns))?;
+        for column in columns {
+            write_aligned_section(&mut writer, &serialize_column_header(column))?;
+            for section in column.data() {
+                write_aligned_section(&mut writer, section.as_bytes())?;
+            }
+        }
+        writer.flush().map_err(|e| e.to_string())?;
+    }
+    fs::rename(&tmp, &path).map_err(|e| e.to_string())
+}
+
+/// Reads all partitions from the snapshot directory `dir` together with the name of the table they belong to.
+pub fn read_partitions(dir: &Path) -> Result<Vec<(String, Vec<Arc<Column>>)>, String> {
+    let mut partitions = Vec::new();
+    for path in partition_files(dir)? {
+        let mut data = Vec::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        let partition = if data.starts_with(MAGIC) {
+            parse_partition(&data, |t, range| Ok(DataSection::from_bytes(t, &data[range])))
+        } else {
+            read_legacy_partition(&mut BufReader::new(&data[..]))
+        };
+        partitions.push(partition.map_err(|e| format!("Invalid partition file {:?}: {}", path, e))?);
+    }
+    Ok(partitions)
+}
+
+/// Memory-maps all partitions from the snapshot directory `dir`. Only the headers of columns are read, their data
+/// sections are paged in when queries access them. The files must not be modified while they are mapped, snapshots
+/// only ever replace them.
+#[cfg(feature = "enable_mmap")]
+pub fn map_partitions(dir: &Path) -> Result<Vec<(String, Vec<Arc<Column>>)>, String> {
+    let mut partitions = Vec::new();
+    for path in partition_files(dir)? {
+        let file = File::open(&path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+        let map = Arc::new(unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to map {:?}: {}", path, e))?);
+        let partition = if map.starts_with(MAGIC) {
+            parse_partition(&map, |t, range| MappedSection::new(map.clone(), t, range).map(DataSection::Mapped))
+        } else {
+            read_legacy_partition(&mut BufReader::new(&map[..]))
+        };
+        partitions.push(partition.map_err(|e| format!("Invalid partition file {:?}: {}", path, e))?);
+    }
+    Ok(partitions)
+}
+
+fn partition_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().map_or(false, |ext| ext == "partition") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Parses a partition file that starts with `MAGIC`, `section` creates each data section from its type and location.
+fn parse_partition<F>(data: &[u8], mut section: F) -> Result<(String, Vec<Arc<Column>>), String>
+    where F: FnMut(EncodingType, Range<usize>) -> Result<DataSection, String> {
+    let mut offset = MAGIC.len();
+    let meta_data = deserialize_meta_data(&data[next_section(data, &mut offset)?], 0);
+    let mut columns = Vec::with_capacity(meta_data.columns.len());
+    for _ in 0..meta_data.columns.len() {
+        let mut column = deserialize_column(&data[next_section(data, &mut offset)?]);
+        let mut sections = Vec::with_capacity(column.data().len());
+        for header in column.data() {
+            let range = next_section(data, &mut offset)?;
+            sections.push(match header.encoding_type() {
+                EncodingType::Null => DataSection::Null(header.len()),
+                t => section(t, range)?,
+            });
+        }
+        column.set_data(sections);
+        columns.push(Arc::new(column));
+    }
+    Ok((meta_data.tablename, columns))
+}
+
+/// Location of the section at `offset`, which is advanced to the start of the following section.
+fn next_section(data: &[u8], offset: &mut usize) -> Result<Range<usize>, String> {
+    if data.len() < *offset + 8 {
+        return Err("Unexpected end of file".to_string());
+    }
+    let len = BigEndian::read_u64(&data[*offset..*offset + 8]) as usize;
+    let start = *offset + 8;
+    if data.len() < start + len {
+        return Err("Unexpected end of file".to_string());
+    }
+    *offset = start + padded(len);
+    Ok(start..start + len)
+}
+
+fn read_legacy_partition<R: Read>(reader: &mut R) -> Result<(String, Vec<Arc<Column>>), String> {
+    let meta_data = deserialize_meta_data(&read_section(reader)?, 0);
+    let mut columns = Vec::with_capacity(meta_data.columns.len());
+    for _ in 0..meta_data.columns.len() {
+        columns.push(Arc::new(deserialize_column(&read_section(reader)?)));
+    }
+    Ok((meta_data.tablename, columns))
+}
+
+/// Writes `data` prefixed by its length and followed by padding to a multiple of 8 bytes.
+fn write_aligned_section<W: Write>(writer: &mut W, data: &[u8]) -> Result<(), String> {
+    write_section(writer, data)?;
+    writer.write_all(&[0; 8][..padded(data.len()) - data.len()]).map_err(|e| e.to_string())
+}
+
+fn padded(len: usize) -> usize {
+    (len + 7) / 8 * 8
+}
+
+/// Writes `data` prefixed by its length.
+pub fn write_section<W: Write>(writer: &mut W, data: &[u8]) -> Result<(), String> {
+    writer.write_u64::<BigEndian>(data.len() as u64).map_err(|e| e.to_string())?;
+    writer.write_all(data).map_err(|e| e.to_string())
+}
+
+pub fn read_section<R: Read>(reader: &mut R) -> Result<Vec<u8>, String> {
+    let len = reader.read_u64::<BigEndian>().map_err(|e| e.to_string())?;
+    let mut data = vec![0; len as usize];
+    reader.read_exact(&mut data).map_err(|e| e.to_string())?;
+    Ok(data)
+}
@@ -25,6 +25,7 @@ pub trait Data<'a>: Send + Sync {
 
     fn cast_ref_str<'b>(&'b self) -> &'b [&'a str] { panic!(self.type_error("cast_ref_str")) }
     fn cast_ref_i64(&self) -> &[i64] { panic!(self.type_error("cast_ref_i64")) }
+    fn cast_ref_f64(&self) -> &[OrderedF64] { panic!(self.type_error("cast_ref_f64")) }
     fn cast_ref_u32(&self) -> &[u32] { panic!(self.type_error("cast_ref_u32")) }
     fn cast_ref_u16(&self) -> &[u16] { panic!(self.type_error("cast_ref_u16")) }
     fn cast_ref_u8(&self) -> &[u8] { panic!(self.type_error("cast_ref_u8")) }
@@ -35,15 +36,18 @@ pub trait Data<'a>: Send + Sync {
     fn cast_ref_usize(&self) -> &[usize] { panic!(self.type_error("cast_ref_usize")) }
 
     fn cast_ref_mixed(&self) -> &[Val<'a>] { panic!(self.type_error("cast_ref_mixed")) }
+    fn cast_ref_keyed(&self) -> &[KeyedVal<'a>] { panic!(self.type_error("cast_ref_keyed")) }
     fn cast_ref_merge_op(&self) -> &[MergeOp] { panic!(self.type_error("cast_ref_merge_op")) }
     fn cast_ref_premerge(&self) -> &[Premerge] { panic!(self.type_error("cast_ref_merge_op")) }
     fn cast_ref_scalar_string(&self) -> &String { panic!(self.type_error("cast_ref_scalar_string")) }
     fn cast_scalar_i64(&self) -> i64 { panic!(self.type_error("cast_scalar_i64")) }
+    fn cast_scalar_f64(&self) -> OrderedF64 { panic!(self.type_error("cast_scalar_f64")) }
     fn cast_scalar_str(&self) -> &'a str { panic!(self.type_error("cast_scalar_str")) }
     fn cast_ref_byte_slices(&self) -> &ByteSlices<'a> { panic!(self.type_error("cast_ref_byte_slices")) }
 
     fn cast_ref_mut_str(&mut self) -> &mut Vec<&'a str> { panic!(self.type_error("cast_ref_mut_str")) }
     fn cast_ref_mut_i64(&mut self) -> &mut Vec<i64> { panic!(self.type_error("cast_ref_mut_i64")) }
+    fn cast_ref_mut_f64(&mut self) -> &mut Vec<OrderedF64> { panic!(self.type_error("cast_ref_mut_f64")) }
     fn cast_ref_mut_u32(&mut self) -> &mut Vec<u32> { panic!(self.type_error("cast_ref_mut_u32")) }
     fn cast_ref_mut_u16(&mut self) -> &mut Vec<u16> { panic!(self.type_error("cast_ref_mut_u16")) }
     fn cast_ref_mut_u8(&mut self) -> &mut Vec<u8> { panic!(self.type_error("cast_ref_mut_u8")) }
@@ -52,6 +56,7 @@ pub trait Data<'a>: Send + Sync {
     fn cast_ref_mut_usize(&mut self) -> &mut Vec<usize> { panic!(self.type_error("cast_ref_mut_usize")) }
 
     fn cast_ref_mut_mixed(&mut self) -> &mut Vec<Val<'a>> { panic!(self.type_error("cast_ref_mut_mixed")) }
+    fn cast_ref_mut_keyed(&mut self) -> &mut Vec<KeyedVal<'a>> { panic!(self.type_error("cast_ref_mut_keyed")) }
     fn cast_ref_mut_merge_op(&mut self) -> &mut Vec<MergeOp> { panic!(self.type_error("cast_ref_mut_merge_op")) }
     fn cast_ref_mut_premerge(&mut self) -> &mut Vec<Premerge> { panic!(self.type_error("cast_ref_mut_premerge_op")) }
     fn cast_ref_mut_byte_slices(&mut self) -> &mut ByteSlices<'a> { panic!(self.type_error("cast_ref_mut_byte_slices")) }
@@ -61,6 +66,12 @@ pub trait Data<'a>: Send + Sync {
     fn make_nullable(&mut self, _present: &[u8]) -> BoxedData<'a> { panic!(self.type_error("nullable")) }
 
     fn display(&self) -> String;
+
+    /// Bytes of heap memory owned by the data, 0 for data borrowed from columns.
+    fn heap_size_bytes(&self) -> usize { 0 }
+
+    /// Owned copy of the data, `None` if the data can't be copied.
+    fn boxed_clone(&self) -> Option<BoxedData<'a>> { None }
 }
 
 impl<'a> DataSource for BoxedData<'a> {
@@ -126,6 +137,10 @@ impl<'a, T: VecData<T> + 'a> Data<'a> for Vec<T> {
     }
 
     fn display(&self) -> String { format!("Vec<{:?}>{}", T::t(), display_slice(&self, 120)) }
+
+    fn heap_size_bytes(&self) -> usize { self.capacity() * mem::size_of::<T>() }
+
+    fn boxed_clone(&self) -> Option<BoxedData<'a>> { Some(Box::new(self.clone())) }
 }
 
 impl<'a> Data<'a> for Vec<&'a str> {
@@ -150,6 +165,11 @@ impl<'a> Data<'a> for Vec<Val<'a>> {
     }
 }
 
+impl<'a> Data<'a> for Vec<KeyedVal<'a>> {
+    fn cast_ref_keyed<'b>(&'b self) -> &'b [KeyedVal<'a>] { self }
+    fn cast_ref_mut_keyed<'b>(&'b mut self) -> &'b mut Vec<KeyedVal<'a>> { self }
+}
+
 impl<'a> Data<'a> for Vec<usize> {
     fn cast_ref_usize(&self) -> &[usize] { self }
     fn cast_ref_mut_usize(&mut self) -> &mut Vec<usize> { self }
@@ -163,6 +183,14 @@ impl<'a> Data<'a> for Vec<i64> {
     }
 }
 
+impl<'a> Data<'a> for Vec<OrderedF64> {
+    fn cast_ref_f64(&self) -> &[OrderedF64] { self }
+    fn cast_ref_mut_f64(&mut self) -> &mut Vec<OrderedF64> { self }
+    fn to_mixed(&self) -> Vec<Val<'a>> {
+        self.iter().map(|f| Val::Float(*f)).collect()
+    }
+}
+
 impl<'a> Data<'a> for Vec<u64> {
     fn cast_ref_u64(&self) -> &[u64] { self }
     fn cast_ref_mut_u64(&mut self) -> &mut Vec<u64> { self }
@@ -209,6 +237,8 @@ impl<'a, T: VecData<T> + 'a> Data<'a> for &'a [T] {
     fn type_error(&self, func_name: &str) -> String { format!("[{:?}].{}", T::t(), func_name) }
 
     fn display(&self) -> String { format!("&{:?}{}", T::t(), display_slice(&self, 120)) }
+
+    fn boxed_clone(&self) -> Option<BoxedData<'a>> { Some(Box::new(self.to_vec())) }
 }
 
 impl<'a> Data<'a> for &'a [&'a str] {
@@ -227,6 +257,10 @@ impl<'a> Data<'a> for &'a [i64] {
     fn cast_ref_i64(&self) -> &[i64] { self }
 }
 
+impl<'a> Data<'a> for &'a [OrderedF64] {
+    fn cast_ref_f64(&self) -> &[OrderedF64] { self }
+}
+
 impl<'a> Data<'a> for &'a [u64] {
     fn cast_ref_u64(&self) -> &[u64] { self }
 }
@@ -278,6 +312,8 @@ impl<'a> Data<'a> for usize {
     fn slice_box<'b>(&'b self, from: usize, to: usize) -> BoxedData<'b> where 'a: 'b { Box::new(min(to, *self) - from) }
 
     fn display(&self) -> String { format!("null({})", self) }
+
+    fn boxed_clone(&self) -> Option<BoxedData<'a>> { Some(Box::new(*self)) }
 }
 
 impl<'a> Data<'a> for RawVal {
@@ -301,5 +337,7 @@ impl<'a> Data<'a> for RawVal {
     }
 
     fn display(&self) -> String { format!("Scalar({})", self) }
+
+    fn boxed_clone(&self) -> Option<BoxedData<'a>> { Some(Box::new(self.clone())) }
 }
 
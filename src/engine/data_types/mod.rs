@@ -1,6 +1,7 @@
 mod byte_slices;
 mod data;
 mod nullable_vec_data;
+mod ordered_f64;
 mod scalar_data;
 mod types;
 mod vec_data;
@@ -11,3 +12,4 @@ pub use self::vec_data::*;
 pub use self::byte_slices::*;
 pub use self::scalar_data::*;
 pub use self::nullable_vec_data::*;
+pub use self::ordered_f64::*;
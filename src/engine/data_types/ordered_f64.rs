@@ -0,0 +1,67 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use heapsize::HeapSizeOf;
+
+
+/// `f64` with a total order so that floats can be stored in columns, sorted and grouped by.
+/// NaN compares equal to itself and greater than all other values.
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct OrderedF64(pub f64);
+
+impl OrderedF64 {
+    pub fn normalized_bits(self) -> u64 {
+        if self.0.is_nan() {
+            ::std::f64::NAN.to_bits()
+        } else if self.0 == 0.0 {
+            0
+        } else {
+            self.0.to_bits()
+        }
+    }
+}
+
+impl Ord for OrderedF64 {
+    fn cmp(&self, other: &OrderedF64) -> Ordering {
+        match self.0.partial_cmp(&other.0) {
+            Some(ordering) => ordering,
+            None => self.0.is_nan().cmp(&other.0.is_nan()),
+        }
+    }
+}
+
+impl PartialOrd for OrderedF64 {
+    fn partial_cmp(&self, other: &OrderedF64) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for OrderedF64 {
+    fn eq(&self, other: &OrderedF64) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OrderedF64 {}
+
+impl Hash for OrderedF64 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized_bits().hash(state)
+    }
+}
+
+impl fmt::Display for OrderedF64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl HeapSizeOf for OrderedF64 {
+    fn heap_size_of_children(&self) -> usize { 0 }
+}
+
+impl From<f64> for OrderedF64 {
+    fn from(f: f64) -> OrderedF64 { OrderedF64(f) }
+}
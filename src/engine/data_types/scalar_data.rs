@@ -13,6 +13,10 @@ impl<'a> Data<'a> for ScalarVal<i64> {
     fn cast_scalar_i64(&self) -> i64 { self.val }
 }
 
+impl<'a> Data<'a> for ScalarVal<OrderedF64> {
+    fn cast_scalar_f64(&self) -> OrderedF64 { self.val }
+}
+
 impl<'a> Data<'a> for ScalarVal<&'a str> {
     fn len(&self) -> usize { 1 }
     fn get_raw(&self, _: usize) -> RawVal { RawVal::Str(self.val.to_string()) }
@@ -60,6 +64,12 @@ impl ScalarData<i64> for i64 {
     fn t() -> EncodingType { EncodingType::ScalarI64 }
 }
 
+impl ScalarData<OrderedF64> for OrderedF64 {
+    fn unwrap(vec: &Data) -> OrderedF64 { vec.cast_scalar_f64() }
+    fn raw_val(val: &OrderedF64) -> RawVal { RawVal::Float(*val) }
+    fn t() -> EncodingType { EncodingType::ScalarF64 }
+}
+
 impl<'a> ScalarData<&'a str> for &'a str {
     fn unwrap(vec: &Data) -> &'a str {
         // TODO(clemens): fix. wait for associated type constructors?
@@ -8,6 +8,7 @@ pub enum EncodingType {
     U16,
     U32,
     U64,
+    F64,
 
     NullableStr,
     NullableI64,
@@ -19,8 +20,10 @@ pub enum EncodingType {
     USize,
     Val,
     Null,
+    KeyedVal,
 
     ScalarI64,
+    ScalarF64,
     ScalarStr,
     ScalarString,
     ConstVal,
@@ -35,6 +38,7 @@ impl EncodingType {
         match self {
             EncodingType::Str => BasicType::String,
             EncodingType::I64 => BasicType::Integer,
+            EncodingType::F64 => BasicType::Float,
             EncodingType::Val => BasicType::Val,
             EncodingType::Null => BasicType::Null,
             _ => panic!("{:?} does not have a corresponding BasicType", &self)
@@ -68,6 +72,14 @@ impl EncodingType {
         }
     }
 
+    pub fn is_scalar(&self) -> bool {
+        match self {
+            EncodingType::ScalarI64 | EncodingType::ScalarF64 | EncodingType::ScalarStr |
+            EncodingType::ScalarString | EncodingType::ConstVal => true,
+            _ => false,
+        }
+    }
+
     pub fn non_nullable(&self) -> EncodingType {
         match self {
             EncodingType::NullableStr => EncodingType::Str,
@@ -82,9 +94,11 @@ impl EncodingType {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, HeapSizeOf)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum BasicType {
     String,
     Integer,
+    Float,
     NullableString,
     NullableInteger,
 
@@ -98,6 +112,7 @@ impl BasicType {
         match self {
             BasicType::String => EncodingType::Str,
             BasicType::Integer => EncodingType::I64,
+            BasicType::Float => EncodingType::F64,
             BasicType::NullableString => EncodingType::NullableStr,
             BasicType::NullableInteger => EncodingType::NullableI64,
             BasicType::Val => EncodingType::Val,
@@ -49,6 +49,13 @@ impl VecData<i64> for i64 {
     fn t() -> EncodingType { EncodingType::I64 }
 }
 
+impl VecData<OrderedF64> for OrderedF64 {
+    fn unwrap<'a, 'b>(vec: &'b Data<'a>) -> &'b [OrderedF64] where OrderedF64: 'a { vec.cast_ref_f64() }
+    fn unwrap_mut<'a, 'b>(vec: &'b mut Data<'a>) -> &'b mut Vec<OrderedF64> where OrderedF64: 'a { vec.cast_ref_mut_f64() }
+    fn wrap_one(value: OrderedF64) -> RawVal { RawVal::Float(value) }
+    fn t() -> EncodingType { EncodingType::F64 }
+}
+
 impl VecData<u64> for u64 {
     fn unwrap<'a, 'b>(vec: &'b Data<'a>) -> &'b [u64] where u64: 'a { vec.cast_ref_u64() }
     fn unwrap_mut<'a, 'b>(vec: &'b mut Data<'a>) -> &'b mut Vec<u64> where u64: 'a { vec.cast_ref_mut_u64() }
@@ -202,6 +209,47 @@ impl VecData<Premerge> for Premerge {
 }
 
 
+/// Value selected by `ARG_MAX` or `ARG_MIN` together with the key that selected it, the key is `None` if no row was
+/// selected.
+#[derive(Debug, PartialEq, PartialOrd, Ord, Eq, Copy, Clone, Hash)]
+pub struct KeyedVal<'a> {
+    pub key: Option<i64>,
+    pub val: Val<'a>,
+}
+
+impl<'a> Display for KeyedVal<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.key {
+            Some(key) => write!(f, "{}@{}", self.val, key),
+            None => write!(f, "null"),
+        }
+    }
+}
+
+impl<'a> HeapSizeOf for KeyedVal<'a> {
+    fn heap_size_of_children(&self) -> usize { self.val.heap_size_of_children() }
+}
+
+impl<'c> VecData<KeyedVal<'c>> for KeyedVal<'c> {
+    fn unwrap<'a, 'b>(vec: &'b Data<'a>) -> &'b [KeyedVal<'c>] where KeyedVal<'c>: 'a {
+        unsafe {
+            mem::transmute::<_, &'b [KeyedVal<'c>]>(vec.cast_ref_keyed())
+        }
+    }
+
+    fn unwrap_mut<'a, 'b>(vec: &'b mut Data<'a>) -> &'b mut Vec<KeyedVal<'c>> where KeyedVal<'c>: 'a {
+        unsafe {
+            mem::transmute::<_, &'b mut Vec<KeyedVal<'c>>>(vec.cast_ref_mut_keyed())
+        }
+    }
+
+    // Keys are only needed to merge partial results, query results contain just the selected value
+    fn wrap_one(value: KeyedVal<'c>) -> RawVal { (&value.val).into() }
+
+    fn t() -> EncodingType { EncodingType::KeyedVal }
+}
+
+
 pub fn display_slice<T: Display>(slice: &[T], max_chars: usize) -> String {
     let mut length = slice.len();
     loop {
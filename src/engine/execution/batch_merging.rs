@@ -1,11 +1,14 @@
-use std::cmp::min;
+use std::cmp::{min, Ordering};
 use std::sync::Arc;
 use std::collections::HashMap;
 use std::usize;
 use std::result::Result;
 
 use mem_store::column::DataSource;
+use mem_store::SharedDictionary;
+use mem_store::value::Val;
 use engine::*;
+use ingest::raw_val::RawVal;
 use errors::QueryError;
 
 #[derive(Debug)]
@@ -26,6 +29,10 @@ impl<'a> BatchResult<'a> {
         self.columns.get(0).map_or(0, |s| s.len())
     }
 
+    pub fn heap_size_bytes(&self) -> usize {
+        self.columns.iter().map(|c| c.heap_size_bytes()).sum()
+    }
+
     pub fn validate(&self) -> Result<(), QueryError> {
         let mut lengths = Vec::new();
         let mut info_str = "".to_owned();
@@ -46,6 +53,55 @@ impl<'a> BatchResult<'a> {
         Ok(())
     }
 
+    /// Copy of the result, `None` if it references buffers of the result or contains data that can't be copied.
+    pub fn try_clone(&self) -> Option<BatchResult<'a>> {
+        if !self.unsafe_referenced_buffers.is_empty() {
+            return None;
+        }
+        let columns = self.columns.iter().map(|c| c.boxed_clone()).collect::<Option<Vec<_>>>()?;
+        Some(BatchResult {
+            columns,
+            projection: self.projection.clone(),
+            aggregations: self.aggregations.clone(),
+            order_by: self.order_by.clone(),
+            level: self.level,
+            batch_count: self.batch_count,
+            show: self.show,
+            unsafe_referenced_buffers: Vec::new(),
+        })
+    }
+
+    /// Replaces the values selected by `ARG_MAX` and `ARG_MIN`, which carry the key that selected them until all partial
+    /// results are merged, with just the selected values.
+    pub fn decode_keyed(mut self) -> BatchResult<'a> {
+        for &(index, aggregator) in &self.aggregations {
+            if aggregator != Aggregator::ArgMax && aggregator != Aggregator::ArgMin {
+                continue;
+            }
+            let vals = self.columns[index].cast_ref_keyed().iter().map(|keyed| keyed.val).collect::<Vec<_>>();
+            // Columns that contain values of a single type and no nulls are converted into columns of that type
+            let integers = vals.iter().map(|val| match *val { Val::Integer(i) => Some(i), _ => None }).collect::<Option<Vec<_>>>();
+            let floats = vals.iter().map(|val| match *val { Val::Float(f) => Some(f), _ => None }).collect::<Option<Vec<_>>>();
+            let strings = vals.iter().map(|val| match *val { Val::Str(s) => Some(s), _ => None }).collect::<Option<Vec<_>>>();
+            self.columns[index] = match (integers, floats, strings) {
+                (Some(integers), _, _) => Data::owned(integers),
+                (_, Some(floats), _) => Data::owned(floats),
+                (_, _, Some(strings)) => Data::owned(strings),
+                _ => Data::owned(vals),
+            };
+        }
+        self
+    }
+
+    /// Replaces the codes of the single group by column, which groups are merged on if all partitions share the
+    /// dictionary of the column, with the strings they encode.
+    pub fn decode_codes(mut self, dictionary: &'a SharedDictionary) -> BatchResult<'a> {
+        let index = self.projection[0];
+        let strings = dictionary.strings(self.columns[index].cast_ref_i64());
+        self.columns[index] = Data::owned(strings);
+        self
+    }
+
     pub fn into_columns(self) -> HashMap<String, Arc<DataSource + 'a>> {
         let mut cols = HashMap::<String, Arc<DataSource>>::default();
         let columns = self.columns.into_iter().map(|c| Arc::new(c)).collect::<Vec<_>>();
@@ -59,6 +115,154 @@ impl<'a> BatchResult<'a> {
     }
 }
 
+/// Merges any number of sorted select results at once, only the first `limit` rows are materialized.
+pub fn merge_sorted<'a>(batches: Vec<BatchResult<'a>>, limit: usize) -> Result<BatchResult<'a>, QueryError> {
+    ensure!(!batches.is_empty(), "No batch results to merge.");
+    let mergeable = batches.len() > 2 && batches.iter().all(|batch|
+        batch.aggregations.is_empty()
+            && !batch.order_by.is_empty()
+            && batch.projection.len() == batches[0].projection.len()
+            && batch.order_by.len() == batches[0].order_by.len()
+            && batch.columns.iter().all(|c| gatherable(c.encoding_type())));
+    if !mergeable {
+        return combine_pairwise(batches, limit);
+    }
+
+    // Columns of the merged result, identified by the index of the corresponding column in each batch
+    let mut sources = Vec::<Vec<usize>>::new();
+    let projection = (0..batches[0].projection.len())
+        .map(|i| source_index(&mut sources, batches.iter().map(|b| b.projection[i]).collect()))
+        .collect::<Vec<_>>();
+    let order_by = (0..batches[0].order_by.len())
+        .map(|i| (source_index(&mut sources, batches.iter().map(|b| b.order_by[i].0).collect()),
+                  batches[0].order_by[i].1))
+        .collect::<Vec<_>>();
+    let same_types = sources.iter().all(|source| {
+        let encoding_type = batches[0].columns[source[0]].encoding_type();
+        batches.iter().zip(source).all(|(b, &i)| b.columns[i].encoding_type() == encoding_type)
+    });
+    if !same_types {
+        return combine_pairwise(batches, limit);
+    }
+
+    // The number of batches is small (at most one per thread and merge level), so the next row is found by linear scan
+    let desc = order_by.iter().map(|&(_, desc)| desc).collect::<Vec<_>>();
+    let sort_key = |batch: &BatchResult<'a>, row: usize| -> Option<Vec<RawVal>> {
+        if row < batch.len() {
+            Some(batch.order_by.iter().map(|&(i, _)| batch.columns[i].get_raw(row)).collect())
+        } else {
+            None
+        }
+    };
+    let mut heads = batches.iter().map(|b| sort_key(b, 0)).collect::<Vec<_>>();
+    let mut cursors = vec![0; batches.len()];
+    let total = batches.iter().map(|b| b.len()).sum::<usize>();
+    let mut rows = Vec::with_capacity(min(limit, total));
+    while rows.len() < limit {
+        let mut next: Option<usize> = None;
+        for (b, head) in heads.iter().enumerate() {
+            if let Some(key) = head {
+                let smaller = match next {
+                    Some(n) => compare_keys(key, heads[n].as_ref().unwrap(), &desc) == Ordering::Less,
+                    None => true,
+                };
+                if smaller { next = Some(b); }
+            }
+        }
+        match next {
+            Some(b) => {
+                rows.push((b, cursors[b]));
+                cursors[b] += 1;
+                heads[b] = sort_key(&batches[b], cursors[b]);
+            }
+            None => break,
+        }
+    }
+
+    let columns = sources.iter()
+        .map(|source| gather(batches.iter().zip(source).map(|(b, &i)| &b.columns[i]).collect(), &rows))
+        .collect::<Vec<_>>();
+    let mut level = 0;
+    let mut batch_count = 0;
+    let mut show = true;
+    let mut unsafe_referenced_buffers = Vec::new();
+    // Remaining rows of all batches are dropped here
+    for batch in batches {
+        level = level.max(batch.level);
+        batch_count += batch.batch_count;
+        show = show && batch.show;
+        unsafe_referenced_buffers.extend(batch.unsafe_referenced_buffers.into_iter());
+    }
+    let result = BatchResult {
+        columns,
+        projection,
+        aggregations: vec![],
+        order_by,
+        level: level + 1,
+        batch_count,
+        show,
+        unsafe_referenced_buffers,
+    };
+    result.validate()?;
+    Ok(result)
+}
+
+fn combine_pairwise<'a>(batches: Vec<BatchResult<'a>>, limit: usize) -> Result<BatchResult<'a>, QueryError> {
+    let mut batches = batches.into_iter();
+    let mut result = batches.next().unwrap();
+    for batch in batches {
+        result = combine(result, batch, limit)?;
+    }
+    Ok(result)
+}
+
+fn source_index(sources: &mut Vec<Vec<usize>>, source: Vec<usize>) -> usize {
+    match sources.iter().position(|s| *s == source) {
+        Some(index) => index,
+        None => {
+            sources.push(source);
+            sources.len() - 1
+        }
+    }
+}
+
+fn compare_keys(left: &[RawVal], right: &[RawVal], desc: &[bool]) -> Ordering {
+    for ((l, r), &desc) in left.iter().zip(right).zip(desc) {
+        let ordering = if desc { r.cmp(l) } else { l.cmp(r) };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn gatherable(encoding_type: EncodingType) -> bool {
+    match encoding_type {
+        EncodingType::Str | EncodingType::I64 | EncodingType::U8 | EncodingType::U16 | EncodingType::U32
+        | EncodingType::U64 | EncodingType::F64 | EncodingType::Val | EncodingType::Null => true,
+        _ => false,
+    }
+}
+
+/// Collects the values at `rows`, given as (batch, row) pairs, from the corresponding column of each batch.
+fn gather<'a>(columns: Vec<&BoxedData<'a>>, rows: &[(usize, usize)]) -> BoxedData<'a> {
+    fn gather_typed<'a, T: VecData<T> + 'a>(columns: Vec<&[T]>, rows: &[(usize, usize)]) -> BoxedData<'a> {
+        Box::new(rows.iter().map(|&(b, i)| columns[b][i]).collect::<Vec<T>>())
+    }
+    match columns[0].encoding_type() {
+        EncodingType::Str => gather_typed(columns.iter().map(|c| c.cast_ref_str()).collect(), rows),
+        EncodingType::I64 => gather_typed(columns.iter().map(|c| c.cast_ref_i64()).collect(), rows),
+        EncodingType::U8 => gather_typed(columns.iter().map(|c| c.cast_ref_u8()).collect(), rows),
+        EncodingType::U16 => gather_typed(columns.iter().map(|c| c.cast_ref_u16()).collect(), rows),
+        EncodingType::U32 => gather_typed(columns.iter().map(|c| c.cast_ref_u32()).collect(), rows),
+        EncodingType::U64 => gather_typed(columns.iter().map(|c| c.cast_ref_u64()).collect(), rows),
+        EncodingType::F64 => gather_typed(columns.iter().map(|c| c.cast_ref_f64()).collect(), rows),
+        EncodingType::Val => gather_typed(columns.iter().map(|c| c.cast_ref_mixed()).collect(), rows),
+        EncodingType::Null => Box::new(rows.len()),
+        t => panic!("Can't gather column of type {:?}", t),
+    }
+}
+
 pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usize) -> Result<BatchResult<'a>, QueryError> {
     ensure!(
         batch1.projection.len()  == batch2.projection.len(),
@@ -132,17 +336,24 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
 
         let mut aggregates = Vec::with_capacity(batch1.aggregations.len());
         for (&(ileft, aggregator), &(iright, _)) in batch1.aggregations.iter().zip(batch2.aggregations.iter()) {
-            let left = left[ileft].i64()?;
-            let right = right[iright].i64()?;
-            let aggregated = qp.merge_aggregate(ops, left, right, aggregator);
-            aggregates.push((aggregated.any(), aggregator));
+            // Histograms are serialized in the same format as quantile sketches and merged in the same way
+            let aggregated = if aggregator == Aggregator::Percentile || aggregator == Aggregator::Histogram {
+                qp.merge_quantile_sketches(ops, left[ileft].str()?, right[iright].str()?).any()
+            } else if aggregator == Aggregator::ArgMax || aggregator == Aggregator::ArgMin {
+                let max = aggregator == Aggregator::ArgMax;
+                qp.merge_arg_extremum(ops, left[ileft], right[iright], max).any()
+            } else {
+                qp.merge_aggregate(ops, left[ileft], right[iright], aggregator).any()
+            };
+            aggregates.push((aggregated, aggregator));
         }
 
         let mut executor = qp.prepare(data)?;
         let mut results = executor.prepare_no_columns();
-        executor.run(1, &mut results, batch1.show || batch2.show);
+        executor.run(1, &mut results, batch1.show || batch2.show)?;
 
         let (columns, projection, aggregations, _) = results.collect_aliased(&group_by_cols, &aggregates, &[]);
+        let merged_buffers = results.collect_pinned();
         let result = BatchResult {
             columns,
             projection,
@@ -154,6 +365,7 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
             unsafe_referenced_buffers: {
                 let mut urb = batch1.unsafe_referenced_buffers;
                 urb.extend(batch2.unsafe_referenced_buffers.into_iter());
+                urb.extend(merged_buffers.into_iter());
                 urb
             },
         };
@@ -222,7 +434,7 @@ pub fn combine<'a>(batch1: BatchResult<'a>, batch2: BatchResult<'a>, limit: usiz
 
             let mut executor = qp.prepare(data)?;
             let mut results = executor.prepare_no_columns();
-            executor.run(1, &mut results, batch1.show || batch2.show);
+            executor.run(1, &mut results, batch1.show || batch2.show)?;
 
             let (columns, projection, _, order_by) = results.collect_aliased(&projection, &[], &order_by);
 
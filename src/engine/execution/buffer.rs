@@ -1,253 +1,288 @@
-use std::fmt;
-use std::marker::PhantomData;
-use std::mem;
-use std::result::Result;
-
-use QueryError;
-use engine::data_types::*;
-use ingest::raw_val::RawVal;
-
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub struct BufferRef<T> {
-    pub i: usize,
-    pub name: &'static str,
-    pub t: PhantomData<T>,
-}
-
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub struct TypedBufferRef {
-    pub buffer: BufferRef<Any>,
-    pub tag: EncodingType,
-}
-
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub enum Any {}
-
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub struct Scalar<T> { t: PhantomData<T> }
-
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub struct Nullable<T> { t: PhantomData<T> }
-
-pub fn error_buffer_ref(name: &'static str) -> BufferRef<Any> {
-    BufferRef {
-        i: 0xdead_beef,
-        name,
-        t: PhantomData,
-    }
-}
-
-impl BufferRef<Any> {
-    pub fn merge_op(self) -> BufferRef<MergeOp> { self.transmute() }
-    pub fn premerge(self) -> BufferRef<Premerge> { self.transmute() }
-    pub fn raw_val(self) -> BufferRef<RawVal> { self.transmute() }
-    pub fn i64(self) -> BufferRef<i64> { self.transmute() }
-    pub fn u64(self) -> BufferRef<u64> { self.transmute() }
-    pub fn u32(self) -> BufferRef<u32> { self.transmute() }
-    pub fn u16(self) -> BufferRef<u16> { self.transmute() }
-    pub fn u8(self) -> BufferRef<u8> { self.transmute() }
-
-    pub fn cast_nullable_any(&self) -> BufferRef<Nullable<Any>> { self.transmute() }
-
-    pub fn nullable_u8(self) -> BufferRef<Nullable<u8>> { self.transmute() }
-    pub fn nullable_u16(self) -> BufferRef<Nullable<u16>> { self.transmute() }
-    pub fn nullable_u32(self) -> BufferRef<Nullable<u32>> { self.transmute() }
-    pub fn nullable_i64(self) -> BufferRef<Nullable<i64>> { self.transmute() }
-    pub fn nullable_str<'a>(self) -> BufferRef<Nullable<&'a str>> { self.transmute() }
-
-    pub fn scalar_i64(self) -> BufferRef<Scalar<i64>> { self.transmute() }
-    pub fn scalar_str<'a>(self) -> BufferRef<Scalar<&'a str>> { self.transmute() }
-    pub fn scalar_string<'a>(self) -> BufferRef<Scalar<String>> { self.transmute() }
-
-    pub fn string(self) -> BufferRef<String> { self.transmute() }
-    pub fn str<'a>(self) -> BufferRef<&'a str> { self.transmute() }
-    pub fn usize(self) -> BufferRef<usize> { self.transmute() }
-    fn transmute<T>(self) -> BufferRef<T> { unsafe { mem::transmute(self) } }
-}
-
-// TODO(clemens): remove this, temporary hack because ther is no buffer type for ByteSlices
-impl From<BufferRef<Any>> for TypedBufferRef {
-    fn from(buffer: BufferRef<Any>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer, EncodingType::Null)
-    }
-}
-
-impl From<BufferRef<u32>> for TypedBufferRef {
-    fn from(buffer: BufferRef<u32>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::U32)
-    }
-}
-
-impl From<BufferRef<u8>> for TypedBufferRef {
-    fn from(buffer: BufferRef<u8>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::U8)
-    }
-}
-
-impl<'a> From<BufferRef<&'a str>> for TypedBufferRef {
-    fn from(buffer: BufferRef<&'a str>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::Str)
-    }
-}
-
-impl From<BufferRef<i64>> for TypedBufferRef {
-    fn from(buffer: BufferRef<i64>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::I64)
-    }
-}
-
-impl<'a> From<BufferRef<Scalar<&'a str>>> for TypedBufferRef {
-    fn from(buffer: BufferRef<Scalar<&'a str>>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::ScalarStr)
-    }
-}
-
-impl From<BufferRef<Scalar<i64>>> for TypedBufferRef {
-    fn from(buffer: BufferRef<Scalar<i64>>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::ScalarI64)
-    }
-}
-
-impl From<BufferRef<usize>> for TypedBufferRef {
-    fn from(buffer: BufferRef<usize>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::USize)
-    }
-}
-
-impl From<BufferRef<MergeOp>> for TypedBufferRef {
-    fn from(buffer: BufferRef<MergeOp>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::MergeOp)
-    }
-}
-
-impl From<BufferRef<Premerge>> for TypedBufferRef {
-    fn from(buffer: BufferRef<Premerge>) -> TypedBufferRef {
-        TypedBufferRef::new(buffer.any(), EncodingType::Premerge)
-    }
-}
-
-impl<T> BufferRef<Nullable<T>> {
-    pub fn cast_non_nullable(self) -> BufferRef<T> { unsafe { mem::transmute(self) } }
-    pub fn nullable_any(self) -> BufferRef<Nullable<Any>> { unsafe { mem::transmute(self) } }
-}
-
-impl<T: Clone> BufferRef<T> {
-    pub fn any(&self) -> BufferRef<Any> { unsafe { mem::transmute(self.clone()) } }
-}
-
-impl<T> fmt::Display for BufferRef<T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", color_code(&format!("{}_{}", self.name, self.i), self.i))
-    }
-}
-
-fn color_code(s: &str, i: usize) -> String {
-    let colors = vec![
-        "\x1b[31m",
-        "\x1b[32m",
-        "\x1b[33m",
-        "\x1b[34m",
-        "\x1b[35m",
-        "\x1b[36m",
-    ];
-    if std::env::var("COLOR").is_ok() {
-        format!("{}{}\x1b[0m", colors[i % colors.len()], s)
-    } else {
-        s.to_string()
-    }
-}
-
-impl TypedBufferRef {
-    pub fn new(buffer: BufferRef<Any>, tag: EncodingType) -> TypedBufferRef {
-        TypedBufferRef { buffer, tag }
-    }
-
-    pub fn any(&self) -> BufferRef<Any> { self.buffer.any() }
-
-    pub fn forget_nullability(&self) -> TypedBufferRef {
-        TypedBufferRef { buffer: self.buffer, tag: self.tag.non_nullable() }
-    }
-
-    pub fn is_nullable(&self) -> bool { self.tag.is_nullable() }
-
-    pub fn nullable_any<'a>(&self) -> Result<BufferRef<Nullable<Any>>, QueryError> {
-        ensure!(self.tag.is_nullable(), "{:?} is not nullable", self.tag);
-        Ok(self.buffer.cast_nullable_any())
-    }
-
-    pub fn str<'a>(&self) -> Result<BufferRef<&'a str>, QueryError> {
-        ensure!(self.tag == EncodingType::Str, "{:?} != Str", self.tag);
-        Ok(self.buffer.str())
-    }
-
-    pub fn i64(&self) -> Result<BufferRef<i64>, QueryError> {
-        ensure!(self.tag == EncodingType::I64, "{:?} != I64", self.tag);
-        Ok(self.buffer.i64())
-    }
-
-    pub fn u64(&self) -> Result<BufferRef<u64>, QueryError> {
-        ensure!(self.tag == EncodingType::U64, "{:?} != U64", self.tag);
-        Ok(self.buffer.u64())
-    }
-
-    pub fn u32(&self) -> Result<BufferRef<u32>, QueryError> {
-        ensure!(self.tag == EncodingType::U32, "{:?} != U32", self.tag);
-        Ok(self.buffer.u32())
-    }
-
-    pub fn u8(&self) -> Result<BufferRef<u8>, QueryError> {
-        ensure!(self.tag == EncodingType::U8, "{:?} != U8", self.tag);
-        Ok(self.buffer.u8())
-    }
-
-    pub fn nullable_u8(&self) -> Result<BufferRef<Nullable<u8>>, QueryError> {
-        ensure!(self.tag == EncodingType::NullableU8, "{:?} != NullableU8", self.tag);
-        Ok(self.buffer.nullable_u8())
-    }
-
-    pub fn nullable_i64(&self) -> Result<BufferRef<Nullable<i64>>, QueryError> {
-        ensure!(self.tag == EncodingType::NullableI64, "{:?} != NullableI64", self.tag);
-        Ok(self.buffer.nullable_i64())
-    }
-
-    pub fn usize(&self) -> Result<BufferRef<usize>, QueryError> {
-        ensure!(self.tag == EncodingType::USize, "{:?} != USize", self.tag);
-        Ok(self.buffer.usize())
-    }
-
-    pub fn merge_op(&self) -> Result<BufferRef<MergeOp>, QueryError> {
-        ensure!(self.tag == EncodingType::MergeOp, "{:?} != MergeOp", self.tag);
-        Ok(self.buffer.merge_op())
-    }
-
-    pub fn premerge(&self) -> Result<BufferRef<Premerge>, QueryError> {
-        ensure!(self.tag == EncodingType::Premerge, "{:?} != Premerge", self.tag);
-        Ok(self.buffer.premerge())
-    }
-
-    // TODO(clemens): better typing for Constants
-    pub fn raw_val(&self) -> Result<BufferRef<RawVal>, QueryError> {
-        // ensure!(self.tag == EncodingType::Str, "{:?} != Str", self.tag);
-        Ok(self.buffer.raw_val())
-    }
-
-    pub fn string(&self) -> Result<BufferRef<String>, QueryError> {
-        ensure!(self.tag == EncodingType::Val, "{:?} != Val", self.tag);
-        Ok(self.buffer.string())
-    }
-
-    pub fn scalar_i64(&self) -> Result<BufferRef<Scalar<i64>>, QueryError> {
-        ensure!(self.tag == EncodingType::ScalarI64, "{:?} != ScalarI64", self.tag);
-        Ok(self.buffer.scalar_i64())
-    }
-
-    pub fn scalar_str<'a, 'b>(&'b self) -> Result<BufferRef<Scalar<&'a str>>, QueryError> {
-        ensure!(self.tag == EncodingType::ScalarStr, "{:?} != ScalarStr", self.tag);
-        Ok(self.buffer.scalar_str())
-    }
-
-    pub fn scalar_string(&self) -> Result<BufferRef<Scalar<String>>, QueryError> {
-        ensure!(self.tag == EncodingType::ScalarString, "{:?} != ScalaString", self.tag);
-        Ok(self.buffer.scalar_string())
-    }
-}
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::result::Result;
+
+use QueryError;
+use engine::data_types::*;
+use ingest::raw_val::RawVal;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BufferRef<T> {
+    pub i: usize,
+    pub name: &'static str,
+    pub t: PhantomData<T>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TypedBufferRef {
+    pub buffer: BufferRef<Any>,
+    pub tag: EncodingType,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Any {}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Scalar<T> { t: PhantomData<T> }
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Nullable<T> { t: PhantomData<T> }
+
+pub fn error_buffer_ref(name: &'static str) -> BufferRef<Any> {
+    BufferRef {
+        i: 0xdead_beef,
+        name,
+        t: PhantomData,
+    }
+}
+
+impl BufferRef<Any> {
+    pub fn merge_op(self) -> BufferRef<MergeOp> { self.transmute() }
+    pub fn premerge(self) -> BufferRef<Premerge> { self.transmute() }
+    pub fn raw_val(self) -> BufferRef<RawVal> { self.transmute() }
+    pub fn i64(self) -> BufferRef<i64> { self.transmute() }
+    pub fn f64(self) -> BufferRef<OrderedF64> { self.transmute() }
+    pub fn u64(self) -> BufferRef<u64> { self.transmute() }
+    pub fn u32(self) -> BufferRef<u32> { self.transmute() }
+    pub fn u16(self) -> BufferRef<u16> { self.transmute() }
+    pub fn u8(self) -> BufferRef<u8> { self.transmute() }
+
+    pub fn cast_nullable_any(&self) -> BufferRef<Nullable<Any>> { self.transmute() }
+
+    pub fn nullable_u8(self) -> BufferRef<Nullable<u8>> { self.transmute() }
+    pub fn nullable_u16(self) -> BufferRef<Nullable<u16>> { self.transmute() }
+    pub fn nullable_u32(self) -> BufferRef<Nullable<u32>> { self.transmute() }
+    pub fn nullable_i64(self) -> BufferRef<Nullable<i64>> { self.transmute() }
+    pub fn nullable_str<'a>(self) -> BufferRef<Nullable<&'a str>> { self.transmute() }
+
+    pub fn scalar_i64(self) -> BufferRef<Scalar<i64>> { self.transmute() }
+    pub fn scalar_f64(self) -> BufferRef<Scalar<OrderedF64>> { self.transmute() }
+    pub fn scalar_str<'a>(self) -> BufferRef<Scalar<&'a str>> { self.transmute() }
+    pub fn scalar_string<'a>(self) -> BufferRef<Scalar<String>> { self.transmute() }
+
+    pub fn string(self) -> BufferRef<String> { self.transmute() }
+    pub fn str<'a>(self) -> BufferRef<&'a str> { self.transmute() }
+    pub fn keyed_val<'a>(self) -> BufferRef<KeyedVal<'a>> { self.transmute() }
+    pub fn usize(self) -> BufferRef<usize> { self.transmute() }
+    fn transmute<T>(self) -> BufferRef<T> { unsafe { mem::transmute(self) } }
+}
+
+// TODO(clemens): remove this, temporary hack because ther is no buffer type for ByteSlices
+impl From<BufferRef<Any>> for TypedBufferRef {
+    fn from(buffer: BufferRef<Any>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer, EncodingType::Null)
+    }
+}
+
+impl From<BufferRef<u32>> for TypedBufferRef {
+    fn from(buffer: BufferRef<u32>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::U32)
+    }
+}
+
+impl From<BufferRef<u8>> for TypedBufferRef {
+    fn from(buffer: BufferRef<u8>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::U8)
+    }
+}
+
+impl<'a> From<BufferRef<&'a str>> for TypedBufferRef {
+    fn from(buffer: BufferRef<&'a str>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::Str)
+    }
+}
+
+impl From<BufferRef<i64>> for TypedBufferRef {
+    fn from(buffer: BufferRef<i64>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::I64)
+    }
+}
+
+impl From<BufferRef<OrderedF64>> for TypedBufferRef {
+    fn from(buffer: BufferRef<OrderedF64>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::F64)
+    }
+}
+
+impl<'a> From<BufferRef<Scalar<&'a str>>> for TypedBufferRef {
+    fn from(buffer: BufferRef<Scalar<&'a str>>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::ScalarStr)
+    }
+}
+
+impl From<BufferRef<Scalar<i64>>> for TypedBufferRef {
+    fn from(buffer: BufferRef<Scalar<i64>>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::ScalarI64)
+    }
+}
+
+impl From<BufferRef<Scalar<OrderedF64>>> for TypedBufferRef {
+    fn from(buffer: BufferRef<Scalar<OrderedF64>>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::ScalarF64)
+    }
+}
+
+impl From<BufferRef<usize>> for TypedBufferRef {
+    fn from(buffer: BufferRef<usize>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::USize)
+    }
+}
+
+impl From<BufferRef<MergeOp>> for TypedBufferRef {
+    fn from(buffer: BufferRef<MergeOp>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::MergeOp)
+    }
+}
+
+impl From<BufferRef<Premerge>> for TypedBufferRef {
+    fn from(buffer: BufferRef<Premerge>) -> TypedBufferRef {
+        TypedBufferRef::new(buffer.any(), EncodingType::Premerge)
+    }
+}
+
+impl<T> BufferRef<Nullable<T>> {
+    pub fn cast_non_nullable(self) -> BufferRef<T> { unsafe { mem::transmute(self) } }
+    pub fn nullable_any(self) -> BufferRef<Nullable<Any>> { unsafe { mem::transmute(self) } }
+}
+
+impl<T: Clone> BufferRef<T> {
+    pub fn any(&self) -> BufferRef<Any> { unsafe { mem::transmute(self.clone()) } }
+}
+
+impl<T> fmt::Display for BufferRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", color_code(&format!("{}_{}", self.name, self.i), self.i))
+    }
+}
+
+fn color_code(s: &str, i: usize) -> String {
+    let colors = vec![
+        "\x1b[31m",
+        "\x1b[32m",
+        "\x1b[33m",
+        "\x1b[34m",
+        "\x1b[35m",
+        "\x1b[36m",
+    ];
+    if std::env::var("COLOR").is_ok() {
+        format!("{}{}\x1b[0m", colors[i % colors.len()], s)
+    } else {
+        s.to_string()
+    }
+}
+
+impl TypedBufferRef {
+    pub fn new(buffer: BufferRef<Any>, tag: EncodingType) -> TypedBufferRef {
+        TypedBufferRef { buffer, tag }
+    }
+
+    pub fn any(&self) -> BufferRef<Any> { self.buffer.any() }
+
+    pub fn forget_nullability(&self) -> TypedBufferRef {
+        TypedBufferRef { buffer: self.buffer, tag: self.tag.non_nullable() }
+    }
+
+    pub fn is_nullable(&self) -> bool { self.tag.is_nullable() }
+
+    pub fn nullable_any<'a>(&self) -> Result<BufferRef<Nullable<Any>>, QueryError> {
+        ensure!(self.tag.is_nullable(), "{:?} is not nullable", self.tag);
+        Ok(self.buffer.cast_nullable_any())
+    }
+
+    pub fn str<'a>(&self) -> Result<BufferRef<&'a str>, QueryError> {
+        ensure!(self.tag == EncodingType::Str, "{:?} != Str", self.tag);
+        Ok(self.buffer.str())
+    }
+
+    pub fn i64(&self) -> Result<BufferRef<i64>, QueryError> {
+        ensure!(self.tag == EncodingType::I64, "{:?} != I64", self.tag);
+        Ok(self.buffer.i64())
+    }
+
+    pub fn f64(&self) -> Result<BufferRef<OrderedF64>, QueryError> {
+        ensure!(self.tag == EncodingType::F64, "{:?} != F64", self.tag);
+        Ok(self.buffer.f64())
+    }
+
+    pub fn u64(&self) -> Result<BufferRef<u64>, QueryError> {
+        ensure!(self.tag == EncodingType::U64, "{:?} != U64", self.tag);
+        Ok(self.buffer.u64())
+    }
+
+    pub fn u32(&self) -> Result<BufferRef<u32>, QueryError> {
+        ensure!(self.tag == EncodingType::U32, "{:?} != U32", self.tag);
+        Ok(self.buffer.u32())
+    }
+
+    pub fn u8(&self) -> Result<BufferRef<u8>, QueryError> {
+        ensure!(self.tag == EncodingType::U8, "{:?} != U8", self.tag);
+        Ok(self.buffer.u8())
+    }
+
+    pub fn nullable_u8(&self) -> Result<BufferRef<Nullable<u8>>, QueryError> {
+        ensure!(self.tag == EncodingType::NullableU8, "{:?} != NullableU8", self.tag);
+        Ok(self.buffer.nullable_u8())
+    }
+
+    pub fn nullable_i64(&self) -> Result<BufferRef<Nullable<i64>>, QueryError> {
+        ensure!(self.tag == EncodingType::NullableI64, "{:?} != NullableI64", self.tag);
+        Ok(self.buffer.nullable_i64())
+    }
+
+    pub fn nullable_str<'a>(&self) -> Result<BufferRef<Nullable<&'a str>>, QueryError> {
+        ensure!(self.tag == EncodingType::NullableStr, "{:?} != NullableStr", self.tag);
+        Ok(self.buffer.nullable_str())
+    }
+
+    pub fn keyed_val<'a>(&self) -> Result<BufferRef<KeyedVal<'a>>, QueryError> {
+        ensure!(self.tag == EncodingType::KeyedVal, "{:?} != KeyedVal", self.tag);
+        Ok(self.buffer.keyed_val())
+    }
+
+    pub fn usize(&self) -> Result<BufferRef<usize>, QueryError> {
+        ensure!(self.tag == EncodingType::USize, "{:?} != USize", self.tag);
+        Ok(self.buffer.usize())
+    }
+
+    pub fn merge_op(&self) -> Result<BufferRef<MergeOp>, QueryError> {
+        ensure!(self.tag == EncodingType::MergeOp, "{:?} != MergeOp", self.tag);
+        Ok(self.buffer.merge_op())
+    }
+
+    pub fn premerge(&self) -> Result<BufferRef<Premerge>, QueryError> {
+        ensure!(self.tag == EncodingType::Premerge, "{:?} != Premerge", self.tag);
+        Ok(self.buffer.premerge())
+    }
+
+    // TODO(clemens): better typing for Constants
+    pub fn raw_val(&self) -> Result<BufferRef<RawVal>, QueryError> {
+        // ensure!(self.tag == EncodingType::Str, "{:?} != Str", self.tag);
+        Ok(self.buffer.raw_val())
+    }
+
+    pub fn string(&self) -> Result<BufferRef<String>, QueryError> {
+        ensure!(self.tag == EncodingType::Val, "{:?} != Val", self.tag);
+        Ok(self.buffer.string())
+    }
+
+    pub fn scalar_i64(&self) -> Result<BufferRef<Scalar<i64>>, QueryError> {
+        ensure!(self.tag == EncodingType::ScalarI64, "{:?} != ScalarI64", self.tag);
+        Ok(self.buffer.scalar_i64())
+    }
+
+    pub fn scalar_f64(&self) -> Result<BufferRef<Scalar<OrderedF64>>, QueryError> {
+        ensure!(self.tag == EncodingType::ScalarF64, "{:?} != ScalarF64", self.tag);
+        Ok(self.buffer.scalar_f64())
+    }
+
+    pub fn scalar_str<'a, 'b>(&'b self) -> Result<BufferRef<Scalar<&'a str>>, QueryError> {
+        ensure!(self.tag == EncodingType::ScalarStr, "{:?} != ScalarStr", self.tag);
+        Ok(self.buffer.scalar_str())
+    }
+
+    pub fn scalar_string(&self) -> Result<BufferRef<Scalar<String>>, QueryError> {
+        ensure!(self.tag == EncodingType::ScalarString, "{:?} != ScalaString", self.tag);
+        Ok(self.buffer.scalar_string())
+    }
+}
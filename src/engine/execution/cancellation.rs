@@ -0,0 +1,22 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+/// Shared flag that signals a running query to stop.
+///
+/// Queries check the flag before processing each partition and between the operators of each partition, so a
+/// cancelled query stops consuming CPU shortly after `cancel` is called and fails with `QueryError::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
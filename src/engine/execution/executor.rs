@@ -4,6 +4,9 @@ use std::fmt;
 use std::marker::PhantomData;
 
 use bitvec::BitVec;
+use regex::Regex;
+
+use QueryError;
 use engine::*;
 use super::*;
 use ingest::raw_val::RawVal;
@@ -13,10 +16,16 @@ pub struct QueryExecutor<'a> {
     ops: Vec<Box<VecOperator<'a> + 'a>>,
     stages: Vec<ExecutorStage>,
     count: usize,
+    buffer_types: Vec<EncodingType>,
     last_buffer: TypedBufferRef,
     shared_buffers: HashMap<&'static str, TypedBufferRef>,
+    limits: Option<QueryLimits>,
+    batch_size: usize,
 }
 
+/// Number of rows processed at a time by streaming stages unless configured otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
 #[derive(Default, Clone)]
 struct ExecutorStage {
     // Vec<(index to op, streamable output)>
@@ -25,11 +34,16 @@ struct ExecutorStage {
 }
 
 impl<'a> QueryExecutor<'a> {
-    pub fn set_buffer_count(&mut self, count: usize) { self.count = count }
+    /// Sets the types of the buffers allocated by the planner.
+    pub fn set_buffer_types(&mut self, buffer_types: Vec<EncodingType>) {
+        self.count = buffer_types.len();
+        self.buffer_types = buffer_types;
+    }
 
     pub fn named_buffer(&mut self, name: &'static str, tag: EncodingType) -> TypedBufferRef {
         let buffer = TypedBufferRef::new(BufferRef { i: self.count, name, t: PhantomData }, tag);
         self.count += 1;
+        self.buffer_types.push(tag);
         self.last_buffer = buffer;
         buffer
     }
@@ -107,12 +121,26 @@ impl<'a> QueryExecutor<'a> {
         Scratchpad::new(self.count, HashMap::default())
     }
 
-    pub fn run(&mut self, len: usize, scratchpad: &mut Scratchpad<'a>, show: bool) {
+    /// Makes `run` fail before executing the next operator once the query is cancelled or exceeds `limits`.
+    pub fn set_limits(&mut self, limits: QueryLimits) {
+        self.limits = Some(limits);
+    }
+
+    /// Sets the number of rows that stages reading from columns process at a time.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size;
+    }
+
+    pub fn run(&mut self, len: usize, scratchpad: &mut Scratchpad<'a>, show: bool) -> Result<(), QueryError> {
+        // Buffers of the scratchpad count towards the memory limit of the query until the run completes
+        let mut reservation = self.limits.as_ref().map(QueryLimits::reservation);
         for stage in 0..self.stages.len() {
-            self.run_stage(len, stage, scratchpad, show);
+            self.run_stage(len, stage, scratchpad, &mut reservation, show)?;
         }
+        Ok(())
     }
 
+
     // TODO(clemens): Make this nicer?
     #[allow(clippy::cyclomatic_complexity)]
     fn partition(&self) -> Vec<ExecutorStage> {
@@ -420,7 +448,7 @@ impl<'a> QueryExecutor<'a> {
             max_input_length = column_length;
         }
         let batch_size = if self.stages[stage].stream {
-            1024
+            self.batch_size
         } else {
             max_input_length
         };
@@ -435,7 +463,12 @@ impl<'a> QueryExecutor<'a> {
         (max_input_length, batch_size)
     }
 
-    fn run_stage(&mut self, column_length: usize, stage: usize, scratchpad: &mut Scratchpad<'a>, show: bool) {
+    fn run_stage(&mut self,
+                 column_length: usize,
+                 stage: usize,
+                 scratchpad: &mut Scratchpad<'a>,
+                 reservation: &mut Option<MemoryReservation>,
+                 show: bool) -> Result<(), QueryError> {
         let (max_length, batch_size) = self.init_stage(column_length, stage, scratchpad);
         let stream = self.stages[stage].stream;
         if show {
@@ -447,7 +480,16 @@ impl<'a> QueryExecutor<'a> {
         while has_more {
             has_more = false;
             for &(op, streamable) in &self.stages[stage].ops {
+                if let Some(ref limits) = self.limits {
+                    limits.check()?;
+                }
                 self.ops[op].execute(stream && streamable, scratchpad);
+                if let Some(error) = self.ops[op].error() {
+                    return Err(error);
+                }
+                if let Some(ref mut reservation) = *reservation {
+                    reservation.resize(scratchpad.size_bytes())?;
+                }
                 if show && iters == 0 {
                     println!("{}", self.ops[op].display(true));
                     for output in self.ops[op].outputs() {
@@ -472,6 +514,7 @@ impl<'a> QueryExecutor<'a> {
         if show && iters > 1 {
             println!("\n[{} more iterations]", iters - 1);
         }
+        Ok(())
     }
 }
 
@@ -481,12 +524,52 @@ impl<'a> Default for QueryExecutor<'a> {
             ops: vec![],
             stages: vec![],
             count: 0,
+            buffer_types: vec![],
             last_buffer: TypedBufferRef::new(error_buffer_ref("ERROR"), EncodingType::Null),
             shared_buffers: HashMap::default(),
+            limits: None,
+            batch_size: DEFAULT_BATCH_SIZE,
         }
     }
 }
 
+impl<'a> QueryExecutor<'a> {
+    /// Structured representation of the operators, buffers and stages, only complete after `prepare` has been called.
+    pub fn plan_graph(&self) -> PlanGraph {
+        let ansi_color = Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        let mut buffers = HashMap::new();
+        let stages = self.stages.iter()
+            .map(|stage| PlanStage {
+                streaming: stage.stream,
+                operators: stage.ops.iter()
+                    .map(|&(i, _)| {
+                        let op = &self.ops[i];
+                        for buffer in op.inputs().into_iter().chain(op.outputs()) {
+                            buffers.entry(buffer.i).or_insert(buffer.name);
+                        }
+                        PlanOperator {
+                            id: i,
+                            operator: op.type_name(),
+                            description: ansi_color.replace_all(&op.display_op(false), "").into_owned(),
+                            inputs: op.inputs().iter().map(|b| b.i).collect(),
+                            outputs: op.outputs().iter().map(|b| b.i).collect(),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        let mut buffers = buffers.into_iter()
+            .map(|(i, name)| PlanBuffer {
+                id: i,
+                name: name.to_string(),
+                encoding: self.buffer_types.get(i).map_or_else(|| "?".to_string(), |t| format!("{:?}", t)),
+            })
+            .collect::<Vec<_>>();
+        buffers.sort_by_key(|b| b.id);
+        PlanGraph::new(stages, buffers, format!("{}", self))
+    }
+}
+
 impl<'a> fmt::Display for QueryExecutor<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let alternate = f.alternate();
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use time::precise_time_ns;
+
+use QueryError;
+use super::CancellationToken;
+
+
+/// Conditions under which a running query is stopped.
+#[derive(Clone, Debug, Default)]
+pub struct QueryLimits {
+    pub cancellation: CancellationToken,
+    /// Time at which the query fails, as returned by `precise_time_ns`.
+    pub deadline_ns: Option<u64>,
+    /// Maximum number of bytes of intermediate buffers and partial results held by all threads of the query.
+    pub max_bytes: Option<usize>,
+    /// Bytes currently held by all threads of the query, shared by all clones of the limits.
+    used_bytes: Arc<AtomicUsize>,
+}
+
+impl QueryLimits {
+    /// Limits for a query that is started now and may run for at most `timeout`.
+    pub fn new(cancellation: CancellationToken, timeout: Option<Duration>, max_bytes: Option<usize>) -> QueryLimits {
+        let deadline_ns = timeout.map(|timeout| {
+            precise_time_ns() + timeout.as_secs() * 1_000_000_000 + u64::from(timeout.subsec_nanos())
+        });
+        QueryLimits { cancellation, deadline_ns, max_bytes, used_bytes: Arc::default() }
+    }
+
+    /// Fails if the query was cancelled or exceeded its timeout.
+    pub fn check(&self) -> Result<(), QueryError> {
+        if self.cancellation.is_cancelled() {
+            return Err(QueryError::Cancelled);
+        }
+        if let Some(deadline_ns) = self.deadline_ns {
+            if precise_time_ns() > deadline_ns {
+                bail!(QueryError::ResourceExhausted, "Query exceeded its timeout");
+            }
+        }
+        Ok(())
+    }
+
+    /// Memory held by one thread of the query, which counts towards `max_bytes` until the reservation is dropped.
+    pub fn reservation(&self) -> MemoryReservation {
+        MemoryReservation {
+            max_bytes: self.max_bytes,
+            used_bytes: self.used_bytes.clone(),
+            bytes: 0,
+        }
+    }
+}
+
+pub struct MemoryReservation {
+    max_bytes: Option<usize>,
+    used_bytes: Arc<AtomicUsize>,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    /// Sets the bytes held by the reservation to `bytes`. Fails if that increases the bytes held by the query beyond
+    /// `max_bytes`. Nothing is tracked for queries without memory limit.
+    pub fn resize(&mut self, bytes: usize) -> Result<(), QueryError> {
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return Ok(()),
+        };
+        if bytes <= self.bytes {
+            self.used_bytes.fetch_sub(self.bytes - bytes, Ordering::SeqCst);
+            self.bytes = bytes;
+            return Ok(());
+        }
+        let used = self.used_bytes.fetch_add(bytes - self.bytes, Ordering::SeqCst) + bytes - self.bytes;
+        self.bytes = bytes;
+        if used > max_bytes {
+            bail!(QueryError::ResourceExhausted,
+                  "Query holds {} bytes of intermediate results, which exceeds the limit of {} bytes", used, max_bytes);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.used_bytes.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
@@ -1,10 +1,33 @@
-pub mod query_task;
-mod buffer;
-mod executor;
-mod batch_merging;
-mod scratchpad;
-
-pub use self::buffer::*;
-pub use self::scratchpad::*;
-pub use self::executor::*;
-pub use self::batch_merging::{BatchResult, combine};
\ No newline at end of file
+pub mod query_task;
+pub mod scan;
+mod buffer;
+mod cancellation;
+mod executor;
+mod limits;
+mod plan_graph;
+mod batch_merging;
+mod scratchpad;
+mod window;
+mod rollup;
+mod result_cache;
+mod subresult_cache;
+mod unnest;
+mod serialization;
+mod spill;
+#[cfg(feature = "serialize")]
+mod row_deserializer;
+
+pub use self::buffer::*;
+pub use self::cancellation::CancellationToken;
+pub use self::limits::{MemoryReservation, QueryLimits};
+pub use self::scratchpad::*;
+pub use self::executor::*;
+pub use self::plan_graph::{PlanBuffer, PlanEdge, PlanGraph, PlanOperator, PlanStage};
+pub use self::batch_merging::{BatchResult, combine, merge_sorted};
+pub use self::window::WindowStage;
+pub use self::unnest::UnnestStage;
+pub use self::serialization::{SerializedBatch, SerializedColumn};
+pub use self::spill::{MergePolicy, PartialResult, SpilledBatch};
+pub use self::rollup::rollup;
+pub use self::result_cache::{CacheKey, ResultCache};
+pub use self::subresult_cache::{SubresultCache, SubresultKey};
\ No newline at end of file
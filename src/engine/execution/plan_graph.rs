@@ -0,0 +1,143 @@
+use std::fmt;
+use std::fmt::Write;
+
+use ingest::list::push_json_string;
+
+
+/// Structured representation of a compiled query plan which can be serialized as JSON or Graphviz DOT.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct PlanGraph {
+    pub stages: Vec<PlanStage>,
+    pub buffers: Vec<PlanBuffer>,
+    text: String,
+}
+
+/// Operators that are executed together, either in a single pass or streamed in batches.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct PlanStage {
+    pub streaming: bool,
+    pub operators: Vec<PlanOperator>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct PlanOperator {
+    pub id: usize,
+    /// Name of the type that implements the operator, e.g. `DictLookup<u8>`.
+    pub operator: String,
+    pub description: String,
+    /// Ids of the buffers read by the operator.
+    pub inputs: Vec<usize>,
+    /// Ids of the buffers written by the operator.
+    pub outputs: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct PlanBuffer {
+    pub id: usize,
+    pub name: String,
+    pub encoding: String,
+}
+
+/// Buffer written by operator `from` and read by operator `to`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct PlanEdge {
+    pub from: usize,
+    pub to: usize,
+    pub buffer: usize,
+}
+
+impl PlanGraph {
+    /// `text` is the plan as formatted by the executor, which is returned by `Display`.
+    pub fn new(stages: Vec<PlanStage>, buffers: Vec<PlanBuffer>, text: String) -> PlanGraph {
+        PlanGraph { stages, buffers, text }
+    }
+
+    pub fn operators(&self) -> impl Iterator<Item=&PlanOperator> {
+        self.stages.iter().flat_map(|stage| stage.operators.iter())
+    }
+
+    /// Connects each operator with all operators that read one of its outputs.
+    pub fn edges(&self) -> Vec<PlanEdge> {
+        let mut edges = Vec::new();
+        for producer in self.operators() {
+            for &buffer in &producer.outputs {
+                for consumer in self.operators() {
+                    if consumer.id != producer.id && consumer.inputs.contains(&buffer) {
+                        edges.push(PlanEdge { from: producer.id, to: consumer.id, buffer });
+                    }
+                }
+            }
+        }
+        edges
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"stages\":[");
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 { json.push(','); }
+            write!(json, "{{\"streaming\":{},\"operators\":[", stage.streaming).unwrap();
+            for (j, op) in stage.operators.iter().enumerate() {
+                if j > 0 { json.push(','); }
+                write!(json, "{{\"id\":{},\"operator\":", op.id).unwrap();
+                push_json_string(&mut json, &op.operator);
+                json.push_str(",\"description\":");
+                push_json_string(&mut json, &op.description);
+                write!(json, ",\"inputs\":{:?},\"outputs\":{:?}}}", op.inputs, op.outputs).unwrap();
+            }
+            json.push_str("]}");
+        }
+        json.push_str("],\"buffers\":[");
+        for (i, buffer) in self.buffers.iter().enumerate() {
+            if i > 0 { json.push(','); }
+            write!(json, "{{\"id\":{},\"name\":", buffer.id).unwrap();
+            push_json_string(&mut json, &buffer.name);
+            json.push_str(",\"encoding\":");
+            push_json_string(&mut json, &buffer.encoding);
+            json.push('}');
+        }
+        json.push_str("],\"edges\":[");
+        for (i, edge) in self.edges().iter().enumerate() {
+            if i > 0 { json.push(','); }
+            write!(json, "{{\"from\":{},\"to\":{},\"buffer\":{}}}", edge.from, edge.to, edge.buffer).unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph plan {\n    node [shape=box];\n");
+        for (i, stage) in self.stages.iter().enumerate() {
+            writeln!(dot, "    subgraph cluster_{} {{", i).unwrap();
+            writeln!(dot, "        label=\"Stage {}{}\";", i, if stage.streaming { " (streaming)" } else { "" }).unwrap();
+            for op in &stage.operators {
+                writeln!(dot, "        op{} [label=\"{}\\n{}\"];",
+                         op.id, escape_dot(&op.operator), escape_dot(&op.description)).unwrap();
+            }
+            writeln!(dot, "    }}").unwrap();
+        }
+        for edge in self.edges() {
+            let label = match self.buffers.iter().find(|b| b.id == edge.buffer) {
+                Some(buffer) => format!("{}_{}: {}", buffer.name, buffer.id, buffer.encoding),
+                None => format!("{}", edge.buffer),
+            };
+            writeln!(dot, "    op{} -> op{} [label=\"{}\"];", edge.from, edge.to, escape_dot(&label)).unwrap();
+        }
+        dot.push('}');
+        dot
+    }
+}
+
+impl fmt::Display for PlanGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
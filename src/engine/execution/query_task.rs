@@ -1,4 +1,5 @@
 use std::cmp;
+use std::usize;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::iter::Iterator;
@@ -6,61 +7,201 @@ use std::mem;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
+use std::time::Duration;
+
+use futures_channel::mpsc;
+use futures_core::{Async, Poll, Stream};
+use futures_core::task;
 
 use ::QueryError;
 use QueryResult;
 use engine::*;
 use ingest::raw_val::RawVal;
-use mem_store::partition::Partition;
-use mem_store::column::DataSource;
+use mem_store::partition::{DELETED_COL, Partition};
+use mem_store::column::{Column, DataSource};
+use mem_store::SharedDictionary;
 use scheduler::*;
 use scheduler::disk_read_scheduler::DiskReadScheduler;
+use scheduler::epochs::EpochGuard;
+use scheduler::topology;
 use syntax::expression::*;
 use time::precise_time_ns;
+use udf::ValueType;
 
 
 pub struct QueryTask {
     main_phase: NormalFormQuery,
+    /// Used instead of `main_phase` for partitions that contain deleted rows.
+    main_phase_excluding_deleted: NormalFormQuery,
     final_pass: Option<NormalFormQuery>,
+    aggregate_ordering: Vec<(usize, bool)>,
+    window_stage: Option<WindowStage>,
+    unnest_stage: Option<UnnestStage>,
+    rollup: bool,
+    /// Dictionary shared by all partitions of the group by column if groups are merged on its codes.
+    shared_dictionary: Option<Arc<SharedDictionary>>,
     explain: bool,
     show: Vec<usize>,
     partitions: Vec<Arc<Partition>>,
     referenced_cols: HashSet<String>,
+    /// Referenced columns that exist in at least one partition, they are all null in partitions that lack them.
+    table_cols: HashSet<String>,
     output_colnames: Vec<String>,
     start_time_ns: u64,
     db: Arc<DiskReadScheduler>,
+    limits: QueryLimits,
+    /// Number of rows processed at a time by streaming stages.
+    batch_size: usize,
+    /// Priority of queued tasks that the query yields to and time in nanoseconds after which it becomes a bulk query.
+    preemption: Option<(Arc<PendingPriority>, u64)>,
+    partitions_pruned: usize,
+    sampling_factor: f64,
+    merge_policy: MergePolicy,
+    /// Whether partitions are processed in order on a single thread and groups are ordered canonically.
+    deterministic: bool,
+    /// Cache that stores the result together with the version of the queried table it was computed from.
+    result_cache: Option<(Arc<ResultCache>, CacheKey, usize)>,
+    /// Cache for the results of individual partitions together with the normalized query they are stored under.
+    subresult_cache: Option<(Arc<SubresultCache>, String)>,
+    /// Epoch pinned before the partitions were snapshotted, which keeps them from being deleted while the query runs.
+    #[allow(dead_code)]
+    epoch: Option<EpochGuard>,
 
     // Lifetime is not actually static, but tied to the lifetime of this struct.
     // There is currently no good way to express this constraint in Rust.
     // Accessing pointers derived from unsafe_state after it has been dropped violates memory safety.
     // TODO(clemens): better encapsulate unsafety using some abstraction such as the refstruct crate.
     unsafe_state: Mutex<QueryState<'static>>,
+    /// Number of partitions that have been claimed by a thread.
     batch_index: AtomicUsize,
+    /// Indices of the partitions created on each NUMA node, and the number of them that have been claimed.
+    node_partitions: Vec<Vec<usize>>,
+    node_cursors: Vec<AtomicUsize>,
+    /// Rows collected by all threads, no further partitions are scanned once they satisfy the limit.
+    rows_collected: AtomicUsize,
     completed: AtomicBool,
-    sender: SharedSender<QueryResult>,
+    sink: ResultSink,
 }
 
 pub struct QueryState<'a> {
     completed_batches: usize,
     partial_results: Vec<BatchResult<'a>>,
-    explains: Vec<String>,
+    /// Number of partial results that are currently being merged outside of the lock.
+    merging: usize,
+    explains: Vec<PlanGraph>,
     rows_scanned: usize,
     rows_collected: usize,
+    /// Number of rows that were considered for the result stream, including rows skipped by the offset.
+    rows_streamed: usize,
     colstacks: Vec<Vec<HashMap<String, Arc<DataSource>>>>,
+    /// Spilled partial results that were read back from disk and may be referenced by other results.
+    unspilled: Vec<Vec<Box<SerializedBatch>>>,
 }
 
+#[derive(Clone)]
 pub struct QueryOutput {
     pub colnames: Vec<String>,
+    /// Type of the values of each column, `None` if its values can have different types.
+    pub coltypes: Vec<Option<ValueType>>,
     pub rows: Vec<Vec<RawVal>>,
     pub query_plans: HashMap<String, u32>,
+    /// Structured representation of each distinct plan in `query_plans`.
+    pub plan_graphs: Vec<PlanGraph>,
     pub stats: QueryStats,
 }
 
+#[cfg(feature = "enable_arrow")]
+impl QueryOutput {
+    /// Converts the result rows into an Arrow record batch.
+    pub fn to_record_batch(&self) -> ::arrow::record_batch::RecordBatch {
+        ::ingest::arrow_loader::to_record_batch(&self.colnames, &self.rows)
+    }
+
+    /// Converts the result rows into record batches of at most `batch_size` rows that share the same schema.
+    pub fn to_record_batches(&self, batch_size: usize) -> Vec<::arrow::record_batch::RecordBatch> {
+        ::ingest::arrow_loader::to_record_batches(&self.colnames, &self.rows, batch_size)
+    }
+}
+
+/// Receives the result of a query.
+pub enum ResultSink {
+    /// Receives the complete result once the query has finished.
+    Complete(SharedSender<QueryResult>),
+    /// Receives the rows of queries without aggregation or ordering as soon as each partition has been processed,
+    /// and the complete result of all other queries. The sender is dropped after the last result.
+    Stream(Mutex<Option<mpsc::UnboundedSender<QueryResult>>>),
+}
+
+impl ResultSink {
+    pub fn stream(sender: mpsc::UnboundedSender<QueryResult>) -> ResultSink {
+        ResultSink::Stream(Mutex::new(Some(sender)))
+    }
+
+    fn is_stream(&self) -> bool {
+        match *self {
+            ResultSink::Stream(_) => true,
+            ResultSink::Complete(_) => false,
+        }
+    }
+
+    /// Sends the last result of the query.
+    fn send(&self, result: QueryResult) {
+        match *self {
+            ResultSink::Complete(ref sender) => sender.send(result),
+            ResultSink::Stream(ref sender) => if let Some(sender) = sender.lock().unwrap().take() {
+                let _ = sender.unbounded_send(result);
+            },
+        }
+    }
+
+    /// Sends a partial result to the stream, ignored if the complete result is expected.
+    fn send_batch(&self, result: QueryResult) {
+        if let ResultSink::Stream(ref sender) = *self {
+            if let Some(ref sender) = *sender.lock().unwrap() {
+                let _ = sender.unbounded_send(result);
+            }
+        }
+    }
+}
+
+/// Results of a query that are returned as they become available.
+///
+/// Each item contains the rows of one or more partitions together with the statistics of all partitions processed
+/// so far, the last item also contains the query plans. Results of queries that aggregate or order rows are returned
+/// as a single item.
+pub struct QueryStream {
+    batches: mpsc::UnboundedReceiver<QueryResult>,
+}
+
+impl QueryStream {
+    pub fn new(batches: mpsc::UnboundedReceiver<QueryResult>) -> QueryStream {
+        QueryStream { batches }
+    }
+}
+
+impl Stream for QueryStream {
+    type Item = QueryOutput;
+    type Error = QueryError;
+
+    fn poll_next(&mut self, cx: &mut task::Context) -> Poll<Option<QueryOutput>, QueryError> {
+        match self.batches.poll_next(cx) {
+            Ok(Async::Ready(Some(Ok(batch)))) => Ok(Async::Ready(Some(batch))),
+            Ok(Async::Ready(Some(Err(err)))) => Err(err),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::Pending) => Ok(Async::Pending),
+            Err(never) => match never {},
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct QueryStats {
     pub runtime_ns: u64,
     pub rows_scanned: usize,
+    /// Number of partitions that were skipped because they contain no rows matching the filter.
+    pub partitions_pruned: usize,
+    /// Fraction of the rows of the table that the query was run on, less than 1 if the query samples the table.
+    pub sampling_factor: f64,
 }
 
 impl Default for QueryStats {
@@ -68,121 +209,449 @@ impl Default for QueryStats {
         QueryStats {
             runtime_ns: 0,
             rows_scanned: 0,
+            partitions_pruned: 0,
+            sampling_factor: 1.0,
         }
     }
 }
 
 
-impl QueryTask {
-    pub fn new(mut query: Query, explain: bool, show: Vec<usize>,
-               source: Vec<Arc<Partition>>,
-               db: Arc<DiskReadScheduler>,
-               sender: SharedSender<QueryResult>) -> QueryTask {
-        let start_time_ns = precise_time_ns();
-        if query.is_select_star() {
-            query.select = find_all_cols(&source).into_iter().map(Expr::ColName).collect();
+/// Query that has been validated against the columns of a table and normalized.
+///
+/// Normalization doesn't depend on the partitions that are queried, so prepared queries keep the compiled query and
+/// only bind their parameters into it when they are run.
+#[derive(Debug, Clone)]
+pub struct CompiledQuery {
+    main_phase: NormalFormQuery,
+    final_pass: Option<NormalFormQuery>,
+    window_stage: Option<WindowStage>,
+    unnest_stage: Option<UnnestStage>,
+    rollup: bool,
+    referenced_cols: HashSet<String>,
+    /// Alias of each result column, in the order of the result columns.
+    aliases: Vec<Option<String>>,
+}
+
+impl CompiledQuery {
+    pub fn new(mut query: Query, all_cols: &[String]) -> Result<CompiledQuery, QueryError> {
+        query.expand_wildcards(all_cols)?;
+        query.validate(all_cols)?;
+
+        let mut aliases = mem::replace(&mut query.aliases, vec![]);
+        let mut window_stage = WindowStage::extract(&mut query);
+        let unnest_stage = UnnestStage::extract(&mut query)?;
+        let rollup = query.rollup;
+
+        let mut referenced_cols = query.find_referenced_cols();
+        if let Some(ref unnest_stage) = unnest_stage {
+            referenced_cols.insert(unnest_stage.column().to_string());
         }
 
-        let referenced_cols = query.find_referenced_cols();
+        let (main_phase, final_pass) = {
+            let _span = trace_span!("Normalize");
+            query.normalize()
+        };
+        let aggregates_last = final_pass.is_none() && !main_phase.aggregate.is_empty();
+        if let Some(ref mut window_stage) = window_stage {
+            window_stage.set_aggregates_last(aggregates_last);
+        } else if aggregates_last {
+            let (mut selected, aggregated): (Vec<_>, Vec<_>) = aliases.into_iter()
+                .zip(query.select.iter())
+                .partition(|(_, expr)| Query::extract_aggregators(expr, &mut vec![]).1.is_empty());
+            selected.extend(aggregated);
+            aliases = selected.into_iter().map(|(alias, _)| alias).collect();
+        }
+
+        Ok(CompiledQuery {
+            main_phase,
+            final_pass,
+            window_stage,
+            unnest_stage,
+            rollup,
+            referenced_cols,
+            aliases,
+        })
+    }
 
-        let (main_phase, final_pass) = query.normalize();
-        let output_colnames = match &final_pass {
+    /// Replaces every column name with the expression returned by `f`, e.g. to bind the parameters of prepared queries.
+    pub fn map_colnames<F>(self, f: &mut F) -> Result<CompiledQuery, QueryError>
+        where F: FnMut(String) -> Result<Expr, QueryError> {
+        let mut referenced_cols = HashSet::new();
+        for name in self.referenced_cols {
+            f(name)?.add_colnames(&mut referenced_cols);
+        }
+        Ok(CompiledQuery {
+            main_phase: self.main_phase.map_colnames(f)?,
+            final_pass: match self.final_pass {
+                Some(final_pass) => Some(final_pass.map_colnames(f)?),
+                None => None,
+            },
+            referenced_cols,
+            ..self
+        })
+    }
+
+    fn output_colnames(&self) -> Vec<String> {
+        let mut output_colnames = match &self.final_pass {
             Some(final_pass) => final_pass.result_column_names(),
-            None => main_phase.result_column_names(),
+            None => self.main_phase.result_column_names(),
         };
+        if let Some(ref window_stage) = self.window_stage {
+            output_colnames = window_stage.colnames(&output_colnames);
+        }
+        for (colname, alias) in output_colnames.iter_mut().zip(&self.aliases) {
+            if let Some(alias) = alias {
+                *colname = alias.clone();
+            }
+        }
+        output_colnames
+    }
+}
+
+impl QueryTask {
+    pub fn new(query: CompiledQuery, explain: bool, show: Vec<usize>,
+               source: Vec<Arc<Partition>>,
+               db: Arc<DiskReadScheduler>,
+               sink: ResultSink,
+               limits: QueryLimits,
+               batch_size: usize) -> Result<QueryTask, QueryError> {
+        let start_time_ns = precise_time_ns();
+        let output_colnames = query.output_colnames();
+        let CompiledQuery { mut main_phase, final_pass, window_stage, unnest_stage, rollup, referenced_cols, .. } = query;
+        let table_cols = find_all_cols(&source).into_iter()
+            .filter(|col| referenced_cols.contains(col))
+            .collect();
+        // Groups are only ordered by their strings once they are decoded, which rules out stages that depend on the order
+        // of the merged groups
+        let shared_dictionary = if rollup || unnest_stage.is_some() || window_stage.is_some()
+            || final_pass.as_ref().map_or(false, |final_pass| final_pass.order_by.is_empty()) {
+            None
+        } else {
+            group_by_dictionary(&main_phase, &source)
+        };
+        main_phase.group_by_codes = shared_dictionary.is_some();
+        let main_phase_excluding_deleted = main_phase.exclude_deleted();
+        let aggregate_ordering = main_phase.aggregate_ordering();
+
+        let mut node_partitions = vec![Vec::new(); source.iter().map(|p| p.numa_node() + 1).max().unwrap_or(1)];
+        for (i, partition) in source.iter().enumerate() {
+            node_partitions[partition.numa_node()].push(i);
+        }
 
-        QueryTask {
+        Ok(QueryTask {
             main_phase,
+            main_phase_excluding_deleted,
             final_pass,
+            aggregate_ordering,
+            window_stage,
+            unnest_stage,
+            rollup,
+            shared_dictionary,
             explain,
             show,
             partitions: source,
             referenced_cols,
+            table_cols,
             output_colnames,
             start_time_ns,
             db,
+            limits,
+            batch_size,
+            preemption: None,
+            partitions_pruned: 0,
+            sampling_factor: 1.0,
+            merge_policy: MergePolicy::default(),
+            deterministic: false,
+            result_cache: None,
+            subresult_cache: None,
+            epoch: None,
 
             unsafe_state: Mutex::new(QueryState {
                 partial_results: Vec::new(),
+                merging: 0,
                 completed_batches: 0,
                 explains: Vec::new(),
                 rows_scanned: 0,
                 rows_collected: 0,
+                rows_streamed: 0,
                 colstacks: Vec::new(),
+                unspilled: Vec::new(),
             }),
             batch_index: AtomicUsize::new(0),
+            node_cursors: node_partitions.iter().map(|_| AtomicUsize::new(0)).collect(),
+            node_partitions,
+            rows_collected: AtomicUsize::new(0),
             completed: AtomicBool::new(false),
-            sender,
+            sink,
+        })
+    }
+
+    /// Reports `count` partitions that were skipped before the query was planned in the stats of the result.
+    pub fn partitions_pruned(mut self, count: usize) -> QueryTask {
+        self.partitions_pruned = count;
+        self
+    }
+
+    /// Keeps `epoch` pinned until the query is dropped.
+    pub fn pin_epoch(mut self, epoch: EpochGuard) -> QueryTask {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    /// Limits the fan-in of merges and the memory used by partial results that are not yet merged.
+    pub fn merge_policy(mut self, merge_policy: MergePolicy) -> QueryTask {
+        self.merge_policy = merge_policy;
+        self
+    }
+
+    /// Produces the same result on every run: partitions are processed in order on a single thread, rows with equal sort
+    /// keys keep their order, and groups of aggregations are ordered by their values after any `ORDER BY` keys.
+    pub fn deterministic(mut self, deterministic: bool) -> QueryTask {
+        self.deterministic = deterministic;
+        self.main_phase.stable_sort = deterministic;
+        self.main_phase_excluding_deleted.stable_sort = deterministic;
+        if let Some(ref mut final_pass) = self.final_pass {
+            final_pass.stable_sort = deterministic;
+        }
+        self
+    }
+
+    /// Reports that the query was run on a sample containing `factor` of the rows of the table.
+    pub fn sampling_factor(mut self, factor: f64) -> QueryTask {
+        self.sampling_factor = factor;
+        self
+    }
+
+    /// Stores the result in `cache` once the query completes successfully.
+    pub fn cache_result(mut self, cache: Arc<ResultCache>, key: CacheKey, version: usize) -> QueryTask {
+        self.result_cache = Some((cache, key, version));
+        self
+    }
+
+    /// Reuses results of aggregations on individual partitions stored in `cache` and stores the ones that are computed.
+    pub fn cache_subresults(mut self, cache: Arc<SubresultCache>) -> QueryTask {
+        if !self.main_phase.aggregate.is_empty() && !self.explain && self.show.is_empty() {
+            let query = format!("{:?} {:?}", self.main_phase, self.unnest_stage);
+            self.subresult_cache = Some((cache, query));
         }
+        self
+    }
+
+    /// Stops processing partitions on the current thread while a task with higher priority is waiting to be started.
+    pub fn preemptible(mut self, pending_priority: Arc<PendingPriority>, bulk_threshold: Duration) -> QueryTask {
+        let bulk_threshold_ns = bulk_threshold.as_secs() * 1_000_000_000 + u64::from(bulk_threshold.subsec_nanos());
+        self.preemption = Some((pending_priority, bulk_threshold_ns));
+        self
     }
 
     pub fn run(&self) {
+        // Tables created by `CREATE TABLE` have no partitions until rows are inserted
+        if self.partitions.is_empty() {
+            self.finish_empty();
+            return;
+        }
         let mut rows_scanned = 0;
         let mut rows_collected = 0;
         let mut colstack = Vec::new();
-        let mut batch_results = Vec::<BatchResult>::new();
+        let mut unspilled = Vec::new();
+        let mut batch_results = Vec::<PartialResult>::new();
         let mut explains = Vec::new();
+        // Partial results held by this thread count towards the memory limit of the query
+        let mut reservation = self.limits.reservation();
         while let Some((partition, id)) = self.next_partition() {
-            trace_start!("Batch {}", id);
-            let show = self.show.iter().any(|&x| x == id);
-            let cols = partition.get_cols(&self.referenced_cols, &self.db);
-            rows_scanned += cols.iter().next().map_or(0, |c| c.1.len());
-            let unsafe_cols = unsafe {
-                mem::transmute::<&HashMap<String, Arc<DataSource>>,
-                    &'static HashMap<String, Arc<DataSource>>>(&cols)
+            if let Err(error) = self.limits.check() {
+                self.fail_with(error);
+                return;
+            }
+            let _span = trace_span!("Batch {}", id);
+            let subresult = self.subresult_key(partition);
+            let cached = subresult.as_ref().and_then(|&(cache, ref key)| cache.get(key));
+            let (batch_result, explain, cols) = match cached {
+                // Rows of cached partitions are not scanned
+                Some((batch_result, cols)) => (batch_result, None, cols),
+                None => {
+                    let show = self.show.iter().any(|&x| x == id);
+                    let mut cols = partition.get_cols(&self.referenced_cols, &self.db);
+                    // Partitions created before a column was added to the table don't contain it
+                    for name in &self.table_cols {
+                        if !cols.contains_key(name) {
+                            cols.insert(name.clone(), Arc::new(Column::null(name, partition.len())));
+                        }
+                    }
+                    let main_phase = match partition.tombstones() {
+                        Some(tombstones) => {
+                            cols.insert(DELETED_COL.to_string(), Arc::new(tombstones));
+                            &self.main_phase_excluding_deleted
+                        }
+                        None => &self.main_phase,
+                    };
+                    rows_scanned += cols.iter().next().map_or(0, |c| c.1.len());
+                    let (cols, len) = match self.unnest_stage {
+                        Some(ref unnest_stage) => match unnest_stage.explode(&cols, partition.len()) {
+                            Ok(exploded) => exploded,
+                            Err(error) => {
+                                self.fail_with(error);
+                                return;
+                            }
+                        },
+                        None => (cols, partition.len()),
+                    };
+                    let unsafe_cols = unsafe {
+                        mem::transmute::<&HashMap<String, Arc<DataSource>>,
+                            &'static HashMap<String, Arc<DataSource>>>(&cols)
+                    };
+                    let offset = if self.offset_pushdown() { self.main_phase.limit.offset as usize } else { 0 };
+                    let (batch_result, explain) = match if main_phase.aggregate.is_empty() {
+                        main_phase.run(unsafe_cols, self.explain, show, id, len, &self.limits, self.batch_size, offset)
+                    } else {
+                        main_phase.run_aggregate(unsafe_cols, self.explain, show, id, len, &self.limits, self.batch_size)
+                    } {
+                        Ok(result) => result,
+                        Err(error) => {
+                            self.fail_with(error);
+                            return;
+                        }
+                    };
+                    if let Some((cache, key)) = subresult {
+                        cache.insert(key, &batch_result, &cols);
+                    }
+                    (batch_result, explain, cols)
+                }
             };
-            let (mut batch_result, explain) = match if self.main_phase.aggregate.is_empty() {
-                self.main_phase.run(unsafe_cols, self.explain, show, id, partition.len())
-            } else {
-                self.main_phase.run_aggregate(unsafe_cols, self.explain, show, id, partition.len())
-            } {
-                Ok(result) => result,
-                Err(error) => {
-                    self.fail_with(error);
+            if self.streaming() {
+                // Rows are sent immediately, so the columns they were read from don't have to be kept alive
+                self.stream_batch(batch_result, mem::replace(&mut rows_scanned, 0), explain);
+                if self.completed.load(Ordering::SeqCst) {
                     return;
                 }
-            };
+                continue;
+            }
             colstack.push(cols);
             rows_collected += batch_result.len();
+            let total_rows_collected = self.rows_collected.fetch_add(batch_result.len(), Ordering::SeqCst) + batch_result.len();
             if let Some(explain) = explain {
                 explains.push(explain);
             }
 
-            // Merge only with previous batch results of same level to get O(n log n) complexity
-            while let Some(br) = batch_results.pop() {
-                if br.level == batch_result.level {
-                    match combine(br, batch_result, self.combined_limit()) {
-                        Ok(result) => batch_result = result,
-                        Err(error) => {
-                            self.fail_with(error);
-                            return;
-                        }
-                    };
-                } else {
-                    batch_results.push(br);
-                    break;
+            // Merge only batch results of the same level to get O(n log n) complexity
+            batch_results.push(PartialResult::InMemory(batch_result));
+            if let Err(error) = self.merge_levels(&mut batch_results, &mut unspilled) {
+                self.fail_with(error);
+                return;
+            }
+            if self.limits.max_bytes.is_some() {
+                let held_bytes = batch_results.iter().map(|br| br.heap_size_bytes()).sum::<usize>();
+                if let Err(error) = reservation.resize(held_bytes) {
+                    self.fail_with(error);
+                    return;
                 }
             }
-            batch_results.push(batch_result);
 
             if self.completed.load(Ordering::SeqCst) {
                 return;
             }
-            if self.sufficient_rows(rows_collected) {
+            if self.sufficient_rows(total_rows_collected) {
                 break;
             }
         }
 
-        match QueryTask::combine_results(batch_results, self.combined_limit()) {
+        match self.combine_partial_results(batch_results, &mut unspilled) {
             Ok(Some(result)) => self.push_result(result, rows_scanned, rows_collected, explains),
             Err(error) => self.fail_with(error),
             _ => {}
         }
         // need to keep colstack alive, otherwise results may reference freed data
-        self.push_colstack(colstack);
+        self.push_colstack(colstack, unspilled);
+    }
+
+    /// Merges the last `fan_in` results into a result of the next level while they all have the same level, then
+    /// spills results to disk if they exceed the memory threshold.
+    fn merge_levels(&self,
+                    batch_results: &mut Vec<PartialResult<'static>>,
+                    unspilled: &mut Vec<Box<SerializedBatch>>) -> Result<(), QueryError> {
+        let fan_in = self.merge_policy.fan_in;
+        while batch_results.len() >= fan_in {
+            let level = batch_results[batch_results.len() - 1].level();
+            let start = batch_results.len() - fan_in;
+            if batch_results[start..].iter().any(|br| br.level() != level) {
+                break;
+            }
+            let group = batch_results.split_off(start);
+            let mut merged = None;
+            for partial in group {
+                let batch_result = QueryTask::unspill(partial, unspilled)?;
+                merged = Some(match merged {
+                    Some(merged) => combine(merged, batch_result, self.combined_limit())?,
+                    None => batch_result,
+                });
+            }
+            let mut merged = merged.unwrap();
+            merged.level = level + 1;
+            batch_results.push(PartialResult::InMemory(merged));
+        }
+
+        if let Some(threshold) = self.merge_policy.spill_threshold {
+            // The oldest results are spilled first since they are merged last
+            let mut held_bytes = batch_results.iter().map(|br| br.heap_size_bytes()).sum::<usize>();
+            for partial in batch_results.iter_mut() {
+                if held_bytes <= threshold {
+                    break;
+                }
+                let spilled = match *partial {
+                    PartialResult::InMemory(ref batch_result) => {
+                        held_bytes -= batch_result.heap_size_bytes();
+                        SpilledBatch::write(batch_result, &self.merge_policy)?
+                    }
+                    PartialResult::Spilled(_) => continue,
+                };
+                *partial = PartialResult::Spilled(spilled);
+            }
+        }
+        Ok(())
+    }
+
+    /// Combines the partial results of a thread, reading spilled results back into memory one at a time.
+    fn combine_partial_results(&self,
+                               batch_results: Vec<PartialResult<'static>>,
+                               unspilled: &mut Vec<Box<SerializedBatch>>)
+                               -> Result<Option<BatchResult<'static>>, QueryError> {
+        if !batch_results.is_empty() && batch_results.iter().all(|br| br.is_sorted()) {
+            let batch_results = batch_results.into_iter()
+                .map(|partial| QueryTask::unspill(partial, unspilled))
+                .collect::<Result<Vec<_>, _>>()?;
+            return merge_sorted(batch_results, self.combined_limit()).map(Some);
+        }
+        let mut full_result = None;
+        for partial in batch_results {
+            let batch_result = QueryTask::unspill(partial, unspilled)?;
+            full_result = Some(match full_result {
+                Some(full_result) => combine(full_result, batch_result, self.combined_limit())?,
+                None => batch_result,
+            });
+        }
+        Ok(full_result)
+    }
+
+    /// Reads `partial` back into memory if it was spilled, the data is kept alive by `unspilled`.
+    fn unspill(partial: PartialResult<'static>,
+               unspilled: &mut Vec<Box<SerializedBatch>>) -> Result<BatchResult<'static>, QueryError> {
+        match partial {
+            PartialResult::InMemory(batch_result) => Ok(batch_result),
+            PartialResult::Spilled(spilled) => {
+                let serialized = Box::new(spilled.read()?);
+                let batch_result = unsafe {
+                    mem::transmute::<BatchResult, BatchResult<'static>>(serialized.batch_result())
+                };
+                unspilled.push(serialized);
+                Ok(batch_result)
+            }
+        }
     }
 
     fn combine_results(batch_results: Vec<BatchResult>, limit: usize) -> Result<Option<BatchResult>, QueryError> {
+        if !batch_results.is_empty() && batch_results.iter().all(|br| !br.order_by.is_empty()) {
+            return merge_sorted(batch_results, limit).map(Some);
+        }
         let mut full_result = None;
         for batch_result in batch_results {
             if let Some(partial) = full_result {
@@ -194,51 +663,154 @@ impl QueryTask {
         Ok(full_result)
     }
 
-    fn push_result(&self, result: BatchResult, rows_scanned: usize, rows_collected: usize, explains: Vec<String>) {
+    fn push_result(&self, result: BatchResult, rows_scanned: usize, rows_collected: usize, explains: Vec<PlanGraph>) {
+        let mut result = unsafe { mem::transmute::<_, BatchResult<'static>>(result) };
         let mut state = self.unsafe_state.lock().unwrap();
         if self.completed.load(Ordering::SeqCst) { return; }
         state.completed_batches += result.batch_count;
         state.explains.extend(explains);
         state.rows_scanned += rows_scanned;
         state.rows_collected += rows_collected;
-        unsafe {
-            let result = mem::transmute::<_, BatchResult<'static>>(result);
-            state.partial_results.push(result);
-        }
-        if state.completed_batches == self.partitions.len() || self.sufficient_rows(state.rows_collected) {
-            let mut owned_results = Vec::with_capacity(0);
-            mem::swap(&mut owned_results, &mut state.partial_results);
-            // TODO(clemens): Handle empty table
-            let full_result = match QueryTask::combine_results(owned_results, self.combined_limit()) {
-                Ok(result) => result.unwrap(),
+        // Results of different threads are merged pairwise without holding the lock,
+        // so threads that finish at the same time perform their merges in parallel
+        loop {
+            let complete = result.batch_count == self.partitions.len()
+                || (state.merging == 0 && self.sufficient_rows(state.rows_collected));
+            if complete {
+                let mut owned_results = mem::replace(&mut state.partial_results, Vec::new());
+                owned_results.push(result);
+                self.finish(&state, owned_results);
+                return;
+            }
+            let partial = state.partial_results.pop();
+            match partial {
+                Some(partial) => {
+                    state.merging += 1;
+                    drop(state);
+                    let merged = {
+                        let _span = trace_span!("Merge");
+                        combine(partial, result, self.combined_limit())
+                    };
+                    state = self.unsafe_state.lock().unwrap();
+                    state.merging -= 1;
+                    if self.completed.load(Ordering::SeqCst) { return; }
+                    match merged {
+                        Ok(merged) => result = merged,
+                        Err(error) => {
+                            self.fail_with_no_lock(error);
+                            return;
+                        }
+                    }
+                }
+                None => {
+                    state.partial_results.push(result);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Combines the remaining partial results and sends the final result.
+    fn finish(&self, state: &QueryState<'static>, results: Vec<BatchResult<'static>>) {
+        let _span = trace_span!("Final merge");
+        let full_result = match QueryTask::combine_results(results, self.combined_limit()) {
+            Ok(result) => {
+                let result = result.unwrap().decode_keyed();
+                match self.shared_dictionary {
+                    Some(ref dictionary) => {
+                        // The query holds the dictionary until the result has been converted into owned values
+                        let dictionary = unsafe {
+                            mem::transmute::<&SharedDictionary, &'static SharedDictionary>(&**dictionary)
+                        };
+                        result.decode_codes(dictionary)
+                    }
+                    None => result,
+                }
+            }
+            Err(error) => {
+                self.fail_with_no_lock(error);
+                return;
+            }
+        };
+        let final_result = if let Some(final_pass) = &self.final_pass {
+            let data_sources = full_result.into_columns();
+            let cols = unsafe {
+                mem::transmute::<&HashMap<String, Arc<DataSource>>,
+                    &'static HashMap<String, Arc<DataSource>>>(&data_sources)
+            };
+            let full_result = match final_pass.run(cols,
+                                                   self.explain,
+                                                   !self.show.is_empty(),
+                                                   0xdeadbeef,
+                                                   cols.iter().next().map(|(_, c)| c.len()).unwrap_or(0),
+                                                   &self.limits,
+                                                   self.batch_size) {
+                Ok((result, _)) => result,
                 Err(error) => {
                     self.fail_with_no_lock(error);
                     return;
                 }
             };
-            let final_result = if let Some(final_pass) = &self.final_pass {
-                let data_sources = full_result.into_columns();
-                let cols = unsafe {
-                    mem::transmute::<&HashMap<String, Arc<DataSource>>,
-                        &'static HashMap<String, Arc<DataSource>>>(&data_sources)
-                };
-                let full_result = final_pass.run(cols,
-                                                 self.explain,
-                                                 !self.show.is_empty(),
-                                                 0xdeadbeef,
-                                                 cols.iter().next().map(|(_, c)| c.len()).unwrap_or(0)).unwrap().0;
-                self.convert_to_output_format(&full_result, state.rows_scanned, &state.explains)
-            } else {
-                self.convert_to_output_format(&full_result, state.rows_scanned, &state.explains)
-            };
-            self.sender.send(Ok(final_result));
+            self.convert_to_output_format(&full_result, state.rows_scanned, &state.explains)
+        } else {
+            self.convert_to_output_format(&full_result, state.rows_scanned, &state.explains)
+        };
+        let final_result = match final_result {
+            Ok(final_result) => final_result,
+            Err(error) => {
+                self.fail_with_no_lock(error);
+                return;
+            }
+        };
+        if let Some((ref cache, ref key, version)) = self.result_cache {
+            cache.insert(key.clone(), version, &final_result);
+        }
+        self.sink.send(Ok(final_result));
+        self.completed.store(true, Ordering::SeqCst);
+    }
+
+    /// Sends an empty result for a query on a table without partitions.
+    fn finish_empty(&self) {
+        let _state = self.unsafe_state.lock().unwrap();
+        if self.completed.load(Ordering::SeqCst) { return; }
+        let output = self.output(vec![], vec![None; self.output_colnames.len()], 0, &[]);
+        self.sink.send(Ok(output));
+        self.completed.store(true, Ordering::SeqCst);
+    }
+
+    /// Sends the rows of a single partition to the result stream.
+    fn stream_batch(&self, result: BatchResult, rows_scanned: usize, explain: Option<PlanGraph>) {
+        let mut state = self.unsafe_state.lock().unwrap();
+        if self.completed.load(Ordering::SeqCst) { return; }
+        state.completed_batches += result.batch_count;
+        state.rows_scanned += rows_scanned;
+        state.explains.extend(explain);
+        let offset = self.main_phase.limit.offset as usize;
+        let end = offset.saturating_add(self.main_phase.limit.limit as usize);
+        let coltypes = self.coltypes(&result);
+        let mut rows = Vec::new();
+        for i in 0..result.len() {
+            if state.rows_streamed >= end { break; }
+            if state.rows_streamed >= offset {
+                rows.push(self.record(&result, i));
+            }
+            state.rows_streamed += 1;
+        }
+        if state.completed_batches == self.partitions.len() || state.rows_streamed >= end {
+            let output = self.output(rows, coltypes, state.rows_scanned, &state.explains);
             self.completed.store(true, Ordering::SeqCst);
+            self.batch_index.store(self.partitions.len(), Ordering::SeqCst);
+            self.sink.send(Ok(output));
+        } else if !rows.is_empty() {
+            let output = self.output(rows, coltypes, state.rows_scanned, &[]);
+            self.sink.send_batch(Ok(output));
         }
     }
 
-    fn push_colstack(&self, colstack: Vec<HashMap<String, Arc<DataSource>>>) {
+    fn push_colstack(&self, colstack: Vec<HashMap<String, Arc<DataSource>>>, unspilled: Vec<Box<SerializedBatch>>) {
         let mut state = self.unsafe_state.lock().unwrap();
         state.colstacks.push(unsafe { mem::transmute(colstack) });
+        state.unspilled.push(unspilled);
     }
 
     fn fail_with(&self, error: QueryError) {
@@ -250,7 +822,17 @@ impl QueryTask {
     fn fail_with_no_lock(&self, error: QueryError) {
         self.completed.store(true, Ordering::SeqCst);
         self.batch_index.store(self.partitions.len(), Ordering::SeqCst);
-        self.sender.send(Err(error));
+        self.sink.send(Err(error));
+    }
+
+    /// Whether rows are sent to the result stream as soon as each partition has been processed.
+    fn streaming(&self) -> bool {
+        self.sink.is_stream()
+            && self.main_phase.aggregate.is_empty()
+            && self.main_phase.order_by.is_empty()
+            && self.final_pass.is_none()
+            && self.window_stage.is_none()
+            && !self.rollup
     }
 
     fn sufficient_rows(&self, rows_collected: usize) -> bool {
@@ -258,49 +840,200 @@ impl QueryTask {
         unordered_select && self.combined_limit() < rows_collected
     }
 
+    /// Open partitions change with every buffered row and all share the same id, so their results are never cached.
+    fn subresult_key(&self, partition: &Partition) -> Option<(&SubresultCache, SubresultKey)> {
+        if partition.is_open() { return None; }
+        self.subresult_cache.as_ref().map(|&(ref cache, ref query)| {
+            (&**cache, SubresultKey::new(partition.id(), partition.deleted_rows(), query))
+        })
+    }
+
     fn next_partition(&self) -> Option<(&Arc<Partition>, usize)> {
-        let index = self.batch_index.fetch_add(1, Ordering::SeqCst);
-        self.partitions.get(index).map(|b| (b, index))
+        if self.sufficient_rows(self.rows_collected.load(Ordering::SeqCst)) {
+            return None;
+        }
+        // Deterministic queries run on a single thread and are not requeued, so they must not yield it
+        if let Some((ref pending_priority, _)) = self.preemption {
+            if !self.deterministic && pending_priority.exceeds(self.priority()) {
+                return None;
+            }
+        }
+        if self.batch_index.load(Ordering::SeqCst) >= self.partitions.len() {
+            return None;
+        }
+        if self.deterministic {
+            let index = self.batch_index.fetch_add(1, Ordering::SeqCst);
+            return Some((&self.partitions[index], index));
+        }
+        // Partitions created on the NUMA node of the current thread are processed first
+        let nodes = self.node_partitions.len();
+        let node = topology::current_node();
+        for i in 0..nodes {
+            let node = (node + i) % nodes;
+            let position = self.node_cursors[node].fetch_add(1, Ordering::SeqCst);
+            if let Some(&index) = self.node_partitions[node].get(position) {
+                self.batch_index.fetch_add(1, Ordering::SeqCst);
+                return Some((&self.partitions[index], index));
+            }
+        }
+        None
     }
 
     fn convert_to_output_format(&self,
                                 full_result: &BatchResult,
                                 rows_scanned: usize,
-                                explains: &[String]) -> QueryOutput {
+                                explains: &[PlanGraph]) -> Result<QueryOutput, QueryError> {
         let limit = self.main_phase.limit.limit as usize;
         let offset = self.main_phase.limit.offset as usize;
         let mut result_rows = Vec::new();
-        let count = cmp::min(limit, full_result.len() - offset);
-        for i in offset..(count + offset) {
-            let mut record = Vec::with_capacity(self.output_colnames.len());
-            // TODO(clemens): use column order of original query
-            for &j in &full_result.projection {
-                record.push(full_result.columns[j].get_raw(i));
-            }
-            for &(aggregation, _) in &full_result.aggregations {
-                record.push(full_result.columns[aggregation].get_raw(i));
-            }
-            result_rows.push(record);
+        let rows = self.row_order(full_result);
+        // Subtotals are computed from all groups before the limit is applied
+        let (skip, take) = if self.rollup {
+            (0, usize::MAX)
+        } else if self.offset_pushdown() {
+            (0, limit)
+        } else {
+            (offset, limit)
+        };
+        for &i in rows.iter().skip(skip).take(take) {
+            result_rows.push(self.record(full_result, i));
+        }
+        if self.rollup {
+            result_rows = rollup(result_rows, full_result.projection.len())?
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .collect();
+        }
+        if let Some(ref window_stage) = self.window_stage {
+            result_rows = window_stage.apply(result_rows);
+        }
+        Ok(self.output(result_rows, self.coltypes(full_result), rows_scanned, explains))
+    }
+
+    /// Types of the output columns, in the order of the values returned by `record`.
+    fn coltypes(&self, result: &BatchResult) -> Vec<Option<ValueType>> {
+        let coltypes = result.projection.iter().cloned()
+            .chain(result.aggregations.iter().map(|&(index, _)| index))
+            .map(|index| value_type(result.columns[index].encoding_type()))
+            .collect::<Vec<_>>();
+        match self.window_stage {
+            Some(ref window_stage) => window_stage.coltypes(&coltypes),
+            None => coltypes,
         }
+    }
+
+    fn record(&self, full_result: &BatchResult, i: usize) -> Vec<RawVal> {
+        let mut record = Vec::with_capacity(self.output_colnames.len());
+        // TODO(clemens): use column order of original query
+        for &j in &full_result.projection {
+            record.push(full_result.columns[j].get_raw(i));
+        }
+        for &(aggregation, _) in &full_result.aggregations {
+            record.push(full_result.columns[aggregation].get_raw(i));
+        }
+        record
+    }
 
+    fn output(&self,
+              rows: Vec<Vec<RawVal>>,
+              coltypes: Vec<Option<ValueType>>,
+              rows_scanned: usize,
+              explains: &[PlanGraph]) -> QueryOutput {
         let mut query_plans = HashMap::new();
+        let mut plan_graphs = Vec::new();
         for plan in explains {
-            *query_plans.entry(plan.to_owned()).or_insert(0) += 1
+            let count = query_plans.entry(format!("{}", plan)).or_insert(0);
+            if *count == 0 {
+                plan_graphs.push(plan.clone());
+            }
+            *count += 1;
         }
 
         QueryOutput {
             colnames: self.output_colnames.clone(),
-            rows: result_rows,
+            coltypes,
+            rows,
             query_plans,
+            plan_graphs,
             stats: QueryStats {
                 runtime_ns: precise_time_ns() - self.start_time_ns,
                 rows_scanned,
+                partitions_pruned: self.partitions_pruned,
+                sampling_factor: self.sampling_factor,
             },
         }
     }
 
+    /// Order of the rows in the merged result, sorting aggregation results if required.
+    fn row_order(&self, full_result: &BatchResult) -> Vec<usize> {
+        let mut rows = (0..full_result.len()).collect::<Vec<_>>();
+        let canonical = self.canonical_groups();
+        if !self.aggregate_ordering.is_empty() || canonical || self.shared_dictionary.is_some() {
+            let keys = rows.iter()
+                .map(|&i| {
+                    let mut key = self.aggregate_ordering.iter()
+                        .map(|&(index, _)| {
+                            let column = if index < full_result.projection.len() {
+                                full_result.projection[index]
+                            } else {
+                                full_result.aggregations[index - full_result.projection.len()].0
+                            };
+                            full_result.columns[column].get_raw(i)
+                        })
+                        .collect::<Vec<_>>();
+                    if canonical {
+                        key.extend(self.record(full_result, i));
+                    } else if self.shared_dictionary.is_some() {
+                        // Groups merged on codes are ordered by code rather than by their strings
+                        key.push(full_result.columns[full_result.projection[0]].get_raw(i));
+                    }
+                    key
+                })
+                .collect::<Vec<_>>();
+            let ordered = self.aggregate_ordering.len();
+            rows.sort_by(|&i, &j| {
+                for (k, &(_, desc)) in self.aggregate_ordering.iter().enumerate() {
+                    let ordering = if desc { keys[j][k].cmp(&keys[i][k]) } else { keys[i][k].cmp(&keys[j][k]) };
+                    if ordering != cmp::Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                keys[i][ordered..].cmp(&keys[j][ordered..])
+            });
+        }
+        rows
+    }
+
+    /// Whether groups are ordered by their values after any `ORDER BY` keys, which requires all groups to be merged.
+    /// Rows produced by a final pass are left in the order it produces them in.
+    fn canonical_groups(&self) -> bool {
+        self.deterministic && !self.main_phase.aggregate.is_empty() && self.final_pass.is_none()
+    }
+
+    /// Whether the offset is applied by the top-n operator of the only partition, so that the rows it skips are never
+    /// materialized. With multiple partitions, each of them has to return the rows up to the end of the limit.
+    fn offset_pushdown(&self) -> bool {
+        self.partitions.len() == 1
+            && !self.deterministic
+            && self.main_phase.aggregate.is_empty()
+            && !self.main_phase.order_by.is_empty()
+            && self.final_pass.is_none()
+            && self.window_stage.is_none()
+            && !self.rollup
+    }
+
     fn combined_limit(&self) -> usize {
-        (self.main_phase.limit.limit + self.main_phase.limit.offset) as usize
+        // Groups can only be truncated while merging if the result is ordered by the grouping key and not rolled up
+        let sorted_aggregation = !self.main_phase.aggregate.is_empty()
+            && (!self.aggregate_ordering.is_empty()
+            || self.final_pass.as_ref().map_or(false, |final_pass| !final_pass.order_by.is_empty()));
+        // Groups merged on codes are not ordered by their strings, so none of them can be dropped before they are decoded
+        if sorted_aggregation || self.rollup || self.canonical_groups() || self.shared_dictionary.is_some() {
+            usize::MAX
+        } else {
+            (self.main_phase.limit.limit + self.main_phase.limit.offset) as usize
+        }
     }
 }
 
@@ -308,12 +1041,67 @@ impl Task for QueryTask {
     fn execute(&self) { self.run(); }
     fn completed(&self) -> bool {
         let batch_index = self.batch_index.load(Ordering::SeqCst);
-        self.completed.load(Ordering::SeqCst) || batch_index >= self.partitions.len()
+        // Tasks without partitions still have to run once to send their empty result
+        self.completed.load(Ordering::SeqCst) || (batch_index >= self.partitions.len() && !self.partitions.is_empty())
+    }
+    fn multithreaded(&self) -> bool { !self.deterministic }
+    fn priority(&self) -> Priority {
+        match self.preemption {
+            Some((_, bulk_threshold_ns)) if !self.deterministic
+                && precise_time_ns() - self.start_time_ns > bulk_threshold_ns => Priority::Bulk,
+            _ => Priority::Interactive,
+        }
+    }
+}
+
+/// Type of the values of a result column with encoding `encoding_type`.
+fn value_type(encoding_type: EncodingType) -> Option<ValueType> {
+    match encoding_type {
+        EncodingType::Str | EncodingType::NullableStr | EncodingType::ScalarStr | EncodingType::ScalarString =>
+            Some(ValueType::String),
+        EncodingType::I64 | EncodingType::U8 | EncodingType::U16 | EncodingType::U32 | EncodingType::U64 |
+        EncodingType::NullableI64 | EncodingType::NullableU8 | EncodingType::NullableU16 | EncodingType::NullableU32 |
+        EncodingType::NullableU64 | EncodingType::ScalarI64 => Some(ValueType::Integer),
+        EncodingType::F64 | EncodingType::ScalarF64 => Some(ValueType::Float),
+        _ => None,
+    }
+}
+
+/// Types of the columns of `rows`, `None` for columns whose values have different types or are all null.
+pub fn row_types(columns: usize, rows: &[Vec<RawVal>]) -> Vec<Option<ValueType>> {
+    (0..columns)
+        .map(|i| {
+            let mut types = rows.iter().filter_map(|row| match row[i] {
+                RawVal::Int(_) => Some(ValueType::Integer),
+                RawVal::Float(_) => Some(ValueType::Float),
+                RawVal::Str(_) => Some(ValueType::String),
+                RawVal::Null => None,
+            });
+            let first = types.next();
+            if types.all(|t| Some(t) == first) { first } else { None }
+        })
+        .collect()
+}
+
+/// Dictionary shared by all partitions of the single group by column of an aggregation, if every partition was encoded
+/// with the same one.
+fn group_by_dictionary(main_phase: &NormalFormQuery, source: &[Arc<Partition>]) -> Option<Arc<SharedDictionary>> {
+    if main_phase.aggregate.is_empty() {
+        return None;
+    }
+    let column = match main_phase.projection[..] {
+        [Expr::ColName(ref column)] => column,
+        _ => return None,
+    };
+    let dictionary = source.first()?.shared_dictionary(column)?;
+    if source.iter().all(|partition| partition.shared_dictionary(column).map_or(false, |d| Arc::ptr_eq(d, dictionary))) {
+        Some(dictionary.clone())
+    } else {
+        None
     }
-    fn multithreaded(&self) -> bool { true }
 }
 
-fn find_all_cols(source: &[Arc<Partition>]) -> Vec<String> {
+pub fn find_all_cols(source: &[Arc<Partition>]) -> Vec<String> {
     let mut cols = HashSet::new();
     for partition in source {
         for name in partition.col_names() {
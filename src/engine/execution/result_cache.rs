@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::Mutex;
+
+use engine::query_task::QueryOutput;
+use ingest::raw_val::RawVal;
+
+
+/// Identifies queries whose results can be shared.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub query: String,
+    /// Role whose column permissions the query was run with.
+    pub role: Option<String>,
+}
+
+impl CacheKey {
+    pub fn new(query: &str, role: Option<&str>) -> CacheKey {
+        CacheKey {
+            query: query.to_string(),
+            role: role.map(|role| role.to_string()),
+        }
+    }
+}
+
+/// Results of recent queries, each of which is valid until the version of the queried table changes.
+/// Once the results take up more than `capacity_bytes`, the least recently used ones are evicted.
+pub struct ResultCache {
+    capacity_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    size_bytes: usize,
+    clock: u64,
+}
+
+struct CacheEntry {
+    version: usize,
+    output: QueryOutput,
+    size_bytes: usize,
+    last_used: u64,
+}
+
+impl ResultCache {
+    /// Cache that holds results of up to `capacity_bytes`, 0 disables the cache.
+    pub fn new(capacity_bytes: usize) -> ResultCache {
+        ResultCache {
+            capacity_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity_bytes > 0
+    }
+
+    /// Cached result for `key`, if it was computed from version `version` of the queried table.
+    pub fn get(&self, key: &CacheKey, version: usize) -> Option<QueryOutput> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+        let (hit, stale) = match state.entries.get_mut(key) {
+            Some(entry) => if entry.version == version {
+                entry.last_used = clock;
+                (Some(entry.output.clone()), false)
+            } else {
+                (None, true)
+            },
+            None => (None, false),
+        };
+        if stale {
+            let entry = state.entries.remove(key).unwrap();
+            state.size_bytes -= entry.size_bytes;
+        }
+        hit
+    }
+
+    /// Stores the result of `key` computed from version `version` of the queried table.
+    pub fn insert(&self, key: CacheKey, version: usize, output: &QueryOutput) {
+        if !self.is_enabled() {
+            return;
+        }
+        let size_bytes = key.query.len() + output.rows.iter()
+            .flat_map(|row| row.iter())
+            .map(|value| mem::size_of::<RawVal>() + match *value {
+                RawVal::Str(ref s) => s.len(),
+                _ => 0,
+            })
+            .sum::<usize>();
+        if size_bytes > self.capacity_bytes {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let previous = state.entries.remove(&key);
+        if let Some(previous) = previous {
+            state.size_bytes -= previous.size_bytes;
+        }
+        while state.size_bytes + size_bytes > self.capacity_bytes {
+            let lru = state.entries.iter()
+                .min_by_key(|&(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            let evicted = state.entries.remove(&lru).unwrap();
+            state.size_bytes -= evicted.size_bytes;
+        }
+        state.clock += 1;
+        let last_used = state.clock;
+        state.size_bytes += size_bytes;
+        state.entries.insert(key, CacheEntry { version, output: output.clone(), size_bytes, last_used });
+    }
+
+    /// Removes all results, e.g. after changes to permissions or functions which affect all queries.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.size_bytes = 0;
+    }
+}
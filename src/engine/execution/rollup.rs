@@ -0,0 +1,94 @@
+use std::cmp::Ordering;
+
+use QueryError;
+use engine::*;
+use ingest::raw_val::RawVal;
+
+
+/// Adds subtotal rows for every prefix of the first `grouping_columns` columns to the result rows of an aggregation.
+///
+/// All remaining columns must be sums or counts, which allows subtotals to be computed by adding up the rows of each
+/// group. Columns that are rolled up are null in subtotal rows, and each subtotal follows the rows it summarizes.
+pub fn rollup(mut rows: Vec<Vec<RawVal>>, grouping_columns: usize) -> Result<Vec<Vec<RawVal>>, QueryError> {
+    rows.sort_by(|a, b| {
+        for i in 0..grouping_columns {
+            let ordering = a[i].cmp(&b[i]);
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+
+    // subtotals[k] accumulates the rows that share the first k grouping columns with the current row
+    let mut subtotals: Vec<Option<Vec<RawVal>>> = vec![None; grouping_columns];
+    let mut result = Vec::with_capacity(rows.len() + 1);
+    for (r, row) in rows.iter().enumerate() {
+        let unchanged = if r == 0 {
+            grouping_columns
+        } else {
+            (0..grouping_columns).take_while(|&i| row[i] == rows[r - 1][i]).count()
+        };
+        for k in (unchanged + 1..grouping_columns).rev() {
+            if let Some(subtotal) = subtotals[k].take() {
+                result.push(subtotal);
+            }
+        }
+        for (k, subtotal) in subtotals.iter_mut().enumerate() {
+            *subtotal = Some(match subtotal.take() {
+                Some(subtotal) => add(subtotal, row, grouping_columns)?,
+                None => row.iter().enumerate()
+                    .map(|(i, value)| if i < k || i >= grouping_columns { value.clone() } else { RawVal::Null })
+                    .collect(),
+            });
+        }
+        result.push(row.clone());
+    }
+    for subtotal in subtotals.into_iter().rev() {
+        if let Some(subtotal) = subtotal {
+            result.push(subtotal);
+        }
+    }
+    Ok(result)
+}
+
+fn add(mut subtotal: Vec<RawVal>, row: &[RawVal], grouping_columns: usize) -> Result<Vec<RawVal>, QueryError> {
+    for i in grouping_columns..row.len() {
+        let sum = match (&subtotal[i], &row[i]) {
+            (RawVal::Int(a), RawVal::Int(b)) => match a.checked_add(*b) {
+                Some(sum) => RawVal::Int(sum),
+                None => bail!(QueryError::Overflow, "Subtotal exceeds the range of 64-bit integers"),
+            },
+            (RawVal::Float(a), RawVal::Float(b)) => RawVal::Float(OrderedF64(a.0 + b.0)),
+            (RawVal::Int(a), RawVal::Float(b)) | (RawVal::Float(b), RawVal::Int(a)) =>
+                RawVal::Float(OrderedF64(*a as f64 + b.0)),
+            (RawVal::Null, value) | (value, RawVal::Null) => value.clone(),
+            (a, b) => return Err(fatal!("Cannot add {:?} and {:?}", a, b)),
+        };
+        subtotal[i] = sum;
+    }
+    Ok(subtotal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest::raw_val::RawVal::*;
+
+    #[test]
+    fn test_rollup() {
+        let rows = vec![
+            vec![Int(1), Str("b".to_string()), Int(2)],
+            vec![Int(0), Str("a".to_string()), Int(1)],
+            vec![Int(1), Str("a".to_string()), Int(3)],
+        ];
+        assert_eq!(rollup(rows, 2).unwrap(), vec![
+            vec![Int(0), Str("a".to_string()), Int(1)],
+            vec![Int(0), Null, Int(1)],
+            vec![Int(1), Str("a".to_string()), Int(3)],
+            vec![Int(1), Str("b".to_string()), Int(2)],
+            vec![Int(1), Null, Int(5)],
+            vec![Null, Null, Int(6)],
+        ]);
+    }
+}
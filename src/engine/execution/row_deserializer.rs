@@ -0,0 +1,217 @@
+use std::error::Error;
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+
+use QueryError;
+use engine::query_task::QueryOutput;
+use ingest::raw_val::RawVal;
+
+
+impl QueryOutput {
+    /// Converts each row into a `T`. Fields of structs are matched to columns by name, tuples are filled with the
+    /// columns in order. Null values can only be converted into `Option` fields.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<Vec<T>, QueryError> {
+        self.rows.iter()
+            .enumerate()
+            .map(|(i, row)| {
+                T::deserialize(RowDeserializer { colnames: &self.colnames, row })
+                    .map_err(|err| QueryError::TypeError(format!("Failed to convert row {}: {}", i, err.0)))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct DeserializeError(String);
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for DeserializeError {
+    fn description(&self) -> &str { &self.0 }
+}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(msg: T) -> DeserializeError {
+        DeserializeError(msg.to_string())
+    }
+}
+
+struct RowDeserializer<'a> {
+    colnames: &'a [String],
+    row: &'a [RawVal],
+}
+
+impl<'de, 'a> de::Deserializer<'de> for RowDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_map(RowAccess { colnames: self.colnames, row: self.row, index: 0 })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str], visitor: V)
+                                           -> Result<V::Value, DeserializeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_seq(RowAccess { colnames: self.colnames, row: self.row, index: 0 })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _: &'static str, _: usize, visitor: V)
+                                                 -> Result<V::Value, DeserializeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit unit_struct
+        newtype_struct enum identifier ignored_any
+    }
+}
+
+struct RowAccess<'a> {
+    colnames: &'a [String],
+    row: &'a [RawVal],
+    index: usize,
+}
+
+impl<'de, 'a> MapAccess<'de> for RowAccess<'a> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, DeserializeError> {
+        match self.colnames.get(self.index) {
+            Some(colname) => seed.deserialize(colname.as_str().into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeserializeError> {
+        let value = &self.row[self.index];
+        self.index += 1;
+        seed.deserialize(ValueDeserializer(value))
+            .map_err(|err| DeserializeError(format!("column {}: {}", self.colnames[self.index - 1], err.0)))
+    }
+}
+
+impl<'de, 'a> SeqAccess<'de> for RowAccess<'a> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, DeserializeError> {
+        match self.row.get(self.index) {
+            Some(value) => {
+                self.index += 1;
+                seed.deserialize(ValueDeserializer(value))
+                    .map(Some)
+                    .map_err(|err| DeserializeError(format!("column {}: {}", self.colnames[self.index - 1], err.0)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.row.len() - self.index)
+    }
+}
+
+struct ValueDeserializer<'a>(&'a RawVal);
+
+impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match self.0 {
+            RawVal::Int(int) => visitor.visit_i64(*int),
+            RawVal::Float(float) => visitor.visit_f64(float.0),
+            RawVal::Str(string) => visitor.visit_str(string),
+            RawVal::Null => visitor.visit_unit(),
+        }
+    }
+
+    /// Booleans are represented as the integers 0 and 1.
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        let value = self.0;
+        match value {
+            RawVal::Int(0) => visitor.visit_bool(false),
+            RawVal::Int(1) => visitor.visit_bool(true),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        let value = self.0;
+        match value {
+            RawVal::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::query_task::row_types;
+    use ingest::raw_val::syntax::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Row {
+        name: String,
+        count: u32,
+        average: f64,
+        parent: Option<String>,
+    }
+
+    fn output(colnames: &[&str], rows: Vec<Vec<RawVal>>) -> QueryOutput {
+        QueryOutput {
+            colnames: colnames.iter().map(|colname| colname.to_string()).collect(),
+            coltypes: row_types(colnames.len(), &rows),
+            rows,
+            query_plans: Default::default(),
+            plan_graphs: vec![],
+            stats: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_structs() {
+        let output = output(&["count", "name", "average", "parent", "extra"], vec![
+            vec![Int(3), Str("a"), Float(0.5), Null, Int(0)],
+            vec![Int(7), Str("b"), Int(2), Str("a"), Null],
+        ]);
+        assert_eq!(output.deserialize::<Row>().unwrap(), vec![
+            Row { name: "a".to_string(), count: 3, average: 0.5, parent: None },
+            Row { name: "b".to_string(), count: 7, average: 2.0, parent: Some("a".to_string()) },
+        ]);
+        assert_eq!(output.deserialize::<(i64, String)>().unwrap()[1], (7, "b".to_string()));
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let missing = output(&["name", "average"], vec![vec![Str("a"), Float(0.5)]]);
+        assert!(missing.deserialize::<Row>().is_err());
+        let mistyped = output(&["name", "count", "average", "parent"], vec![vec![Int(1), Int(3), Float(0.5), Null]]);
+        assert!(mistyped.deserialize::<Row>().is_err());
+        let negative = output(&["name", "count", "average", "parent"], vec![vec![Str("a"), Int(-3), Float(0.5), Null]]);
+        assert!(negative.deserialize::<Row>().is_err());
+    }
+}
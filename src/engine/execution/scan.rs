@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use ::QueryError;
+use engine::*;
+use ingest::buffer::Buffer;
+use ingest::input_column::InputColumn;
+use ingest::raw_val::RawVal;
+use mem_store::column::{Column, DataSource};
+use mem_store::column_builder::*;
+use mem_store::partition::{DELETED_COL, Partition};
+use scheduler::disk_read_scheduler::DiskReadScheduler;
+use syntax::expression::Expr;
+use syntax::limit::LimitClause;
+
+
+const ROW_INDEX_COL: &str = "$row_index";
+
+/// Indices of all rows of `partition` that satisfy `filter` and are not deleted yet.
+pub fn matching_rows(partition: &Partition, filter: &Expr, drs: &DiskReadScheduler) -> Result<Vec<usize>, QueryError> {
+    let mut colnames = HashSet::new();
+    filter.add_colnames(&mut colnames);
+    let rows = scan(partition, &colnames, vec![Expr::ColName(ROW_INDEX_COL.to_string())], filter.clone(), drs)?;
+    rows.into_iter()
+        .map(|row| match row[0] {
+            RawVal::Int(index) => Ok(index as usize),
+            ref value => Err(fatal!("Unexpected row index {:?}", value)),
+        })
+        .collect()
+}
+
+/// All columns of `partition` without the rows that are deleted. Integer and string columns are copied as a whole,
+/// only columns of other types are converted value by value.
+pub fn live_columns(partition: &Partition, drs: &DiskReadScheduler) -> Result<HashMap<String, InputColumn>, QueryError> {
+    let colnames = partition.col_names().into_iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let projection = colnames.iter().cloned().map(Expr::ColName).collect();
+    let all_cols = colnames.iter().cloned().collect::<HashSet<_>>();
+    scan_with(partition, &all_cols, projection, Expr::Const(RawVal::Int(1)), drs, |result| {
+        colnames.iter().zip(&result.projection)
+            .map(|(name, &index)| {
+                let data = &result.columns[index];
+                let column = match data.encoding_type() {
+                    EncodingType::I64 => InputColumn::Int(data.cast_ref_i64().to_vec()),
+                    EncodingType::Str => InputColumn::Str(data.cast_ref_str().iter().map(|s| s.to_string()).collect()),
+                    EncodingType::Null => InputColumn::Null(data.len()),
+                    _ => InputColumn::Values((0..data.len()).map(|i| data.get_raw(i)).collect()),
+                };
+                (name.clone(), column)
+            })
+            .collect()
+    })
+}
+
+/// All values of `column`.
+pub fn column_values(column: &Arc<Column>) -> Result<Vec<RawVal>, QueryError> {
+    let mut cols = HashMap::<String, Arc<DataSource>>::new();
+    cols.insert(column.name().to_string(), Arc::new(column.clone()));
+    let query = NormalFormQuery {
+        projection: vec![Expr::ColName(column.name().to_string())],
+        filter: Expr::Const(RawVal::Int(1)),
+        aggregate: vec![],
+        order_by: vec![],
+        limit: LimitClause { limit: column.len() as u64, offset: 0 },
+        sum_overflow: SumOverflow::default(),
+        row_sample: None,
+        stable_sort: false,
+        group_by_codes: false,
+    };
+    let rows = evaluate(&cols, &query, 0, column.len())?;
+    Ok(rows.into_iter().map(|mut row| row.pop().unwrap()).collect())
+}
+
+/// Column `name` that contains the value of `expr` for each row of the partition with the columns `partition`.
+pub fn generated_column(partition: &[Arc<Column>], name: &str, expr: &Expr) -> Result<Arc<Column>, QueryError> {
+    let len = partition.first().map_or(0, |column| column.len());
+    let mut cols = partition.iter()
+        .map(|column| (column.name().to_string(), Arc::new(column.clone()) as Arc<DataSource>))
+        .collect::<HashMap<_, _>>();
+    let mut colnames = HashSet::new();
+    expr.add_colnames(&mut colnames);
+    for colname in colnames {
+        if !cols.contains_key(&colname) {
+            let null = Arc::new(Column::null(&colname, len));
+            cols.insert(colname, null);
+        }
+    }
+    let query = NormalFormQuery {
+        projection: vec![expr.clone()],
+        filter: Expr::Const(RawVal::Int(1)),
+        aggregate: vec![],
+        order_by: vec![],
+        limit: LimitClause { limit: len as u64, offset: 0 },
+        sum_overflow: SumOverflow::default(),
+        row_sample: None,
+        stable_sort: false,
+        group_by_codes: false,
+    };
+    let values = evaluate(&cols, &query, 0, len)?.into_iter().map(|mut row| row.pop().unwrap()).collect();
+    let mut buffer = Buffer::default();
+    buffer.push_untyped_cols(vec![(name.to_string(), values)].into_iter().collect());
+    Ok(buffer.into_columns().pop().unwrap())
+}
+
+/// Decodes the columns `colnames` of `cols`, which all have `len` rows.
+pub fn rows(cols: &HashMap<String, Arc<DataSource>>, colnames: &[String], len: usize) -> Result<Vec<Vec<RawVal>>, QueryError> {
+    let query = NormalFormQuery {
+        projection: colnames.iter().cloned().map(Expr::ColName).collect(),
+        filter: Expr::Const(RawVal::Int(1)),
+        aggregate: vec![],
+        order_by: vec![],
+        limit: LimitClause { limit: len as u64, offset: 0 },
+        sum_overflow: SumOverflow::default(),
+        row_sample: None,
+        stable_sort: false,
+        group_by_codes: false,
+    };
+    evaluate(cols, &query, 0, len)
+}
+
+fn scan(partition: &Partition,
+        colnames: &HashSet<String>,
+        projection: Vec<Expr>,
+        filter: Expr,
+        drs: &DiskReadScheduler) -> Result<Vec<Vec<RawVal>>, QueryError> {
+    scan_with(partition, colnames, projection, filter, drs, result_rows)
+}
+
+/// Runs a query on `partition` and passes its result to `f`, which must copy out any data it needs since the result
+/// references the columns of the partition.
+fn scan_with<T, F>(partition: &Partition,
+                   colnames: &HashSet<String>,
+                   projection: Vec<Expr>,
+                   filter: Expr,
+                   drs: &DiskReadScheduler,
+                   f: F) -> Result<T, QueryError> where F: FnOnce(&BatchResult) -> T {
+    let mut cols = partition.get_cols(colnames, drs);
+    // Like in queries, columns that were added to the table after the partition was created are null
+    for name in colnames {
+        if !cols.contains_key(name) {
+            cols.insert(name.clone(), Arc::new(Column::null(name, partition.len())));
+        }
+    }
+    let mut row_index = IntColBuilder::new(false);
+    for i in 0..partition.len() {
+        row_index.push(&Some(i as i64));
+    }
+    cols.insert(ROW_INDEX_COL.to_string(), Arc::new(row_index.finalize(ROW_INDEX_COL)));
+
+    let mut query = NormalFormQuery {
+        projection,
+        filter,
+        aggregate: vec![],
+        order_by: vec![],
+        limit: LimitClause { limit: partition.len() as u64, offset: 0 },
+        sum_overflow: SumOverflow::default(),
+        row_sample: None,
+        stable_sort: false,
+        group_by_codes: false,
+    };
+    if let Some(tombstones) = partition.tombstones() {
+        cols.insert(DELETED_COL.to_string(), Arc::new(tombstones));
+        query = query.exclude_deleted();
+    }
+
+    let (result, _) = query.run(&cols, false, false, partition.id() as usize, partition.len(),
+                                &QueryLimits::default(), DEFAULT_BATCH_SIZE, 0)?;
+    Ok(f(&result))
+}
+
+fn evaluate(cols: &HashMap<String, Arc<DataSource>>,
+            query: &NormalFormQuery,
+            partition: usize,
+            len: usize) -> Result<Vec<Vec<RawVal>>, QueryError> {
+    let (result, _) = query.run(cols, false, false, partition, len, &QueryLimits::default(), DEFAULT_BATCH_SIZE, 0)?;
+    Ok(result_rows(&result))
+}
+
+fn result_rows(result: &BatchResult) -> Vec<Vec<RawVal>> {
+    (0..result.len())
+        .map(|i| result.projection.iter().map(|&j| result.columns[j].get_raw(i)).collect())
+        .collect()
+}
@@ -0,0 +1,133 @@
+use engine::*;
+use ingest::raw_val::RawVal;
+use mem_store::value::Val;
+
+
+/// Copy of a `BatchResult` that owns all of its data, so that it can be sent to other processes or stored on disk.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct SerializedBatch {
+    pub columns: Vec<SerializedColumn>,
+    pub projection: Vec<usize>,
+    pub aggregations: Vec<(usize, Aggregator)>,
+    pub order_by: Vec<(usize, bool)>,
+    pub level: u32,
+    pub batch_count: usize,
+    pub show: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum SerializedColumn {
+    Null(usize),
+    Int(Vec<i64>),
+    Float(Vec<OrderedF64>),
+    Str(Vec<String>),
+    /// Values selected by `ARG_MAX` or `ARG_MIN` together with the key that selected them.
+    Keyed(Vec<(Option<i64>, RawVal)>),
+    /// Columns of all other types, e.g. nullable columns, are stored as individual values.
+    Mixed(Vec<RawVal>),
+}
+
+impl SerializedBatch {
+    pub fn new(batch: &BatchResult) -> SerializedBatch {
+        SerializedBatch {
+            columns: batch.columns.iter().map(|column| SerializedColumn::new(&**column)).collect(),
+            projection: batch.projection.clone(),
+            aggregations: batch.aggregations.clone(),
+            order_by: batch.order_by.clone(),
+            level: batch.level,
+            batch_count: batch.batch_count,
+            show: batch.show,
+        }
+    }
+
+    /// Batch result that references the data of `self`, which can be merged with other batch results.
+    pub fn batch_result(&self) -> BatchResult {
+        BatchResult {
+            columns: self.columns.iter().map(SerializedColumn::data).collect(),
+            projection: self.projection.clone(),
+            aggregations: self.aggregations.clone(),
+            order_by: self.order_by.clone(),
+            level: self.level,
+            batch_count: self.batch_count,
+            show: self.show,
+            unsafe_referenced_buffers: Vec::new(),
+        }
+    }
+}
+
+impl SerializedColumn {
+    fn new(data: &Data) -> SerializedColumn {
+        match data.get_type() {
+            EncodingType::Null => SerializedColumn::Null(data.len()),
+            EncodingType::I64 => SerializedColumn::Int(data.cast_ref_i64().to_vec()),
+            EncodingType::F64 => SerializedColumn::Float(data.cast_ref_f64().to_vec()),
+            EncodingType::Str => SerializedColumn::Str(data.cast_ref_str().iter().map(|s| s.to_string()).collect()),
+            EncodingType::KeyedVal => SerializedColumn::Keyed(data.cast_ref_keyed().iter()
+                .map(|keyed| (keyed.key, (&keyed.val).into()))
+                .collect()),
+            _ => SerializedColumn::Mixed((0..data.len()).map(|i| data.get_raw(i)).collect()),
+        }
+    }
+
+    fn data(&self) -> BoxedData {
+        match self {
+            SerializedColumn::Null(len) => Data::empty(*len),
+            SerializedColumn::Int(values) => Data::owned(values.clone()),
+            SerializedColumn::Float(values) => Data::owned(values.clone()),
+            SerializedColumn::Str(values) => Data::owned(values.iter().map(|s| s.as_str()).collect::<Vec<_>>()),
+            SerializedColumn::Mixed(values) => Data::owned(values.iter().map(val).collect::<Vec<_>>()),
+            SerializedColumn::Keyed(values) => Data::owned(values.iter()
+                .map(|(key, value)| KeyedVal { key: *key, val: val(value) })
+                .collect::<Vec<_>>()),
+        }
+    }
+}
+
+fn val(value: &RawVal) -> Val {
+    match value {
+        RawVal::Int(i) => Val::Integer(*i),
+        RawVal::Float(f) => Val::Float(*f),
+        RawVal::Str(s) => Val::Str(s),
+        RawVal::Null => Val::Null,
+    }
+}
+
+#[cfg(all(test, feature = "serialize", feature = "ingest_json"))]
+mod tests {
+    extern crate serde_json;
+
+    use super::*;
+
+    #[test]
+    fn test_batch_roundtrip() {
+        let batch = BatchResult {
+            columns: vec![
+                Data::owned(vec!["a", "b"]),
+                Data::owned(vec![3i64, 4]),
+                Data::owned(vec![Val::Null, Val::Float(OrderedF64(0.5))]),
+                Data::empty(2),
+            ],
+            projection: vec![0, 2, 3],
+            aggregations: vec![(1, Aggregator::Sum)],
+            order_by: vec![(0, true)],
+            level: 1,
+            batch_count: 2,
+            show: false,
+            unsafe_referenced_buffers: Vec::new(),
+        };
+        let serialized = SerializedBatch::new(&batch);
+        let json = serde_json::to_string(&serialized).unwrap();
+        let deserialized = serde_json::from_str::<SerializedBatch>(&json).unwrap();
+        assert_eq!(deserialized, serialized);
+
+        let result = deserialized.batch_result();
+        assert_eq!(result.aggregations, batch.aggregations);
+        for (column, expected) in result.columns.iter().zip(&batch.columns) {
+            assert_eq!(column.get_type(), expected.get_type());
+            assert_eq!((0..2).map(|i| column.get_raw(i)).collect::<Vec<_>>(),
+                       (0..2).map(|i| expected.get_raw(i)).collect::<Vec<_>>());
+        }
+    }
+}
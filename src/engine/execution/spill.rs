@@ -0,0 +1,277 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use QueryError;
+use engine::*;
+use ingest::raw_val::RawVal;
+
+/// Used to give each spilled batch a unique file name.
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Bounds the memory used by the partial results that each worker thread holds until they are merged.
+#[derive(Clone, Debug)]
+pub struct MergePolicy {
+    /// Number of partial results of the same level that are merged into one result of the next level.
+    pub fan_in: usize,
+    /// Bytes of unmerged partial results held by a thread above which results are written to disk.
+    pub spill_threshold: Option<usize>,
+    /// Directory that spilled results are written to, the system's temporary directory if `None`.
+    pub spill_directory: Option<PathBuf>,
+}
+
+impl Default for MergePolicy {
+    fn default() -> MergePolicy {
+        MergePolicy { fan_in: 2, spill_threshold: None, spill_directory: None }
+    }
+}
+
+/// Partial result of a query that is either held in memory or has been written to disk.
+pub enum PartialResult<'a> {
+    InMemory(BatchResult<'a>),
+    Spilled(SpilledBatch),
+}
+
+impl<'a> PartialResult<'a> {
+    pub fn level(&self) -> u32 {
+        match *self {
+            PartialResult::InMemory(ref batch) => batch.level,
+            PartialResult::Spilled(ref spilled) => spilled.level,
+        }
+    }
+
+    pub fn is_sorted(&self) -> bool {
+        match *self {
+            PartialResult::InMemory(ref batch) => !batch.order_by.is_empty(),
+            PartialResult::Spilled(ref spilled) => !spilled.order_by.is_empty(),
+        }
+    }
+
+    /// Bytes of memory used by the partial result.
+    pub fn heap_size_bytes(&self) -> usize {
+        match *self {
+            PartialResult::InMemory(ref batch) => batch.heap_size_bytes(),
+            PartialResult::Spilled(_) => 0,
+        }
+    }
+}
+
+/// Batch result whose columns were written to a file, which is deleted when it is dropped.
+pub struct SpilledBatch {
+    path: PathBuf,
+    projection: Vec<usize>,
+    aggregations: Vec<(usize, Aggregator)>,
+    order_by: Vec<(usize, bool)>,
+    level: u32,
+    batch_count: usize,
+    show: bool,
+}
+
+impl SpilledBatch {
+    pub fn write(batch: &BatchResult, policy: &MergePolicy) -> Result<SpilledBatch, QueryError> {
+        let dir = policy.spill_directory.clone().unwrap_or_else(env::temp_dir);
+        let path = dir.join(format!("locustdb-{}-{}.spill", process::id(), SPILL_COUNTER.fetch_add(1, Ordering::SeqCst)));
+        let spilled = SpilledBatch {
+            path,
+            projection: batch.projection.clone(),
+            aggregations: batch.aggregations.clone(),
+            order_by: batch.order_by.clone(),
+            level: batch.level,
+            batch_count: batch.batch_count,
+            show: batch.show,
+        };
+        let serialized = SerializedBatch::new(batch);
+        let file = File::create(&spilled.path).map_err(|err| io_error(&spilled.path, err))?;
+        let mut writer = BufWriter::new(file);
+        write_columns(&mut writer, &serialized.columns)
+            .and_then(|_| writer.flush())
+            .map_err(|err| io_error(&spilled.path, err))?;
+        Ok(spilled)
+    }
+
+    /// Reads the batch back into memory and deletes the file.
+    pub fn read(self) -> Result<SerializedBatch, QueryError> {
+        let file = File::open(&self.path).map_err(|err| io_error(&self.path, err))?;
+        let columns = read_columns(&mut BufReader::new(file)).map_err(|err| io_error(&self.path, err))?;
+        Ok(SerializedBatch {
+            columns,
+            projection: self.projection.clone(),
+            aggregations: self.aggregations.clone(),
+            order_by: self.order_by.clone(),
+            level: self.level,
+            batch_count: self.batch_count,
+            show: self.show,
+        })
+    }
+}
+
+impl Drop for SpilledBatch {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn io_error(path: &PathBuf, err: io::Error) -> QueryError {
+    QueryError::IoError(format!("Failed to spill partial result to {}: {}", path.display(), err))
+}
+
+fn write_columns<W: Write>(writer: &mut W, columns: &[SerializedColumn]) -> io::Result<()> {
+    writer.write_u64::<BigEndian>(columns.len() as u64)?;
+    for column in columns {
+        match *column {
+            SerializedColumn::Null(len) => {
+                writer.write_u8(0)?;
+                writer.write_u64::<BigEndian>(len as u64)?;
+            }
+            SerializedColumn::Int(ref values) => {
+                writer.write_u8(1)?;
+                writer.write_u64::<BigEndian>(values.len() as u64)?;
+                for &value in values {
+                    writer.write_i64::<BigEndian>(value)?;
+                }
+            }
+            SerializedColumn::Float(ref values) => {
+                writer.write_u8(2)?;
+                writer.write_u64::<BigEndian>(values.len() as u64)?;
+                for value in values {
+                    writer.write_f64::<BigEndian>(value.0)?;
+                }
+            }
+            SerializedColumn::Str(ref values) => {
+                writer.write_u8(3)?;
+                writer.write_u64::<BigEndian>(values.len() as u64)?;
+                for value in values {
+                    write_str(writer, value)?;
+                }
+            }
+            SerializedColumn::Mixed(ref values) => {
+                writer.write_u8(4)?;
+                writer.write_u64::<BigEndian>(values.len() as u64)?;
+                for value in values {
+                    write_raw_val(writer, value)?;
+                }
+            }
+            SerializedColumn::Keyed(ref values) => {
+                writer.write_u8(5)?;
+                writer.write_u64::<BigEndian>(values.len() as u64)?;
+                for &(key, ref value) in values {
+                    match key {
+                        Some(key) => {
+                            writer.write_u8(1)?;
+                            writer.write_i64::<BigEndian>(key)?;
+                        }
+                        None => writer.write_u8(0)?,
+                    }
+                    write_raw_val(writer, value)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_raw_val<W: Write>(writer: &mut W, value: &RawVal) -> io::Result<()> {
+    match *value {
+        RawVal::Null => writer.write_u8(0),
+        RawVal::Int(int) => {
+            writer.write_u8(1)?;
+            writer.write_i64::<BigEndian>(int)
+        }
+        RawVal::Float(float) => {
+            writer.write_u8(2)?;
+            writer.write_f64::<BigEndian>(float.0)
+        }
+        RawVal::Str(ref string) => {
+            writer.write_u8(3)?;
+            write_str(writer, string)
+        }
+    }
+}
+
+fn read_columns<R: Read>(reader: &mut R) -> io::Result<Vec<SerializedColumn>> {
+    let count = reader.read_u64::<BigEndian>()? as usize;
+    let mut columns = Vec::with_capacity(count);
+    for _ in 0..count {
+        let tag = reader.read_u8()?;
+        let len = reader.read_u64::<BigEndian>()? as usize;
+        columns.push(match tag {
+            0 => SerializedColumn::Null(len),
+            1 => SerializedColumn::Int((0..len).map(|_| reader.read_i64::<BigEndian>()).collect::<Result<_, _>>()?),
+            2 => SerializedColumn::Float(
+                (0..len).map(|_| reader.read_f64::<BigEndian>().map(OrderedF64)).collect::<Result<_, _>>()?),
+            3 => SerializedColumn::Str((0..len).map(|_| read_str(reader)).collect::<Result<_, _>>()?),
+            4 => SerializedColumn::Mixed((0..len).map(|_| read_raw_val(reader)).collect::<Result<_, _>>()?),
+            5 => SerializedColumn::Keyed((0..len).map(|_| read_keyed(reader)).collect::<Result<_, _>>()?),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid column tag {}", tag))),
+        });
+    }
+    Ok(columns)
+}
+
+fn read_raw_val<R: Read>(reader: &mut R) -> io::Result<RawVal> {
+    Ok(match reader.read_u8()? {
+        0 => RawVal::Null,
+        1 => RawVal::Int(reader.read_i64::<BigEndian>()?),
+        2 => RawVal::Float(OrderedF64(reader.read_f64::<BigEndian>()?)),
+        3 => RawVal::Str(read_str(reader)?),
+        tag => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid value tag {}", tag))),
+    })
+}
+
+fn read_keyed<R: Read>(reader: &mut R) -> io::Result<(Option<i64>, RawVal)> {
+    let key = match reader.read_u8()? {
+        0 => None,
+        1 => Some(reader.read_i64::<BigEndian>()?),
+        tag => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid key tag {}", tag))),
+    };
+    Ok((key, read_raw_val(reader)?))
+}
+
+fn write_str<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    writer.write_u64::<BigEndian>(string.len() as u64)?;
+    writer.write_all(string.as_bytes())
+}
+
+fn read_str<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = reader.read_u64::<BigEndian>()? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mem_store::value::Val;
+
+    #[test]
+    fn test_spill_roundtrip() {
+        let batch = BatchResult {
+            columns: vec![
+                Data::owned(vec!["a", "b"]),
+                Data::owned(vec![3i64, 4]),
+                Data::owned(vec![Val::Null, Val::Float(OrderedF64(0.5))]),
+                Data::empty(2),
+                Data::owned(vec![KeyedVal { key: Some(-1), val: Val::Str("c") }, KeyedVal { key: None, val: Val::Null }]),
+            ],
+            projection: vec![0, 2, 3],
+            aggregations: vec![(1, Aggregator::Sum), (4, Aggregator::ArgMax)],
+            order_by: vec![],
+            level: 1,
+            batch_count: 2,
+            show: false,
+            unsafe_referenced_buffers: Vec::new(),
+        };
+        let spilled = SpilledBatch::write(&batch, &MergePolicy::default()).unwrap();
+        let path = spilled.path.clone();
+        assert!(path.exists());
+        let serialized = spilled.read().unwrap();
+        assert!(!path.exists());
+        assert_eq!(serialized, SerializedBatch::new(&batch));
+    }
+}
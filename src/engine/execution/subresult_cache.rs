@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use disk_store::interface::PartitionID;
+use engine::execution::BatchResult;
+use mem_store::column::DataSource;
+
+
+/// Identifies the result of a query on a single partition. Partitions are immutable except for deletions, so the
+/// result stays valid as long as the number of deleted rows is unchanged.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SubresultKey {
+    pub partition: PartitionID,
+    pub deleted_rows: usize,
+    /// Normalized query that was run on the partition.
+    pub query: String,
+}
+
+impl SubresultKey {
+    pub fn new(partition: PartitionID, deleted_rows: usize, query: &str) -> SubresultKey {
+        SubresultKey {
+            partition,
+            deleted_rows,
+            query: query.to_string(),
+        }
+    }
+}
+
+/// Results of aggregations on individual partitions, which allows repeated queries to only aggregate partitions that
+/// were created since they last ran. Once the results take up more than `capacity_bytes`, the least recently used
+/// ones are evicted.
+pub struct SubresultCache {
+    capacity_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<SubresultKey, CacheEntry>,
+    size_bytes: usize,
+    clock: u64,
+}
+
+struct CacheEntry {
+    result: BatchResult<'static>,
+    /// Columns referenced by `result`, which must be kept alive for as long as the result.
+    cols: HashMap<String, Arc<DataSource>>,
+    size_bytes: usize,
+    last_used: u64,
+}
+
+impl SubresultCache {
+    /// Cache that holds results of up to `capacity_bytes`, 0 disables the cache.
+    pub fn new(capacity_bytes: usize) -> SubresultCache {
+        SubresultCache {
+            capacity_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity_bytes > 0
+    }
+
+    /// Copy of the cached result for `key` together with the columns it references.
+    pub fn get(&self, key: &SubresultKey) -> Option<(BatchResult<'static>, HashMap<String, Arc<DataSource>>)> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+        match state.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = clock;
+                entry.result.try_clone().map(|result| (result, entry.cols.clone()))
+            }
+            None => None,
+        }
+    }
+
+    /// Stores a copy of `result`, which may reference data in `cols`. Results that can't be copied are not cached.
+    pub fn insert(&self, key: SubresultKey, result: &BatchResult<'static>, cols: &HashMap<String, Arc<DataSource>>) {
+        if !self.is_enabled() {
+            return;
+        }
+        let result = match result.try_clone() {
+            Some(result) => result,
+            None => return,
+        };
+        let size_bytes = key.query.len() + result.heap_size_bytes();
+        if size_bytes > self.capacity_bytes {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let previous = state.entries.remove(&key);
+        if let Some(previous) = previous {
+            state.size_bytes -= previous.size_bytes;
+        }
+        while state.size_bytes + size_bytes > self.capacity_bytes {
+            let lru = state.entries.iter()
+                .min_by_key(|&(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            let evicted = state.entries.remove(&lru).unwrap();
+            state.size_bytes -= evicted.size_bytes;
+        }
+        state.clock += 1;
+        let last_used = state.clock;
+        state.size_bytes += size_bytes;
+        state.entries.insert(key, CacheEntry { result, cols: cols.clone(), size_bytes, last_used });
+    }
+
+    /// Removes all results, e.g. after changes to functions which affect all queries.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.size_bytes = 0;
+    }
+}
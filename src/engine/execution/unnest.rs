@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::mem;
+use std::str;
+use std::sync::Arc;
+
+use ::QueryError;
+use engine::*;
+use engine::execution::scan;
+use ingest::list;
+use ingest::raw_val::RawVal;
+use mem_store::column::DataSource;
+use mem_store::raw_col::MixedCol;
+use syntax::expression::*;
+
+
+/// Explodes each partition into one row for every element of a list column before the query is evaluated. The
+/// elements of list columns are read from their sections directly, strings of other columns such as rows that are still
+/// buffered are parsed as JSON arrays.
+///
+/// `UNNEST(column)` is replaced by a column that holds the list elements, all other columns repeat the values of the
+/// row that contains the list. Rows with empty or null lists are dropped and values that are not lists are treated
+/// as lists with a single element.
+#[derive(Debug, Clone)]
+pub struct UnnestStage {
+    column: String,
+    unnested: String,
+}
+
+impl UnnestStage {
+    /// Replaces `UNNEST(column)` in all expressions of `query` with the column that holds the list elements.
+    pub fn extract(query: &mut Query) -> Result<Option<UnnestStage>, QueryError> {
+        let mut column: Option<String> = None;
+        {
+            let mut rewrite = |expr: Expr| expr.map_unnest(&mut |name| {
+                if let Some(ref column) = column {
+                    if *column != name {
+                        bail!(QueryError::NotImplemented, "UNNEST of more than one column ({}, {})", column, name)
+                    }
+                }
+                let unnested = Expr::ColName(UnnestStage::unnested_colname(&name));
+                column = Some(name);
+                Ok(unnested)
+            });
+            let select = mem::replace(&mut query.select, vec![]);
+            query.select = select.into_iter().map(&mut rewrite).collect::<Result<_, _>>()?;
+            let filter = mem::replace(&mut query.filter, Expr::Const(RawVal::Int(1)));
+            query.filter = rewrite(filter)?;
+            let order_by = mem::replace(&mut query.order_by, vec![]);
+            query.order_by = order_by.into_iter()
+                .map(|(expr, desc)| rewrite(expr).map(|expr| (expr, desc)))
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(column.map(|column| UnnestStage {
+            unnested: UnnestStage::unnested_colname(&column),
+            column,
+        }))
+    }
+
+    /// The list column, which has to be loaded in addition to the columns referenced by the query.
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Explodes the columns `cols` of a partition with `len` rows, returns the exploded columns and their length.
+    pub fn explode(&self, cols: &HashMap<String, Arc<DataSource>>, len: usize)
+                   -> Result<(HashMap<String, Arc<DataSource>>, usize), QueryError> {
+        let colnames = cols.keys().cloned().collect::<Vec<_>>();
+        let list_index = colnames.iter().position(|name| *name == self.column);
+        let mut lists = cols.get(&self.column).and_then(|column| list_elements(&**column));
+        let mut exploded = colnames.iter().map(|_| MixedCol::default()).collect::<Vec<_>>();
+        let mut elements = MixedCol::default();
+        for (i, row) in scan::rows(cols, &colnames, len)?.into_iter().enumerate() {
+            let list = match (lists.as_mut(), list_index.map(|i| &row[i])) {
+                (Some(lists), _) => mem::replace(&mut lists[i], vec![]),
+                (None, Some(&RawVal::Str(ref value))) => match list::decode(value) {
+                    Some(list) => list.into_iter().map(RawVal::Str).collect(),
+                    None => vec![RawVal::Str(value.clone())],
+                },
+                (None, Some(&RawVal::Null)) | (None, None) => vec![],
+                (None, Some(value)) => vec![value.clone()],
+            };
+            for element in list {
+                elements.push(element);
+                for (col, value) in exploded.iter_mut().zip(row.iter()) {
+                    col.push(value.clone());
+                }
+            }
+        }
+
+        let exploded_len = elements.len();
+        let mut result = HashMap::<String, Arc<DataSource>>::new();
+        for (name, col) in colnames.into_iter().zip(exploded) {
+            let col = col.finalize(&name);
+            result.insert(name, col);
+        }
+        result.insert(self.unnested.clone(), elements.finalize(&self.unnested));
+        Ok((result, exploded_len))
+    }
+
+    fn unnested_colname(column: &str) -> String {
+        format!("unnest({})", column)
+    }
+}
+
+/// Elements of each list of `column` if it is a list column, looked up in its dictionary without decoding the lists.
+fn list_elements(column: &DataSource) -> Option<Vec<Vec<RawVal>>> {
+    if !column.codec().is_list() {
+        return None;
+    }
+    let sections = column.data_sections();
+    let dict_data = sections[3].cast_ref_u8();
+    let dictionary = sections[2].cast_ref_u64().iter()
+        .map(|offset_len| {
+            let offset = (offset_len >> 24) as usize;
+            let len = (offset_len & 0x00ff_ffff) as usize;
+            unsafe { str::from_utf8_unchecked(&dict_data[offset..(offset + len)]) }
+        })
+        .collect::<Vec<_>>();
+    let mut start = 0;
+    let lists = sections[0].cast_ref_u32().iter()
+        .map(|&end| {
+            let list = (start..end as usize)
+                .map(|i| match sections[1].get_raw(i) {
+                    RawVal::Int(index) => RawVal::Str(dictionary[index as usize].to_string()),
+                    _ => unreachable!(),
+                })
+                .collect();
+            start = end as usize;
+            list
+        })
+        .collect();
+    Some(lists)
+}
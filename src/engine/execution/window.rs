@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use std::i64;
+use std::mem;
+
+use engine::*;
+use ingest::raw_val::RawVal;
+use syntax::expression::*;
+use syntax::limit::LimitClause;
+use udf::ValueType;
+
+
+/// Evaluates window functions on the merged and ordered query result.
+///
+/// All expressions that window functions depend on are added to the query as additional columns, the window
+/// functions are then computed from the result rows before the original limit is applied.
+#[derive(Debug, Clone)]
+pub struct WindowStage {
+    columns: Vec<OutputColumn>,
+    windows: Vec<WindowColumns>,
+    is_aggregate: Vec<bool>,
+    aggregates_last: bool,
+    limit: LimitClause,
+}
+
+#[derive(Debug, Clone)]
+enum OutputColumn {
+    Selected(usize),
+    Window(usize),
+}
+
+#[derive(Debug, Clone)]
+struct WindowColumns {
+    function: WindowFunction,
+    args: Vec<usize>,
+    partition_by: Vec<usize>,
+    order_by: Vec<(usize, bool)>,
+}
+
+impl WindowStage {
+    /// Replaces all window functions selected by `query` with the columns required to evaluate them.
+    pub fn extract(query: &mut Query) -> Option<WindowStage> {
+        let has_windows = query.select.iter().any(|expr| match expr {
+            Expr::Window(_) => true,
+            _ => false,
+        });
+        if !has_windows {
+            return None;
+        }
+
+        let mut select = Vec::new();
+        let mut columns = Vec::new();
+        let mut windows = Vec::new();
+        {
+            let mut add_column = |expr: Expr| {
+                select.push(expr);
+                select.len() - 1
+            };
+            for expr in mem::replace(&mut query.select, vec![]) {
+                match expr {
+                    Expr::Window(window) => {
+                        let window = *window;
+                        columns.push(OutputColumn::Window(windows.len()));
+                        windows.push(WindowColumns {
+                            function: window.function,
+                            args: window.args.into_iter().map(&mut add_column).collect(),
+                            partition_by: window.partition_by.into_iter().map(&mut add_column).collect(),
+                            order_by: window.order_by.into_iter().map(|(expr, desc)| (add_column(expr), desc)).collect(),
+                        });
+                    }
+                    expr => columns.push(OutputColumn::Selected(add_column(expr))),
+                }
+            }
+        }
+        let is_aggregate = select.iter()
+            .map(|expr| !Query::extract_aggregators(expr, &mut vec![]).1.is_empty())
+            .collect();
+        query.select = select;
+        let limit = mem::replace(&mut query.limit, LimitClause { limit: i64::MAX as u64, offset: 0 });
+        Some(WindowStage { columns, windows, is_aggregate, aggregates_last: false, limit })
+    }
+
+    /// Queries without a final pass output all aggregates after all other columns.
+    pub fn set_aggregates_last(&mut self, aggregates_last: bool) {
+        self.aggregates_last = aggregates_last;
+    }
+
+    pub fn colnames(&self, result_colnames: &[String]) -> Vec<String> {
+        let result_columns = self.result_columns();
+        self.columns.iter()
+            .map(|column| match *column {
+                OutputColumn::Selected(i) => result_colnames[result_columns[i]].clone(),
+                OutputColumn::Window(i) => format!("{}_{}", self.windows[i].function.name(), i),
+            })
+            .collect()
+    }
+
+    pub fn apply(&self, rows: Vec<Vec<RawVal>>) -> Vec<Vec<RawVal>> {
+        let result_columns = self.result_columns();
+        let window_values = self.windows.iter()
+            .map(|window| window.evaluate(&rows, &result_columns))
+            .collect::<Vec<_>>();
+        rows.iter()
+            .enumerate()
+            .skip(self.limit.offset as usize)
+            .take(self.limit.limit as usize)
+            .map(|(i, row)| self.columns.iter()
+                .map(|column| match *column {
+                    OutputColumn::Selected(j) => row[result_columns[j]].clone(),
+                    OutputColumn::Window(w) => window_values[w][i].clone(),
+                })
+                .collect())
+            .collect()
+    }
+
+    /// Types of the output columns given the types `coltypes` of the columns of the result rows.
+    pub fn coltypes(&self, coltypes: &[Option<ValueType>]) -> Vec<Option<ValueType>> {
+        let result_columns = self.result_columns();
+        self.columns.iter()
+            .map(|column| match *column {
+                OutputColumn::Selected(j) => coltypes[result_columns[j]],
+                OutputColumn::Window(w) => {
+                    let window = &self.windows[w];
+                    match window.function {
+                        WindowFunction::RowNumber => Some(ValueType::Integer),
+                        WindowFunction::Lag(_) | WindowFunction::Lead(_) | WindowFunction::Sum =>
+                            coltypes[result_columns[window.args[0]]],
+                    }
+                }
+            })
+            .collect()
+    }
+
+    // Index of each selected expression in the result rows.
+    fn result_columns(&self) -> Vec<usize> {
+        if !self.aggregates_last {
+            return (0..self.is_aggregate.len()).collect();
+        }
+        let mut result_columns = vec![0; self.is_aggregate.len()];
+        let mut next = 0;
+        for pass in &[false, true] {
+            for (i, &is_aggregate) in self.is_aggregate.iter().enumerate() {
+                if is_aggregate == *pass {
+                    result_columns[i] = next;
+                    next += 1;
+                }
+            }
+        }
+        result_columns
+    }
+}
+
+impl WindowColumns {
+    fn evaluate(&self, rows: &[Vec<RawVal>], result_columns: &[usize]) -> Vec<RawVal> {
+        let value = |row: usize, column: usize| &rows[row][result_columns[column]];
+        let same_partition = |a: usize, b: usize| self.partition_by.iter().all(|&c| value(a, c) == value(b, c));
+
+        // Stable sort keeps rows that are equal under the window ordering in result order
+        let mut indices = (0..rows.len()).collect::<Vec<_>>();
+        indices.sort_by(|&a, &b| {
+            self.partition_by.iter()
+                .map(|&c| value(a, c).cmp(value(b, c)))
+                .chain(self.order_by.iter().map(|&(c, desc)| {
+                    let ordering = value(a, c).cmp(value(b, c));
+                    if desc { ordering.reverse() } else { ordering }
+                }))
+                .find(|&ordering| ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let mut result = vec![RawVal::Null; rows.len()];
+        let mut start = 0;
+        while start < indices.len() {
+            let mut end = start + 1;
+            while end < indices.len() && same_partition(indices[start], indices[end]) {
+                end += 1;
+            }
+            let partition = &indices[start..end];
+            match self.function {
+                WindowFunction::RowNumber => for (n, &row) in partition.iter().enumerate() {
+                    result[row] = RawVal::Int(n as i64 + 1);
+                },
+                WindowFunction::Lag(offset) => for (n, &row) in partition.iter().enumerate().skip(offset) {
+                    result[row] = value(partition[n - offset], self.args[0]).clone();
+                },
+                WindowFunction::Lead(offset) => for (n, &row) in partition.iter().enumerate() {
+                    if let Some(&next) = partition.get(n + offset) {
+                        result[row] = value(next, self.args[0]).clone();
+                    }
+                },
+                WindowFunction::Sum => {
+                    let mut sum = RawVal::Int(0);
+                    for &row in partition {
+                        let next = match (&sum, value(row, self.args[0])) {
+                            (&RawVal::Int(s), &RawVal::Int(i)) => RawVal::Int(s + i),
+                            (&RawVal::Int(s), &RawVal::Float(f)) => RawVal::Float(OrderedF64(s as f64 + f.0)),
+                            (&RawVal::Float(s), &RawVal::Int(i)) => RawVal::Float(OrderedF64(s.0 + i as f64)),
+                            (&RawVal::Float(s), &RawVal::Float(f)) => RawVal::Float(OrderedF64(s.0 + f.0)),
+                            (s, _) => s.clone(),
+                        };
+                        sum = next;
+                        result[row] = sum.clone();
+                    }
+                }
+            }
+            start = end;
+        }
+        result
+    }
+}
@@ -1,11 +1,59 @@
+use engine::data_types::OrderedF64;
+use super::hyperloglog;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum Aggregator {
     Sum = 0,
     Count = 1,
+    /// Register-wise maximum of HyperLogLog sketch lanes.
+    ApproxCountDistinct = 2,
+    /// Merges quantile sketches, which are stored as strings rather than integers.
+    Percentile = 3,
+    Min = 4,
+    Max = 5,
+    /// Merges histograms, which are stored as strings like quantile sketches.
+    Histogram = 6,
+    /// Value in the row with the largest key, stored as string together with the key.
+    ArgMax = 7,
+    /// Value in the row with the smallest key, stored as string together with the key.
+    ArgMin = 8,
+}
+
+/// How sums of integers that exceed the range of `i64` are handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum SumOverflow {
+    /// The query fails with `QueryError::Overflow`.
+    Error,
+    /// Integers are summed as floats, which can't overflow but are only exact up to 2^53.
+    Float,
+}
+
+impl Default for SumOverflow {
+    fn default() -> SumOverflow {
+        SumOverflow::Error
+    }
 }
 
 impl Aggregator {
-    pub fn combine_i64(self, accumulator: i64, elem: i64) -> i64 {
-        accumulator + elem
+    /// Combines two partial aggregates, `None` if the result overflows.
+    pub fn combine_i64(self, accumulator: i64, elem: i64) -> Option<i64> {
+        match self {
+            Aggregator::Sum | Aggregator::Count => accumulator.checked_add(elem),
+            Aggregator::ApproxCountDistinct => Some(hyperloglog::merge(accumulator, elem)),
+            Aggregator::Min => Some(accumulator.min(elem)),
+            Aggregator::Max => Some(accumulator.max(elem)),
+            Aggregator::Percentile => panic!("Quantile sketches cannot be combined as integers"),
+            Aggregator::Histogram => panic!("Histograms cannot be combined as integers"),
+            Aggregator::ArgMax | Aggregator::ArgMin => panic!("{:?} cannot be combined as integers", self),
+        }
+    }
+
+    pub fn combine_f64(self, accumulator: OrderedF64, elem: OrderedF64) -> OrderedF64 {
+        match self {
+            Aggregator::Sum | Aggregator::Count => OrderedF64(accumulator.0 + elem.0),
+            _ => panic!("{:?} cannot be combined as floats", self),
+        }
     }
 }
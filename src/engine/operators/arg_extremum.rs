@@ -0,0 +1,165 @@
+use bitvec::BitVec;
+use engine::*;
+use mem_store::value::Val;
+
+
+/// Payload of `ARG_MAX` or `ARG_MIN`.
+pub trait Payload<'a>: Copy {
+    fn val(self) -> Val<'a>;
+}
+
+macro_rules! integer_payload {
+    ($($t:ty),*) => {
+        $(
+            impl<'a> Payload<'a> for $t {
+                fn val(self) -> Val<'a> { Val::Integer(self as i64) }
+            }
+        )*
+    }
+}
+
+integer_payload!(u8, u16, u32, u64, i64);
+
+impl<'a> Payload<'a> for OrderedF64 {
+    fn val(self) -> Val<'a> { Val::Float(self) }
+}
+
+impl<'a> Payload<'a> for &'a str {
+    fn val(self) -> Val<'a> { Val::Str(self) }
+}
+
+/// Whether `key` replaces the `current` key of a group, ties are resolved in favor of `current`.
+fn selects(key: i64, current: Option<i64>, max: bool) -> bool {
+    match current {
+        Some(current) => if max { key > current } else { key < current },
+        None => true,
+    }
+}
+
+/// Value of `payload` in the row with the largest (or smallest) `key` of each group, together with the key.
+/// Rows with a null key are skipped.
+#[derive(Debug)]
+pub struct VecArgExtremum<'a, T, P> {
+    pub grouping: BufferRef<T>,
+    pub key: BufferRef<i64>,
+    pub key_present: Option<BufferRef<Nullable<Any>>>,
+    pub payload: BufferRef<P>,
+    pub payload_present: Option<BufferRef<Nullable<Any>>>,
+    pub max_index: BufferRef<Scalar<i64>>,
+    pub max: bool,
+    pub selected: BufferRef<KeyedVal<'a>>,
+}
+
+impl<'a, T: GenericIntVec<T>, P: VecData<P> + Payload<'a> + 'a> VecOperator<'a> for VecArgExtremum<'a, T, P> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let selected = {
+            let grouping = scratchpad.get(self.grouping);
+            let keys = scratchpad.get(self.key);
+            let key_present = self.key_present.map(|present| scratchpad.get_null_map(present));
+            let payload = scratchpad.get(self.payload);
+            let payload_present = self.payload_present.map(|present| scratchpad.get_null_map(present));
+            let len = scratchpad.get_scalar(&self.max_index) as usize + 1;
+            let mut selected = vec![KeyedVal { key: None, val: Val::Null }; len];
+            for (row, (i, &key)) in grouping.iter().zip(keys.iter()).enumerate() {
+                if let Some(ref present) = key_present {
+                    if !(&**present).is_set(row) { continue; }
+                }
+                let selected = &mut selected[i.cast_usize()];
+                if selects(key, selected.key, self.max) {
+                    let val = match payload_present {
+                        Some(ref present) if !(&**present).is_set(row) => Val::Null,
+                        _ => payload[row].val(),
+                    };
+                    *selected = KeyedVal { key: Some(key), val };
+                }
+            }
+            selected
+        };
+        scratchpad.set(self.selected, selected);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> {
+        vec![self.grouping.any(), self.key.any(), self.payload.any(), self.max_index.any()]
+    }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.selected.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        let name = if self.max { "arg_max" } else { "arg_min" };
+        format!("{}[{}] = {}({}, {})", self.selected, self.grouping, name, self.payload, self.key)
+    }
+    fn display_output(&self) -> bool { false }
+}
+
+
+#[derive(Debug)]
+pub struct MergeArgExtremum<'a> {
+    pub merge_ops: BufferRef<MergeOp>,
+    pub left: BufferRef<KeyedVal<'a>>,
+    pub right: BufferRef<KeyedVal<'a>>,
+    pub max: bool,
+    pub merged: BufferRef<KeyedVal<'a>>,
+}
+
+impl<'a> VecOperator<'a> for MergeArgExtremum<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let merged = {
+            let ops = scratchpad.get(self.merge_ops);
+            let left = scratchpad.get(self.left);
+            let right = scratchpad.get(self.right);
+            let mut merged = Vec::with_capacity(ops.len());
+            let mut i = 0;
+            let mut j = 0;
+            for op in ops.iter() {
+                match *op {
+                    MergeOp::TakeLeft => {
+                        merged.push(left[i]);
+                        i += 1;
+                    }
+                    MergeOp::TakeRight => {
+                        merged.push(right[j]);
+                        j += 1;
+                    }
+                    MergeOp::MergeRight => {
+                        let current = merged.last_mut().unwrap();
+                        if let Some(key) = right[j].key {
+                            if selects(key, current.key, self.max) {
+                                *current = right[j];
+                            }
+                        }
+                        j += 1;
+                    }
+                }
+            }
+            merged
+        };
+        scratchpad.set(self.merged, merged);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.left.any(), self.right.any(), self.merge_ops.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.merged.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        let name = if self.max { "merge_arg_max" } else { "merge_arg_min" };
+        format!("{}({}; {}, {})", name, self.merge_ops, self.left, self.right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selects() {
+        assert!(selects(-17, None, true));
+        assert!(selects(3, Some(2), true));
+        assert!(!selects(2, Some(2), true));
+        assert!(selects(1, Some(2), false));
+        assert!(!selects(3, Some(2), false));
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+
+use seahash;
+
+use engine::data_types::OrderedF64;
+
+
+/// Multiplier for multiply-shift hashing, the odd integer closest to 2^64 divided by the golden ratio.
+const MULTIPLIER: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Hashes a whole batch of keys in a loop that is specialized for the type of the keys, so that probing the grouping
+/// hash table only has to compare keys.
+pub trait BatchHash: Copy + Eq {
+    fn hash_batch(keys: &[Self], hashes: &mut Vec<u64>);
+}
+
+/// Multiply-shift hash of an integer key. Only the high bits of the product are well distributed, so they are rotated
+/// into the low bits that select the bucket.
+#[inline]
+pub fn multiply_shift(key: u64) -> u64 {
+    key.wrapping_mul(MULTIPLIER).rotate_left(32)
+}
+
+macro_rules! integer_batch_hash {
+    ($($t:ty),*) => {
+        $(
+            impl BatchHash for $t {
+                fn hash_batch(keys: &[$t], hashes: &mut Vec<u64>) {
+                    hashes.clear();
+                    hashes.extend(keys.iter().map(|&key| multiply_shift(key as u64)));
+                }
+            }
+        )*
+    }
+}
+
+integer_batch_hash!(u8, u16, u32, u64, i64);
+
+impl BatchHash for OrderedF64 {
+    fn hash_batch(keys: &[OrderedF64], hashes: &mut Vec<u64>) {
+        hashes.clear();
+        // Equal floats have equal normalized bits
+        hashes.extend(keys.iter().map(|key| multiply_shift(key.normalized_bits())));
+    }
+}
+
+impl<'a> BatchHash for &'a str {
+    fn hash_batch(keys: &[&'a str], hashes: &mut Vec<u64>) {
+        hashes.clear();
+        hashes.extend(keys.iter().map(|key| seahash::hash(key.as_bytes())));
+    }
+}
+
+/// Hashes each row of `row_len` byte slices.
+pub fn hash_byte_slice_rows(data: &[&[u8]], row_len: usize, hashes: &mut Vec<u64>) {
+    hashes.clear();
+    hashes.extend(data.chunks(row_len).map(|row| {
+        row.iter().fold(0, |hash, slice| multiply_shift(hash ^ seahash::hash(slice)))
+    }));
+}
+
+/// Key of a hash table together with its hash, which was computed by `BatchHash`.
+#[derive(Copy, Clone, Debug)]
+pub struct Prehashed<T> {
+    pub hash: u64,
+    pub key: T,
+}
+
+impl<T: Eq> PartialEq for Prehashed<T> {
+    fn eq(&self, other: &Prehashed<T>) -> bool {
+        self.hash == other.hash && self.key == other.key
+    }
+}
+
+impl<T: Eq> Eq for Prehashed<T> {}
+
+impl<T> Hash for Prehashed<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+/// Hasher that passes through the hash of `Prehashed` keys.
+#[derive(Default)]
+pub struct PassThroughHasher(u64);
+
+impl Hasher for PassThroughHasher {
+    fn finish(&self) -> u64 { self.0 }
+    fn write(&mut self, _: &[u8]) { panic!("PassThroughHasher only hashes Prehashed keys") }
+    fn write_u64(&mut self, hash: u64) { self.0 = hash }
+}
+
+pub type PrehashedMap<K, V> = HashMap<Prehashed<K>, V, BuildHasherDefault<PassThroughHasher>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_keys_select_distinct_buckets() {
+        let keys = (0..256u64).collect::<Vec<_>>();
+        let mut hashes = Vec::new();
+        u64::hash_batch(&keys, &mut hashes);
+        let mut buckets = hashes.iter().map(|hash| hash & 1023).collect::<Vec<_>>();
+        buckets.sort();
+        buckets.dedup();
+        assert_eq!(buckets.len(), 256);
+    }
+
+    #[test]
+    fn test_prehashed_map() {
+        let keys = ["a", "b", "a", "c", "b"];
+        let mut hashes = Vec::new();
+        <&str as BatchHash>::hash_batch(&keys, &mut hashes);
+        let mut map = PrehashedMap::default();
+        let groups = keys.iter().zip(hashes.iter())
+            .map(|(&key, &hash)| {
+                let len = map.len();
+                *map.entry(Prehashed { hash, key }).or_insert(len)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(groups, vec![0, 1, 0, 2, 1]);
+    }
+}
@@ -0,0 +1,102 @@
+use bitvec::BitVec;
+use engine::*;
+
+
+/// Replaces null elements of `lhs` with the corresponding element of `rhs`.
+#[derive(Debug)]
+pub struct Coalesce<T> {
+    pub lhs: BufferRef<Nullable<T>>,
+    pub rhs: BufferRef<T>,
+    pub output: BufferRef<T>,
+}
+
+impl<'a, T: VecData<T> + 'a> VecOperator<'a> for Coalesce<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let coalesced = {
+            let (lhs, present) = scratchpad.get_nullable(self.lhs);
+            let rhs = scratchpad.get(self.rhs);
+            lhs.iter().zip(rhs.iter()).enumerate()
+                .map(|(i, (&l, &r))| if (&*present).is_set(i) { l } else { r })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, coalesced);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("coalesce({}, {})", self.lhs, self.rhs)
+    }
+}
+
+
+/// Replaces null elements of `lhs` with the constant `rhs`.
+#[derive(Debug)]
+pub struct CoalesceConst<T> {
+    pub lhs: BufferRef<Nullable<T>>,
+    pub rhs: BufferRef<Scalar<T>>,
+    pub output: BufferRef<T>,
+}
+
+impl<'a, T: VecData<T> + ScalarData<T> + 'a> VecOperator<'a> for CoalesceConst<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let coalesced = {
+            let (lhs, present) = scratchpad.get_nullable(self.lhs);
+            let rhs = scratchpad.get_scalar(&self.rhs);
+            lhs.iter().enumerate()
+                .map(|(i, &l)| if (&*present).is_set(i) { l } else { rhs })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, coalesced);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("coalesce({}, {})", self.lhs, self.rhs)
+    }
+}
+
+
+/// Replaces null elements of `lhs` with the corresponding element of `rhs`, the result is null only if both are null.
+#[derive(Debug)]
+pub struct CoalesceNullable<T> {
+    pub lhs: BufferRef<Nullable<T>>,
+    pub rhs: BufferRef<Nullable<T>>,
+    pub output: BufferRef<Nullable<T>>,
+}
+
+impl<'a, T: VecData<T> + 'a> VecOperator<'a> for CoalesceNullable<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let (coalesced, present) = {
+            let (lhs, lhs_present) = scratchpad.get_nullable(self.lhs);
+            let (rhs, rhs_present) = scratchpad.get_nullable(self.rhs);
+            let coalesced = lhs.iter().zip(rhs.iter()).enumerate()
+                .map(|(i, (&l, &r))| if (&*lhs_present).is_set(i) { l } else { r })
+                .collect::<Vec<_>>();
+            let present = lhs_present.iter().zip(rhs_present.iter())
+                .map(|(&l, &r)| l | r)
+                .collect::<Vec<_>>();
+            (coalesced, present)
+        };
+        scratchpad.set_nullable(self.output, coalesced, present);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("coalesce({}, {})", self.lhs, self.rhs)
+    }
+}
@@ -39,6 +39,12 @@ impl Comparator<u64> for CmpLessThan {
     fn is_less_than() -> bool { false }
 }
 
+impl Comparator<OrderedF64> for CmpLessThan {
+    fn cmp(left: OrderedF64, right: OrderedF64) -> bool { left < right }
+    fn cmp_eq(left: OrderedF64, right: OrderedF64) -> bool { left <= right }
+    fn is_less_than() -> bool { true }
+}
+
 impl<'a> Comparator<&'a str> for CmpLessThan {
     fn cmp(left: &str, right: &str) -> bool { left < right }
     fn cmp_eq(left: &str, right: &str) -> bool { left <= right }
@@ -79,6 +85,12 @@ impl Comparator<i64> for CmpGreaterThan {
     fn is_less_than() -> bool { false }
 }
 
+impl Comparator<OrderedF64> for CmpGreaterThan {
+    fn cmp(left: OrderedF64, right: OrderedF64) -> bool { left > right }
+    fn cmp_eq(left: OrderedF64, right: OrderedF64) -> bool { left >= right }
+    fn is_less_than() -> bool { false }
+}
+
 impl<'a> Comparator<&'a str> for CmpGreaterThan {
     fn cmp(left: &str, right: &str) -> bool { left > right }
     fn cmp_eq(left: &str, right: &str) -> bool { left >= right }
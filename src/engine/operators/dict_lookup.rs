@@ -1,6 +1,10 @@
 use std::str;
 
+use regex::Regex;
+
 use engine::*;
+use ingest::list;
+use syntax::expression::Func2Type;
 
 
 #[derive(Debug)]
@@ -45,6 +49,56 @@ impl<'a, T: GenericIntVec<T>> VecOperator<'a> for DictLookup<'a, T> {
     }
 }
 
+/// Dictionary index of the first string that is not less than `constant`, or of the last string that is not greater
+/// than `constant` if `at_most` is set. Requires the dictionary to be sorted.
+#[derive(Debug)]
+pub struct DictBound<'a> {
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub constant: BufferRef<Scalar<&'a str>>,
+    pub at_most: bool,
+    pub output: BufferRef<Scalar<i64>>,
+}
+
+impl<'a> VecOperator<'a> for DictBound<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let result = {
+            let constant = scratchpad.get_scalar(&self.constant);
+            let constant = constant.as_bytes();
+            let dict_indices = scratchpad.get(self.dict_indices);
+            let dict_data = scratchpad.get(self.dict_data);
+            let entry = |i: usize| {
+                let offset = (dict_indices[i] >> 24) as usize;
+                let len = (dict_indices[i] & 0x00ff_ffff) as usize;
+                &dict_data[offset..(offset + len)]
+            };
+            // Number of strings that are less than (or not greater than) `constant`
+            let (mut lo, mut hi) = (0, dict_indices.len());
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let string = entry(mid);
+                if string < constant || (self.at_most && string == constant) {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            if self.at_most { lo as i64 - 1 } else { lo as i64 }
+        };
+        scratchpad.set_const(self.output, result);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.constant.any(), self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { false }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("dict_bound({}, {}, {}, at_most={})", self.dict_indices, self.dict_data, self.constant, self.at_most)
+    }
+}
+
 #[derive(Debug)]
 pub struct InverseDictLookup<'a> {
     pub dict_indices: BufferRef<u64>,
@@ -84,3 +138,162 @@ impl<'a> VecOperator<'a> for InverseDictLookup<'a> {
         format!("inverse_dict_lookup({}, {}, {})", self.dict_indices, self.dict_data, self.constant)
     }
 }
+
+/// Evaluates `comparison` of each dictionary entry with `constant`.
+#[derive(Debug)]
+pub struct CompareDictionary<'a> {
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub constant: BufferRef<Scalar<&'a str>>,
+    pub comparison: Func2Type,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for CompareDictionary<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let matches = {
+            let constant = scratchpad.get_scalar(&self.constant);
+            let constant = constant.as_bytes();
+            let dict_indices = scratchpad.get(self.dict_indices);
+            let dict_data = scratchpad.get(self.dict_data);
+            dict_indices.iter()
+                .map(|offset_len| {
+                    let offset = (offset_len >> 24) as usize;
+                    let len = (offset_len & 0x00ff_ffff) as usize;
+                    let entry = &dict_data[offset..(offset + len)];
+                    let matches = match self.comparison {
+                        Func2Type::Equals => entry == constant,
+                        Func2Type::NotEquals => entry != constant,
+                        Func2Type::LT => entry < constant,
+                        Func2Type::LTE => entry <= constant,
+                        Func2Type::GT => entry > constant,
+                        Func2Type::GTE => entry >= constant,
+                        _ => panic!("{:?} is not a comparison", self.comparison),
+                    };
+                    matches as u8
+                })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, matches);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.constant.any(), self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}] {:?} {}", self.dict_data, self.dict_indices, self.comparison, self.constant)
+    }
+}
+
+/// Decodes every dictionary entry, which gives the groups of a grouping by dictionary index.
+#[derive(Debug)]
+pub struct DictionaryEntries<'a> {
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub output: BufferRef<&'a str>,
+}
+
+impl<'a> VecOperator<'a> for DictionaryEntries<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let entries = {
+            let dict_data = scratchpad.get_pinned(self.dict_data);
+            let dict_indices = scratchpad.get(self.dict_indices);
+            dict_indices.iter()
+                .map(|offset_len| {
+                    let offset = (offset_len >> 24) as usize;
+                    let len = (offset_len & 0x00ff_ffff) as usize;
+                    unsafe { str::from_utf8_unchecked(&dict_data[offset..(offset + len)]) }
+                })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, entries);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}]", self.dict_data, self.dict_indices)
+    }
+}
+
+/// Evaluates `regex` once for each dictionary entry.
+#[derive(Debug)]
+pub struct RegexDictionary {
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub regex: Regex,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for RegexDictionary {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let matches = {
+            let dict_indices = scratchpad.get(self.dict_indices);
+            let dict_data = scratchpad.get(self.dict_data);
+            dict_indices.iter()
+                .map(|offset_len| {
+                    let offset = (offset_len >> 24) as usize;
+                    let len = (offset_len & 0x00ff_ffff) as usize;
+                    let string = unsafe { str::from_utf8_unchecked(&dict_data[offset..(offset + len)]) };
+                    self.regex.is_match(string) as u8
+                })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, matches);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("regex({}[{}], {:?})", self.dict_data, self.dict_indices, self.regex)
+    }
+}
+
+
+/// Checks once for each dictionary entry whether the list it encodes contains `element`.
+#[derive(Debug)]
+pub struct ArrayContainsDictionary {
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub element: String,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for ArrayContainsDictionary {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let matches = {
+            let dict_indices = scratchpad.get(self.dict_indices);
+            let dict_data = scratchpad.get(self.dict_data);
+            dict_indices.iter()
+                .map(|offset_len| {
+                    let offset = (offset_len >> 24) as usize;
+                    let len = (offset_len & 0x00ff_ffff) as usize;
+                    let string = unsafe { str::from_utf8_unchecked(&dict_data[offset..(offset + len)]) };
+                    list::contains(string, &self.element) as u8
+                })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, matches);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("array_contains({}[{}], {:?})", self.dict_data, self.dict_indices, self.element)
+    }
+}
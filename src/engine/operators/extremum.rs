@@ -0,0 +1,55 @@
+use std::i64;
+
+use engine::*;
+
+
+/// Minimum or maximum of `input` for each group.
+#[derive(Debug)]
+pub struct VecExtremum<T> {
+    pub input: BufferRef<i64>,
+    pub grouping: BufferRef<T>,
+    pub output: BufferRef<i64>,
+    pub max_index: BufferRef<Scalar<i64>>,
+    pub max: bool,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for VecExtremum<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let nums = scratchpad.get(self.input);
+        let grouping = scratchpad.get(self.grouping);
+        let mut extremes = scratchpad.get_mut(self.output);
+
+        // Groups without rows keep the initial value and are removed when the result is compacted
+        let len = scratchpad.get_scalar(&self.max_index) as usize + 1;
+        if len > extremes.len() {
+            extremes.resize(len, if self.max { i64::MIN } else { i64::MAX });
+        }
+
+        if self.max {
+            for (i, &n) in grouping.iter().zip(nums.iter()) {
+                let extreme = &mut extremes[i.cast_usize()];
+                if n > *extreme { *extreme = n; }
+            }
+        } else {
+            for (i, &n) in grouping.iter().zip(nums.iter()) {
+                let extreme = &mut extremes[i.cast_usize()];
+                if n < *extreme { *extreme = n; }
+            }
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(0));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}] {}= {}", self.output, self.grouping, if self.max { "max" } else { "min" }, self.input)
+    }
+    fn display_output(&self) -> bool { false }
+}
@@ -0,0 +1,122 @@
+use std::cmp;
+
+use engine::*;
+use syntax::expression::Func2Type;
+
+
+/// Arithmetic on two inputs where at least one is a float. Integer inputs are converted, scalars are broadcast.
+#[derive(Debug)]
+pub struct FloatArithmetic {
+    pub lhs: TypedBufferRef,
+    pub rhs: TypedBufferRef,
+    pub op: Func2Type,
+    pub output: BufferRef<OrderedF64>,
+}
+
+impl<'a> VecOperator<'a> for FloatArithmetic {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let result = {
+            let len = cmp::max(len(self.lhs, scratchpad), len(self.rhs, scratchpad));
+            let lhs = floats(self.lhs, len, scratchpad);
+            let rhs = floats(self.rhs, len, scratchpad);
+            lhs.iter().zip(rhs.iter())
+                .map(|(&l, &r)| OrderedF64(match self.op {
+                    Func2Type::Add => l + r,
+                    Func2Type::Subtract => l - r,
+                    Func2Type::Multiply => l * r,
+                    Func2Type::Divide => l / r,
+                    Func2Type::Modulo => l % r,
+                    op => panic!("{:?} is not an arithmetic operation", op),
+                }))
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, result);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} {} {}", self.lhs, symbol(self.op), self.rhs)
+    }
+}
+
+
+/// Comparison of two inputs where at least one is a float.
+#[derive(Debug)]
+pub struct FloatComparison {
+    pub lhs: TypedBufferRef,
+    pub rhs: TypedBufferRef,
+    pub op: Func2Type,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for FloatComparison {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let result = {
+            let len = cmp::max(len(self.lhs, scratchpad), len(self.rhs, scratchpad));
+            let lhs = floats(self.lhs, len, scratchpad);
+            let rhs = floats(self.rhs, len, scratchpad);
+            lhs.iter().zip(rhs.iter())
+                .map(|(&l, &r)| (match self.op {
+                    Func2Type::Equals => l == r,
+                    Func2Type::NotEquals => l != r,
+                    Func2Type::LT => l < r,
+                    Func2Type::LTE => l <= r,
+                    Func2Type::GT => l > r,
+                    Func2Type::GTE => l >= r,
+                    op => panic!("{:?} is not a comparison", op),
+                }) as u8)
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, result);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} {} {}", self.lhs, symbol(self.op), self.rhs)
+    }
+}
+
+fn symbol(op: Func2Type) -> &'static str {
+    match op {
+        Func2Type::Add => "+",
+        Func2Type::Subtract => "-",
+        Func2Type::Multiply => "*",
+        Func2Type::Divide => "/",
+        Func2Type::Modulo => "%",
+        Func2Type::Equals => "==",
+        Func2Type::NotEquals => "<>",
+        Func2Type::LT => "<",
+        Func2Type::LTE => "<=",
+        Func2Type::GT => ">",
+        Func2Type::GTE => ">=",
+        _ => "?",
+    }
+}
+
+fn len<'a>(buffer: TypedBufferRef, scratchpad: &Scratchpad<'a>) -> usize {
+    if buffer.tag.is_scalar() { 1 } else { scratchpad.get_any(buffer.any()).len() }
+}
+
+fn floats<'a>(buffer: TypedBufferRef, len: usize, scratchpad: &Scratchpad<'a>) -> Vec<f64> {
+    match buffer.tag {
+        EncodingType::F64 => scratchpad.get(buffer.buffer.f64()).iter().map(|f| f.0).collect(),
+        EncodingType::I64 => scratchpad.get(buffer.buffer.i64()).iter().map(|&i| i as f64).collect(),
+        EncodingType::U8 => scratchpad.get(buffer.buffer.u8()).iter().map(|&i| f64::from(i)).collect(),
+        EncodingType::U16 => scratchpad.get(buffer.buffer.u16()).iter().map(|&i| f64::from(i)).collect(),
+        EncodingType::U32 => scratchpad.get(buffer.buffer.u32()).iter().map(|&i| f64::from(i)).collect(),
+        EncodingType::U64 => scratchpad.get(buffer.buffer.u64()).iter().map(|&i| i as f64).collect(),
+        EncodingType::ScalarF64 => vec![scratchpad.get_scalar(&buffer.buffer.scalar_f64()).0; len],
+        EncodingType::ScalarI64 => vec![scratchpad.get_scalar(&buffer.buffer.scalar_i64()) as f64; len],
+        t => panic!("{:?} cannot be converted to float", t),
+    }
+}
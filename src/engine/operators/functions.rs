@@ -1,9 +1,14 @@
+use std::hash::Hasher;
 use std::i64;
 
-use chrono::{NaiveDateTime, Datelike};
+use chrono::{NaiveDate, NaiveDateTime, Datelike};
 use regex;
+use seahash;
 
+use engine::data_types::OrderedF64;
+use ingest::list;
 use super::map_operator::MapOp;
+use syntax::expression::TimeUnit;
 
 
 pub struct ToYear;
@@ -14,6 +19,69 @@ impl MapOp<i64, i64> for ToYear {
 }
 
 
+pub struct DateTrunc {
+    pub unit: TimeUnit,
+}
+
+impl MapOp<i64, i64> for DateTrunc {
+    fn apply(&self, unix_ts: i64) -> i64 { date_trunc(unix_ts, self.unit) }
+    fn name() -> &'static str { "date_trunc" }
+}
+
+
+pub struct Extract {
+    pub unit: TimeUnit,
+}
+
+impl MapOp<i64, i64> for Extract {
+    fn apply(&self, unix_ts: i64) -> i64 { extract(unix_ts, self.unit) }
+    fn name() -> &'static str { "extract" }
+}
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Truncates a unix timestamp to the start of the `unit` it falls into. Weeks start on Monday.
+pub fn date_trunc(unix_ts: i64, unit: TimeUnit) -> i64 {
+    if let Some(seconds) = unit.seconds() {
+        return unix_ts - floor_mod(unix_ts, seconds);
+    }
+    let start_of_day = |date: NaiveDate| date.and_hms(0, 0, 0).timestamp();
+    let date = NaiveDateTime::from_timestamp(unix_ts, 0).date();
+    match unit {
+        // 1970-01-01 was a Thursday
+        TimeUnit::Week => {
+            let day = (unix_ts - floor_mod(unix_ts, SECONDS_PER_DAY)) / SECONDS_PER_DAY;
+            (day - floor_mod(day + 3, 7)) * SECONDS_PER_DAY
+        }
+        TimeUnit::Month => start_of_day(NaiveDate::from_ymd(date.year(), date.month(), 1)),
+        TimeUnit::Quarter => start_of_day(NaiveDate::from_ymd(date.year(), (date.month() - 1) / 3 * 3 + 1, 1)),
+        TimeUnit::Year => start_of_day(NaiveDate::from_ymd(date.year(), 1, 1)),
+        _ => unix_ts - floor_mod(unix_ts, SECONDS_PER_DAY),
+    }
+}
+
+/// Extracts the `unit` field from a unix timestamp. Days of the week are numbered from 0 (Sunday) to 6.
+pub fn extract(unix_ts: i64, unit: TimeUnit) -> i64 {
+    let date = NaiveDateTime::from_timestamp(unix_ts, 0).date();
+    match unit {
+        TimeUnit::Second => floor_mod(unix_ts, 60),
+        TimeUnit::Minute => floor_mod(unix_ts, 3600) / 60,
+        TimeUnit::Hour => floor_mod(unix_ts, SECONDS_PER_DAY) / 3600,
+        TimeUnit::Day => i64::from(date.day()),
+        TimeUnit::Week => i64::from(date.iso_week().week()),
+        TimeUnit::Month => i64::from(date.month()),
+        TimeUnit::Quarter => i64::from((date.month() - 1) / 3 + 1),
+        TimeUnit::Year => i64::from(date.year()),
+        TimeUnit::DayOfWeek => i64::from(date.weekday().num_days_from_sunday()),
+        TimeUnit::DayOfYear => i64::from(date.ordinal()),
+    }
+}
+
+fn floor_mod(x: i64, n: i64) -> i64 {
+    ((x % n) + n) % n
+}
+
+
 pub struct BooleanNot;
 
 impl MapOp<u8, u8> for BooleanNot {
@@ -35,3 +103,134 @@ impl<'a> MapOp<&'a str, u8> for RegexMatch {
     }
     fn name() -> &'static str { "not" }
 }
+
+
+pub struct ArrayContains {
+    pub element: String,
+}
+
+impl<'a> MapOp<&'a str, u8> for ArrayContains {
+    fn apply(&self, s: &'a str) -> u8 { list::contains(s, &self.element) as u8 }
+    fn name() -> &'static str { "array_contains" }
+}
+
+
+pub struct HashStr;
+
+impl<'a> MapOp<&'a str, i64> for HashStr {
+    fn apply(&self, s: &'a str) -> i64 { seahash::hash(s.as_bytes()) as i64 }
+    fn name() -> &'static str { "hash" }
+}
+
+
+pub struct HashInt;
+
+impl MapOp<i64, i64> for HashInt {
+    fn apply(&self, i: i64) -> i64 {
+        let mut hasher = seahash::SeaHasher::new();
+        hasher.write_i64(i);
+        hasher.finish() as i64
+    }
+    fn name() -> &'static str { "hash" }
+}
+
+
+pub struct Redact;
+
+impl<'a, T> MapOp<T, &'a str> for Redact {
+    fn apply(&self, _: T) -> &'a str { "***" }
+    fn name() -> &'static str { "redact" }
+}
+
+
+pub struct Truncate {
+    pub length: usize,
+}
+
+impl<'a> MapOp<&'a str, &'a str> for Truncate {
+    fn apply(&self, s: &'a str) -> &'a str {
+        s.char_indices().nth(self.length).map(|(i, _)| &s[..i]).unwrap_or(s)
+    }
+    fn name() -> &'static str { "truncate" }
+}
+
+
+pub struct Substring {
+    pub start: usize,
+    pub length: usize,
+}
+
+impl<'a> MapOp<&'a str, &'a str> for Substring {
+    fn apply(&self, s: &'a str) -> &'a str {
+        let start = s.char_indices().nth(self.start).map(|(i, _)| i).unwrap_or_else(|| s.len());
+        let s = &s[start..];
+        s.char_indices().nth(self.length).map(|(i, _)| &s[..i]).unwrap_or(s)
+    }
+    fn name() -> &'static str { "substring" }
+}
+
+
+pub struct StringLength;
+
+impl<'a> MapOp<&'a str, i64> for StringLength {
+    fn apply(&self, s: &'a str) -> i64 { s.chars().count() as i64 }
+    fn name() -> &'static str { "length" }
+}
+
+
+pub struct IntToFloat;
+
+impl MapOp<i64, OrderedF64> for IntToFloat {
+    fn apply(&self, i: i64) -> OrderedF64 { OrderedF64(i as f64) }
+    fn name() -> &'static str { "to_float" }
+}
+
+
+pub struct FloatToInt;
+
+impl MapOp<OrderedF64, i64> for FloatToInt {
+    fn apply(&self, f: OrderedF64) -> i64 { float_to_int(f.0) }
+    fn name() -> &'static str { "to_int" }
+}
+
+
+pub struct ParseInt;
+
+impl<'a> MapOp<&'a str, i64> for ParseInt {
+    fn apply(&self, s: &'a str) -> i64 { parse_int(s) }
+    fn name() -> &'static str { "parse_int" }
+}
+
+
+pub struct ParseFloat;
+
+impl<'a> MapOp<&'a str, OrderedF64> for ParseFloat {
+    fn apply(&self, s: &'a str) -> OrderedF64 { OrderedF64(parse_float(s)) }
+    fn name() -> &'static str { "parse_float" }
+}
+
+/// Rounds towards zero, out of range values saturate and NaN becomes 0.
+pub fn float_to_int(f: f64) -> i64 {
+    if f.is_nan() {
+        0
+    } else if f >= i64::MAX as f64 {
+        i64::MAX
+    } else if f <= i64::MIN as f64 {
+        i64::MIN
+    } else {
+        f as i64
+    }
+}
+
+/// Strings holding a float are rounded towards zero, strings that are not valid numbers are converted to 0.
+pub fn parse_int(s: &str) -> i64 {
+    let s = s.trim();
+    s.parse::<i64>()
+        .or_else(|_| s.parse::<f64>().map(float_to_int))
+        .unwrap_or(0)
+}
+
+/// Strings that are not valid numbers are converted to NaN.
+pub fn parse_float(s: &str) -> f64 {
+    s.trim().parse().unwrap_or(::std::f64::NAN)
+}
@@ -1,46 +1,48 @@
-use std::hash::Hash;
-
-use fnv::FnvHashMap;
-
 use engine::*;
 use ingest::raw_val::RawVal;
+use super::batch_hash::{BatchHash, Prehashed, PrehashedMap};
 
 
 #[derive(Debug)]
-pub struct HashMapGrouping<T: VecData<T> + Hash> {
+pub struct HashMapGrouping<T: VecData<T> + BatchHash> {
     input: BufferRef<T>,
     unique_out: BufferRef<T>,
     grouping_key_out: BufferRef<u32>,
     cardinality_out: BufferRef<Scalar<i64>>,
-    map: FnvHashMap<T, u32>,
+    map: PrehashedMap<T, u32>,
+    hashes: Vec<u64>,
+    expected_cardinality: usize,
 }
 
-impl<'a, T: VecData<T> + Hash + 'a> HashMapGrouping<T> {
+impl<'a, T: VecData<T> + BatchHash + 'a> HashMapGrouping<T> {
     pub fn boxed(input: BufferRef<T>,
                  unique_out: BufferRef<T>,
                  grouping_key_out: BufferRef<u32>,
                  cardinality_out: BufferRef<Scalar<i64>>,
-                 _max_index: usize) -> BoxedOperator<'a> {
+                 expected_cardinality: usize) -> BoxedOperator<'a> {
         Box::new(HashMapGrouping::<T> {
             input,
             unique_out,
             grouping_key_out,
             cardinality_out,
-            map: FnvHashMap::default(),
+            map: PrehashedMap::with_capacity_and_hasher(expected_cardinality, Default::default()),
+            hashes: Vec::new(),
+            expected_cardinality,
         })
     }
 }
 
-impl<'a, T: VecData<T> + Hash + 'a> VecOperator<'a> for HashMapGrouping<T> {
+impl<'a, T: VecData<T> + BatchHash + 'a> VecOperator<'a> for HashMapGrouping<T> {
     fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
         let count = {
             let raw_grouping_key = scratchpad.get(self.input);
             let mut grouping = scratchpad.get_mut(self.grouping_key_out);
             let mut unique = scratchpad.get_mut(self.unique_out);
             if stream { grouping.clear() }
-            for i in raw_grouping_key.iter() {
-                grouping.push(*self.map.entry(*i).or_insert_with(|| {
-                    unique.push(*i);
+            T::hash_batch(&raw_grouping_key, &mut self.hashes);
+            for (&key, &hash) in raw_grouping_key.iter().zip(self.hashes.iter()) {
+                grouping.push(*self.map.entry(Prehashed { hash, key }).or_insert_with(|| {
+                    unique.push(key);
                     unique.len() as u32 - 1
                 }));
             }
@@ -50,8 +52,7 @@ impl<'a, T: VecData<T> + Hash + 'a> VecOperator<'a> for HashMapGrouping<T> {
     }
 
     fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
-        // TODO(clemens): Estimate capacities for unique + map?
-        scratchpad.set(self.unique_out, Vec::new());
+        scratchpad.set(self.unique_out, Vec::with_capacity(self.expected_cardinality));
         scratchpad.set(self.grouping_key_out, Vec::with_capacity(batch_size));
     }
 
@@ -1,7 +1,6 @@
-use fnv::FnvHashMap;
-
 use engine::*;
 use ingest::raw_val::RawVal;
+use super::batch_hash::{self, Prehashed, PrehashedMap};
 
 
 #[derive(Debug)]
@@ -31,17 +30,18 @@ impl<'a> HashMapGroupingByteSlices {
 
 impl<'a> VecOperator<'a> for HashMapGroupingByteSlices {
     fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
-        // TODO(clemens): Fnv is suboptimal for larger inputs (http://cglab.ca/~abeinges/blah/hash-rs/). use xx hash?
         let count = {
             let raw_grouping_key_any = scratchpad.get_any(self.input);
             let raw_grouping_key = raw_grouping_key_any.cast_ref_byte_slices();
-            let mut map: FnvHashMap<&[&'a [u8]], u32> = FnvHashMap::default();
+            let mut hashes = Vec::with_capacity(raw_grouping_key.len());
+            batch_hash::hash_byte_slice_rows(&raw_grouping_key.data, raw_grouping_key.row_len, &mut hashes);
+            let mut map: PrehashedMap<&[&'a [u8]], u32> = PrehashedMap::default();
             let mut grouping = scratchpad.get_mut(self.grouping_key_out);
             let mut unique_any = scratchpad.get_any_mut(self.unique_out);
             let mut unique = unique_any.cast_ref_mut_byte_slices();
             if stream { grouping.clear() }
-            for row in raw_grouping_key.data.chunks(raw_grouping_key.row_len) {
-                grouping.push(*map.entry(row).or_insert_with(|| {
+            for (row, &hash) in raw_grouping_key.data.chunks(raw_grouping_key.row_len).zip(hashes.iter()) {
+                grouping.push(*map.entry(Prehashed { hash, key: row }).or_insert_with(|| {
                     for slice in row {
                         unique.data.push(*slice);
                     }
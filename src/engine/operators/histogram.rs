@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use QueryError;
+use engine::*;
+
+
+/// Number of values in each bucket of equal width, identified by the index of the bucket.
+/// Histograms are serialized as strings of `bucket:count` pairs like `QuantileSketch`, which allows partial histograms
+/// to be merged by `MergeQuantileSketches`.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: BTreeMap<i64, i64>,
+}
+
+impl Histogram {
+    pub fn parse(serialized: &str) -> Result<Histogram, QueryError> {
+        let buckets = serialized.split(',')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let mut parts = entry.split(':');
+                match (parts.next().map(str::parse::<i64>), parts.next().map(str::parse::<i64>), parts.next()) {
+                    (Some(Ok(bucket)), Some(Ok(count)), None) => Ok((bucket, count)),
+                    _ => Err(fatal!("Invalid histogram entry {:?}", entry)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Histogram { buckets })
+    }
+
+    pub fn serialize(&self) -> String {
+        self.buckets.iter()
+            .map(|(bucket, count)| format!("{}:{}", bucket, count))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn insert(&mut self, bucket: i64) {
+        *self.buckets.entry(bucket).or_insert(0) += 1;
+    }
+
+    /// Pairs of the smallest value in each nonempty bucket and the number of values it contains, e.g. `0:12,100:40`.
+    pub fn labeled(&self, bucket_width: i64) -> String {
+        self.buckets.iter()
+            .map(|(bucket, count)| format!("{}:{}", bucket.saturating_mul(bucket_width), count))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+
+#[derive(Debug)]
+pub struct VecHistogram<'a, T> {
+    pub input: BufferRef<i64>,
+    pub grouping: BufferRef<T>,
+    pub max_index: BufferRef<Scalar<i64>>,
+    pub string_store: BufferRef<u8>,
+    pub output: BufferRef<&'a str>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for VecHistogram<'a, T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let histograms = {
+            let buckets = scratchpad.get(self.input);
+            let grouping = scratchpad.get(self.grouping);
+            let len = scratchpad.get_scalar(&self.max_index) as usize + 1;
+            let mut histograms = (0..len).map(|_| Histogram::default()).collect::<Vec<_>>();
+            for (i, &bucket) in grouping.iter().zip(buckets.iter()) {
+                histograms[i.cast_usize()].insert(bucket);
+            }
+            histograms.iter().map(Histogram::serialize).collect::<Vec<_>>()
+        };
+        scratchpad.set_pinned_strings(self.string_store, self.output, &histograms);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}] = histogram({})", self.output, self.grouping, self.input)
+    }
+    fn display_output(&self) -> bool { false }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        for &bucket in &[3, -1, 0, 3, 3] {
+            histogram.insert(bucket);
+        }
+        let serialized = histogram.serialize();
+        assert_eq!(serialized, "-1:1,0:1,3:3");
+        assert_eq!(Histogram::parse(&serialized).unwrap().labeled(50), "-50:1,0:1,150:3");
+        assert!(Histogram::parse("").unwrap().buckets.is_empty());
+        assert!(Histogram::parse("1:2,3").is_err());
+        assert!(Histogram::parse("1:x").is_err());
+        assert!(Histogram::parse("1:2:3").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use std::cmp;
+
+use engine::*;
+use super::map_operator::MapOp;
+
+
+// HyperLogLog sketches with 64 registers of 6 bits each. Every aggregate column holds one lane of up to
+// `REGISTERS_PER_LANE` registers packed into an i64, so sketches are merged with the same machinery as sums and counts.
+const PRECISION: u32 = 6;
+const REGISTERS: usize = 1 << PRECISION;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MASK: i64 = (1 << REGISTER_BITS) - 1;
+const REGISTERS_PER_LANE: usize = 10;
+pub const LANES: usize = (REGISTERS + REGISTERS_PER_LANE - 1) / REGISTERS_PER_LANE;
+
+/// Returns the lane `lane` of a sketch that contains only the value with hash `hash`.
+pub fn registers(hash: i64, lane: usize) -> i64 {
+    let hash = hash as u64;
+    let register = (hash & (REGISTERS as u64 - 1)) as usize;
+    if register / REGISTERS_PER_LANE != lane {
+        return 0;
+    }
+    let remaining = hash >> PRECISION;
+    let rank = (remaining.leading_zeros() - PRECISION + 1) as i64;
+    rank << (REGISTER_BITS * (register % REGISTERS_PER_LANE))
+}
+
+/// Register-wise maximum of two lanes.
+pub fn merge(left: i64, right: i64) -> i64 {
+    let mut merged = 0;
+    for i in 0..REGISTERS_PER_LANE {
+        let shift = REGISTER_BITS * i;
+        let register = cmp::max((left >> shift) & REGISTER_MASK, (right >> shift) & REGISTER_MASK);
+        merged |= register << shift;
+    }
+    merged
+}
+
+/// Estimates the number of distinct values from all lanes of a sketch.
+pub fn estimate(lanes: &[i64]) -> i64 {
+    let mut sum = 0.0;
+    let mut zeros = 0;
+    for register in 0..REGISTERS {
+        let lane = lanes[register / REGISTERS_PER_LANE];
+        let rank = (lane >> (REGISTER_BITS * (register % REGISTERS_PER_LANE))) & REGISTER_MASK;
+        sum += 1.0 / (1u64 << rank) as f64;
+        if rank == 0 {
+            zeros += 1;
+        }
+    }
+    let m = REGISTERS as f64;
+    let raw = 0.709 * m * m / sum;
+    let estimate = if raw <= 2.5 * m && zeros > 0 {
+        m * (m / zeros as f64).ln()
+    } else {
+        raw
+    };
+    estimate.round() as i64
+}
+
+
+pub struct HllRegisters {
+    pub lane: usize,
+}
+
+impl MapOp<i64, i64> for HllRegisters {
+    fn apply(&self, hash: i64) -> i64 { registers(hash, self.lane) }
+    fn name() -> &'static str { "hll_registers" }
+}
+
+
+#[derive(Debug)]
+pub struct VecMergeRegisters<T> {
+    pub input: BufferRef<i64>,
+    pub grouping: BufferRef<T>,
+    pub output: BufferRef<i64>,
+    pub max_index: BufferRef<Scalar<i64>>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for VecMergeRegisters<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let lanes = scratchpad.get(self.input);
+        let grouping = scratchpad.get(self.grouping);
+        let mut sketches = scratchpad.get_mut(self.output);
+
+        let len = scratchpad.get_scalar(&self.max_index) as usize + 1;
+        if len > sketches.len() {
+            sketches.resize(len, 0);
+        }
+
+        for (i, &lane) in grouping.iter().zip(lanes.iter()) {
+            let sketch = &mut sketches[i.cast_usize()];
+            *sketch = merge(*sketch, lane);
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(0));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}] |= {}", self.output, self.grouping, self.input)
+    }
+    fn display_output(&self) -> bool { false }
+}
@@ -0,0 +1,119 @@
+use engine::*;
+
+
+/// Tests each element of `input` for membership in the set of (encoded) `values`.
+#[derive(Debug)]
+pub struct IsIn<T> {
+    pub input: BufferRef<T>,
+    pub values: Vec<BufferRef<Scalar<i64>>>,
+    pub output: BufferRef<u8>,
+    pub set: Option<Vec<i64>>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for IsIn<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        if self.set.is_none() {
+            let mut set = self.values.iter().map(|value| scratchpad.get_scalar(value)).collect::<Vec<_>>();
+            set.sort();
+            set.dedup();
+            self.set = Some(set);
+        }
+        let set = self.set.as_ref().unwrap();
+        let input = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for i in input.iter() {
+            output.push(i.to_i64().map_or(false, |i| set.binary_search(&i).is_ok()) as u8);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> {
+        let mut inputs = vec![self.input.any()];
+        inputs.extend(self.values.iter().map(|value| value.any()));
+        inputs
+    }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} IN ({})", self.input, self.values.iter().map(|value| format!("{}", value)).collect::<Vec<_>>().join(", "))
+    }
+}
+
+
+/// Tests whether each element of `input` lies between the (encoded) bounds `low` and `high`, inclusive.
+#[derive(Debug)]
+pub struct InRange<T> {
+    pub input: BufferRef<T>,
+    pub low: BufferRef<Scalar<i64>>,
+    pub high: BufferRef<Scalar<i64>>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for InRange<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let low = scratchpad.get_scalar(&self.low);
+        let high = scratchpad.get_scalar(&self.high);
+        let input = scratchpad.get(self.input);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for i in input.iter() {
+            output.push(i.to_i64().map_or(false, |i| low <= i && i <= high) as u8);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any(), self.low.any(), self.high.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} BETWEEN {} AND {}", self.input, self.low, self.high)
+    }
+}
+
+
+/// Tests each element of `input` for membership in `set`, which holds a byte for every possible value of `input`.
+#[derive(Debug)]
+pub struct IsInSet<T> {
+    pub input: BufferRef<T>,
+    pub set: BufferRef<u8>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for IsInSet<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let input = scratchpad.get(self.input);
+        let set = scratchpad.get(self.set);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear() }
+        for i in input.iter() {
+            output.push(set[i.cast_usize()]);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any(), self.set.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, buffer: usize) -> bool { buffer == self.input.i }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}]", self.set, self.input)
+    }
+}
@@ -0,0 +1,32 @@
+use bitvec::BitVec;
+use engine::*;
+
+
+/// Tests whether each element of `input` is null, or whether it is not null if `is_not_null` is set.
+#[derive(Debug)]
+pub struct IsNull {
+    pub input: BufferRef<Nullable<Any>>,
+    pub is_not_null: bool,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a> VecOperator<'a> for IsNull {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let is_null = {
+            let len = scratchpad.get_any(self.input.any()).len();
+            let present = scratchpad.get_null_map(self.input);
+            (0..len).map(|i| ((&*present).is_set(i) == self.is_not_null) as u8).collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, is_null);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} IS {}NULL", self.input, if self.is_not_null { "NOT " } else { "" })
+    }
+}
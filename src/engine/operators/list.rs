@@ -0,0 +1,79 @@
+use engine::*;
+use ingest::list;
+
+
+/// Serializes the lists formed by `elements` as JSON arrays, the list of each row ends at the offset in `ends`.
+#[derive(Debug)]
+pub struct EncodeLists<'a> {
+    pub ends: BufferRef<u32>,
+    pub elements: BufferRef<&'a str>,
+    pub string_store: BufferRef<u8>,
+    pub output: BufferRef<&'a str>,
+}
+
+impl<'a> VecOperator<'a> for EncodeLists<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let encoded = {
+            let ends = scratchpad.get(self.ends);
+            let elements = scratchpad.get(self.elements);
+            let mut start = 0;
+            ends.iter()
+                .map(|&end| {
+                    let encoded = list::encode(&elements[start..end as usize]);
+                    start = end as usize;
+                    encoded
+                })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set_pinned_strings(self.string_store, self.output, &encoded);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.ends.any(), self.elements.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("encode_lists({}, {})", self.ends, self.elements)
+    }
+}
+
+
+/// Checks for the list of each row whether any of its elements, given as dictionary indices in `elements`, is `code`.
+#[derive(Debug)]
+pub struct ListContains<T> {
+    pub ends: BufferRef<u32>,
+    pub elements: BufferRef<T>,
+    pub code: BufferRef<Scalar<i64>>,
+    pub output: BufferRef<u8>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for ListContains<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let contains = {
+            let code = scratchpad.get_scalar(&self.code);
+            let ends = scratchpad.get(self.ends);
+            let elements = scratchpad.get(self.elements);
+            let mut start = 0;
+            ends.iter()
+                .map(|&end| {
+                    let contains = elements[start..end as usize].iter().any(|e| e.to_i64() == Some(code));
+                    start = end as usize;
+                    contains as u8
+                })
+                .collect::<Vec<_>>()
+        };
+        scratchpad.set(self.output, contains);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.ends.any(), self.elements.any(), self.code.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("list_contains({}, {}, {})", self.ends, self.elements, self.code)
+    }
+}
@@ -1,26 +1,37 @@
+use QueryError;
 use engine::*;
 
 
 #[derive(Debug)]
-pub struct MergeAggregate {
+pub struct MergeAggregate<T> {
     pub merge_ops: BufferRef<MergeOp>,
-    pub left: BufferRef<i64>,
-    pub right: BufferRef<i64>,
-    pub aggregated: BufferRef<i64>,
+    pub left: BufferRef<T>,
+    pub right: BufferRef<T>,
+    pub aggregated: BufferRef<T>,
     pub aggregator: Aggregator,
+    pub overflow: bool,
 }
 
-impl<'a> VecOperator<'a> for MergeAggregate {
+impl<'a, T: Aggregate<T> + 'a> VecOperator<'a> for MergeAggregate<T> {
     fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
         let aggregated = {
             let ops = scratchpad.get(self.merge_ops);
             let left = scratchpad.get(self.left);
             let right = scratchpad.get(self.right);
-            merge_aggregate(&ops, &left, &right, self.aggregator)
+            merge_aggregate(&ops, &left, &right, self.aggregator, &mut self.overflow)
         };
         scratchpad.set(self.aggregated, aggregated);
     }
 
+    fn error(&self) -> Option<QueryError> {
+        if self.overflow {
+            Some(QueryError::Overflow(format!("Merging partial results of {:?} exceeds the range of 64-bit integers",
+                                              self.aggregator)))
+        } else {
+            None
+        }
+    }
+
     fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.left.any(), self.right.any(), self.merge_ops.any()] }
     fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.aggregated.any()] }
     fn can_stream_input(&self, _: usize) -> bool { false }
@@ -32,7 +43,8 @@ impl<'a> VecOperator<'a> for MergeAggregate {
     }
 }
 
-fn merge_aggregate(ops: &[MergeOp], left: &[i64], right: &[i64], aggregator: Aggregator) -> Vec<i64> {
+fn merge_aggregate<T: Aggregate<T>>(ops: &[MergeOp], left: &[T], right: &[T], aggregator: Aggregator,
+                                    overflow: &mut bool) -> Vec<T> {
     let mut result = Vec::with_capacity(ops.len());
     let mut i = 0;
     let mut j = 0;
@@ -55,7 +67,13 @@ fn merge_aggregate(ops: &[MergeOp], left: &[i64], right: &[i64], aggregator: Agg
             MergeOp::MergeRight => {
                 // TODO(clemens): make inlining of aggregator operation possible
                 let last = result.len() - 1;
-                result[last] = aggregator.combine_i64(result[last], right[j]);
+                result[last] = match T::combine(aggregator, result[last], right[j]) {
+                    Some(combined) => combined,
+                    None => {
+                        *overflow = true;
+                        result[last]
+                    }
+                };
                 j += 1;
             }
         }
@@ -63,3 +81,21 @@ fn merge_aggregate(ops: &[MergeOp], left: &[i64], right: &[i64], aggregator: Agg
     result
 }
 
+
+/// Values that partial aggregates can be combined for.
+pub trait Aggregate<T>: VecData<T> {
+    /// Combined aggregate, `None` if it overflows.
+    fn combine(aggregator: Aggregator, accumulator: T, elem: T) -> Option<T>;
+}
+
+impl Aggregate<i64> for i64 {
+    fn combine(aggregator: Aggregator, accumulator: i64, elem: i64) -> Option<i64> {
+        aggregator.combine_i64(accumulator, elem)
+    }
+}
+
+impl Aggregate<OrderedF64> for OrderedF64 {
+    fn combine(aggregator: Aggregator, accumulator: OrderedF64, elem: OrderedF64) -> Option<OrderedF64> {
+        Some(aggregator.combine_f64(accumulator, elem))
+    }
+}
@@ -1,11 +1,18 @@
 pub mod vector_operator;
 pub mod comparator;
+pub mod histogram;
+pub mod hyperloglog;
+pub mod quantile_sketch;
+pub mod functions;
 
+mod arg_extremum;
 mod assemble_nullable;
+mod batch_hash;
 mod binary_operator;
 mod bit_unpack;
 mod bool_op;
 mod column_ops;
+mod coalesce;
 mod combine_null_maps;
 mod compact;
 mod comparison_operators;
@@ -17,12 +24,16 @@ mod delta_decode;
 mod dict_lookup;
 mod encode_const;
 mod exists;
+mod extremum;
 mod filter;
-mod functions;
+mod float_operators;
 mod hashmap_grouping;
 mod hashmap_grouping_byte_slices;
 mod identity;
 mod indices;
+mod is_in;
+mod is_null;
+mod list;
 mod make_nullable;
 mod map_operator;
 mod merge;
@@ -37,18 +48,29 @@ mod null_vec;
 mod numeric_operators;
 mod parameterized_vec_vec_int_op;
 mod propagate_nullability;
+mod radix_grouping;
+mod run_length_decode;
+mod sample_rows;
+mod scalar_f64;
 mod scalar_i64;
 mod scalar_str;
 mod select;
+mod simd;
+mod simd_comparison;
 mod sort_by;
 mod sort_by_slices;
+mod string_functions;
 mod sum;
 mod top_n;
+mod top_n_multi;
 mod type_conversion;
+mod udf;
 mod unhexpack_strings;
 mod unpack_strings;
 #[cfg(feature = "enable_lz4")]
 mod lz4_decode;
+#[cfg(feature = "enable_zstd")]
+mod zstd_decode;
 mod merge_deduplicate_partitioned;
 mod partition;
 mod subpartition;
@@ -61,7 +61,8 @@ impl<LHS: PrimInt, RHS: PrimInt> BinaryOp<LHS, RHS, i64> for Multiplication<LHS,
 impl<LHS: PrimInt, RHS: PrimInt> BinaryOp<LHS, RHS, i64> for Division<LHS, RHS> {
     #[inline]
     fn perform(lhs: LHS, rhs: RHS) -> i64 {
-        lhs.to_i64().unwrap() / rhs.to_i64().unwrap()
+        // Division by zero evaluates to zero rather than aborting the query
+        lhs.to_i64().unwrap().checked_div(rhs.to_i64().unwrap()).unwrap_or(0)
     }
 
     fn symbol() -> &'static str { "/" }
@@ -70,7 +71,7 @@ impl<LHS: PrimInt, RHS: PrimInt> BinaryOp<LHS, RHS, i64> for Division<LHS, RHS>
 impl<LHS: PrimInt, RHS: PrimInt> BinaryOp<LHS, RHS, i64> for Modulo<LHS, RHS> {
     #[inline]
     fn perform(lhs: LHS, rhs: RHS) -> i64 {
-        lhs.to_i64().unwrap() % rhs.to_i64().unwrap()
+        lhs.to_i64().unwrap().checked_rem(rhs.to_i64().unwrap()).unwrap_or(0)
     }
 
     fn symbol() -> &'static str { "%" }
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+
+use engine::*;
+
+
+// Relative accuracy of quantiles computed from the sketch.
+const ALPHA: f64 = 0.01;
+
+/// Mergeable quantile sketch which counts values in logarithmically sized buckets (DDSketch).
+/// Sketches are serialized as strings of `bucket:count` pairs so they can be stored in intermediary results.
+#[derive(Debug, Default)]
+pub struct QuantileSketch {
+    buckets: BTreeMap<i64, i64>,
+}
+
+impl QuantileSketch {
+    pub fn parse(serialized: &str) -> QuantileSketch {
+        let buckets = serialized.split(',')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let mut parts = entry.split(':');
+                let bucket = parts.next().unwrap().parse::<i64>().unwrap();
+                let count = parts.next().unwrap().parse::<i64>().unwrap();
+                (bucket, count)
+            })
+            .collect();
+        QuantileSketch { buckets }
+    }
+
+    pub fn serialize(&self) -> String {
+        self.buckets.iter()
+            .map(|(bucket, count)| format!("{}:{}", bucket, count))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn insert(&mut self, value: i64) {
+        *self.buckets.entry(bucket(value)).or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        for (&bucket, &count) in &other.buckets {
+            *self.buckets.entry(bucket).or_insert(0) += count;
+        }
+    }
+
+    pub fn quantile(&self, q: f64) -> Option<i64> {
+        let total = self.buckets.values().sum::<i64>();
+        if total == 0 {
+            return None;
+        }
+        let rank = (q.max(0.0).min(1.0) * (total - 1) as f64) as i64;
+        let mut seen = 0;
+        for (&bucket, &count) in &self.buckets {
+            seen += count;
+            if seen > rank {
+                return Some(value(bucket));
+            }
+        }
+        None
+    }
+}
+
+fn gamma() -> f64 {
+    (1.0 + ALPHA) / (1.0 - ALPHA)
+}
+
+// Buckets are ordered by the values they contain, 0 is reserved for the value 0.
+fn bucket(value: i64) -> i64 {
+    if value == 0 {
+        0
+    } else {
+        let index = 1 + ((value as f64).abs().ln() / gamma().ln()).ceil() as i64;
+        if value > 0 { index } else { -index }
+    }
+}
+
+fn value(bucket: i64) -> i64 {
+    if bucket == 0 {
+        0
+    } else {
+        let gamma = gamma();
+        let magnitude = 2.0 * gamma.powi((bucket.abs() - 1) as i32) / (gamma + 1.0);
+        if bucket > 0 { magnitude.round() as i64 } else { -magnitude.round() as i64 }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct VecQuantileSketch<'a, T> {
+    pub input: BufferRef<i64>,
+    pub grouping: BufferRef<T>,
+    pub max_index: BufferRef<Scalar<i64>>,
+    pub string_store: BufferRef<u8>,
+    pub output: BufferRef<&'a str>,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for VecQuantileSketch<'a, T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let sketches = {
+            let nums = scratchpad.get(self.input);
+            let grouping = scratchpad.get(self.grouping);
+            let len = scratchpad.get_scalar(&self.max_index) as usize + 1;
+            let mut sketches = (0..len).map(|_| QuantileSketch::default()).collect::<Vec<_>>();
+            for (i, &n) in grouping.iter().zip(nums.iter()) {
+                sketches[i.cast_usize()].insert(n);
+            }
+            sketches.iter().map(QuantileSketch::serialize).collect::<Vec<_>>()
+        };
+        scratchpad.set_pinned_strings(self.string_store, self.output, &sketches);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}] = quantile_sketch({})", self.output, self.grouping, self.input)
+    }
+    fn display_output(&self) -> bool { false }
+}
+
+
+#[derive(Debug)]
+pub struct MergeQuantileSketches<'a> {
+    pub merge_ops: BufferRef<MergeOp>,
+    pub left: BufferRef<&'a str>,
+    pub right: BufferRef<&'a str>,
+    pub string_store: BufferRef<u8>,
+    pub merged: BufferRef<&'a str>,
+}
+
+impl<'a> VecOperator<'a> for MergeQuantileSketches<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let merged = {
+            let ops = scratchpad.get(self.merge_ops);
+            let left = scratchpad.get(self.left);
+            let right = scratchpad.get(self.right);
+            let mut merged = Vec::<QuantileSketch>::with_capacity(ops.len());
+            let mut i = 0;
+            let mut j = 0;
+            for op in ops.iter() {
+                match *op {
+                    MergeOp::TakeLeft => {
+                        merged.push(QuantileSketch::parse(left[i]));
+                        i += 1;
+                    }
+                    MergeOp::TakeRight => {
+                        merged.push(QuantileSketch::parse(right[j]));
+                        j += 1;
+                    }
+                    MergeOp::MergeRight => {
+                        merged.last_mut().unwrap().merge(&QuantileSketch::parse(right[j]));
+                        j += 1;
+                    }
+                }
+            }
+            merged.iter().map(QuantileSketch::serialize).collect::<Vec<_>>()
+        };
+        scratchpad.set_pinned_strings(self.string_store, self.merged, &merged);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.left.any(), self.right.any(), self.merge_ops.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.merged.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("merge_quantile_sketches({}; {}, {})", self.merge_ops, self.left, self.right)
+    }
+}
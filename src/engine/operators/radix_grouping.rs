@@ -0,0 +1,132 @@
+use std::cmp;
+
+use num_cpus;
+use scoped_threadpool::Pool;
+
+use engine::*;
+use ingest::raw_val::RawVal;
+use super::batch_hash::{BatchHash, Prehashed, PrehashedMap};
+
+
+/// Expected number of groups above which a single hash table no longer fits into the CPU caches and grouping is
+/// performed by `RadixHashMapGrouping` instead.
+pub const RADIX_GROUPING_THRESHOLD: usize = 1 << 16;
+
+const RADIX_BITS: usize = 6;
+const RADIX_PARTITIONS: usize = 1 << RADIX_BITS;
+/// Bits of the hash that select the radix partition. The lowest bits select the bucket within a hash table.
+const RADIX_SHIFT: usize = 24;
+/// Number of rows above which radix partitions are grouped in parallel.
+const PARALLEL_ROWS: usize = 1 << 20;
+
+/// Hash map grouping for many groups. Rows are first partitioned by bits of the hash of their key, and every radix
+/// partition is then grouped with a separate hash table that is small enough to be cache friendly. Radix partitions
+/// contain disjoint keys, so they are grouped independently and their groups are concatenated.
+#[derive(Debug)]
+pub struct RadixHashMapGrouping<T: VecData<T> + BatchHash> {
+    input: BufferRef<T>,
+    unique_out: BufferRef<T>,
+    grouping_key_out: BufferRef<u32>,
+    cardinality_out: BufferRef<Scalar<i64>>,
+    expected_cardinality: usize,
+}
+
+impl<'a, T: VecData<T> + BatchHash + 'a> RadixHashMapGrouping<T> {
+    pub fn boxed(input: BufferRef<T>,
+                 unique_out: BufferRef<T>,
+                 grouping_key_out: BufferRef<u32>,
+                 cardinality_out: BufferRef<Scalar<i64>>,
+                 expected_cardinality: usize) -> BoxedOperator<'a> {
+        Box::new(RadixHashMapGrouping::<T> {
+            input,
+            unique_out,
+            grouping_key_out,
+            cardinality_out,
+            expected_cardinality,
+        })
+    }
+}
+
+impl<'a, T: VecData<T> + BatchHash + 'a> VecOperator<'a> for RadixHashMapGrouping<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let count = {
+            let keys = scratchpad.get(self.input);
+            let mut hashes = Vec::with_capacity(keys.len());
+            T::hash_batch(&keys, &mut hashes);
+            let mut partitions = vec![Vec::new(); RADIX_PARTITIONS];
+            for (row, &hash) in hashes.iter().enumerate() {
+                partitions[(hash >> RADIX_SHIFT) as usize & (RADIX_PARTITIONS - 1)].push(row);
+            }
+            let groups = group_partitions(&keys, &hashes, &partitions, self.expected_cardinality / RADIX_PARTITIONS);
+
+            let mut grouping = scratchpad.get_mut(self.grouping_key_out);
+            let mut unique = scratchpad.get_mut(self.unique_out);
+            grouping.clear();
+            grouping.resize(keys.len(), 0);
+            for (rows, (partition_unique, ids)) in partitions.iter().zip(groups) {
+                let offset = unique.len() as u32;
+                for (&row, &id) in rows.iter().zip(ids.iter()) {
+                    grouping[row] = offset + id;
+                }
+                unique.extend(partition_unique);
+            }
+            RawVal::Int(unique.len() as i64)
+        };
+        scratchpad.set_any(self.cardinality_out.any(), Data::constant(count));
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.unique_out, Vec::with_capacity(self.expected_cardinality));
+        scratchpad.set(self.grouping_key_out, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.unique_out.any(), self.grouping_key_out.any(), self.cardinality_out.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, output: usize) -> bool { output != self.unique_out.i }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("radix_hashmap_grouping({})", self.input)
+    }
+}
+
+/// Groups the rows of each radix partition and returns the keys of its groups together with the group of each of its
+/// rows, numbered from zero within the partition.
+fn group_partitions<T: BatchHash + Send + Sync>(keys: &[T],
+                                                hashes: &[u64],
+                                                partitions: &[Vec<usize>],
+                                                expected_cardinality: usize) -> Vec<(Vec<T>, Vec<u32>)> {
+    let group = |rows: &Vec<usize>| {
+        let mut map = PrehashedMap::with_capacity_and_hasher(expected_cardinality, Default::default());
+        let mut unique = Vec::with_capacity(expected_cardinality);
+        let ids = rows.iter()
+            .map(|&row| {
+                let key = keys[row];
+                *map.entry(Prehashed { hash: hashes[row], key }).or_insert_with(|| {
+                    unique.push(key);
+                    unique.len() as u32 - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        (unique, ids)
+    };
+    if keys.len() < PARALLEL_ROWS {
+        return partitions.iter().map(&group).collect();
+    }
+
+    let threads = cmp::min(num_cpus::get(), partitions.len());
+    let chunk_size = (partitions.len() + threads - 1) / threads;
+    let mut groups = partitions.iter().map(|_| (Vec::new(), Vec::new())).collect::<Vec<_>>();
+    let group = &group;
+    Pool::new(threads as u32).scoped(|scope| {
+        for (partitions, groups) in partitions.chunks(chunk_size).zip(groups.chunks_mut(chunk_size)) {
+            scope.execute(move || {
+                for (rows, result) in partitions.iter().zip(groups.iter_mut()) {
+                    *result = group(rows);
+                }
+            });
+        }
+    });
+    groups
+}
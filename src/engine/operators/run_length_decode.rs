@@ -0,0 +1,52 @@
+use engine::*;
+
+#[derive(Debug)]
+pub struct RunLengthDecode<T> {
+    pub values: BufferRef<T>,
+    pub run_lengths: BufferRef<u32>,
+    pub decoded: BufferRef<T>,
+    pub batch_size: usize,
+    pub run: usize,
+    pub run_offset: usize,
+    pub has_more: bool,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for RunLengthDecode<T> {
+    fn execute(&mut self, streaming: bool, scratchpad: &mut Scratchpad<'a>) {
+        let values = scratchpad.get(self.values);
+        let run_lengths = scratchpad.get(self.run_lengths);
+        let mut decoded = scratchpad.get_mut(self.decoded);
+        if streaming { decoded.clear(); }
+        while self.run < values.len() && decoded.len() < self.batch_size {
+            let remaining = run_lengths[self.run] as usize - self.run_offset;
+            let count = remaining.min(self.batch_size - decoded.len());
+            for _ in 0..count {
+                decoded.push(values[self.run]);
+            }
+            if count == remaining {
+                self.run += 1;
+                self.run_offset = 0;
+            } else {
+                self.run_offset += count;
+            }
+        }
+        self.has_more = self.run < values.len();
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        self.batch_size = batch_size;
+        scratchpad.set(self.decoded, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.values.any(), self.run_lengths.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.decoded.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+    fn is_streaming_producer(&self) -> bool { true }
+    fn has_more(&self) -> bool { self.has_more }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("run_length_decode({}, {})", self.values, self.run_lengths)
+    }
+}
@@ -0,0 +1,45 @@
+use std::cmp;
+
+use engine::*;
+use syntax::sample::random_fraction;
+
+#[derive(Debug)]
+pub struct SampleRows {
+    pub output: BufferRef<u8>,
+    pub fraction: f64,
+    pub seed: u64,
+
+    pub current_index: usize,
+    pub len: usize,
+    pub batch_size: usize,
+}
+
+impl<'a> VecOperator<'a> for SampleRows {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let end = cmp::min(self.current_index + self.batch_size, self.len);
+        let mut output = scratchpad.get_mut(self.output);
+        output.clear();
+        // Whether a row is sampled only depends on its position, so the sample does not change with the batch size
+        for i in self.current_index..end {
+            output.push((random_fraction(self.seed, i as u64) < self.fraction) as u8);
+        }
+        self.current_index = end;
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        self.batch_size = batch_size;
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+    fn is_streaming_producer(&self) -> bool { true }
+    fn has_more(&self) -> bool { self.current_index < self.len }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("sample_rows({}%)", self.fraction * 100.0)
+    }
+}
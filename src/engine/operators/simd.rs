@@ -0,0 +1,230 @@
+use std::i64;
+
+use engine::data_types::GenericIntVec;
+
+
+/// Comparison of each element of an integer column with a scalar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equals,
+    NotEquals,
+    LessThan,
+    LessThanEquals,
+}
+
+impl Comparison {
+    pub fn perform(self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            Comparison::Equals => lhs == rhs,
+            Comparison::NotEquals => lhs != rhs,
+            Comparison::LessThan => lhs < rhs,
+            Comparison::LessThanEquals => lhs <= rhs,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equals => "=",
+            Comparison::NotEquals => "<>",
+            Comparison::LessThan => "<",
+            Comparison::LessThanEquals => "<=",
+        }
+    }
+
+    /// Result of the comparison for all elements if `rhs` lies outside of the range `min..=max` of the column type.
+    pub fn constant_result(self, rhs: i64, min: i64, max: i64) -> Option<u8> {
+        let out_of_range = rhs < min || rhs > max;
+        match self {
+            Comparison::Equals if out_of_range => Some(0),
+            Comparison::NotEquals if out_of_range => Some(1),
+            Comparison::LessThan if rhs > max => Some(1),
+            Comparison::LessThan if rhs <= min => Some(0),
+            Comparison::LessThanEquals if rhs >= max => Some(1),
+            Comparison::LessThanEquals if rhs < min => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the result of comparing each element of `lhs` with `rhs` to `output`, which has the same length as `lhs`.
+/// `rhs` must lie within the range of the column type.
+pub type ComparisonKernel<T> = unsafe fn(lhs: &[T], rhs: i64, comparison: Comparison, output: &mut [u8]);
+
+/// Sum of all elements.
+pub type SumKernel<T> = unsafe fn(&[T]) -> i64;
+
+/// Integer types that have SIMD implementations of comparisons with scalars and summation.
+pub trait SimdInt: GenericIntVec<Self> + Into<i64> {
+    const MIN: i64;
+    const MAX: i64;
+
+    /// Comparison kernel supported by the CPU, `None` if there is no SIMD implementation for it.
+    fn comparison_kernel() -> Option<ComparisonKernel<Self>>;
+    /// Summation kernel supported by the CPU, `None` if there is no SIMD implementation for it.
+    fn sum_kernel() -> Option<SumKernel<Self>>;
+}
+
+macro_rules! simd_int {
+    ($t:ty, $min:expr, $max:expr, $compare:ident, $sum:ident) => {
+        impl SimdInt for $t {
+            const MIN: i64 = $min;
+            const MAX: i64 = $max;
+
+            #[cfg(target_arch = "x86_64")]
+            fn comparison_kernel() -> Option<ComparisonKernel<$t>> {
+                if is_x86_feature_detected!("avx2") { Some(avx2::$compare) } else { None }
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            fn sum_kernel() -> Option<SumKernel<$t>> {
+                if is_x86_feature_detected!("avx2") { Some(avx2::$sum) } else { None }
+            }
+
+            #[cfg(not(target_arch = "x86_64"))]
+            fn comparison_kernel() -> Option<ComparisonKernel<$t>> { None }
+
+            #[cfg(not(target_arch = "x86_64"))]
+            fn sum_kernel() -> Option<SumKernel<$t>> { None }
+        }
+    }
+}
+
+simd_int!(u8, 0, 0xff, compare_u8, sum_u8);
+simd_int!(u16, 0, 0xffff, compare_u16, sum_u16);
+simd_int!(u32, 0, 0xffff_ffff, compare_u32, sum_u32);
+simd_int!(i64, i64::MIN, i64::MAX, compare_i64, sum_i64);
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::Comparison;
+
+    /// Writes bit `i` of `mask` to `output[i]`, the length of `output` is a multiple of 8.
+    #[inline]
+    fn write_mask(mask: u32, output: &mut [u8]) {
+        for (i, bytes) in output.chunks_mut(8).enumerate() {
+            // Replicates the 8 bits into every byte, keeps bit j in byte j and then moves it to the lowest bit
+            let bits = u64::from((mask >> (8 * i)) & 0xff).wrapping_mul(0x0101_0101_0101_0101) & 0x8040_2010_0804_0201;
+            let spread = (bits.wrapping_add(0x7f7f_7f7f_7f7f_7f7f) & 0x8080_8080_8080_8080) >> 7;
+            LittleEndian::write_u64(bytes, spread);
+        }
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn movemask_8(v: __m256i) -> u32 { _mm256_movemask_epi8(v) as u32 }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn movemask_16(v: __m256i) -> u32 {
+        let packed = _mm_packs_epi16(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1));
+        _mm_movemask_epi8(packed) as u32
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn movemask_32(v: __m256i) -> u32 { _mm256_movemask_ps(_mm256_castsi256_ps(v)) as u32 }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn movemask_64(v: __m256i) -> u32 { _mm256_movemask_pd(_mm256_castsi256_pd(v)) as u32 }
+
+    // Unsigned values are compared with signed instructions after flipping their sign bit.
+    // Each iteration processes `vectors` vectors of `lanes` elements, which yields a multiple of 8 results.
+    macro_rules! comparison_kernel {
+        ($name:ident, $t:ty, $lanes:expr, $vectors:expr, $set1:ident, $sign_bit:expr,
+         $cmpeq:ident, $cmpgt:ident, $movemask:ident) => {
+            #[target_feature(enable = "avx2")]
+            pub unsafe fn $name(lhs: &[$t], rhs: i64, comparison: Comparison, output: &mut [u8]) {
+                let sign_bit = $set1($sign_bit);
+                let rhs_vec = _mm256_xor_si256($set1(rhs as $t as _), sign_bit);
+                let step = $lanes * $vectors;
+                let chunks = lhs.len() / step;
+                for chunk in 0..chunks {
+                    let mut mask = 0u32;
+                    for j in 0..$vectors {
+                        let ptr = lhs.as_ptr().add(chunk * step + j * $lanes) as *const __m256i;
+                        let values = _mm256_xor_si256(_mm256_loadu_si256(ptr), sign_bit);
+                        let bits = match comparison {
+                            Comparison::Equals | Comparison::NotEquals => $movemask($cmpeq(values, rhs_vec)),
+                            Comparison::LessThan => $movemask($cmpgt(rhs_vec, values)),
+                            Comparison::LessThanEquals => $movemask($cmpgt(values, rhs_vec)),
+                        };
+                        mask |= bits << (j * $lanes);
+                    }
+                    if comparison == Comparison::NotEquals || comparison == Comparison::LessThanEquals {
+                        mask = !mask;
+                    }
+                    write_mask(mask, &mut output[chunk * step..(chunk + 1) * step]);
+                }
+                let done = chunks * step;
+                for (out, &l) in output[done..].iter_mut().zip(&lhs[done..]) {
+                    *out = comparison.perform(l as i64, rhs) as u8;
+                }
+            }
+        }
+    }
+
+    comparison_kernel!(compare_u8, u8, 32, 1, _mm256_set1_epi8, 0x80u8 as i8, _mm256_cmpeq_epi8, _mm256_cmpgt_epi8, movemask_8);
+    comparison_kernel!(compare_u16, u16, 16, 1, _mm256_set1_epi16, 0x8000u16 as i16, _mm256_cmpeq_epi16, _mm256_cmpgt_epi16, movemask_16);
+    comparison_kernel!(compare_u32, u32, 8, 1, _mm256_set1_epi32, 0x8000_0000u32 as i32, _mm256_cmpeq_epi32, _mm256_cmpgt_epi32, movemask_32);
+    comparison_kernel!(compare_i64, i64, 4, 2, _mm256_set1_epi64x, 0, _mm256_cmpeq_epi64, _mm256_cmpgt_epi64, movemask_64);
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn horizontal_sum(sums: __m256i) -> i64 {
+        let mut lanes = [0i64; 4];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sums);
+        lanes.iter().fold(0i64, |sum, &lane| sum.wrapping_add(lane))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_u8(values: &[u8]) -> i64 {
+        let zero = _mm256_setzero_si256();
+        let mut sums = zero;
+        let chunks = values.len() / 32;
+        for chunk in 0..chunks {
+            let v = _mm256_loadu_si256(values.as_ptr().add(chunk * 32) as *const __m256i);
+            // Sums each group of 8 bytes into one of the four 64-bit lanes
+            sums = _mm256_add_epi64(sums, _mm256_sad_epu8(v, zero));
+        }
+        values[chunks * 32..].iter().fold(horizontal_sum(sums), |sum, &v| sum + i64::from(v))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_u16(values: &[u16]) -> i64 {
+        let mut sums = _mm256_setzero_si256();
+        let chunks = values.len() / 8;
+        for chunk in 0..chunks {
+            let v = _mm_loadu_si128(values.as_ptr().add(chunk * 8) as *const __m128i);
+            sums = _mm256_add_epi64(sums, _mm256_cvtepu16_epi64(v));
+            sums = _mm256_add_epi64(sums, _mm256_cvtepu16_epi64(_mm_srli_si128(v, 8)));
+        }
+        values[chunks * 8..].iter().fold(horizontal_sum(sums), |sum, &v| sum + i64::from(v))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_u32(values: &[u32]) -> i64 {
+        let mut sums = _mm256_setzero_si256();
+        let chunks = values.len() / 4;
+        for chunk in 0..chunks {
+            let v = _mm_loadu_si128(values.as_ptr().add(chunk * 4) as *const __m128i);
+            sums = _mm256_add_epi64(sums, _mm256_cvtepu32_epi64(v));
+        }
+        values[chunks * 4..].iter().fold(horizontal_sum(sums), |sum, &v| sum + i64::from(v))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn sum_i64(values: &[i64]) -> i64 {
+        let mut sums = _mm256_setzero_si256();
+        let chunks = values.len() / 4;
+        for chunk in 0..chunks {
+            let v = _mm256_loadu_si256(values.as_ptr().add(chunk * 4) as *const __m256i);
+            sums = _mm256_add_epi64(sums, v);
+        }
+        values[chunks * 4..].iter().fold(horizontal_sum(sums), |sum, &v| sum.wrapping_add(v))
+    }
+}
@@ -0,0 +1,43 @@
+use engine::*;
+use super::simd::{Comparison, ComparisonKernel, SimdInt};
+
+
+/// Compares each element of an integer column with a scalar using a SIMD kernel.
+pub struct SimdComparison<T> {
+    pub lhs: BufferRef<T>,
+    pub rhs: BufferRef<Scalar<i64>>,
+    pub output: BufferRef<u8>,
+    pub comparison: Comparison,
+    pub kernel: ComparisonKernel<T>,
+}
+
+impl<'a, T: SimdInt + 'a> VecOperator<'a> for SimdComparison<T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        let lhs = scratchpad.get(self.lhs);
+        let rhs = scratchpad.get_scalar(&self.rhs);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        let start = output.len();
+        match self.comparison.constant_result(rhs, T::MIN, T::MAX) {
+            Some(result) => output.resize(start + lhs.len(), result),
+            None => {
+                output.resize(start + lhs.len(), 0);
+                unsafe { (self.kernel)(&lhs, rhs, self.comparison, &mut output[start..]) }
+            }
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} {} {}", self.lhs, self.comparison.symbol(), self.rhs)
+    }
+}
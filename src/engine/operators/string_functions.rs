@@ -0,0 +1,166 @@
+use std::cmp;
+use std::fmt;
+use std::str;
+
+use engine::*;
+
+
+#[derive(Debug)]
+pub struct ChangeCase<'a> {
+    pub input: BufferRef<&'a str>,
+    pub upper: bool,
+    pub string_store: BufferRef<u8>,
+    pub output: BufferRef<&'a str>,
+}
+
+impl<'a> VecOperator<'a> for ChangeCase<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let strings = {
+            let input = scratchpad.get(self.input);
+            if self.upper {
+                input.iter().map(|s| s.to_uppercase()).collect::<Vec<_>>()
+            } else {
+                input.iter().map(|s| s.to_lowercase()).collect::<Vec<_>>()
+            }
+        };
+        scratchpad.set_pinned_strings(self.string_store, self.output, &strings);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}({})", if self.upper { "upper" } else { "lower" }, self.input)
+    }
+}
+
+/// Changes the case of each dictionary entry on the first call to `execute` and looks up the result for each index,
+/// so that every distinct value is only converted once.
+#[derive(Debug)]
+pub struct DictChangeCase<'a, T> {
+    pub indices: BufferRef<T>,
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub upper: bool,
+    pub string_store: BufferRef<u8>,
+    pub dictionary: BufferRef<&'a str>,
+    pub output: BufferRef<&'a str>,
+    pub initialized: bool,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for DictChangeCase<'a, T> {
+    fn execute(&mut self, stream: bool, scratchpad: &mut Scratchpad<'a>) {
+        if !self.initialized {
+            let changed = {
+                let dict_indices = scratchpad.get(self.dict_indices);
+                let dict_data = scratchpad.get(self.dict_data);
+                dict_indices.iter()
+                    .map(|offset_len| {
+                        let offset = (offset_len >> 24) as usize;
+                        let len = (offset_len & 0x00ff_ffff) as usize;
+                        let string = unsafe { str::from_utf8_unchecked(&dict_data[offset..(offset + len)]) };
+                        if self.upper { string.to_uppercase() } else { string.to_lowercase() }
+                    })
+                    .collect::<Vec<_>>()
+            };
+            scratchpad.set_pinned_strings(self.string_store, self.dictionary, &changed);
+            self.initialized = true;
+        }
+        let indices = scratchpad.get(self.indices);
+        let dictionary = scratchpad.get(self.dictionary);
+        let mut output = scratchpad.get_mut(self.output);
+        if stream { output.clear(); }
+        for i in indices.iter() {
+            output.push(dictionary[i.cast_usize()]);
+        }
+    }
+
+    fn init(&mut self, _: usize, batch_size: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(batch_size));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.indices.any(), self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, buffer: usize) -> bool { buffer == self.indices.i }
+    fn can_stream_output(&self, _: usize) -> bool { true }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}({}[{}[{}]])", if self.upper { "upper" } else { "lower" }, self.dict_data, self.dict_indices, self.indices)
+    }
+}
+
+
+/// Concatenates strings from `lhs` and `rhs`, either of which may be a scalar.
+#[derive(Debug)]
+pub struct Concat<'a> {
+    pub lhs: TypedBufferRef,
+    pub rhs: TypedBufferRef,
+    pub string_store: BufferRef<u8>,
+    pub output: BufferRef<&'a str>,
+}
+
+impl<'a> VecOperator<'a> for Concat<'a> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let concatenated = {
+            let len = cmp::max(len(self.lhs, scratchpad), len(self.rhs, scratchpad));
+            let lhs = strings(self.lhs, len, scratchpad);
+            let rhs = strings(self.rhs, len, scratchpad);
+            lhs.iter().zip(rhs.iter()).map(|(l, r)| format!("{}{}", l, r)).collect::<Vec<_>>()
+        };
+        scratchpad.set_pinned_strings(self.string_store, self.output, &concatenated);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.lhs.any(), self.rhs.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{} || {}", self.lhs, self.rhs)
+    }
+}
+
+/// Formats each number in `input` as a string.
+#[derive(Debug)]
+pub struct ToStr<'a, T> {
+    pub input: BufferRef<T>,
+    pub string_store: BufferRef<u8>,
+    pub output: BufferRef<&'a str>,
+}
+
+impl<'a, T: VecData<T> + fmt::Display + 'a> VecOperator<'a> for ToStr<'a, T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let strings = {
+            let input = scratchpad.get(self.input);
+            input.iter().map(|x| x.to_string()).collect::<Vec<_>>()
+        };
+        scratchpad.set_pinned_strings(self.string_store, self.output, &strings);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.input.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("to_string({})", self.input)
+    }
+}
+
+fn len<'a>(buffer: TypedBufferRef, scratchpad: &Scratchpad<'a>) -> usize {
+    if buffer.tag.is_scalar() { 1 } else { scratchpad.get_any(buffer.any()).len() }
+}
+
+fn strings<'a>(buffer: TypedBufferRef, len: usize, scratchpad: &Scratchpad<'a>) -> Vec<&'a str> {
+    if buffer.tag.is_scalar() {
+        vec![scratchpad.get_scalar(&buffer.buffer.scalar_str()); len]
+    } else {
+        scratchpad.get(buffer.buffer.str()).to_vec()
+    }
+}
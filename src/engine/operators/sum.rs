@@ -1,12 +1,30 @@
+use std::cmp::min;
+use std::i64;
+
+use QueryError;
 use engine::*;
+use super::simd::{SimdInt, SumKernel};
 
 
-#[derive(Debug)]
 pub struct VecSum<T, U> {
     pub input: BufferRef<T>,
     pub grouping: BufferRef<U>,
     pub output: BufferRef<i64>,
     pub max_index: BufferRef<Scalar<i64>>,
+    /// Used instead of summing by group when all rows belong to the same group.
+    pub simd: Option<SumKernel<T>>,
+    pub overflow: bool,
+}
+
+impl<T: SimdInt, U> VecSum<T, U> {
+    pub fn new(input: BufferRef<T>,
+               grouping: BufferRef<U>,
+               output: BufferRef<i64>,
+               max_index: BufferRef<Scalar<i64>>) -> VecSum<T, U> {
+        // The kernel for `i64` wraps around on overflow, the sum of a batch of narrower integers always fits
+        let simd = if T::MAX == i64::MAX { None } else { T::sum_kernel() };
+        VecSum { input, grouping, output, max_index, simd, overflow: false }
+    }
 }
 
 impl<'a, T, U> VecOperator<'a> for VecSum<T, U> where
@@ -21,8 +39,68 @@ impl<'a, T, U> VecOperator<'a> for VecSum<T, U> where
             sums.resize(len, 0);
         }
 
+        match self.simd {
+            Some(kernel) if len == 1 => {
+                let count = min(grouping.len(), nums.len());
+                let (sum, overflow) = sums[0].overflowing_add(unsafe { kernel(&nums[..count]) });
+                sums[0] = sum;
+                self.overflow |= overflow;
+            }
+            _ => for (i, n) in grouping.iter().zip(nums.iter()) {
+                let sum = &mut sums[i.cast_usize()];
+                let (result, overflow) = sum.overflowing_add(Into::<i64>::into(*n));
+                *sum = result;
+                self.overflow |= overflow;
+            }
+        }
+    }
+
+    fn error(&self) -> Option<QueryError> {
+        if self.overflow {
+            Some(QueryError::Overflow(format!("Sum of {} exceeds the range of 64-bit integers", self.input)))
+        } else {
+            None
+        }
+    }
+
+    fn init(&mut self, _: usize, _: usize, scratchpad: &mut Scratchpad<'a>) {
+        scratchpad.set(self.output, Vec::with_capacity(0));
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.grouping.any(), self.input.any(), self.max_index.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { true }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}[{}] += {}", self.output, self.grouping, self.input)
+    }
+    fn display_output(&self) -> bool { false }
+}
+
+
+#[derive(Debug)]
+pub struct VecSumF64<U> {
+    pub input: BufferRef<OrderedF64>,
+    pub grouping: BufferRef<U>,
+    pub output: BufferRef<OrderedF64>,
+    pub max_index: BufferRef<Scalar<i64>>,
+}
+
+impl<'a, U: GenericIntVec<U>> VecOperator<'a> for VecSumF64<U> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let nums = scratchpad.get(self.input);
+        let grouping = scratchpad.get(self.grouping);
+        let mut sums = scratchpad.get_mut(self.output);
+
+        let len = scratchpad.get_scalar(&self.max_index) as usize + 1;
+        if len > sums.len() {
+            sums.resize(len, OrderedF64(0.0));
+        }
+
         for (i, n) in grouping.iter().zip(nums.iter()) {
-            sums[i.cast_usize()] += Into::<i64>::into(*n);
+            sums[i.cast_usize()].0 += n.0;
         }
     }
 
@@ -12,6 +12,8 @@ pub struct TopN<T, C> {
     pub indices: BufferRef<usize>,
     pub keys: BufferRef<T>,
     pub n: usize,
+    /// Number of leading rows of the top `n` that are not output.
+    pub offset: usize,
     pub last_index: usize,
     pub c: PhantomData<C>,
 }
@@ -67,8 +69,8 @@ impl<'a, T: VecData<T> + 'a, C: Comparator<T> + fmt::Debug> VecOperator<'a> for
             } else {
                 sort_indices.sort_unstable_by(|i, j| keys[*i].cmp(&keys[*j]).reverse());
             }
-            let mut output = Vec::with_capacity(indices.len());
-            for i in sort_indices {
+            let mut output = Vec::with_capacity(indices.len().saturating_sub(self.offset));
+            for i in sort_indices.into_iter().skip(self.offset) {
                 output.push(indices[i]);
             }
             output
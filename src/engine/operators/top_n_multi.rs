@@ -0,0 +1,149 @@
+use std::cell::Ref;
+use std::cmp::{self, Ordering};
+
+use engine::*;
+
+
+/// Determines the indices of the first `n` rows when ordering by multiple `rankings`, ties are broken by row index.
+/// The first `offset` of these rows are not output.
+#[derive(Debug)]
+pub struct TopNMulti {
+    pub rankings: Vec<TypedBufferRef>,
+    pub descending: Vec<bool>,
+    pub n: usize,
+    pub offset: usize,
+    pub top_n: BufferRef<usize>,
+}
+
+impl<'a> VecOperator<'a> for TopNMulti {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let top_n = {
+            let keys = self.rankings.iter()
+                .zip(self.descending.iter())
+                .map(|(&ranking, &desc)| (key_column(ranking, scratchpad), desc))
+                .collect::<Vec<_>>();
+            let len = keys.first().map_or(0, |(key, _)| key.len());
+            let cmp_rows = |i: usize, j: usize| {
+                for (key, desc) in &keys {
+                    let ordering = key.cmp_rows(i, j);
+                    if ordering != Ordering::Equal {
+                        return if *desc { ordering.reverse() } else { ordering };
+                    }
+                }
+                i.cmp(&j)
+            };
+
+            // Max heap that contains the best `n` rows seen so far with the worst of them at the root
+            let mut heap = Vec::with_capacity(self.n);
+            for row in 0..len {
+                if heap.len() < self.n {
+                    heap.push(row);
+                    sift_up(&mut heap, &cmp_rows);
+                } else if self.n > 0 && cmp_rows(row, heap[0]) == Ordering::Less {
+                    heap[0] = row;
+                    sift_down(&mut heap, &cmp_rows);
+                }
+            }
+            heap.sort_unstable_by(|&i, &j| cmp_rows(i, j));
+            let offset = cmp::min(self.offset, heap.len());
+            heap.drain(..offset);
+            heap
+        };
+        scratchpad.set(self.top_n, top_n);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { self.rankings.iter().map(|r| r.any()).collect() }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.top_n.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        let rankings = self.rankings.iter()
+            .zip(self.descending.iter())
+            .map(|(ranking, &desc)| format!("{}{}", ranking, if desc { " desc" } else { "" }))
+            .collect::<Vec<_>>();
+        format!("top_n({})", rankings.join(", "))
+    }
+}
+
+fn sift_up<F: Fn(usize, usize) -> Ordering>(heap: &mut [usize], cmp_rows: &F) {
+    let mut node = heap.len() - 1;
+    while node > 0 {
+        let parent = (node - 1) / 2;
+        if cmp_rows(heap[node], heap[parent]) == Ordering::Greater {
+            heap.swap(node, parent);
+            node = parent;
+        } else {
+            break;
+        }
+    }
+}
+
+fn sift_down<F: Fn(usize, usize) -> Ordering>(heap: &mut [usize], cmp_rows: &F) {
+    let mut node = 0;
+    loop {
+        let left_child = 2 * node + 1;
+        let right_child = 2 * node + 2;
+        let mut largest = node;
+        if left_child < heap.len() && cmp_rows(heap[left_child], heap[largest]) == Ordering::Greater {
+            largest = left_child;
+        }
+        if right_child < heap.len() && cmp_rows(heap[right_child], heap[largest]) == Ordering::Greater {
+            largest = right_child;
+        }
+        if largest == node {
+            break;
+        }
+        heap.swap(node, largest);
+        node = largest;
+    }
+}
+
+trait KeyColumn {
+    fn len(&self) -> usize;
+    fn cmp_rows(&self, i: usize, j: usize) -> Ordering;
+}
+
+impl<'b, T: Ord> KeyColumn for Ref<'b, [T]> {
+    fn len(&self) -> usize { <[T]>::len(self) }
+    fn cmp_rows(&self, i: usize, j: usize) -> Ordering { self[i].cmp(&self[j]) }
+}
+
+fn key_column<'a, 'b>(ranking: TypedBufferRef, scratchpad: &'b Scratchpad<'a>) -> Box<KeyColumn + 'b> {
+    match ranking.tag {
+        EncodingType::U8 => Box::new(scratchpad.get(ranking.buffer.u8())),
+        EncodingType::U16 => Box::new(scratchpad.get(ranking.buffer.u16())),
+        EncodingType::U32 => Box::new(scratchpad.get(ranking.buffer.u32())),
+        EncodingType::U64 => Box::new(scratchpad.get(ranking.buffer.u64())),
+        EncodingType::I64 => Box::new(scratchpad.get(ranking.buffer.i64())),
+        EncodingType::F64 => Box::new(scratchpad.get(ranking.buffer.f64())),
+        EncodingType::Str => Box::new(scratchpad.get(ranking.buffer.str())),
+        t => panic!("top_n not supported for type {:?}", t),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heap() {
+        let keys = vec![5, 3, 8, 1, 9, 2, 7];
+        let cmp_rows = |i: usize, j: usize| keys[i].cmp(&keys[j]);
+        let mut heap = Vec::new();
+        for row in 0..3 {
+            heap.push(row);
+            sift_up(&mut heap, &cmp_rows);
+        }
+        assert_eq!(keys[heap[0]], 8);
+        for row in 3..keys.len() {
+            if cmp_rows(row, heap[0]) == Ordering::Less {
+                heap[0] = row;
+                sift_down(&mut heap, &cmp_rows);
+            }
+        }
+        heap.sort_unstable_by(|&i, &j| cmp_rows(i, j));
+        assert_eq!(heap, vec![3, 5, 1]);
+    }
+}
@@ -0,0 +1,180 @@
+use std::borrow::Cow;
+use std::str;
+use std::sync::Arc;
+
+use engine::*;
+use ingest::raw_val::RawVal;
+use udf::{ArgSlice, ResultVec, ScalarFunction, ValueType};
+
+
+pub struct ScalarUdf {
+    pub function: Arc<ScalarFunction>,
+    pub args: Vec<BufferRef<Any>>,
+    pub string_store: BufferRef<u8>,
+    pub output: TypedBufferRef,
+}
+
+impl<'a> VecOperator<'a> for ScalarUdf {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let results = {
+            let args = self.args.iter().map(|&arg| scratchpad.get_any(arg)).collect::<Vec<_>>();
+            let len = args.iter()
+                .filter(|arg| !arg.get_type().is_scalar())
+                .map(|arg| arg.len())
+                .max()
+                .unwrap_or(1);
+            if self.function.is_vectorized() {
+                let values = args.iter()
+                    .zip(self.function.signature.args.iter())
+                    .map(|(arg, &t)| ArgValues::new(&**arg, t, len))
+                    .collect::<Vec<_>>();
+                let slices = values.iter().map(|values| values.slice()).collect::<Vec<_>>();
+                let results = self.function.call_vectorized(&slices);
+                assert!(results.len() == len,
+                        "Function {} returned {} values for {} rows", self.function.name, results.len(), len);
+                results
+            } else {
+                let mut row = Vec::with_capacity(args.len());
+                let mut results = Vec::with_capacity(len);
+                for i in 0..len {
+                    row.clear();
+                    row.extend(args.iter().map(|arg| arg.get_raw(i)));
+                    results.push(self.function.call(&row));
+                }
+                to_result_vec(&self.function, results, self.output.tag)
+            }
+        };
+        set_results(&self.function, results, self.string_store, self.output, scratchpad);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { self.args.clone() }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        let args = self.args.iter().map(|arg| format!("{}", arg)).collect::<Vec<_>>();
+        format!("{}({})", self.function.name, args.join(", "))
+    }
+}
+
+pub struct DictUdf<T> {
+    pub function: Arc<ScalarFunction>,
+    pub indices: BufferRef<T>,
+    pub dict_indices: BufferRef<u64>,
+    pub dict_data: BufferRef<u8>,
+    pub string_store: BufferRef<u8>,
+    pub output: TypedBufferRef,
+}
+
+impl<'a, T: GenericIntVec<T>> VecOperator<'a> for DictUdf<T> {
+    fn execute(&mut self, _: bool, scratchpad: &mut Scratchpad<'a>) {
+        let results = {
+            let dict_data = scratchpad.get_pinned(self.dict_data);
+            let dict_indices = scratchpad.get(self.dict_indices);
+            let evaluated = dict_indices.iter()
+                .map(|offset_len| {
+                    let offset = (offset_len >> 24) as usize;
+                    let len = (offset_len & 0x00ff_ffff) as usize;
+                    let string = unsafe { str::from_utf8_unchecked(&dict_data[offset..(offset + len)]) };
+                    self.function.call(&[RawVal::Str(string.to_string())])
+                })
+                .collect::<Vec<_>>();
+            let indices = scratchpad.get(self.indices);
+            indices.iter().map(|i| evaluated[i.cast_usize()].clone()).collect::<Vec<_>>()
+        };
+        let results = to_result_vec(&self.function, results, self.output.tag);
+        set_results(&self.function, results, self.string_store, self.output, scratchpad);
+    }
+
+    fn inputs(&self) -> Vec<BufferRef<Any>> { vec![self.indices.any(), self.dict_indices.any(), self.dict_data.any()] }
+    fn outputs(&self) -> Vec<BufferRef<Any>> { vec![self.output.any()] }
+    fn can_stream_input(&self, _: usize) -> bool { false }
+    fn can_stream_output(&self, _: usize) -> bool { false }
+    fn allocates(&self) -> bool { true }
+
+    fn display_op(&self, _: bool) -> String {
+        format!("{}({}[{}[{}]])", self.function.name, self.dict_data, self.dict_indices, self.indices)
+    }
+}
+
+/// Values of an argument of a vectorized function, borrowed from the argument's buffer unless it is a scalar.
+enum ArgValues<'b> {
+    Integer(Cow<'b, [i64]>),
+    Float(Vec<f64>),
+    String(Cow<'b, [&'b str]>),
+}
+
+impl<'b> ArgValues<'b> {
+    fn new<'a: 'b>(data: &'b Data<'a>, t: ValueType, len: usize) -> ArgValues<'b> {
+        match (t, data.get_type()) {
+            (ValueType::Integer, EncodingType::I64) => ArgValues::Integer(Cow::Borrowed(data.cast_ref_i64())),
+            (ValueType::Float, EncodingType::F64) => ArgValues::Float(data.cast_ref_f64().iter().map(|f| f.0).collect()),
+            (ValueType::String, EncodingType::Str) => ArgValues::String(Cow::Borrowed(data.cast_ref_str())),
+            (ValueType::String, EncodingType::ScalarStr) => ArgValues::String(Cow::Owned(vec![data.cast_scalar_str(); len])),
+            (ValueType::String, _) => ArgValues::String(Cow::Owned(vec![data.cast_ref_scalar_string().as_str(); len])),
+            (ValueType::Integer, _) => ArgValues::Integer((0..len)
+                .map(|i| match data.get_raw(i) { RawVal::Int(i) => i, _ => 0 })
+                .collect()),
+            (ValueType::Float, _) => ArgValues::Float((0..len)
+                .map(|i| match data.get_raw(i) {
+                    RawVal::Float(f) => f.0,
+                    RawVal::Int(i) => i as f64,
+                    _ => ::std::f64::NAN,
+                })
+                .collect()),
+        }
+    }
+
+    fn slice(&self) -> ArgSlice {
+        match *self {
+            ArgValues::Integer(ref values) => ArgSlice::Integer(values),
+            ArgValues::Float(ref values) => ArgSlice::Float(values),
+            ArgValues::String(ref values) => ArgSlice::String(values),
+        }
+    }
+}
+
+/// Converts the values returned by a function that is called for each row into a vector of type `t`.
+fn to_result_vec(function: &ScalarFunction, results: Vec<RawVal>, t: EncodingType) -> ResultVec {
+    match t {
+        EncodingType::I64 => ResultVec::Integer(results.into_iter()
+            .map(|result| match result {
+                RawVal::Int(i) => i,
+                other => panic!("Function {} returned {:?}, expected integer", function.name, other),
+            })
+            .collect()),
+        EncodingType::F64 => ResultVec::Float(results.into_iter()
+            .map(|result| match result {
+                RawVal::Float(f) => f.0,
+                RawVal::Int(i) => i as f64,
+                other => panic!("Function {} returned {:?}, expected float", function.name, other),
+            })
+            .collect()),
+        EncodingType::Str => ResultVec::String(results.into_iter()
+            .map(|result| match result {
+                RawVal::Str(s) => s,
+                other => panic!("Function {} returned {:?}, expected string", function.name, other),
+            })
+            .collect()),
+        t => panic!("Unsupported return type {:?} for function {}", t, function.name),
+    }
+}
+
+fn set_results<'a>(function: &ScalarFunction,
+                   results: ResultVec,
+                   string_store: BufferRef<u8>,
+                   output: TypedBufferRef,
+                   scratchpad: &mut Scratchpad<'a>) {
+    match (output.tag, results) {
+        (EncodingType::I64, ResultVec::Integer(ints)) => scratchpad.set(output.i64().unwrap(), ints),
+        (EncodingType::F64, ResultVec::Float(floats)) =>
+            scratchpad.set(output.f64().unwrap(), floats.into_iter().map(OrderedF64).collect()),
+        (EncodingType::F64, ResultVec::Integer(ints)) =>
+            scratchpad.set(output.f64().unwrap(), ints.into_iter().map(|i| OrderedF64(i as f64)).collect()),
+        (EncodingType::Str, ResultVec::String(strings)) =>
+            scratchpad.set_pinned_strings(string_store, output.str().unwrap(), &strings),
+        (t, results) => panic!("Function {} returned {:?}, expected {:?}", function.name, results, t),
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt::Write;
 use std::intrinsics::type_name;
 use std::marker::PhantomData;
 use std::result::Result;
+use std::sync::Arc;
 
 use itertools::Itertools;
 use regex::Regex;
@@ -11,11 +12,14 @@ use ingest::raw_val::RawVal;
 use mem_store::*;
 use locustdb_derive::reify_types;
 use QueryError;
+use syntax::expression::{Func2Type, TimeUnit};
+use udf::ScalarFunction;
 
 use super::assemble_nullable::AssembleNullable;
 use super::binary_operator::*;
 use super::bit_unpack::BitUnpackOperator;
 use super::bool_op::*;
+use super::coalesce::*;
 use super::column_ops::*;
 use super::combine_null_maps::CombineNullMaps;
 use super::compact::Compact;
@@ -27,12 +31,22 @@ use super::delta_decode::*;
 use super::dict_lookup::*;
 use super::encode_const::*;
 use super::exists::Exists;
+use super::extremum::VecExtremum;
 use super::filter::{Filter, NullableFilter};
+use super::float_operators::{FloatArithmetic, FloatComparison};
 use super::functions::*;
+use super::arg_extremum::{MergeArgExtremum, VecArgExtremum};
+use super::histogram::VecHistogram;
+use super::hyperloglog::{HllRegisters, VecMergeRegisters};
+use super::quantile_sketch::{MergeQuantileSketches, VecQuantileSketch};
 use super::hashmap_grouping::HashMapGrouping;
 use super::hashmap_grouping_byte_slices::HashMapGroupingByteSlices;
+use super::radix_grouping::{self, RadixHashMapGrouping};
 use super::identity::Identity;
 use super::indices::Indices;
+use super::is_in::{InRange, IsIn, IsInSet};
+use super::list::{EncodeLists, ListContains};
+use super::is_null::IsNull;
 use super::make_nullable::MakeNullable;
 use super::map_operator::MapOperator;
 use super::merge::Merge;
@@ -49,17 +63,25 @@ use super::numeric_operators::*;
 use super::parameterized_vec_vec_int_op::*;
 use super::partition::Partition;
 use super::propagate_nullability::PropagateNullability;
+use super::run_length_decode::RunLengthDecode;
+use super::scalar_f64::ScalarF64;
 use super::scalar_i64::ScalarI64;
+use super::sample_rows::SampleRows;
 use super::scalar_str::ScalarStr;
 use super::select::*;
+use super::simd::{Comparison, SimdInt};
+use super::simd_comparison::SimdComparison;
 use super::slice_pack::*;
 use super::slice_unpack::*;
 use super::sort_by::SortBy;
 use super::sort_by_slices::SortBySlices;
+use super::string_functions::{ChangeCase, Concat, DictChangeCase, ToStr};
 use super::subpartition::SubPartition;
-use super::sum::VecSum;
+use super::sum::{VecSum, VecSumF64};
 use super::top_n::TopN;
+use super::top_n_multi::TopNMulti;
 use super::type_conversion::TypeConversionOperator;
+use super::udf::*;
 use super::unhexpack_strings::UnhexpackStrings;
 use super::unpack_strings::UnpackStrings;
 use super::comparison_operators::*;
@@ -81,6 +103,8 @@ pub trait VecOperator<'a> {
     fn is_streaming_producer(&self) -> bool { false }
     fn has_more(&self) -> bool { false }
     fn custom_output_len(&self) -> Option<usize> { None }
+    /// Error that occurred during `execute`, e.g. because a result exceeds the range of its type.
+    fn error(&self) -> Option<QueryError> { None }
 
     fn display(&self, full: bool) -> String {
         let mut s = String::new();
@@ -94,6 +118,7 @@ pub trait VecOperator<'a> {
 
     fn display_output(&self) -> bool { true }
     fn display_op(&self, alternate: bool) -> String;
+    fn type_name(&self) -> String { short_type_name::<Self>() }
 }
 
 
@@ -193,6 +218,27 @@ impl<'a> VecOperator<'a> {
         panic!("LZ4 is not enabled in this build of LocustDB. Recompile with `features enable_lz4`")
     }
 
+    #[cfg(feature = "enable_zstd")]
+    pub fn zstd_decode(encoded: BufferRef<u8>,
+                       decoded_len: usize,
+                       decoded: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
+        use super::zstd_decode::ZstdDecode;
+        use std::io::Read;
+        let reader: Box<Read> = Box::new(&[] as &[u8]);
+        reify_types! {
+            "zstd_decode";
+            decoded: Integer;
+            Ok(Box::new(ZstdDecode::<'a, _> { encoded, decoded, decoded_len, reader, has_more: true }))
+        }
+    }
+
+    #[cfg(not(feature = "enable_zstd"))]
+    pub fn zstd_decode(_: BufferRef<u8>,
+                       _: usize,
+                       _: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
+        panic!("Zstd is not enabled in this build of LocustDB. Recompile with `features enable_zstd`")
+    }
+
     pub fn unpack_strings(packed: BufferRef<u8>, unpacked: BufferRef<&'a str>) -> BoxedOperator<'a> {
         Box::new(UnpackStrings::<'a> { packed, unpacked, iterator: None, has_more: true })
     }
@@ -213,6 +259,16 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn run_length_decode(values: TypedBufferRef,
+                             run_lengths: BufferRef<u32>,
+                             decoded: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "run_length_decode";
+            values, decoded: Integer;
+            Ok(Box::new(RunLengthDecode { values, run_lengths, decoded, batch_size: 0, run: 0, run_offset: 0, has_more: true }))
+        }
+    }
+
     pub fn inverse_dict_lookup(dict_indices: BufferRef<u64>,
                                dict_data: BufferRef<u8>,
                                constant: BufferRef<Scalar<&'a str>>,
@@ -220,6 +276,28 @@ impl<'a> VecOperator<'a> {
         Box::new(InverseDictLookup { dict_indices, dict_data, constant, output })
     }
 
+    pub fn dict_bound(dict_indices: BufferRef<u64>,
+                      dict_data: BufferRef<u8>,
+                      constant: BufferRef<Scalar<&'a str>>,
+                      at_most: bool,
+                      output: BufferRef<Scalar<i64>>) -> BoxedOperator<'a> {
+        Box::new(DictBound { dict_indices, dict_data, constant, at_most, output })
+    }
+
+    pub fn compare_dictionary(dict_indices: BufferRef<u64>,
+                              dict_data: BufferRef<u8>,
+                              constant: BufferRef<Scalar<&'a str>>,
+                              comparison: Func2Type,
+                              output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(CompareDictionary { dict_indices, dict_data, constant, comparison, output })
+    }
+
+    pub fn dictionary_entries(dict_indices: BufferRef<u64>,
+                              dict_data: BufferRef<u8>,
+                              output: BufferRef<&'a str>) -> BoxedOperator<'a> {
+        Box::new(DictionaryEntries { dict_indices, dict_data, output })
+    }
+
     pub fn encode_int_const(constant: BufferRef<Scalar<i64>>,
                             codec: Codec,
                             output: BufferRef<Scalar<i64>>) -> BoxedOperator<'a> {
@@ -253,6 +331,8 @@ impl<'a> VecOperator<'a> {
             "select";
             input, output: PrimitiveUSize;
             Ok(Box::new(Select { input, indices, output }));
+            input, output: KeyedVal;
+            Ok(Box::new(Select { input, indices, output }));
             input, output: NullablePrimitive;
             Ok(Box::new(SelectNullable { input, indices, output }))
         }
@@ -266,6 +346,10 @@ impl<'a> VecOperator<'a> {
         Box::new(ScalarI64 { val, hide_value, output })
     }
 
+    pub fn scalar_f64(val: OrderedF64, output: BufferRef<Scalar<OrderedF64>>) -> BoxedOperator<'a> {
+        Box::new(ScalarF64 { val, output })
+    }
+
     pub fn scalar_str(val: String, pinned: BufferRef<Scalar<String>>, output: BufferRef<Scalar<&'a str>>) -> BoxedOperator<'a> {
         Box::new(ScalarStr { val, pinned, output })
     }
@@ -287,6 +371,10 @@ impl<'a> VecOperator<'a> {
         }
     }
 
+    pub fn sample_rows(len: usize, fraction: f64, seed: u64, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(SampleRows { output, fraction, seed, current_index: 0, len, batch_size: 0 /* initialized later */ })
+    }
+
     pub fn constant_vec(val: BoxedData<'a>, output: BufferRef<Any>) -> BoxedOperator<'a> {
         Box::new(ConstantVec { val, output })
     }
@@ -302,7 +390,7 @@ impl<'a> VecOperator<'a> {
             Ok(Box::new(BinaryOperator { lhs, rhs, output, op: PhantomData::<LessThan> }));
 
             lhs: IntegerNoU64, rhs: ScalarI64;
-            Ok(Box::new(BinaryVSOperator { lhs, rhs, output, op: PhantomData::<LessThan> }));
+            Ok(VecOperator::scalar_comparison::<_, LessThan>(lhs, rhs, output, Comparison::LessThan));
             lhs: ScalarI64, rhs: IntegerNoU64;
             Ok(Box::new(BinarySVOperator { lhs, rhs, output, op: PhantomData::<LessThan> }));
             lhs: IntegerNoU64, rhs: IntegerNoU64;
@@ -321,7 +409,7 @@ impl<'a> VecOperator<'a> {
             Ok(Box::new(BinaryOperator { lhs, rhs, output, op: PhantomData::<LessThanEquals> }));
 
             lhs: IntegerNoU64, rhs: ScalarI64;
-            Ok(Box::new(BinaryVSOperator { lhs, rhs, output, op: PhantomData::<LessThanEquals> }));
+            Ok(VecOperator::scalar_comparison::<_, LessThanEquals>(lhs, rhs, output, Comparison::LessThanEquals));
             lhs: ScalarI64, rhs: IntegerNoU64;
             Ok(Box::new(BinarySVOperator { lhs, rhs, output, op: PhantomData::<LessThanEquals> }));
             lhs: IntegerNoU64, rhs: IntegerNoU64;
@@ -342,9 +430,9 @@ impl<'a> VecOperator<'a> {
             Ok(Box::new(BinaryOperator { lhs, rhs, output, op: PhantomData::<Equals> }));
 
             lhs: IntegerNoU64, rhs: ScalarI64;
-            Ok(Box::new(BinaryVSOperator { lhs, rhs, output, op: PhantomData::<Equals> }));
+            Ok(VecOperator::scalar_comparison::<_, Equals>(lhs, rhs, output, Comparison::Equals));
             lhs: ScalarI64, rhs: IntegerNoU64;
-            Ok(Box::new(BinaryVSOperator { lhs: rhs, rhs: lhs, output, op: PhantomData::<Equals> }));
+            Ok(VecOperator::scalar_comparison::<_, Equals>(rhs, lhs, output, Comparison::Equals));
             lhs: IntegerNoU64, rhs: IntegerNoU64;
             Ok(Box::new(BinaryOperator { lhs, rhs, output, op: PhantomData::<Equals> }))
         }
@@ -363,14 +451,26 @@ impl<'a> VecOperator<'a> {
             Ok(Box::new(BinaryOperator { lhs, rhs, output, op: PhantomData::<NotEquals> }));
 
             lhs: IntegerNoU64, rhs: ScalarI64;
-            Ok(Box::new(BinaryVSOperator { lhs, rhs, output, op: PhantomData::<NotEquals> }));
+            Ok(VecOperator::scalar_comparison::<_, NotEquals>(lhs, rhs, output, Comparison::NotEquals));
             lhs: ScalarI64, rhs: IntegerNoU64;
-            Ok(Box::new(BinaryVSOperator { lhs: rhs, rhs: lhs, output, op: PhantomData::<NotEquals> }));
+            Ok(VecOperator::scalar_comparison::<_, NotEquals>(rhs, lhs, output, Comparison::NotEquals));
             lhs: IntegerNoU64, rhs: IntegerNoU64;
             Ok(Box::new(BinaryOperator { lhs, rhs, output, op: PhantomData::<NotEquals> }))
         }
     }
 
+    /// Compares each element of `lhs` with `rhs` using a SIMD kernel if the CPU supports one.
+    fn scalar_comparison<T, Op>(lhs: BufferRef<T>,
+                                rhs: BufferRef<Scalar<i64>>,
+                                output: BufferRef<u8>,
+                                comparison: Comparison) -> BoxedOperator<'a>
+        where T: SimdInt + 'a, Op: BinaryOp<T, i64, u8> + 'a {
+        match T::comparison_kernel() {
+            Some(kernel) => Box::new(SimdComparison { lhs, rhs, output, comparison, kernel }),
+            None => Box::new(BinaryVSOperator { lhs, rhs, output, op: PhantomData::<Op> }),
+        }
+    }
+
     pub fn addition(lhs: TypedBufferRef,
                     rhs: TypedBufferRef,
                     output: BufferRef<i64>) -> Result<BoxedOperator<'a>, QueryError> {
@@ -499,10 +599,263 @@ impl<'a> VecOperator<'a> {
         Box::new(MapOperator { input, output, map: ToYear })
     }
 
+    pub fn date_trunc(input: BufferRef<i64>, unit: TimeUnit, output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: DateTrunc { unit } })
+    }
+
+    pub fn extract(input: BufferRef<i64>, unit: TimeUnit, output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: Extract { unit } })
+    }
+
     pub fn regex(input: BufferRef<&'a str>, r: &str, output: BufferRef<u8>) -> BoxedOperator<'a> {
         Box::new(MapOperator { input, output, map: RegexMatch { r: regex::Regex::new(r).unwrap() } })
     }
 
+    pub fn regex_dictionary(dict_indices: BufferRef<u64>, dict_data: BufferRef<u8>, r: &str, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(RegexDictionary { dict_indices, dict_data, regex: regex::Regex::new(r).unwrap(), output })
+    }
+
+    pub fn array_contains(input: BufferRef<&'a str>, element: &str, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: ArrayContains { element: element.to_string() } })
+    }
+
+    pub fn array_contains_dictionary(dict_indices: BufferRef<u64>, dict_data: BufferRef<u8>, element: &str, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(ArrayContainsDictionary { dict_indices, dict_data, element: element.to_string(), output })
+    }
+
+    pub fn encode_lists(ends: BufferRef<u32>, elements: BufferRef<&'a str>, string_store: BufferRef<u8>, output: BufferRef<&'a str>) -> BoxedOperator<'a> {
+        Box::new(EncodeLists { ends, elements, string_store, output })
+    }
+
+    pub fn list_contains(ends: BufferRef<u32>, elements: TypedBufferRef, code: BufferRef<Scalar<i64>>, output: BufferRef<u8>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "list_contains";
+            elements: Integer;
+            Ok(Box::new(ListContains { ends, elements, code, output }))
+        }
+    }
+
+    pub fn hash(input: TypedBufferRef, output: BufferRef<i64>) -> Result<BoxedOperator<'a>, QueryError> {
+        match input.tag {
+            EncodingType::Str => Ok(Box::new(MapOperator { input: input.str()?, output, map: HashStr })),
+            EncodingType::I64 => Ok(Box::new(MapOperator { input: input.i64()?, output, map: HashInt })),
+            _ => Err(fatal!("hash not implemented for type {:?}", input.tag)),
+        }
+    }
+
+    pub fn redact(input: TypedBufferRef, output: BufferRef<&'a str>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "redact";
+            input: Primitive;
+            Ok(Box::new(MapOperator { input, output, map: Redact }))
+        }
+    }
+
+    pub fn truncate(input: BufferRef<&'a str>, length: usize, output: BufferRef<&'a str>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: Truncate { length } })
+    }
+
+    pub fn substring(input: BufferRef<&'a str>, start: usize, length: usize, output: BufferRef<&'a str>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: Substring { start, length } })
+    }
+
+    pub fn length(input: BufferRef<&'a str>, output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: StringLength })
+    }
+
+    pub fn is_null(input: BufferRef<Nullable<Any>>, is_not_null: bool, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(IsNull { input, is_not_null, output })
+    }
+
+    pub fn coalesce(lhs: TypedBufferRef, rhs: TypedBufferRef, output: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
+        match (lhs.tag, rhs.tag) {
+            (EncodingType::NullableI64, EncodingType::I64) =>
+                Ok(Box::new(Coalesce { lhs: lhs.nullable_i64()?, rhs: rhs.i64()?, output: output.i64()? })),
+            (EncodingType::NullableStr, EncodingType::Str) =>
+                Ok(Box::new(Coalesce { lhs: lhs.nullable_str()?, rhs: rhs.str()?, output: output.str()? })),
+            (EncodingType::NullableI64, EncodingType::ScalarI64) =>
+                Ok(Box::new(CoalesceConst { lhs: lhs.nullable_i64()?, rhs: rhs.scalar_i64()?, output: output.i64()? })),
+            (EncodingType::NullableStr, EncodingType::ScalarStr) =>
+                Ok(Box::new(CoalesceConst { lhs: lhs.nullable_str()?, rhs: rhs.scalar_str()?, output: output.str()? })),
+            (EncodingType::NullableI64, EncodingType::NullableI64) =>
+                Ok(Box::new(CoalesceNullable { lhs: lhs.nullable_i64()?, rhs: rhs.nullable_i64()?, output: output.nullable_i64()? })),
+            (EncodingType::NullableStr, EncodingType::NullableStr) =>
+                Ok(Box::new(CoalesceNullable { lhs: lhs.nullable_str()?, rhs: rhs.nullable_str()?, output: output.nullable_str()? })),
+            (l, r) => Err(fatal!("coalesce not implemented for types {:?} and {:?}", l, r)),
+        }
+    }
+
+    pub fn int_to_float(input: BufferRef<i64>, output: BufferRef<OrderedF64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: IntToFloat })
+    }
+
+    pub fn float_to_int(input: BufferRef<OrderedF64>, output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: FloatToInt })
+    }
+
+    pub fn parse_int(input: BufferRef<&'a str>, output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: ParseInt })
+    }
+
+    pub fn parse_float(input: BufferRef<&'a str>, output: BufferRef<OrderedF64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: ParseFloat })
+    }
+
+    pub fn to_str(input: TypedBufferRef, string_store: BufferRef<u8>, output: BufferRef<&'a str>) -> Result<BoxedOperator<'a>, QueryError> {
+        match input.tag {
+            EncodingType::I64 => Ok(Box::new(ToStr { input: input.i64()?, string_store, output })),
+            EncodingType::F64 => Ok(Box::new(ToStr { input: input.f64()?, string_store, output })),
+            _ => Err(fatal!("to_str not implemented for type {:?}", input.tag)),
+        }
+    }
+
+    pub fn change_case(input: BufferRef<&'a str>, upper: bool, string_store: BufferRef<u8>, output: BufferRef<&'a str>) -> BoxedOperator<'a> {
+        Box::new(ChangeCase { input, upper, string_store, output })
+    }
+
+    pub fn dict_change_case(indices: TypedBufferRef,
+                            dict_indices: BufferRef<u64>,
+                            dict_data: BufferRef<u8>,
+                            upper: bool,
+                            string_store: BufferRef<u8>,
+                            dictionary: BufferRef<&'a str>,
+                            output: BufferRef<&'a str>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "dict_change_case";
+            indices: Integer;
+            Ok(Box::new(DictChangeCase { indices, dict_indices, dict_data, upper, string_store, dictionary, output, initialized: false }))
+        }
+    }
+
+    pub fn float_arithmetic(lhs: TypedBufferRef, rhs: TypedBufferRef, op: Func2Type, output: BufferRef<OrderedF64>) -> BoxedOperator<'a> {
+        Box::new(FloatArithmetic { lhs, rhs, op, output })
+    }
+
+    pub fn float_comparison(lhs: TypedBufferRef, rhs: TypedBufferRef, op: Func2Type, output: BufferRef<u8>) -> BoxedOperator<'a> {
+        Box::new(FloatComparison { lhs, rhs, op, output })
+    }
+
+    pub fn concat(lhs: TypedBufferRef, rhs: TypedBufferRef, string_store: BufferRef<u8>, output: BufferRef<&'a str>) -> BoxedOperator<'a> {
+        Box::new(Concat { lhs, rhs, string_store, output })
+    }
+
+    pub fn scalar_udf(function: Arc<ScalarFunction>,
+                      args: Vec<TypedBufferRef>,
+                      string_store: BufferRef<u8>,
+                      output: TypedBufferRef) -> BoxedOperator<'a> {
+        let args = args.iter().map(|arg| arg.any()).collect();
+        Box::new(ScalarUdf { function, args, string_store, output })
+    }
+
+    pub fn dict_udf(function: Arc<ScalarFunction>,
+                    indices: TypedBufferRef,
+                    dict_indices: BufferRef<u64>,
+                    dict_data: BufferRef<u8>,
+                    string_store: BufferRef<u8>,
+                    output: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "dict_udf";
+            indices: Integer;
+            Ok(Box::new(DictUdf { function, indices, dict_indices, dict_data, string_store, output }))
+        }
+    }
+
+    pub fn is_in(input: TypedBufferRef, values: Vec<TypedBufferRef>, output: BufferRef<u8>) -> Result<BoxedOperator<'a>, QueryError> {
+        let values = values.iter().map(|value| value.scalar_i64()).collect::<Result<Vec<_>, _>>()?;
+        reify_types! {
+            "is_in";
+            input: Integer;
+            Ok(Box::new(IsIn { input, values, output, set: None }))
+        }
+    }
+
+    pub fn in_range(input: TypedBufferRef, low: TypedBufferRef, high: TypedBufferRef, output: BufferRef<u8>) -> Result<BoxedOperator<'a>, QueryError> {
+        let low = low.scalar_i64()?;
+        let high = high.scalar_i64()?;
+        reify_types! {
+            "in_range";
+            input: Integer;
+            Ok(Box::new(InRange { input, low, high, output }))
+        }
+    }
+
+    pub fn is_in_set(input: TypedBufferRef, set: BufferRef<u8>, output: BufferRef<u8>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "is_in_set";
+            input: Integer;
+            Ok(Box::new(IsInSet { input, set, output }))
+        }
+    }
+
+    pub fn hll_registers(input: BufferRef<i64>, lane: usize, output: BufferRef<i64>) -> BoxedOperator<'a> {
+        Box::new(MapOperator { input, output, map: HllRegisters { lane } })
+    }
+
+    pub fn merge_registers(input: BufferRef<i64>,
+                           grouping: TypedBufferRef,
+                           max_index: BufferRef<Scalar<i64>>,
+                           output: BufferRef<i64>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "merge_registers";
+            grouping: Integer;
+            Ok(Box::new(VecMergeRegisters { input, grouping, output, max_index }))
+        }
+    }
+
+    pub fn extremum(input: BufferRef<i64>,
+                    grouping: TypedBufferRef,
+                    max_index: BufferRef<Scalar<i64>>,
+                    max: bool,
+                    output: BufferRef<i64>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "extremum";
+            grouping: Integer;
+            Ok(Box::new(VecExtremum { input, grouping, output, max_index, max }))
+        }
+    }
+
+    pub fn quantile_sketch(input: BufferRef<i64>,
+                           grouping: TypedBufferRef,
+                           max_index: BufferRef<Scalar<i64>>,
+                           string_store: BufferRef<u8>,
+                           output: BufferRef<&'a str>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "quantile_sketch";
+            grouping: Integer;
+            Ok(Box::new(VecQuantileSketch { input, grouping, max_index, string_store, output }))
+        }
+    }
+
+    pub fn arg_extremum(grouping: TypedBufferRef,
+                        key: TypedBufferRef,
+                        payload: TypedBufferRef,
+                        max_index: BufferRef<Scalar<i64>>,
+                        max: bool,
+                        selected: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
+        let key_present = if key.is_nullable() { Some(key.nullable_any()?) } else { None };
+        let key = key.forget_nullability().i64()?;
+        let payload_present = if payload.is_nullable() { Some(payload.nullable_any()?) } else { None };
+        let payload = payload.forget_nullability();
+        let selected = selected.keyed_val()?;
+        reify_types! {
+            "arg_extremum";
+            grouping: Integer, payload: Primitive;
+            Ok(Box::new(VecArgExtremum { grouping, key, key_present, payload, payload_present, max_index, max, selected }))
+        }
+    }
+
+    pub fn histogram(input: BufferRef<i64>,
+                     grouping: TypedBufferRef,
+                     max_index: BufferRef<Scalar<i64>>,
+                     string_store: BufferRef<u8>,
+                     output: BufferRef<&'a str>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "histogram";
+            grouping: Integer;
+            Ok(Box::new(VecHistogram { input, grouping, max_index, string_store, output }))
+        }
+    }
+
     pub fn summation(input: TypedBufferRef,
                      grouping: TypedBufferRef,
                      max_index: BufferRef<Scalar<i64>>,
@@ -510,7 +863,18 @@ impl<'a> VecOperator<'a> {
         reify_types! {
             "summation";
             input: IntegerNoU64, grouping: Integer;
-            Ok(Box::new(VecSum { input, grouping, output, max_index }))
+            Ok(Box::new(VecSum::new(input, grouping, output, max_index)))
+        }
+    }
+
+    pub fn summation_f64(input: BufferRef<OrderedF64>,
+                         grouping: TypedBufferRef,
+                         max_index: BufferRef<Scalar<i64>>,
+                         output: BufferRef<OrderedF64>) -> Result<BoxedOperator<'a>, QueryError> {
+        reify_types! {
+            "summation_f64";
+            grouping: Integer;
+            Ok(Box::new(VecSumF64 { input, grouping, output, max_index }))
         }
     }
 
@@ -549,13 +913,15 @@ impl<'a> VecOperator<'a> {
     pub fn compact(data: TypedBufferRef, select: TypedBufferRef, compacted: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
         reify_types! {
             "compact";
-            data, compacted: Integer, select: Integer;
+            data, compacted: Primitive, select: Integer;
+            Ok(Box::new(Compact { data, select, compacted }));
+            data, compacted: KeyedVal, select: Integer;
             Ok(Box::new(Compact { data, select, compacted }))
         }
     }
 
     pub fn hash_map_grouping(raw_grouping_key: TypedBufferRef,
-                             max_cardinality: usize,
+                             expected_cardinality: usize,
                              unique_out: TypedBufferRef,
                              grouping_key_out: BufferRef<u32>,
                              cardinality_out: BufferRef<Scalar<i64>>) -> Result<BoxedOperator<'a>, QueryError> {
@@ -563,10 +929,18 @@ impl<'a> VecOperator<'a> {
             return Ok(HashMapGroupingByteSlices::boxed(
                 raw_grouping_key.buffer, unique_out.buffer, grouping_key_out, cardinality_out, columns));
         }
-        reify_types! {
-            "hash_map_grouping";
-            raw_grouping_key, unique_out: Primitive;
-            Ok(HashMapGrouping::boxed(raw_grouping_key, unique_out, grouping_key_out, cardinality_out, max_cardinality))
+        if expected_cardinality >= radix_grouping::RADIX_GROUPING_THRESHOLD {
+            reify_types! {
+                "radix_hash_map_grouping";
+                raw_grouping_key, unique_out: Primitive;
+                Ok(RadixHashMapGrouping::boxed(raw_grouping_key, unique_out, grouping_key_out, cardinality_out, expected_cardinality))
+            }
+        } else {
+            reify_types! {
+                "hash_map_grouping";
+                raw_grouping_key, unique_out: Primitive;
+                Ok(HashMapGrouping::boxed(raw_grouping_key, unique_out, grouping_key_out, cardinality_out, expected_cardinality))
+            }
         }
     }
 
@@ -593,20 +967,36 @@ impl<'a> VecOperator<'a> {
     pub fn top_n(input: TypedBufferRef,
                  keys: TypedBufferRef,
                  n: usize, desc: bool,
+                 offset: usize,
                  indices_out: BufferRef<usize>) -> Result<BoxedOperator<'a>, QueryError> {
         if desc {
             reify_types! {
                 "top_n_desc";
                 input, keys: Primitive;
-                Ok(Box::new(TopN { input, keys, indices: indices_out, last_index: 0, n, c: PhantomData::<CmpGreaterThan> }))
+                Ok(Box::new(TopN { input, keys, indices: indices_out, last_index: 0, n, offset, c: PhantomData::<CmpGreaterThan> }))
             }
         } else {
             reify_types! {
                 "top_n_asc";
                 input, keys: Primitive;
-                Ok(Box::new(TopN { input, keys, indices: indices_out, last_index: 0, n, c: PhantomData::<CmpLessThan> }))
+                Ok(Box::new(TopN { input, keys, indices: indices_out, last_index: 0, n, offset, c: PhantomData::<CmpLessThan> }))
+            }
+        }
+    }
+
+    pub fn top_n_multi(rankings: Vec<TypedBufferRef>,
+                       descending: Vec<bool>,
+                       n: usize,
+                       offset: usize,
+                       top_n: BufferRef<usize>) -> Result<BoxedOperator<'a>, QueryError> {
+        for ranking in &rankings {
+            match ranking.tag {
+                EncodingType::U8 | EncodingType::U16 | EncodingType::U32 | EncodingType::U64
+                | EncodingType::I64 | EncodingType::F64 | EncodingType::Str => {}
+                t => bail!(QueryError::NotImplemented, "top_n not supported for type {:?}", t),
             }
         }
+        Ok(Box::new(TopNMulti { rankings, descending, n, offset, top_n }))
     }
 
     pub fn merge_deduplicate(left: TypedBufferRef,
@@ -684,11 +1074,45 @@ impl<'a> VecOperator<'a> {
     }
 
     pub fn merge_aggregate(merge_ops: BufferRef<MergeOp>,
-                           left: BufferRef<i64>,
-                           right: BufferRef<i64>,
+                           left: TypedBufferRef,
+                           right: TypedBufferRef,
                            aggregator: Aggregator,
-                           aggregated_out: BufferRef<i64>) -> BoxedOperator<'a> {
-        Box::new(MergeAggregate { merge_ops, left, right, aggregated: aggregated_out, aggregator })
+                           aggregated_out: TypedBufferRef) -> Result<BoxedOperator<'a>, QueryError> {
+        match left.tag {
+            EncodingType::I64 => Ok(Box::new(MergeAggregate {
+                merge_ops,
+                left: left.i64()?,
+                right: right.i64()?,
+                aggregated: aggregated_out.i64()?,
+                aggregator,
+                overflow: false,
+            })),
+            EncodingType::F64 => Ok(Box::new(MergeAggregate {
+                merge_ops,
+                left: left.f64()?,
+                right: right.f64()?,
+                aggregated: aggregated_out.f64()?,
+                aggregator,
+                overflow: false,
+            })),
+            t => Err(fatal!("merge_aggregate not implemented for type {:?}", t)),
+        }
+    }
+
+    pub fn merge_quantile_sketches(merge_ops: BufferRef<MergeOp>,
+                                   left: BufferRef<&'a str>,
+                                   right: BufferRef<&'a str>,
+                                   string_store: BufferRef<u8>,
+                                   merged: BufferRef<&'a str>) -> BoxedOperator<'a> {
+        Box::new(MergeQuantileSketches { merge_ops, left, right, string_store, merged })
+    }
+
+    pub fn merge_arg_extremum(merge_ops: BufferRef<MergeOp>,
+                              left: BufferRef<KeyedVal<'a>>,
+                              right: BufferRef<KeyedVal<'a>>,
+                              max: bool,
+                              merged: BufferRef<KeyedVal<'a>>) -> BoxedOperator<'a> {
+        Box::new(MergeArgExtremum { merge_ops, left, right, max, merged })
     }
 
     pub fn merge_partitioned(partitioning: BufferRef<Premerge>,
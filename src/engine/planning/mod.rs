@@ -8,3 +8,4 @@ pub use self::planner::QueryPlanner;
 pub use self::filter::Filter;
 pub use self::query::Query;
 pub use self::query::NormalFormQuery;
+pub use self::query::Wildcard;
@@ -1,25 +1,45 @@
+use std::cmp;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
+use std::i64;
 use std::iter::Iterator;
+use std::mem;
 use std::sync::Arc;
 
 use ::QueryError;
+use errors::Suggestion;
 use engine::*;
 use ingest::raw_val::RawVal;
 use mem_store::column::DataSource;
+use mem_store::partition::{DELETED_COL, Partition};
+use mem_store::catalog::{self, ColumnStats};
 use syntax::expression::*;
 use syntax::limit::*;
+use syntax::sample::{RowSample, SampleClause};
+use syntax::statement::GeneratedColumn;
+
+/// Estimated fraction of rows that must remain before further predicates are only evaluated on the remaining rows.
+const SHORT_CIRCUIT_SELECTIVITY: f64 = 0.25;
 
 /// NormalFormQuery observes the following invariants:
 /// - none of the expressions contain aggregation functions
-/// - if aggregate.len() > 0 then order_by.len() == 0 and vice versa
+/// - if aggregate.len() > 0 then order_by only refers to expressions in projection or aggregate
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct NormalFormQuery {
     pub projection: Vec<Expr>,
     pub filter: Expr,
     pub aggregate: Vec<(Aggregator, Expr)>,
     pub order_by: Vec<(Expr, bool)>,
     pub limit: LimitClause,
+    pub sum_overflow: SumOverflow,
+    pub row_sample: Option<RowSample>,
+    /// Whether rows with equal sort keys keep their order, which rules out the top-n operator.
+    pub stable_sort: bool,
+    /// Whether groups of the single group by column are identified by the codes of the dictionary that all partitions
+    /// share, so that partial results are merged on codes. The codes are decoded once all results are merged.
+    pub group_by_codes: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -29,47 +49,109 @@ pub struct Query {
     pub filter: Expr,
     pub order_by: Vec<(Expr, bool)>,
     pub limit: LimitClause,
+    pub distinct: bool,
+    /// Name given to each selected expression with `AS`, if any.
+    pub aliases: Vec<Option<String>>,
+    /// Whether subtotals are computed for each prefix of the grouping columns (`GROUP BY ROLLUP`).
+    pub rollup: bool,
+    /// How overflowing integer sums are handled, `None` to use the database's default.
+    pub sum_overflow: Option<SumOverflow>,
+    /// Random subset of the table that the query is run on, if any.
+    pub sample: Option<SampleClause>,
+}
+
+/// Selects all columns that start with `prefix` except for the columns in `except`. Written as `prefix*` or
+/// `prefix* EXCEPT(a, b)` and represented as a column name in the select clause until it is expanded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wildcard {
+    pub prefix: String,
+    pub except: Vec<String>,
+}
+
+impl Wildcard {
+    pub fn parse(colname: &str) -> Option<Wildcard> {
+        let (pattern, except) = match colname.find(" EXCEPT(") {
+            Some(start) if colname.ends_with(')') => {
+                let list = &colname[(start + " EXCEPT(".len())..(colname.len() - 1)];
+                (&colname[..start], list.split(", ").map(str::to_string).collect())
+            }
+            _ => (colname, vec![]),
+        };
+        if pattern.ends_with('*') {
+            Some(Wildcard { prefix: pattern[..(pattern.len() - 1)].to_string(), except })
+        } else {
+            None
+        }
+    }
+
+    pub fn matches(&self, column: &str) -> bool {
+        column.starts_with(&self.prefix) && !self.except.iter().any(|except| except == column)
+    }
+}
+
+impl fmt::Display for Wildcard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}*", self.prefix)?;
+        if !self.except.is_empty() {
+            write!(f, " EXCEPT({})", self.except.join(", "))?;
+        }
+        Ok(())
+    }
 }
 
 impl NormalFormQuery {
+    /// Returns the rows up to the end of the limit. If the rows are ordered, the first `offset` of them are dropped by
+    /// the top-n operator, which is only correct if the partition holds all rows of the query.
     #[inline(never)] // produces more useful profiles
     pub fn run<'a>(&self,
                    columns: &'a HashMap<String, Arc<DataSource>>,
                    explain: bool,
                    show: bool,
                    partition: usize,
-                   partition_length: usize) -> Result<(BatchResult<'a>, Option<String>), QueryError> {
+                   partition_length: usize,
+                   limits: &QueryLimits,
+                   batch_size: usize,
+                   offset: usize) -> Result<(BatchResult<'a>, Option<PlanGraph>), QueryError> {
         let limit = (self.limit.limit + self.limit.offset) as usize;
+        let planning = trace_span!("Plan partition {}", partition);
         let mut planner = QueryPlanner::default();
 
-        let (filter_plan, _) = QueryPlan::compile_expr(&self.filter, Filter::None, columns, &mut planner)?;
-        let mut filter = match filter_plan.tag {
-            EncodingType::U8 => Filter::U8(filter_plan.u8()?),
-            EncodingType::NullableU8 => Filter::NullableU8(filter_plan.nullable_u8()?),
-            _ => Filter::None,
-        };
+        let mut filter = self.compile_filter(columns, partition, partition_length, &mut planner)?;
 
         // Sorting
         let mut sort_indices = None;
-        for (plan, desc) in self.order_by.iter().rev() {
-            let (ranking, _) = query_plan::order_preserving(
-                QueryPlan::compile_expr(&plan, filter, columns, &mut planner)?, &mut planner);
-
-            // TODO(clemens): better criterion for using top_n
-            // TODO(clemens): top_n for multiple columns?
-            sort_indices = Some(if limit < partition_length / 2 && self.order_by.len() == 1 {
-                planner.top_n(ranking, limit, *desc)
-            } else {
-                // TODO(clemens): Optimization: sort directly if only single column selected
-                match sort_indices {
-                    None => {
-                        let indices = planner.indices(ranking);
-                        planner.sort_by(ranking, indices,
-                                        *desc, false /* unstable sort */)
+        // TODO(clemens): better criterion for using top_n
+        let use_top_n = !self.stable_sort && (limit < partition_length / 2 || offset > 0);
+        let n = cmp::min(limit, partition_length);
+        if use_top_n && self.order_by.len() > 1 {
+            let mut rankings = Vec::with_capacity(self.order_by.len());
+            let mut descending = Vec::with_capacity(self.order_by.len());
+            for (plan, desc) in &self.order_by {
+                let (ranking, _) = query_plan::order_preserving(
+                    QueryPlan::compile_expr(&plan, filter, columns, &mut planner)?, &mut planner);
+                rankings.push(ranking);
+                descending.push(*desc);
+            }
+            sort_indices = Some(planner.top_n_multi(rankings, descending, n, offset));
+        } else {
+            for (plan, desc) in self.order_by.iter().rev() {
+                let (ranking, _) = query_plan::order_preserving(
+                    QueryPlan::compile_expr(&plan, filter, columns, &mut planner)?, &mut planner);
+
+                sort_indices = Some(if use_top_n {
+                    planner.top_n(ranking, n, *desc, offset)
+                } else {
+                    // TODO(clemens): Optimization: sort directly if only single column selected
+                    match sort_indices {
+                        None => {
+                            let indices = planner.indices(ranking);
+                            planner.sort_by(ranking, indices,
+                                            *desc, self.stable_sort)
+                        }
+                        Some(indices) => planner.sort_by(ranking, indices, *desc, true /* stable sort */)
                     }
-                    Some(indices) => planner.sort_by(ranking, indices, *desc, true /* stable sort */)
-                }
-            });
+                });
+            }
         }
         if let Some(sort_indices) = sort_indices {
             filter = match filter {
@@ -86,7 +168,7 @@ impl NormalFormQuery {
                     Filter::Indices(planner.select(filter, sort_indices).usize()?)
                 }
                 Filter::None => Filter::Indices(sort_indices),
-                Filter::Indices(_) => unreachable!(),
+                Filter::Indices(indices) => Filter::Indices(planner.select(indices.into(), sort_indices).usize()?),
             };
         }
 
@@ -113,7 +195,11 @@ impl NormalFormQuery {
         let mut executor = planner.prepare(vec![])?;
         let mut results = executor.prepare(NormalFormQuery::column_data(columns));
         debug!("{:#}", &executor);
-        executor.run(columns.iter().next().unwrap().1.len(), &mut results, show);
+        drop(planning);
+        let _running = trace_span!("Run partition {}", partition);
+        executor.set_limits(limits.clone());
+        executor.set_batch_size(batch_size);
+        executor.run(columns.iter().next().unwrap().1.len(), &mut results, show)?;
         let (columns, projection, _, order_by) = results.collect_aliased(&select, &[], &order_by);
 
         Ok(
@@ -127,7 +213,7 @@ impl NormalFormQuery {
                 show,
                 unsafe_referenced_buffers: results.collect_pinned(),
             },
-             if explain { Some(format!("{}", executor)) } else { None }))
+             if explain { Some(executor.plan_graph()) } else { None }))
     }
 
     #[inline(never)] // produces more useful profiles
@@ -136,35 +222,56 @@ impl NormalFormQuery {
                              explain: bool,
                              show: bool,
                              partition: usize,
-                             partition_length: usize)
-                             -> Result<(BatchResult<'a>, Option<String>), QueryError> {
-        trace_start!("run_aggregate");
-
+                             partition_length: usize,
+                             limits: &QueryLimits,
+                             batch_size: usize)
+                             -> Result<(BatchResult<'a>, Option<PlanGraph>), QueryError> {
+        let planning = trace_span!("Plan aggregation of partition {}", partition);
         let mut planner = QueryPlanner::default();
 
         // Filter
-        let (filter_plan, filter_type) = QueryPlan::compile_expr(&self.filter, Filter::None, columns, &mut planner)?;
-        let filter = match filter_type.encoding_type() {
-            EncodingType::U8 => Filter::U8(filter_plan.u8()?),
-            EncodingType::NullableU8 => Filter::NullableU8(filter_plan.nullable_u8()?),
-            _ => Filter::None,
-        };
+        let filter = self.compile_filter(columns, partition, partition_length, &mut planner)?;
 
-        // Combine all group by columns into a single decodable grouping key
+        // Grouping by a single dictionary encoded column aggregates by dictionary index, otherwise all group by columns
+        // are combined into a single decodable grouping key. Codes of dictionaries shared by all partitions are
+        // aggregated like integers and are not decoded.
+        let dictionary_grouping = if self.group_by_codes {
+            None
+        } else {
+            query_plan::dictionary_grouping_key(&self.projection, filter, columns, &mut planner)?
+        };
         let ((raw_grouping_key, raw_grouping_key_type),
             max_grouping_key,
             decode_plans,
-            encoded_group_by_placeholder) =
-            query_plan::compile_grouping_key(&self.projection, filter, columns, partition_length, &mut planner)?;
+            encoded_group_by_placeholder,
+            dictionary_entries) = match dictionary_grouping {
+            Some((grouping_key, dictionary_size, entries)) => (grouping_key, dictionary_size, vec![], None, Some(entries)),
+            None if self.group_by_codes => {
+                let (codes, dictionary_size) =
+                    query_plan::shared_dictionary_grouping_key(&self.projection, filter, columns, &mut planner)?;
+                (codes, dictionary_size, vec![], None, None)
+            }
+            None => {
+                let (grouping_key, max_grouping_key, decode_plans, placeholder) =
+                    query_plan::compile_grouping_key(&self.projection, filter, columns, partition_length, &mut planner)?;
+                (grouping_key, max_grouping_key, decode_plans, Some(placeholder), None)
+            }
+        };
+
+        // Choose grouping strategy based on the number of rows that are expected to pass the filter
+        let selectivity = NormalFormQuery::estimate_selectivity(&self.filter, columns);
+        let expected_rows = (partition_length as f64 * selectivity).ceil() as usize;
+        let expected_groups = cmp::min(max_grouping_key as usize, expected_rows);
+        // Dictionaries contain at most one entry for every two rows, so grouping by dictionary index is always dense
+        let dense_grouping = dictionary_entries.is_some() || (raw_grouping_key_type.is_positive_integer()
+            && query_plan::use_dense_grouping(max_grouping_key, expected_rows));
 
         // Reduce cardinality of grouping key if necessary and perform grouping
-        // TODO(clemens): also determine and use is_dense. always true for hashmap, depends on group by columns for raw.
         let (encoded_group_by_column,
             grouping_key,
             grouping_key_type,
             aggregation_cardinality) =
-        // TODO(clemens): refine criterion
-            if max_grouping_key < 1 << 16 && raw_grouping_key_type.is_positive_integer() {
+            if dense_grouping {
                 let max_grouping_key_buf = planner.scalar_i64(max_grouping_key, true);
                 (None,
                  raw_grouping_key,
@@ -173,7 +280,7 @@ impl NormalFormQuery {
             } else {
                 query_plan::prepare_hashmap_grouping(
                     raw_grouping_key,
-                    max_grouping_key as usize,
+                    expected_groups,
                     &mut planner)?
             };
 
@@ -182,20 +289,80 @@ impl NormalFormQuery {
         let mut selector = None;
         let mut selector_index = None;
         for (i, &(aggregator, ref expr)) in self.aggregate.iter().enumerate() {
-            let (plan, plan_type) = QueryPlan::compile_expr(expr, filter, columns, &mut planner)?;
-            let (aggregate, t) = query_plan::prepare_aggregation(
-                plan,
-                plan_type,
-                grouping_key,
-                aggregation_cardinality,
-                aggregator,
-                &mut planner)?;
+            // Aggregates with a FILTER clause only include the rows that satisfy their condition
+            let (expr, condition) = match *expr {
+                Expr::Filtered(ref expr, ref condition) => {
+                    let (condition_plan, condition_type) = QueryPlan::compile_expr(condition, filter, columns, &mut planner)?;
+                    let condition = match condition_type.encoding_type() {
+                        EncodingType::U8 => Filter::U8(condition_plan.u8()?),
+                        EncodingType::NullableU8 => Filter::NullableU8(condition_plan.nullable_u8()?),
+                        _ => bail!(QueryError::TypeError, "FILTER condition must be boolean, found {:?}", condition_type),
+                    };
+                    (&**expr, condition)
+                }
+                ref expr => (expr, Filter::None),
+            };
+            // ARG_MAX and ARG_MIN aggregate a value together with the key that selects it
+            let (expr, mut key) = match *expr {
+                Expr::Func2(Func2Type::KeyedBy, ref payload, ref key) =>
+                    (&**payload, Some(QueryPlan::compile_expr(key, filter, columns, &mut planner)?)),
+                ref expr => (expr, None),
+            };
+            let (mut plan, plan_type) = QueryPlan::compile_expr(expr, filter, columns, &mut planner)?;
+            let aggregated_grouping_key = match condition {
+                Filter::U8(condition) => {
+                    if !plan.tag.is_scalar() {
+                        plan = planner.filter(plan, condition);
+                    }
+                    if let Some((ref mut key, _)) = key {
+                        if !key.tag.is_scalar() {
+                            *key = planner.filter(*key, condition);
+                        }
+                    }
+                    planner.filter(grouping_key, condition)
+                }
+                Filter::NullableU8(condition) => {
+                    if !plan.tag.is_scalar() {
+                        plan = planner.nullable_filter(plan, condition);
+                    }
+                    if let Some((ref mut key, _)) = key {
+                        if !key.tag.is_scalar() {
+                            *key = planner.nullable_filter(*key, condition);
+                        }
+                    }
+                    planner.nullable_filter(grouping_key, condition)
+                }
+                _ => grouping_key,
+            };
+            // Counts that skip nulls or filtered rows can be zero for groups that exist
+            let unfiltered = match condition {
+                Filter::None => true,
+                _ => false,
+            };
+            let nonzero = aggregator == Aggregator::Count && !plan.is_nullable() && unfiltered;
+            let (aggregate, t) = match key {
+                Some(key) => query_plan::prepare_arg_extremum(
+                    (plan, plan_type),
+                    key,
+                    aggregated_grouping_key,
+                    aggregation_cardinality,
+                    aggregator == Aggregator::ArgMax,
+                    &mut planner)?,
+                None => query_plan::prepare_aggregation(
+                    plan,
+                    plan_type,
+                    aggregated_grouping_key,
+                    aggregation_cardinality,
+                    aggregator,
+                    self.sum_overflow,
+                    &mut planner)?,
+            };
             // TODO(clemens): if summation column is strictly positive, can use sum as well
-            if aggregator == Aggregator::Count {
+            if nonzero {
                 selector = Some((aggregate, t.encoding_type()));
                 selector_index = Some(i)
             }
-            aggregation_results.push((aggregator, aggregate, t))
+            aggregation_results.push((aggregator, aggregate, t, nonzero))
         }
 
         // Determine selector
@@ -205,22 +372,27 @@ impl NormalFormQuery {
         };
 
         // Construct (encoded) group by column
-        let encoded_group_by_column = match encoded_group_by_column {
-            None => planner.nonzero_indices(selector, grouping_key_type.encoding_type()),
-            Some(x) => x,
+        let encoded_group_by_column = match (encoded_group_by_column, dictionary_entries) {
+            (Some(x), _) => x,
+            // The dictionary entries of groups that exist form the group by column
+            (None, Some(entries)) => planner.compact(entries.into(), selector),
+            (None, None) => planner.nonzero_indices(selector, grouping_key_type.encoding_type()),
         };
-        planner.connect(encoded_group_by_column, encoded_group_by_placeholder);
+        if let Some(placeholder) = encoded_group_by_placeholder {
+            planner.connect(encoded_group_by_column, placeholder);
+        }
 
         // Compact and decode aggregation results
         let mut aggregation_cols = Vec::new();
         {
-            let mut decode_compact = |aggregator: Aggregator,
+            let mut decode_compact = |nonzero: bool,
                                       aggregate: TypedBufferRef,
                                       t: Type| {
-                let compacted = match aggregator {
-                    // TODO(clemens): if summation column is strictly positive, can use NonzeroCompact
-                    Aggregator::Sum => planner.compact(aggregate, selector),
-                    Aggregator::Count => planner.nonzero_compact(aggregate),
+                // TODO(clemens): if summation column is strictly positive, can use NonzeroCompact
+                let compacted = if nonzero {
+                    planner.nonzero_compact(aggregate)
+                } else {
+                    planner.compact(aggregate, selector)
                 };
                 if t.is_encoded() {
                     Ok(t.codec.clone().unwrap().decode(compacted, &mut planner))
@@ -229,17 +401,17 @@ impl NormalFormQuery {
                 }
             };
 
-            for (i, &(aggregator, aggregate, ref t)) in aggregation_results.iter().enumerate() {
+            for (i, &(aggregator, aggregate, ref t, nonzero)) in aggregation_results.iter().enumerate() {
                 if selector_index != Some(i) {
-                    let decode_compacted = decode_compact(aggregator, aggregate, t.clone())?;
+                    let decode_compacted = decode_compact(nonzero, aggregate, t.clone())?;
                     aggregation_cols.push((decode_compacted, aggregator))
                 }
             }
 
             // TODO(clemens): is there a simpler way to do this?
             if let Some(i) = selector_index {
-                let (aggregator, aggregate, ref t) = aggregation_results[i];
-                let selector = decode_compact(aggregator, aggregate, t.clone())?;
+                let (aggregator, aggregate, ref t, nonzero) = aggregation_results[i];
+                let selector = decode_compact(nonzero, aggregate, t.clone())?;
                 aggregation_cols.insert(i, (selector, aggregator));
             }
         }
@@ -250,6 +422,13 @@ impl NormalFormQuery {
             let decoded = decode_plan.clone();
             grouping_columns.push(decoded);
         }
+        if dictionary_entries.is_some() {
+            grouping_columns.push(encoded_group_by_column);
+        }
+        // Codes have the same type in all partitions, independently of the size of the dictionary when they were created
+        if self.group_by_codes {
+            grouping_columns.push(planner.cast(encoded_group_by_column, EncodingType::I64));
+        }
 
         // If the grouping is not order preserving, we need to sort all output columns by using the ordering constructed from the decoded group by columns
         // This is necessary to make it possible to efficiently merge with other batch results
@@ -261,17 +440,21 @@ impl NormalFormQuery {
                                 false /* desc */,
                                 false /* stable */)
             } else {
-                if grouping_columns.len() != 1 {
-                    // TODO(clemens): debug print `planner`
-                    bail!(QueryError::NotImplemented,
-                        "Grouping key is not order preserving and more than 1 grouping column\nGrouping key type: {:?}\nTODO: PLANNER",
-                        &grouping_key_type )
+                // Lexicographic sort by all grouping columns, starting with the least significant one
+                let mut sort_indices = None;
+                for &s in grouping_columns.iter().rev() {
+                    sort_indices = Some(match sort_indices {
+                        None => {
+                            let indices = planner.indices(s);
+                            planner.sort_by(s, indices, false /* desc */, false /* stable */)
+                        }
+                        Some(indices) => planner.sort_by(s, indices, false /* desc */, true /* stable */),
+                    });
+                }
+                match sort_indices {
+                    Some(sort_indices) => sort_indices,
+                    None => bail!(QueryError::NotImplemented, "Grouping key is not order preserving and there are no grouping columns"),
                 }
-                let indices = planner.indices(grouping_columns[0]);
-                planner.sort_by(grouping_columns[0],
-                                indices,
-                                false /* desc */,
-                                false /* stable */)
             };
 
             let mut aggregations2 = Vec::new();
@@ -295,7 +478,11 @@ impl NormalFormQuery {
         let mut executor = planner.prepare(vec![])?;
         let mut results = executor.prepare(NormalFormQuery::column_data(columns));
         debug!("{:#}", &executor);
-        executor.run(columns.iter().next().map(|c| c.1.len()).unwrap_or(1), &mut results, show);
+        drop(planning);
+        let _running = trace_span!("Run partition {}", partition);
+        executor.set_limits(limits.clone());
+        executor.set_batch_size(batch_size);
+        executor.run(columns.iter().next().map(|c| c.1.len()).unwrap_or(1), &mut results, show)?;
         let (columns, projection, aggregations, _) = results.collect_aliased(
             &grouping_columns.iter().map(|s| s.any()).collect::<Vec<_>>(),
             &aggregation_cols.iter().map(|&(s, aggregator)| (s.any(), aggregator)).collect::<Vec<_>>(),
@@ -318,17 +505,186 @@ impl NormalFormQuery {
         } else {
             Ok((
                 batch,
-                if explain { Some(format!("{}", executor)) } else { None }
+                if explain { Some(executor.plan_graph()) } else { None }
             ))
         }
     }
 
+    /// Filters that are always true or always false are resolved without evaluating any rows.
+    fn compile_filter(&self,
+                      columns: &HashMap<String, Arc<DataSource>>,
+                      partition: usize,
+                      partition_length: usize,
+                      planner: &mut QueryPlanner) -> Result<Filter, QueryError> {
+        // Rows that are not part of the sample are excluded before any predicates are evaluated
+        let sampled = match self.row_sample {
+            Some(RowSample { fraction, seed }) => {
+                let seed = seed ^ partition as u64;
+                Filter::U8(planner.sample_rows(partition_length, OrderedF64(fraction), seed))
+            }
+            None => Filter::None,
+        };
+        match self.filter {
+            Expr::Const(RawVal::Int(0)) | Expr::Const(RawVal::Null) => {
+                let empty = planner.null_vec(0, EncodingType::Null);
+                Ok(Filter::Indices(planner.indices(empty)))
+            }
+            Expr::Const(_) => Ok(sampled),
+            ref filter => {
+                // Evaluate the most selective predicates first, once few enough rows are expected to remain the
+                // remaining predicates are only evaluated on the rows that passed all previous ones
+                let mut predicates = NormalFormQuery::pair_range_bounds(filter.conjuncts()).into_iter()
+                    .map(|predicate| (NormalFormQuery::estimate_selectivity(&predicate, columns), predicate))
+                    .collect::<Vec<_>>();
+                predicates.sort_by(|&(a, _), &(b, _)| a.partial_cmp(&b).unwrap_or(cmp::Ordering::Equal));
+
+                let mut result = sampled;
+                let mut stage: Option<Expr> = None;
+                let mut remaining = 1.0;
+                for (selectivity, predicate) in predicates {
+                    if remaining <= SHORT_CIRCUIT_SELECTIVITY {
+                        if let Some(stage) = stage.take() {
+                            result = NormalFormQuery::apply_predicate(&stage, result, columns, partition_length, planner)?;
+                        }
+                    }
+                    stage = Some(match stage.take() {
+                        None => predicate,
+                        Some(stage) => Expr::func(Func2Type::And, stage, predicate),
+                    });
+                    remaining *= selectivity;
+                }
+                match stage {
+                    Some(stage) => NormalFormQuery::apply_predicate(&stage, result, columns, partition_length, planner),
+                    None => Ok(result),
+                }
+            }
+        }
+    }
+
+    /// Combines each lower bound on an expression with an upper bound on the same expression into a single
+    /// conjunction, so that they stay adjacent and can be evaluated as one range check.
+    fn pair_range_bounds(predicates: Vec<&Expr>) -> Vec<Expr> {
+        let mut paired = vec![false; predicates.len()];
+        let mut result = Vec::with_capacity(predicates.len());
+        for i in 0..predicates.len() {
+            if paired[i] {
+                continue;
+            }
+            if let Some((expr, lower, _)) = predicates[i].range_bound() {
+                let key = format!("{:?}", expr);
+                let matching = (i + 1..predicates.len()).find(|&j| !paired[j] && match predicates[j].range_bound() {
+                    Some((other, other_lower, _)) => other_lower != lower && format!("{:?}", other) == key,
+                    None => false,
+                });
+                if let Some(j) = matching {
+                    paired[j] = true;
+                    result.push(Expr::func(Func2Type::And, predicates[i].clone(), predicates[j].clone()));
+                    continue;
+                }
+            }
+            result.push(predicates[i].clone());
+        }
+        result
+    }
+
+    /// Estimated fraction of rows in the partition for which `predicate` is true.
+    fn estimate_selectivity(predicate: &Expr, columns: &HashMap<String, Arc<DataSource>>) -> f64 {
+        catalog::selectivity(predicate, &|name| match columns.get(name) {
+            Some(column) => ColumnStats::of_data_source(&**column),
+            None => ColumnStats::unknown(),
+        })
+    }
+
+    /// Restricts `filter` to the rows for which `predicate` is true.
+    fn apply_predicate(predicate: &Expr,
+                       filter: Filter,
+                       columns: &HashMap<String, Arc<DataSource>>,
+                       partition_length: usize,
+                       planner: &mut QueryPlanner) -> Result<Filter, QueryError> {
+        let indices = match filter {
+            Filter::None => {
+                let (plan, _) = QueryPlan::compile_expr(predicate, Filter::None, columns, planner)?;
+                return Ok(match plan.tag {
+                    EncodingType::U8 => Filter::U8(plan.u8()?),
+                    EncodingType::NullableU8 => Filter::NullableU8(plan.nullable_u8()?),
+                    _ => Filter::None,
+                });
+            }
+            Filter::U8(where_true) => {
+                let buffer = planner.null_vec(partition_length, EncodingType::Null);
+                let indices = planner.indices(buffer).into();
+                planner.filter(indices, where_true).usize()?
+            }
+            Filter::NullableU8(where_true) => {
+                let buffer = planner.null_vec(partition_length, EncodingType::Null);
+                let indices = planner.indices(buffer).into();
+                planner.nullable_filter(indices, where_true).usize()?
+            }
+            Filter::Indices(indices) => indices,
+        };
+        let (plan, _) = QueryPlan::compile_expr(predicate, Filter::Indices(indices), columns, planner)?;
+        Ok(Filter::Indices(match plan.tag {
+            EncodingType::U8 => planner.filter(indices.into(), plan.u8()?).usize()?,
+            EncodingType::NullableU8 => planner.nullable_filter(indices.into(), plan.nullable_u8()?).usize()?,
+            _ => indices,
+        }))
+    }
+
     fn column_data(columns: &HashMap<String, Arc<DataSource>>) -> HashMap<String, Vec<&Data>> {
         columns.iter()
             .map(|(name, column)| (name.to_string(), column.data_sections()))
             .collect()
     }
 
+    /// Extends the filter to exclude rows that are marked as deleted by the `DELETED_COL` column.
+    pub fn exclude_deleted(&self) -> NormalFormQuery {
+        let not_deleted = Expr::Func2(Func2Type::Equals,
+                                      Box::new(Expr::ColName(DELETED_COL.to_string())),
+                                      Box::new(Expr::Const(RawVal::Int(0))));
+        let filter = match self.filter {
+            Expr::Const(RawVal::Int(1)) => not_deleted,
+            ref filter => Expr::Func2(Func2Type::And, Box::new(filter.clone()), Box::new(not_deleted)),
+        };
+        NormalFormQuery { filter, ..self.clone() }
+    }
+
+    /// Replaces every column name with the expression returned by `f`.
+    pub fn map_colnames<F>(self, f: &mut F) -> Result<NormalFormQuery, QueryError>
+        where F: FnMut(String) -> Result<Expr, QueryError> {
+        Ok(NormalFormQuery {
+            projection: self.projection.into_iter()
+                .map(|expr| expr.map_colnames(f))
+                .collect::<Result<_, _>>()?,
+            filter: self.filter.map_colnames(f)?.fold_constants(),
+            aggregate: self.aggregate.into_iter()
+                .map(|(aggregator, expr)| expr.map_colnames(f).map(|expr| (aggregator, expr)))
+                .collect::<Result<_, _>>()?,
+            order_by: self.order_by.into_iter()
+                .map(|(expr, desc)| expr.map_colnames(f).map(|expr| (expr, desc)))
+                .collect::<Result<_, _>>()?,
+            ..self
+        })
+    }
+
+    /// Indices of the result columns that the merged result of an aggregation query is sorted by.
+    pub fn aggregate_ordering(&self) -> Vec<(usize, bool)> {
+        if self.aggregate.is_empty() {
+            return vec![];
+        }
+        self.order_by.iter()
+            .filter_map(|(expr, desc)| {
+                let expr = format!("{:?}", expr);
+                self.projection.iter()
+                    .position(|selected| format!("{:?}", selected) == expr)
+                    .or_else(|| self.aggregate.iter()
+                        .position(|(aggregator, selected)|
+                            format!("{:?}", Expr::Aggregate(*aggregator, Box::new(selected.clone()))) == expr)
+                        .map(|i| i + self.projection.len()))
+                    .map(|i| (i, *desc))
+            })
+            .collect()
+    }
+
     pub fn result_column_names(&self) -> Vec<String> {
         let mut anon_columns = -1;
         let select_cols = self.projection
@@ -349,6 +705,13 @@ impl NormalFormQuery {
                 match agg {
                     Aggregator::Count => format!("count_{}", anon_aggregates),
                     Aggregator::Sum => format!("sum_{}", anon_aggregates),
+                    Aggregator::ApproxCountDistinct => format!("approx_count_distinct_{}", anon_aggregates),
+                    Aggregator::Percentile => format!("percentile_{}", anon_aggregates),
+                    Aggregator::Histogram => format!("histogram_{}", anon_aggregates),
+                    Aggregator::ArgMax => format!("arg_max_{}", anon_aggregates),
+                    Aggregator::ArgMin => format!("arg_min_{}", anon_aggregates),
+                    Aggregator::Min => format!("min_{}", anon_aggregates),
+                    Aggregator::Max => format!("max_{}", anon_aggregates),
                 }
             });
 
@@ -376,7 +739,22 @@ impl Query {
             }
         }
 
-        let require_final_pass = (!aggregate.is_empty() && !self.order_by.is_empty())
+        // DISTINCT is lowered to a grouping by all selected columns with a dummy aggregate that is dropped in the final pass
+        let distinct_only = self.distinct && aggregate.is_empty();
+        if distinct_only {
+            aggregate.push((Aggregator::Count, Expr::Const(RawVal::Int(0))));
+        }
+
+        // Aggregation results ordered by selected columns or aggregates are sorted after merging without a final pass
+        let order_by_selected = self.order_by.iter().all(|(expr, _)| {
+            let expr = format!("{:?}", expr);
+            select.iter().any(|selected| format!("{:?}", selected) == expr)
+                || aggregate.iter().any(|(aggregator, selected)|
+                format!("{:?}", Expr::Aggregate(*aggregator, Box::new(selected.clone()))) == expr)
+        });
+
+        let require_final_pass = distinct_only
+            || (!aggregate.is_empty() && !self.order_by.is_empty() && !order_by_selected)
             || final_projection.iter()
             .any(|expr| match expr {
                 Expr::ColName(_) => false,
@@ -387,7 +765,16 @@ impl Query {
             let mut final_order_by = Vec::new();
             for (expr, desc) in &self.order_by {
                 let (full_expr, aggregates) = Query::extract_aggregators(expr, &mut aggregate_colnames);
-                if aggregates.is_empty() {
+                let selected = if distinct_only {
+                    // Ordering by a column that is not selected would change which rows are distinct
+                    let expr = format!("{:?}", full_expr);
+                    select.iter().position(|selected| format!("{:?}", selected) == expr)
+                } else {
+                    None
+                };
+                if let Some(index) = selected {
+                    final_order_by.push((Expr::ColName(select_colnames[index].clone()), *desc));
+                } else if aggregates.is_empty() {
                     let column_name = format!("_cs{}", select_colnames.len());
                     select_colnames.push(column_name.clone());
                     select.push(full_expr);
@@ -400,10 +787,14 @@ impl Query {
             (
                 NormalFormQuery {
                     projection: select,
-                    filter: self.filter.clone(),
+                    filter: self.filter.clone().fold_constants(),
                     aggregate,
                     order_by: vec![],
                     limit: self.limit.clone(),
+                    sum_overflow: self.sum_overflow.unwrap_or_default(),
+                    row_sample: self.sample.and_then(|sample| sample.row_sample()),
+                    stable_sort: false,
+                    group_by_codes: false,
                 },
                 Some(NormalFormQuery {
                     projection: final_projection,
@@ -411,16 +802,24 @@ impl Query {
                     aggregate: vec![],
                     order_by: final_order_by,
                     limit: self.limit.clone(),
+                    sum_overflow: self.sum_overflow.unwrap_or_default(),
+                    row_sample: None,
+                    stable_sort: false,
+                    group_by_codes: false,
                 }),
             )
         } else {
             (
                 NormalFormQuery {
                     projection: select,
-                    filter: self.filter.clone(),
+                    filter: self.filter.clone().fold_constants(),
                     aggregate,
                     order_by: self.order_by.clone(),
                     limit: self.limit.clone(),
+                    sum_overflow: self.sum_overflow.unwrap_or_default(),
+                    row_sample: self.sample.and_then(|sample| sample.row_sample()),
+                    stable_sort: false,
+                    group_by_codes: false,
                 },
                 None,
             )
@@ -432,34 +831,192 @@ impl Query {
             Expr::Aggregate(aggregator, expr) => {
                 let column_name = format!("_ca{}", column_names.len());
                 column_names.push(column_name.clone());
-                // TODO(clemens): ensure no nested aggregates
                 (Expr::ColName(column_name), vec![(*aggregator, *expr.clone())])
             }
             Expr::Func1(t, expr) => {
                 let (expr, aggregates) = Query::extract_aggregators(expr, column_names);
                 (Expr::Func1(*t, Box::new(expr)), aggregates)
             }
+            Expr::In(expr, values) => {
+                let (expr, aggregates) = Query::extract_aggregators(expr, column_names);
+                (Expr::In(Box::new(expr), values.clone()), aggregates)
+            }
             Expr::Func2(t, expr1, expr2) => {
                 let (expr1, mut aggregates1) = Query::extract_aggregators(expr1, column_names);
                 let (expr2, aggregates2) = Query::extract_aggregators(expr2, column_names);
                 aggregates1.extend(aggregates2);
                 (Expr::Func2(*t, Box::new(expr1), Box::new(expr2)), aggregates1)
             }
-            Expr::Const(_) | Expr::ColName(_) => (expr.clone(), vec![]),
+            Expr::Func(name, args) => {
+                let (args, aggregates) = Query::extract_aggregators_all(args, column_names);
+                (Expr::Func(name.clone(), args), aggregates)
+            }
+            Expr::Udf(function, args) => {
+                let (args, aggregates) = Query::extract_aggregators_all(args, column_names);
+                (Expr::Udf(function.clone(), args), aggregates)
+            }
+            // Window functions are evaluated on the query result and replaced by their inputs before normalization
+            Expr::Window(_) | Expr::Filtered(_, _) | Expr::Const(_) | Expr::ColName(_) => (expr.clone(), vec![]),
         }
     }
 
-    pub fn is_select_star(&self) -> bool {
-        if self.select.len() == 1 {
-            match self.select[0] {
-                Expr::ColName(ref colname) if colname == "*" => true,
-                _ => false,
+    fn extract_aggregators_all(exprs: &[Expr], column_names: &mut Vec<String>) -> (Vec<Expr>, Vec<(Aggregator, Expr)>) {
+        let mut result = Vec::with_capacity(exprs.len());
+        let mut aggregates = Vec::new();
+        for expr in exprs {
+            let (expr, expr_aggregates) = Query::extract_aggregators(expr, column_names);
+            result.push(expr);
+            aggregates.extend(expr_aggregates);
+        }
+        (result, aggregates)
+    }
+
+    /// Rejects aggregates in the filter, aggregates nested inside other aggregates and references to columns which are
+    /// not in `table_cols`. Columns are not checked if the table has no columns.
+    pub fn validate(&self, table_cols: &[String]) -> Result<(), QueryError> {
+        if let Some(aggregate) = self.filter.find_aggregate() {
+            bail!(QueryError::InvalidQuery, "Aggregate {} is not allowed in WHERE clause", aggregate)
+        }
+        for expr in self.select.iter().chain(self.order_by.iter().map(|(expr, _)| expr)) {
+            if let Some((outer, inner)) = Query::find_nested_aggregate(expr) {
+                bail!(QueryError::InvalidQuery, "Aggregate {} is nested inside aggregate {}", inner, outer)
             }
-        } else {
-            false
+        }
+        if let Some(keyed) = self.select.iter().filter_map(Query::find_keyed_aggregate).next() {
+            if self.rollup {
+                bail!(QueryError::NotImplemented, "{} with GROUP BY ROLLUP", keyed)
+            }
+            if self.select.iter().any(|expr| match *expr { Expr::Window(_) => true, _ => false }) {
+                bail!(QueryError::NotImplemented, "{} together with window functions", keyed)
+            }
+        }
+        if !table_cols.is_empty() {
+            let mut missing = self.find_referenced_cols().into_iter()
+                .filter(|colname| !table_cols.contains(colname))
+                .collect::<Vec<_>>();
+            missing.sort();
+            if let Some(column) = missing.into_iter().next() {
+                return Err(QueryError::UnknownColumn {
+                    suggestion: Suggestion::closest(&column, table_cols),
+                    column,
+                    table: self.table.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Outermost aggregate that contains another aggregate and the aggregate it contains.
+    fn find_nested_aggregate(expr: &Expr) -> Option<(&Expr, &Expr)> {
+        match *expr {
+            Expr::Aggregate(_, ref arg) => arg.find_aggregate().map(|inner| (expr, inner)),
+            _ => expr.children().into_iter().filter_map(Query::find_nested_aggregate).next(),
+        }
+    }
+
+    /// `ARG_MAX` or `ARG_MIN` aggregate contained in `expr`.
+    fn find_keyed_aggregate(expr: &Expr) -> Option<&Expr> {
+        match *expr {
+            Expr::Aggregate(Aggregator::ArgMax, _) | Expr::Aggregate(Aggregator::ArgMin, _) => Some(expr),
+            _ => expr.children().into_iter().filter_map(Query::find_keyed_aggregate).next(),
         }
     }
 
+    /// Replaces references to generated columns with the expressions that compute them. Stored columns are read from the
+    /// partitions that contain them, `table_cols` are the columns that exist in at least one partition.
+    pub fn expand_generated_columns(&mut self, generated: &[GeneratedColumn], table_cols: &[String]) -> Result<(), QueryError> {
+        if generated.is_empty() {
+            return Ok(());
+        }
+        let mut rewrite = |name: String| -> Result<Expr, QueryError> {
+            Ok(match generated.iter().find(|column| column.name == name) {
+                Some(column) if column.stored && table_cols.contains(&name) =>
+                    Expr::func(Func2Type::Coalesce, Expr::ColName(name), column.expr.clone()),
+                Some(column) => column.expr.clone(),
+                None => Expr::ColName(name),
+            })
+        };
+        // Selected generated columns keep their name
+        let mut select = Vec::with_capacity(self.select.len());
+        let mut aliases = Vec::with_capacity(self.select.len());
+        let selected = self.select.drain(..)
+            .zip(self.aliases.drain(..).chain(::std::iter::repeat(None)));
+        for (expr, alias) in selected {
+            let alias = match (alias, &expr) {
+                (None, &Expr::ColName(ref name)) if generated.iter().any(|column| &column.name == name) => Some(name.clone()),
+                (alias, _) => alias,
+            };
+            select.push(expr.map_colnames(&mut rewrite)?);
+            aliases.push(alias);
+        }
+        self.select = select;
+        self.aliases = aliases;
+        let filter = mem::replace(&mut self.filter, Expr::Const(RawVal::Null));
+        self.filter = filter.map_colnames(&mut rewrite)?;
+        self.order_by = self.order_by.drain(..)
+            .map(|(expr, desc)| expr.map_colnames(&mut rewrite).map(|expr| (expr, desc)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(())
+    }
+
+    /// Replaces each `Wildcard` in the select clause with the matching columns of the table in alphabetical order.
+    pub fn expand_wildcards(&mut self, table_cols: &[String]) -> Result<(), QueryError> {
+        let mut table_cols = table_cols.to_vec();
+        table_cols.sort();
+        let mut select = Vec::with_capacity(self.select.len());
+        let mut aliases = Vec::with_capacity(self.select.len());
+        let selected = self.select.drain(..)
+            .zip(self.aliases.drain(..).chain(::std::iter::repeat(None)));
+        for (expr, alias) in selected {
+            let wildcard = match expr {
+                Expr::ColName(ref colname) => Wildcard::parse(colname),
+                _ => None,
+            };
+            match wildcard {
+                Some(wildcard) => {
+                    for except in &wildcard.except {
+                        if !table_cols.is_empty() && !table_cols.contains(except) {
+                            return Err(QueryError::UnknownColumn {
+                                suggestion: Suggestion::closest(except, &table_cols),
+                                column: except.clone(),
+                                table: self.table.clone(),
+                            });
+                        }
+                    }
+                    let matching = table_cols.iter()
+                        .filter(|col| wildcard.matches(col))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if matching.is_empty() && !table_cols.is_empty() {
+                        bail!(QueryError::InvalidQuery, "No column of table {} matches {}", self.table, wildcard)
+                    }
+                    aliases.extend(matching.iter().map(|_| None));
+                    select.extend(matching.into_iter().map(Expr::ColName));
+                }
+                None => {
+                    select.push(expr);
+                    aliases.push(alias);
+                }
+            }
+        }
+        self.select = select;
+        self.aliases = aliases;
+        Ok(())
+    }
+
+    /// Query that a node of a cluster runs on its shard of the data. Aggregations return all groups and rows are
+    /// returned up to the end of the limit, so that the coordinator can merge the results and apply the limit.
+    pub fn partial(mut self) -> Query {
+        let aggregates = self.distinct
+            || self.select.iter().any(|expr| !Query::extract_aggregators(expr, &mut vec![]).1.is_empty());
+        self.limit = if aggregates {
+            LimitClause { limit: i64::MAX as u64, offset: 0 }
+        } else {
+            LimitClause { limit: self.limit.limit.saturating_add(self.limit.offset), offset: 0 }
+        };
+        self
+    }
+
     pub fn find_referenced_cols(&self) -> HashSet<String> {
         let mut colnames = HashSet::new();
         for expr in &self.select {
@@ -471,6 +1028,63 @@ impl Query {
         self.filter.add_colnames(&mut colnames);
         colnames
     }
+
+    /// Computes the column names and the single row of results of queries that select only `count` and the `min` or
+    /// `max` of integer columns over the whole table from the row counts and zone maps of `partitions`. Returns `None`
+    /// if the query needs to scan the partitions.
+    pub fn answer_from_statistics(&self, partitions: &[Arc<Partition>]) -> Option<(Vec<String>, Vec<RawVal>)> {
+        let unfiltered = match self.filter {
+            Expr::Const(RawVal::Int(1)) => true,
+            _ => false,
+        };
+        if !unfiltered || self.distinct || self.rollup || self.sample.is_some() || !self.order_by.is_empty()
+            || self.limit.limit == 0 || self.limit.offset > 0 || partitions.is_empty() {
+            return None;
+        }
+        let has_column = |name: &str| partitions.iter().any(|p| p.col_names().contains(&name));
+        // Zone maps include deleted rows, so they are only used if no rows were deleted
+        let no_deletions = partitions.iter().all(|p| p.deleted_rows() == 0);
+        let mut row = Vec::with_capacity(self.select.len());
+        for expr in &self.select {
+            let value = match *expr {
+                Expr::Aggregate(Aggregator::Count, box Expr::Const(ref value)) if *value != RawVal::Null =>
+                    partitions.iter().map(|p| p.len() - p.deleted_rows()).sum::<usize>() as i64,
+                Expr::Aggregate(Aggregator::Count, box Expr::ColName(ref name)) if no_deletions && has_column(&name[..]) => {
+                    let mut count = 0;
+                    for p in partitions {
+                        count += p.len() - p.zone_map(name).null_count?;
+                    }
+                    count as i64
+                }
+                Expr::Aggregate(aggregator, box Expr::ColName(ref name))
+                if (aggregator == Aggregator::Min || aggregator == Aggregator::Max) && no_deletions && has_column(&name[..]) => {
+                    let mut extreme = None;
+                    for p in partitions {
+                        let zone_map = p.zone_map(name);
+                        if zone_map.null_count != Some(0) {
+                            return None;
+                        }
+                        let (min, max) = zone_map.range?;
+                        let value = if aggregator == Aggregator::Min { min } else { max };
+                        extreme = Some(match extreme {
+                            Some(extreme) => aggregator.combine_i64(extreme, value)?,
+                            None => value,
+                        });
+                    }
+                    extreme?
+                }
+                _ => return None,
+            };
+            row.push(RawVal::Int(value));
+        }
+        let mut colnames = self.normalize().0.result_column_names();
+        for (colname, alias) in colnames.iter_mut().zip(&self.aliases) {
+            if let Some(ref alias) = *alias {
+                *colname = alias.clone();
+            }
+        }
+        Some((colnames, row))
+    }
 }
 
 
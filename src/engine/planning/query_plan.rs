@@ -1,17 +1,21 @@
+use std::cmp;
 use std::collections::HashMap;
 use std::i64;
 use std::result::Result;
 use std::sync::Arc;
+use std::usize;
 
 use chrono::{Datelike, NaiveDateTime};
 use regex::Regex;
 
 use ::QueryError;
 use engine::*;
+use engine::operators::functions::{date_trunc, extract, float_to_int, parse_float, parse_int};
 use ingest::raw_val::RawVal;
 use mem_store::*;
 use mem_store::column::DataSource;
 use syntax::expression::*;
+use udf::ScalarFunction;
 use locustdb_derive::ASTBuilder;
 
 
@@ -75,6 +79,31 @@ pub enum QueryPlan {
         #[output]
         decoded: BufferRef<Scalar<i64>>,
     },
+    /// Determines the dictionary index bounding a range comparison with a string constant.
+    DictBound {
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        constant: BufferRef<Scalar<&'static str>>,
+        at_most: bool,
+        #[output]
+        bound: BufferRef<Scalar<i64>>,
+    },
+    /// Outputs a byte for each entry of a string dictionary which is 1 if `comparison` of the entry with `constant` holds.
+    CompareDictionary {
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        constant: BufferRef<Scalar<&'static str>>,
+        comparison: Func2Type,
+        #[output]
+        matches: BufferRef<u8>,
+    },
+    /// Outputs all entries of a string dictionary in order of their dictionary index.
+    DictionaryEntries {
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        #[output]
+        entries: BufferRef<&'static str>,
+    },
     /// Casts `input` to the specified type.
     Cast {
         input: TypedBufferRef,
@@ -88,6 +117,13 @@ pub enum QueryPlan {
         #[output(t = "base=provided")]
         decoded: TypedBufferRef,
     },
+    /// Zstd decodes `bytes` into `decoded_len` elements of type `t`.
+    ZstdDecode {
+        bytes: BufferRef<u8>,
+        decoded_len: usize,
+        #[output(t = "base=provided")]
+        decoded: TypedBufferRef,
+    },
     /// Decodes a byte array of tightly packed strings.
     UnpackStrings {
         bytes: BufferRef<u8>,
@@ -110,9 +146,16 @@ pub enum QueryPlan {
         #[output]
         delta_decoded: BufferRef<i64>,
     },
+    /// Repeats each of `values` the number of times given by the corresponding entry in `run_lengths`.
+    RunLengthDecode {
+        values: TypedBufferRef,
+        run_lengths: BufferRef<u32>,
+        #[output(t = "base=values")]
+        decoded: TypedBufferRef,
+    },
     HashMapGrouping {
         raw_grouping_key: TypedBufferRef,
-        max_cardinality: usize,
+        expected_cardinality: usize,
         #[output(t = "base=raw_grouping_key")]
         unique: TypedBufferRef,
         #[output]
@@ -190,6 +233,58 @@ pub enum QueryPlan {
         #[output]
         count: BufferRef<i64>,
     },
+    SumF64 {
+        grouping_key: TypedBufferRef,
+        plan: BufferRef<OrderedF64>,
+        max_index: BufferRef<Scalar<i64>>,
+        #[output]
+        sum: BufferRef<OrderedF64>,
+    },
+    MergeRegisters {
+        grouping_key: TypedBufferRef,
+        plan: BufferRef<i64>,
+        max_index: BufferRef<Scalar<i64>>,
+        #[output]
+        merged: BufferRef<i64>,
+    },
+    /// Minimum of `plan` for each group, or maximum if `max` is set.
+    Extremum {
+        grouping_key: TypedBufferRef,
+        plan: BufferRef<i64>,
+        max_index: BufferRef<Scalar<i64>>,
+        max: bool,
+        #[output]
+        extremes: BufferRef<i64>,
+    },
+    QuantileSketch {
+        grouping_key: TypedBufferRef,
+        plan: BufferRef<i64>,
+        max_index: BufferRef<Scalar<i64>>,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output]
+        sketches: BufferRef<&'static str>,
+    },
+    /// Value of `payload` in the row with the largest (or smallest) `key` of each group, together with the key.
+    ArgExtremum {
+        grouping_key: TypedBufferRef,
+        key: TypedBufferRef,
+        payload: TypedBufferRef,
+        max_index: BufferRef<Scalar<i64>>,
+        max: bool,
+        #[output(t = "base=provided")]
+        selected: TypedBufferRef,
+    },
+    /// Serialized histogram of the bucket indices in `plan` for each group.
+    Histogram {
+        grouping_key: TypedBufferRef,
+        plan: BufferRef<i64>,
+        max_index: BufferRef<Scalar<i64>>,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output]
+        histograms: BufferRef<&'static str>,
+    },
     LessThan {
         lhs: TypedBufferRef,
         rhs: TypedBufferRef,
@@ -266,12 +361,242 @@ pub enum QueryPlan {
         #[output(t = "base=i64;null=timestamp")]
         year: TypedBufferRef,
     },
+    /// Truncates each timestamp in `timestamp` to the start of the `unit` it falls into.
+    DateTrunc {
+        timestamp: BufferRef<i64>,
+        unit: TimeUnit,
+        #[output]
+        truncated: BufferRef<i64>,
+    },
+    /// Extracts the `unit` field (e.g. the hour of the day) from each timestamp in `timestamp`.
+    Extract {
+        timestamp: BufferRef<i64>,
+        unit: TimeUnit,
+        #[output]
+        extracted: BufferRef<i64>,
+    },
+    /// Replaces each element of `input` with a 64 bit hash of its value.
+    Hash {
+        input: TypedBufferRef,
+        #[output]
+        hashed: BufferRef<i64>,
+    },
+    /// Replaces each element of `input` with a fixed placeholder string.
+    Redact {
+        input: TypedBufferRef,
+        #[output]
+        redacted: BufferRef<&'static str>,
+    },
+    /// Truncates each string in `input` to at most `length` characters.
+    Truncate {
+        input: BufferRef<&'static str>,
+        length: usize,
+        #[output]
+        truncated: BufferRef<&'static str>,
+    },
+    /// Substring of each string in `input` starting at character `start` with at most `length` characters.
+    Substring {
+        input: BufferRef<&'static str>,
+        start: usize,
+        length: usize,
+        #[output]
+        substring: BufferRef<&'static str>,
+    },
+    /// Number of characters in each string in `input`.
+    Length {
+        input: BufferRef<&'static str>,
+        #[output]
+        length: BufferRef<i64>,
+    },
+    /// Converts each string in `input` to upper case if `upper` is set and to lower case otherwise.
+    ChangeCase {
+        input: BufferRef<&'static str>,
+        upper: bool,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output]
+        changed: BufferRef<&'static str>,
+    },
+    /// Changes the case of each entry of a string dictionary once and looks up the result for each of `indices`.
+    DictChangeCase {
+        indices: TypedBufferRef,
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        upper: bool,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[internal]
+        dictionary: BufferRef<&'static str>,
+        #[output]
+        changed: BufferRef<&'static str>,
+    },
+    /// Concatenates the strings in `lhs` and `rhs`, either of which may be a scalar.
+    Concat {
+        lhs: TypedBufferRef,
+        rhs: TypedBufferRef,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output]
+        concatenated: BufferRef<&'static str>,
+    },
+    /// Applies the arithmetic operation `op` to `lhs` and `rhs`, integer operands are converted to floats.
+    FloatArithmetic {
+        lhs: TypedBufferRef,
+        rhs: TypedBufferRef,
+        op: Func2Type,
+        #[output]
+        arithmetic: BufferRef<OrderedF64>,
+    },
+    /// Compares `lhs` and `rhs` as floats.
+    FloatComparison {
+        lhs: TypedBufferRef,
+        rhs: TypedBufferRef,
+        op: Func2Type,
+        #[output]
+        compared: BufferRef<u8>,
+    },
+    IntToFloat {
+        input: BufferRef<i64>,
+        #[output]
+        float: BufferRef<OrderedF64>,
+    },
+    FloatToInt {
+        input: BufferRef<OrderedF64>,
+        #[output]
+        int: BufferRef<i64>,
+    },
+    /// Parses each string in `input` as an integer.
+    ParseInt {
+        input: BufferRef<&'static str>,
+        #[output]
+        int: BufferRef<i64>,
+    },
+    /// Parses each string in `input` as a float.
+    ParseFloat {
+        input: BufferRef<&'static str>,
+        #[output]
+        float: BufferRef<OrderedF64>,
+    },
+    /// Formats each integer or float in `input` as a string.
+    ToStr {
+        input: TypedBufferRef,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output]
+        string: BufferRef<&'static str>,
+    },
+    /// Tests whether each element of `plan` is null, or whether it is not null if `is_not_null` is set.
+    IsNull {
+        plan: TypedBufferRef,
+        is_not_null: bool,
+        #[output]
+        is_null: BufferRef<u8>,
+    },
+    /// Replaces null elements of `lhs` with the corresponding element of `rhs`.
+    Coalesce {
+        lhs: TypedBufferRef,
+        rhs: TypedBufferRef,
+        #[output(t = "base=provided")]
+        coalesced: TypedBufferRef,
+    },
+    /// Maps each hash in `input` to lane `lane` of a HyperLogLog sketch containing just that hash.
+    HllRegisters {
+        input: BufferRef<i64>,
+        lane: usize,
+        #[output]
+        registers: BufferRef<i64>,
+    },
+    /// Evaluates the user-defined function `function` on each row of `args`.
+    ScalarUdf {
+        name: String,
+        #[nohash]
+        function: Arc<ScalarFunction>,
+        args: Vec<TypedBufferRef>,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output(t = "base=provided")]
+        evaluated: TypedBufferRef,
+    },
+    /// Evaluates the user-defined function `function` once for each dictionary entry and looks up the result for each of `indices`.
+    DictUdf {
+        name: String,
+        #[nohash]
+        function: Arc<ScalarFunction>,
+        indices: TypedBufferRef,
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output(t = "base=provided")]
+        evaluated: TypedBufferRef,
+    },
+    /// Tests whether each element of `plan` is equal to any of the scalar `values`, which are encoded in the same way as `plan`.
+    IsIn {
+        plan: TypedBufferRef,
+        values: Vec<TypedBufferRef>,
+        #[output]
+        is_in: BufferRef<u8>,
+    },
+    /// Tests whether each element of `plan` lies between the scalars `low` and `high` (inclusive), which are encoded in the same way as `plan`.
+    InRange {
+        plan: TypedBufferRef,
+        low: TypedBufferRef,
+        high: TypedBufferRef,
+        #[output]
+        in_range: BufferRef<u8>,
+    },
+    /// Tests whether each element of `plan` is contained in `set`, which holds one byte for each encoded value.
+    IsInSet {
+        plan: TypedBufferRef,
+        set: BufferRef<u8>,
+        #[output]
+        is_in: BufferRef<u8>,
+    },
+    /// Outputs a byte for each entry of a string dictionary which is 1 if the entry matches `regex`.
+    RegexDictionary {
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        regex: String,
+        #[output]
+        matches: BufferRef<u8>,
+    },
     Regex {
         plan: BufferRef<&'static str>,
         regex: String,
         #[output]
         matches: BufferRef<u8>,
     },
+    /// Outputs a byte for each entry of a string dictionary which is 1 if the list it encodes contains `element`.
+    ArrayContainsDictionary {
+        offset_len: BufferRef<u64>,
+        backing_store: BufferRef<u8>,
+        element: String,
+        #[output]
+        matches: BufferRef<u8>,
+    },
+    ArrayContains {
+        plan: BufferRef<&'static str>,
+        element: String,
+        #[output]
+        contains: BufferRef<u8>,
+    },
+    /// Outputs a byte for each list which is 1 if any of its elements, given as dictionary indices, is `code`.
+    ListContains {
+        ends: BufferRef<u32>,
+        elements: TypedBufferRef,
+        code: BufferRef<Scalar<i64>>,
+        #[output]
+        contains: BufferRef<u8>,
+    },
+    /// Serializes each list of `elements`, which ends at the corresponding offset in `ends`, as JSON array.
+    EncodeLists {
+        ends: BufferRef<u32>,
+        elements: BufferRef<&'static str>,
+        #[internal]
+        string_store: BufferRef<u8>,
+        #[output]
+        encoded: BufferRef<&'static str>,
+    },
     /// Outputs a vector of indices from `0..plan.len()`
     Indices {
         plan: TypedBufferRef,
@@ -287,16 +612,27 @@ pub enum QueryPlan {
         #[output]
         permutation: BufferRef<usize>,
     },
-    /// Outputs the `n` largest/smallest elements of `ranking` and their corresponding indices.
+    /// Outputs the `n` largest/smallest elements of `ranking` and their corresponding indices, except for the first
+    /// `offset` of them.
     TopN {
         ranking: TypedBufferRef,
         n: usize,
         desc: bool,
+        offset: usize,
         #[internal(t = "base=ranking")]
         tmp_keys: TypedBufferRef,
         #[output]
         top_n: BufferRef<usize>,
     },
+    /// Outputs the indices of the first `n` rows when ordering by all `rankings`, except for the first `offset` of them.
+    TopNMulti {
+        rankings: Vec<TypedBufferRef>,
+        descending: Vec<bool>,
+        n: usize,
+        offset: usize,
+        #[output]
+        top_n: BufferRef<usize>,
+    },
     /// Outputs all elements in `plan` where the index corresponds to an entry in `indices`.
     Select {
         plan: TypedBufferRef,
@@ -334,6 +670,11 @@ pub enum QueryPlan {
         #[output]
         scalar_i64: BufferRef<Scalar<i64>>,
     },
+    ScalarF64 {
+        value: OrderedF64,
+        #[output]
+        scalar_f64: BufferRef<Scalar<OrderedF64>>,
+    },
     ScalarStr {
         value: String,
         #[internal]
@@ -348,6 +689,14 @@ pub enum QueryPlan {
         #[output(t = "base=provided")]
         expanded: TypedBufferRef,
     },
+    /// Outputs 1 for each of `len` rows that is part of a random sample of `fraction` of the rows.
+    SampleRows {
+        len: usize,
+        fraction: OrderedF64,
+        seed: u64,
+        #[output]
+        sampled: BufferRef<u8>,
+    },
     /// Merges `lhs` and `rhs` and outputs a merge plan .
     Merge {
         lhs: TypedBufferRef,
@@ -430,42 +779,179 @@ pub enum QueryPlan {
     /// Merges `lhs` and `lhs` according to `merge_ops`, combining duplicates.
     MergeAggregate {
         merge_ops: BufferRef<MergeOp>,
-        lhs: BufferRef<i64>,
-        rhs: BufferRef<i64>,
+        lhs: TypedBufferRef,
+        rhs: TypedBufferRef,
         aggregator: Aggregator,
+        #[output(t = "base=lhs")]
+        merged: TypedBufferRef,
+    },
+    MergeQuantileSketches {
+        merge_ops: BufferRef<MergeOp>,
+        lhs: BufferRef<&'static str>,
+        rhs: BufferRef<&'static str>,
+        #[internal]
+        string_store: BufferRef<u8>,
         #[output]
-        merged: BufferRef<i64>,
+        merged: BufferRef<&'static str>,
     },
+    MergeArgExtremum {
+        merge_ops: BufferRef<MergeOp>,
+        lhs: TypedBufferRef,
+        rhs: TypedBufferRef,
+        max: bool,
+        #[output(t = "base=lhs")]
+        merged: TypedBufferRef,
+    },
+}
+
+/// Whether grouping directly by the raw grouping key, which requires arrays of size `max_grouping_key` for every
+/// aggregate, is expected to be cheaper than hash map grouping, which does work proportional to the number of rows.
+pub fn use_dense_grouping(max_grouping_key: i64, expected_rows: usize) -> bool {
+    max_grouping_key < 1 << 12
+        || (max_grouping_key < 1 << 24 && max_grouping_key <= 2 * expected_rows as i64)
 }
 
 pub fn prepare_hashmap_grouping(raw_grouping_key: TypedBufferRef,
-                                max_cardinality: usize,
+                                expected_cardinality: usize,
                                 planner: &mut QueryPlanner)
                                 -> Result<(Option<TypedBufferRef>,
                                            TypedBufferRef,
                                            Type,
                                            BufferRef<Scalar<i64>>), QueryError> {
     let (unique_out, grouping_key_out, cardinality_out) =
-        planner.hash_map_grouping(raw_grouping_key, max_cardinality);
+        planner.hash_map_grouping(raw_grouping_key, expected_cardinality);
     Ok((Some(unique_out),
         grouping_key_out.into(),
         Type::encoded(Codec::opaque(EncodingType::U32, BasicType::Integer, false, false, true, true)),
         cardinality_out))
 }
 
+/// Returns the dictionary indices, the dictionary size and the decoded dictionary if `exprs` is a single column with a
+/// sorted dictionary. Aggregating by dictionary index yields groups in the order of the dictionary, so no grouping key
+/// has to be constructed and the decoded dictionary compacted by the groups that exist is the group by column.
+pub fn dictionary_grouping_key(exprs: &[Expr],
+                               filter: Filter,
+                               columns: &HashMap<String, Arc<DataSource>>,
+                               planner: &mut QueryPlanner)
+                               -> Result<Option<((TypedBufferRef, Type), i64, BufferRef<&'static str>)>, QueryError> {
+    let dictionary_size = match exprs {
+        [Expr::ColName(ref name)] => match columns.get::<str>(name.as_ref()).and_then(|c| c.range()) {
+            Some((_, max)) => max,
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    planner.checkpoint();
+    let (indices, t) = QueryPlan::compile_expr(&exprs[0], filter, columns, planner)?;
+    let dictionary = match t.codec {
+        Some(ref codec) if codec.is_order_preserving() => codec.dictionary(planner),
+        _ => None,
+    };
+    match dictionary {
+        Some((offset_len, backing_store)) => {
+            let entries = planner.dictionary_entries(offset_len, backing_store);
+            Ok(Some(((indices, t), dictionary_size, entries)))
+        }
+        None => {
+            planner.reset();
+            Ok(None)
+        }
+    }
+}
+
+/// Grouping key of a single column that is encoded with a dictionary shared by all partitions, which consists of the
+/// codes of the dictionary. Returns the codes, which are not decoded and grouped in ascending order, together with the
+/// size of the dictionary.
+pub fn shared_dictionary_grouping_key(exprs: &[Expr],
+                                      filter: Filter,
+                                      columns: &HashMap<String, Arc<DataSource>>,
+                                      planner: &mut QueryPlanner)
+                                      -> Result<((TypedBufferRef, Type), i64), QueryError> {
+    let name = match exprs {
+        [Expr::ColName(ref name)] => name,
+        _ => return Err(fatal!("Grouping by codes requires a single group by column, found {:?}", exprs)),
+    };
+    let dictionary_size = match columns.get::<str>(name.as_ref()).and_then(|c| c.range()) {
+        Some((_, max)) => max,
+        None => return Err(fatal!("Column {} has no dictionary", name)),
+    };
+    let (codes, t) = QueryPlan::compile_expr(&exprs[0], filter, columns, planner)?;
+    match t.codec {
+        Some(ref codec) if codec.is_shared_dictionary() => {
+            let t = Type::encoded(Codec::opaque(codes.tag, BasicType::Integer, false, true, true, true));
+            Ok(((codes, t), dictionary_size))
+        }
+        _ => Err(fatal!("Column {} is not encoded with a shared dictionary", name)),
+    }
+}
+
 // TODO(clemens): add QueryPlan::Aggregation and merge with prepare function
 pub fn prepare_aggregation(plan: TypedBufferRef,
                            plan_type: Type,
                            grouping_key: TypedBufferRef,
                            max_index: BufferRef<Scalar<i64>>,
                            aggregator: Aggregator,
+                           sum_overflow: SumOverflow,
                            planner: &mut QueryPlanner)
                            -> Result<(TypedBufferRef, Type), QueryError> {
     Ok(match (aggregator, plan) {
+        // Integers are converted to floats before they are summed, so that the sum can't overflow
+        (Aggregator::Sum, plan) if sum_overflow == SumOverflow::Float && plan_type.decoded == BasicType::Integer => {
+            let nullable = plan.is_nullable();
+            let decoded = match plan_type.codec {
+                Some(ref codec) => codec.decode(plan, planner),
+                None => plan,
+            };
+            let mut decoded = planner.cast(decoded, EncodingType::I64);
+            if nullable {
+                let zero = planner.scalar_i64(0, true);
+                decoded = planner.coalesce(decoded, zero.into(), EncodingType::I64);
+            }
+            let floats = planner.int_to_float(decoded.i64()?);
+            (planner.sum_f64(grouping_key, floats, max_index).into(),
+             Type::unencoded(BasicType::Float))
+        }
+        // Null values are skipped by counting only present values and summing nulls as zero
+        (Aggregator::Count, plan) if plan.is_nullable() => {
+            let present = planner.is_null(plan, true);
+            (planner.sum(grouping_key, present.into(), max_index).into(),
+             Type::unencoded(BasicType::Integer))
+        }
+        (Aggregator::Sum, plan) if plan.is_nullable() && plan_type.decoded == BasicType::Integer => {
+            let decoded = match plan_type.codec {
+                Some(ref codec) => codec.decode(plan, planner),
+                None => plan,
+            };
+            let decoded = planner.cast(decoded, EncodingType::I64);
+            let zero = planner.scalar_i64(0, true);
+            let fused = planner.coalesce(decoded, zero.into(), EncodingType::I64);
+            (planner.sum(grouping_key, fused, max_index).into(),
+             Type::unencoded(BasicType::Integer))
+        }
         (Aggregator::Count, _) => (
             planner.count(grouping_key, max_index).into(),
             Type::encoded(Codec::integer_cast(EncodingType::U32))
         ),
+        (Aggregator::Sum, plan) if plan_type.decoded == BasicType::Float => (
+            planner.sum_f64(grouping_key, plan.f64()?, max_index).into(),
+            Type::unencoded(BasicType::Float)
+        ),
+        (Aggregator::ApproxCountDistinct, _) | (Aggregator::Percentile, _) | (Aggregator::Histogram, _)
+        | (Aggregator::Min, _) | (Aggregator::Max, _)
+        if plan_type.decoded == BasicType::Float =>
+            bail!(QueryError::TypeError, "{:?} is not supported for floats", aggregator),
+        (Aggregator::Min, _) | (Aggregator::Max, _) if plan_type.decoded != BasicType::Integer =>
+            bail!(QueryError::TypeError, "{:?} is only supported for integers, found {:?}", aggregator, plan_type.decoded),
+        (Aggregator::Min, plan) | (Aggregator::Max, plan) if plan.is_nullable() =>
+            bail!(QueryError::NotImplemented, "{:?} of nullable values", aggregator),
+        (Aggregator::Min, mut plan) | (Aggregator::Max, mut plan) => {
+            if plan_type.is_encoded() {
+                plan = plan_type.codec.clone().unwrap().decode(plan, planner);
+            }
+            let plan = planner.cast(plan, EncodingType::I64).i64()?;
+            (planner.extremum(grouping_key, plan, max_index, aggregator == Aggregator::Max).into(),
+             Type::unencoded(BasicType::Integer))
+        }
         (Aggregator::Sum, mut plan) => {
             if !plan_type.is_summation_preserving() {
                 plan = plan_type.codec.clone().unwrap().decode(plan, planner);
@@ -474,9 +960,62 @@ pub fn prepare_aggregation(plan: TypedBufferRef,
             (planner.sum(grouping_key, plan, max_index).into(),
              Type::unencoded(BasicType::Integer))
         }
+        (Aggregator::ApproxCountDistinct, mut plan) => {
+            if plan_type.is_encoded() {
+                plan = plan_type.codec.clone().unwrap().decode(plan, planner);
+            }
+            (planner.merge_registers(grouping_key, plan.i64()?, max_index).into(),
+             Type::unencoded(BasicType::Integer))
+        }
+        (Aggregator::Percentile, mut plan) => {
+            if plan_type.is_encoded() {
+                plan = plan_type.codec.clone().unwrap().decode(plan, planner);
+            }
+            let plan = planner.cast(plan, EncodingType::I64).i64()?;
+            (planner.quantile_sketch(grouping_key, plan, max_index).into(),
+             Type::unencoded(BasicType::String))
+        }
+        (Aggregator::Histogram, mut plan) => {
+            if plan_type.is_encoded() {
+                plan = plan_type.codec.clone().unwrap().decode(plan, planner);
+            }
+            let plan = planner.cast(plan, EncodingType::I64).i64()?;
+            (planner.histogram(grouping_key, plan, max_index).into(),
+             Type::unencoded(BasicType::String))
+        }
+        (Aggregator::ArgMax, _) | (Aggregator::ArgMin, _) =>
+            bail!(QueryError::InvalidQuery, "{:?} requires a value and a key", aggregator),
     })
 }
 
+/// Plans `ARG_MAX` (or `ARG_MIN` if `max` is false) of `payload` by `key`.
+pub fn prepare_arg_extremum((payload, payload_type): (TypedBufferRef, Type),
+                            (key, key_type): (TypedBufferRef, Type),
+                            grouping_key: TypedBufferRef,
+                            max_index: BufferRef<Scalar<i64>>,
+                            max: bool,
+                            planner: &mut QueryPlanner) -> Result<(TypedBufferRef, Type), QueryError> {
+    if key_type.decoded != BasicType::Integer {
+        bail!(QueryError::TypeError, "Key of ARG_MAX and ARG_MIN must be an integer, found {:?}", key_type.decoded)
+    }
+    match payload_type.decoded {
+        BasicType::Integer | BasicType::Float | BasicType::String => {}
+        _ => bail!(QueryError::TypeError, "Value of ARG_MAX and ARG_MIN must be an integer, float or string, found {:?}", payload_type.decoded),
+    }
+    let key = match key_type.codec {
+        Some(ref codec) => codec.decode(key, planner),
+        None => key,
+    };
+    let key = planner.cast(key, EncodingType::I64);
+    let payload = match payload_type.codec {
+        Some(ref codec) => codec.decode(payload, planner),
+        None => payload,
+    };
+    // The key is kept with the selected value until all partial results are merged
+    Ok((planner.arg_extremum(grouping_key, key, payload, max_index, max, EncodingType::KeyedVal),
+        Type::unencoded(BasicType::Val)))
+}
+
 pub fn order_preserving((plan, t): (TypedBufferRef, Type),
                         planner: &mut QueryPlanner) -> (TypedBufferRef, Type) {
     if t.is_order_preserving() {
@@ -518,6 +1057,36 @@ impl Function2 {
             encoding_invariance: true,
         }
     }
+
+    pub fn float_op(factory: Factory, type_lhs: BasicType, type_rhs: BasicType) -> Function2 {
+        Function2 {
+            factory,
+            type_lhs,
+            type_rhs,
+            type_out: Type::unencoded(BasicType::Float).mutable(),
+            encoding_invariance: false,
+        }
+    }
+
+    pub fn float_comparison_op(factory: Factory, type_lhs: BasicType, type_rhs: BasicType) -> Function2 {
+        Function2 {
+            factory,
+            type_lhs,
+            type_rhs,
+            type_out: Type::unencoded(BasicType::Boolean).mutable(),
+            encoding_invariance: false,
+        }
+    }
+
+    pub fn string_op(factory: Factory) -> Function2 {
+        Function2 {
+            factory,
+            type_lhs: BasicType::String,
+            type_rhs: BasicType::String,
+            type_out: Type::unencoded(BasicType::String),
+            encoding_invariance: false,
+        }
+    }
 }
 
 lazy_static! {
@@ -525,7 +1094,7 @@ lazy_static! {
 }
 
 fn function2_registry() -> HashMap<Func2Type, Vec<Function2>> {
-    vec![
+    let mut registry: HashMap<Func2Type, Vec<Function2>> = vec![
         (Func2Type::Add,
          vec![Function2::integer_op(Box::new(|qp, lhs, rhs| qp.add(lhs, rhs).into()))]),
         (Func2Type::Subtract,
@@ -536,6 +1105,8 @@ fn function2_registry() -> HashMap<Func2Type, Vec<Function2>> {
          vec![Function2::integer_op(Box::new(|qp, lhs, rhs| qp.divide(lhs, rhs).into()))]),
         (Func2Type::Modulo,
          vec![Function2::integer_op(Box::new(|qp, lhs, rhs| qp.modulo(lhs, rhs).into()))]),
+        (Func2Type::Concat,
+         vec![Function2::string_op(Box::new(|qp, lhs, rhs| qp.concat(lhs, rhs).into()))]),
         (Func2Type::LT,
          vec![Function2::comparison_op(Box::new(|qp, lhs, rhs| qp.less_than(lhs, rhs).into()),
                                        BasicType::Integer),
@@ -566,11 +1137,62 @@ fn function2_registry() -> HashMap<Func2Type, Vec<Function2>> {
                                        BasicType::Integer),
               Function2::comparison_op(Box::new(|qp, lhs, rhs| qp.not_equals(lhs, rhs).into()),
                                        BasicType::String)]),
-    ].into_iter().collect()
+    ].into_iter().collect();
+
+    // Operations with at least one float operand convert integers to floats
+    let float_operands = [
+        (BasicType::Float, BasicType::Float),
+        (BasicType::Float, BasicType::Integer),
+        (BasicType::Integer, BasicType::Float),
+    ];
+    for &op in &[Func2Type::Add, Func2Type::Subtract, Func2Type::Multiply, Func2Type::Divide, Func2Type::Modulo] {
+        for &(type_lhs, type_rhs) in &float_operands {
+            registry.get_mut(&op).unwrap().push(Function2::float_op(
+                Box::new(move |qp, lhs, rhs| qp.float_arithmetic(lhs, rhs, op).into()), type_lhs, type_rhs));
+        }
+    }
+    for &op in &[Func2Type::LT, Func2Type::LTE, Func2Type::GT, Func2Type::GTE, Func2Type::Equals, Func2Type::NotEquals] {
+        for &(type_lhs, type_rhs) in &float_operands {
+            registry.get_mut(&op).unwrap().push(Function2::float_comparison_op(
+                Box::new(move |qp, lhs, rhs| qp.float_comparison(lhs, rhs, op).into()), type_lhs, type_rhs));
+        }
+    }
+    registry
 }
 
 impl QueryPlan {
     pub fn compile_expr(
+        expr: &Expr,
+        filter: Filter,
+        columns: &HashMap<String, Arc<DataSource>>,
+        planner: &mut QueryPlanner) -> Result<(TypedBufferRef, Type), QueryError> {
+        match *expr {
+            Expr::ColName(_) | Expr::Const(_) => return QueryPlan::compile_expr_unshared(expr, filter, columns, planner),
+            _ => {}
+        }
+        // Expressions that are already computed for all rows (e.g. as part of the filter) are filtered instead of recomputed
+        let key = format!("{:?}", expr);
+        let shared = planner.unfiltered_exprs.get(&key).cloned();
+        if let Some((plan, t)) = shared {
+            if plan.tag.is_scalar() {
+                return Ok((plan, t));
+            }
+            let plan = match filter {
+                Filter::U8(filter) => planner.filter(plan, filter),
+                Filter::NullableU8(filter) => planner.nullable_filter(plan, filter),
+                Filter::Indices(indices) => planner.select(plan, indices),
+                Filter::None => plan,
+            };
+            return Ok((plan, t));
+        }
+        let (plan, t) = QueryPlan::compile_expr_unshared(expr, filter, columns, planner)?;
+        if let Filter::None = filter {
+            planner.unfiltered_exprs.insert(key, (plan, t.clone()));
+        }
+        Ok((plan, t))
+    }
+
+    fn compile_expr_unshared(
         expr: &Expr,
         filter: Filter,
         columns: &HashMap<String, Arc<DataSource>>,
@@ -606,6 +1228,9 @@ impl QueryPlan {
                 (planner.or(plan_lhs, plan_rhs), Type::bit_vec())
             }
             Func2(And, ref lhs, ref rhs) => {
+                if let Some(plan) = QueryPlan::compile_range_check(lhs, rhs, filter, columns, planner)? {
+                    return Ok((plan, Type::bit_vec()));
+                }
                 let (plan_lhs, type_lhs) = QueryPlan::compile_expr(lhs, filter, columns, planner)?;
                 let (plan_rhs, type_rhs) = QueryPlan::compile_expr(rhs, filter, columns, planner)?;
                 if type_lhs.decoded != BasicType::Boolean || type_rhs.decoded != BasicType::Boolean {
@@ -622,15 +1247,91 @@ impl QueryPlan {
                         if t.decoded != BasicType::String {
                             bail!(QueryError::TypeError, "Expected expression of type `String` as first argument to regex. Actual: {:?}", t)
                         }
+                        // Match each dictionary entry once and filter on the set of matching encoded values
+                        if !plan.is_nullable() {
+                            if let Some((offset_len, backing_store)) = t.codec.as_ref().and_then(|c| c.dictionary(planner)) {
+                                let matches = planner.regex_dictionary(offset_len, backing_store, regex);
+                                return Ok((planner.is_in_set(plan, matches).into(), Type::bit_vec()));
+                            }
+                        }
                         if let Some(codec) = t.codec.clone() {
                             plan = codec.decode(plan, planner);
                         }
-                        (planner.regex(plan.str()?, regex).into(), t)
+                        (planner.regex(plan.str()?, regex).into(), Type::bit_vec())
                     }
                     _ => bail!(QueryError::TypeError, "Expected string constant as second argument to `regex`, actual: {:?}", regex),
                 }
             }
+            Func2(ArrayContains, ref expr, ref element) => {
+                match element {
+                    box Const(RawVal::Str(element)) => {
+                        if let Some(plan) = QueryPlan::compile_list_contains(expr, element, filter, columns, planner) {
+                            return Ok((plan, Type::bit_vec()));
+                        }
+                        // Other strings are parsed as JSON arrays, e.g. rows that are still buffered
+                        let (mut plan, t) = QueryPlan::compile_expr(expr, filter, columns, planner)?;
+                        if t.decoded != BasicType::String {
+                            bail!(QueryError::TypeError, "Expected expression of type `String` as first argument to array_contains. Actual: {:?}", t)
+                        }
+                        // Check each dictionary entry once and filter on the set of matching encoded values
+                        if !plan.is_nullable() {
+                            if let Some((offset_len, backing_store)) = t.codec.as_ref().and_then(|c| c.dictionary(planner)) {
+                                let matches = planner.array_contains_dictionary(offset_len, backing_store, element);
+                                return Ok((planner.is_in_set(plan, matches).into(), Type::bit_vec()));
+                            }
+                        }
+                        if let Some(codec) = t.codec.clone() {
+                            plan = codec.decode(plan, planner);
+                        }
+                        (planner.array_contains(plan.str()?, element).into(), Type::bit_vec())
+                    }
+                    _ => bail!(QueryError::TypeError, "Expected string constant as second argument to `array_contains`, actual: {:?}", element),
+                }
+            }
+            In(ref expr, ref values) => {
+                if values.is_empty() {
+                    bail!(QueryError::TypeError, "Empty IN list")
+                }
+                let (plan, t) = QueryPlan::compile_expr(expr, filter, columns, planner)?;
+                let dictionary = match t.codec {
+                    Some(ref codec) if t.decoded == BasicType::String => codec.dictionary(planner),
+                    _ => None,
+                };
+                let compare_encoded = !t.is_scalar && !plan.is_nullable() && match t.decoded {
+                    BasicType::String => dictionary.is_some(),
+                    BasicType::Integer => true,
+                    _ => false,
+                };
+                if !compare_encoded {
+                    // Fall back to a disjunction of equality comparisons
+                    let equals = |value: &RawVal| Expr::func(Equals, (**expr).clone(), Const(value.clone()));
+                    let disjunction = values[1..].iter()
+                        .fold(equals(&values[0]), |disjunction, value| Expr::func(Or, disjunction, equals(value)));
+                    return QueryPlan::compile_expr(&disjunction, filter, columns, planner);
+                }
+                let mut encoded_values = Vec::with_capacity(values.len());
+                for value in values {
+                    encoded_values.push(match (value, dictionary) {
+                        (&RawVal::Str(ref s), Some((offset_len, backing_store))) => {
+                            let constant = planner.scalar_str(s);
+                            planner.inverse_dict_lookup(offset_len, backing_store, constant).into()
+                        }
+                        (&RawVal::Int(i), None) => {
+                            let i = match t.codec {
+                                Some(ref codec) if t.is_encoded() => codec.encode_int(i),
+                                _ => i,
+                            };
+                            planner.scalar_i64(i, true).into()
+                        }
+                        _ => bail!(QueryError::TypeError, "Found {:?} in IN list of {:?}", value, &t),
+                    });
+                }
+                (planner.is_in(plan, encoded_values).into(), Type::bit_vec())
+            }
             Func2(function, ref lhs, ref rhs) => {
+                if let Some(plan) = QueryPlan::compile_run_length_comparison(function, lhs, rhs, filter, columns, planner) {
+                    return Ok((plan, Type::bit_vec()));
+                }
                 let (mut plan_lhs, mut type_lhs) = QueryPlan::compile_expr(lhs, filter, columns, planner)?;
                 let (mut plan_rhs, mut type_rhs) = QueryPlan::compile_expr(rhs, filter, columns, planner)?;
 
@@ -647,7 +1348,12 @@ impl QueryPlan {
                         function, type_lhs, type_rhs),
                 };
 
-                if declaration.encoding_invariance && type_lhs.is_scalar && type_rhs.is_encoded() {
+                // Range comparisons can only be evaluated on encoded values if the encoding preserves order
+                let encoding_invariant = declaration.encoding_invariance && match function {
+                    Func2Type::Equals | Func2Type::NotEquals => true,
+                    _ => type_lhs.is_order_preserving() && type_rhs.is_order_preserving(),
+                };
+                if encoding_invariant && type_lhs.is_scalar && type_rhs.is_encoded() {
                     plan_lhs = if type_rhs.decoded == BasicType::Integer {
                         if let QueryPlan::ScalarI64 { value, .. } = *planner.resolve(&plan_lhs) {
                             planner.scalar_i64(type_rhs.codec.unwrap().encode_int(value), true).into()
@@ -655,11 +1361,12 @@ impl QueryPlan {
                             panic!("whoops");
                         }
                     } else if type_rhs.decoded == BasicType::String {
-                        type_rhs.codec.clone().unwrap().encode_str(plan_lhs.scalar_str()?, planner).into()
+                        let comparison = function.flipped().unwrap_or(function);
+                        type_rhs.codec.clone().unwrap().encode_str(plan_lhs.scalar_str()?, comparison, planner).into()
                     } else {
                         panic!("whoops");
                     };
-                } else if declaration.encoding_invariance && type_rhs.is_scalar && type_lhs.is_encoded() {
+                } else if encoding_invariant && type_rhs.is_scalar && type_lhs.is_encoded() {
                     plan_rhs = if type_lhs.decoded == BasicType::Integer {
                         if let QueryPlan::ScalarI64 { value, .. } = *planner.resolve(&plan_rhs) {
                             planner.scalar_i64(type_lhs.codec.unwrap().encode_int(value), true).into()
@@ -667,11 +1374,24 @@ impl QueryPlan {
                             panic!("whoops");
                         }
                     } else if type_lhs.decoded == BasicType::String {
-                        type_lhs.codec.clone().unwrap().encode_str(plan_rhs.scalar_str()?, planner).into()
+                        type_lhs.codec.clone().unwrap().encode_str(plan_rhs.scalar_str()?, function, planner).into()
                     } else {
                         panic!("whoops");
                     };
                 } else {
+                    // Comparisons with dictionaries that don't preserve order are evaluated once per dictionary entry
+                    if declaration.encoding_invariance && type_lhs.decoded == BasicType::String {
+                        if type_rhs.is_scalar && !type_lhs.is_scalar && !plan_lhs.is_nullable() {
+                            if let Some(matches) = QueryPlan::compare_dictionary(&type_lhs, plan_rhs, function, planner) {
+                                return Ok((planner.is_in_set(plan_lhs, matches).into(), Type::bit_vec()));
+                            }
+                        } else if type_lhs.is_scalar && !type_rhs.is_scalar && !plan_rhs.is_nullable() {
+                            let comparison = function.flipped().unwrap_or(function);
+                            if let Some(matches) = QueryPlan::compare_dictionary(&type_rhs, plan_lhs, comparison, planner) {
+                                return Ok((planner.is_in_set(plan_rhs, matches).into(), Type::bit_vec()));
+                            }
+                        }
+                    }
                     if let Some(codec) = type_lhs.codec {
                         plan_lhs = codec.decode(plan_lhs, planner);
                     }
@@ -683,36 +1403,322 @@ impl QueryPlan {
                 let plan = (declaration.factory)(planner, plan_lhs, plan_rhs);
                 (plan, declaration.type_out.clone())
             }
+            // Truncation to units of fixed length is integer arithmetic which can skip decoding widened integers
+            Func1(Func1Type::DateTrunc(unit), ref inner) if unit.seconds().is_some() => {
+                let (plan, t) = QueryPlan::compile_expr(inner, filter, columns, planner)?;
+                if t.decoded != BasicType::Integer {
+                    bail!(QueryError::TypeError, "Found date_trunc({:?}), expected date_trunc(integer)", &t)
+                }
+                let input = match t.codec {
+                    Some(ref codec) if !codec.is_widening() => codec.decode(plan, planner),
+                    _ => plan,
+                };
+                let seconds: TypedBufferRef = planner.scalar_i64(unit.seconds().unwrap(), true).into();
+                let quotient = planner.divide(input, seconds);
+                (planner.multiply(quotient.into(), seconds).into(), Type::unencoded(BasicType::Integer).mutable())
+            }
+            Func1(Func1Type::Cast(target), box Const(ref value)) => {
+                let casted = match (value, target) {
+                    (&RawVal::Int(i), BasicType::Float) => RawVal::Float(OrderedF64(i as f64)),
+                    (&RawVal::Float(f), BasicType::Integer) => RawVal::Int(float_to_int(f.0)),
+                    (&RawVal::Str(ref s), BasicType::Integer) => RawVal::Int(parse_int(s)),
+                    (&RawVal::Str(ref s), BasicType::Float) => RawVal::Float(OrderedF64(parse_float(s))),
+                    (&RawVal::Int(_), BasicType::String) | (&RawVal::Float(_), BasicType::String) =>
+                        RawVal::Str(value.to_string()),
+                    (&RawVal::Int(_), BasicType::Integer)
+                    | (&RawVal::Float(_), BasicType::Float)
+                    | (&RawVal::Str(_), BasicType::String) => value.clone(),
+                    _ => bail!(QueryError::TypeError, "Cannot cast {} to {:?}", value, target),
+                };
+                QueryPlan::compile_expr(&Const(casted), filter, columns, planner)?
+            }
+            Func1(Func1Type::Cast(target), ref inner) => {
+                let (plan, t) = QueryPlan::compile_expr(inner, filter, columns, planner)?;
+                if t.decoded == target {
+                    return Ok((plan, t));
+                }
+                let decoded = match t.codec.clone() {
+                    Some(codec) => codec.decode(plan, planner),
+                    None => plan,
+                };
+                let casted = match (t.decoded, target) {
+                    (BasicType::Integer, BasicType::Float) | (BasicType::Boolean, BasicType::Float) => {
+                        let input = planner.cast(decoded, EncodingType::I64).i64()?;
+                        planner.int_to_float(input).into()
+                    }
+                    (BasicType::Integer, BasicType::String) | (BasicType::Boolean, BasicType::String) => {
+                        let input = planner.cast(decoded, EncodingType::I64);
+                        planner.to_str(input).into()
+                    }
+                    (BasicType::Boolean, BasicType::Integer) => planner.cast(decoded, EncodingType::I64),
+                    (BasicType::Float, BasicType::Integer) => planner.float_to_int(decoded.f64()?).into(),
+                    (BasicType::Float, BasicType::String) => planner.to_str(decoded).into(),
+                    (BasicType::String, BasicType::Integer) => planner.parse_int(decoded.str()?).into(),
+                    (BasicType::String, BasicType::Float) => planner.parse_float(decoded.str()?).into(),
+                    _ => bail!(QueryError::TypeError, "Cannot cast {:?} to {:?}", &t, target),
+                };
+                (casted, Type::unencoded(target))
+            }
+            Func1(ftype @ Func1Type::IsNull, ref inner) | Func1(ftype @ Func1Type::IsNotNull, ref inner) => {
+                let (plan, t) = QueryPlan::compile_expr(inner, filter, columns, planner)?;
+                if t.is_scalar {
+                    bail!(QueryError::NotImplemented, "{:?} on constant {:?}", ftype, inner)
+                }
+                let nullable = if plan.is_nullable() { plan } else { planner.make_nullable(plan) };
+                let is_not_null = match ftype { Func1Type::IsNotNull => true, _ => false };
+                (planner.is_null(nullable, is_not_null).into(), Type::bit_vec())
+            }
+            Func2(Func2Type::Coalesce, ref lhs, ref rhs) => {
+                let (plan_lhs, type_lhs) = QueryPlan::compile_expr(lhs, filter, columns, planner)?;
+                if !plan_lhs.is_nullable() {
+                    return Ok((plan_lhs, type_lhs));
+                }
+                let (plan_rhs, type_rhs) = QueryPlan::compile_expr(rhs, filter, columns, planner)?;
+                // E.g. columns that are missing from the partition
+                if type_lhs.decoded == BasicType::Null {
+                    return Ok((plan_rhs, type_rhs));
+                }
+                if type_lhs.decoded != type_rhs.decoded {
+                    bail!(QueryError::TypeError, "Found coalesce({:?}, {:?}), expected arguments of identical type", &type_lhs, &type_rhs)
+                }
+                let mut args = Vec::with_capacity(2);
+                for (plan, t) in vec![(plan_lhs, type_lhs.clone()), (plan_rhs, type_rhs)] {
+                    let decoded = match t.codec {
+                        Some(ref codec) => codec.decode(plan, planner),
+                        None => plan,
+                    };
+                    args.push(if t.decoded == BasicType::Integer && !t.is_scalar {
+                        planner.cast(decoded, EncodingType::I64)
+                    } else {
+                        decoded
+                    });
+                }
+                let coalesced_type = if args[1].is_nullable() { args[0].tag } else { args[0].tag.non_nullable() };
+                (planner.coalesce(args[0], args[1], coalesced_type), Type::unencoded(type_lhs.decoded))
+            }
             Func1(ftype, ref inner) => {
                 let (plan, t) = QueryPlan::compile_expr(inner, filter, columns, planner)?;
                 let decoded = match t.codec.clone() {
                     Some(codec) => codec.decode(plan, planner),
                     None => plan,
                 };
-                let plan = match ftype {
+                match ftype {
                     Func1Type::ToYear => {
                         if t.decoded != BasicType::Integer {
                             bail!(QueryError::TypeError, "Found to_year({:?}), expected to_year(integer)", &t)
                         }
-                        planner.to_year(decoded).into()
+                        (planner.to_year(decoded).into(), t.decoded())
                     }
                     Func1Type::Not => {
                         if t.decoded != BasicType::Boolean {
                             bail!(QueryError::TypeError, "Found NOT({:?}), expected NOT(boolean)", &t)
                         }
-                        planner.not(decoded.u8()?).into()
+                        (planner.not(decoded.u8()?).into(), t.decoded())
                     }
                     Func1Type::Negate => {
                         bail!(QueryError::TypeError, "Found negate({:?}), expected negate(integer)", &t)
                     }
-                };
-                (plan, t.decoded())
+                    Func1Type::Hash => {
+                        let input = match t.decoded {
+                            BasicType::String => decoded,
+                            BasicType::Integer => planner.cast(decoded, EncodingType::I64),
+                            _ => bail!(QueryError::TypeError, "Found hash({:?}), expected hash(string) or hash(integer)", &t),
+                        };
+                        (planner.hash(input).into(), Type::unencoded(BasicType::Integer))
+                    }
+                    Func1Type::Redact => (planner.redact(decoded).into(), Type::unencoded(BasicType::String)),
+                    Func1Type::Truncate(length) => {
+                        if t.decoded != BasicType::String {
+                            bail!(QueryError::TypeError, "Found truncate({:?}), expected truncate(string)", &t)
+                        }
+                        (planner.truncate(decoded.str()?, length).into(), Type::unencoded(BasicType::String))
+                    }
+                    Func1Type::Lower | Func1Type::Upper => {
+                        if t.decoded != BasicType::String {
+                            bail!(QueryError::TypeError, "Found {:?}({:?}), expected {:?}(string)", ftype, &t, ftype)
+                        }
+                        let upper = match ftype { Func1Type::Upper => true, _ => false };
+                        if let Some((offset_len, backing_store)) = t.codec.as_ref().and_then(|c| c.dictionary(planner)) {
+                            let changed = planner.dict_change_case(plan, offset_len, backing_store, upper);
+                            return Ok((changed.into(), Type::unencoded(BasicType::String)));
+                        }
+                        (planner.change_case(decoded.str()?, upper).into(), Type::unencoded(BasicType::String))
+                    }
+                    Func1Type::Length => {
+                        if t.decoded != BasicType::String {
+                            bail!(QueryError::TypeError, "Found length({:?}), expected length(string)", &t)
+                        }
+                        (planner.length(decoded.str()?).into(), Type::unencoded(BasicType::Integer))
+                    }
+                    Func1Type::Substr(start, length) => {
+                        if t.decoded != BasicType::String {
+                            bail!(QueryError::TypeError, "Found substr({:?}), expected substr(string)", &t)
+                        }
+                        let length = length.unwrap_or(usize::MAX);
+                        (planner.substring(decoded.str()?, start, length).into(), Type::unencoded(BasicType::String))
+                    }
+                    Func1Type::DateTrunc(unit) | Func1Type::Extract(unit) => {
+                        if t.decoded != BasicType::Integer {
+                            bail!(QueryError::TypeError, "Found {:?}({:?}), expected integer timestamp", ftype, &t)
+                        }
+                        let input = planner.cast(decoded, EncodingType::I64).i64()?;
+                        let plan = match ftype {
+                            Func1Type::DateTrunc(_) => planner.date_trunc(input, unit),
+                            _ => planner.extract(input, unit),
+                        };
+                        (plan.into(), Type::unencoded(BasicType::Integer))
+                    }
+                    Func1Type::Cast(_) | Func1Type::IsNull | Func1Type::IsNotNull => unreachable!(),
+                    Func1Type::Unnest => bail!(QueryError::NotImplemented, "unnest is only supported on a column name"),
+                    Func1Type::HllRegisters(lane) => {
+                        if t.decoded != BasicType::Integer {
+                            bail!(QueryError::TypeError, "Found hll_registers({:?}), expected hll_registers(integer)", &t)
+                        }
+                        let input = planner.cast(decoded, EncodingType::I64).i64()?;
+                        (planner.hll_registers(input, lane).into(), Type::unencoded(BasicType::Integer))
+                    }
+                }
+            }
+            Udf(ref function, ref args) => {
+                let signature = &function.signature;
+                if args.len() != signature.args.len() {
+                    bail!(QueryError::TypeError, "Function {} expects {} arguments, found {}",
+                          &function.name, signature.args.len(), args.len())
+                }
+                let mut arg_plans = Vec::with_capacity(args.len());
+                for (arg, &expected) in args.iter().zip(signature.args.iter()) {
+                    let (plan, t) = QueryPlan::compile_expr(arg, filter, columns, planner)?;
+                    if t.decoded != expected.basic_type() {
+                        bail!(QueryError::TypeError, "Found {}(..{:?}..), expected argument of type {:?}", &function.name, &t, expected)
+                    }
+                    if signature.dictionary_eval {
+                        if let Some((offset_len, backing_store)) = t.codec.as_ref().and_then(|c| c.dictionary(planner)) {
+                            let evaluated = planner.dict_udf(
+                                &function.name, function.clone(), plan, offset_len, backing_store,
+                                signature.returns.encoding_type());
+                            return Ok((evaluated, Type::unencoded(signature.returns.basic_type())));
+                        }
+                    }
+                    let decoded = match t.codec.clone() {
+                        Some(codec) => codec.decode(plan, planner),
+                        None => plan,
+                    };
+                    arg_plans.push(if t.decoded == BasicType::Integer && !t.is_scalar {
+                        planner.cast(decoded, EncodingType::I64)
+                    } else {
+                        decoded
+                    });
+                }
+                let evaluated = planner.scalar_udf(&function.name, function.clone(), arg_plans, signature.returns.encoding_type());
+                (evaluated, Type::unencoded(signature.returns.basic_type()))
             }
             Const(RawVal::Int(i)) => (planner.scalar_i64(i, false).into(), Type::scalar(BasicType::Integer)),
+            Const(RawVal::Float(f)) => (planner.scalar_f64(f).into(), Type::scalar(BasicType::Float)),
             Const(RawVal::Str(ref s)) => (planner.scalar_str(s).into(), Type::scalar(BasicType::String)),
             ref x => bail!(QueryError::NotImplemented, "{:?}.compile_vec()", x),
         })
     }
+
+    /// Evaluates comparisons of a run-length encoded column with a constant once per run rather than once per row.
+    /// Evaluates a lower and an upper bound on the same integer expression with a single range check, on encoded
+    /// values if the encoding preserves order.
+    fn compile_range_check(
+        lhs: &Expr,
+        rhs: &Expr,
+        filter: Filter,
+        columns: &HashMap<String, Arc<DataSource>>,
+        planner: &mut QueryPlanner) -> Result<Option<TypedBufferRef>, QueryError> {
+        let (expr, lhs_is_lower, lhs_bound, rhs_bound) = match (lhs.range_bound(), rhs.range_bound()) {
+            (Some((expr, lhs_is_lower, lhs_bound)), Some((other, rhs_is_lower, rhs_bound)))
+            if lhs_is_lower != rhs_is_lower && format!("{:?}", expr) == format!("{:?}", other) =>
+                (expr, lhs_is_lower, lhs_bound, rhs_bound),
+            _ => return Ok(None),
+        };
+        let (low, high) = if lhs_is_lower { (lhs_bound, rhs_bound) } else { (rhs_bound, lhs_bound) };
+        let (mut plan, t) = QueryPlan::compile_expr(expr, filter, columns, planner)?;
+        if t.decoded != BasicType::Integer || t.is_scalar || plan.is_nullable() {
+            return Ok(None);
+        }
+        let (low, high) = match t.codec {
+            Some(ref codec) if t.is_encoded() && t.is_order_preserving() => (codec.encode_int(low), codec.encode_int(high)),
+            Some(ref codec) => {
+                plan = codec.decode(plan, planner);
+                (low, high)
+            }
+            None => (low, high),
+        };
+        let low = planner.scalar_i64(low, true).into();
+        let high = planner.scalar_i64(high, true).into();
+        Ok(Some(planner.in_range(plan, low, high).into()))
+    }
+
+    /// Checks whether the lists of a list column contain `element` by comparing the dictionary indices of their
+    /// elements to the index of `element`, without decoding the lists. Returns `None` if `expr` is not a list column.
+    fn compile_list_contains(
+        expr: &Expr,
+        element: &str,
+        filter: Filter,
+        columns: &HashMap<String, Arc<DataSource>>,
+        planner: &mut QueryPlanner) -> Option<TypedBufferRef> {
+        let codec = match *expr {
+            Expr::ColName(ref name) => columns.get::<str>(name.as_ref())?.codec(),
+            _ => return None,
+        };
+        let (ends, elements, offset_len, backing_store) = codec.list(planner)?;
+        let element = planner.scalar_str(element);
+        let code = planner.inverse_dict_lookup(offset_len, backing_store, element);
+        let contains: TypedBufferRef = planner.list_contains(ends, elements, code).into();
+        Some(match filter {
+            Filter::U8(filter) => planner.filter(contains, filter),
+            Filter::NullableU8(filter) => planner.nullable_filter(contains, filter),
+            Filter::Indices(indices) => planner.select(contains, indices),
+            Filter::None => contains,
+        })
+    }
+
+    fn compile_run_length_comparison(
+        function: Func2Type,
+        lhs: &Expr,
+        rhs: &Expr,
+        filter: Filter,
+        columns: &HashMap<String, Arc<DataSource>>,
+        planner: &mut QueryPlanner) -> Option<TypedBufferRef> {
+        match filter {
+            Filter::None => {}
+            _ => return None,
+        }
+        let (name, value, column_is_lhs) = match (lhs, rhs) {
+            (&Expr::ColName(ref name), &Expr::Const(RawVal::Int(value))) => (name, value, true),
+            (&Expr::Const(RawVal::Int(value)), &Expr::ColName(ref name)) => (name, value, false),
+            _ => return None,
+        };
+        let declaration = FUNCTION2_REGISTRY.get(&function)?.iter().find(|declaration|
+            declaration.encoding_invariance
+                && declaration.type_lhs == BasicType::Integer
+                && declaration.type_rhs == BasicType::Integer)?;
+        let column = columns.get::<str>(name.as_ref())?;
+        let codec = column.codec().run_values()?;
+        let value = if codec.is_identity() { value } else { codec.encode_int(value) };
+        let values = planner.column_section(name, 0, column.range(), column.encoding_type());
+        let run_lengths = planner.column_section(name, 1, None, EncodingType::U32).u32().ok()?;
+        let value = planner.scalar_i64(value, true).into();
+        let run_matches = if column_is_lhs {
+            (declaration.factory)(planner, values, value)
+        } else {
+            (declaration.factory)(planner, value, values)
+        };
+        Some(planner.run_length_decode(run_matches, run_lengths))
+    }
+
+    /// Evaluates `comparison` of each entry in the dictionary of a string column of type `t` with `constant`.
+    fn compare_dictionary(t: &Type,
+                          constant: TypedBufferRef,
+                          comparison: Func2Type,
+                          planner: &mut QueryPlanner) -> Option<BufferRef<u8>> {
+        let constant = constant.scalar_str().ok()?;
+        let (offset_len, backing_store) = t.codec.as_ref()?.dictionary(planner)?;
+        Some(planner.compare_dictionary(offset_len, backing_store, constant, comparison))
+    }
 }
 
 fn encoding_range(plan: &TypedBufferRef, planner: &QueryPlanner) -> Option<(i64, i64)> {
@@ -726,6 +1732,22 @@ fn encoding_range(plan: &TypedBufferRef, planner: &QueryPlanner) -> Option<(i64,
             (i64::from(NaiveDateTime::from_timestamp(min, 0).year()),
              i64::from(NaiveDateTime::from_timestamp(max, 0).year()))
         ),
+        DateTrunc { timestamp, unit, .. } => encoding_range(&timestamp.into(), planner).map(|(min, max)|
+            (date_trunc(min, unit), date_trunc(max, unit))
+        ),
+        Extract { timestamp, unit, .. } => match unit {
+            TimeUnit::Second | TimeUnit::Minute => Some((0, 59)),
+            TimeUnit::Hour => Some((0, 23)),
+            TimeUnit::Day => Some((1, 31)),
+            TimeUnit::Week => Some((1, 53)),
+            TimeUnit::Month => Some((1, 12)),
+            TimeUnit::Quarter => Some((1, 4)),
+            TimeUnit::DayOfWeek => Some((0, 6)),
+            TimeUnit::DayOfYear => Some((1, 366)),
+            TimeUnit::Year => encoding_range(&timestamp.into(), planner).map(|(min, max)|
+                (extract(min, unit), extract(max, unit))
+            ),
+        },
         Filter { ref plan, .. } => encoding_range(plan, planner),
         // TODO(clemens): this is just wrong
         Divide { ref lhs, ref rhs, .. } => if let ScalarI64 { value: c, .. } = planner.resolve(rhs) {
@@ -739,9 +1761,35 @@ fn encoding_range(plan: &TypedBufferRef, planner: &QueryPlanner) -> Option<(i64,
         } else {
             None
         },
+        Subtract { ref lhs, ref rhs, .. } => if let ScalarI64 { value: c, .. } = planner.resolve(rhs) {
+            encoding_range(lhs, planner).map(|(min, max)| (min - *c, max - *c))
+        } else {
+            None
+        },
+        Multiply { ref lhs, ref rhs, .. } => if let ScalarI64 { value: c, .. } = planner.resolve(rhs) {
+            encoding_range(lhs, planner).map(|(min, max)|
+                if *c >= 0 { (min * *c, max * *c) } else { (max * *c, min * *c) })
+        } else {
+            None
+        },
+        Modulo { ref lhs, ref rhs, .. } => if let ScalarI64 { value: c, .. } = planner.resolve(rhs) {
+            let c = c.abs();
+            encoding_range(lhs, planner).and_then(|(min, max)|
+                if c == 0 {
+                    None
+                } else if min >= 0 {
+                    Some((0, cmp::min(max, c - 1)))
+                } else {
+                    Some((-(c - 1), c - 1))
+                })
+        } else {
+            None
+        },
         Cast { ref input, .. } => encoding_range(input, planner),
         LZ4Decode { bytes, .. } => encoding_range(&bytes.into(), planner),
+        ZstdDecode { bytes, .. } => encoding_range(&bytes.into(), planner),
         DeltaDecode { ref plan, .. } => encoding_range(plan, planner),
+        RunLengthDecode { ref values, .. } => encoding_range(values, planner),
         _ => None, // TODO(clemens): many more cases where we can determine range
     }
 }
@@ -781,19 +1829,30 @@ pub fn compile_grouping_key(
                     let offset = planner.scalar_i64(offset, true);
                     planner.add(gk_plan, offset.into()).into()
                 } else { gk_plan };
+                // Computed expressions with known range can be used directly as positive integer grouping key
+                let is_computed = gk_type.codec.is_none() && encoding_range.is_some() && !gk_plan.is_nullable();
+                let (gk_plan, raw_gk_type) = if is_computed {
+                    (planner.cast(gk_plan, EncodingType::I64),
+                     Type::encoded(Codec::opaque(EncodingType::I64, BasicType::Integer, false, true, true, true)))
+                } else {
+                    (gk_plan, gk_type.clone())
+                };
 
                 let encoded_group_by_placeholder = planner.buffer_provider.named_buffer(
-                    "encoded_group_by_placeholder", gk_type.encoding_type());
-                let decoded_group_by = gk_type.codec.clone().map_or(
-                    encoded_group_by_placeholder,
-                    |codec| codec.decode(encoded_group_by_placeholder, planner));
-                let decoded_group_by = if offset == 0 { decoded_group_by } else {
+                    "encoded_group_by_placeholder", gk_plan.tag);
+                let mut decoded_group_by = encoded_group_by_placeholder;
+                if offset != 0 {
                     let offset = planner.scalar_i64(-offset, true);
-                    let sum = planner.add(decoded_group_by, offset.into()).into();
-                    planner.cast(sum, gk_type.encoding_type())
-                };
+                    decoded_group_by = planner.add(decoded_group_by, offset.into()).into();
+                }
+                if offset != 0 || is_computed {
+                    decoded_group_by = planner.cast(decoded_group_by, gk_type.encoding_type());
+                }
+                if let Some(codec) = gk_type.codec.clone() {
+                    decoded_group_by = codec.decode(decoded_group_by, planner);
+                }
 
-                ((gk_plan.clone(), gk_type.clone()),
+                ((gk_plan, raw_gk_type),
                  max_cardinality,
                  vec![(decoded_group_by, gk_type.decoded())],
                  encoded_group_by_placeholder)
@@ -928,19 +1987,32 @@ pub fn prepare<'a>(plan: QueryPlan, constant_vecs: &mut Vec<BoxedData<'a>>, resu
         QueryPlan::Filter { plan, select, filtered } => VecOperator::filter(plan, select, filtered)?,
         QueryPlan::NullableFilter { plan, select, filtered } => VecOperator::nullable_filter(plan, select, filtered)?,
         QueryPlan::ScalarI64 { value, hide_value, scalar_i64 } => VecOperator::scalar_i64(value, hide_value, scalar_i64),
+        QueryPlan::ScalarF64 { value, scalar_f64 } => VecOperator::scalar_f64(value, scalar_f64),
         QueryPlan::ScalarStr { value, pinned_string, scalar_str } => VecOperator::scalar_str(value.to_string(), pinned_string, scalar_str),
         QueryPlan::NullVec { len, nulls } => VecOperator::null_vec(len, nulls.any()),
         QueryPlan::ConstantExpand { value, len, expanded } => VecOperator::constant_expand(value, len, expanded)?,
+        QueryPlan::SampleRows { len, fraction, seed, sampled } => VecOperator::sample_rows(len, fraction.0, seed, sampled),
         QueryPlan::DictLookup { indices, offset_len, backing_store, decoded } => VecOperator::dict_lookup(indices, offset_len, backing_store, decoded)?,
         QueryPlan::InverseDictLookup { offset_len, backing_store, constant, decoded } => VecOperator::inverse_dict_lookup(offset_len, backing_store, constant, decoded),
+        QueryPlan::DictBound { offset_len, backing_store, constant, at_most, bound } => VecOperator::dict_bound(offset_len, backing_store, constant, at_most, bound),
+        QueryPlan::CompareDictionary { offset_len, backing_store, constant, comparison, matches } => VecOperator::compare_dictionary(offset_len, backing_store, constant, comparison, matches),
+        QueryPlan::DictionaryEntries { offset_len, backing_store, entries } => VecOperator::dictionary_entries(offset_len, backing_store, entries),
         QueryPlan::Cast { input, casted } => VecOperator::type_conversion(input, casted)?,
         QueryPlan::DeltaDecode { plan, delta_decoded } => VecOperator::delta_decode(plan, delta_decoded)?,
+        QueryPlan::RunLengthDecode { values, run_lengths, decoded } => VecOperator::run_length_decode(values, run_lengths, decoded)?,
         QueryPlan::LZ4Decode { bytes, decoded_len, decoded } => VecOperator::lz4_decode(bytes, decoded_len, decoded)?,
+        QueryPlan::ZstdDecode { bytes, decoded_len, decoded } => VecOperator::zstd_decode(bytes, decoded_len, decoded)?,
         QueryPlan::UnpackStrings { bytes, unpacked_strings } => VecOperator::unpack_strings(bytes, unpacked_strings),
         QueryPlan::UnhexpackStrings { bytes, uppercase, total_bytes, string_store, unpacked_strings } => VecOperator::unhexpack_strings(bytes, uppercase, total_bytes, string_store, unpacked_strings),
-        QueryPlan::HashMapGrouping { raw_grouping_key, max_cardinality, unique, grouping_key, cardinality } => VecOperator::hash_map_grouping(raw_grouping_key, max_cardinality, unique, grouping_key, cardinality)?,
+        QueryPlan::HashMapGrouping { raw_grouping_key, expected_cardinality, unique, grouping_key, cardinality } => VecOperator::hash_map_grouping(raw_grouping_key, expected_cardinality, unique, grouping_key, cardinality)?,
         QueryPlan::Count { grouping_key, max_index, count } => VecOperator::count(grouping_key, max_index, count)?,
         QueryPlan::Sum { plan, grouping_key, max_index, count } => VecOperator::summation(plan, grouping_key, max_index, count)?,
+        QueryPlan::SumF64 { plan, grouping_key, max_index, sum } => VecOperator::summation_f64(plan, grouping_key, max_index, sum)?,
+        QueryPlan::MergeRegisters { plan, grouping_key, max_index, merged } => VecOperator::merge_registers(plan, grouping_key, max_index, merged)?,
+        QueryPlan::Extremum { plan, grouping_key, max_index, max, extremes } => VecOperator::extremum(plan, grouping_key, max_index, max, extremes)?,
+        QueryPlan::QuantileSketch { plan, grouping_key, max_index, string_store, sketches } => VecOperator::quantile_sketch(plan, grouping_key, max_index, string_store, sketches)?,
+        QueryPlan::Histogram { plan, grouping_key, max_index, string_store, histograms } => VecOperator::histogram(plan, grouping_key, max_index, string_store, histograms)?,
+        QueryPlan::ArgExtremum { grouping_key, key, payload, max_index, max, selected } => VecOperator::arg_extremum(grouping_key, key, payload, max_index, max, selected)?,
         QueryPlan::Exists { indices, max_index, exists } => VecOperator::exists(indices, max_index, exists)?,
         QueryPlan::Compact { plan, select, compacted } => VecOperator::compact(plan, select, compacted)?,
         QueryPlan::NonzeroIndices { plan, nonzero_indices } => VecOperator::nonzero_indices(plan, nonzero_indices)?,
@@ -962,10 +2034,42 @@ pub fn prepare<'a>(plan: QueryPlan, constant_vecs: &mut Vec<BoxedData<'a>>, resu
         QueryPlan::And { lhs, rhs, and } => VecOperator::and(lhs.u8()?, rhs.u8()?, and.u8()?),
         QueryPlan::Not { input, not } => VecOperator::not(input, not),
         QueryPlan::ToYear { timestamp, year } => VecOperator::to_year(timestamp.i64()?, year.i64()?),
+        QueryPlan::DateTrunc { timestamp, unit, truncated } => VecOperator::date_trunc(timestamp, unit, truncated),
+        QueryPlan::Extract { timestamp, unit, extracted } => VecOperator::extract(timestamp, unit, extracted),
+        QueryPlan::Hash { input, hashed } => VecOperator::hash(input, hashed)?,
+        QueryPlan::Redact { input, redacted } => VecOperator::redact(input, redacted)?,
+        QueryPlan::Truncate { input, length, truncated } => VecOperator::truncate(input, length, truncated),
+        QueryPlan::Substring { input, start, length, substring } => VecOperator::substring(input, start, length, substring),
+        QueryPlan::Length { input, length } => VecOperator::length(input, length),
+        QueryPlan::ChangeCase { input, upper, string_store, changed } => VecOperator::change_case(input, upper, string_store, changed),
+        QueryPlan::DictChangeCase { indices, offset_len, backing_store, upper, string_store, dictionary, changed } =>
+            VecOperator::dict_change_case(indices, offset_len, backing_store, upper, string_store, dictionary, changed)?,
+        QueryPlan::Concat { lhs, rhs, string_store, concatenated } => VecOperator::concat(lhs, rhs, string_store, concatenated),
+        QueryPlan::FloatArithmetic { lhs, rhs, op, arithmetic } => VecOperator::float_arithmetic(lhs, rhs, op, arithmetic),
+        QueryPlan::FloatComparison { lhs, rhs, op, compared } => VecOperator::float_comparison(lhs, rhs, op, compared),
+        QueryPlan::IsNull { plan, is_not_null, is_null } => VecOperator::is_null(plan.nullable_any()?, is_not_null, is_null),
+        QueryPlan::Coalesce { lhs, rhs, coalesced } => VecOperator::coalesce(lhs, rhs, coalesced)?,
+        QueryPlan::IntToFloat { input, float } => VecOperator::int_to_float(input, float),
+        QueryPlan::FloatToInt { input, int } => VecOperator::float_to_int(input, int),
+        QueryPlan::ParseInt { input, int } => VecOperator::parse_int(input, int),
+        QueryPlan::ParseFloat { input, float } => VecOperator::parse_float(input, float),
+        QueryPlan::ToStr { input, string_store, string } => VecOperator::to_str(input, string_store, string)?,
+        QueryPlan::HllRegisters { input, lane, registers } => VecOperator::hll_registers(input, lane, registers),
+        QueryPlan::ScalarUdf { function, args, string_store, evaluated, .. } => VecOperator::scalar_udf(function, args, string_store, evaluated),
+        QueryPlan::DictUdf { function, indices, offset_len, backing_store, string_store, evaluated, .. } => VecOperator::dict_udf(function, indices, offset_len, backing_store, string_store, evaluated)?,
+        QueryPlan::IsIn { plan, values, is_in } => VecOperator::is_in(plan, values, is_in)?,
+        QueryPlan::InRange { plan, low, high, in_range } => VecOperator::in_range(plan, low, high, in_range)?,
+        QueryPlan::IsInSet { plan, set, is_in } => VecOperator::is_in_set(plan, set, is_in)?,
+        QueryPlan::RegexDictionary { offset_len, backing_store, regex, matches } => VecOperator::regex_dictionary(offset_len, backing_store, &regex, matches),
         QueryPlan::Regex { plan, regex, matches } => VecOperator::regex(plan, &regex, matches),
+        QueryPlan::ArrayContainsDictionary { offset_len, backing_store, element, matches } => VecOperator::array_contains_dictionary(offset_len, backing_store, &element, matches),
+        QueryPlan::ArrayContains { plan, element, contains } => VecOperator::array_contains(plan, &element, contains),
+        QueryPlan::ListContains { ends, elements, code, contains } => VecOperator::list_contains(ends, elements, code, contains)?,
+        QueryPlan::EncodeLists { ends, elements, string_store, encoded } => VecOperator::encode_lists(ends, elements, string_store, encoded),
         QueryPlan::Indices { plan, indices } => VecOperator::indices(plan, indices),
         QueryPlan::SortBy { ranking, indices, desc, stable, permutation } => VecOperator::sort_by(ranking, indices, desc, stable, permutation)?,
-        QueryPlan::TopN { ranking, n, desc, tmp_keys, top_n } => VecOperator::top_n(ranking, tmp_keys, n, desc, top_n)?,
+        QueryPlan::TopN { ranking, n, desc, offset, tmp_keys, top_n } => VecOperator::top_n(ranking, tmp_keys, n, desc, offset, top_n)?,
+        QueryPlan::TopNMulti { rankings, descending, n, offset, top_n } => VecOperator::top_n_multi(rankings, descending, n, offset, top_n)?,
         QueryPlan::Connect { input, output } => VecOperator::identity(input, output),
         QueryPlan::Merge { lhs, rhs, limit, desc, merge_ops, merged } => VecOperator::merge(lhs, rhs, limit, desc, merge_ops, merged)?,
         QueryPlan::MergePartitioned { partitioning, lhs, rhs, limit, desc, take_left, merged } => VecOperator::merge_partitioned(partitioning, lhs, rhs, limit, desc, take_left, merged)?,
@@ -975,7 +2079,9 @@ pub fn prepare<'a>(plan: QueryPlan, constant_vecs: &mut Vec<BoxedData<'a>>, resu
         QueryPlan::Subpartition { partitioning, lhs, rhs, desc, subpartitioning } => VecOperator::subpartition(partitioning, lhs, rhs, desc, subpartitioning)?,
         QueryPlan::MergeDrop { merge_ops, lhs, rhs, merged } => VecOperator::merge_drop(merge_ops, lhs, rhs, merged)?,
         QueryPlan::MergeKeep { take_left, lhs, rhs, merged } => VecOperator::merge_keep(take_left, lhs, rhs, merged)?,
-        QueryPlan::MergeAggregate { merge_ops, lhs, rhs, aggregator, merged } => VecOperator::merge_aggregate(merge_ops, lhs, rhs, aggregator, merged),
+        QueryPlan::MergeAggregate { merge_ops, lhs, rhs, aggregator, merged } => VecOperator::merge_aggregate(merge_ops, lhs, rhs, aggregator, merged)?,
+        QueryPlan::MergeQuantileSketches { merge_ops, lhs, rhs, string_store, merged } => VecOperator::merge_quantile_sketches(merge_ops, lhs, rhs, string_store, merged),
+        QueryPlan::MergeArgExtremum { merge_ops, lhs, rhs, max, merged } => VecOperator::merge_arg_extremum(merge_ops, lhs.keyed_val()?, rhs.keyed_val()?, max, merged.keyed_val()?),
         QueryPlan::ConstantVec { index, constant_vec } => VecOperator::constant_vec(std::mem::replace(&mut constant_vecs[index], Data::empty(1)), constant_vec.any()),
     };
     result.push(operation);
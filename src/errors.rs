@@ -1,3 +1,6 @@
+use std::cmp;
+use std::fmt;
+
 use failure::Backtrace;
 
 
@@ -15,6 +18,143 @@ pub enum QueryError {
     NotImplemented(String),
     #[fail(display = "Type error: {}", _0)]
     TypeError(String),
+    #[fail(display = "Invalid query: {}", _0)]
+    InvalidQuery(String),
+    #[fail(display = "Column {} does not exist in table {}{}", column, table, suggestion)]
+    UnknownColumn { column: String, table: String, suggestion: Suggestion },
+    /// Error caused by the part of the query given by the span.
+    #[fail(display = "{}\n{}", _0, _1)]
+    Spanned(Box<QueryError>, SourceSpan),
+    #[fail(display = "Permission denied: {}", _0)]
+    PermissionDenied(String),
+    #[fail(display = "Query was cancelled")]
+    Cancelled,
+    #[fail(display = "Resource limit exceeded: {}", _0)]
+    ResourceExhausted(String),
+    #[fail(display = "I/O error: {}", _0)]
+    IoError(String),
+    #[fail(display = "Remote node failed: {}", _0)]
+    RemoteError(String),
+    #[fail(display = "Integer overflow: {}", _0)]
+    Overflow(String),
+}
+
+impl QueryError {
+    /// Attaches the `len` bytes of `query` starting at `offset` that caused the error, which are pointed out below
+    /// the error message.
+    pub fn at(self, query: &str, offset: usize, len: usize) -> QueryError {
+        match self {
+            spanned @ QueryError::Spanned(_, _) => spanned,
+            error => QueryError::Spanned(Box::new(error), SourceSpan { query: query.to_string(), offset, len }),
+        }
+    }
+
+    /// Attaches the first reference to the unknown column in `query` to `UnknownColumn` errors.
+    pub fn locate(self, query: &str) -> QueryError {
+        let position = match self {
+            QueryError::UnknownColumn { ref column, .. } => find_identifier(query, column).map(|offset| (offset, column.len())),
+            _ => None,
+        };
+        match position {
+            Some((offset, len)) => self.at(query, offset, len),
+            None => self,
+        }
+    }
+
+    /// The error without the part of the query that caused it.
+    pub fn kind(&self) -> &QueryError {
+        match *self {
+            QueryError::Spanned(ref error, _) => error.kind(),
+            ref error => error,
+        }
+    }
+
+    pub fn span(&self) -> Option<&SourceSpan> {
+        match *self {
+            QueryError::Spanned(_, ref span) => Some(span),
+            _ => None,
+        }
+    }
+}
+
+/// Part of a query, displayed as the line of the query that contains it with carets below the span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSpan {
+    pub query: String,
+    /// Byte offset of the start of the span.
+    pub offset: usize,
+    /// Length of the span in bytes.
+    pub len: usize,
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let offset = cmp::min(self.offset, self.query.len());
+        let line_start = self.query[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.query[offset..].find('\n').map_or(self.query.len(), |i| offset + i);
+        let end = cmp::min(offset + self.len, line_end);
+        let indent = self.query[line_start..offset].chars().count();
+        let width = cmp::max(1, self.query.get(offset..end).map_or(1, |span| span.chars().count()));
+        write!(f, "  {}\n  {}{}", &self.query[line_start..line_end], " ".repeat(indent), "^".repeat(width))
+    }
+}
+
+/// Known name that is most similar to a name that does not exist, displayed as a "did you mean" hint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion(pub Option<String>);
+
+impl Suggestion {
+    /// Picks the candidate with the smallest edit distance to `name` if it is small enough to be a likely typo.
+    pub fn closest<'a, I: IntoIterator<Item=&'a String>>(name: &str, candidates: I) -> Suggestion {
+        let max_distance = cmp::max(1, name.chars().count() / 3);
+        let closest = candidates.into_iter()
+            .map(|candidate| (edit_distance(&name.to_lowercase(), &candidate.to_lowercase()), candidate))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .min();
+        Suggestion(closest.map(|(_, candidate)| candidate.clone()))
+    }
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref name) => write!(f, ", did you mean {}?", name),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Levenshtein distance between `a` and `b` in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = cmp::min(substitution, cmp::min(previous[j + 1] + 1, current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Byte offset of the first occurrence of the identifier `name` in `query` outside of string literals.
+fn find_identifier(query: &str, name: &str) -> Option<usize> {
+    let bytes = query.as_bytes();
+    let is_identifier_char = |c: u8| (c as char).is_alphanumeric() || c == b'_';
+    let mut in_string = false;
+    for i in 0..bytes.len() {
+        if bytes[i] == b'\'' {
+            in_string = !in_string;
+        } else if !in_string
+            && bytes[i..].starts_with(name.as_bytes())
+            && (i == 0 || !is_identifier_char(bytes[i - 1]))
+            && bytes.get(i + name.len()).map_or(true, |&c| !is_identifier_char(c)) {
+            return Some(i);
+        }
+    }
+    None
 }
 
 #[macro_export]
@@ -50,3 +190,27 @@ macro_rules! ensure {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggestion() {
+        let columns = vec!["timestamp".to_string(), "user_id".to_string(), "id".to_string()];
+        assert_eq!(Suggestion::closest("tmestamp", &columns), Suggestion(Some("timestamp".to_string())));
+        assert_eq!(Suggestion::closest("idd", &columns), Suggestion(Some("id".to_string())));
+        assert_eq!(Suggestion::closest("revenue", &columns), Suggestion(None));
+    }
+
+    #[test]
+    fn test_span() {
+        let error = QueryError::UnknownColumn {
+            column: "idd".to_string(),
+            table: "t".to_string(),
+            suggestion: Suggestion(Some("id".to_string())),
+        }.locate("SELECT 'idd', idd\nFROM t;");
+        assert_eq!(error.to_string(),
+                   "Column idd does not exist in table t, did you mean id?\n  SELECT 'idd', idd\n                ^^^");
+    }
+}
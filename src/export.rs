@@ -0,0 +1,233 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Component, Path};
+
+use ingest::raw_val::RawVal;
+
+
+/// File format of query results written by `COPY (SELECT ...) TO 'file'`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    /// Determines the format from the extension of `path` (`.csv` or `.parquet`).
+    pub fn detect(path: &str) -> Option<ExportFormat> {
+        let path = path.to_lowercase();
+        if path.ends_with(".csv") {
+            Some(ExportFormat::Csv)
+        } else if path.ends_with(".parquet") {
+            Some(ExportFormat::Parquet)
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes the rows of a query result to a file one batch at a time.
+pub trait ResultWriter {
+    fn write(&mut self, rows: &[Vec<RawVal>]) -> Result<(), String>;
+    /// Writes all remaining data and metadata to the file.
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+/// Location of `file` in `directory`. Absolute paths and paths that contain `..` are rejected so that files can only
+/// be written inside of `directory`.
+pub fn resolve(directory: &str, file: &str) -> Result<String, String> {
+    let outside = Path::new(file).components().any(|component| match component {
+        Component::Normal(_) | Component::CurDir => false,
+        Component::ParentDir | Component::RootDir | Component::Prefix(_) => true,
+    });
+    if outside || file.is_empty() {
+        return Err(format!("Path {} must be relative to the export directory and must not contain ..", file));
+    }
+    Ok(Path::new(directory).join(file).to_string_lossy().into_owned())
+}
+
+/// Creates the file `path` for a result with columns `colnames`. The types of the columns of Parquet files are
+/// determined from `rows`, the first batch of the result.
+pub fn create(path: &str, format: ExportFormat, colnames: &[String], rows: &[Vec<RawVal>]) -> Result<Box<ResultWriter>, String> {
+    if format == ExportFormat::Parquet && !cfg!(feature = "enable_parquet") {
+        return Err("Parquet not supported in this build of LocustDB. Recompile with --features enable_parquet.".to_string());
+    }
+    let file = File::create(path).map_err(|err| format!("Failed to create {}: {}", path, err))?;
+    match format {
+        ExportFormat::Csv => Ok(Box::new(CsvWriter::new(BufWriter::new(file), colnames)?)),
+        ExportFormat::Parquet => parquet_writer(file, colnames, rows),
+    }
+}
+
+#[cfg(feature = "enable_parquet")]
+fn parquet_writer(file: File, colnames: &[String], rows: &[Vec<RawVal>]) -> Result<Box<ResultWriter>, String> {
+    Ok(Box::new(parquet_export::ParquetWriter::new(file, colnames, rows)?))
+}
+
+#[cfg(not(feature = "enable_parquet"))]
+fn parquet_writer(_: File, _: &[String], _: &[Vec<RawVal>]) -> Result<Box<ResultWriter>, String> {
+    unreachable!()
+}
+
+struct CsvWriter<W: Write> {
+    output: W,
+}
+
+impl<W: Write> CsvWriter<W> {
+    fn new(mut output: W, colnames: &[String]) -> Result<CsvWriter<W>, String> {
+        let header = colnames.iter().map(|colname| csv_field(colname)).collect::<Vec<_>>();
+        writeln!(output, "{}", header.join(",")).map_err(|err| err.to_string())?;
+        Ok(CsvWriter { output })
+    }
+}
+
+impl<W: Write> ResultWriter for CsvWriter<W> {
+    fn write(&mut self, rows: &[Vec<RawVal>]) -> Result<(), String> {
+        for row in rows {
+            let fields = row.iter()
+                .map(|value| match value {
+                    RawVal::Null => String::new(),
+                    RawVal::Str(s) => csv_field(s),
+                    value => value.to_string(),
+                })
+                .collect::<Vec<_>>();
+            writeln!(self.output, "{}", fields.join(",")).map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.output.flush().map_err(|err| err.to_string())
+    }
+}
+
+/// Quotes `field` if it contains delimiters, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(feature = "enable_parquet")]
+mod parquet_export {
+    extern crate parquet;
+
+    use std::fs::File;
+    use std::rc::Rc;
+
+    use self::parquet::basic::{LogicalType, Repetition, Type as PhysicalType};
+    use self::parquet::column::writer::ColumnWriter;
+    use self::parquet::data_type::ByteArray;
+    use self::parquet::file::properties::WriterProperties;
+    use self::parquet::file::writer::{FileWriter, RowGroupWriter, SerializedFileWriter};
+    use self::parquet::schema::types::Type;
+
+    use ingest::raw_val::RawVal;
+    use super::ResultWriter;
+
+    /// Writes each batch of rows as a row group. All columns are optional and hold either integers, floats or strings.
+    pub struct ParquetWriter {
+        writer: SerializedFileWriter<File>,
+        colnames: Vec<String>,
+    }
+
+    impl ParquetWriter {
+        pub fn new(file: File, colnames: &[String], rows: &[Vec<RawVal>]) -> Result<ParquetWriter, String> {
+            let mut fields = colnames.iter().enumerate()
+                .map(|(i, colname)| {
+                    let physical_type = column_type(rows.iter().map(|row| &row[i]));
+                    let logical_type = match physical_type {
+                        PhysicalType::BYTE_ARRAY => LogicalType::UTF8,
+                        _ => LogicalType::NONE,
+                    };
+                    Type::primitive_type_builder(colname, physical_type)
+                        .with_repetition(Repetition::OPTIONAL)
+                        .with_logical_type(logical_type)
+                        .build()
+                        .map(Rc::new)
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| err.to_string())?;
+            let schema = Type::group_type_builder("schema")
+                .with_fields(&mut fields)
+                .build()
+                .map_err(|err| err.to_string())?;
+            let properties = WriterProperties::builder().build();
+            let writer = SerializedFileWriter::new(file, Rc::new(schema), Rc::new(properties))
+                .map_err(|err| err.to_string())?;
+            Ok(ParquetWriter { writer, colnames: colnames.to_vec() })
+        }
+    }
+
+    impl ResultWriter for ParquetWriter {
+        fn write(&mut self, rows: &[Vec<RawVal>]) -> Result<(), String> {
+            if rows.is_empty() {
+                return Ok(());
+            }
+            let mut row_group = self.writer.next_row_group().map_err(|err| err.to_string())?;
+            let mut column = 0;
+            while let Some(mut column_writer) = row_group.next_column().map_err(|err| err.to_string())? {
+                let colname = &self.colnames[column];
+                let def_levels = rows.iter()
+                    .map(|row| if row[column] == RawVal::Null { 0 } else { 1 })
+                    .collect::<Vec<i16>>();
+                let values = rows.iter().map(|row| &row[column]).filter(|value| **value != RawVal::Null);
+                let written = match column_writer {
+                    ColumnWriter::Int64ColumnWriter(ref mut typed) => {
+                        let values = values
+                            .map(|value| match value {
+                                RawVal::Int(int) => Ok(*int),
+                                value => Err(format!("Value {} of integer column {} is not an integer", value, colname)),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        typed.write_batch(&values, Some(&def_levels), None)
+                    }
+                    ColumnWriter::DoubleColumnWriter(ref mut typed) => {
+                        let values = values
+                            .map(|value| match value {
+                                RawVal::Int(int) => Ok(*int as f64),
+                                RawVal::Float(float) => Ok(float.0),
+                                value => Err(format!("Value {} of float column {} is not a number", value, colname)),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        typed.write_batch(&values, Some(&def_levels), None)
+                    }
+                    ColumnWriter::ByteArrayColumnWriter(ref mut typed) => {
+                        let values = values
+                            .map(|value| match value {
+                                RawVal::Str(s) => ByteArray::from(s.as_bytes().to_vec()),
+                                value => ByteArray::from(value.to_string().into_bytes()),
+                            })
+                            .collect::<Vec<_>>();
+                        typed.write_batch(&values, Some(&def_levels), None)
+                    }
+                    _ => return Err(format!("Unexpected type of column {}", colname)),
+                };
+                written.map_err(|err| err.to_string())?;
+                row_group.close_column(column_writer).map_err(|err| err.to_string())?;
+                column += 1;
+            }
+            self.writer.close_row_group(row_group).map_err(|err| err.to_string())
+        }
+
+        fn finish(mut self: Box<Self>) -> Result<(), String> {
+            self.writer.close().map_err(|err| err.to_string())
+        }
+    }
+
+    /// Integers if all non-null values are integers, floats if they are numbers and strings otherwise.
+    fn column_type<'a, I: Iterator<Item=&'a RawVal>>(values: I) -> PhysicalType {
+        let mut column_type = None;
+        for value in values {
+            column_type = match (column_type, value) {
+                (_, RawVal::Null) => column_type,
+                (Some(PhysicalType::BYTE_ARRAY), _) | (_, RawVal::Str(_)) => Some(PhysicalType::BYTE_ARRAY),
+                (None, RawVal::Int(_)) | (Some(PhysicalType::INT64), RawVal::Int(_)) => Some(PhysicalType::INT64),
+                _ => Some(PhysicalType::DOUBLE),
+            };
+        }
+        column_type.unwrap_or(PhysicalType::BYTE_ARRAY)
+    }
+}
@@ -0,0 +1,171 @@
+use std::cmp;
+use std::sync::Arc;
+
+use arrow::array::*;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+
+use ingest::raw_val::RawVal;
+use mem_store::column::Column;
+use mem_store::column_builder::*;
+use scheduler::*;
+
+
+/// Stores each record batch as a partition of `tablename`, converting the Arrow arrays column by column.
+pub fn ingest_batches(ldb: &InnerLocustDB, tablename: &str, batches: &[RecordBatch]) -> Result<(), String> {
+    ldb.ensure_writable()?;
+    for batch in batches {
+        if batch.num_rows() == 0 {
+            continue;
+        }
+        let mut partition = Vec::with_capacity(batch.num_columns());
+        for i in 0..batch.num_columns() {
+            let field = batch.schema().field(i);
+            partition.push(convert_array(field.name(), field.data_type(), batch.column(i))?);
+        }
+        ldb.store_partition(tablename, partition);
+    }
+    Ok(())
+}
+
+fn convert_array(name: &str, data_type: &DataType, array: &ArrayRef) -> Result<Arc<Column>, String> {
+    Ok(match *data_type {
+        DataType::Boolean => {
+            let array = downcast::<BooleanArray>(name, array)?;
+            let mut builder = IntColBuilder::new(true);
+            for i in 0..array.len() {
+                builder.push(&if array.is_null(i) { None } else { Some(array.value(i) as i64) });
+            }
+            builder.finalize(name)
+        }
+        DataType::Int32 => {
+            let array = downcast::<Int32Array>(name, array)?;
+            let mut builder = IntColBuilder::new(true);
+            for i in 0..array.len() {
+                builder.push(&if array.is_null(i) { None } else { Some(i64::from(array.value(i))) });
+            }
+            builder.finalize(name)
+        }
+        DataType::Int64 => {
+            let array = downcast::<Int64Array>(name, array)?;
+            let mut builder = IntColBuilder::new(true);
+            for i in 0..array.len() {
+                builder.push(&if array.is_null(i) { None } else { Some(array.value(i)) });
+            }
+            builder.finalize(name)
+        }
+        DataType::Float64 => {
+            let array = downcast::<Float64Array>(name, array)?;
+            let mut builder = FloatColBuilder::new(true);
+            for i in 0..array.len() {
+                builder.push(&if array.is_null(i) { None } else { Some(array.value(i)) });
+            }
+            builder.finalize(name)
+        }
+        DataType::Utf8 => {
+            let array = downcast::<BinaryArray>(name, array)?;
+            let mut builder = StringColBuilder::default();
+            for i in 0..array.len() {
+                let value = if array.is_null(i) { String::new() } else { array.get_string(i) };
+                builder.push(&value);
+            }
+            ColumnBuilder::<String>::finalize(builder, name)
+        }
+        ref t => return Err(format!("Column {} has unsupported Arrow type {:?}", name, t)),
+    })
+}
+
+fn downcast<'a, T: 'static>(name: &str, array: &'a ArrayRef) -> Result<&'a T, String> {
+    array.as_any().downcast_ref::<T>()
+        .ok_or_else(|| format!("Array of column {} does not match its type", name))
+}
+
+/// Converts query result rows into a record batch with one array per column.
+///
+/// Columns that contain only integers have type `Int64`, columns that also contain floats have type `Float64` and
+/// all other columns have type `Utf8`. Null values are preserved.
+pub fn to_record_batch(colnames: &[String], rows: &[Vec<RawVal>]) -> RecordBatch {
+    to_batch(&result_schema(colnames, rows), rows)
+}
+
+/// Converts query result rows into record batches of at most `batch_size` rows, which can be sent to clients one at
+/// a time. Column types are determined from all rows so that every batch has the same schema.
+///
+/// There is no Arrow Flight endpoint that sends these batches yet. It requires a gRPC server and the Arrow IPC format,
+/// neither of which is available with the version of the `arrow` crate LocustDB builds with.
+pub fn to_record_batches(colnames: &[String], rows: &[Vec<RawVal>], batch_size: usize) -> Vec<RecordBatch> {
+    let schema = result_schema(colnames, rows);
+    if rows.is_empty() {
+        return vec![to_batch(&schema, rows)];
+    }
+    rows.chunks(cmp::max(batch_size, 1))
+        .map(|chunk| to_batch(&schema, chunk))
+        .collect()
+}
+
+fn result_schema(colnames: &[String], rows: &[Vec<RawVal>]) -> Arc<Schema> {
+    let fields = colnames.iter().enumerate()
+        .map(|(i, name)| {
+            let data_type = if rows.iter().all(|row| is_int(&row[i])) {
+                DataType::Int64
+            } else if rows.iter().all(|row| is_int(&row[i]) || is_float(&row[i])) {
+                DataType::Float64
+            } else {
+                DataType::Utf8
+            };
+            Field::new(name, data_type, true)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn to_batch(schema: &Arc<Schema>, rows: &[Vec<RawVal>]) -> RecordBatch {
+    let mut arrays = Vec::with_capacity(schema.fields().len());
+    for (i, field) in schema.fields().iter().enumerate() {
+        let values = rows.iter().map(|row| &row[i]);
+        let array = match *field.data_type() {
+            DataType::Int64 => {
+                let ints = values.map(|v| match *v {
+                    RawVal::Int(int) => Some(int),
+                    _ => None,
+                }).collect::<Vec<_>>();
+                Arc::new(Int64Array::from(ints)) as ArrayRef
+            }
+            DataType::Float64 => {
+                let floats = values.map(|v| match *v {
+                    RawVal::Int(int) => Some(int as f64),
+                    RawVal::Float(float) => Some(float.0),
+                    _ => None,
+                }).collect::<Vec<_>>();
+                Arc::new(Float64Array::from(floats)) as ArrayRef
+            }
+            _ => {
+                let mut builder = BinaryBuilder::new(rows.len());
+                for v in values {
+                    match *v {
+                        RawVal::Null => builder.append_null().unwrap(),
+                        RawVal::Str(ref s) => builder.append_string(s).unwrap(),
+                        ref v => builder.append_string(&v.to_string()).unwrap(),
+                    }
+                }
+                Arc::new(builder.finish()) as ArrayRef
+            }
+        };
+        arrays.push(array);
+    }
+    RecordBatch::new(schema.clone(), arrays)
+}
+
+fn is_int(value: &RawVal) -> bool {
+    match *value {
+        RawVal::Int(_) | RawVal::Null => true,
+        _ => false,
+    }
+}
+
+fn is_float(value: &RawVal) -> bool {
+    match *value {
+        RawVal::Float(_) => true,
+        _ => false,
+    }
+}
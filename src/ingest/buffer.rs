@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use mem_store::column::Column;
 use mem_store::raw_col::MixedCol;
 use ingest::raw_val::RawVal;
 use ingest::input_column::InputColumn;
 use std::cmp;
 
+/// Number of rows in the partitions created by the CSV and JSON loaders.
+pub const LOADER_PARTITION_ROWS: usize = 1 << 16;
 
 #[derive(PartialEq, Debug, HeapSizeOf)]
 pub struct Buffer {
@@ -42,6 +46,9 @@ impl Buffer {
                 InputColumn::Int(vec) => buffered_col.push_ints(vec),
                 InputColumn::Str(vec) => buffered_col.push_strings(vec),
                 InputColumn::Null(c) => buffered_col.push_nulls(c),
+                InputColumn::Values(vals) => for val in vals {
+                    buffered_col.push(val);
+                },
             }
             new_length = cmp::max(new_length, buffered_col.len())
         }
@@ -77,5 +84,16 @@ impl Buffer {
     pub fn len(&self) -> usize {
         self.length
     }
+
+    pub fn into_columns(self) -> Vec<Arc<Column>> {
+        self.columns()
+    }
+
+    /// Columns with the rows buffered so far, which leaves the buffer unchanged.
+    pub fn columns(&self) -> Vec<Arc<Column>> {
+        self.buffer.iter()
+            .map(|(name, raw_col)| raw_col.finalize(name))
+            .collect()
+    }
 }
 
@@ -0,0 +1,66 @@
+extern crate flate2;
+#[cfg(feature = "enable_zstd")]
+extern crate zstd;
+
+use std::fs::File;
+use std::io::Read;
+
+use self::flate2::read::GzDecoder;
+
+use super::object_store;
+
+
+/// Compression of an input file, which is decompressed while the file is ingested.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Determines the compression from the extension of `filename` (`.gz` or `.zst`).
+    pub fn detect(filename: &str) -> Compression {
+        if filename.ends_with(".gz") {
+            Compression::Gzip
+        } else if filename.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens `filename`, which may also be the URL of an object (see `object_store::open`), for reading and decompresses
+/// its contents on the fly.
+pub fn open(filename: &str, compression: Compression) -> Result<Box<Read>, String> {
+    decompress(open_raw(filename)?, compression)
+}
+
+/// Opens `filename` for reading without decompressing its contents.
+pub fn open_raw(filename: &str) -> Result<Box<Read>, String> {
+    if object_store::is_url(filename) {
+        object_store::open(filename)
+    } else {
+        Ok(Box::new(File::open(filename).map_err(|x| x.to_string())?))
+    }
+}
+
+pub fn decompress(f: Box<Read>, compression: Compression) -> Result<Box<Read>, String> {
+    Ok(match compression {
+        Compression::None => Box::new(f),
+        Compression::Gzip => Box::new(GzDecoder::new(f)),
+        Compression::Zstd => zstd_decoder(f)?,
+    })
+}
+
+#[cfg(feature = "enable_zstd")]
+fn zstd_decoder(f: Box<Read>) -> Result<Box<Read>, String> {
+    let decoder = zstd::stream::read::Decoder::new(f).map_err(|x| x.to_string())?;
+    Ok(Box::new(decoder))
+}
+
+#[cfg(not(feature = "enable_zstd"))]
+fn zstd_decoder(_: Box<Read>) -> Result<Box<Read>, String> {
+    Err("zstd not supported in this build of LocustDB. Recompile with --features enable_zstd.".to_string())
+}
@@ -1,17 +1,25 @@
 extern crate csv;
-extern crate flate2;
 
 use mem_store::column::*;
 use mem_store::column_builder::*;
-use mem_store::strings::fast_build_string_column;
+use mem_store::strings::{build_list_column, fast_build_string_column};
 use scheduler::*;
-use self::flate2::read::GzDecoder;
+use scoped_threadpool::Pool;
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
+use std::mem;
 use std::ops::BitOr;
 use std::str;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use super::buffer::LOADER_PARTITION_ROWS;
+use super::compression::{self, Compression};
 use super::extractor;
+use super::list;
+use super::load_transaction::{self, StagedLoad};
+use super::progress::{CountingReader, LoadProgress, ProgressCallback};
+use super::regex_transform::{self, RegexTransform};
+use super::schema::*;
 use stringpack::*;
 
 type IngestionTransform = HashMap<String, extractor::Extractor>;
@@ -22,10 +30,20 @@ pub struct Options {
     partition_size: usize,
     colnames: Option<Vec<String>>,
     extractors: IngestionTransform,
+    regex_transforms: Vec<RegexTransform>,
     ignore_cols: HashSet<String>,
+    select_cols: Option<HashSet<String>>,
     always_string: HashSet<String>,
+    schema: Schema,
+    null_tokens: HashSet<String>,
     allow_nulls: bool,
-    unzip: bool,
+    compression: Compression,
+    delimiter: u8,
+    quote: u8,
+    quoting: bool,
+    escape: Option<u8>,
+    max_in_flight_partitions: usize,
+    progress: Option<ProgressCallback>,
 }
 
 impl Options {
@@ -33,13 +51,23 @@ impl Options {
         Options {
             filename: filename.to_owned(),
             tablename: tablename.to_owned(),
-            partition_size: 1 << 16,
+            partition_size: LOADER_PARTITION_ROWS,
             colnames: None,
             extractors: HashMap::new(),
+            regex_transforms: Vec::new(),
             ignore_cols: HashSet::new(),
+            select_cols: None,
             always_string: HashSet::new(),
+            schema: Schema::default(),
+            null_tokens: HashSet::new(),
             allow_nulls: false,
-            unzip: filename.ends_with(".gz"),
+            compression: Compression::detect(filename),
+            delimiter: b',',
+            quote: b'"',
+            quoting: true,
+            escape: None,
+            max_in_flight_partitions: 1,
+            progress: None,
         }
     }
 
@@ -58,11 +86,29 @@ impl Options {
         self
     }
 
+    /// Adds the columns derived by `transform`, which are loaded even if its source column is ignored.
+    pub fn with_regex_transform(mut self, transform: RegexTransform) -> Options {
+        self.regex_transforms.push(transform);
+        self
+    }
+
     pub fn with_ignore_cols(mut self, ignore: &[String]) -> Options {
         self.ignore_cols = ignore.into_iter().map(|x| x.to_owned()).collect();
         self
     }
 
+    /// Overrides the compression determined from the file extension.
+    pub fn with_compression(mut self, compression: Compression) -> Options {
+        self.compression = compression;
+        self
+    }
+
+    /// Only loads the columns with the given names.
+    pub fn with_columns(mut self, columns: &[&str]) -> Options {
+        self.select_cols = Some(columns.iter().map(|&x| x.to_owned()).collect());
+        self
+    }
+
     pub fn with_always_string(mut self, always_string: &[&str]) -> Options {
         self.always_string = always_string.into_iter().map(|&x| x.to_owned()).collect();
         self
@@ -72,79 +118,209 @@ impl Options {
         self.allow_nulls = true;
         self
     }
+
+    /// Types of columns that override type inference.
+    pub fn with_schema(mut self, schema: Schema) -> Options {
+        self.schema = schema;
+        self
+    }
+
+    /// Values that are loaded as null, in addition to empty fields. Implies `allow_nulls`.
+    pub fn with_null_tokens(mut self, tokens: &[&str]) -> Options {
+        self.null_tokens = tokens.iter().map(|&x| x.to_owned()).collect();
+        self.allow_nulls = true;
+        self
+    }
+
+    /// Field delimiter, e.g. `b'\t'` for TSV files.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Options {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_quote(mut self, quote: u8) -> Options {
+        self.quote = quote;
+        self
+    }
+
+    /// Treat quote characters as part of the field value.
+    pub fn without_quoting(mut self) -> Options {
+        self.quoting = false;
+        self
+    }
+
+    /// Character that escapes quotes within quoted fields, quotes are escaped by doubling them if not set.
+    pub fn with_escape(mut self, escape: u8) -> Options {
+        self.escape = Some(escape);
+        self
+    }
+
+    /// Number of partitions of raw values that may wait to be compressed, after which reading the file pauses until
+    /// compression catches up. Each such partition holds `partition_size` rows in memory.
+    pub fn with_max_in_flight_partitions(mut self, partitions: usize) -> Options {
+        self.max_in_flight_partitions = partitions;
+        self
+    }
+
+    /// Calls `callback` every time a partition of the file has been sealed.
+    pub fn with_progress<F>(mut self, callback: F) -> Options
+        where F: Fn(&LoadProgress) + Send + Sync + 'static {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder.has_headers(self.colnames.is_none())
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .quoting(self.quoting)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none());
+        builder
+    }
 }
 
-pub fn ingest_file(ldb: &InnerLocustDB, opts: &Options) -> Result<(), String> {
-    // Can't combine these two branches because csv::Reader takes a type param which differs for creating from Reader/File
-    if opts.unzip {
-        let f = File::open(&opts.filename).map_err(|x| x.to_string())?;
-        let decoded = GzDecoder::new(f);
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(opts.colnames.is_none())
-            .from_reader(decoded);
-        let headers = match opts.colnames {
-            Some(ref colnames) => colnames.clone(),
-            None => reader.headers().unwrap().iter().map(str::to_owned).collect()
-        };
-        auto_ingest(ldb, reader.records().map(|r| r.unwrap()), &headers, opts)
-    } else {
-        let mut reader = csv::ReaderBuilder::new()
-            .has_headers(opts.colnames.is_none())
-            .from_path(&opts.filename)
-            .map_err(|x| x.to_string())?;
-        let headers = match opts.colnames {
-            Some(ref colnames) => colnames.clone(),
-            None => reader.headers().unwrap().iter().map(str::to_owned).collect()
-        };
-        auto_ingest(ldb, reader.records().map(|r| r.unwrap()), &headers, opts)
+/// Loads the file into its table, or stages its partitions if `staging` is the transaction it is loaded as part of.
+pub fn ingest_file(ldb: &InnerLocustDB, opts: &Options, staging: Option<&StagedLoad>) -> Result<(), String> {
+    ldb.ensure_writable()?;
+    if let Some(staging) = staging {
+        staging.check(&opts.tablename)?;
     }
+    let bytes_read = Arc::new(AtomicUsize::new(0));
+    let file = CountingReader::new(compression::open_raw(&opts.filename)?, bytes_read.clone());
+    let input = compression::decompress(Box::new(file), opts.compression)?;
+    let mut reader = opts.reader_builder().from_reader(input);
+    let headers = match opts.colnames {
+        Some(ref colnames) => colnames.clone(),
+        None => reader.headers().map_err(|x| x.to_string())?.iter().map(str::to_owned).collect()
+    };
+    auto_ingest(ldb, reader.records(), &headers, opts, staging, &bytes_read)
 }
 
-fn auto_ingest<T>(ldb: &InnerLocustDB, records: T, colnames: &[String], opts: &Options) -> Result<(), String>
-    where T: Iterator<Item=csv::StringRecord> {
-    let ignore = colnames.iter().map(|x| opts.ignore_cols.contains(x)).collect::<Vec<_>>();
+/// Parses `records` into partitions of raw values on the calling thread and compresses them on a second thread.
+fn auto_ingest<T>(ldb: &InnerLocustDB,
+                  records: T,
+                  colnames: &[String],
+                  opts: &Options,
+                  staging: Option<&StagedLoad>,
+                  bytes_read: &AtomicUsize) -> Result<(), String>
+    where T: Iterator<Item=csv::Result<csv::StringRecord>> {
+    // Derived columns follow the columns of the file
+    let mut sources = Vec::with_capacity(opts.regex_transforms.len());
+    for transform in &opts.regex_transforms {
+        match colnames.iter().position(|col| col == transform.source()) {
+            Some(index) => sources.push(index),
+            None => return Err(format!("Column {} does not exist in {}", transform.source(), opts.filename)),
+        }
+    }
+    let mut colnames = colnames.to_vec();
+    let derived = regex_transform::derived_columns(&opts.regex_transforms, &colnames)?;
+    let file_cols = colnames.len();
+    colnames.extend(derived);
+    let colnames = &colnames[..];
+    if let Some(ref select_cols) = opts.select_cols {
+        if let Some(missing) = select_cols.iter().find(|&col| !colnames.contains(col)) {
+            return Err(format!("Column {} does not exist in {}", missing, opts.filename));
+        }
+    }
+    // Types declared with the table are overridden by those passed to the loader
+    let schema = ldb.table_schema(&opts.tablename).unwrap_or_default().merge(&opts.schema);
+    let ignore = colnames.iter()
+        .map(|x| opts.ignore_cols.contains(x) || opts.select_cols.as_ref().map_or(false, |cols| !cols.contains(x)))
+        .collect::<Vec<_>>();
     let string = colnames.iter().map(|x| opts.always_string.contains(x)).collect::<Vec<_>>();
-    let mut raw_cols = (0..colnames.len()).map(|_| RawCol::new(opts.allow_nulls)).collect::<Vec<_>>();
-    let mut row_num = 0usize;
-    for row in records {
-        for (i, val) in row.iter().enumerate() {
-            if !ignore[i] {
-                raw_cols[i].push(val);
-            }
+    let new_raw_cols = || (0..colnames.len()).map(|_| RawCol::new(opts.allow_nulls)).collect::<Vec<_>>();
+    // Bounds the memory used by raw values when compression is slower than reading
+    let (sender, receiver) = mpsc::sync_channel::<(Vec<RawCol>, usize)>(opts.max_in_flight_partitions);
+    let mut compressed = Ok(());
+    let mut read = Ok(());
+    Pool::new(1).scoped(|scope| {
+        {
+            let (schema, ignore, string, compressed) = (&schema, &ignore, &string, &mut compressed);
+            scope.execute(move || {
+                let mut progress = LoadProgress::default();
+                for (mut raw_cols, rows) in receiver {
+                    let partition = create_batch(&mut raw_cols, colnames, opts, schema, ignore, string)
+                        .and_then(|partition| load_transaction::store_partition(ldb, &opts.tablename, staging, partition));
+                    if let Err(err) = partition {
+                        // Dropping the receiver stops the reader
+                        *compressed = Err(err);
+                        return;
+                    }
+                    progress.bytes_read = bytes_read.load(Ordering::Relaxed);
+                    progress.rows_ingested += rows;
+                    progress.partitions_sealed += 1;
+                    if let Some(ref callback) = opts.progress {
+                        callback(&progress);
+                    }
+                }
+            });
         }
 
-        if row_num % opts.partition_size == opts.partition_size - 1 {
-            let partition = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &string);
-            ldb.store_partition(&opts.tablename, partition);
+        let mut raw_cols = new_raw_cols();
+        let mut row_num = 0usize;
+        for row in records {
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => {
+                    read = Err(format!("Failed to parse {}: {}", opts.filename, err));
+                    break;
+                }
+            };
+            for (i, val) in row.iter().enumerate() {
+                if !ignore[i] {
+                    raw_cols[i].push(if opts.null_tokens.contains(val) { "" } else { val });
+                }
+            }
+            let mut i = file_cols;
+            for (transform, &source) in opts.regex_transforms.iter().zip(sources.iter()) {
+                let val = row.get(source).unwrap_or("");
+                let val = if opts.null_tokens.contains(val) { "" } else { val };
+                for val in transform.apply(val) {
+                    if !ignore[i] {
+                        raw_cols[i].push(val.unwrap_or(""));
+                    }
+                    i += 1;
+                }
+            }
+
+            row_num += 1;
+            if row_num % opts.partition_size == 0 {
+                let full = mem::replace(&mut raw_cols, new_raw_cols());
+                if sender.send((full, opts.partition_size)).is_err() {
+                    break;
+                }
+            }
         }
-        row_num += 1;
-    }
 
-    if row_num % opts.partition_size != 0 {
-        let partition = create_batch(&mut raw_cols, colnames, &opts.extractors, &ignore, &string);
-        ldb.store_partition(&opts.tablename, partition);
-    }
-    Ok(())
+        if read.is_ok() && row_num % opts.partition_size != 0 {
+            let _ = sender.send((raw_cols, row_num % opts.partition_size));
+        }
+        drop(sender);
+    });
+    compressed.and(read)
 }
 
-fn create_batch(cols: &mut [RawCol], colnames: &[String], extractors: &IngestionTransform, ignore: &[bool], string: &[bool]) -> Vec<Arc<Column>> {
+fn create_batch(cols: &mut [RawCol], colnames: &[String], opts: &Options, schema: &Schema, ignore: &[bool], string: &[bool]) -> Result<Vec<Arc<Column>>, String> {
     let mut mem_store = Vec::new();
     for (i, col) in cols.iter_mut().enumerate() {
         if !ignore[i] {
-            let new_column = match extractors.get(&colnames[i]) {
+            let new_column = match opts.extractors.get(&colnames[i]) {
                 Some(extractor) => col.extract(&colnames[i], *extractor),
-                None => col.finalize(&colnames[i], string[i]),
+                None => col.finalize(&colnames[i], string[i], schema.get(&colnames[i]))?,
             };
             mem_store.push(new_column);
         }
     }
-    mem_store
+    Ok(mem_store)
 }
 
 pub struct CSVIngestionTask {
     options: Options,
     locustdb: Arc<InnerLocustDB>,
     sender: SharedSender<Result<(), String>>,
+    staging: Option<Arc<StagedLoad>>,
 }
 
 impl CSVIngestionTask {
@@ -155,16 +331,28 @@ impl CSVIngestionTask {
             options,
             locustdb,
             sender,
+            staging: None,
         }
     }
+
+    /// Loads the file as part of a transaction.
+    pub fn staged(mut self, staging: Arc<StagedLoad>) -> CSVIngestionTask {
+        self.staging = Some(staging);
+        self
+    }
 }
 
 impl Task for CSVIngestionTask {
     fn execute(&self) {
-        self.sender.send(ingest_file(&self.locustdb, &self.options))
+        let result = ingest_file(&self.locustdb, &self.options, self.staging.as_ref().map(|staging| &**staging));
+        if let Some(ref staging) = self.staging {
+            staging.load_completed(&result);
+        }
+        self.sender.send(result)
     }
     fn completed(&self) -> bool { false }
     fn multithreaded(&self) -> bool { false }
+    fn priority(&self) -> Priority { Priority::Background }
 }
 
 
@@ -197,30 +385,64 @@ impl RawCol {
         self.values.push(elem);
     }
 
-    fn finalize(&mut self, name: &str, string: bool) -> Arc<Column> {
-        let result = if self.types.contains_string || string {
-            fast_build_string_column(name, self.values.iter(), self.values.len(),
-                                     self.lhex, self.uhex, self.string_bytes)
-        } else if self.types.contains_int {
-            let mut builder = IntColBuilder::default();
-            for s in self.values.iter() {
-                let int = if s.is_empty() {
-                    if self.allow_null { None } else { Some(0) }
-                } else if let Ok(int) = s.parse::<i64>() {
-                    Some(int)
-                } else if let Ok(float) = s.parse::<f64>() {
-                    Some(float as i64)
-                } else {
-                    unreachable!("{} should be parseable as int or float. {} {:?}", s, name, self.types)
-                };
-                builder.push(&int);
+    /// Builds a column from the pushed values, the type is taken from `schema` if given and inferred otherwise.
+    fn finalize(&mut self, name: &str, string: bool, schema: Option<&ColumnSchema>) -> Result<Arc<Column>, String> {
+        let column_type = match schema {
+            Some(schema) => Some(schema.column_type),
+            None if self.types.contains_string || string => Some(ColumnType::String),
+            None if self.types.contains_float => Some(ColumnType::Float),
+            None if self.types.contains_int => Some(ColumnType::Integer),
+            None => None,
+        };
+        let allow_null = schema.map_or(self.allow_null, |schema| schema.nullable);
+        let hex = schema.map_or(true, |schema| schema.encoding != EncodingHint::NoHex);
+        let result = match column_type {
+            Some(ColumnType::String) => fast_build_string_column(
+                name, self.values.iter(), self.values.len(),
+                hex && self.lhex, hex && self.uhex, self.string_bytes),
+            Some(ColumnType::StringList) => {
+                let mut lists = Vec::with_capacity(self.values.len());
+                for s in self.values.iter() {
+                    match list::decode(s) {
+                        Some(elements) => lists.push(elements),
+                        None if s.is_empty() => lists.push(vec![]),
+                        None => return Err(format!("Value {} of column {} is not a list of strings", s, name)),
+                    }
+                }
+                Arc::new(build_list_column(name, &lists))
             }
-            builder.finalize(name)
-        } else {
-            Arc::new(Column::null(name, self.values.len()))
+            Some(ColumnType::Float) => {
+                let mut builder = FloatColBuilder::new(allow_null);
+                for s in self.values.iter() {
+                    let float = if s.is_empty() {
+                        if allow_null { None } else { Some(0.0) }
+                    } else if let Ok(float) = s.parse::<f64>() {
+                        Some(float)
+                    } else {
+                        return Err(format!("Value {} of column {} is not a float", s, name));
+                    };
+                    builder.push(&float);
+                }
+                builder.finalize(name)
+            }
+            Some(ColumnType::Integer) => {
+                let mut builder = IntColBuilder::default();
+                for s in self.values.iter() {
+                    let int = if s.is_empty() {
+                        if allow_null { None } else { Some(0) }
+                    } else if let Ok(int) = s.parse::<i64>() {
+                        Some(int)
+                    } else {
+                        return Err(format!("Value {} of column {} is not an integer", s, name));
+                    };
+                    builder.push(&int);
+                }
+                builder.finalize(name)
+            }
+            None => Arc::new(Column::null(name, self.values.len())),
         };
         self.clear();
-        result
+        Ok(result)
     }
 
     fn extract(&mut self, name: &str, extractor: extractor::Extractor) -> Arc<Column> {
@@ -261,35 +483,42 @@ fn is_uppercase_hex(string: &str) -> bool {
 struct ColType {
     contains_string: bool,
     contains_int: bool,
+    contains_float: bool,
     contains_null: bool,
 }
 
 impl ColType {
-    fn new(string: bool, int: bool, null: bool) -> ColType {
-        ColType { contains_string: string, contains_int: int, contains_null: null }
+    fn new(string: bool, int: bool, float: bool, null: bool) -> ColType {
+        ColType { contains_string: string, contains_int: int, contains_float: float, contains_null: null }
     }
 
     fn string() -> ColType {
-        ColType::new(true, false, false)
+        ColType::new(true, false, false, false)
     }
 
     fn int() -> ColType {
-        ColType::new(false, true, false)
+        ColType::new(false, true, false, false)
+    }
+
+    fn float() -> ColType {
+        ColType::new(false, false, true, false)
     }
 
     fn null() -> ColType {
-        ColType::new(false, false, true)
+        ColType::new(false, false, false, true)
     }
 
     fn nothing() -> ColType {
-        ColType::new(false, false, false)
+        ColType::new(false, false, false, false)
     }
 
     fn determine(s: &str) -> ColType {
         if s.is_empty() {
             ColType::null()
-        } else if s.parse::<i64>().is_ok() || s.parse::<f64>().is_ok() {
+        } else if s.parse::<i64>().is_ok() {
             ColType::int()
+        } else if s.parse::<f64>().is_ok() {
+            ColType::float()
         } else {
             ColType::string()
         }
@@ -302,6 +531,7 @@ impl BitOr for ColType {
         ColType {
             contains_string: self.contains_string | rhs.contains_string,
             contains_int: self.contains_int | rhs.contains_int,
+            contains_float: self.contains_float | rhs.contains_float,
             contains_null: self.contains_null | rhs.contains_null,
         }
     }
@@ -1,7 +1,9 @@
-#[allow(dead_code)]
+use ingest::raw_val::RawVal;
+
 pub enum InputColumn {
     Int(Vec<i64>),
     Str(Vec<String>),
     Null(usize),
+    /// Values of any type, which are converted one at a time.
+    Values(Vec<RawVal>),
 }
-
@@ -0,0 +1,241 @@
+extern crate serde_json;
+
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read};
+use std::sync::Arc;
+
+use engine::data_types::BasicType;
+use ingest::buffer::{Buffer, LOADER_PARTITION_ROWS};
+use ingest::compression::{self, Compression};
+use ingest::load_transaction::{self, StagedLoad};
+use ingest::raw_val::RawVal;
+use ingest::regex_transform::{self, RegexTransform};
+use ingest::schema::*;
+use scheduler::*;
+use self::serde_json::Value;
+
+
+/// Determines the types of columns loaded from JSON.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TypeInference {
+    /// Columns take the most general type of their values, integers are widened to floats and numbers to strings.
+    Widen,
+    /// Loading fails if a column contains values of different types (integers, floats and strings).
+    Strict,
+    /// All values are loaded as strings.
+    Strings,
+}
+
+pub struct Options {
+    filename: String,
+    tablename: String,
+    partition_size: usize,
+    type_inference: TypeInference,
+    regex_transforms: Vec<RegexTransform>,
+    ignore_cols: HashSet<String>,
+    always_string: HashSet<String>,
+    schema: Schema,
+    compression: Compression,
+}
+
+impl Options {
+    pub fn new(filename: &str, tablename: &str) -> Options {
+        Options {
+            filename: filename.to_owned(),
+            tablename: tablename.to_owned(),
+            partition_size: LOADER_PARTITION_ROWS,
+            type_inference: TypeInference::Widen,
+            regex_transforms: Vec::new(),
+            ignore_cols: HashSet::new(),
+            always_string: HashSet::new(),
+            schema: Schema::default(),
+            compression: Compression::detect(filename),
+        }
+    }
+
+    pub fn with_partition_size(mut self, chunk_size: usize) -> Options {
+        self.partition_size = chunk_size;
+        self
+    }
+
+    pub fn with_type_inference(mut self, type_inference: TypeInference) -> Options {
+        self.type_inference = type_inference;
+        self
+    }
+
+    /// Adds the string columns derived by `transform`, which are loaded even if its source field is ignored.
+    pub fn with_regex_transform(mut self, transform: RegexTransform) -> Options {
+        self.regex_transforms.push(transform);
+        self
+    }
+
+    /// Types of columns that override type inference.
+    pub fn with_schema(mut self, schema: Schema) -> Options {
+        self.schema = schema;
+        self
+    }
+
+    /// Overrides the compression determined from the file extension.
+    pub fn with_compression(mut self, compression: Compression) -> Options {
+        self.compression = compression;
+        self
+    }
+
+    pub fn with_ignore_cols(mut self, ignore: &[String]) -> Options {
+        self.ignore_cols = ignore.into_iter().map(|x| x.to_owned()).collect();
+        self
+    }
+
+    pub fn with_always_string(mut self, always_string: &[&str]) -> Options {
+        self.always_string = always_string.into_iter().map(|&x| x.to_owned()).collect();
+        self
+    }
+}
+
+/// Loads the file into its table, or stages its partitions if `staging` is the transaction it is loaded as part of.
+pub fn ingest_file(ldb: &InnerLocustDB, opts: &Options, staging: Option<&StagedLoad>) -> Result<(), String> {
+    ldb.ensure_writable()?;
+    if let Some(staging) = staging {
+        staging.check(&opts.tablename)?;
+    }
+    let input = compression::open(&opts.filename, opts.compression)?;
+    ingest_lines(ldb, BufReader::new(input), opts, staging)
+}
+
+/// Loads one JSON object per line, each top-level field becomes a column and missing fields are null.
+fn ingest_lines<R: Read>(ldb: &InnerLocustDB,
+                         reader: BufReader<R>,
+                         opts: &Options,
+                         staging: Option<&StagedLoad>) -> Result<(), String> {
+    // Types declared with the table are overridden by those passed to the loader
+    let schema = ldb.table_schema(&opts.tablename).unwrap_or_default().merge(&opts.schema);
+    let mut column_types = HashMap::<String, BasicType>::new();
+    let derived_columns = regex_transform::derived_columns(&opts.regex_transforms, &[])?;
+    let mut buffer = Buffer::default();
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line.map_err(|x| x.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = match serde_json::from_str(&line) {
+            Ok(Value::Object(fields)) => fields,
+            Ok(_) => return Err(format!("Line {} is not a JSON object", line_num + 1)),
+            Err(err) => return Err(format!("Failed to parse line {}: {}", line_num + 1, err)),
+        };
+
+        if let Some(column) = derived_columns.iter().find(|&column| fields.contains_key(column)) {
+            return Err(format!("Line {}: field {} is also derived by a regex transform", line_num + 1, column));
+        }
+        // Fields that are not strings are treated like empty strings
+        let mut derived = Vec::with_capacity(derived_columns.len());
+        for transform in &opts.regex_transforms {
+            let source = fields.get(transform.source()).and_then(Value::as_str).unwrap_or("");
+            for (column, value) in transform.columns().iter().zip(transform.apply(source)) {
+                let value = value.map_or(Value::Null, |value| Value::String(value.to_owned()));
+                derived.push((column.clone(), value));
+            }
+        }
+
+        let mut row = Vec::with_capacity(fields.len() + derived.len());
+        for (name, value) in fields.into_iter().chain(derived) {
+            if opts.ignore_cols.contains(&name) {
+                continue;
+            }
+            let value = if let Some(column) = schema.get(&name) {
+                column.convert(&name, to_raw_val(value))
+                    .map_err(|err| format!("Line {}: {}", line_num + 1, err))?
+            } else if opts.always_string.contains(&name) {
+                to_string(to_raw_val(value))
+            } else {
+                match opts.type_inference {
+                    TypeInference::Widen => to_raw_val(value),
+                    TypeInference::Strings => to_string(to_raw_val(value)),
+                    TypeInference::Strict => {
+                        let value = to_raw_val(value);
+                        if value != RawVal::Null {
+                            let t = *column_types.entry(name.clone()).or_insert_with(|| value.get_type());
+                            if t != value.get_type() {
+                                return Err(format!("Line {}: column {} contains values of type {:?} and {:?}",
+                                                   line_num + 1, name, t, value.get_type()));
+                            }
+                        }
+                        value
+                    }
+                }
+            };
+            row.push((name, value));
+        }
+        buffer.push_row(row);
+
+        if buffer.len() == opts.partition_size {
+            let partition = ::std::mem::replace(&mut buffer, Buffer::default()).into_columns();
+            load_transaction::store_partition(ldb, &opts.tablename, staging, partition)?;
+        }
+    }
+
+    if buffer.len() > 0 {
+        load_transaction::store_partition(ldb, &opts.tablename, staging, buffer.into_columns())?;
+    }
+    Ok(())
+}
+
+pub fn to_raw_val(value: Value) -> RawVal {
+    match value {
+        Value::Null => RawVal::Null,
+        Value::Bool(b) => RawVal::Int(b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(int) => RawVal::Int(int),
+            None => RawVal::Float(n.as_f64().unwrap_or(0.0).into()),
+        },
+        Value::String(s) => RawVal::Str(s),
+        // Nested values are kept as JSON strings
+        value @ Value::Array(_) | value @ Value::Object(_) => RawVal::Str(value.to_string()),
+    }
+}
+
+fn to_string(value: RawVal) -> RawVal {
+    match value {
+        RawVal::Int(i) => RawVal::Str(i.to_string()),
+        RawVal::Float(f) => RawVal::Str(f.to_string()),
+        value => value,
+    }
+}
+
+pub struct JSONIngestionTask {
+    options: Options,
+    locustdb: Arc<InnerLocustDB>,
+    sender: SharedSender<Result<(), String>>,
+    staging: Option<Arc<StagedLoad>>,
+}
+
+impl JSONIngestionTask {
+    pub fn new(options: Options,
+               locustdb: Arc<InnerLocustDB>,
+               sender: SharedSender<Result<(), String>>) -> JSONIngestionTask {
+        JSONIngestionTask {
+            options,
+            locustdb,
+            sender,
+            staging: None,
+        }
+    }
+
+    /// Loads the file as part of a transaction.
+    pub fn staged(mut self, staging: Arc<StagedLoad>) -> JSONIngestionTask {
+        self.staging = Some(staging);
+        self
+    }
+}
+
+impl Task for JSONIngestionTask {
+    fn execute(&self) {
+        let result = ingest_file(&self.locustdb, &self.options, self.staging.as_ref().map(|staging| &**staging));
+        if let Some(ref staging) = self.staging {
+            staging.load_completed(&result);
+        }
+        self.sender.send(result)
+    }
+    fn completed(&self) -> bool { false }
+    fn multithreaded(&self) -> bool { false }
+    fn priority(&self) -> Priority { Priority::Background }
+}
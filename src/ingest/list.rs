@@ -0,0 +1,155 @@
+use std::char;
+use std::iter::Peekable;
+use std::str::Chars;
+
+
+/// Serializes `elements` as JSON array, e.g. `["a","b"]`. Lists are ingested and returned by queries in this form, and
+/// buffered rows of list columns are stored as such strings.
+pub fn encode<S: AsRef<str>>(elements: &[S]) -> String {
+    let mut list = String::from("[");
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            list.push(',');
+        }
+        push_json_string(&mut list, element.as_ref());
+    }
+    list.push(']');
+    list
+}
+
+/// Appends `string` to `out` as quoted and escaped JSON string.
+pub fn push_json_string(out: &mut String, string: &str) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parses a JSON array of strings. Numbers and booleans are converted to strings and null elements are skipped.
+/// Returns `None` if `list` is not an array of such values.
+pub fn decode(list: &str) -> Option<Vec<String>> {
+    let mut chars = list.trim().chars().peekable();
+    if chars.next() != Some('[') {
+        return None;
+    }
+    let mut elements = Vec::new();
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&']') {
+        chars.next();
+        return if chars.next().is_none() { Some(elements) } else { None };
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            elements.push(parse_string(&mut chars)?);
+        } else {
+            let mut scalar = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == ']' || c.is_whitespace() {
+                    break;
+                }
+                scalar.push(c);
+                chars.next();
+            }
+            match scalar.as_ref() {
+                "null" => {}
+                "true" | "false" => elements.push(scalar),
+                _ if scalar.parse::<f64>().is_ok() => elements.push(scalar),
+                _ => return None,
+            }
+        }
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => {}
+            ']' => break,
+            _ => return None,
+        }
+    }
+    if chars.next().is_none() { Some(elements) } else { None }
+}
+
+/// Whether `list` is a JSON array that contains `element`.
+pub fn contains(list: &str, element: &str) -> bool {
+    decode(list).map_or(false, |elements| elements.iter().any(|e| e == element))
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+// Parses the remainder of a string literal after the opening quote
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                '"' => string.push('"'),
+                '\\' => string.push('\\'),
+                '/' => string.push('/'),
+                'b' => string.push('\u{8}'),
+                'f' => string.push('\u{c}'),
+                'n' => string.push('\n'),
+                'r' => string.push('\r'),
+                't' => string.push('\t'),
+                'u' => {
+                    let high = parse_hex(chars)?;
+                    // Characters outside the basic multilingual plane are escaped as surrogate pairs
+                    let code = if high >= 0xd800 && high < 0xdc00 {
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = parse_hex(chars)?;
+                        if low < 0xdc00 || low >= 0xe000 {
+                            return None;
+                        }
+                        0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                    } else {
+                        high
+                    };
+                    string.push(char::from_u32(code)?);
+                }
+                _ => return None,
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let mut code = 0;
+    for _ in 0..4 {
+        code = code * 16 + chars.next()?.to_digit(16)?;
+    }
+    Some(code)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let elements = vec!["a", "b\"c", "d\\e", "", "ü\n"];
+        assert_eq!(decode(&encode(&elements)), Some(elements.iter().map(|s| s.to_string()).collect()));
+        assert_eq!(decode(" [ \"x\" , 1, true, null ] "), Some(vec!["x".to_string(), "1".to_string(), "true".to_string()]));
+        assert_eq!(decode("[\"\\ud83d\\ude00\"]"), Some(vec!["😀".to_string()]));
+        assert_eq!(decode("[]"), Some(vec![]));
+        assert_eq!(decode("[\"a\",]"), None);
+        assert_eq!(decode("[{\"a\": 1}]"), None);
+        assert_eq!(decode("abc"), None);
+    }
+}
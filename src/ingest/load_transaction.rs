@@ -0,0 +1,157 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use futures_channel::oneshot;
+use futures_core::*;
+
+#[cfg(feature = "ingest_csv")]
+use ingest::csv_loader::{CSVIngestionTask, Options as LoadOptions};
+#[cfg(feature = "ingest_json")]
+use ingest::json_loader::{JSONIngestionTask, Options as JsonLoadOptions};
+use mem_store::column::Column;
+use scheduler::*;
+
+
+/// Bulk load of one or more files into a table that either becomes visible all at once when it is committed, or
+/// leaves no trace if it is aborted or any of its files fails to load.
+///
+/// Loaded partitions are held in memory until the transaction is committed. Dropping the transaction aborts it.
+pub struct LoadTransaction {
+    locustdb: Arc<InnerLocustDB>,
+    staging: Arc<StagedLoad>,
+}
+
+/// Partitions loaded by a transaction that are not part of their table yet.
+pub struct StagedLoad {
+    table: String,
+    state: Mutex<StagedState>,
+}
+
+#[derive(Default)]
+struct StagedState {
+    partitions: Vec<Vec<Arc<Column>>>,
+    /// First error of a load that is part of the transaction, which can then only be aborted.
+    error: Option<String>,
+    /// Number of loads that have been started but not completed.
+    running: usize,
+    finished: bool,
+}
+
+impl LoadTransaction {
+    pub fn new(locustdb: Arc<InnerLocustDB>, table: &str) -> LoadTransaction {
+        LoadTransaction {
+            locustdb,
+            staging: Arc::new(StagedLoad { table: table.to_string(), state: Mutex::new(StagedState::default()) }),
+        }
+    }
+
+    pub fn table(&self) -> &str {
+        &self.staging.table
+    }
+
+    /// Loads a CSV file into the transaction, the table of `options` must be the table of the transaction.
+    #[cfg(feature = "ingest_csv")]
+    pub fn load_csv(&self, options: LoadOptions) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
+        let (sender, receiver) = oneshot::channel();
+        self.staging.load_started();
+        let task = CSVIngestionTask::new(options, self.locustdb.clone(), SharedSender::new(sender))
+            .staged(self.staging.clone());
+        let _ = self.locustdb.schedule(task);
+        receiver
+    }
+
+    /// Loads a file with one JSON object per line into the transaction.
+    #[cfg(feature = "ingest_json")]
+    pub fn load_json(&self, options: JsonLoadOptions) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
+        let (sender, receiver) = oneshot::channel();
+        self.staging.load_started();
+        let task = JSONIngestionTask::new(options, self.locustdb.clone(), SharedSender::new(sender))
+            .staged(self.staging.clone());
+        let _ = self.locustdb.schedule(task);
+        receiver
+    }
+
+    /// Adds all partitions loaded by the transaction to the table at once.
+    /// Fails without changing the table if any load that is part of the transaction failed or is still running.
+    pub fn commit(self) -> Result<(), String> {
+        let (partitions, error) = self.staging.finish();
+        if let Some(error) = error {
+            return Err(format!("Load into {} was rolled back: {}", self.table(), error));
+        }
+        self.locustdb.ensure_writable()?;
+        self.locustdb.store_partitions(self.table(), partitions);
+        Ok(())
+    }
+
+    /// Discards all partitions loaded by the transaction.
+    pub fn abort(self) {}
+}
+
+impl Drop for LoadTransaction {
+    fn drop(&mut self) {
+        self.staging.finish();
+    }
+}
+
+impl StagedLoad {
+    /// Fails if partitions of `table` can't be part of the transaction.
+    pub fn check(&self, table: &str) -> Result<(), String> {
+        let state = self.state.lock().unwrap();
+        if state.finished {
+            Err(format!("Transaction on {} has already been committed or aborted", self.table))
+        } else if table != self.table {
+            Err(format!("Can't load into {} as part of a transaction on {}", table, self.table))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn stage(&self, partition: Vec<Arc<Column>>) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return Err(format!("Transaction on {} has already been committed or aborted", self.table));
+        }
+        state.partitions.push(partition);
+        Ok(())
+    }
+
+    fn load_started(&self) {
+        self.state.lock().unwrap().running += 1;
+    }
+
+    pub fn load_completed(&self, result: &Result<(), String>) {
+        let mut state = self.state.lock().unwrap();
+        state.running -= 1;
+        if let Err(ref error) = *result {
+            if state.error.is_none() {
+                state.error = Some(error.to_string());
+            }
+        }
+    }
+
+    /// Ends the transaction and returns the staged partitions together with the reason they can't be committed.
+    fn finish(&self) -> (Vec<Vec<Arc<Column>>>, Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.finished = true;
+        let partitions = mem::replace(&mut state.partitions, Vec::new());
+        if state.running > 0 {
+            let error = format!("{} loads have not completed", state.running);
+            return (partitions, Some(error));
+        }
+        (partitions, state.error.take())
+    }
+}
+
+/// Stores `partition` as part of `table`, or stages it if it is loaded as part of a transaction.
+pub fn store_partition(ldb: &InnerLocustDB,
+                       table: &str,
+                       staging: Option<&StagedLoad>,
+                       partition: Vec<Arc<Column>>) -> Result<(), String> {
+    match staging {
+        Some(staging) => staging.stage(partition),
+        None => {
+            ldb.store_partition(table, partition);
+            Ok(())
+        }
+    }
+}
@@ -1,8 +1,28 @@
+#[cfg(any(feature = "ingest_csv", feature = "ingest_json"))]
+pub mod compression;
+#[cfg(any(feature = "ingest_csv", feature = "ingest_json"))]
+pub mod object_store;
+#[cfg(feature = "ingest_csv")]
 pub mod csv_loader;
+#[cfg(feature = "enable_arrow")]
+pub mod arrow_loader;
+#[cfg(feature = "ingest_json")]
+pub mod json_loader;
+#[cfg(any(feature = "ingest_csv", feature = "ingest_json"))]
+pub mod load_transaction;
+#[cfg(feature = "ingest_csv")]
+pub mod progress;
 pub mod raw_val;
+pub mod schema;
 pub mod input_column;
+pub mod list;
 pub mod buffer;
+pub mod table_writer;
 pub mod extractor;
+pub mod regex_transform;
+#[cfg(feature = "ingest_csv")]
 pub mod nyc_taxi_data;
+#[cfg(feature = "colgen")]
 pub mod colgen;
+#[cfg(feature = "colgen")]
 mod alias_method_fork;
\ No newline at end of file
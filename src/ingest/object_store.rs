@@ -0,0 +1,317 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, Cursor, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use chrono::Utc;
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use hex;
+
+
+/// Size of the byte ranges that objects are downloaded in.
+const CHUNK_BYTES: usize = 8 << 20;
+/// Number of byte ranges that are downloaded concurrently ahead of the reader.
+const PARALLEL_REQUESTS: usize = 4;
+
+/// Whether `filename` refers to an object in object storage (`s3://bucket/key`) or on a web server
+/// (`http://host/path`) rather than a local file.
+pub fn is_url(filename: &str) -> bool {
+    filename.starts_with("s3://") || filename.starts_with("http://") || filename.starts_with("https://")
+}
+
+/// Streams the object at `url`, which is downloaded in ranges that are fetched in parallel.
+///
+/// Objects given by `s3://bucket/key` URLs are read from the endpoint in `AWS_ENDPOINT_URL`, which defaults to
+/// `http://s3.<region>.amazonaws.com`. Requests are signed with the credentials in `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` for the region in `AWS_REGION`, if any.
+pub fn open(url: &str) -> Result<Box<Read>, String> {
+    let object = Arc::new(Object::parse(url)?);
+    let len = object.content_length()?;
+    Ok(Box::new(ObjectReader::new(object, len, CHUNK_BYTES, PARALLEL_REQUESTS)))
+}
+
+struct Object {
+    /// Host and port of the server.
+    host: String,
+    path: String,
+    credentials: Option<Credentials>,
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    region: String,
+}
+
+impl Object {
+    fn parse(url: &str) -> Result<Object, String> {
+        if url.starts_with("https://") {
+            return Err(format!("Failed to open {}: HTTPS is not supported, use an http:// URL", url));
+        }
+        if url.starts_with("s3://") {
+            let region = env::var("AWS_REGION")
+                .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|_| "us-east-1".to_string());
+            let endpoint = env::var("AWS_ENDPOINT_URL")
+                .unwrap_or_else(|_| format!("http://s3.{}.amazonaws.com", region));
+            let (host, _) = Object::split_http_url(endpoint.trim_right_matches('/'))?;
+            let path = &url["s3://".len()..];
+            if !path.contains('/') {
+                return Err(format!("Failed to open {}: URL must have the form s3://bucket/key", url));
+            }
+            let credentials = match (env::var("AWS_ACCESS_KEY_ID"), env::var("AWS_SECRET_ACCESS_KEY")) {
+                (Ok(access_key), Ok(secret_key)) => Some(Credentials {
+                    access_key,
+                    secret_key,
+                    session_token: env::var("AWS_SESSION_TOKEN").ok(),
+                    region,
+                }),
+                // Public buckets can be read with unsigned requests
+                _ => None,
+            };
+            // Buckets are addressed by path so that names with dots work with plain HTTP
+            Ok(Object { host, path: format!("/{}", uri_encode(path)), credentials })
+        } else {
+            let (host, path) = Object::split_http_url(url)?;
+            Ok(Object { host, path, credentials: None })
+        }
+    }
+
+    fn split_http_url(url: &str) -> Result<(String, String), String> {
+        if !url.starts_with("http://") {
+            return Err(format!("Unsupported URL {}, use an http:// URL", url));
+        }
+        let rest = &url["http://".len()..];
+        Ok(match rest.find('/') {
+            Some(slash) => (rest[..slash].to_string(), rest[slash..].to_string()),
+            None => (rest.to_string(), "/".to_string()),
+        })
+    }
+
+    fn content_length(&self) -> Result<usize, String> {
+        let response = self.request("HEAD", None)?;
+        header(&response.headers, "content-length")
+            .and_then(|len| len.parse::<usize>().ok())
+            .ok_or_else(|| format!("Response for {} has no Content-Length", self.path))
+    }
+
+    /// Downloads the bytes from `start` up to (excluding) `end`.
+    fn range(&self, start: usize, end: usize) -> Result<Vec<u8>, String> {
+        let response = self.request("GET", Some((start, end)))?;
+        if response.body.len() != end - start {
+            return Err(format!("Expected {} bytes of {} but received {}", end - start, self.path, response.body.len()));
+        }
+        Ok(response.body)
+    }
+
+    fn request(&self, method: &str, range: Option<(usize, usize)>) -> Result<Response, String> {
+        let mut headers = vec![("host".to_string(), self.host.clone())];
+        if let Some(ref credentials) = self.credentials {
+            headers.extend(credentials.sign(method, &self.path, &self.host));
+        }
+        if let Some((start, end)) = range {
+            headers.push(("range".to_string(), format!("bytes={}-{}", start, end - 1)));
+        }
+        let response = http_request(&self.host, method, &self.path, &headers)
+            .map_err(|err| format!("Request to {} failed: {}", self.host, err))?;
+        match response.status {
+            200 | 206 => Ok(response),
+            status => Err(format!("Request for {} failed with status {}: {}",
+                                  self.path, status, String::from_utf8_lossy(&response.body))),
+        }
+    }
+}
+
+impl Credentials {
+    /// Headers that authenticate a request with AWS Signature Version 4.
+    fn sign(&self, method: &str, path: &str, host: &str) -> Vec<(String, String)> {
+        let now = Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = sha256_hex(b"");
+
+        let mut headers = vec![
+            ("host".to_string(), host.to_string()),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), timestamp.clone()),
+        ];
+        if let Some(ref token) = self.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let canonical_headers = headers.iter()
+            .map(|&(ref name, ref value)| format!("{}:{}\n", name, value.trim()))
+            .collect::<String>();
+        let signed_headers = headers.iter().map(|&(ref name, _)| name.as_str()).collect::<Vec<_>>().join(";");
+        let canonical_request = format!("{}\n{}\n\n{}\n{}\n{}",
+                                        method, path, canonical_headers, signed_headers, payload_hash);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                                     timestamp, scope, sha256_hex(canonical_request.as_bytes()));
+
+        let mut key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in &[self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+        let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                                    self.access_key, scope, signed_headers, signature);
+
+        // The host header is added by `request`
+        headers.remove(0);
+        headers.push(("authorization".to_string(), authorization));
+        headers
+    }
+}
+
+/// Reader that returns the bytes of an object in order while the following ranges are downloaded in the background.
+struct ObjectReader {
+    object: Arc<Object>,
+    len: usize,
+    chunk_bytes: usize,
+    parallelism: usize,
+    /// Start of the first range that has not been requested yet.
+    next_request: usize,
+    pending: VecDeque<Receiver<Result<Vec<u8>, String>>>,
+    current: Cursor<Vec<u8>>,
+}
+
+impl ObjectReader {
+    fn new(object: Arc<Object>, len: usize, chunk_bytes: usize, parallelism: usize) -> ObjectReader {
+        let mut reader = ObjectReader {
+            object,
+            len,
+            chunk_bytes,
+            parallelism,
+            next_request: 0,
+            pending: VecDeque::new(),
+            current: Cursor::new(Vec::new()),
+        };
+        reader.request_ranges();
+        reader
+    }
+
+    fn request_ranges(&mut self) {
+        while self.pending.len() < self.parallelism && self.next_request < self.len {
+            let start = self.next_request;
+            let end = cmp::min(start + self.chunk_bytes, self.len);
+            let (sender, receiver) = mpsc::channel();
+            let object = self.object.clone();
+            thread::spawn(move || {
+                let _ = sender.send(object.range(start, end));
+            });
+            self.pending.push_back(receiver);
+            self.next_request = end;
+        }
+    }
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let next = match self.pending.pop_front() {
+                Some(next) => next,
+                None => return Ok(0),
+            };
+            let chunk = next.recv()
+                .unwrap_or_else(|_| Err("Download thread panicked".to_string()))
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            self.current = Cursor::new(chunk);
+            self.request_ranges();
+        }
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+fn http_request(host: &str, method: &str, path: &str, headers: &[(String, String)]) -> io::Result<Response> {
+    let mut stream = TcpStream::connect(host)?;
+    // Responses to HTTP/1.0 requests are not chunked and end when the connection is closed
+    let mut request = format!("{} {} HTTP/1.0\r\n", method, path);
+    for &(ref name, ref value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "Malformed HTTP response");
+    let end_of_headers = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(malformed)?;
+    let head = String::from_utf8_lossy(&response[..end_of_headers]).to_string();
+    let mut lines = head.split("\r\n");
+    let status = lines.next()
+        .and_then(|status_line| status_line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(malformed)?;
+    let headers = lines
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => Some((name.trim().to_lowercase(), value.trim().to_string())),
+                _ => None,
+            }
+        })
+        .collect();
+    let body = if method == "HEAD" { vec![] } else { response[end_of_headers + 4..].to_vec() };
+    Ok(Response { status, headers, body })
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref value)| value.as_str())
+}
+
+/// Percent-encodes all characters of `path` except unreserved characters and `/`.
+fn uri_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for &byte in path.as_bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    hasher.result_str()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut hmac = Hmac::new(Sha256::new(), key);
+    hmac.input(data);
+    hmac.result().code().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // Signing key derivation from the AWS Signature Version 4 documentation
+        let mut key = hmac_sha256(b"AWS4wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", b"20120215");
+        for part in &["us-east-1", "iam", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        assert_eq!(hex::encode(key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+        assert_eq!(uri_encode("bucket/a b+c.csv"), "bucket/a%20b%2Bc.csv");
+    }
+}
@@ -0,0 +1,36 @@
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+/// Progress of loading a file, which is reported every time a partition is sealed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadProgress {
+    /// Bytes read from the file, before decompression.
+    pub bytes_read: usize,
+    /// Rows that are part of sealed partitions.
+    pub rows_ingested: usize,
+    pub partitions_sealed: usize,
+}
+
+pub type ProgressCallback = Arc<Fn(&LoadProgress) + Send + Sync>;
+
+/// Counts the bytes read from the wrapped reader.
+pub struct CountingReader<R> {
+    inner: R,
+    bytes_read: Arc<AtomicUsize>,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R, bytes_read: Arc<AtomicUsize>) -> CountingReader<R> {
+        CountingReader { inner, bytes_read }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.bytes_read.fetch_add(count, Ordering::Relaxed);
+        Ok(count)
+    }
+}
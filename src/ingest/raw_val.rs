@@ -1,10 +1,12 @@
 use std::fmt;
-use engine::data_types::BasicType;
+use engine::data_types::{BasicType, OrderedF64};
 
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, HeapSizeOf)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum RawVal {
     Int(i64),
+    Float(OrderedF64),
     Str(String),
     Null,
 }
@@ -13,6 +15,7 @@ impl RawVal {
     pub fn get_type(&self) -> BasicType {
         match *self {
             RawVal::Int(_) => BasicType::Integer,
+            RawVal::Float(_) => BasicType::Float,
             RawVal::Str(_) => BasicType::String,
             RawVal::Null => BasicType::Null,
         }
@@ -24,6 +27,7 @@ impl fmt::Display for RawVal {
         match *self {
             RawVal::Null => write!(f, "null"),
             RawVal::Int(i) => write!(f, "{}", i),
+            RawVal::Float(x) => write!(f, "{}", x),
             RawVal::Str(ref s) => write!(f, "\"{}\"", s),
         }
     }
@@ -34,4 +38,7 @@ pub mod syntax {
 
     #[allow(non_snake_case)]
     pub fn Str(s: &str) -> super::RawVal { super::RawVal::Str(s.to_string()) }
+
+    #[allow(non_snake_case)]
+    pub fn Float(f: f64) -> super::RawVal { super::RawVal::Float(f.into()) }
 }
\ No newline at end of file
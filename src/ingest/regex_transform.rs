@@ -0,0 +1,72 @@
+use regex::Regex;
+
+
+/// Derives columns from a string column while it is loaded, so that queries don't have to parse its values.
+/// The regex is matched against each value of the `source` column and every named capture group becomes a column,
+/// e.g. `^(?P<host>[^/?]+)(?P<path>/[^?]*)?(\?(?P<query>.*))?$` splits URLs into `host`, `path` and `query`.
+/// Derived values are missing if the regex does not match or the group does not participate in the match, they are
+/// loaded as empty fields from CSV and as null from JSON.
+#[derive(Debug, Clone)]
+pub struct RegexTransform {
+    source: String,
+    regex: Regex,
+    columns: Vec<String>,
+}
+
+impl RegexTransform {
+    pub fn new(source: &str, pattern: &str) -> Result<RegexTransform, String> {
+        let regex = Regex::new(pattern).map_err(|err| format!("Invalid regex {}: {}", pattern, err))?;
+        let columns = regex.capture_names()
+            .filter_map(|name| name.map(str::to_owned))
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            return Err(format!("Regex {} for column {} has no named capture groups", pattern, source));
+        }
+        Ok(RegexTransform { source: source.to_owned(), regex, columns })
+    }
+
+    pub fn source(&self) -> &str { &self.source }
+
+    /// Names of the derived columns.
+    pub fn columns(&self) -> &[String] { &self.columns }
+
+    /// Values of the derived columns for `value` of the source column, in the order of `columns`.
+    pub fn apply<'a>(&self, value: &'a str) -> Vec<Option<&'a str>> {
+        match self.regex.captures(value) {
+            Some(captures) => self.columns.iter()
+                .map(|name| captures.name(name).map(|m| m.as_str()))
+                .collect(),
+            None => vec![None; self.columns.len()],
+        }
+    }
+}
+
+/// Names of all columns derived by `transforms`, which must not collide with each other or with `colnames`.
+pub fn derived_columns(transforms: &[RegexTransform], colnames: &[String]) -> Result<Vec<String>, String> {
+    let mut derived = Vec::<String>::new();
+    for transform in transforms {
+        for column in &transform.columns {
+            if colnames.contains(column) || derived.contains(column) {
+                return Err(format!("Column {} derived from {} already exists", column, transform.source));
+            }
+            derived.push(column.clone());
+        }
+    }
+    Ok(derived)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_transform() {
+        let transform = RegexTransform::new("url", r"^(?P<host>[^/?]+)(?P<path>/[^?]*)?(\?(?P<query>.*))?$").unwrap();
+        assert_eq!(transform.columns(), &["host".to_string(), "path".to_string(), "query".to_string()]);
+        assert_eq!(transform.apply("example.com/a/b?x=1"), vec![Some("example.com"), Some("/a/b"), Some("x=1")]);
+        assert_eq!(transform.apply("example.com"), vec![Some("example.com"), None, None]);
+        assert_eq!(transform.apply(""), vec![None, None, None]);
+        assert!(RegexTransform::new("url", "(a)").is_err());
+        assert!(derived_columns(&[transform.clone(), transform], &[]).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use ingest::list;
+use ingest::raw_val::RawVal;
+
+
+/// Column types that can be assigned to loaded columns.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    String,
+    /// List of strings. Sealed partitions store the lists in a list column that holds the offset at which the list of
+    /// each row ends and the dictionary encoded elements. Buffered rows hold the list as JSON array string.
+    StringList,
+}
+
+/// Hints that restrict which encodings are chosen for a column.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EncodingHint {
+    /// Encoding is chosen based on the loaded values.
+    Auto,
+    /// Hex strings are stored as text rather than packed into bytes.
+    NoHex,
+}
+
+/// Type, nullability and encoding hint of a single column, which override type inference.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColumnSchema {
+    pub column_type: ColumnType,
+    pub nullable: bool,
+    pub encoding: EncodingHint,
+}
+
+impl ColumnSchema {
+    pub fn new(column_type: ColumnType) -> ColumnSchema {
+        ColumnSchema {
+            column_type,
+            nullable: true,
+            encoding: EncodingHint::Auto,
+        }
+    }
+
+    /// If not nullable, missing values are loaded as zero or empty string.
+    pub fn nullable(mut self, nullable: bool) -> ColumnSchema {
+        self.nullable = nullable;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: EncodingHint) -> ColumnSchema {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Converts the value of column `name` to the column type, failing if it is not representable.
+    pub fn convert(&self, name: &str, value: RawVal) -> Result<RawVal, String> {
+        Ok(match (self.column_type, value) {
+            (column_type, RawVal::Null) => if self.nullable {
+                RawVal::Null
+            } else {
+                match column_type {
+                    ColumnType::Integer => RawVal::Int(0),
+                    ColumnType::Float => RawVal::Float(0f64.into()),
+                    ColumnType::String => RawVal::Str(String::new()),
+                    ColumnType::StringList => RawVal::Str("[]".to_string()),
+                }
+            },
+            (ColumnType::String, RawVal::Int(int)) => RawVal::Str(int.to_string()),
+            (ColumnType::String, RawVal::Float(float)) => RawVal::Str(float.to_string()),
+            (ColumnType::String, RawVal::Str(s)) => RawVal::Str(s),
+            (ColumnType::StringList, RawVal::Str(ref s)) if list::decode(s).is_some() =>
+                RawVal::Str(list::encode(&list::decode(s).unwrap())),
+            (ColumnType::Integer, RawVal::Int(int)) => RawVal::Int(int),
+            (ColumnType::Float, RawVal::Int(int)) => RawVal::Float((int as f64).into()),
+            (ColumnType::Float, RawVal::Float(float)) => RawVal::Float(float),
+            (ColumnType::Integer, RawVal::Str(ref s)) if s.parse::<i64>().is_ok() => RawVal::Int(s.parse().unwrap()),
+            (ColumnType::Float, RawVal::Str(ref s)) if s.parse::<f64>().is_ok() => RawVal::Float(s.parse::<f64>().unwrap().into()),
+            (column_type, value) => return Err(format!("Value {} of column {} is not of type {:?}", value, name, column_type)),
+        })
+    }
+}
+
+/// Column schemas by column name. Columns without schema are inferred from their values.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    columns: HashMap<String, ColumnSchema>,
+}
+
+impl Schema {
+    pub fn with_column(mut self, name: &str, column: ColumnSchema) -> Schema {
+        self.columns.insert(name.to_string(), column);
+        self
+    }
+
+    /// Schema that fits the values of the columns `colnames` in `rows`. Columns that only contain nulls have no schema.
+    pub fn infer(colnames: &[String], rows: &[Vec<RawVal>]) -> Schema {
+        let mut schema = Schema::default();
+        for (i, name) in colnames.iter().enumerate() {
+            let mut column_type = None;
+            for row in rows {
+                column_type = match (column_type, &row[i]) {
+                    (_, RawVal::Null) => column_type,
+                    (Some(ColumnType::String), _) | (_, RawVal::Str(_)) => Some(ColumnType::String),
+                    (None, RawVal::Int(_)) | (Some(ColumnType::Integer), RawVal::Int(_)) => Some(ColumnType::Integer),
+                    _ => Some(ColumnType::Float),
+                };
+            }
+            if let Some(column_type) = column_type {
+                schema = schema.with_column(name, ColumnSchema::new(column_type));
+            }
+        }
+        schema
+    }
+
+    /// All column schemas ordered by column name.
+    pub fn columns(&self) -> Vec<(String, ColumnSchema)> {
+        let mut columns = self.columns.iter().map(|(name, column)| (name.clone(), *column)).collect::<Vec<_>>();
+        columns.sort_by(|a, b| a.0.cmp(&b.0));
+        columns
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.get(name)
+    }
+
+    /// Adds all columns of `other`, replacing those that are in both schemas.
+    pub fn merge(mut self, other: &Schema) -> Schema {
+        for (name, column) in &other.columns {
+            self.columns.insert(name.clone(), *column);
+        }
+        self
+    }
+
+    /// Converts each value of `row` that has a column schema to the column type.
+    pub fn conform(&self, row: Vec<(String, RawVal)>) -> Result<Vec<(String, RawVal)>, String> {
+        row.into_iter()
+            .map(|(name, value)| match self.columns.get(&name) {
+                Some(column) => column.convert(&name, value).map(|value| (name, value)),
+                None => Ok((name, value)),
+            })
+            .collect()
+    }
+}
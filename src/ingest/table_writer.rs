@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+
+use disk_store::interface::WalSeq;
+use ingest::buffer::Buffer;
+use ingest::raw_val::RawVal;
+use scheduler::*;
+
+
+/// Handle for continuously writing rows to a table.
+///
+/// Rows are appended to the write-ahead log and buffered in the table's open partition, which queries see as soon as
+/// `write` returns. Partitions that reach `partition_size_rows` are sealed and compressed by a background task.
+pub struct TableWriter {
+    locustdb: Arc<InnerLocustDB>,
+    table: String,
+}
+
+impl TableWriter {
+    pub fn new(locustdb: Arc<InnerLocustDB>, table: &str) -> TableWriter {
+        TableWriter {
+            locustdb,
+            table: table.to_string(),
+        }
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    pub fn write(&self, row: Vec<(String, RawVal)>) -> Result<(), String> {
+        self.locustdb.ensure_writable()?;
+        let row = self.locustdb.conform(&self.table, row)?;
+        if let Some(full_buffer) = self.locustdb.buffer_row(&self.table, row) {
+            let _ = self.locustdb.schedule(SealPartitionTask {
+                locustdb: self.locustdb.clone(),
+                table: self.table.clone(),
+                buffer: Mutex::new(Some(full_buffer)),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn write_all<I>(&self, rows: I) -> Result<(), String> where I: IntoIterator<Item=Vec<(String, RawVal)>> {
+        for row in rows {
+            self.write(row)?;
+        }
+        Ok(())
+    }
+
+    /// Seals the open partition, which compresses all rows written so far and removes them from the write-ahead log.
+    pub fn flush(&self) {
+        self.locustdb.flush_table(&self.table);
+    }
+}
+
+struct SealPartitionTask {
+    locustdb: Arc<InnerLocustDB>,
+    table: String,
+    buffer: Mutex<Option<(Arc<Buffer>, WalSeq)>>,
+}
+
+impl Task for SealPartitionTask {
+    fn execute(&self) {
+        let full_buffer = self.buffer.lock().unwrap().take();
+        if let Some((buffer, wal_seq)) = full_buffer {
+            self.locustdb.store_buffer(&self.table, buffer, wal_seq);
+        }
+    }
+
+    fn completed(&self) -> bool { self.buffer.lock().unwrap().is_none() }
+    fn multithreaded(&self) -> bool { false }
+    fn priority(&self) -> Priority { Priority::Background }
+}
@@ -17,9 +17,9 @@ extern crate itertools;
 extern crate num;
 extern crate num_cpus;
 extern crate regex;
+extern crate scoped_threadpool;
 extern crate seahash;
 extern crate time;
-extern crate tempdir;
 extern crate fnv;
 extern crate byteorder;
 extern crate lru;
@@ -27,12 +27,24 @@ extern crate crypto;
 extern crate hex;
 #[cfg(feature = "enable_rocksdb")]
 extern crate capnp;
+#[cfg(feature = "enable_arrow")]
+extern crate arrow;
+#[cfg(feature = "enable_mmap")]
+extern crate memmap;
 extern crate std_semaphore;
+#[cfg(feature = "colgen")]
 extern crate aliasmethod;
+#[cfg(feature = "colgen")]
 extern crate rand;
 extern crate locustdb_derive;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde_derive;
 
 
 #[macro_use]
@@ -45,23 +57,64 @@ mod ingest;
 mod engine;
 mod scheduler;
 mod locustdb;
+mod access_control;
 mod disk_store;
 mod stringpack;
 mod bitvec;
+mod udf;
+mod export;
+mod metrics;
+mod query_log;
 pub mod unit_fmt;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "cluster")]
+pub mod cluster;
+#[cfg(feature = "replication")]
+pub mod replication;
 
-pub use engine::query_task::QueryOutput;
-pub use errors::QueryError;
+pub use access_control::{ColumnAccess, Masking};
+pub use disk_store::catalog::{Catalog, GeneratedEntry, PartitionEntry, TableEntry};
+pub use engine::query_task::{QueryOutput, QueryStream};
+pub use engine::{CancellationToken, Query, SumOverflow};
+pub use engine::{PlanBuffer, PlanEdge, PlanGraph, PlanOperator, PlanStage};
+pub use errors::{QueryError, SourceSpan, Suggestion};
+#[cfg(feature = "ingest_csv")]
 pub use ingest::csv_loader::Options as LoadOptions;
+#[cfg(any(feature = "ingest_csv", feature = "ingest_json"))]
+pub use ingest::compression::Compression;
 pub use ingest::extractor;
+#[cfg(any(feature = "ingest_csv", feature = "ingest_json"))]
+pub use ingest::load_transaction::LoadTransaction;
+#[cfg(feature = "ingest_json")]
+pub use ingest::json_loader::{Options as JsonLoadOptions, TypeInference};
+#[cfg(feature = "ingest_csv")]
 pub use ingest::nyc_taxi_data;
+#[cfg(feature = "ingest_csv")]
+pub use ingest::progress::LoadProgress;
 pub use ingest::raw_val::RawVal as Value;
+pub use ingest::regex_transform::RegexTransform;
+pub use ingest::schema::{ColumnSchema, ColumnType, EncodingHint, Schema};
 pub use ingest::raw_val::syntax as value_syntax;
+pub use ingest::table_writer::TableWriter;
+#[cfg(feature = "colgen")]
 pub use ingest::colgen;
 pub use locustdb::LocustDB as LocustDB;
 pub use locustdb::Options as Options;
-pub use mem_store::table::TableStats;
+pub use locustdb::LocustDBBuilder;
+pub use locustdb::QueryHook;
+pub use locustdb::QueryHandle;
+pub use locustdb::Page;
+pub use mem_store::BlockCompression;
+pub use syntax::builder::{self, QueryBuilder};
+pub use syntax::expression::Expr;
+pub use syntax::prepared::PreparedQuery;
+pub use syntax::sample::{SampleClause, SampleMethod};
+pub use mem_store::table::{MemStats, TableStats};
 pub use disk_store::noop_storage::NoopStorage;
+pub use udf::{ArgSlice, ResultVec, Signature, ValueType};
 
 pub type QueryResult = Result<QueryOutput, QueryError>;
 
@@ -1,24 +1,51 @@
+use std::path::PathBuf;
 use std::str;
 use std::sync::Arc;
+use std::time::Duration;
 
-use futures_channel::oneshot;
+use futures_channel::{mpsc, oneshot};
 use futures_core::*;
 use futures_util::FutureExt;
+#[cfg(feature = "colgen")]
 use futures_util;
-use futures_executor::block_on;
+use futures_executor::{block_on, block_on_stream};
 use num_cpus;
+use time::precise_time_ns;
 
 use QueryError;
 use QueryResult;
+use Value;
+use access_control::ColumnAccess;
 use disk_store::interface::*;
 use disk_store::noop_storage::NoopStorage;
-use engine::query_task::QueryTask;
+use engine::{CacheKey, CancellationToken, MergePolicy, Query, QueryLimits, SumOverflow, DEFAULT_BATCH_SIZE};
+use engine::query_task::{CompiledQuery, QueryOutput, QueryStats, QueryStream, QueryTask, ResultSink, find_all_cols, row_types};
+use export;
+#[cfg(feature = "enable_arrow")]
+use arrow::record_batch::RecordBatch;
+#[cfg(feature = "colgen")]
 use ingest::colgen::GenTable;
+#[cfg(feature = "enable_arrow")]
+use ingest::arrow_loader;
+#[cfg(feature = "ingest_csv")]
 use ingest::csv_loader::{CSVIngestionTask, Options as LoadOptions};
+use ingest::schema::Schema;
+use ingest::table_writer::TableWriter;
+#[cfg(feature = "ingest_json")]
+use ingest::json_loader::{JSONIngestionTask, Options as JsonLoadOptions};
+#[cfg(any(feature = "ingest_csv", feature = "ingest_json"))]
+use ingest::load_transaction::LoadTransaction;
 use mem_store::*;
+#[cfg(feature = "replication")]
+use replication::ChangeSet;
 use scheduler::*;
+use syntax::expression::Expr;
+use syntax::keyset::Keyset;
 use syntax::parser;
-use trace::{Trace, TraceBuilder};
+use syntax::prepared::{self, PreparedQuery};
+use syntax::statement::{CopyTo, Delete, GeneratedColumn, Insert, InsertSelect, Statement};
+use trace::{start_toplevel, Trace, TraceBuilder};
+use udf::{ArgSlice, ResultVec, ScalarFunction, Signature};
 
 
 pub struct LocustDB {
@@ -39,27 +66,408 @@ impl LocustDB {
         LocustDB { inner_locustdb: locustdb }
     }
 
-    pub fn run_query(&self, query: &str, explain: bool, show: Vec<usize>) -> Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> {
+    pub fn builder() -> LocustDBBuilder {
+        LocustDBBuilder::default()
+    }
+
+    /// Opens the snapshot directory at `path` that was written with `snapshot` for queries only. Partitions are loaded
+    /// into memory without locking or modifying the directory, so any number of processes can serve queries from a
+    /// dataset that is produced by another process. Statements that modify data are rejected.
+    pub fn open_read_only(path: &str) -> Result<LocustDB, String> {
+        LocustDB::builder().open_read_only(path)
+    }
+
+    pub fn run_query(&self, query: &str, explain: bool, show: Vec<usize>) -> QueryHandle {
+        self.run_query_hooked(query, explain, show, None, true)
+    }
+
+    /// Runs `query` without reading from or writing to the result cache.
+    pub fn run_query_uncached(&self, query: &str, explain: bool, show: Vec<usize>) -> QueryHandle {
+        self.run_query_hooked(query, explain, show, None, false)
+    }
+
+    /// Runs `query` and returns its rows in batches as soon as they are available, so that the results of large scans
+    /// can be consumed without materializing them. Results of queries that aggregate or order rows and of statements
+    /// are returned as a single batch. The query hook is not invoked for streamed queries.
+    pub fn run_query_streaming(&self, query: &str) -> QueryStream {
+        let (sender, receiver) = mpsc::unbounded();
+        match self.prepare_query(query, false, vec![], None, None, ResultSink::stream(sender.clone()), CancellationToken::default()) {
+            Ok(task) => {
+                let _ = self.schedule(task);
+            }
+            Err((result, _)) => {
+                let _ = sender.unbounded_send(result);
+            }
+        }
+        QueryStream::new(receiver)
+    }
+
+    /// Parses `query`, which may contain `?` or `$1`, `$2`, ... placeholders for parameters, so that it can be run
+    /// many times with `run_prepared` without parsing it again. The query is normalized on its first run, later runs
+    /// reuse the normalized query as long as the columns of the table don't change.
+    pub fn prepare(&self, query: &str) -> Result<PreparedQuery, QueryError> {
+        let (parsed, param_count) = prepared::parse(query)?;
+        let parsed = self.inner_locustdb.functions().resolve(parsed)?;
+        Ok(PreparedQuery::new(query, parsed, param_count))
+    }
+
+    /// Runs a query created by `prepare` with `params` in place of its placeholders.
+    pub fn run_prepared(&self, query: &PreparedQuery, params: &[Value]) -> QueryHandle {
+        self.run_hooked(query.sql(), |sink, cancellation| {
+            self.prepare_select_with(query.query().clone(), false, vec![], None, None, Some((query, params)), sink,
+                                     cancellation)
+        })
+    }
+
+    /// Runs a query constructed with `Query::table`. The query hook receives the debug representation of the query.
+    pub fn run(&self, query: Query) -> QueryHandle {
+        self.run_hooked(&format!("{:?}", query), |sink, cancellation| {
+            let query = self.inner_locustdb.functions().resolve(query).map_err(|err| (Err(err), "empty"))?;
+            self.prepare_select(query, false, vec![], None, None, sink, cancellation)
+        })
+    }
+
+    /// Runs `query`, which must be ordered by selected columns, and returns at most `LIMIT` rows together with a cursor
+    /// that is passed with the same query to get the rows that follow. Unlike `OFFSET`, skipping to later pages with a
+    /// cursor does not require computing the rows of all preceding pages.
+    pub fn run_page(&self, query: &str, cursor: Option<&str>) -> impl Future<Item=Result<Page, QueryError>, Error=oneshot::Canceled> {
+        let parsed = parser::parse_query(query)
+            .and_then(|parsed| self.inner_locustdb.functions().resolve(parsed))
+            .and_then(|parsed| Keyset::of(&parsed).map(|keyset| (parsed, keyset)));
+        let keyset = parsed.as_ref().ok().map(|&(ref parsed, ref keyset)| (keyset.clone(), parsed.limit.limit));
+        let handle = self.run_hooked(query, |sink, cancellation| {
+            let (mut parsed, keyset) = parsed.map_err(|err| (Err(err), "empty"))?;
+            if let Some(cursor) = cursor {
+                keyset.seek(&mut parsed, cursor).map_err(|err| (Err(err), "empty"))?;
+            }
+            self.prepare_select(parsed, false, vec![], None, None, sink, cancellation)
+        });
+        handle.map(move |(result, _)| result.and_then(|output| {
+            let cursor = match keyset {
+                Some((ref keyset, limit)) => keyset.cursor(&output.colnames, &output.rows, limit)?,
+                None => None,
+            };
+            Ok(Page { output, cursor })
+        }))
+    }
+
+    /// Counters of queries and ingested data and the size of all tables in the Prometheus text format.
+    pub fn metrics(&self) -> String {
+        self.inner_locustdb.metrics().prometheus(&self.inner_locustdb.stats())
+    }
+
+    /// Runs `query` on the shard of the data held by this node of a cluster, see `Query::partial`.
+    pub fn run_partial_query(&self, query: &str) -> QueryHandle {
+        self.run_hooked(query, |sink, cancellation| {
+            let parsed = parser::parse_query(query)
+                .and_then(|parsed| self.inner_locustdb.functions().resolve(parsed))
+                .map_err(|err| (Err(err), "empty"))?;
+            self.prepare_select(parsed.partial(), false, vec![], None, None, sink, cancellation)
+        })
+    }
+
+    /// Runs `query` with the column permissions of `role`.
+    pub fn run_query_as(&self, role: &str, query: &str, explain: bool, show: Vec<usize>) -> QueryHandle {
+        self.run_query_hooked(query, explain, show, Some(role), true)
+    }
+
+    /// Sets the permissions of `role` for `column` of `table`. Columns are accessible to all roles by default.
+    pub fn set_column_access(&self, role: &str, table: &str, column: &str, access: ColumnAccess) {
+        self.inner_locustdb.set_column_access(role, table, column, access);
+    }
+
+    /// Sets the retention period of `table`, partitions are dropped once all values of the integer column `column`
+    /// (a Unix timestamp in seconds) are older than `max_age`. Expired partitions are dropped every minute.
+    /// Fails on databases opened in read-only mode.
+    pub fn set_retention(&self, table: &str, column: &str, max_age: Duration) -> Result<(), String> {
+        self.inner_locustdb.set_retention(table, column, max_age)
+    }
+
+    /// Builds bloom filters for `columns` of all partitions of `table` that are created from now on, which allows
+    /// queries that compare these columns to constants to skip partitions that don't contain the value.
+    pub fn set_bloom_filter_columns(&self, table: &str, columns: &[&str]) {
+        self.inner_locustdb.set_bloom_filter_columns(table, columns);
+    }
+
+    /// Encodes the string `columns` of all partitions of `table` that are created from now on with a dictionary that
+    /// is shared by all partitions, so that equal strings have the same code in every partition.
+    /// Shared dictionaries grow with every new value and are only suitable for columns with few distinct values.
+    ///
+    /// Aggregations grouped by one of these columns merge the results of different partitions on the codes and only
+    /// decode the groups of the final result to strings.
+    pub fn set_shared_dictionary_columns(&self, table: &str, columns: &[&str]) {
+        self.inner_locustdb.set_shared_dictionary_columns(table, columns);
+    }
+
+    /// Compresses the in-memory column data of all partitions of `table` that are created from now on.
+    /// Compressed data takes less memory but is decompressed by every query, so this suits rarely queried tables.
+    /// `BlockCompression::None` keeps data uncompressed even in builds that compress columns with LZ4 by default.
+    pub fn set_block_compression(&self, table: &str, compression: BlockCompression) -> Result<(), String> {
+        self.inner_locustdb.set_block_compression(table, compression)
+    }
+
+    /// Immediately evicts the least recently queried partitions until tables fit into `mem_size_limit_tables` and
+    /// returns their number. The limit is also enforced by a background thread once per second.
+    pub fn enforce_mem_limit(&self) -> usize {
+        self.inner_locustdb.enforce_mem_limit()
+    }
+
+    /// Immediately drops all partitions that exceeded the retention period of their table and returns their number.
+    pub fn enforce_retention(&self) -> usize {
+        self.inner_locustdb.enforce_retention()
+    }
+
+    /// Immediately merges adjacent partitions that contain fewer than half of `merge_partition_rows` rows (the size of
+    /// partitions created by the loaders if not set) and returns the number of partitions that were removed.
+    pub fn merge_small_partitions(&self) -> usize {
+        self.inner_locustdb.merge_small_partitions()
+    }
+
+    /// Sealed partitions with ids greater than `after` or contained in `ids`, see `replication::Follower`.
+    #[cfg(feature = "replication")]
+    pub fn replication_changes(&self, after: PartitionID, ids: &[PartitionID]) -> ChangeSet {
+        self.inner_locustdb.replication_changes(after, &ids.iter().cloned().collect())
+    }
+
+    /// Applies changes received from a leader, which adds or replaces the partitions they contain and removes all
+    /// partitions that the leader no longer has.
+    #[cfg(feature = "replication")]
+    pub fn apply_replicated_changes(&self, changes: ChangeSet) {
+        self.inner_locustdb.apply_replicated_changes(changes)
+    }
+
+    /// Ids of the partitions of a leader that were received before and are missing or out of date.
+    #[cfg(feature = "replication")]
+    pub fn stale_replicas(&self, live: &[(String, PartitionID, usize)], after: PartitionID) -> Vec<PartitionID> {
+        self.inner_locustdb.stale_replicas(live, after)
+    }
+
+    /// Registers a scalar function that can be called by name from any query.
+    /// Functions are called with the values of their arguments for each row and must return a value of the type given by `signature`.
+    pub fn register_function<F>(&self, name: &str, signature: Signature, function: F)
+        where F: Fn(&[Value]) -> Value + Send + Sync + 'static {
+        self.inner_locustdb.register_function(ScalarFunction::new(name, signature, function));
+    }
+
+    /// Registers a vectorized scalar function that can be called by name from any query.
+    /// Functions are called once per batch of rows with a slice of values for each argument and must return a vector
+    /// of the type given by `signature` with one value for each row.
+    pub fn register_udf<F>(&self, name: &str, signature: Signature, function: F)
+        where F: Fn(&[ArgSlice]) -> ResultVec + Send + Sync + 'static {
+        self.inner_locustdb.register_function(ScalarFunction::vectorized(name, signature, function));
+    }
+
+    fn run_query_hooked(&self, query: &str, explain: bool, show: Vec<usize>, role: Option<&str>, use_cache: bool) -> QueryHandle {
+        let cache_key = if use_cache && !explain && show.is_empty() { Some(CacheKey::new(query, role)) } else { None };
+        self.run_hooked(query, |sink, cancellation| self.prepare_query(query, explain, show, role, cache_key, sink, cancellation))
+    }
+
+    /// Runs the task returned by `prepare` and invokes the query hook with `query` and the result.
+    fn run_hooked<F>(&self, query: &str, prepare: F) -> QueryHandle
+        where F: FnOnce(ResultSink, CancellationToken) -> Result<QueryTask, (QueryResult, &'static str)> {
+        let cancellation = CancellationToken::default();
+        let start_time_ns = precise_time_ns();
         let (sender, receiver) = oneshot::channel();
+        // Spans of parsing and normalizing the query are part of the trace of the task that runs it
+        let mut trace_builder = start_toplevel("schedule");
+        let prepared = trace_builder.collect_spans(|| {
+            trace_start!("Prepare query");
+            prepare(ResultSink::Complete(SharedSender::new(sender)), cancellation.clone())
+        });
+        let result: Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> =
+            match prepared {
+                Ok(task) => {
+                    let trace_receiver = self.inner_locustdb.schedule_traced(task, trace_builder);
+                    Box::new(receiver.join(trace_receiver))
+                }
+                Err((result, trace)) => Box::new(future::ok((result, TraceBuilder::new(trace.to_owned()).finalize()))),
+            };
+        let inner_locustdb = self.inner_locustdb.clone();
+        let logged_query = query.to_string();
+        let result: Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>> =
+            Box::new(result.map(move |(result, trace)| {
+                let duration_ns = precise_time_ns() - start_time_ns;
+                let (rows_scanned, rows_returned, partitions_pruned) = match result {
+                    Ok(ref output) => (output.stats.rows_scanned, output.rows.len(), output.stats.partitions_pruned),
+                    Err(_) => (0, 0, 0),
+                };
+                inner_locustdb.metrics().record_query(duration_ns, rows_scanned, result.is_err());
+                if let Some(threshold) = inner_locustdb.opts().slow_query_threshold {
+                    if duration_ns >= threshold.as_secs() * 1_000_000_000 + u64::from(threshold.subsec_nanos()) {
+                        warn!("Slow query ({}ms): {}", duration_ns / 1_000_000, logged_query);
+                    }
+                }
+                inner_locustdb.query_log().record(&logged_query, duration_ns, rows_scanned, rows_returned,
+                                                  partitions_pruned, result.as_ref().err().map(|err| err.to_string()));
+                (result, trace)
+            }));
+        let result = match self.inner_locustdb.opts().query_hook.clone() {
+            Some(hook) => {
+                let query = query.to_string();
+                Box::new(result.map(move |(result, trace)| {
+                    hook(query.as_str(), &result);
+                    (result, trace)
+                }))
+            }
+            None => result,
+        };
+        QueryHandle { result, cancellation }
+    }
 
+    /// Plans `query` for execution. Statements other than queries are executed immediately, their result is returned
+    /// as error together with the name of their trace, as are errors that occur during planning and cached results.
+    fn prepare_query(&self, query: &str, explain: bool, show: Vec<usize>, role: Option<&str>, cache_key: Option<CacheKey>, sink: ResultSink, cancellation: CancellationToken) -> Result<QueryTask, (QueryResult, &'static str)> {
         // TODO(clemens): perform compilation and table snapshot in asynchronous task?
-        let query = match parser::parse_query(query) {
+        let statement = {
+            let _span = trace_span!("Parse");
+            parser::parse_statement(query)
+        };
+        let parsed = match statement {
+            Ok(Statement::Select(query)) => self.inner_locustdb.functions().resolve(query),
+            Ok(Statement::Insert(insert)) => return Err((self.insert(insert), "insert")),
+            Ok(Statement::InsertSelect(insert)) => return Err((self.insert_select(insert, role), "insert")),
+            Ok(Statement::CopyTo(copy)) => return Err((self.copy_to(copy, role), "copy")),
+            Ok(Statement::Delete(delete)) => return Err((self.delete(delete), "delete")),
+            Ok(Statement::CreateTable(create_table)) => return Err((
+                self.inner_locustdb.create_table(&create_table.table, create_table.schema())
+                    .map(|_| statement_output(vec![]))
+                    .map_err(QueryError::NotImplemented),
+                "create_table")),
+            Ok(Statement::DropTable { table, if_exists }) => return Err((self.drop_table(&table, if_exists), "drop_table")),
+            Ok(Statement::AddColumn { table, column }) => return Err((self.add_column(&table, column), "add_column")),
+            Ok(Statement::CreateView { view, sql }) => return Err((self.create_view(&view, &sql), "create_view")),
+            Ok(Statement::DropView { view, if_exists }) => return Err((self.drop_view(&view, if_exists), "drop_view")),
+            Err(err) => Err(err),
+        };
+        match parsed {
+            Ok(parsed) => self.prepare_select(parsed, explain, show, role, cache_key, sink, cancellation)
+                .map_err(|(result, trace)| (result.map_err(|err| err.locate(query)), trace)),
+            Err(err) => Err((Err(err), "empty")),
+        }
+    }
+
+    fn prepare_select(&self, query: Query, explain: bool, show: Vec<usize>, role: Option<&str>, cache_key: Option<CacheKey>, sink: ResultSink, cancellation: CancellationToken) -> Result<QueryTask, (QueryResult, &'static str)> {
+        self.prepare_select_with(query, explain, show, role, cache_key, None, sink, cancellation)
+    }
+
+    /// Prepares a task for `query`, which contains the placeholders of `prepared` if it is given. Placeholders are
+    /// bound to the parameters after views and generated columns have been expanded.
+    fn prepare_select_with(&self, query: Query, explain: bool, show: Vec<usize>, role: Option<&str>, cache_key: Option<CacheKey>,
+                           prepared: Option<(&PreparedQuery, &[Value])>, sink: ResultSink, cancellation: CancellationToken)
+                           -> Result<QueryTask, (QueryResult, &'static str)> {
+        let query = match self.expand_views(query) {
             Ok(query) => query,
-            Err(err) => {
-                return Box::new(future::ok(
-                    (Err(err),
-                     TraceBuilder::new("empty".to_owned()).finalize())));
-            }
+            Err(err) => return Err((Err(err), "empty")),
+        };
+        // Partitions that are removed from the table after the snapshot is taken are kept until the query completes
+        let epoch = self.inner_locustdb.pin_epoch();
+        // System tables have no version, so their results are never cached
+        let (data, version) = match self.inner_locustdb.versioned_snapshot(&query.table) {
+            Some((data, version)) => (data, Some(version)),
+            None => match self.inner_locustdb.system_table(&query.table) {
+                Some(data) => (data, None),
+                // TODO(clemens): A table may not exist on all nodes, so querying empty table is valid and should return empty result.
+                None => return Err((
+                    Err(QueryError::NotImplemented(format!("Table {} does not exist!", &query.table))),
+                    "empty")),
+            },
         };
+        // Buffered rows don't change the version of the table, so results that include open partitions are not cached
+        let has_open_partition = data.iter().any(|partition| partition.is_open());
+        let result_cache = self.inner_locustdb.result_cache().clone();
+        let cache_entry = match (cache_key, version) {
+            // Samples without a seed differ between runs and are not cached
+            (Some(key), Some(version)) if !has_open_partition && query.sample.map_or(true, |sample| sample.seed.is_some()) =>
+                if result_cache.is_enabled() { Some((key, version)) } else { None },
+            _ => None,
+        };
+        if let Some((ref key, version)) = cache_entry {
+            if let Some(output) = result_cache.get(key, version) {
+                return Err((Ok(output), "cached"));
+            }
+        }
 
-        let mut data = match self.inner_locustdb.snapshot(&query.table) {
-            Some(data) => data,
-            // TODO(clemens): A table may not exist on all nodes, so querying empty table is valid and should return empty result.
-            None => return Box::new(future::ok((
-                Err(QueryError::NotImplemented(format!("Table {} does not exist!", &query.table))),
-                TraceBuilder::new("empty".to_owned()).finalize()))),
+        let mut query = query;
+        let generated = self.inner_locustdb.generated_columns(&query.table);
+        let table_columns = find_all_cols(&data);
+        if let Err(err) = query.expand_generated_columns(&generated, &table_columns) {
+            return Err((Err(err), "empty"));
+        }
+        let query = match role {
+            Some(role) => {
+                let mut query = query;
+                let policy = self.inner_locustdb.access_policy();
+                let visible_columns = policy.visible_columns(role, &query.table, find_all_cols(&data));
+                if let Err(err) = query.expand_wildcards(&visible_columns) {
+                    return Err((Err(err), "empty"));
+                }
+                match policy.apply(role, query) {
+                    Ok(query) => query,
+                    Err(err) => return Err((Err(err), "empty")),
+                }
+            }
+            None => query,
+        };
+        let mut query = Query {
+            sum_overflow: query.sum_overflow.or(Some(self.inner_locustdb.opts().sum_overflow)),
+            ..query
+        };
+        // The query with placeholders is normalized once, all other steps use the query with bound parameters.
+        // Samples pick a seed and may change the filter on every run, so sampled queries are normalized every time.
+        let template = match prepared {
+            Some((prepared, params)) => {
+                let bound = prepared.bind_query(query.clone(), params).map_err(|err| (Err(err), "empty"))?;
+                let template = query;
+                query = bound;
+                if query.sample.is_none() { Some((template, prepared, params)) } else { None }
+            }
+            None => None,
         };
 
+        // Aggregates that follow from the row counts and zone maps of the partitions are returned without a scan
+        if !explain && show.is_empty() {
+            if let Some((colnames, row)) = query.answer_from_statistics(&data) {
+                let rows = vec![row];
+                let output = QueryOutput {
+                    coltypes: row_types(colnames.len(), &rows),
+                    colnames,
+                    rows,
+                    query_plans: Default::default(),
+                    plan_graphs: vec![],
+                    stats: QueryStats::default(),
+                };
+                return Err((Ok(output), "statistics"));
+            }
+        }
+
+        // Partitions are sampled before pruning, so that the sampling factor is relative to the whole table
+        let mut data = data;
+        let mut sampling_factor = 1.0;
+        if let Some(mut sample) = query.sample {
+            let seed = sample.seed.unwrap_or_else(precise_time_ns);
+            sample.seed = Some(seed);
+            query.sample = Some(sample);
+            let (sampled, factor) = sample.sample_partitions(data.clone(), seed);
+            sampling_factor = factor;
+            if sampled.is_empty() {
+                // A partition is kept to compute the (empty) result
+                data.truncate(1);
+                query.filter = Expr::Const(Value::Int(0));
+            } else {
+                data = sampled;
+            }
+        }
+
+        // Skip partitions whose zone maps show that they contain no rows that match the filter.
+        // At least one partition is kept to compute the (empty) result.
+        let partition_count = data.len();
+        let (matching, pruned): (Vec<_>, Vec<_>) = data.into_iter().partition(|p| p.may_match(&query.filter));
+        let mut data: Vec<_> = if matching.is_empty() { pruned.into_iter().take(1).collect() } else { matching };
+        let partitions_pruned = partition_count - data.len();
+        if self.inner_locustdb.opts().deterministic {
+            data.sort_by_key(|partition| partition.id());
+        }
+
         if self.inner_locustdb.opts().seq_disk_read {
             self.inner_locustdb.disk_read_scheduler()
                 .schedule_sequential_read(&mut data,
@@ -70,14 +478,195 @@ impl LocustDB {
             let _ = self.inner_locustdb.schedule(read_data);
         }
 
-        let task = QueryTask::new(
-            query, explain, show, data,
-            self.inner_locustdb.disk_read_scheduler().clone(),
-            SharedSender::new(sender));
-        let trace_receiver = self.schedule(task);
-        Box::new(receiver.join(trace_receiver))
+        let opts = self.inner_locustdb.opts();
+        let limits = QueryLimits::new(cancellation, opts.query_timeout, opts.query_memory_limit);
+        let compiled = match template {
+            Some((template, prepared, params)) => {
+                let key = format!("{:?} {:?}", template, table_columns);
+                prepared.compile_bound(key, params, || {
+                    let mut template = template;
+                    template.expand_wildcards(&table_columns)?;
+                    // Placeholders are not validated as columns, tables without columns are not validated at all
+                    let mut columns = table_columns.clone();
+                    if !columns.is_empty() {
+                        columns.extend(prepared.param_colnames());
+                    }
+                    self.compile(template, &columns)
+                })
+            }
+            None => self.compile(query, &find_all_cols(&data)),
+        }.map_err(|err| (Err(err), "empty"))?;
+        let task = QueryTask::new(compiled, explain, show, data, self.inner_locustdb.disk_read_scheduler().clone(), sink, limits,
+                                  opts.batch_size_rows)
+            .map_err(|err| (Err(err), "empty"))?
+            .preemptible(self.inner_locustdb.pending_priority().clone(), opts.bulk_query_threshold)
+            .pin_epoch(epoch)
+            .partitions_pruned(partitions_pruned)
+            .sampling_factor(sampling_factor)
+            .merge_policy(MergePolicy {
+                fan_in: opts.merge_fan_in,
+                spill_threshold: opts.merge_spill_threshold,
+                spill_directory: opts.spill_directory.as_ref().map(PathBuf::from),
+            })
+            .deterministic(opts.deterministic);
+        let task = match cache_entry {
+            Some((key, version)) => task.cache_result(result_cache, key, version),
+            None => task,
+        };
+        // Partitions of system tables are created for every query and may reuse ids
+        let subresult_cache = self.inner_locustdb.subresult_cache();
+        Ok(if version.is_some() && subresult_cache.is_enabled() { task.cache_subresults(subresult_cache.clone()) } else { task })
+    }
+
+    /// Writes the rows of an `INSERT INTO` statement and makes them queryable.
+    fn insert(&self, insert: Insert) -> QueryResult {
+        self.inner_locustdb.ensure_writable().map_err(QueryError::PermissionDenied)?;
+        let table = insert.table.clone();
+        let rows = insert.named_rows().into_iter()
+            .map(|row| self.inner_locustdb.conform(&table, row))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(QueryError::TypeError)?;
+        let row_count = rows.len();
+        for row in rows {
+            self.inner_locustdb.ingest(&table, row);
+        }
+        self.inner_locustdb.flush_table(&table);
+        Ok(statement_output(vec![("inserted", Value::Int(row_count as i64))]))
+    }
+
+    /// Writes the result of the query of a `CREATE TABLE ... AS SELECT` or `INSERT INTO ... SELECT` statement to its
+    /// table, which is created with column types that fit the result if the statement creates it.
+    fn insert_select(&self, insert: InsertSelect, role: Option<&str>) -> QueryResult {
+        self.inner_locustdb.ensure_writable().map_err(QueryError::PermissionDenied)?;
+        let query = self.inner_locustdb.functions().resolve(insert.query)?;
+        let (sender, receiver) = oneshot::channel();
+        let sink = ResultSink::Complete(SharedSender::new(sender));
+        let output = match self.prepare_select(query, false, vec![], role, None, sink, CancellationToken::default()) {
+            Ok(task) => {
+                let _ = self.schedule(task);
+                block_on(receiver).map_err(|_| QueryError::Cancelled)??
+            }
+            Err((result, _)) => result?,
+        };
+        let columns = insert.columns.unwrap_or(output.colnames);
+        if columns.len() != output.rows.get(0).map_or(columns.len(), |row| row.len()) {
+            bail!(QueryError::ParseError, "INSERT has {} columns but the query selects {}", columns.len(), output.rows[0].len());
+        }
+        if insert.create {
+            self.inner_locustdb.create_table(&insert.table, Schema::infer(&columns, &output.rows))
+                .map_err(QueryError::NotImplemented)?;
+        }
+        self.insert(Insert { table: insert.table, columns, rows: output.rows })
+    }
+
+    /// Writes the result of the query of a `COPY (SELECT ...) TO 'file'` statement to the file in `export_directory`
+    /// as batches of rows become available. Writing files is not permitted to roles.
+    fn copy_to(&self, copy: CopyTo, role: Option<&str>) -> QueryResult {
+        if let Some(role) = role {
+            bail!(QueryError::PermissionDenied, "Role {} may not write files", role);
+        }
+        let path = match self.inner_locustdb.opts().export_directory {
+            Some(ref directory) => export::resolve(directory, &copy.path).map_err(QueryError::PermissionDenied)?,
+            None => bail!(QueryError::PermissionDenied, "COPY is disabled, set an `export_directory` to enable it"),
+        };
+        let query = self.inner_locustdb.functions().resolve(copy.query)?;
+        let (sender, receiver) = mpsc::unbounded();
+        match self.prepare_select(query, false, vec![], None, None, ResultSink::stream(sender.clone()), CancellationToken::default()) {
+            Ok(task) => {
+                let _ = self.schedule(task);
+            }
+            Err((result, _)) => {
+                let _ = sender.unbounded_send(result);
+            }
+        }
+        drop(sender);
+
+        let mut writer = None;
+        let mut exported = 0;
+        for batch in block_on_stream(QueryStream::new(receiver)) {
+            let batch = batch?;
+            if writer.is_none() {
+                writer = Some(export::create(&path, copy.format, &batch.colnames, &batch.rows)
+                    .map_err(QueryError::IoError)?);
+            }
+            if let Some(ref mut writer) = writer {
+                writer.write(&batch.rows).map_err(QueryError::IoError)?;
+            }
+            exported += batch.rows.len();
+        }
+        let writer = match writer {
+            Some(writer) => writer,
+            None => export::create(&path, copy.format, &[], &[]).map_err(QueryError::IoError)?,
+        };
+        writer.finish().map_err(QueryError::IoError)?;
+        Ok(statement_output(vec![("exported", Value::Int(exported as i64))]))
+    }
+
+    /// Deletes the rows selected by a `DELETE FROM` statement.
+    fn delete(&self, delete: Delete) -> QueryResult {
+        let filter = self.inner_locustdb.functions().resolve_expr(delete.filter)?;
+        let deleted = self.inner_locustdb.delete(&delete.table, &filter)?;
+        Ok(statement_output(vec![("deleted", Value::Int(deleted as i64))]))
+    }
+
+    /// Adds the generated column of an `ALTER TABLE ... ADD COLUMN ... AS` statement to its table.
+    fn add_column(&self, table: &str, column: GeneratedColumn) -> QueryResult {
+        self.inner_locustdb.ensure_writable().map_err(QueryError::PermissionDenied)?;
+        let expr = self.inner_locustdb.functions().resolve_expr(column.expr)?;
+        self.inner_locustdb.add_generated_column(table, GeneratedColumn { expr, ..column })
+            .map_err(QueryError::NotImplemented)?;
+        Ok(statement_output(vec![]))
+    }
+
+    fn drop_table(&self, table: &str, if_exists: bool) -> QueryResult {
+        match self.inner_locustdb.drop_table(table) {
+            Ok(true) => Ok(statement_output(vec![])),
+            Ok(false) if if_exists => Ok(statement_output(vec![])),
+            Ok(false) => Err(QueryError::NotImplemented(format!("Table {} does not exist!", table))),
+            Err(err) => Err(QueryError::PermissionDenied(err)),
+        }
+    }
+
+    /// Adds the view of a `CREATE VIEW` statement, whose query has to select from an existing table or view.
+    fn create_view(&self, view: &str, sql: &str) -> QueryResult {
+        let query = parser::parse_query(sql)?;
+        if self.inner_locustdb.snapshot(&query.table).is_none() && self.inner_locustdb.view(&query.table).is_none() {
+            bail!(QueryError::NotImplemented, "Table {} does not exist!", query.table)
+        }
+        self.inner_locustdb.create_view(view, sql).map_err(QueryError::NotImplemented)?;
+        Ok(statement_output(vec![]))
     }
 
+    fn drop_view(&self, view: &str, if_exists: bool) -> QueryResult {
+        match self.inner_locustdb.drop_view(view) {
+            Ok(true) => Ok(statement_output(vec![])),
+            Ok(false) if if_exists => Ok(statement_output(vec![])),
+            Ok(false) => Err(QueryError::NotImplemented(format!("View {} does not exist!", view))),
+            Err(err) => Err(QueryError::PermissionDenied(err)),
+        }
+    }
+
+    /// Validates `query` against `all_cols` and normalizes it.
+    fn compile(&self, query: Query, all_cols: &[String]) -> Result<CompiledQuery, QueryError> {
+        self.inner_locustdb.metrics().record_normalization();
+        CompiledQuery::new(query, all_cols)
+    }
+
+    /// Replaces views that `query` selects from with the queries that define them.
+    fn expand_views(&self, mut query: Query) -> Result<Query, QueryError> {
+        let mut expanded = Vec::new();
+        while let Some(sql) = self.inner_locustdb.view(&query.table) {
+            if expanded.contains(&query.table) {
+                bail!(QueryError::InvalidQuery, "View {} refers to itself", query.table)
+            }
+            expanded.push(query.table.clone());
+            let view = self.inner_locustdb.functions().resolve(parser::parse_query(&sql)?)?;
+            query = parser::inline_query(query, view)?;
+        }
+        Ok(query)
+    }
+
+    #[cfg(feature = "ingest_csv")]
     pub fn load_csv(&self, options: LoadOptions) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
         let (sender, receiver) = oneshot::channel();
         let task = CSVIngestionTask::new(
@@ -88,13 +677,44 @@ impl LocustDB {
         receiver
     }
 
+    /// Loads a file with one JSON object per line.
+    #[cfg(feature = "ingest_json")]
+    pub fn load_json(&self, options: JsonLoadOptions) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
+        let (sender, receiver) = oneshot::channel();
+        let task = JSONIngestionTask::new(
+            options,
+            self.inner_locustdb.clone(),
+            SharedSender::new(sender));
+        self.schedule(task);
+        receiver
+    }
+
+    /// Starts a bulk load into `table` whose files only become queryable once it is committed, all at the same time.
+    #[cfg(any(feature = "ingest_csv", feature = "ingest_json"))]
+    pub fn begin_load(&self, table: &str) -> LoadTransaction {
+        LoadTransaction::new(self.inner_locustdb.clone(), table)
+    }
+
+    /// Stores each record batch as a partition of `table`.
+    #[cfg(feature = "enable_arrow")]
+    pub fn ingest_arrow(&self, table: &str, batches: Vec<RecordBatch>) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
+        let inner = self.inner_locustdb.clone();
+        let table = table.to_string();
+        let (task, receiver) = Task::from_fn_with_priority(move || arrow_loader::ingest_batches(&inner, &table, &batches),
+                                                           Priority::Background);
+        self.schedule(task);
+        receiver
+    }
+
+    #[cfg(feature = "colgen")]
     pub fn gen_table(&self, opts: GenTable) -> impl Future<Item=(), Error=oneshot::Canceled> {
         let mut receivers = Vec::new();
         let opts = Arc::new(opts);
         for partition in 0..opts.partitions {
             let opts = opts.clone();
             let inner = self.inner_locustdb.clone();
-            let (task, receiver) = Task::from_fn(move || inner.gen_partition(&opts, partition as u64));
+            let (task, receiver) = Task::from_fn_with_priority(move || inner.gen_partition(&opts, partition as u64),
+                                                               Priority::Background);
             self.schedule(task);
             receivers.push(receiver);
         }
@@ -127,6 +747,34 @@ impl LocustDB {
         self.mem_tree(2)
     }
 
+    /// Returns a handle for continuously writing rows to `table`.
+    pub fn table_writer(&self, table: &str) -> TableWriter {
+        TableWriter::new(self.inner_locustdb.clone(), table)
+    }
+
+    /// Turns all buffered rows into partitions, which compresses them and removes them from the write-ahead log.
+    pub fn flush(&self) {
+        self.inner_locustdb.flush();
+    }
+
+    /// Writes all tables to the directory at `path` in their compressed form.
+    pub fn snapshot(&self, path: &str) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
+        let inner = self.inner_locustdb.clone();
+        let path = path.to_string();
+        let (task, receiver) = Task::from_fn(move || inner.write_snapshot(&path));
+        self.schedule(task);
+        receiver
+    }
+
+    /// Loads all tables from a snapshot created with `snapshot`.
+    pub fn restore(&self, path: &str) -> impl Future<Item=Result<(), String>, Error=oneshot::Canceled> {
+        let inner = self.inner_locustdb.clone();
+        let path = path.to_string();
+        let (task, receiver) = Task::from_fn(move || inner.restore_snapshot(&path));
+        self.schedule(task);
+        receiver
+    }
+
     pub fn recover(&self) {
         self.inner_locustdb.drop_pending_tasks();
         InnerLocustDB::start_worker_threads(&self.inner_locustdb);
@@ -146,6 +794,14 @@ impl LocustDB {
         receiver
     }
 
+    /// Bytes used by the resident data of each table, column and encoding.
+    pub fn mem_stats(&self) -> impl Future<Item=Vec<MemStats>, Error=oneshot::Canceled> {
+        let inner = self.inner_locustdb.clone();
+        let (task, receiver) = Task::from_fn(move || inner.mem_stats());
+        self.schedule(task);
+        receiver
+    }
+
     fn schedule<T: Task + 'static>(&self, task: T) -> impl Future<Item=Trace, Error=oneshot::Canceled> {
         self.inner_locustdb.schedule(task)
     }
@@ -162,34 +818,437 @@ impl LocustDB {
     }
 }
 
+/// Future for the result of a running query which can be used to cancel it.
+/// Statements other than queries are executed before the handle is returned and can't be cancelled.
+pub struct QueryHandle {
+    result: Box<Future<Item=(QueryResult, Trace), Error=oneshot::Canceled>>,
+    cancellation: CancellationToken,
+}
+
+impl QueryHandle {
+    /// Stops the query, which then completes with `QueryError::Cancelled`.
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    /// Token that cancels the query, e.g. from another thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+}
+
+impl Future for QueryHandle {
+    type Item = (QueryResult, Trace);
+    type Error = oneshot::Canceled;
+
+    fn poll(&mut self, cx: &mut task::Context) -> Poll<(QueryResult, Trace), oneshot::Canceled> {
+        self.result.poll(cx)
+    }
+}
+
+/// Rows returned by `LocustDB::run_page`.
+pub struct Page {
+    pub output: QueryOutput,
+    /// Continues the query after the last row of the page, `None` if there are no further rows.
+    pub cursor: Option<String>,
+}
+
+/// Result of a statement that is not a query, consisting of a single row with the given values.
+fn statement_output(values: Vec<(&str, Value)>) -> QueryOutput {
+    let (colnames, row): (Vec<_>, Vec<_>) = values.into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .unzip();
+    let rows = if row.is_empty() { vec![] } else { vec![row] };
+    QueryOutput {
+        coltypes: row_types(colnames.len(), &rows),
+        colnames,
+        rows,
+        query_plans: Default::default(),
+        plan_graphs: vec![],
+        stats: QueryStats::default(),
+    }
+}
+
 impl Drop for LocustDB {
     fn drop(&mut self) {
         self.inner_locustdb.stop();
     }
 }
 
+/// Callback invoked with the query string and result after each query completes.
+pub type QueryHook = Arc<Fn(&str, &QueryResult) + Send + Sync>;
+
 #[derive(Clone)]
 pub struct Options {
     pub threads: usize,
     pub read_threads: usize,
     pub db_path: Option<String>,
+    /// File that table definitions and partition manifests are persisted to, `catalog` in `db_path` by default.
+    pub catalog_path: Option<String>,
     pub mem_size_limit_tables: usize,
     pub mem_lz4: bool,
     pub readahead: usize,
     pub seq_disk_read: bool,
+    /// Memory-maps the data of snapshots opened with `open_read_only` instead of reading it into memory.
+    pub mmap: bool,
+    pub read_only: bool,
+    pub flush_interval_secs: u64,
+    pub partition_size_rows: usize,
+    pub compaction_threshold: f64,
+    /// Merges small partitions in the background, see `LocustDBBuilder::merge_partition_rows`.
+    pub merge_partition_rows: Option<usize>,
+    pub query_hook: Option<QueryHook>,
+    pub query_timeout: Option<Duration>,
+    pub query_memory_limit: Option<usize>,
+    pub result_cache_bytes: usize,
+    pub subresult_cache_bytes: usize,
+    pub batch_size_rows: usize,
+    pub max_concurrent_queries: Option<usize>,
+    pub bulk_query_threshold: Duration,
+    pub pin_threads: bool,
+    /// Number of recent queries kept in the `_query_log` system table.
+    pub query_log_size: usize,
+    /// Queries that take longer are logged as warning.
+    pub slow_query_threshold: Option<Duration>,
+    /// How sums that exceed the range of 64-bit integers are handled by queries that don't specify it.
+    pub sum_overflow: SumOverflow,
+    /// Number of partial results of the same level that are merged into one result of the next level.
+    pub merge_fan_in: usize,
+    /// Bytes of unmerged partial results held by a worker thread above which results are written to disk.
+    pub merge_spill_threshold: Option<usize>,
+    /// Directory for spilled partial results, the system's temporary directory if `None`.
+    pub spill_directory: Option<String>,
+    /// Makes results reproducible, see `LocustDBBuilder::deterministic`.
+    pub deterministic: bool,
+    /// Directory that `COPY (SELECT ...) TO 'file'` writes files to, `COPY` is rejected if `None`.
+    pub export_directory: Option<String>,
+}
+
+impl Options {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.db_path.is_some() && !cfg!(feature = "enable_rocksdb") {
+            return Err("`db_path` is set, but the RocksDB storage backend is not enabled in this build of LocustDB".to_string());
+        }
+        if self.read_only && self.db_path.is_none() {
+            return Err("`read_only` requires a `db_path`".to_string());
+        }
+        if self.db_path.is_some() && self.read_threads == 0 {
+            return Err("`read_threads` must be at least 1 when reading from disk".to_string());
+        }
+        if self.readahead > self.mem_size_limit_tables {
+            return Err(format!("`readahead` ({}) must not be larger than `mem_size_limit_tables` ({})",
+                               self.readahead, self.mem_size_limit_tables));
+        }
+        if self.partition_size_rows == 0 {
+            return Err("`partition_size_rows` must be at least 1".to_string());
+        }
+        if self.merge_partition_rows == Some(0) {
+            return Err("`merge_partition_rows` must be at least 1".to_string());
+        }
+        if !(self.compaction_threshold > 0.0 && self.compaction_threshold <= 1.0) {
+            return Err(format!("`compaction_threshold` ({}) must be greater than 0 and at most 1", self.compaction_threshold));
+        }
+        if self.batch_size_rows == 0 {
+            return Err("`batch_size_rows` must be at least 1".to_string());
+        }
+        if self.max_concurrent_queries == Some(0) {
+            return Err("`max_concurrent_queries` must be at least 1".to_string());
+        }
+        if self.merge_fan_in < 2 {
+            return Err("`merge_fan_in` must be at least 2".to_string());
+        }
+        if self.mmap && !cfg!(feature = "enable_mmap") {
+            return Err("`mmap` is set, but memory mapping is not enabled in this build of LocustDB".to_string());
+        }
+        if self.threads == 0 && self.seq_disk_read {
+            return Err("`seq_disk_read` requires at least one worker thread".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Defaults shared by all targets, `Options::default` overrides the ones that depend on the target.
+fn base_options() -> Options {
+    Options {
+        threads: num_cpus::get(),
+        read_threads: num_cpus::get(),
+        db_path: None,
+        catalog_path: None,
+        mem_size_limit_tables: 1024 * 1024 * 1024, // 1 GiB
+        mem_lz4: true,
+        readahead: 256 * 1024 * 1024, // 256 MiB
+        seq_disk_read: false,
+        mmap: false,
+        read_only: false,
+        flush_interval_secs: 60,
+        partition_size_rows: 1 << 20,
+        compaction_threshold: 0.5,
+        merge_partition_rows: None,
+        query_hook: None,
+        query_timeout: None,
+        query_memory_limit: None,
+        result_cache_bytes: 0,
+        subresult_cache_bytes: 0,
+        batch_size_rows: DEFAULT_BATCH_SIZE,
+        max_concurrent_queries: None,
+        bulk_query_threshold: Duration::from_secs(1),
+        pin_threads: false,
+        query_log_size: 1000,
+        slow_query_threshold: None,
+        sum_overflow: SumOverflow::Error,
+        merge_fan_in: 2,
+        merge_spill_threshold: None,
+        spill_directory: None,
+        deterministic: false,
+        export_directory: None,
+    }
 }
 
 impl Default for Options {
+    #[cfg(not(target_arch = "wasm32"))]
     fn default() -> Options {
         Options {
-            threads: num_cpus::get(),
-            read_threads: num_cpus::get(),
-            db_path: None,
             mem_size_limit_tables: 8 * 1024 * 1024 * 1024, // 8 GiB
-            mem_lz4: true,
-            readahead: 256 * 1024 * 1024, // 256 MiB
-            seq_disk_read: false,
+            ..base_options()
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Options {
+        Options {
+            threads: 0, // run all tasks on the calling thread
+            read_threads: 1,
+            mem_lz4: false,
+            readahead: 16 * 1024 * 1024, // 16 MiB
+            ..base_options()
         }
     }
 }
 
+#[derive(Default)]
+pub struct LocustDBBuilder {
+    opts: Options,
+}
+
+impl LocustDBBuilder {
+    /// Limit for the in-memory size of tables in bytes. When it is exceeded, the least recently queried partitions are
+    /// evicted and reloaded from `db_path` once they are accessed again.
+    pub fn mem_size_limit_tables(mut self, bytes: usize) -> LocustDBBuilder {
+        self.opts.mem_size_limit_tables = bytes;
+        self
+    }
+
+    /// Number of worker threads. If set to 0, all tasks are run on the thread that schedules them.
+    pub fn threads(mut self, threads: usize) -> LocustDBBuilder {
+        self.opts.threads = threads;
+        self
+    }
+
+    /// Number of threads used to read data from disk.
+    pub fn read_threads(mut self, read_threads: usize) -> LocustDBBuilder {
+        self.opts.read_threads = read_threads;
+        self
+    }
+
+    /// Directory used to persist data.
+    pub fn db_path(mut self, path: &str) -> LocustDBBuilder {
+        self.opts.db_path = Some(path.to_string());
+        self
+    }
+
+    /// File that the catalog of tables is persisted to, which restores tables, their declared column types, retention
+    /// and generated columns on restart.
+    pub fn catalog_path(mut self, path: &str) -> LocustDBBuilder {
+        self.opts.catalog_path = Some(path.to_string());
+        self
+    }
+
+    /// Rejects all ingestion, only data already persisted at `db_path` can be queried.
+    pub fn read_only(mut self, read_only: bool) -> LocustDBBuilder {
+        self.opts.read_only = read_only;
+        self
+    }
+
+    /// Interval in seconds at which buffered rows are turned into partitions and removed from the write-ahead log.
+    pub fn flush_interval_secs(mut self, secs: u64) -> LocustDBBuilder {
+        self.opts.flush_interval_secs = secs;
+        self
+    }
+
+    /// Number of buffered rows at which a table's open partition is sealed.
+    pub fn partition_size_rows(mut self, rows: usize) -> LocustDBBuilder {
+        self.opts.partition_size_rows = rows;
+        self
+    }
+
+    /// Fraction of deleted rows at which a partition is rewritten without them.
+    pub fn compaction_threshold(mut self, fraction: f64) -> LocustDBBuilder {
+        self.opts.compaction_threshold = fraction;
+        self
+    }
+
+    /// Every minute, merges runs of adjacent partitions with fewer than `rows / 2` rows into partitions of at most `rows`
+    /// rows. Disabled by default.
+    pub fn merge_partition_rows(mut self, rows: usize) -> LocustDBBuilder {
+        self.opts.merge_partition_rows = Some(rows);
+        self
+    }
+
+    /// Keep data cached in memory lz4 encoded.
+    pub fn mem_lz4(mut self, mem_lz4: bool) -> LocustDBBuilder {
+        self.opts.mem_lz4 = mem_lz4;
+        self
+    }
+
+    /// How much data to load at a time when reading from disk during queries in bytes.
+    pub fn readahead(mut self, bytes: usize) -> LocustDBBuilder {
+        self.opts.readahead = bytes;
+        self
+    }
+
+    /// Memory-maps the column data of snapshots opened with `open_read_only`, which are then paged in as queries access
+    /// them rather than read into memory up front.
+    pub fn mmap(mut self, mmap: bool) -> LocustDBBuilder {
+        self.opts.mmap = mmap;
+        self
+    }
+
+    /// Read data from disk sequentially with a single thread. Improves performance on HDD.
+    pub fn seq_disk_read(mut self, seq_disk_read: bool) -> LocustDBBuilder {
+        self.opts.seq_disk_read = seq_disk_read;
+        if seq_disk_read {
+            self.opts.read_threads = 1;
+        }
+        self
+    }
+
+    /// Registers a callback that is invoked after every query, e.g. to record metrics or log queries.
+    pub fn on_query<F>(mut self, hook: F) -> LocustDBBuilder
+        where F: Fn(&str, &QueryResult) + Send + Sync + 'static {
+        self.opts.query_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Number of recent queries that are recorded in the `_query_log` system table. Defaults to 1000.
+    pub fn query_log_size(mut self, queries: usize) -> LocustDBBuilder {
+        self.opts.query_log_size = queries;
+        self
+    }
+
+    /// Number of partial results of the same level that are merged at once. Larger values merge each row fewer
+    /// times, but each thread holds more unmerged results. Defaults to 2.
+    pub fn merge_fan_in(mut self, fan_in: usize) -> LocustDBBuilder {
+        self.opts.merge_fan_in = fan_in;
+        self
+    }
+
+    /// Writes partial results to `directory` (the system's temporary directory if `None`) once the unmerged results
+    /// held by a worker thread exceed `threshold_bytes`, and reads them back when they are merged.
+    pub fn merge_spill(mut self, threshold_bytes: usize, directory: Option<&str>) -> LocustDBBuilder {
+        self.opts.merge_spill_threshold = Some(threshold_bytes);
+        self.opts.spill_directory = directory.map(str::to_string);
+        self
+    }
+
+    /// Allows `COPY (SELECT ...) TO 'file'` statements, which write `file` relative to `directory`. Paths that are
+    /// absolute or contain `..` are rejected.
+    pub fn export_directory(mut self, directory: &str) -> LocustDBBuilder {
+        self.opts.export_directory = Some(directory.to_string());
+        self
+    }
+
+    /// Runs each query on a single thread that processes partitions in order, sorts stably and orders groups of
+    /// aggregations without `ORDER BY` by their values, so that the same data always produces identical results.
+    pub fn deterministic(mut self, deterministic: bool) -> LocustDBBuilder {
+        self.opts.deterministic = deterministic;
+        self
+    }
+
+    /// Logs a warning for every query that runs for longer than `threshold`.
+    pub fn slow_query_threshold(mut self, threshold: Duration) -> LocustDBBuilder {
+        self.opts.slow_query_threshold = Some(threshold);
+        self
+    }
+
+    /// Handling of integer sums that exceed the range of `i64`. By default, queries fail with `QueryError::Overflow`.
+    /// Can be overridden for individual queries with `QueryBuilder::sum_overflow`.
+    pub fn sum_overflow(mut self, sum_overflow: SumOverflow) -> LocustDBBuilder {
+        self.opts.sum_overflow = sum_overflow;
+        self
+    }
+
+    /// Maximum time a query may run before it fails with `QueryError::ResourceExhausted`.
+    pub fn query_timeout(mut self, timeout: Duration) -> LocustDBBuilder {
+        self.opts.query_timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum bytes of intermediate buffers and partial results a query may hold at the same time, summed over all
+    /// threads that execute it, before it fails with `QueryError::ResourceExhausted`.
+    pub fn query_memory_limit(mut self, bytes: usize) -> LocustDBBuilder {
+        self.opts.query_memory_limit = Some(bytes);
+        self
+    }
+
+    /// Maximum size of cached query results in bytes. Results are reused until the queried table changes. Disabled
+    /// by default.
+    pub fn result_cache_bytes(mut self, bytes: usize) -> LocustDBBuilder {
+        self.opts.result_cache_bytes = bytes;
+        self
+    }
+
+    /// Maximum size of cached aggregation results of individual partitions in bytes. Since partitions don't change,
+    /// repeated aggregations only have to process partitions that were created since they last ran. Disabled by
+    /// default.
+    pub fn subresult_cache_bytes(mut self, bytes: usize) -> LocustDBBuilder {
+        self.opts.subresult_cache_bytes = bytes;
+        self
+    }
+
+    /// Number of rows that operators reading from columns process at a time. Smaller batches reduce the size of
+    /// intermediate buffers, larger batches reduce the overhead per batch.
+    pub fn batch_size_rows(mut self, rows: usize) -> LocustDBBuilder {
+        self.opts.batch_size_rows = rows;
+        self
+    }
+
+    /// Maximum number of queries that are executed at the same time, further queries wait until one of them completes.
+    pub fn max_concurrent_queries(mut self, queries: usize) -> LocustDBBuilder {
+        self.opts.max_concurrent_queries = Some(queries);
+        self
+    }
+
+    /// Time after which a running query yields its worker threads to more recent queries and other tasks.
+    pub fn bulk_query_threshold(mut self, threshold: Duration) -> LocustDBBuilder {
+        self.opts.bulk_query_threshold = threshold;
+        self
+    }
+
+    /// Pins worker threads to CPUs spread across NUMA nodes and lets them process the partitions that were created on
+    /// their own node first.
+    pub fn pin_threads(mut self, pin_threads: bool) -> LocustDBBuilder {
+        self.opts.pin_threads = pin_threads;
+        self
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.opts
+    }
+
+    pub fn build(self) -> Result<LocustDB, String> {
+        self.opts.validate()?;
+        Ok(LocustDB::new(&self.opts))
+    }
+
+    /// Opens the snapshot directory at `path` with the options of the builder, see `LocustDB::open_read_only`.
+    pub fn open_read_only(mut self, path: &str) -> Result<LocustDB, String> {
+        if self.opts.db_path.is_some() {
+            return Err("`open_read_only` can not be combined with a `db_path`".to_string());
+        }
+        self.opts.validate()?;
+        self.opts.read_only = true;
+        let locustdb = LocustDB::new(&self.opts);
+        locustdb.inner_locustdb.load_snapshot(path)?;
+        Ok(locustdb)
+    }
+}
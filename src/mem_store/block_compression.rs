@@ -0,0 +1,43 @@
+use std::fmt::Debug;
+
+use mem_store::lz4;
+use mem_store::zstd;
+
+
+/// General purpose compression of the first data section of columns, which is decompressed at query time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlockCompression {
+    None,
+    LZ4,
+    Zstd,
+}
+
+impl BlockCompression {
+    /// Returns an error if this build of LocustDB doesn't support the compression.
+    pub fn ensure_supported(self) -> Result<(), String> {
+        match self {
+            BlockCompression::LZ4 if !cfg!(feature = "enable_lz4") =>
+                Err("lz4 not supported in this build of LocustDB. Recompile with --features enable_lz4.".to_string()),
+            BlockCompression::Zstd if !cfg!(feature = "enable_zstd") =>
+                Err("zstd not supported in this build of LocustDB. Recompile with --features enable_zstd.".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn encode<T: Debug>(self, data: &[T]) -> Vec<u8> {
+        match self {
+            BlockCompression::LZ4 => lz4::encode(data),
+            BlockCompression::Zstd => zstd::encode(data),
+            BlockCompression::None => panic!("BlockCompression::None.encode()"),
+        }
+    }
+
+    /// Decodes `encoded` into `decoded` and returns the number of decoded elements.
+    pub fn decode<T>(self, encoded: &[u8], decoded: &mut [T]) -> usize {
+        match self {
+            BlockCompression::LZ4 => lz4::decode_all(encoded, decoded),
+            BlockCompression::Zstd => zstd::decode_all(encoded, decoded),
+            BlockCompression::None => panic!("BlockCompression::None.decode()"),
+        }
+    }
+}
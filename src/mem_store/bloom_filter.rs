@@ -0,0 +1,62 @@
+use heapsize::HeapSizeOf;
+use seahash;
+
+use ingest::raw_val::RawVal;
+
+
+const BITS_PER_VALUE: usize = 10;
+const HASH_COUNT: u64 = 7;
+
+/// Set of integer and string values that may report values as contained that were never inserted (with a probability
+/// of about 1%), but never misses a value that was inserted.
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    pub fn new(expected_values: usize) -> BloomFilter {
+        let words = (expected_values * BITS_PER_VALUE + 63) / 64;
+        BloomFilter { bits: vec![0; words.max(1)] }
+    }
+
+    pub fn insert(&mut self, value: &RawVal) {
+        if let Some(hash) = hash(value) {
+            let len = self.bits.len() as u64 * 64;
+            for bit in bit_indices(hash, len) {
+                self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+    }
+
+    /// Returns `false` if `value` was definitely not inserted. Always `true` for floats and null.
+    pub fn may_contain(&self, value: &RawVal) -> bool {
+        match hash(value) {
+            Some(hash) => {
+                let len = self.bits.len() as u64 * 64;
+                bit_indices(hash, len).all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+            }
+            None => true,
+        }
+    }
+}
+
+impl HeapSizeOf for BloomFilter {
+    fn heap_size_of_children(&self) -> usize {
+        self.bits.heap_size_of_children()
+    }
+}
+
+fn hash(value: &RawVal) -> Option<u64> {
+    match *value {
+        RawVal::Int(int) => Some(seahash::hash(&int.to_le_bytes())),
+        RawVal::Str(ref string) => Some(seahash::hash(string.as_bytes()) ^ 0x9e37_79b9_7f4a_7c15),
+        RawVal::Float(_) | RawVal::Null => None,
+    }
+}
+
+// Double hashing, derives all indices from the two halves of a single hash
+fn bit_indices(hash: u64, len: u64) -> impl Iterator<Item=u64> {
+    let h1 = hash & 0xffff_ffff;
+    let h2 = hash >> 32;
+    (0..HASH_COUNT).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % len)
+}
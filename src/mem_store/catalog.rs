@@ -0,0 +1,126 @@
+use std::cmp;
+
+use engine::data_types::BasicType;
+use ingest::raw_val::RawVal;
+use mem_store::codec::CodecOp;
+use mem_store::column::{Column, DataSource};
+use mem_store::zone_map::ZoneMap;
+use syntax::expression::*;
+
+
+/// Statistics about the values of a column in one partition, which are computed when the partition is sealed or
+/// compacted and stored with its metadata.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub zone_map: ZoneMap,
+    /// Estimated number of distinct non-null values, `None` if it is not known.
+    pub distinct_values: Option<usize>,
+}
+
+impl ColumnStats {
+    pub fn of(column: &Column) -> ColumnStats {
+        let zone_map = ZoneMap::of(column);
+        ColumnStats { zone_map, distinct_values: distinct_values(column, zone_map) }
+    }
+
+    /// Statistics of a column that is being queried, the number of nulls is not determined.
+    pub fn of_data_source(column: &DataSource) -> ColumnStats {
+        let zone_map = ZoneMap::of_data_source(column);
+        ColumnStats { zone_map, distinct_values: distinct_values(column, zone_map) }
+    }
+
+    /// Statistics of a column that is not part of a partition and therefore all null.
+    pub fn null(len: usize) -> ColumnStats {
+        ColumnStats { zone_map: ZoneMap::null(len), distinct_values: Some(0) }
+    }
+
+    pub fn unknown() -> ColumnStats {
+        ColumnStats { zone_map: ZoneMap::unknown(), distinct_values: None }
+    }
+}
+
+/// Size of the dictionary of dictionary encoded columns, otherwise bounded by the width of the range of integer columns
+/// and the number of values that are not null.
+fn distinct_values(column: &DataSource, zone_map: ZoneMap) -> Option<usize> {
+    if column.full_type().decoded == BasicType::Null || zone_map.null_count == Some(column.len()) {
+        return Some(0);
+    }
+    let codec = column.codec();
+    // Shared dictionaries also contain the values of other partitions
+    let dictionary = codec.ops().windows(3)
+        .filter_map(|ops| match *ops {
+            [CodecOp::PushDataSection(offsets), CodecOp::PushDataSection(_), CodecOp::DictLookup(_)] => Some(offsets),
+            _ => None,
+        })
+        .next()
+        .and_then(|offsets| column.data_sections().get(offsets).map(|section| section.len()));
+    let range = zone_map.range.map(|(min, max)| (max as i128 - min as i128 + 1) as usize);
+    let present = zone_map.null_count.map(|null_count| column.len() - null_count);
+    match (dictionary.or(range), present) {
+        (Some(bound), Some(present)) => Some(cmp::min(bound, present)),
+        (bound, present) => bound.or(present),
+    }
+}
+
+/// Estimated fraction of rows that satisfy `filter`, assuming that values are distributed uniformly within the range of
+/// their column and over their distinct values.
+pub fn selectivity<F>(filter: &Expr, stats: &F) -> f64 where F: Fn(&str) -> ColumnStats {
+    match *filter {
+        Expr::Const(RawVal::Int(0)) | Expr::Const(RawVal::Null) => 0.0,
+        Expr::Const(_) => 1.0,
+        Expr::Func2(Func2Type::And, ref lhs, ref rhs) => selectivity(lhs, stats) * selectivity(rhs, stats),
+        Expr::Func2(Func2Type::Or, ref lhs, ref rhs) => {
+            let (lhs, rhs) = (selectivity(lhs, stats), selectivity(rhs, stats));
+            lhs + rhs - lhs * rhs
+        }
+        Expr::Func1(Func1Type::Not, ref expr) => 1.0 - selectivity(expr, stats),
+        Expr::Func2(op, box Expr::ColName(ref name), box Expr::Const(ref value)) =>
+            compare_selectivity(op, stats(name), value),
+        Expr::Func2(op, box Expr::Const(ref value), box Expr::ColName(ref name)) => match op.flipped() {
+            Some(flipped) => compare_selectivity(flipped, stats(name), value),
+            None => DEFAULT_SELECTIVITY,
+        },
+        Expr::In(box Expr::ColName(ref name), ref values) => {
+            let stats = stats(name);
+            values.iter()
+                .map(|value| compare_selectivity(Func2Type::Equals, stats, value))
+                .sum::<f64>()
+                .min(1.0)
+        }
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+const DEFAULT_SELECTIVITY: f64 = 0.5;
+
+fn compare_selectivity(op: Func2Type, stats: ColumnStats, value: &RawVal) -> f64 {
+    let range = match (stats.zone_map.range, value) {
+        (Some(range), &RawVal::Int(value)) => Some((range, value)),
+        _ => None,
+    };
+    let equal = match (range, stats.distinct_values) {
+        (Some(((min, max), value)), _) if value < min || value > max => 0.0,
+        (_, Some(distinct)) => 1.0 / cmp::max(distinct, 1) as f64,
+        (Some(((min, max), _)), None) => 1.0 / (max as f64 - min as f64 + 1.0),
+        (None, None) => return DEFAULT_SELECTIVITY,
+    };
+    match op {
+        Func2Type::Equals => equal,
+        Func2Type::NotEquals => 1.0 - equal,
+        _ => match range {
+            Some(((min, max), value)) => {
+                let width = max as f64 - min as f64 + 1.0;
+                // Fraction of values that are less than `value`
+                let less = ((value as f64 - min as f64) / width).max(0.0).min(1.0);
+                match op {
+                    Func2Type::LT => less,
+                    Func2Type::LTE => (less + equal).min(1.0),
+                    Func2Type::GT => (1.0 - less - equal).max(0.0),
+                    Func2Type::GTE => 1.0 - less,
+                    _ => DEFAULT_SELECTIVITY,
+                }
+            }
+            None => DEFAULT_SELECTIVITY,
+        },
+    }
+}
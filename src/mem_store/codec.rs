@@ -1,6 +1,8 @@
 use engine::*;
 use engine::data_types::*;
 use engine::planning::QueryPlanner;
+use mem_store::BlockCompression;
+use syntax::expression::Func2Type;
 
 
 #[derive(Debug, Clone, HeapSizeOf)]
@@ -77,8 +79,13 @@ impl Codec {
         }
     }
 
-    pub fn with_lz4(&self, decoded_length: usize) -> Codec {
-        let mut ops = vec![CodecOp::LZ4(self.section_types[0], decoded_length)];
+    pub fn with_compression(&self, compression: BlockCompression, decoded_length: usize) -> Codec {
+        let t = self.section_types[0];
+        let mut ops = vec![match compression {
+            BlockCompression::LZ4 => CodecOp::LZ4(t, decoded_length),
+            BlockCompression::Zstd => CodecOp::Zstd(t, decoded_length),
+            BlockCompression::None => return self.clone(),
+        }];
         for &op in &self.ops {
             ops.push(op);
         }
@@ -89,15 +96,14 @@ impl Codec {
         codec
     }
 
-    pub fn without_lz4(&self) -> Codec {
+    pub fn without_compression(&self) -> Codec {
         let mut ops = Vec::with_capacity(self.ops.len() - 1);
         let mut decoded_type = None;
         for &op in &self.ops {
-            if let CodecOp::LZ4(t, _) = op {
-                decoded_type = Some(t);
-                continue;
+            match op {
+                CodecOp::LZ4(t, _) | CodecOp::Zstd(t, _) => decoded_type = Some(t),
+                _ => ops.push(op),
             }
-            ops.push(op);
         }
         let mut codec = if ops.is_empty() {
             Codec::identity(self.decoded_type)
@@ -112,6 +118,15 @@ impl Codec {
         codec
     }
 
+    /// Block compression of the first data section and the type of its decompressed values.
+    pub fn block_compression(&self) -> Option<(BlockCompression, EncodingType)> {
+        match self.ops.get(0) {
+            Some(&CodecOp::LZ4(t, _)) => Some((BlockCompression::LZ4, t)),
+            Some(&CodecOp::Zstd(t, _)) => Some((BlockCompression::Zstd, t)),
+            _ => None,
+        }
+    }
+
     pub fn decode(&self,
                   plan: TypedBufferRef,
                   planner: &mut QueryPlanner) -> TypedBufferRef {
@@ -136,6 +151,11 @@ impl Codec {
                     planner.add(lhs, rhs).into()
                 }
                 CodecOp::Delta(_) => planner.delta_decode(stack.pop().unwrap()).into(),
+                CodecOp::RunLength(_) => {
+                    let run_lengths = stack.pop().unwrap().u32().unwrap();
+                    let values = stack.pop().unwrap();
+                    planner.run_length_decode(values, run_lengths)
+                }
                 CodecOp::ToI64(_) => planner.cast(stack.pop().unwrap(), EncodingType::I64),
                 CodecOp::PushDataSection(section_index) =>
                     planner.column_section(
@@ -143,7 +163,7 @@ impl Codec {
                         section_index,
                         None,
                         self.section_types[section_index]),
-                CodecOp::DictLookup(_t) => {
+                CodecOp::DictLookup(_t) | CodecOp::SharedDictLookup(_t) => {
                     let dict_data = stack.pop().unwrap();
                     let dict_indices = stack.pop().unwrap();
                     let indices = stack.pop().unwrap();
@@ -151,9 +171,16 @@ impl Codec {
                 }
                 CodecOp::LZ4(t, decoded_length) =>
                     planner.lz4_decode(stack.pop().unwrap().u8().unwrap(), decoded_length, t),
+                CodecOp::Zstd(t, decoded_length) =>
+                    planner.zstd_decode(stack.pop().unwrap().u8().unwrap(), decoded_length, t),
                 CodecOp::UnpackStrings => planner.unpack_strings(stack.pop().unwrap().u8().unwrap()).into(),
                 CodecOp::UnhexpackStrings(upper, total_bytes) =>
                     planner.unhexpack_strings(stack.pop().unwrap().u8().unwrap(), upper, total_bytes).into(),
+                CodecOp::List => {
+                    let elements = stack.pop().unwrap().str().unwrap();
+                    let ends = stack.pop().unwrap().u32().unwrap();
+                    planner.encode_lists(ends, elements).into()
+                }
                 CodecOp::Unknown => panic!("unknown decode plan!"),
             };
             stack.push(plan);
@@ -185,16 +212,91 @@ impl Codec {
     pub fn is_elementwise_decodable(&self) -> bool { self.is_fixed_width }
     pub fn is_identity(&self) -> bool { self.ops.is_empty() }
 
+    /// Encodes `string_const` such that `comparison` of encoded values with the result gives the same result as on
+    /// the decoded values.
     pub fn encode_str(&self,
                       string_const: BufferRef<Scalar<&'static str>>,
+                      comparison: Func2Type,
                       planner: &mut QueryPlanner) -> BufferRef<Scalar<i64>> {
+        let (offset_len, backing_store) = match self.dictionary(planner) {
+            Some(dictionary) => dictionary,
+            None => panic!("encode_str not supported for {:?}", &self.ops),
+        };
+        // Sorted dictionaries allow range comparisons by finding the boundary of the matching indices
+        match comparison {
+            Func2Type::LT | Func2Type::GTE if self.is_order_preserving =>
+                planner.dict_bound(offset_len, backing_store, string_const, false),
+            Func2Type::LTE | Func2Type::GT if self.is_order_preserving =>
+                planner.dict_bound(offset_len, backing_store, string_const, true),
+            _ => planner.inverse_dict_lookup(offset_len, backing_store, string_const),
+        }
+    }
+
+    /// Returns the dictionary offsets and backing store if this codec is a plain or shared dictionary lookup.
+    pub fn dictionary(&self, planner: &mut QueryPlanner) -> Option<(BufferRef<u64>, BufferRef<u8>)> {
         match self.ops[..] {
-            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)] => {
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::DictLookup(_)] |
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::SharedDictLookup(_)] => {
                 let offset_len = planner.column_section(&self.column_name, 1, None, EncodingType::U64).u64().unwrap();
                 let backing_store = planner.column_section(&self.column_name, 2, None, EncodingType::U8).u8().unwrap();
-                planner.inverse_dict_lookup(offset_len, backing_store, string_const)
+                Some((offset_len, backing_store))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this codec looks up values in a dictionary that is shared by all partitions of the column.
+    pub fn is_shared_dictionary(&self) -> bool {
+        match self.ops[..] {
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::SharedDictLookup(_)] => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the offsets at which the list of each row ends, the dictionary indices of all list elements and the
+    /// dictionary offsets and backing store if this codec decodes lists of strings.
+    pub fn list(&self, planner: &mut QueryPlanner) -> Option<(BufferRef<u32>, TypedBufferRef, BufferRef<u64>, BufferRef<u8>)> {
+        match self.ops[..] {
+            [CodecOp::PushDataSection(1), CodecOp::PushDataSection(2), CodecOp::PushDataSection(3), CodecOp::DictLookup(t), CodecOp::List] => {
+                let ends = planner.column_section(&self.column_name, 0, None, EncodingType::U32).u32().unwrap();
+                let elements = planner.column_section(&self.column_name, 1, None, t);
+                let offset_len = planner.column_section(&self.column_name, 2, None, EncodingType::U64).u64().unwrap();
+                let backing_store = planner.column_section(&self.column_name, 3, None, EncodingType::U8).u8().unwrap();
+                Some((ends, elements, offset_len, backing_store))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this codec decodes lists of strings.
+    pub fn is_list(&self) -> bool {
+        self.ops.last() == Some(&CodecOp::List)
+    }
+
+    /// Codec of the run values if this codec starts by expanding run-length encoded values.
+    pub fn run_values(&self) -> Option<Codec> {
+        if self.ops.len() < 2 || self.ops[0] != CodecOp::PushDataSection(1) {
+            return None;
+        }
+        match self.ops[1] {
+            CodecOp::RunLength(t) => {
+                let mut codec = if self.ops.len() == 2 {
+                    Codec::identity(self.decoded_type)
+                } else {
+                    Codec::new(self.ops[2..].to_vec(), vec![t])
+                };
+                codec.set_column_name(&self.column_name);
+                Some(codec)
             }
-            _ => panic!("encode_str not supported for {:?}", &self.ops),
+            _ => None,
+        }
+    }
+
+    /// True if decoding only widens the encoded integers to i64.
+    pub fn is_widening(&self) -> bool {
+        match self.ops[..] {
+            [CodecOp::ToI64(_)] => true,
+            _ => false,
         }
     }
 
@@ -278,12 +380,18 @@ pub enum CodecOp {
     Nullable,
     Add(EncodingType, i64),
     Delta(EncodingType),
+    RunLength(EncodingType),
     ToI64(EncodingType),
     PushDataSection(usize),
     DictLookup(EncodingType),
+    /// Lookup in a dictionary that is shared by all partitions and therefore not sorted.
+    SharedDictLookup(EncodingType),
     LZ4(EncodingType, usize),
+    Zstd(EncodingType, usize),
     UnpackStrings,
     UnhexpackStrings(bool, usize),
+    /// Groups the strings on top of the stack into lists that end at the offsets below them, decoded as JSON arrays.
+    List,
     Unknown,
 }
 
@@ -293,11 +401,15 @@ impl CodecOp {
             CodecOp::Nullable => BasicType::Integer,
             CodecOp::Add(_, _) => BasicType::Integer,
             CodecOp::Delta(_) => BasicType::Integer,
+            CodecOp::RunLength(_) => BasicType::Integer,
             CodecOp::ToI64(_) => BasicType::Integer,
             CodecOp::DictLookup(_) => BasicType::String,
+            CodecOp::SharedDictLookup(_) => BasicType::String,
             CodecOp::LZ4(_, _) => BasicType::Integer,
+            CodecOp::Zstd(_, _) => BasicType::Integer,
             CodecOp::UnpackStrings => BasicType::String,
             CodecOp::UnhexpackStrings(_, _) => BasicType::String,
+            CodecOp::List => BasicType::String,
             CodecOp::PushDataSection(_) => panic!("PushDataSection.input_type()"),
             CodecOp::Unknown => panic!("Unknown.output_type()"),
         }
@@ -308,12 +420,16 @@ impl CodecOp {
             CodecOp::Nullable => false,
             CodecOp::Add(_, x) => *x == 0,
             CodecOp::Delta(_) => false,
+            CodecOp::RunLength(_) => false,
             CodecOp::ToI64(_) => true,
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => false,
+            CodecOp::SharedDictLookup(_) => false,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::Zstd(_, _) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
+            CodecOp::List => false,
             CodecOp::Unknown => panic!("Unknown.is_summation_preserving()"),
         }
     }
@@ -323,12 +439,16 @@ impl CodecOp {
             CodecOp::Nullable => false,
             CodecOp::Add(_, _) => true,
             CodecOp::Delta(_) => false,
+            CodecOp::RunLength(_) => false,
             CodecOp::ToI64(_) => true,
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => true,
+            CodecOp::SharedDictLookup(_) => false,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::Zstd(_, _) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
+            CodecOp::List => false,
             CodecOp::Unknown => panic!("Unknown.is_order_preserving()"),
         }
     }
@@ -338,12 +458,16 @@ impl CodecOp {
             CodecOp::Nullable => false,
             CodecOp::Add(_, _) => true,
             CodecOp::Delta(_) => false,
+            CodecOp::RunLength(_) => false,
             CodecOp::ToI64(_) => true, // TODO(clemens): no it's not (hack to make grouping key work)
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => true,
+            CodecOp::SharedDictLookup(_) => true,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::Zstd(_, _) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
+            CodecOp::List => false,
             CodecOp::Unknown => panic!("Unknown.is_positive_integer()"),
         }
     }
@@ -353,12 +477,16 @@ impl CodecOp {
             CodecOp::Nullable => false,
             CodecOp::Add(_, _) => true,
             CodecOp::Delta(_) => false,
+            CodecOp::RunLength(_) => false,
             CodecOp::ToI64(_) => true,
             CodecOp::PushDataSection(_) => true,
             CodecOp::DictLookup(_) => true,
+            CodecOp::SharedDictLookup(_) => true,
             CodecOp::LZ4(_, _) => false,
+            CodecOp::Zstd(_, _) => false,
             CodecOp::UnpackStrings => false,
             CodecOp::UnhexpackStrings(_, _) => false,
+            CodecOp::List => false,
             CodecOp::Unknown => panic!("Unknown.is_fixed_width()"),
         }
     }
@@ -368,12 +496,16 @@ impl CodecOp {
             CodecOp::Nullable => 0,
             CodecOp::Add(_, _) => 1,
             CodecOp::Delta(_) => 1,
+            CodecOp::RunLength(_) => 2,
             CodecOp::ToI64(_) => 1,
             CodecOp::PushDataSection(_) => 0,
             CodecOp::DictLookup(_) => 3,
+            CodecOp::SharedDictLookup(_) => 3,
             CodecOp::LZ4(_, _) => 1,
+            CodecOp::Zstd(_, _) => 1,
             CodecOp::UnpackStrings => 1,
             CodecOp::UnhexpackStrings(_, _) => 1,
+            CodecOp::List => 2,
             CodecOp::Unknown => panic!("Unknown.is_fixed_width()"),
         }
     }
@@ -387,16 +519,24 @@ impl CodecOp {
                 format!("Add({:?})", t)
             }
             CodecOp::Delta(t) => format!("Delta({:?})", t),
+            CodecOp::RunLength(t) => format!("RunLength({:?})", t),
             CodecOp::ToI64(t) => format!("ToI64({:?})", t),
             CodecOp::PushDataSection(i) => format!("Data({})", i),
             CodecOp::DictLookup(t) => format!("Dict({:?})", t),
+            CodecOp::SharedDictLookup(t) => format!("SharedDict({:?})", t),
             CodecOp::LZ4(t, decoded_len) => if alternate {
                 format!("LZ4({:?}, {})", t, decoded_len)
             } else {
                 format!("LZ4({:?})", t)
             }
+            CodecOp::Zstd(t, decoded_len) => if alternate {
+                format!("Zstd({:?}, {})", t, decoded_len)
+            } else {
+                format!("Zstd({:?})", t)
+            }
             CodecOp::UnpackStrings => "StrUnpack".to_string(),
             CodecOp::UnhexpackStrings(_, _) => "StrHexUnpack".to_string(),
+            CodecOp::List => "List".to_string(),
             CodecOp::Unknown => "Unknown".to_string(),
         }
     }
@@ -1,10 +1,13 @@
 use std::fmt;
 use std::mem;
+use std::ptr;
+use std::slice;
 use std::sync::Arc;
 
 use mem_store::*;
+#[cfg(feature = "enable_mmap")]
+use mem_store::mapped_section::MappedSection;
 use engine::data_types::*;
-use mem_store::lz4;
 
 use heapsize::HeapSizeOf;
 
@@ -84,26 +87,67 @@ impl Column {
 
     pub fn lz4_encode(&mut self) {
         if cfg!(feature = "enable_lz4") {
-            let (encoded, worth_it) = self.data[0].lz4_encode();
-            if worth_it {
-                self.codec = self.codec.with_lz4(self.data[0].len());
-                self.data[0] = encoded;
-            }
+            self.compress(BlockCompression::LZ4);
         }
     }
 
     #[cfg(feature = "enable_lz4")]
     pub fn lz4_decode(&mut self) {
-        if let Some(CodecOp::LZ4(decoded_type, _)) = self.codec.ops().get(0).map(|c| *c) {
+        if let Some(CodecOp::LZ4(_, _)) = self.codec.ops().get(0).map(|c| *c) {
             trace!("lz4_decode before: {:?}", self);
-            self.codec = self.codec.without_lz4();
-            self.data[0] = self.data[0].lz4_decode(decoded_type, self.len);
+            self.decompress();
             trace!("lz4_decode after: {:?}", self);
         }
     }
 
+    /// Replaces the block compression of the first data section, `BlockCompression::None` leaves it uncompressed.
+    pub fn set_block_compression(&mut self, compression: BlockCompression) {
+        self.decompress();
+        if compression != BlockCompression::None {
+            self.compress(compression);
+        }
+    }
+
+    fn compress(&mut self, compression: BlockCompression) {
+        // Offsets of lists are read directly by queries that unnest them
+        if self.codec.is_list() {
+            return;
+        }
+        let (encoded, worth_it) = self.data[0].compress(compression);
+        if worth_it {
+            self.codec = self.codec.with_compression(compression, self.data[0].len());
+            self.data[0] = encoded;
+        }
+    }
+
+    fn decompress(&mut self) {
+        if let Some((compression, decoded_type)) = self.codec.block_compression() {
+            self.codec = self.codec.without_compression();
+            self.data[0] = self.data[0].decompress(compression, decoded_type, self.len);
+        }
+    }
+
+    /// Minimum and maximum value of integer columns. Unlike `range`, this is not relative to the integer offset.
+    pub fn decoded_range(&self) -> Option<(i64, i64)> {
+        let offset = self.codec.ops().iter()
+            .filter_map(|op| match *op {
+                CodecOp::Add(_, offset) => Some(offset),
+                _ => None,
+            })
+            .next()
+            .unwrap_or(0);
+        self.range.map(|(min, max)| (min + offset, max + offset))
+    }
+
     pub fn name(&self) -> &str { &self.name }
     pub fn data(&self) -> &[DataSection] { &self.data }
+
+    /// Replaces the data sections by sections of the same types, e.g. by sections that are stored separately.
+    pub fn set_data(&mut self, data: Vec<DataSection>) {
+        assert_eq!(data.iter().map(DataSection::encoding_type).collect::<Vec<_>>(),
+                   self.data.iter().map(DataSection::encoding_type).collect::<Vec<_>>());
+        self.data = data;
+    }
     pub fn basic_type(&self) -> BasicType { self.codec.decoded_type() }
     pub fn section_encoding_type(&self, section: usize) -> EncodingType { self.data[section].encoding_type() }
 
@@ -160,7 +204,10 @@ pub enum DataSection {
     U32(Vec<u32>),
     U64(Vec<u64>),
     I64(Vec<i64>),
+    F64(Vec<OrderedF64>),
     Null(usize),
+    #[cfg(feature = "enable_mmap")]
+    Mapped(MappedSection),
 }
 
 impl DataSection {
@@ -171,7 +218,38 @@ impl DataSection {
             DataSection::U32(ref x) => x,
             DataSection::U64(ref x) => x,
             DataSection::I64(ref x) => x,
+            DataSection::F64(ref x) => x,
             DataSection::Null(ref x) => x,
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(ref x) => x.to_any_vec(),
+        }
+    }
+
+    /// Section with values of type `t` copied from `bytes`, which holds them in the byte order of the machine.
+    pub fn from_bytes(t: EncodingType, bytes: &[u8]) -> DataSection {
+        match t {
+            EncodingType::U8 => DataSection::U8(bytes.to_vec()),
+            EncodingType::U16 => DataSection::U16(vec_from_bytes(bytes)),
+            EncodingType::U32 => DataSection::U32(vec_from_bytes(bytes)),
+            EncodingType::U64 => DataSection::U64(vec_from_bytes(bytes)),
+            EncodingType::I64 => DataSection::I64(vec_from_bytes(bytes)),
+            EncodingType::F64 => DataSection::F64(vec_from_bytes(bytes)),
+            _ => panic!("Unexpected type {:?} for data section", t),
+        }
+    }
+
+    /// Values of the section in the byte order of the machine, empty for null sections.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            DataSection::U8(ref x) => x,
+            DataSection::U16(ref x) => bytes_of(x),
+            DataSection::U32(ref x) => bytes_of(x),
+            DataSection::U64(ref x) => bytes_of(x),
+            DataSection::I64(ref x) => bytes_of(x),
+            DataSection::F64(ref x) => bytes_of(x),
+            DataSection::Null(_) => &[],
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(ref x) => x.as_bytes(),
         }
     }
 
@@ -182,7 +260,10 @@ impl DataSection {
             DataSection::U32(ref x) => x.len(),
             DataSection::U64(ref x) => x.len(),
             DataSection::I64(ref x) => x.len(),
+            DataSection::F64(ref x) => x.len(),
             DataSection::Null(ref x) => *x,
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(ref x) => x.len(),
         }
     }
 
@@ -193,7 +274,10 @@ impl DataSection {
             DataSection::U32(ref x) => x.capacity(),
             DataSection::U64(ref x) => x.capacity(),
             DataSection::I64(ref x) => x.capacity(),
+            DataSection::F64(ref x) => x.capacity(),
             DataSection::Null(ref x) => *x,
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(ref x) => x.len(),
         }
     }
 
@@ -204,79 +288,70 @@ impl DataSection {
             DataSection::U32(_) => EncodingType::U32,
             DataSection::U64(_) => EncodingType::U64,
             DataSection::I64(_) => EncodingType::I64,
+            DataSection::F64(_) => EncodingType::F64,
             DataSection::Null(_) => EncodingType::Null,
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(ref x) => x.encoding_type(),
         }
     }
 
-    pub fn lz4_encode(&self) -> (DataSection, bool) {
+    /// Compresses the section into bytes. Also returns whether this reduces its size by at least 10%.
+    pub fn compress(&self, compression: BlockCompression) -> (DataSection, bool) {
         let min_reduction = 90;
-        match self {
-            DataSection::U8(ref x) => {
-                let mut encoded = lz4::encode(&x);
-                encoded.shrink_to_fit();
-                let len = encoded.len();
-                (DataSection::U8(encoded), len * 100 < x.len() * min_reduction)
-            }
-            DataSection::U16(ref x) => {
-                let mut encoded = lz4::encode(&x);
-                encoded.shrink_to_fit();
-                let len = encoded.len();
-                (DataSection::U8(encoded), len * 100 < x.len() * 2 * min_reduction)
-            }
-            DataSection::U32(ref x) => {
-                let mut encoded = lz4::encode(&x);
-                encoded.shrink_to_fit();
-                let len = encoded.len();
-                (DataSection::U8(encoded), len * 100 < x.len() * 4 * min_reduction)
-            }
-            DataSection::U64(ref x) => {
-                let mut encoded = lz4::encode(&x);
-                encoded.shrink_to_fit();
-                let len = encoded.len();
-                (DataSection::U8(encoded), len * 100 < x.len() * 8 * min_reduction)
-            }
-            DataSection::I64(ref x) => {
-                let mut encoded = lz4::encode(&x);
-                encoded.shrink_to_fit();
-                let len = encoded.len();
-                (DataSection::U8(encoded), len * 100 < x.len() * 8 * min_reduction)
-            }
-            DataSection::Null(ref x) => (DataSection::Null(*x), false)
-        }
+        let (mut encoded, size_bytes) = match self {
+            DataSection::U8(ref x) => (compression.encode(&x), x.len()),
+            DataSection::U16(ref x) => (compression.encode(&x), x.len() * 2),
+            DataSection::U32(ref x) => (compression.encode(&x), x.len() * 4),
+            DataSection::U64(ref x) => (compression.encode(&x), x.len() * 8),
+            DataSection::I64(ref x) => (compression.encode(&x), x.len() * 8),
+            DataSection::F64(ref x) => (compression.encode(&x), x.len() * 8),
+            DataSection::Null(ref x) => return (DataSection::Null(*x), false),
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(ref x) => return x.materialize().compress(compression),
+        };
+        encoded.shrink_to_fit();
+        let len = encoded.len();
+        (DataSection::U8(encoded), len * 100 < size_bytes * min_reduction)
     }
 
-    #[cfg(feature = "enable_lz4")]
-    pub fn lz4_decode(&self, decoded_type: EncodingType, len: usize) -> DataSection {
+    pub fn decompress(&self, compression: BlockCompression, decoded_type: EncodingType, len: usize) -> DataSection {
         match self {
             DataSection::U8(encoded) => match decoded_type {
                 EncodingType::U8 => {
                     let mut decoded = vec![0; len];
-                    lz4::decode::<u8>(&mut lz4::decoder(encoded), &mut decoded);
+                    compression.decode::<u8>(encoded, &mut decoded);
                     DataSection::U8(decoded)
                 }
                 EncodingType::U16 => {
                     let mut decoded = vec![0; len];
-                    lz4::decode::<u16>(&mut lz4::decoder(encoded), &mut decoded);
+                    compression.decode::<u16>(encoded, &mut decoded);
                     DataSection::U16(decoded)
                 }
                 EncodingType::U32 => {
                     let mut decoded = vec![0; len];
-                    lz4::decode::<u32>(&mut lz4::decoder(encoded), &mut decoded);
+                    compression.decode::<u32>(encoded, &mut decoded);
                     DataSection::U32(decoded)
                 }
                 EncodingType::U64 => {
                     let mut decoded = vec![0; len];
-                    lz4::decode::<u64>(&mut lz4::decoder(encoded), &mut decoded);
+                    compression.decode::<u64>(encoded, &mut decoded);
                     DataSection::U64(decoded)
                 }
                 EncodingType::I64 => {
                     let mut decoded = vec![0; len];
-                    lz4::decode::<i64>(&mut lz4::decoder(encoded), &mut decoded);
+                    compression.decode::<i64>(encoded, &mut decoded);
                     DataSection::I64(decoded)
                 }
-                t => panic!("Unexpected type {:?} for lz4 decode", t),
+                EncodingType::F64 => {
+                    let mut decoded = vec![OrderedF64(0.0); len];
+                    compression.decode::<OrderedF64>(encoded, &mut decoded);
+                    DataSection::F64(decoded)
+                }
+                t => panic!("Unexpected type {:?} for {:?} decode", t, compression),
             }
-            _ => panic!("Trying to lz4 encode non u8 data section")
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(ref x) => x.materialize().decompress(compression, decoded_type, len),
+            _ => panic!("Trying to decompress non u8 data section")
         }
     }
 
@@ -288,7 +363,10 @@ impl DataSection {
                 DataSection::U32(ref mut x) => x.shrink_to_fit(),
                 DataSection::U64(ref mut x) => x.shrink_to_fit(),
                 DataSection::I64(ref mut x) => x.shrink_to_fit(),
+                DataSection::F64(ref mut x) => x.shrink_to_fit(),
                 DataSection::Null(_) => {}
+                #[cfg(feature = "enable_mmap")]
+                DataSection::Mapped(_) => {}
             }
         }
     }
@@ -302,7 +380,10 @@ impl HeapSizeOf for DataSection {
             DataSection::U32(ref x) => x.heap_size_of_children(),
             DataSection::U64(ref x) => x.heap_size_of_children(),
             DataSection::I64(ref x) => x.heap_size_of_children(),
+            DataSection::F64(ref x) => x.heap_size_of_children(),
             DataSection::Null(_) => 0,
+            #[cfg(feature = "enable_mmap")]
+            DataSection::Mapped(_) => 0,
         }
     }
 }
@@ -342,3 +423,24 @@ impl From<Vec<i64>> for DataSection {
     }
 }
 
+impl From<Vec<OrderedF64>> for DataSection {
+    fn from(vec: Vec<OrderedF64>) -> Self {
+        assert_eq!(vec.len(), vec.capacity());
+        DataSection::F64(vec)
+    }
+}
+
+
+pub fn bytes_of<T>(values: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, values.len() * mem::size_of::<T>()) }
+}
+
+fn vec_from_bytes<T: Copy>(bytes: &[u8]) -> Vec<T> {
+    let len = bytes.len() / mem::size_of::<T>();
+    let mut values = Vec::<T>::with_capacity(len);
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), values.as_mut_ptr() as *mut u8, len * mem::size_of::<T>());
+        values.set_len(len);
+    }
+    values
+}
@@ -8,6 +8,7 @@ use std::sync::Arc;
 use mem_store::integers::*;
 use mem_store::column::*;
 use mem_store::strings::*;
+use engine::data_types::OrderedF64;
 use bitvec::*;
 
 
@@ -104,8 +105,8 @@ impl ColumnBuilder<Option<i64>> for IntColBuilder {
 
     fn finalize(self, name: &str) -> Arc<Column> {
         // TODO(clemens): heuristic for deciding delta encoding could probably be improved
-        let delta_encode = self.allow_delta_encode &&
-            (self.increasing * 10 > self.data.len() as u64 * 9 && cfg!(feature = "enable_lz4"));
+        // Mostly increasing columns are candidates for delta encoding, `IntegerColumn` decides whether it pays off
+        let delta_encode = self.allow_delta_encode && self.increasing * 10 > self.data.len() as u64 * 9;
         let present = if self.any_null && self.nullable { Some(self.present) } else { None };
         IntegerColumn::new_boxed(name,
                                  self.data,
@@ -117,6 +118,27 @@ impl ColumnBuilder<Option<i64>> for IntColBuilder {
 }
 
 
+#[derive(Default)]
+pub struct FloatColBuilder {
+    data: Vec<OrderedF64>,
+}
+
+impl ColumnBuilder<Option<f64>> for FloatColBuilder {
+    fn new(_allow_null: bool) -> FloatColBuilder { FloatColBuilder::default() }
+
+    #[inline]
+    fn push(&mut self, elem: &Option<f64>) {
+        // TODO(clemens): support nullable float columns
+        self.data.push(OrderedF64(elem.unwrap_or(0.0)));
+    }
+
+    fn finalize(mut self, name: &str) -> Arc<Column> {
+        self.data.shrink_to_fit();
+        Arc::new(Column::new(name, self.data.len(), None, vec![], vec![self.data.into()]))
+    }
+}
+
+
 pub struct UniqueValues<T> {
     max_count: usize,
     values: HashSet<T>,
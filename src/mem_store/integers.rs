@@ -21,7 +21,21 @@ impl IntegerColumn {
         let original_range = Some((min, max));
         let min0 = min;
         let max0 = max;
-        if delta_encode && !values.is_empty() {
+        if null.is_none() {
+            let runs = IntegerColumn::count_runs(&values);
+            let width = IntegerColumn::width(min, max);
+            if runs * (width + 4) < values.len() * width {
+                let mut column = IntegerColumn::run_length_encode(name, &values, min, max);
+                column.lz4_encode();
+                return Arc::new(column);
+            }
+        }
+        // Without LZ4, delta encoding only saves memory if the deltas fit into a narrower type than the values
+        let delta_encode = delta_encode && !values.is_empty() && (cfg!(feature = "enable_lz4") || {
+            let (delta_min, delta_max) = IntegerColumn::delta_range(&values);
+            IntegerColumn::width(delta_min, delta_max) < IntegerColumn::width(min, max)
+        });
+        if delta_encode {
             let mut previous = values[0];
             max = previous;
             min = previous;
@@ -118,6 +132,74 @@ impl IntegerColumn {
             if let Some(present) = null_map { vec![values.into(), present.into()] } else { vec![values.into()] })
     }
 
+    /// Stores each run of identical values as the value and the length of the run.
+    fn run_length_encode(name: &str, values: &[i64], min: i64, max: i64) -> Column {
+        let mut run_values = Vec::new();
+        let mut run_lengths = Vec::<u32>::new();
+        for &value in values {
+            let runs = run_values.len();
+            if runs > 0 && run_values[runs - 1] == value && run_lengths[runs - 1] < u32::MAX {
+                run_lengths[runs - 1] += 1;
+            } else {
+                run_values.push(value);
+                run_lengths.push(1);
+            }
+        }
+        run_lengths.shrink_to_fit();
+        let (data, t, offset): (DataSection, EncodingType, i64) = if min >= 0 && max <= From::from(u8::MAX) {
+            (IntegerColumn::encode::<u8>(run_values, 0).into(), EncodingType::U8, 0)
+        } else if max - min <= From::from(u8::MAX) {
+            (IntegerColumn::encode::<u8>(run_values, min).into(), EncodingType::U8, min)
+        } else if min >= 0 && max <= From::from(u16::MAX) {
+            (IntegerColumn::encode::<u16>(run_values, 0).into(), EncodingType::U16, 0)
+        } else if max - min <= From::from(u16::MAX) {
+            (IntegerColumn::encode::<u16>(run_values, min).into(), EncodingType::U16, min)
+        } else if min >= 0 && max <= From::from(u32::MAX) {
+            (IntegerColumn::encode::<u32>(run_values, 0).into(), EncodingType::U32, 0)
+        } else if max - min <= From::from(u32::MAX) {
+            (IntegerColumn::encode::<u32>(run_values, min).into(), EncodingType::U32, min)
+        } else {
+            (DataSection::I64(run_values), EncodingType::I64, 0)
+        };
+        let codec = match t {
+            EncodingType::I64 => vec![CodecOp::PushDataSection(1), CodecOp::RunLength(t)],
+            _ if offset == 0 => vec![CodecOp::PushDataSection(1), CodecOp::RunLength(t), CodecOp::ToI64(t)],
+            _ => vec![CodecOp::PushDataSection(1), CodecOp::RunLength(t), CodecOp::Add(t, offset)],
+        };
+        Column::new(
+            name,
+            values.len(),
+            Some((min - offset, max - offset)),
+            codec,
+            vec![data, run_lengths.into()])
+    }
+
+    fn count_runs(values: &[i64]) -> usize {
+        if values.is_empty() { return 0; }
+        1 + values.windows(2).filter(|pair| pair[0] != pair[1]).count()
+    }
+
+    fn delta_range(values: &[i64]) -> (i64, i64) {
+        let mut min = values[0];
+        let mut max = values[0];
+        for pair in values.windows(2) {
+            let delta = pair[1] - pair[0];
+            if max < delta { max = delta }
+            if min > delta { min = delta }
+        }
+        (min, max)
+    }
+
+    /// Number of bytes per value required to store integers between `min` and `max`.
+    fn width(min: i64, max: i64) -> usize {
+        match max.checked_sub(min) {
+            Some(diff) if diff <= From::from(u8::MAX) => 1,
+            Some(diff) if diff <= From::from(u16::MAX) => 2,
+            Some(diff) if diff <= From::from(u32::MAX) => 4,
+            _ => 8,
+        }
+    }
+
     pub fn encode<T: GenericIntVec<T>>(values: Vec<i64>, offset: i64) -> Vec<T> {
         let mut encoded_vals = Vec::with_capacity(values.len());
         for v in values {
@@ -1,30 +1,31 @@
 use std::sync::{Arc, Mutex};
 use lru::LruCache;
-use mem_store::partition::ColumnKey;
+use disk_store::interface::PartitionID;
 
 
+/// Partitions with resident columns, ordered by the time they were last queried.
 #[derive(Clone)]
 pub struct LRU {
-    cache: Arc<Mutex<LruCache<ColumnKey, ()>>>,
+    cache: Arc<Mutex<LruCache<PartitionID, ()>>>,
 }
 
 impl LRU {
-    pub fn touch(&self, column: &ColumnKey) {
+    pub fn touch(&self, partition: PartitionID) {
         let mut cache = self.cache.lock().unwrap();
-        cache.get(column);
+        cache.get(&partition);
     }
 
-    pub fn put(&self, column: ColumnKey) {
+    pub fn put(&self, partition: PartitionID) {
         let mut cache = self.cache.lock().unwrap();
-        cache.put(column, ());
+        cache.put(partition, ());
     }
 
-    pub fn remove(&self, column: &ColumnKey) {
+    pub fn remove(&self, partition: PartitionID) {
         let mut cache = self.cache.lock().unwrap();
-        cache.pop(column);
+        cache.pop(&partition);
     }
 
-    pub fn evict(&self) -> Option<ColumnKey> {
+    pub fn evict(&self) -> Option<PartitionID> {
         let mut cache = self.cache.lock().unwrap();
         cache.pop_lru().map(|x| x.0)
     }
@@ -48,6 +48,10 @@ pub fn decode<T>(src: &mut Read, dst: &mut [T]) -> usize {
     read / mem::size_of::<T>()
 }
 
+pub fn decode_all<T>(data: &[u8], dst: &mut [T]) -> usize {
+    decode(&mut decoder(data), dst)
+}
+
 
 #[cfg(test)]
 mod tests {
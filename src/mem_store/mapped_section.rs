@@ -0,0 +1,112 @@
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::slice;
+use std::sync::Arc;
+
+use memmap::Mmap;
+
+use engine::data_types::*;
+use mem_store::column::{bytes_of, DataSection};
+
+
+/// Data section stored in a memory-mapped file. Queries read the values directly from the mapping, so the operating
+/// system only pages in the parts of the file that are accessed and can drop them again under memory pressure.
+pub struct MappedSection {
+    // Keeps the mapping alive for as long as `values` points into it
+    _map: Arc<Mmap>,
+    values: MappedValues,
+}
+
+enum MappedValues {
+    U8(&'static [u8]),
+    U16(&'static [u16]),
+    U32(&'static [u32]),
+    U64(&'static [u64]),
+    I64(&'static [i64]),
+    F64(&'static [OrderedF64]),
+}
+
+impl MappedSection {
+    /// Section with values of type `t` stored at `range` of `map`, which has to be aligned to the size of the values.
+    pub fn new(map: Arc<Mmap>, t: EncodingType, range: Range<usize>) -> Result<MappedSection, String> {
+        let values = {
+            let bytes = &map[range];
+            match t {
+                EncodingType::U8 => MappedValues::U8(cast(bytes)?),
+                EncodingType::U16 => MappedValues::U16(cast(bytes)?),
+                EncodingType::U32 => MappedValues::U32(cast(bytes)?),
+                EncodingType::U64 => MappedValues::U64(cast(bytes)?),
+                EncodingType::I64 => MappedValues::I64(cast(bytes)?),
+                EncodingType::F64 => MappedValues::F64(cast(bytes)?),
+                _ => return Err(format!("Data sections of type {:?} can't be mapped", t)),
+            }
+        };
+        Ok(MappedSection { _map: map, values })
+    }
+
+    pub fn to_any_vec(&self) -> &Data {
+        let data: &Data<'static> = match self.values {
+            MappedValues::U8(ref x) => x,
+            MappedValues::U16(ref x) => x,
+            MappedValues::U32(ref x) => x,
+            MappedValues::U64(ref x) => x,
+            MappedValues::I64(ref x) => x,
+            MappedValues::F64(ref x) => x,
+        };
+        // The values are only valid as long as `self` holds the mapping
+        unsafe { mem::transmute::<&Data<'static>, &Data>(data) }
+    }
+
+    pub fn len(&self) -> usize {
+        match self.values {
+            MappedValues::U8(x) => x.len(),
+            MappedValues::U16(x) => x.len(),
+            MappedValues::U32(x) => x.len(),
+            MappedValues::U64(x) => x.len(),
+            MappedValues::I64(x) => x.len(),
+            MappedValues::F64(x) => x.len(),
+        }
+    }
+
+    pub fn encoding_type(&self) -> EncodingType {
+        match self.values {
+            MappedValues::U8(_) => EncodingType::U8,
+            MappedValues::U16(_) => EncodingType::U16,
+            MappedValues::U32(_) => EncodingType::U32,
+            MappedValues::U64(_) => EncodingType::U64,
+            MappedValues::I64(_) => EncodingType::I64,
+            MappedValues::F64(_) => EncodingType::F64,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self.values {
+            MappedValues::U8(x) => x,
+            MappedValues::U16(x) => bytes_of(x),
+            MappedValues::U32(x) => bytes_of(x),
+            MappedValues::U64(x) => bytes_of(x),
+            MappedValues::I64(x) => bytes_of(x),
+            MappedValues::F64(x) => bytes_of(x),
+        }
+    }
+
+    /// Copies the values into memory.
+    pub fn materialize(&self) -> DataSection {
+        DataSection::from_bytes(self.encoding_type(), self.as_bytes())
+    }
+}
+
+impl fmt::Debug for MappedSection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Mapped({:?}, len={})", self.encoding_type(), self.len())
+    }
+}
+
+fn cast<T>(bytes: &[u8]) -> Result<&'static [T], String> {
+    let size = mem::size_of::<T>();
+    if bytes.as_ptr() as usize % size != 0 || bytes.len() % size != 0 {
+        return Err(format!("Mapped data section of {} bytes is not aligned to {} bytes", bytes.len(), size));
+    }
+    Ok(unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, bytes.len() / size) })
+}
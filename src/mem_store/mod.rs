@@ -1,23 +1,38 @@
+pub mod block_compression;
+pub mod bloom_filter;
+pub mod catalog;
 pub mod codec;
 pub mod column;
 pub mod column_builder;
 pub mod integers;
 pub mod partition;
 pub mod raw_col;
+pub mod shared_dictionary;
 pub mod strings;
+pub mod system_tables;
 pub mod table;
 pub mod tree;
 pub mod value;
+pub mod zone_map;
 #[cfg(feature = "enable_lz4")]
 pub mod lz4;
+#[cfg(feature = "enable_mmap")]
+pub mod mapped_section;
+#[cfg(feature = "enable_zstd")]
+pub mod zstd;
 mod mixed_column;
 pub(crate) mod lru;
 
 pub use self::column::{Column, DataSection, DataSource};
 pub use self::codec::{Codec, CodecOp};
 pub use self::tree::*;
-pub use self::table::TableStats;
+pub use self::table::{MemStats, TableStats};
 pub use self::lru::LRU;
+pub use self::zone_map::ZoneMap;
+pub use self::catalog::ColumnStats;
+pub use self::bloom_filter::BloomFilter;
+pub use self::block_compression::BlockCompression;
+pub use self::shared_dictionary::SharedDictionary;
 
 
 #[cfg(not(feature = "enable_lz4"))]
@@ -27,4 +42,22 @@ pub mod lz4 {
     pub fn encode<T: Debug>(_: &[T]) -> Vec<u8> {
         panic!("lz4 not supported in this build of LocustDB. Recompile with --features enable_lz4.")
     }
+
+    pub fn decode_all<T>(_: &[u8], _: &mut [T]) -> usize {
+        panic!("lz4 not supported in this build of LocustDB. Recompile with --features enable_lz4.")
+    }
+}
+
+
+#[cfg(not(feature = "enable_zstd"))]
+pub mod zstd {
+    use std::fmt::Debug;
+
+    pub fn encode<T: Debug>(_: &[T]) -> Vec<u8> {
+        panic!("zstd not supported in this build of LocustDB. Recompile with --features enable_zstd.")
+    }
+
+    pub fn decode_all<T>(_: &[u8], _: &mut [T]) -> usize {
+        panic!("zstd not supported in this build of LocustDB. Recompile with --features enable_zstd.")
+    }
 }
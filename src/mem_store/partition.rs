@@ -3,63 +3,88 @@ use std::collections::HashSet;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use bitvec::*;
 use disk_store::interface::*;
 use heapsize::HeapSizeOf;
-use ingest::buffer::Buffer;
 use mem_store::*;
+use mem_store::column_builder::*;
+use mem_store::zone_map;
 use scheduler::disk_read_scheduler::DiskReadScheduler;
+use scheduler::topology;
+use syntax::expression::Expr;
 
 
 pub type ColumnKey = (PartitionID, String);
 
+/// Name of the column that marks deleted rows, which is only passed to queries over partitions with deleted rows.
+pub const DELETED_COL: &str = "$deleted";
+
 pub struct Partition {
     id: PartitionID,
     len: usize,
-    cols: Vec<ColumnHandle>,
+    /// Shared with the copies of the partition that mark additional rows as deleted.
+    cols: Arc<Vec<ColumnHandle>>,
     lru: LRU,
+    tombstones: Option<Tombstones>,
+    bloom_filters: Arc<HashMap<String, BloomFilter>>,
+    /// Dictionaries shared by all partitions that columns of the partition are encoded with.
+    shared_dictionaries: Arc<HashMap<String, Arc<SharedDictionary>>>,
+    /// NUMA node of the thread that created the partition.
+    numa_node: usize,
+    /// Whether the partition is a view of rows that are still buffered and have not been sealed yet.
+    open: bool,
+}
+
+/// Rows removed by `DELETE` statements, partitions are immutable so deleted rows are filtered out by every query.
+struct Tombstones {
+    deleted: Vec<u8>,
+    count: usize,
+    column: Arc<Column>,
 }
 
 impl Partition {
-    pub fn new(id: PartitionID, cols: Vec<Arc<Column>>, lru: LRU) -> (Partition, Vec<ColumnKey>) {
-        let mut keys = Vec::with_capacity(cols.len());
-        (Partition {
+    /// Creates a resident partition. It has to be put into the LRU cache once it is reachable from its table,
+    /// otherwise the memory limit enforcer might try to evict a partition it can't find.
+    pub fn new(id: PartitionID, cols: Vec<Arc<Column>>, lru: LRU) -> Partition {
+        Partition {
             id,
             len: cols[0].len(),
-            cols: cols.into_iter()
-                .map(|c| {
-                    let key = (id, c.name().to_string());
-                    // Can't put into lru directly, because then memory limit enforcer might try to evict unreachable column.
-                    keys.push(key);
-                    ColumnHandle::resident(id, c)
-                })
-                .collect(),
+            cols: Arc::new(cols.into_iter().map(|c| ColumnHandle::resident(id, c)).collect()),
             lru,
-        }, keys)
+            tombstones: None,
+            bloom_filters: Arc::new(HashMap::new()),
+            shared_dictionaries: Arc::new(HashMap::new()),
+            numa_node: topology::current_node(),
+            open: false,
+        }
+    }
+
+    /// Creates a view of the rows buffered by a table, which is neither stored nor tracked by the LRU cache.
+    pub fn open(cols: Vec<Arc<Column>>) -> Partition {
+        let mut partition = Partition::new(PartitionID::max_value(), cols, LRU::default());
+        partition.open = true;
+        partition
     }
 
     pub fn nonresident(id: PartitionID, len: usize, cols: &[ColumnMetadata], lru: LRU) -> Partition {
         Partition {
             id,
             len,
-            cols: cols.iter()
-                .map(|c| ColumnHandle::non_resident(id, c.name.to_string(), c.size_bytes))
-                .collect(),
+            cols: Arc::new(cols.iter()
+                .map(|c| ColumnHandle::non_resident(id, c.name.to_string(), c.size_bytes, c.stats))
+                .collect()),
             lru,
+            tombstones: None,
+            bloom_filters: Arc::new(HashMap::new()),
+            shared_dictionaries: Arc::new(HashMap::new()),
+            numa_node: topology::current_node(),
+            open: false,
         }
     }
 
-    pub fn from_buffer(id: PartitionID, buffer: Buffer, lru: LRU) -> (Partition, Vec<ColumnKey>) {
-        Partition::new(
-            id,
-            buffer.buffer.into_iter()
-                .map(|(name, raw_col)| raw_col.finalize(&name))
-                .collect(),
-            lru)
-    }
-
     pub fn get_cols(&self, referenced_cols: &HashSet<String>, drs: &DiskReadScheduler) -> HashMap<String, Arc<DataSource>> {
         let mut columns = HashMap::<String, Arc<DataSource>>::new();
-        for handle in &self.cols {
+        for handle in self.cols.iter() {
             if referenced_cols.contains(handle.name()) {
                 let column = drs.get_or_load(&handle);
                 columns.insert(handle.name().to_string(), Arc::new(column));
@@ -68,9 +93,120 @@ impl Partition {
         columns
     }
 
+    /// Minimum and maximum value of the integer column `name`, loading it if it is not resident.
+    pub fn range(&self, name: &str, drs: &DiskReadScheduler) -> Option<(i64, i64)> {
+        self.cols.iter()
+            .find(|handle| handle.name() == name)
+            .and_then(|handle| drs.get_or_load(handle).decoded_range())
+    }
+
+    /// All columns of the partition, loading any that are not resident.
+    pub fn columns(&self, drs: &DiskReadScheduler) -> Vec<Arc<Column>> {
+        self.cols.iter().map(|handle| drs.get_or_load(handle)).collect()
+    }
+
+    /// Removes the partition from the LRU cache, called when the partition is deleted.
+    pub fn release(&self) {
+        self.lru.remove(self.id);
+    }
+
+    /// Column with value 1 for all deleted rows and 0 for all other rows, `None` if no rows were deleted.
+    pub fn tombstones(&self) -> Option<Arc<Column>> {
+        self.tombstones.as_ref().map(|t| t.column.clone())
+    }
+
+    /// Number of deleted rows.
+    pub fn deleted_rows(&self) -> usize {
+        self.tombstones.as_ref().map_or(0, |t| t.count)
+    }
+
+    /// Indices of all deleted rows.
+    pub fn deleted_indices(&self) -> Vec<usize> {
+        match self.tombstones {
+            Some(ref tombstones) => (0..self.len).filter(|&index| tombstones.deleted.is_set(index)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns a copy of the partition in which the rows at `indices` are marked as deleted, together with the number
+    /// of rows that were not deleted before. The partition itself is unchanged, so running queries are unaffected.
+    pub fn with_deleted_rows(&self, indices: &[usize]) -> (Partition, usize) {
+        let (mut deleted, mut count) = match self.tombstones {
+            Some(ref tombstones) => (tombstones.deleted.clone(), tombstones.count),
+            None => (Vec::new(), 0),
+        };
+        let previous_count = count;
+        for &index in indices {
+            if !deleted.is_set(index) {
+                deleted.set(index);
+                count += 1;
+            }
+        }
+        let mut builder = IntColBuilder::new(false);
+        for index in 0..self.len {
+            builder.push(&Some(deleted.is_set(index) as i64));
+        }
+        let column = builder.finalize(DELETED_COL);
+        let partition = Partition {
+            id: self.id,
+            len: self.len,
+            cols: self.cols.clone(),
+            lru: self.lru.clone(),
+            tombstones: Some(Tombstones { deleted, count, column }),
+            bloom_filters: self.bloom_filters.clone(),
+            shared_dictionaries: self.shared_dictionaries.clone(),
+            numa_node: self.numa_node,
+            open: self.open,
+        };
+        (partition, count - previous_count)
+    }
+
+    /// Statistics of column `name`, which is all null if the partition does not contain it.
+    pub fn column_stats(&self, name: &str) -> ColumnStats {
+        self.cols.iter()
+            .find(|handle| handle.name() == name)
+            .map_or(ColumnStats::null(self.len), |handle| handle.stats)
+    }
+
+    /// Zone map of column `name`, which is all null if the partition does not contain it.
+    pub fn zone_map(&self, name: &str) -> ZoneMap {
+        self.column_stats(name).zone_map
+    }
+
+    /// Returns `false` if the zone maps and bloom filters of the partition show that none of its rows satisfy `filter`.
+    pub fn may_match(&self, filter: &Expr) -> bool {
+        zone_map::may_match(filter,
+                            self.len,
+                            &|name| self.zone_map(name),
+                            &|name, value| self.bloom_filters.get(name).map_or(true, |b| b.may_contain(value)))
+    }
+
+    pub fn add_bloom_filter(&mut self, column: &str, bloom_filter: BloomFilter) {
+        Arc::get_mut(&mut self.bloom_filters)
+            .expect("Bloom filters are added before the partition is shared")
+            .insert(column.to_string(), bloom_filter);
+    }
+
+    /// Records that `column` is encoded with the codes of `dictionary`.
+    pub fn add_shared_dictionary(&mut self, column: &str, dictionary: Arc<SharedDictionary>) {
+        Arc::get_mut(&mut self.shared_dictionaries)
+            .expect("Shared dictionaries are added before the partition is shared")
+            .insert(column.to_string(), dictionary);
+    }
+
+    /// Dictionary shared by all partitions that `column` is encoded with, if any.
+    pub fn shared_dictionary(&self, column: &str) -> Option<&Arc<SharedDictionary>> {
+        self.shared_dictionaries.get(column)
+    }
+
+    /// Handles of all columns, resident or not.
+    pub fn column_handles(&self) -> &[ColumnHandle] {
+        &self.cols
+    }
+
     pub fn col_names(&self) -> Vec<&str> {
         let mut names = Vec::new();
-        for handle in &self.cols {
+        for handle in self.cols.iter() {
             names.push(handle.name());
         }
         names
@@ -78,7 +214,7 @@ impl Partition {
 
     pub fn non_residents(&self, cols: &HashSet<String>) -> HashSet<String> {
         let mut non_residents = HashSet::new();
-        for handle in &self.cols {
+        for handle in self.cols.iter() {
             if !handle.is_resident() && cols.contains(handle.name()) {
                 non_residents.insert(handle.name().to_string());
             }
@@ -87,7 +223,7 @@ impl Partition {
     }
 
     pub fn nonresidents_match(&self, nonresidents: &HashSet<String>, eligible: &HashSet<String>) -> bool {
-        for handle in &self.cols {
+        for handle in self.cols.iter() {
             if handle.is_resident() {
                 if nonresidents.contains(handle.name()) {
                     return false;
@@ -101,7 +237,7 @@ impl Partition {
 
     pub fn promise_load(&self, cols: &HashSet<String>) -> usize {
         let mut total_size = 0;
-        for handle in &self.cols {
+        for handle in self.cols.iter() {
             if cols.contains(handle.name()) {
                 handle.load_scheduled.store(true, Ordering::SeqCst);
                 total_size += handle.size_bytes();
@@ -111,11 +247,11 @@ impl Partition {
     }
 
     pub fn restore(&self, col: &Arc<Column>) {
-        for handle in &self.cols {
+        for handle in self.cols.iter() {
             if handle.name() == col.name() {
                 let mut maybe_column = handle.col.lock().unwrap();
                 if maybe_column.is_none() {
-                    self.lru.put(handle.key.clone());
+                    self.lru.put(self.id);
                 }
                 *maybe_column = Some(col.clone());
                 handle.resident.store(true, Ordering::SeqCst);
@@ -124,26 +260,29 @@ impl Partition {
         }
     }
 
-    pub fn evict(&self, col: &str) -> usize {
-        for handle in &self.cols {
-            if handle.name() == col {
-                let mut maybe_column = handle.col.lock().unwrap();
-                let mem_size = handle.heap_size_of_children();
+    /// Drops all resident columns, which are reloaded from disk on the next access. Returns the number of bytes freed.
+    pub fn evict(&self) -> usize {
+        let mut mem_size = 0;
+        for handle in self.cols.iter() {
+            let mut maybe_column = handle.col.lock().unwrap();
+            if maybe_column.is_some() {
+                mem_size += handle.heap_size_of_children();
                 handle.resident.store(false, Ordering::SeqCst);
                 *maybe_column = None;
-                self.lru.remove(&handle.key);
-                return mem_size;
             }
         }
-        0
+        self.lru.remove(self.id);
+        mem_size
     }
 
     pub fn id(&self) -> u64 { self.id }
     pub fn len(&self) -> usize { self.len }
+    pub fn numa_node(&self) -> usize { self.numa_node }
+    pub fn is_open(&self) -> bool { self.open }
 
     pub fn mem_tree(&self, coltrees: &mut HashMap<String, MemTreeColumn>, depth: usize) {
         if depth == 0 { return; }
-        for handle in &self.cols {
+        for handle in self.cols.iter() {
             let col = handle.col.lock().unwrap();
             let mut coltree = coltrees.entry(handle.name().to_string())
                 .or_insert(MemTreeColumn {
@@ -173,16 +312,18 @@ impl Partition {
 
 impl HeapSizeOf for Partition {
     fn heap_size_of_children(&self) -> usize {
-        self.cols.iter().map(|handle| handle.col.lock().unwrap().heap_size_of_children()).sum()
+        self.cols.iter().map(|handle| handle.col.lock().unwrap().heap_size_of_children()).sum::<usize>()
+            + self.bloom_filters.heap_size_of_children()
     }
 }
 
 
 pub struct ColumnHandle {
-    key: (PartitionID, String),
+    key: ColumnKey,
     size_bytes: AtomicUsize,
     resident: AtomicBool,
     load_scheduled: AtomicBool,
+    stats: ColumnStats,
     col: Mutex<Option<Arc<Column>>>,
 }
 
@@ -193,16 +334,18 @@ impl ColumnHandle {
             size_bytes: AtomicUsize::new(col.heap_size_of_children()),
             resident: AtomicBool::new(true),
             load_scheduled: AtomicBool::new(false),
+            stats: ColumnStats::of(&col),
             col: Mutex::new(Some(col)),
         }
     }
 
-    fn non_resident(id: PartitionID, name: String, size_bytes: usize) -> ColumnHandle {
+    fn non_resident(id: PartitionID, name: String, size_bytes: usize, stats: ColumnStats) -> ColumnHandle {
         ColumnHandle {
             key: (id, name),
             size_bytes: AtomicUsize::new(size_bytes),
             resident: AtomicBool::new(false),
             load_scheduled: AtomicBool::new(false),
+            stats,
             col: Mutex::new(None),
         }
     }
@@ -219,10 +362,6 @@ impl ColumnHandle {
         self.load_scheduled.load(Ordering::SeqCst)
     }
 
-    pub fn key(&self) -> &(PartitionID, String) {
-        &self.key
-    }
-
     pub fn id(&self) -> PartitionID {
         self.key.0
     }
@@ -235,6 +374,10 @@ impl ColumnHandle {
         self.col.lock().unwrap()
     }
 
+    pub fn stats(&self) -> ColumnStats {
+        self.stats
+    }
+
     pub fn size_bytes(&self) -> usize {
         self.size_bytes.load(Ordering::SeqCst)
     }
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::{u8, u16};
+
+use engine::data_types::EncodingType;
+use ingest::raw_val::RawVal;
+use mem_store::codec::CodecOp;
+use mem_store::column::{Column, DataSection};
+use stringpack::IndexedPackedStrings;
+
+
+/// Dictionary that is shared by all partitions of a string column, so that a string has the same code in every
+/// partition. Codes are assigned in order of insertion and never change, which means they are not order preserving.
+/// Partial results of a group by on the column are merged on codes and only decoded once all of them are merged.
+#[derive(Default)]
+pub struct SharedDictionary {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    strings: Vec<String>,
+    codes: HashMap<String, u32>,
+}
+
+impl SharedDictionary {
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().strings.len()
+    }
+
+    /// Strings that correspond to `codes`, which must have been assigned by this dictionary.
+    pub fn strings(&self, codes: &[i64]) -> Vec<&str> {
+        let inner = self.inner.lock().unwrap();
+        codes.iter()
+            .map(|&code| {
+                let string = inner.strings[code as usize].as_str();
+                // Strings are never removed or modified, and moving a `String` when `strings` grows does not move
+                // its heap buffer, so the reference stays valid for as long as the dictionary.
+                unsafe { &*(string as *const str) }
+            })
+            .collect()
+    }
+
+    /// Code of `string`, `None` if it is not part of the dictionary.
+    pub fn code(&self, string: &str) -> Option<u32> {
+        self.inner.lock().unwrap().codes.get(string).cloned()
+    }
+
+    /// Encodes `values` with codes from the dictionary, adding strings that are not part of it yet.
+    /// Returns `None` if any of the values is not a string.
+    pub fn encode(&self, name: &str, values: &[RawVal]) -> Option<Column> {
+        if !values.iter().all(|value| match *value { RawVal::Str(_) => true, _ => false }) {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let mut codes = Vec::with_capacity(values.len());
+        for value in values {
+            if let RawVal::Str(ref string) = *value {
+                let existing = inner.codes.get(string.as_str()).cloned();
+                let code = match existing {
+                    Some(code) => code,
+                    None => {
+                        let code = inner.strings.len() as u32;
+                        inner.codes.insert(string.clone(), code);
+                        inner.strings.push(string.clone());
+                        code
+                    }
+                };
+                codes.push(code);
+            }
+        }
+
+        // Every partition stores a copy of the dictionary as it was when the partition was created
+        let mut packed = IndexedPackedStrings::default();
+        for string in &inner.strings {
+            packed.push(string);
+        }
+        let (dictionary_indices, dictionary_data) = packed.into_parts();
+        let dict_size = inner.strings.len();
+        let (index_type, indices) = if dict_size <= From::from(u8::MAX) {
+            (EncodingType::U8, DataSection::U8(codes.iter().map(|&code| code as u8).collect()))
+        } else if dict_size <= From::from(u16::MAX) {
+            (EncodingType::U16, DataSection::U16(codes.iter().map(|&code| code as u16).collect()))
+        } else {
+            (EncodingType::U32, DataSection::U32(codes))
+        };
+        Some(Column::new(
+            name,
+            values.len(),
+            Some((0, dict_size as i64)),
+            vec![
+                CodecOp::PushDataSection(1),
+                CodecOp::PushDataSection(2),
+                CodecOp::SharedDictLookup(index_type),
+            ],
+            vec![indices,
+                 DataSection::U64(dictionary_indices),
+                 DataSection::U8(dictionary_data)]))
+    }
+}
+
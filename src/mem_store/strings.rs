@@ -173,6 +173,64 @@ pub fn dictionary_compress<T: PrimInt>(strings: &[Option<Rc<String>>],
     (encoded_values, dictionary_indices, dictionary_data)
 }
 
+/// Builds a column of lists of strings. The first section holds the offset at which the list of each row ends within
+/// the second section, which holds the dictionary indices of all elements. The sorted dictionary of distinct elements
+/// makes up the last two sections.
+pub fn build_list_column<S: AsRef<str>>(name: &str, lists: &[Vec<S>]) -> Column {
+    let mut unique_values = HashSetSea::default();
+    for list in lists {
+        for element in list {
+            unique_values.insert(element.as_ref());
+        }
+    }
+    let dict_size = unique_values.len();
+    let mut mapping = unique_values.into_iter().collect::<Vec<_>>();
+    mapping.sort();
+    let mut dictionary: HashMapSea<&str, u32> = HashMapSea::default();
+    for (i, &s) in mapping.iter().enumerate() {
+        dictionary.insert(s, i as u32);
+    }
+    let mut ends = Vec::with_capacity(lists.len());
+    let mut elements = Vec::new();
+    for list in lists {
+        elements.extend(list.iter().map(|element| dictionary[element.as_ref()]));
+        ends.push(elements.len() as u32);
+    }
+    let mut packed_mapping = IndexedPackedStrings::default();
+    for s in mapping {
+        packed_mapping.push(s);
+    }
+    let (dictionary_indices, dictionary_data) = packed_mapping.into_parts();
+    let (index_type, elements) = if dict_size <= From::from(u8::MAX) {
+        (EncodingType::U8, DataSection::U8(elements.iter().map(|&i| i as u8).collect()))
+    } else if dict_size <= From::from(u16::MAX) {
+        (EncodingType::U16, DataSection::U16(elements.iter().map(|&i| i as u16).collect()))
+    } else {
+        (EncodingType::U32, DataSection::U32(elements))
+    };
+    Column::new(
+        name,
+        lists.len(),
+        None,
+        list_codec(index_type),
+        vec![DataSection::U32(ends),
+             elements,
+             DataSection::U64(dictionary_indices),
+             DataSection::U8(dictionary_data)])
+}
+
+/// Codec of lists of strings, see `build_list_column`.
+pub fn list_codec(index_type: EncodingType) -> Vec<CodecOp> {
+    vec![
+        CodecOp::PushDataSection(1),
+        CodecOp::PushDataSection(2),
+        CodecOp::PushDataSection(3),
+        CodecOp::DictLookup(index_type),
+        CodecOp::List,
+    ]
+}
+
+/// Codec of dictionary encoded strings. Dictionaries are sorted, so dictionary indices preserve the order of strings.
 pub fn dict_codec(index_type: EncodingType) -> Vec<CodecOp> {
     vec![
         CodecOp::PushDataSection(1),
@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use ingest::raw_val::RawVal;
+use mem_store::*;
+use mem_store::partition::Partition;
+use mem_store::raw_col::MixedCol;
+use mem_store::table::Table;
+use query_log::QueryLog;
+
+
+/// Contents of the system table `name` as a single partition, `None` if there is no system table called `name`.
+pub fn snapshot(name: &str, tables: &HashMap<String, Table>, query_log: &QueryLog) -> Option<Vec<Arc<Partition>>> {
+    let mut tables = tables.values().collect::<Vec<_>>();
+    tables.sort_by(|a, b| a.name().cmp(b.name()));
+    let rows = match name {
+        "_tables" => table_rows(&tables),
+        "_columns" => column_rows(&tables),
+        "_partitions" => partition_rows(&tables),
+        "_column_stats" => column_stats_rows(&tables),
+        "_mem_stats" => mem_stats_rows(&tables),
+        "_query_log" => query_log_rows(query_log),
+        _ => return None,
+    };
+    Some(vec![Arc::new(rows.into_partition())])
+}
+
+/// One row for each table.
+fn table_rows(tables: &[&Table]) -> Rows {
+    let mut rows = Rows::new(&["name", "row_count", "partition_count", "size_bytes"]);
+    for table in tables {
+        let partitions = table.snapshot();
+        rows.push(vec![
+            RawVal::Str(table.name().to_string()),
+            RawVal::Int(partitions.iter().map(|p| p.len()).sum::<usize>() as i64),
+            RawVal::Int(partitions.len() as i64),
+            RawVal::Int(partitions.iter().map(|p| size_bytes(p)).sum::<usize>() as i64),
+        ]);
+    }
+    rows
+}
+
+/// One row for each column of each table, with the types and encodings of all of its resident partitions.
+fn column_rows(tables: &[&Table]) -> Rows {
+    let mut rows = Rows::new(&["table_name", "column_name", "data_type", "encodings", "row_count", "size_bytes"]);
+    for table in tables {
+        let mut columns = BTreeMap::<String, (BTreeSet<String>, BTreeSet<String>, usize, usize)>::new();
+        for partition in table.snapshot() {
+            for handle in partition.column_handles() {
+                let column = columns.entry(handle.name().to_string()).or_insert_with(Default::default);
+                if let Some(ref col) = *handle.try_get() {
+                    column.0.insert(format!("{:?}", col.basic_type()));
+                    column.1.insert(col.codec().signature(false));
+                }
+                column.2 += partition.len();
+                column.3 += handle.size_bytes();
+            }
+        }
+        for (name, (types, encodings, row_count, size_bytes)) in columns {
+            rows.push(vec![
+                RawVal::Str(table.name().to_string()),
+                RawVal::Str(name),
+                RawVal::Str(join(types)),
+                RawVal::Str(join(encodings)),
+                RawVal::Int(row_count as i64),
+                RawVal::Int(size_bytes as i64),
+            ]);
+        }
+    }
+    rows
+}
+
+/// One row for each partition of each table.
+fn partition_rows(tables: &[&Table]) -> Rows {
+    let mut rows = Rows::new(&["table_name", "partition_id", "row_count", "deleted_rows", "size_bytes", "encodings"]);
+    for table in tables {
+        let mut partitions = table.snapshot();
+        partitions.sort_by_key(|p| p.id());
+        for partition in partitions {
+            let encodings = partition.column_handles().iter()
+                .map(|handle| match *handle.try_get() {
+                    Some(ref col) => format!("{}: {}", handle.name(), col.codec().signature(false)),
+                    None => format!("{}: <nonresident>", handle.name()),
+                })
+                .collect::<BTreeSet<_>>();
+            rows.push(vec![
+                RawVal::Str(table.name().to_string()),
+                RawVal::Int(partition.id() as i64),
+                RawVal::Int(partition.len() as i64),
+                RawVal::Int(partition.deleted_rows() as i64),
+                RawVal::Int(size_bytes(&partition) as i64),
+                RawVal::Str(join(encodings)),
+            ]);
+        }
+    }
+    rows
+}
+
+/// One row for each column of each partition of each table, with the statistics that are used to prune and plan queries.
+fn column_stats_rows(tables: &[&Table]) -> Rows {
+    let mut rows = Rows::new(&["table_name", "partition_id", "column_name", "min", "max", "null_count",
+                               "distinct_values", "size_bytes"]);
+    let optional = |value: Option<i64>| value.map_or(RawVal::Null, RawVal::Int);
+    for table in tables {
+        let mut partitions = table.snapshot();
+        partitions.sort_by_key(|p| p.id());
+        for partition in partitions {
+            for handle in partition.column_handles() {
+                let stats = handle.stats();
+                let range = stats.zone_map.range;
+                rows.push(vec![
+                    RawVal::Str(table.name().to_string()),
+                    RawVal::Int(partition.id() as i64),
+                    RawVal::Str(handle.name().to_string()),
+                    optional(range.map(|(min, _)| min)),
+                    optional(range.map(|(_, max)| max)),
+                    optional(stats.zone_map.null_count.map(|count| count as i64)),
+                    optional(stats.distinct_values.map(|count| count as i64)),
+                    RawVal::Int(handle.size_bytes() as i64),
+                ]);
+            }
+        }
+    }
+    rows
+}
+
+/// One row for each encoding of each column of each table.
+fn mem_stats_rows(tables: &[&Table]) -> Rows {
+    let mut rows = Rows::new(&["table_name", "column_name", "encoding", "row_count", "size_bytes"]);
+    for table in tables {
+        for stats in table.mem_stats() {
+            rows.push(vec![
+                RawVal::Str(stats.table),
+                RawVal::Str(stats.column),
+                RawVal::Str(stats.encoding),
+                RawVal::Int(stats.rows as i64),
+                RawVal::Int(stats.size_bytes as i64),
+            ]);
+        }
+    }
+    rows
+}
+
+/// One row for each of the most recent queries, from oldest to newest.
+fn query_log_rows(query_log: &QueryLog) -> Rows {
+    let mut rows = Rows::new(&["timestamp", "query", "normalized_query", "duration_ns", "rows_scanned",
+                               "rows_returned", "partitions_pruned", "error"]);
+    for entry in query_log.entries() {
+        rows.push(vec![
+            RawVal::Int(entry.timestamp),
+            RawVal::Str(entry.query),
+            RawVal::Str(entry.normalized),
+            RawVal::Int(entry.duration_ns as i64),
+            RawVal::Int(entry.rows_scanned as i64),
+            RawVal::Int(entry.rows_returned as i64),
+            RawVal::Int(entry.partitions_pruned as i64),
+            entry.error.map_or(RawVal::Null, RawVal::Str),
+        ]);
+    }
+    rows
+}
+
+fn size_bytes(partition: &Partition) -> usize {
+    partition.column_handles().iter().map(|handle| handle.size_bytes()).sum()
+}
+
+fn join(values: BTreeSet<String>) -> String {
+    values.into_iter().collect::<Vec<_>>().join(", ")
+}
+
+struct Rows {
+    names: Vec<&'static str>,
+    columns: Vec<MixedCol>,
+}
+
+impl Rows {
+    fn new(names: &[&'static str]) -> Rows {
+        Rows {
+            names: names.to_vec(),
+            columns: names.iter().map(|_| MixedCol::default()).collect(),
+        }
+    }
+
+    fn push(&mut self, row: Vec<RawVal>) {
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            column.push(value);
+        }
+    }
+
+    fn into_partition(self) -> Partition {
+        let columns = self.names.iter().zip(self.columns)
+            .map(|(name, column)| column.finalize(name))
+            .collect();
+        // System tables are not part of the LRU cache of the database, they are dropped after the query
+        Partition::new(0, columns, LRU::default())
+    }
+}
@@ -3,25 +3,42 @@ use std::mem;
 use std::ops::DerefMut;
 use std::str;
 use std::sync::Arc;
-use std::sync::{Mutex, RwLock};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use disk_store::interface::*;
 use heapsize::HeapSizeOf;
 use ingest::buffer::Buffer;
 use ingest::input_column::InputColumn;
 use ingest::raw_val::RawVal;
-use mem_store::partition::{Partition, ColumnKey};
+use mem_store::partition::Partition;
 use mem_store::*;
 
+/// Source of table versions, shared by all tables so that a table that is dropped and created again doesn't repeat
+/// the versions of the dropped table.
+static NEXT_VERSION: AtomicUsize = AtomicUsize::new(0);
 
 pub struct Table {
     name: String,
     batch_size: usize,
     partitions: RwLock<HashMap<PartitionID, Arc<Partition>>>,
     buffer: Mutex<Buffer>,
+    /// View of the buffered rows that queries run on, built on demand and discarded whenever rows are buffered.
+    open_partition: Mutex<Option<Arc<Partition>>>,
+    /// Buffers that were taken from the table and remain queryable until their partition has been loaded.
+    sealing: Mutex<Vec<SealingBuffer>>,
+    /// Sequence number of the last buffered row in the write-ahead log, only modified while holding the buffer lock.
+    buffer_wal_seq: AtomicUsize,
+    /// Changes whenever partitions are added, removed or rows are deleted.
+    version: AtomicUsize,
     lru: LRU,
 }
 
+struct SealingBuffer {
+    buffer: Arc<Buffer>,
+    open_partition: Option<Arc<Partition>>,
+}
+
 impl Table {
     pub fn new(batch_size: usize, name: &str, lru: LRU) -> Table {
         Table {
@@ -29,6 +46,10 @@ impl Table {
             batch_size: batch_size_override(batch_size, name),
             partitions: RwLock::new(HashMap::new()),
             buffer: Mutex::new(Buffer::default()),
+            open_partition: Mutex::new(None),
+            sealing: Mutex::new(Vec::new()),
+            buffer_wal_seq: AtomicUsize::new(0),
+            version: AtomicUsize::new(NEXT_VERSION.fetch_add(1, Ordering::SeqCst)),
             lru,
         }
     }
@@ -42,6 +63,38 @@ impl Table {
         partitions.values().cloned().collect()
     }
 
+    /// Snapshot of the partitions together with the version of the table they belong to.
+    /// Rows that are buffered or being sealed are included as open partitions, which are not reflected in the version.
+    pub fn versioned_snapshot(&self) -> (Vec<Arc<Partition>>, usize) {
+        // Locks are acquired in the same order as by `ingest` and `load_sealed_partition`
+        let buffer = self.buffer.lock().unwrap();
+        let mut open_partition = self.open_partition.lock().unwrap();
+        let mut sealing = self.sealing.lock().unwrap();
+        let partitions = self.partitions.read().unwrap();
+        let mut snapshot = partitions.values().cloned().collect::<Vec<_>>();
+        for sealed in sealing.iter_mut() {
+            let sealed_buffer = &sealed.buffer;
+            snapshot.push(sealed.open_partition
+                .get_or_insert_with(|| Arc::new(Partition::open(sealed_buffer.columns())))
+                .clone());
+        }
+        if buffer.len() > 0 {
+            snapshot.push(open_partition
+                .get_or_insert_with(|| Arc::new(Partition::open(buffer.columns())))
+                .clone());
+        }
+        (snapshot, self.version())
+    }
+
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// Assigns a new version to the table, which invalidates cached results of queries on it.
+    pub fn mark_modified(&self) {
+        self.version.store(NEXT_VERSION.fetch_add(1, Ordering::SeqCst), Ordering::SeqCst);
+    }
+
     pub fn load_table_metadata(batch_size: usize, storage: &DiskStore, lru: &LRU) -> HashMap<String, Table> {
         let mut tables = HashMap::new();
         for md in storage.load_metadata() {
@@ -58,59 +111,122 @@ impl Table {
         partitions[&id].restore(&col);
     }
 
-    pub fn evict(&self, key: &ColumnKey) -> usize {
+    pub fn evict(&self, id: PartitionID) -> usize {
         let partitions = self.partitions.read().unwrap();
-        partitions.get(&key.0).map(|p| p.evict(&key.1)).unwrap_or(0)
+        partitions.get(&id).map(|p| p.evict()).unwrap_or(0)
     }
 
     pub fn insert_nonresident_partition(&self, md: &PartitionMetadata) {
         let partition = Arc::new(Partition::nonresident(md.id, md.len, &md.columns, self.lru.clone()));
         let mut partitions = self.partitions.write().unwrap();
         partitions.insert(md.id, partition);
+        self.mark_modified();
     }
 
-    pub fn ingest(&self, row: Vec<(String, RawVal)>) {
-        let mut buffer = self.buffer.lock().unwrap();
+    /// Appends `row` to the write-ahead log and the buffer.
+    /// Returns the buffered rows and the sequence number of the last of them once the buffer is full.
+    pub fn ingest(&self, row: Vec<(String, RawVal)>, storage: &DiskStore) -> Option<(Arc<Buffer>, WalSeq)> {
+        let mut buffer = self.buffer_mut();
+        let seq = storage.append_wal(&self.name, &row);
+        self.buffer_wal_seq.store(seq as usize, Ordering::SeqCst);
         buffer.push_row(row);
-        self.batch_if_needed(buffer.deref_mut());
+        self.take_if_full(buffer.deref_mut())
     }
 
-    pub fn ingest_homogeneous(&self, columns: HashMap<String, InputColumn>) {
+    /// Adds a row recovered from the write-ahead log to the buffer.
+    pub fn recover(&self, row: Vec<(String, RawVal)>, seq: WalSeq) {
+        let mut buffer = self.buffer_mut();
+        self.buffer_wal_seq.store(seq as usize, Ordering::SeqCst);
+        buffer.push_row(row);
+    }
+
+    /// Removes all rows from the buffer and returns them together with the sequence number of the last of them.
+    pub fn take_buffer(&self) -> Option<(Arc<Buffer>, WalSeq)> {
         let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() == 0 {
+            None
+        } else {
+            Some(self.take(buffer.deref_mut()))
+        }
+    }
+
+    pub fn ingest_homogeneous(&self, columns: HashMap<String, InputColumn>) {
+        let mut buffer = self.buffer_mut();
         buffer.push_typed_cols(columns);
     }
 
-    pub fn ingest_heterogeneous(&self, columns: HashMap<String, Vec<RawVal>>) {
-        let mut buffer = self.buffer.lock().unwrap();
+    pub fn ingest_heterogeneous(&self, columns: HashMap<String, Vec<RawVal>>) -> Option<(Arc<Buffer>, WalSeq)> {
+        let mut buffer = self.buffer_mut();
         buffer.push_untyped_cols(columns);
-        self.batch_if_needed(&mut buffer);
+        self.take_if_full(&mut buffer)
     }
 
     pub fn load_partition(&self, partition: Partition) {
         let mut partitions = self.partitions.write().unwrap();
         partitions.insert(partition.id(), Arc::new(partition));
+        self.mark_modified();
     }
 
-    fn batch_if_needed(&self, buffer: &mut Buffer) {
-        if buffer.len() < self.batch_size { return; }
-        self.batch(buffer);
+    pub fn load_partitions(&self, loaded: Vec<Partition>) {
+        let mut partitions = self.partitions.write().unwrap();
+        for partition in loaded {
+            partitions.insert(partition.id(), Arc::new(partition));
+        }
+        self.mark_modified();
     }
 
-    fn batch(&self, buffer: &mut Buffer) {
-        let buffer = mem::replace(buffer, Buffer::default());
-        self.persist_batch(&buffer);
-        // TODO(clemens): get unique partition ID
-        let (new_partition, keys) = Partition::from_buffer(0, buffer, self.lru.clone());
+    /// Loads the partition that `buffer` was sealed into, which atomically replaces the open partition of its rows.
+    pub fn load_sealed_partition(&self, partition: Partition, buffer: &Arc<Buffer>) {
+        let mut sealing = self.sealing.lock().unwrap();
         let mut partitions = self.partitions.write().unwrap();
-        partitions.insert(new_partition.id(), Arc::new(new_partition));
-        for key in keys { self.lru.put(key); }
+        partitions.insert(partition.id(), Arc::new(partition));
+        sealing.retain(|sealed| !Arc::ptr_eq(&sealed.buffer, buffer));
+        self.mark_modified();
+    }
+
+    /// Replaces partitions by the copies in `updated` that have the same ids and mark additional rows as deleted.
+    pub fn update_partitions(&self, updated: &[Arc<Partition>]) {
+        if updated.is_empty() { return; }
+        let mut partitions = self.partitions.write().unwrap();
+        for partition in updated {
+            if let Some(existing) = partitions.get_mut(&partition.id()) {
+                *existing = partition.clone();
+            }
+        }
+        self.mark_modified();
     }
 
-    /*fn load_buffer(&self, buffer: Buffer) {
-        self.load_batch(buffer.into());
-    }*/
+    /// Replaces the partitions with ids `old` by `partition`, or just removes them if `partition` is `None`.
+    pub fn replace_partitions(&self, old: &[PartitionID], partition: Option<Partition>) {
+        let mut partitions = self.partitions.write().unwrap();
+        for id in old {
+            partitions.remove(id);
+        }
+        if let Some(partition) = partition {
+            partitions.insert(partition.id(), Arc::new(partition));
+        }
+        self.mark_modified();
+    }
 
-    fn persist_batch(&self, _batch: &Buffer) {}
+    /// Locks the buffer to add rows to it, which invalidates the open partition.
+    fn buffer_mut(&self) -> MutexGuard<Buffer> {
+        let buffer = self.buffer.lock().unwrap();
+        *self.open_partition.lock().unwrap() = None;
+        buffer
+    }
+
+    fn take_if_full(&self, buffer: &mut Buffer) -> Option<(Arc<Buffer>, WalSeq)> {
+        if buffer.len() < self.batch_size { return None; }
+        Some(self.take(buffer))
+    }
+
+    /// Must be called while holding the buffer lock, the rows stay queryable until `load_sealed_partition` is called.
+    fn take(&self, buffer: &mut Buffer) -> (Arc<Buffer>, WalSeq) {
+        let buffer = Arc::new(mem::replace(buffer, Buffer::default()));
+        let open_partition = self.open_partition.lock().unwrap().take();
+        self.sealing.lock().unwrap().push(SealingBuffer { buffer: buffer.clone(), open_partition });
+        (buffer, self.buffer_wal_seq.load(Ordering::SeqCst) as WalSeq)
+    }
 
     pub fn mem_tree(&self, depth: usize) -> MemTreeTable {
         assert!(depth > 0);
@@ -148,6 +264,31 @@ impl Table {
         }
     }
 
+    /// Bytes and rows of the resident data of each column of the table, broken down by encoding.
+    pub fn mem_stats(&self) -> Vec<MemStats> {
+        let mut stats = HashMap::<(String, String), MemStats>::new();
+        for partition in self.snapshot() {
+            for handle in partition.column_handles() {
+                if let Some(ref column) = *handle.try_get() {
+                    let encoding = column.codec().signature(false);
+                    let entry = stats.entry((handle.name().to_string(), encoding.clone()))
+                        .or_insert_with(|| MemStats {
+                            table: self.name().to_string(),
+                            column: handle.name().to_string(),
+                            encoding,
+                            rows: 0,
+                            size_bytes: 0,
+                        });
+                    entry.rows += column.len();
+                    entry.size_bytes += column.heap_size_of_children();
+                }
+            }
+        }
+        let mut stats = stats.into_iter().map(|(_, s)| s).collect::<Vec<_>>();
+        stats.sort_by(|a, b| (&a.column, &a.encoding).cmp(&(&b.column, &b.encoding)));
+        stats
+    }
+
     pub fn max_partition_id(&self) -> u64 {
         let partitions = self.partitions.read().unwrap();
         partitions.keys().max().cloned().unwrap_or(0)
@@ -199,4 +340,14 @@ pub struct TableStats {
     pub size_per_column: Vec<(String, usize)>,
 }
 
+/// Memory used by the resident data of a column of a table that is stored with one particular encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemStats {
+    pub table: String,
+    pub column: String,
+    pub encoding: String,
+    pub rows: usize,
+    pub size_bytes: usize,
+}
+
 
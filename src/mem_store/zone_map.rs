@@ -0,0 +1,107 @@
+use engine::data_types::BasicType;
+use ingest::raw_val::RawVal;
+use mem_store::codec::CodecOp;
+use mem_store::column::{Column, DataSection, DataSource};
+use syntax::expression::*;
+
+
+/// Statistics about the values of a column in one partition, used to skip partitions that can not match a filter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ZoneMap {
+    /// Minimum and maximum value of integer columns.
+    pub range: Option<(i64, i64)>,
+    /// Number of null values, `None` if it is not known.
+    pub null_count: Option<usize>,
+}
+
+impl ZoneMap {
+    pub fn of(column: &Column) -> ZoneMap {
+        let range = if column.basic_type() == BasicType::Integer { column.decoded_range() } else { None };
+        let null_count = if column.basic_type() == BasicType::Null {
+            Some(column.len())
+        } else if !column.codec().ops().iter().any(|op| match *op { CodecOp::Nullable => true, _ => false }) {
+            Some(0)
+        } else {
+            // Nullable columns store the bitmap of present values in their second data section
+            match column.data().get(1) {
+                Some(&DataSection::U8(ref present)) =>
+                    Some(column.len() - present.iter().map(|byte| byte.count_ones() as usize).sum::<usize>()),
+                _ => None,
+            }
+        };
+        ZoneMap { range, null_count }
+    }
+
+    /// Zone map derived from the range of a column that is being queried, the number of nulls is not determined.
+    pub fn of_data_source(column: &DataSource) -> ZoneMap {
+        let range = if column.full_type().decoded == BasicType::Integer {
+            let offset = column.codec().ops().iter()
+                .filter_map(|op| match *op {
+                    CodecOp::Add(_, offset) => Some(offset),
+                    _ => None,
+                })
+                .next()
+                .unwrap_or(0);
+            column.range().map(|(min, max)| (min + offset, max + offset))
+        } else {
+            None
+        };
+        ZoneMap { range, null_count: None }
+    }
+
+    /// Zone map of a column that is not part of a partition and therefore all null.
+    pub fn null(len: usize) -> ZoneMap {
+        ZoneMap { range: None, null_count: Some(len) }
+    }
+
+    pub fn unknown() -> ZoneMap {
+        ZoneMap { range: None, null_count: None }
+    }
+}
+
+/// Returns `false` if it follows from the zone maps of a partition with `len` rows that none of its rows satisfy
+/// `filter`. `may_contain` returns `false` if a column definitely does not contain a value.
+pub fn may_match<F, G>(filter: &Expr, len: usize, zone_map: &F, may_contain: &G) -> bool
+    where F: Fn(&str) -> ZoneMap, G: Fn(&str, &RawVal) -> bool {
+    match *filter {
+        Expr::Func2(Func2Type::And, ref lhs, ref rhs) =>
+            may_match(lhs, len, zone_map, may_contain) && may_match(rhs, len, zone_map, may_contain),
+        Expr::Func2(Func2Type::Or, ref lhs, ref rhs) =>
+            may_match(lhs, len, zone_map, may_contain) || may_match(rhs, len, zone_map, may_contain),
+        Expr::Func2(op, box Expr::ColName(ref name), box Expr::Const(ref value)) if op.flipped().is_some() =>
+            may_compare(op, zone_map(name), len, value)
+                && (op != Func2Type::Equals || may_contain(name, value)),
+        Expr::Func2(op, box Expr::Const(ref value), box Expr::ColName(ref name)) => match op.flipped() {
+            Some(flipped) => may_compare(flipped, zone_map(name), len, value)
+                && (op != Func2Type::Equals || may_contain(name, value)),
+            None => true,
+        },
+        Expr::In(box Expr::ColName(ref name), ref values) => {
+            let zone_map = zone_map(name);
+            values.iter().any(|value| may_compare(Func2Type::Equals, zone_map, len, value) && may_contain(name, value))
+        }
+        Expr::Func1(Func1Type::IsNull, box Expr::ColName(ref name)) => zone_map(name).null_count != Some(0),
+        Expr::Func1(Func1Type::IsNotNull, box Expr::ColName(ref name)) => zone_map(name).null_count != Some(len),
+        _ => true,
+    }
+}
+
+/// Whether `op` applied to a column with the given zone map and `value` can be true for any row.
+fn may_compare(op: Func2Type, zone_map: ZoneMap, len: usize, value: &RawVal) -> bool {
+    // Comparisons with null values are never true
+    if zone_map.null_count == Some(len) {
+        return false;
+    }
+    match (zone_map.range, value) {
+        (Some((min, max)), &RawVal::Int(value)) => match op {
+            Func2Type::Equals => min <= value && value <= max,
+            Func2Type::NotEquals => !(min == value && max == value),
+            Func2Type::LT => min < value,
+            Func2Type::LTE => min <= value,
+            Func2Type::GT => max > value,
+            Func2Type::GTE => max >= value,
+            _ => true,
+        },
+        _ => true,
+    }
+}
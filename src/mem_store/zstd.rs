@@ -0,0 +1,60 @@
+extern crate zstd;
+
+use std::io::Read;
+use std::mem;
+use std::slice::{from_raw_parts, from_raw_parts_mut};
+use std::fmt::Debug;
+
+const COMPRESSION_LEVEL: i32 = 3;
+
+
+pub fn decoder<'a>(data: &'a [u8]) -> Box<Read + 'a> {
+    Box::new(zstd::stream::read::Decoder::new(data).unwrap())
+}
+
+pub fn encode<T: Debug>(data: &[T]) -> Vec<u8> {
+    let ptr_t = data.as_ptr();
+    let data_u8: &[u8] = unsafe {
+        let ptr_u8 = mem::transmute::<_, *const u8>(ptr_t);
+        from_raw_parts(ptr_u8, data.len() * mem::size_of::<T>())
+    };
+    zstd::stream::encode_all(data_u8, COMPRESSION_LEVEL).unwrap()
+}
+
+pub fn decode<T>(src: &mut Read, dst: &mut [T]) -> usize {
+    let ptr_t = dst.as_ptr();
+    let dst_u8: &mut [u8] = unsafe {
+        let ptr_u8 = mem::transmute::<_, *mut u8>(ptr_t);
+        from_raw_parts_mut(ptr_u8, dst.len() * mem::size_of::<T>())
+    };
+
+    let mut read = 0;
+    // Reads return at most one frame at a time, so might have to call multiple times to fill buffer
+    while read < dst_u8.len() && 0 != {
+        let len = src.read(&mut dst_u8[read..]).unwrap();
+        read += len;
+        len
+    } {}
+    assert_eq!(read % mem::size_of::<T>(), 0);
+    read / mem::size_of::<T>()
+}
+
+pub fn decode_all<T>(data: &[u8], dst: &mut [T]) -> usize {
+    decode(&mut decoder(data), dst)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let data = vec![10i64, 12095, -51235, 3, 0, 0, 12353, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10, 10];
+        let encoded = encode(&data);
+        let mut decoded = vec![0i64; data.len()];
+        let count = decode_all(&encoded, &mut decoded);
+        assert_eq!(count, data.len());
+        assert_eq!(decoded, data);
+    }
+}
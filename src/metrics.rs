@@ -0,0 +1,122 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use mem_store::table::TableStats;
+
+
+/// Upper bounds of the buckets of the query latency histogram in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0, 2.5, 10.0];
+
+/// Counters of the work done by a database since it was started.
+#[derive(Default)]
+pub struct Metrics {
+    queries: AtomicUsize,
+    query_errors: AtomicUsize,
+    rows_scanned: AtomicUsize,
+    /// Sum of query latencies in nanoseconds.
+    query_latency_ns: AtomicUsize,
+    /// Number of queries with latency in each bucket of `LATENCY_BUCKETS` and above the last bucket.
+    query_latency_buckets: [AtomicUsize; 12],
+    rows_ingested: AtomicUsize,
+    bytes_ingested: AtomicUsize,
+    queries_normalized: AtomicUsize,
+}
+
+impl Metrics {
+    pub fn record_query(&self, latency_ns: u64, rows_scanned: usize, failed: bool) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.query_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.rows_scanned.fetch_add(rows_scanned, Ordering::Relaxed);
+        self.query_latency_ns.fetch_add(latency_ns as usize, Ordering::Relaxed);
+        let seconds = latency_ns as f64 / 1e9;
+        let bucket = LATENCY_BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.query_latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a query was validated and normalized before it was run.
+    pub fn record_normalization(&self) {
+        self.queries_normalized.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a partition of `rows` rows that takes up `bytes` bytes of memory.
+    pub fn record_ingestion(&self, rows: usize, bytes: usize) {
+        self.rows_ingested.fetch_add(rows, Ordering::Relaxed);
+        self.bytes_ingested.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Formats the counters and the size of `tables` in the Prometheus text format.
+    pub fn prometheus(&self, tables: &[TableStats]) -> String {
+        let mut out = String::new();
+        counter(&mut out, "locustdb_queries_total", "Number of queries run.", self.queries.load(Ordering::Relaxed));
+        counter(&mut out, "locustdb_query_errors_total", "Number of queries that failed.",
+                self.query_errors.load(Ordering::Relaxed));
+        counter(&mut out, "locustdb_rows_scanned_total", "Number of rows scanned by queries.",
+                self.rows_scanned.load(Ordering::Relaxed));
+        counter(&mut out, "locustdb_queries_normalized_total",
+                "Number of queries that were normalized, prepared queries are only normalized on their first run.",
+                self.queries_normalized.load(Ordering::Relaxed));
+        counter(&mut out, "locustdb_rows_ingested_total", "Number of rows ingested.",
+                self.rows_ingested.load(Ordering::Relaxed));
+        counter(&mut out, "locustdb_ingested_bytes_total", "Size of ingested partitions in bytes.",
+                self.bytes_ingested.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP locustdb_query_duration_seconds Latency of queries.");
+        let _ = writeln!(out, "# TYPE locustdb_query_duration_seconds histogram");
+        let mut cumulative = 0;
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            cumulative += self.query_latency_buckets[i].load(Ordering::Relaxed);
+            let _ = writeln!(out, "locustdb_query_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        cumulative += self.query_latency_buckets[LATENCY_BUCKETS.len()].load(Ordering::Relaxed);
+        let _ = writeln!(out, "locustdb_query_duration_seconds_bucket{{le=\"+Inf\"}} {}", cumulative);
+        let _ = writeln!(out, "locustdb_query_duration_seconds_sum {}",
+                         self.query_latency_ns.load(Ordering::Relaxed) as f64 / 1e9);
+        let _ = writeln!(out, "locustdb_query_duration_seconds_count {}", cumulative);
+
+        gauge(&mut out, "locustdb_table_rows", "Number of rows in each table.", tables, |table| table.rows);
+        gauge(&mut out, "locustdb_table_partitions", "Number of partitions of each table.", tables, |table| table.batches);
+        gauge(&mut out, "locustdb_table_memory_bytes", "Memory used by the partitions and buffer of each table.", tables,
+              |table| table.batches_bytes + table.buffer_bytes);
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn gauge<F: Fn(&TableStats) -> usize>(out: &mut String, name: &str, help: &str, tables: &[TableStats], value: F) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for table in tables {
+        let _ = writeln!(out, "{}{{table=\"{}\"}} {}", name, escape_label(&table.name), value(table));
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let metrics = Metrics::default();
+        metrics.record_query(2_000_000, 10, false);
+        metrics.record_query(20_000_000_000, 5, true);
+        let out = metrics.prometheus(&[]);
+        assert!(out.contains("locustdb_queries_total 2\n"));
+        assert!(out.contains("locustdb_query_errors_total 1\n"));
+        assert!(out.contains("locustdb_rows_scanned_total 15\n"));
+        assert!(out.contains("locustdb_query_duration_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(out.contains("locustdb_query_duration_seconds_bucket{le=\"0.0025\"} 1\n"));
+        assert!(out.contains("locustdb_query_duration_seconds_bucket{le=\"10\"} 1\n"));
+        assert!(out.contains("locustdb_query_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+    }
+}
@@ -0,0 +1,287 @@
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use futures_executor::block_on;
+
+use ingest::raw_val::RawVal;
+use locustdb::LocustDB;
+use udf::ValueType;
+use syntax::parser;
+
+
+const PROTOCOL_VERSION_3: i32 = 196_608;
+const SSL_REQUEST: i32 = 80_877_103;
+const CANCEL_REQUEST: i32 = 80_877_102;
+
+const INT8_OID: i32 = 20;
+const TEXT_OID: i32 = 25;
+const FLOAT8_OID: i32 = 701;
+
+/// Longest message accepted from clients, which bounds the memory allocated for a message before it is read.
+const MAX_MESSAGE_LEN: i32 = 16 * 1024 * 1024;
+
+/// Front end that speaks the PostgreSQL wire protocol, so that `psql` and other Postgres clients can query the
+/// database directly.
+///
+/// Only the simple query flow is supported, results are sent in text format and authentication is not required.
+/// Messages of the extended query protocol are answered with an error. `COPY` statements are rejected unless enabled with
+/// `allow_copy`.
+pub struct PostgresServer {
+    listener: TcpListener,
+    locustdb: Arc<LocustDB>,
+    allow_copy: bool,
+}
+
+impl PostgresServer {
+    /// Listens on `addr`, e.g. `127.0.0.1:5432`. Port 0 picks any free port.
+    pub fn bind(locustdb: Arc<LocustDB>, addr: &str) -> Result<PostgresServer, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+        Ok(PostgresServer { listener, locustdb, allow_copy: false })
+    }
+
+    /// Allows clients to write files to the `export_directory` of the database with `COPY` statements.
+    pub fn allow_copy(mut self, allow_copy: bool) -> PostgresServer {
+        self.allow_copy = allow_copy;
+        self
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// Accepts connections until the process exits, each connection is handled on its own thread.
+    pub fn run(&self) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let locustdb = self.locustdb.clone();
+                    let allow_copy = self.allow_copy;
+                    thread::spawn(move || {
+                        if let Err(err) = Connection::new(locustdb, stream, allow_copy).and_then(|mut c| c.run()) {
+                            debug!("Postgres connection closed: {}", err);
+                        }
+                    });
+                }
+                Err(err) => warn!("Failed to accept connection: {}", err),
+            }
+        }
+    }
+
+    /// Accepts connections on a new thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::spawn(move || self.run())
+    }
+}
+
+/// Listens on `addr` and accepts connections on a background thread.
+pub fn serve(locustdb: Arc<LocustDB>, addr: &str) -> Result<SocketAddr, String> {
+    let server = PostgresServer::bind(locustdb, addr)?;
+    let addr = server.addr();
+    server.spawn();
+    Ok(addr)
+}
+
+struct Connection {
+    locustdb: Arc<LocustDB>,
+    allow_copy: bool,
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn new(locustdb: Arc<LocustDB>, stream: TcpStream, allow_copy: bool) -> io::Result<Connection> {
+        Ok(Connection {
+            locustdb,
+            allow_copy,
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        if !self.startup()? {
+            return Ok(());
+        }
+        // Set while skipping messages of an extended query that failed, until the client sends Sync
+        let mut failed_extended_query = false;
+        loop {
+            let tag = match self.reader.read_u8() {
+                Ok(tag) => tag,
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            let body = self.read_body()?;
+            match tag {
+                b'Q' => {
+                    let query = cstring(&body);
+                    self.simple_query(query.trim())?;
+                    self.ready_for_query()?;
+                }
+                b'X' => return Ok(()),
+                b'S' => {
+                    failed_extended_query = false;
+                    self.ready_for_query()?;
+                }
+                b'P' | b'B' | b'D' | b'E' | b'C' | b'H' | b'F' => {
+                    if !failed_extended_query {
+                        failed_extended_query = true;
+                        self.error("0A000", "Extended query protocol is not supported")?;
+                        self.writer.flush()?;
+                    }
+                }
+                _ => {
+                    self.error("08P01", &format!("Unsupported message type {:?}", tag as char))?;
+                    self.ready_for_query()?;
+                }
+            }
+        }
+    }
+
+    /// Handles the startup message, returns false if the connection should be closed.
+    fn startup(&mut self) -> io::Result<bool> {
+        loop {
+            let body = self.read_body()?;
+            if body.len() < 4 {
+                return Ok(false);
+            }
+            let version = (&body[0..4]).read_i32::<BigEndian>()?;
+            match version {
+                SSL_REQUEST => {
+                    self.writer.write_all(b"N")?;
+                    self.writer.flush()?;
+                }
+                CANCEL_REQUEST => return Ok(false),
+                PROTOCOL_VERSION_3 => break,
+                _ => {
+                    self.error("08P01", &format!("Unsupported protocol version {}", version))?;
+                    self.writer.flush()?;
+                    return Ok(false);
+                }
+            }
+        }
+
+        self.message(b'R', |m| m.write_i32::<BigEndian>(0))?;
+        for &(name, value) in &[("server_version", "9.6.0"),
+                                ("server_encoding", "UTF8"),
+                                ("client_encoding", "UTF8"),
+                                ("DateStyle", "ISO, MDY"),
+                                ("integer_datetimes", "on")] {
+            self.message(b'S', |m| {
+                write_cstring(m, name)?;
+                write_cstring(m, value)
+            })?;
+        }
+        self.message(b'K', |m| {
+            m.write_i32::<BigEndian>(0)?;
+            m.write_i32::<BigEndian>(0)
+        })?;
+        self.ready_for_query()?;
+        Ok(true)
+    }
+
+    fn simple_query(&mut self, query: &str) -> io::Result<()> {
+        if query.trim_right_matches(';').trim().is_empty() {
+            return self.message(b'I', |_| Ok(()));
+        }
+        if !self.allow_copy && parser::is_copy(query) {
+            return self.error("42501", "COPY is not permitted over the Postgres protocol");
+        }
+        let output = match block_on(self.locustdb.run_query(query, false, vec![])) {
+            Ok((Ok(output), _)) => output,
+            Ok((Err(err), _)) => return self.error("42000", &err.to_string()),
+            Err(_) => return self.error("57014", "Query was cancelled"),
+        };
+
+        // Columns whose values can have different types are sent as text
+        let types = output.coltypes.iter()
+            .map(|coltype| match *coltype {
+                Some(ValueType::Integer) => (INT8_OID, 8),
+                Some(ValueType::Float) => (FLOAT8_OID, 8),
+                _ => (TEXT_OID, -1),
+            })
+            .collect::<Vec<_>>();
+        self.message(b'T', |m| {
+            m.write_i16::<BigEndian>(output.colnames.len() as i16)?;
+            for (name, &(oid, size)) in output.colnames.iter().zip(types.iter()) {
+                write_cstring(m, name)?;
+                m.write_i32::<BigEndian>(0)?;
+                m.write_i16::<BigEndian>(0)?;
+                m.write_i32::<BigEndian>(oid)?;
+                m.write_i16::<BigEndian>(size)?;
+                m.write_i32::<BigEndian>(-1)?;
+                m.write_i16::<BigEndian>(0)?;
+            }
+            Ok(())
+        })?;
+        for row in &output.rows {
+            self.message(b'D', |m| {
+                m.write_i16::<BigEndian>(row.len() as i16)?;
+                for value in row {
+                    let text = match *value {
+                        RawVal::Null => {
+                            m.write_i32::<BigEndian>(-1)?;
+                            continue;
+                        }
+                        RawVal::Str(ref s) => s.clone(),
+                        ref value => format!("{}", value),
+                    };
+                    m.write_i32::<BigEndian>(text.len() as i32)?;
+                    m.write_all(text.as_bytes())?;
+                }
+                Ok(())
+            })?;
+        }
+        let command = format!("SELECT {}", output.rows.len());
+        self.message(b'C', |m| write_cstring(m, &command))
+    }
+
+    fn error(&mut self, code: &str, message: &str) -> io::Result<()> {
+        self.message(b'E', |m| {
+            for &(field, value) in &[(b'S', "ERROR"), (b'V', "ERROR"), (b'C', code), (b'M', message)] {
+                m.write_u8(field)?;
+                write_cstring(m, value)?;
+            }
+            m.write_u8(0)
+        })
+    }
+
+    fn ready_for_query(&mut self) -> io::Result<()> {
+        self.message(b'Z', |m| m.write_u8(b'I'))?;
+        self.writer.flush()
+    }
+
+    /// Writes a message with the given tag and the body written by `body`.
+    fn message<F>(&mut self, tag: u8, body: F) -> io::Result<()> where F: FnOnce(&mut Vec<u8>) -> io::Result<()> {
+        let mut message = Vec::new();
+        body(&mut message)?;
+        self.writer.write_u8(tag)?;
+        self.writer.write_i32::<BigEndian>(message.len() as i32 + 4)?;
+        self.writer.write_all(&message)
+    }
+
+    /// Reads the length prefixed body of a message.
+    fn read_body(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.reader.read_i32::<BigEndian>()?;
+        if len < 4 || len > MAX_MESSAGE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid message length {}", len)));
+        }
+        let mut body = vec![0; len as usize - 4];
+        self.reader.read_exact(&mut body)?;
+        Ok(body)
+    }
+}
+
+/// Null-terminated string at the start of `bytes`.
+fn cstring(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or_else(|| bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn write_cstring(out: &mut Vec<u8>, s: &str) -> io::Result<()> {
+    out.write_all(s.as_bytes())?;
+    out.write_u8(0)
+}
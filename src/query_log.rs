@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use time;
+
+
+/// Query that was run by the database.
+#[derive(Debug, Clone)]
+pub struct QueryLogEntry {
+    /// Unix timestamp in seconds at which the query completed.
+    pub timestamp: i64,
+    pub query: String,
+    /// Query with all literals replaced by `?`, so that queries that differ only in constants are equal.
+    pub normalized: String,
+    pub duration_ns: u64,
+    pub rows_scanned: usize,
+    pub rows_returned: usize,
+    pub partitions_pruned: usize,
+    pub error: Option<String>,
+}
+
+/// Ring buffer of the most recently run queries.
+pub struct QueryLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<QueryLogEntry>>,
+}
+
+impl QueryLog {
+    pub fn new(capacity: usize) -> QueryLog {
+        QueryLog {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Adds an entry for `query`, dropping the oldest entry once the log is full.
+    pub fn record(&self, query: &str, duration_ns: u64, rows_scanned: usize, rows_returned: usize,
+                  partitions_pruned: usize, error: Option<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let entry = QueryLogEntry {
+            timestamp: time::now().to_timespec().sec,
+            query: query.to_string(),
+            normalized: normalize(query),
+            duration_ns,
+            rows_scanned,
+            rows_returned,
+            partitions_pruned,
+            error,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// All entries from oldest to newest.
+    pub fn entries(&self) -> Vec<QueryLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Replaces string and number literals with `?` and collapses whitespace.
+pub fn normalize(query: &str) -> String {
+    let mut normalized = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    // Whether the previous character is part of an identifier, in which case digits don't start a number
+    let mut in_identifier = false;
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            while let Some(c) = chars.next() {
+                if c == '\'' {
+                    // '' is an escaped quote inside the string
+                    if chars.peek() == Some(&'\'') {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+            }
+            normalized.push('?');
+            in_identifier = false;
+        } else if c.is_ascii_digit() && !in_identifier {
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() || c == '.' {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            normalized.push('?');
+        } else if c.is_whitespace() {
+            while chars.peek().map_or(false, |c| c.is_whitespace()) {
+                chars.next();
+            }
+            normalized.push(' ');
+            in_identifier = false;
+        } else {
+            normalized.push(c);
+            in_identifier = c.is_alphanumeric() || c == '_';
+        }
+    }
+    normalized.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("SELECT  col1, 'it''s' FROM t\n WHERE x > 10.5 AND y = 'a';"),
+                   "SELECT col1, ? FROM t WHERE x > ? AND y = ?;");
+    }
+
+    #[test]
+    fn test_capacity() {
+        let log = QueryLog::new(2);
+        for query in &["a", "b", "c"] {
+            log.record(query, 0, 0, 0, 0, None);
+        }
+        let queries = log.entries().into_iter().map(|entry| entry.query).collect::<Vec<_>>();
+        assert_eq!(queries, vec!["b", "c"]);
+    }
+}
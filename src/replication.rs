@@ -0,0 +1,169 @@
+use std::io::{Cursor, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use disk_store::interface::PartitionID;
+use disk_store::rocksdb::{deserialize_column, deserialize_meta_data, serialize_column, serialize_meta_data};
+use disk_store::snapshot::{read_section, write_section};
+use locustdb::LocustDB;
+use mem_store::column::Column;
+
+
+/// Changes to the sealed partitions of a leader, which are served with `GET /replication` by `server::Server`.
+///
+/// Followers request the partitions with ids greater than the largest id they received (`after`) together with any
+/// partitions they are missing or that changed since they received them (`ids`). Every response lists all partitions
+/// of the leader, which allows followers to find those and to remove the partitions that the leader removed.
+pub struct ChangeSet {
+    /// Table, id and number of deleted rows of every partition of the leader.
+    pub live: Vec<(String, PartitionID, usize)>,
+    pub partitions: Vec<ReplicatedPartition>,
+}
+
+pub struct ReplicatedPartition {
+    pub id: PartitionID,
+    pub table: String,
+    pub columns: Vec<Arc<Column>>,
+    /// Indices of the rows that were deleted on the leader.
+    pub deleted: Vec<usize>,
+}
+
+impl ChangeSet {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.write(&mut data).unwrap();
+        data
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<ChangeSet, String> {
+        let mut reader = Cursor::new(data);
+        let live_count = reader.read_u64::<BigEndian>().map_err(|e| e.to_string())?;
+        let mut live = Vec::with_capacity(live_count as usize);
+        for _ in 0..live_count {
+            let table = String::from_utf8(read_section(&mut reader)?).map_err(|e| e.to_string())?;
+            let id = reader.read_u64::<BigEndian>().map_err(|e| e.to_string())?;
+            let deleted = reader.read_u64::<BigEndian>().map_err(|e| e.to_string())?;
+            live.push((table, id, deleted as usize));
+        }
+        let partition_count = reader.read_u64::<BigEndian>().map_err(|e| e.to_string())?;
+        let mut partitions = Vec::with_capacity(partition_count as usize);
+        for _ in 0..partition_count {
+            let id = reader.read_u64::<BigEndian>().map_err(|e| e.to_string())?;
+            let meta_data = deserialize_meta_data(&read_section(&mut reader)?, id);
+            let mut columns = Vec::with_capacity(meta_data.columns.len());
+            for _ in 0..meta_data.columns.len() {
+                columns.push(Arc::new(deserialize_column(&read_section(&mut reader)?)));
+            }
+            let deleted_count = reader.read_u64::<BigEndian>().map_err(|e| e.to_string())?;
+            let mut deleted = Vec::with_capacity(deleted_count as usize);
+            for _ in 0..deleted_count {
+                deleted.push(reader.read_u64::<BigEndian>().map_err(|e| e.to_string())? as usize);
+            }
+            partitions.push(ReplicatedPartition { id, table: meta_data.tablename, columns, deleted });
+        }
+        Ok(ChangeSet { live, partitions })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), String> {
+        writer.write_u64::<BigEndian>(self.live.len() as u64).map_err(|e| e.to_string())?;
+        for &(ref table, id, deleted) in &self.live {
+            write_section(writer, table.as_bytes())?;
+            writer.write_u64::<BigEndian>(id).map_err(|e| e.to_string())?;
+            writer.write_u64::<BigEndian>(deleted as u64).map_err(|e| e.to_string())?;
+        }
+        writer.write_u64::<BigEndian>(self.partitions.len() as u64).map_err(|e| e.to_string())?;
+        for partition in &self.partitions {
+            writer.write_u64::<BigEndian>(partition.id).map_err(|e| e.to_string())?;
+            write_section(writer, &serialize_meta_data(&partition.table, &partition.columns))?;
+            for column in &partition.columns {
+                write_section(writer, &serialize_column(column))?;
+            }
+            writer.write_u64::<BigEndian>(partition.deleted.len() as u64).map_err(|e| e.to_string())?;
+            for &index in &partition.deleted {
+                writer.write_u64::<BigEndian>(index as u64).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the tables of a database in sync with those of a leader, to serve reads as a warm standby.
+///
+/// Only sealed partitions are replicated, so rows that are buffered by the leader become visible on the follower once
+/// they are sealed. Tables whose name starts with `_meta_` are not replicated. The follower must not ingest rows
+/// itself, since its partitions take the ids of the partitions of the leader.
+pub struct Follower {
+    leader: String,
+    locustdb: Arc<LocustDB>,
+    /// Largest id of a partition received from the leader.
+    last_partition: PartitionID,
+}
+
+impl Follower {
+    /// The leader is given by the address its server listens on, e.g. `10.0.0.1:8080`.
+    pub fn new(leader: &str, locustdb: Arc<LocustDB>) -> Follower {
+        Follower { leader: leader.to_string(), locustdb, last_partition: 0 }
+    }
+
+    /// Applies all changes of the leader since the last synchronization and returns the number of partitions received.
+    pub fn sync(&mut self) -> Result<usize, String> {
+        let changes = self.fetch(self.last_partition, &[])?;
+        // Partitions may be sealed out of order, and rows of received partitions may have been deleted since
+        let stale = self.locustdb.stale_replicas(&changes.live, self.last_partition);
+        let mut received = self.apply(changes);
+        if !stale.is_empty() {
+            let changes = self.fetch(PartitionID::max_value(), &stale)?;
+            received += self.apply(changes);
+        }
+        Ok(received)
+    }
+
+    /// Synchronizes every `interval` on a new thread until the process exits.
+    pub fn spawn(mut self, interval: Duration) -> thread::JoinHandle<()> {
+        thread::spawn(move || loop {
+            if let Err(err) = self.sync() {
+                warn!("Failed to replicate from {}: {}", self.leader, err);
+            }
+            thread::sleep(interval);
+        })
+    }
+
+    fn apply(&mut self, changes: ChangeSet) -> usize {
+        let received = changes.partitions.len();
+        if let Some(max_id) = changes.partitions.iter().map(|partition| partition.id).max() {
+            if max_id > self.last_partition {
+                self.last_partition = max_id;
+            }
+        }
+        self.locustdb.apply_replicated_changes(changes);
+        received
+    }
+
+    fn fetch(&self, after: PartitionID, ids: &[PartitionID]) -> Result<ChangeSet, String> {
+        let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+        let path = format!("/replication?after={}&ids={}", after, ids);
+        let response = get(&self.leader, &path).map_err(|err| format!("{}: {}", self.leader, err))?;
+        let end_of_headers = match response.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(end_of_headers) => end_of_headers,
+            None => return Err(format!("{}: Malformed response", self.leader)),
+        };
+        let body = &response[end_of_headers + 4..];
+        if !response.starts_with(b"HTTP/1.0 200") && !response.starts_with(b"HTTP/1.1 200") {
+            return Err(format!("{}: {}", self.leader, String::from_utf8_lossy(body)));
+        }
+        ChangeSet::deserialize(body)
+    }
+}
+
+fn get(addr: &str, path: &str) -> ::std::io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(addr)?;
+    // Responses to HTTP/1.0 requests are not chunked and end when the connection is closed
+    write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, addr)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    Ok(response)
+}
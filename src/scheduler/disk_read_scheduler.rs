@@ -125,7 +125,7 @@ impl DiskReadScheduler {
                             handle.update_size_bytes(column.heap_size_of_children());
                         }
                     }
-                    self.lru.touch(&handle.key());
+                    self.lru.touch(handle.id());
                     return column.clone();
                 } else {
                     debug!("{}.{} was not resident!", handle.name(), handle.id());
@@ -147,7 +147,7 @@ impl DiskReadScheduler {
                 };
                 // Need to hold lock when we put new value into lru
                 let mut maybe_column = handle.try_get();
-                self.lru.put(handle.key().clone());
+                self.lru.put(handle.id());
                 #[cfg(feature = "enable_lz4")] {
                     if self.lz4_decode {
                         column.lz4_decode();
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use disk_store::interface::*;
+use mem_store::partition::Partition;
+
+/// Defers deleting partitions that were removed from their table until no running query can still reference them.
+///
+/// Queries pin the current epoch before they take a snapshot of their table and unpin it once they complete.
+/// Removing partitions advances the epoch, so queries that pinned a later epoch can't have seen them, and the
+/// partitions are deleted once all queries that pinned the epoch they were removed in or an earlier one completed.
+pub struct Epochs {
+    state: Mutex<EpochState>,
+    storage: Arc<DiskStore>,
+}
+
+struct EpochState {
+    current: u64,
+    /// Number of running queries that pinned each epoch.
+    pinned: BTreeMap<u64, usize>,
+    /// Partitions removed from their table together with the epoch they were removed in.
+    retired: Vec<(u64, Arc<Partition>)>,
+}
+
+/// Keeps the epoch it was created in pinned until it is dropped.
+pub struct EpochGuard {
+    epochs: Arc<Epochs>,
+    epoch: u64,
+}
+
+impl Epochs {
+    pub fn new(storage: Arc<DiskStore>) -> Epochs {
+        Epochs {
+            state: Mutex::new(EpochState { current: 0, pinned: BTreeMap::new(), retired: Vec::new() }),
+            storage,
+        }
+    }
+
+    pub fn pin(epochs: &Arc<Epochs>) -> EpochGuard {
+        let mut state = epochs.state.lock().unwrap();
+        let epoch = state.current;
+        *state.pinned.entry(epoch).or_insert(0) += 1;
+        EpochGuard { epochs: epochs.clone(), epoch }
+    }
+
+    /// Deletes `partitions`, which must already have been removed from their table, once no query references them.
+    pub fn retire(&self, partitions: Vec<Arc<Partition>>) {
+        let reclaimable = {
+            let mut state = self.state.lock().unwrap();
+            let epoch = state.current;
+            state.current += 1;
+            state.retired.extend(partitions.into_iter().map(|partition| (epoch, partition)));
+            state.reclaimable()
+        };
+        self.reclaim(reclaimable);
+    }
+
+    /// Number of partitions that are waiting for queries to complete before they are deleted.
+    pub fn retired_count(&self) -> usize {
+        self.state.lock().unwrap().retired.len()
+    }
+
+    fn unpin(&self, epoch: u64) {
+        let reclaimable = {
+            let mut state = self.state.lock().unwrap();
+            let remaining = {
+                let count = state.pinned.get_mut(&epoch).unwrap();
+                *count -= 1;
+                *count
+            };
+            if remaining == 0 {
+                state.pinned.remove(&epoch);
+            }
+            state.reclaimable()
+        };
+        self.reclaim(reclaimable);
+    }
+
+    fn reclaim(&self, partitions: Vec<Arc<Partition>>) {
+        for partition in partitions {
+            partition.release();
+            let column_names = partition.col_names().iter().map(|name| name.to_string()).collect::<Vec<_>>();
+            self.storage.delete_partition(partition.id(), &column_names);
+        }
+    }
+}
+
+impl EpochState {
+    /// Removes and returns the retired partitions that no running query can reference.
+    fn reclaimable(&mut self) -> Vec<Arc<Partition>> {
+        let oldest_pinned = self.pinned.keys().next().cloned().unwrap_or(self.current);
+        let (reclaimable, retired) = self.retired.drain(..).partition(|&(epoch, _)| epoch < oldest_pinned);
+        self.retired = retired;
+        reclaimable.into_iter().map(|(_, partition)| partition).collect()
+    }
+}
+
+impl Drop for EpochGuard {
+    fn drop(&mut self) {
+        self.epochs.unpin(self.epoch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disk_store::noop_storage::NoopStorage;
+    use mem_store::*;
+
+    fn partition(id: PartitionID) -> Arc<Partition> {
+        Arc::new(Partition::new(id, vec![Arc::new(Column::null("x", 3))], LRU::default()))
+    }
+
+    #[test]
+    fn test_reclaim_after_unpin() {
+        let epochs = Arc::new(Epochs::new(Arc::new(NoopStorage)));
+        epochs.retire(vec![partition(0)]);
+        assert_eq!(epochs.retired_count(), 0);
+
+        let old_query = Epochs::pin(&epochs);
+        epochs.retire(vec![partition(1)]);
+        let new_query = Epochs::pin(&epochs);
+        epochs.retire(vec![partition(2)]);
+        assert_eq!(epochs.retired_count(), 2);
+
+        drop(old_query);
+        assert_eq!(epochs.retired_count(), 1);
+        drop(new_query);
+        assert_eq!(epochs.retired_count(), 0);
+    }
+}
@@ -1,32 +1,90 @@
-use std::collections::{HashMap, VecDeque};
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::str;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures_core::*;
 use futures_channel::oneshot;
 use heapsize::HeapSizeOf;
 use time;
 
+use ::QueryError;
+use access_control::{AccessPolicy, ColumnAccess};
+use disk_store::catalog::{Catalog, GeneratedEntry, PartitionEntry, TableEntry};
 use disk_store::interface::*;
+use engine::data_types::BasicType;
+use engine::execution::{ResultCache, SubresultCache};
+use engine::scan;
+use ingest::buffer::{Buffer, LOADER_PARTITION_ROWS};
+#[cfg(feature = "colgen")]
 use ingest::colgen::GenTable;
 use ingest::input_column::InputColumn;
 use ingest::raw_val::RawVal;
+use ingest::list;
+use ingest::schema::{ColumnType, Schema};
+#[cfg(feature = "replication")]
+use replication::{ChangeSet, ReplicatedPartition};
 use locustdb::Options;
 use mem_store::*;
 use mem_store::partition::Partition;
+use metrics::Metrics;
+use query_log::QueryLog;
+use mem_store::system_tables;
 use mem_store::table::*;
 use scheduler::*;
 use scheduler::disk_read_scheduler::DiskReadScheduler;
+use scheduler::epochs::{EpochGuard, Epochs};
+use scheduler::topology::{self, Topology};
+use syntax::expression::Expr;
+use syntax::parser;
+use syntax::statement::{GeneratedColumn, Statement};
 use trace::*;
+use udf::{FunctionRegistry, ScalarFunction};
 
 
+const MAINTENANCE_INTERVAL_SECS: u64 = 60;
+
 pub struct InnerLocustDB {
     tables: RwLock<HashMap<String, Table>>,
+    /// Column types declared with `CREATE TABLE`.
+    schemas: RwLock<HashMap<String, Schema>>,
+    /// Held while deleting rows, to prevent concurrent deletions from being lost when a partition is compacted.
+    deletion_lock: Mutex<()>,
+    /// For each table, the integer column that holds the timestamp of rows (in seconds) and the age at which rows are
+    /// dropped.
+    retention: RwLock<HashMap<String, (String, Duration)>>,
+    /// Columns of each table for which partitions have bloom filters.
+    bloom_filter_columns: RwLock<HashMap<String, HashSet<String>>>,
+    /// Compression of the in-memory column data of each table.
+    block_compression: RwLock<HashMap<String, BlockCompression>>,
+    /// Columns of each table that are computed from its other columns, in the order in which they were added.
+    generated_columns: RwLock<HashMap<String, Vec<GeneratedColumn>>>,
+    /// Dictionaries shared by all partitions of string columns of each table.
+    shared_dictionaries: RwLock<HashMap<String, HashMap<String, Arc<SharedDictionary>>>>,
+    /// SQL of the query that defines each view.
+    views: RwLock<HashMap<String, String>>,
+    /// File that table and view definitions and partition manifests are persisted to.
+    catalog_path: Option<PathBuf>,
+    /// Held while the catalog is written.
+    catalog_lock: Mutex<()>,
+    /// Whether partitions were added or removed since the catalog was last written.
+    catalog_dirty: AtomicBool,
+    access_policy: RwLock<AccessPolicy>,
+    functions: RwLock<FunctionRegistry>,
+    /// Results of recent queries, invalidated by changes to the table they were computed from.
+    result_cache: Arc<ResultCache>,
+    /// Results of aggregations on individual partitions.
+    subresult_cache: Arc<SubresultCache>,
+    metrics: Metrics,
+    query_log: QueryLog,
     lru: LRU,
+    /// Partitions removed from their table that are deleted once no running query references them.
+    epochs: Arc<Epochs>,
     pub storage: Arc<DiskStore>,
     disk_read_scheduler: Arc<DiskReadScheduler>,
 
@@ -36,12 +94,16 @@ pub struct InnerLocustDB {
     running: AtomicBool,
     idle_queue: Condvar,
     task_queue: Mutex<VecDeque<Arc<TaskState>>>,
+    /// Highest priority of the queued tasks that can be started but haven't been picked up by a worker thread yet.
+    pending_priority: Arc<PendingPriority>,
 }
 
 struct TaskState {
     trace_builder: RwLock<Option<TraceBuilder>>,
     trace_sender: SharedSender<Trace>,
     task: Box<Task>,
+    /// Whether a worker thread has started executing the task.
+    started: AtomicBool,
 }
 
 impl Drop for TaskState {
@@ -54,7 +116,13 @@ impl Drop for TaskState {
 impl InnerLocustDB {
     pub fn new(storage: Arc<DiskStore>, opts: &Options) -> InnerLocustDB {
         let lru = LRU::default();
-        let existing_tables = Table::load_table_metadata(1 << 20, storage.as_ref(), &lru);
+        let mut existing_tables = Table::load_table_metadata(opts.partition_size_rows, storage.as_ref(), &lru);
+        // Rows that were not part of a stored partition yet are recovered from the write-ahead log
+        for WalEntry { seq, tablename, row } in storage.load_wal() {
+            existing_tables.entry(tablename.clone())
+                .or_insert_with(|| Table::new(opts.partition_size_rows, &tablename, lru.clone()))
+                .recover(row, seq);
+        }
         let max_pid = existing_tables.iter().map(|(_, t)| t.max_partition_id()).max().unwrap_or(0);
         let disk_read_scheduler = Arc::new(
             DiskReadScheduler::new(storage.clone(),
@@ -62,9 +130,29 @@ impl InnerLocustDB {
                                    opts.read_threads,
                                    !opts.mem_lz4));
 
-        InnerLocustDB {
+        let catalog_path = opts.catalog_path.as_ref().map(PathBuf::from)
+            .or_else(|| opts.db_path.as_ref().map(|db_path| Path::new(db_path).join("catalog")));
+        let locustdb = InnerLocustDB {
             tables: RwLock::new(existing_tables),
+            schemas: RwLock::new(HashMap::new()),
+            deletion_lock: Mutex::new(()),
+            retention: RwLock::new(HashMap::new()),
+            bloom_filter_columns: RwLock::new(HashMap::new()),
+            block_compression: RwLock::new(HashMap::new()),
+            generated_columns: RwLock::new(HashMap::new()),
+            shared_dictionaries: RwLock::new(HashMap::new()),
+            views: RwLock::new(HashMap::new()),
+            catalog_path,
+            catalog_lock: Mutex::new(()),
+            catalog_dirty: AtomicBool::new(false),
+            access_policy: RwLock::new(AccessPolicy::default()),
+            functions: RwLock::new(FunctionRegistry::default()),
+            result_cache: Arc::new(ResultCache::new(opts.result_cache_bytes)),
+            subresult_cache: Arc::new(SubresultCache::new(opts.subresult_cache_bytes)),
+            metrics: Metrics::default(),
+            query_log: QueryLog::new(opts.query_log_size),
             lru,
+            epochs: Arc::new(Epochs::new(storage.clone())),
             storage,
             disk_read_scheduler,
             running: AtomicBool::new(true),
@@ -74,16 +162,149 @@ impl InnerLocustDB {
             next_partition_id: AtomicUsize::new(max_pid as usize + 1),
             idle_queue: Condvar::new(),
             task_queue: Mutex::new(VecDeque::new()),
+            pending_priority: Arc::new(PendingPriority::default()),
+        };
+        locustdb.restore_catalog();
+        locustdb
+    }
+
+    /// Creates the tables of the catalog that have no partitions in storage and restores their declared column types,
+    /// retention, generated columns and views.
+    fn restore_catalog(&self) {
+        let catalog = match self.catalog_path {
+            Some(ref path) => match Catalog::read(path) {
+                Ok(catalog) => catalog,
+                Err(err) => {
+                    warn!("{}", err);
+                    return;
+                }
+            },
+            None => return,
+        };
+        self.views.write().unwrap().extend(catalog.views);
+        for (name, entry) in catalog.tables {
+            let missing = {
+                let mut tables = self.tables.write().unwrap();
+                let table = tables.entry(name.clone())
+                    .or_insert_with(|| Table::new(self.opts.partition_size_rows, &name, self.lru.clone()));
+                let stored = table.snapshot().iter().map(|partition| partition.id()).collect::<HashSet<_>>();
+                entry.partitions.iter().filter(|partition| !stored.contains(&partition.id)).count()
+            };
+            if missing > 0 {
+                warn!("{} partitions of table {} in the catalog are missing from storage", missing, name);
+            }
+            if !entry.columns.is_empty() {
+                let schema = entry.columns.iter()
+                    .fold(Schema::default(), |schema, &(ref column, schema_column)| schema.with_column(column, schema_column));
+                self.schemas.write().unwrap().insert(name.clone(), schema);
+            }
+            if let Some((column, max_age)) = entry.retention {
+                self.retention.write().unwrap().insert(name.clone(), (column, Duration::from_secs(max_age)));
+            }
+            for generated in entry.generated {
+                let statement = format!("ALTER TABLE {} ADD COLUMN {} AS {} {}", name, generated.name, generated.sql,
+                                        if generated.stored { "STORED" } else { "VIRTUAL" });
+                let restored = match parser::parse_statement(&statement) {
+                    Ok(Statement::AddColumn { column, .. }) => self.functions().resolve_expr(column.expr.clone())
+                        .map_err(|err| err.to_string())
+                        .and_then(|expr| self.push_generated_column(&name, GeneratedColumn { expr, ..column })),
+                    Ok(_) => Err(format!("Unexpected statement {}", statement)),
+                    Err(err) => Err(err.to_string()),
+                };
+                if let Err(err) = restored {
+                    warn!("Failed to restore generated column {}.{}: {}", name, generated.name, err);
+                }
+            }
+        }
+    }
+
+    /// Writes the definitions and partitions of all tables and the definitions of views to the catalog.
+    fn save_catalog(&self) {
+        let path = match self.catalog_path {
+            Some(ref path) if !self.opts.read_only => path,
+            _ => return,
+        };
+        let _saving = self.catalog_lock.lock().unwrap();
+        self.catalog_dirty.store(false, Ordering::SeqCst);
+        let mut catalog = Catalog::default();
+        for (name, table) in self.tables.read().unwrap().iter() {
+            if name.starts_with("_meta_") {
+                continue;
+            }
+            let partitions = table.snapshot().iter()
+                .filter(|partition| !partition.is_open())
+                .map(|partition| PartitionEntry {
+                    id: partition.id(),
+                    len: partition.len(),
+                    columns: partition.col_names().into_iter().map(str::to_string).collect(),
+                })
+                .collect();
+            catalog.tables.insert(name.clone(), TableEntry { partitions, ..TableEntry::default() });
+        }
+        for (name, schema) in self.schemas.read().unwrap().iter() {
+            if let Some(entry) = catalog.tables.get_mut(name) {
+                entry.columns = schema.columns();
+            }
+        }
+        for (name, &(ref column, max_age)) in self.retention.read().unwrap().iter() {
+            if let Some(entry) = catalog.tables.get_mut(name) {
+                entry.retention = Some((column.clone(), max_age.as_secs()));
+            }
+        }
+        for (name, columns) in self.generated_columns.read().unwrap().iter() {
+            if let Some(entry) = catalog.tables.get_mut(name) {
+                entry.generated = columns.iter()
+                    .map(|column| GeneratedEntry { name: column.name.clone(), sql: column.sql.clone(), stored: column.stored })
+                    .collect();
+            }
+        }
+        catalog.views = self.views.read().unwrap().iter()
+            .map(|(name, sql)| (name.clone(), sql.clone()))
+            .collect();
+        if let Err(err) = catalog.write(path) {
+            warn!("{}", err);
+        }
+    }
+
+    /// Writes the catalog if partitions were added or removed since it was last written. Partition changes are only
+    /// persisted by this, so that sealing a partition does not rewrite the catalog.
+    pub fn save_dirty_catalog(&self) {
+        if self.catalog_dirty.load(Ordering::SeqCst) {
+            self.save_catalog();
         }
     }
 
     pub fn start_worker_threads(locustdb: &Arc<InnerLocustDB>) {
+        // With zero worker threads all tasks are run inline by `schedule`, which is the only mode
+        // supported on targets without threads (e.g. wasm32-unknown-unknown).
+        if locustdb.opts.threads == 0 {
+            return;
+        }
+        let topology = if locustdb.opts.pin_threads { Some(Topology::detect()) } else { None };
         for id in 0..locustdb.opts.threads {
             let cloned = locustdb.clone();
-            thread::spawn(move || InnerLocustDB::worker_loop(cloned, id));
+            let cpu = topology.as_ref().map(|topology| topology.worker_cpu(id));
+            thread::spawn(move || {
+                if let Some((node, cpu)) = cpu {
+                    if !topology::pin_current_thread(node, cpu) {
+                        warn!("Failed to pin worker thread {} to CPU {}", id, cpu);
+                    }
+                }
+                InnerLocustDB::worker_loop(cloned, id)
+            });
         }
         let cloned = locustdb.clone();
-        thread::spawn(move || InnerLocustDB::enforce_mem_limit(&cloned));
+        thread::spawn(move || InnerLocustDB::enforce_mem_limit_periodically(&cloned));
+        let cloned = locustdb.clone();
+        thread::spawn(move || InnerLocustDB::flush_periodically(&cloned));
+        let cloned = locustdb.clone();
+        thread::spawn(move || InnerLocustDB::maintain_periodically(&cloned));
+    }
+
+    /// Keeps partitions that are removed from their table from being deleted until the returned guard is dropped.
+    /// Must be called before taking the snapshot of partitions that are queried.
+    pub fn pin_epoch(&self) -> EpochGuard {
+        Epochs::pin(&self.epochs)
     }
 
     pub fn snapshot(&self, table: &str) -> Option<Vec<Arc<Partition>>> {
@@ -91,6 +312,18 @@ impl InnerLocustDB {
         tables.get(table).map(|t| t.snapshot())
     }
 
+    /// Snapshot of `table` together with its current version.
+    pub fn versioned_snapshot(&self, table: &str) -> Option<(Vec<Arc<Partition>>, usize)> {
+        let tables = self.tables.read().unwrap();
+        tables.get(table).map(|t| t.versioned_snapshot())
+    }
+
+    /// Contents of the system table `table`, `None` if there is no system table with that name.
+    pub fn system_table(&self, table: &str) -> Option<Vec<Arc<Partition>>> {
+        let tables = self.tables.read().unwrap();
+        system_tables::snapshot(table, &tables, &self.query_log)
+    }
+
     pub fn full_snapshot(&self) -> Vec<Vec<Arc<Partition>>> {
         let tables = self.tables.read().unwrap();
         tables.values().map(|t| t.snapshot()).collect()
@@ -102,77 +335,413 @@ impl InnerLocustDB {
         let _ = self.task_queue.lock();
         self.running.store(false, Ordering::SeqCst);
         self.idle_queue.notify_all();
+        self.save_dirty_catalog();
     }
 
     fn worker_loop(locustdb: Arc<InnerLocustDB>, thread_id: usize) {
         while locustdb.running.load(Ordering::SeqCst) {
             if let Some(task) = InnerLocustDB::await_task(&locustdb) {
-                if let Some(ref tb) = *task.trace_builder.read().unwrap() {
-                    tb.activate();
-                }
-                {
-                    trace_start!("Worker thread {}", thread_id);
-                    task.task.execute();
-                }
-                if let Some(ref mut tb) = *task.trace_builder.write().unwrap() {
-                    tb.collect();
-                }
+                InnerLocustDB::execute_task(&task, thread_id);
             }
         }
         drop(locustdb) // Make clippy happy
     }
 
+    fn execute_task(task: &TaskState, thread_id: usize) {
+        if let Some(ref tb) = *task.trace_builder.read().unwrap() {
+            tb.activate();
+        }
+        {
+            trace_start!("Worker thread {}", thread_id);
+            task.task.execute();
+        }
+        if let Some(ref mut tb) = *task.trace_builder.write().unwrap() {
+            tb.collect();
+        }
+    }
+
     fn await_task(ldb: &Arc<InnerLocustDB>) -> Option<Arc<TaskState>> {
         let mut task_queue = ldb.task_queue.lock().unwrap();
-        while task_queue.is_empty() {
+        loop {
+            task_queue.retain(|task| !task.task.completed());
+            let next = ldb.next_task(&mut task_queue);
+            if let Some(task) = next {
+                if !task_queue.is_empty() {
+                    ldb.idle_queue.notify_one();
+                }
+                return Some(task);
+            }
             if !ldb.running.load(Ordering::SeqCst) { return None; }
             task_queue = ldb.idle_queue.wait(task_queue).unwrap();
         }
-        while let Some(task) = task_queue.pop_front() {
-            if task.task.completed() {
-                continue;
-            }
-            if task.task.multithreaded() {
-                task_queue.push_front(task.clone());
+    }
+
+    /// Removes the queued task with the highest priority that may be started from the queue, or the earliest one if
+    /// there are several. Multithreaded tasks remain queued so that other threads can join them.
+    fn next_task(&self, task_queue: &mut VecDeque<Arc<TaskState>>) -> Option<Arc<TaskState>> {
+        let running_queries = InnerLocustDB::running_queries(task_queue);
+        let mut next: Option<(usize, Priority)> = None;
+        for (i, task) in task_queue.iter().enumerate() {
+            let priority = task.task.priority();
+            if self.may_start(task, running_queries) && next.map_or(true, |(_, max)| priority > max) {
+                next = Some((i, priority));
             }
-            if !task_queue.is_empty() {
-                ldb.idle_queue.notify_one();
+        }
+        let task = match next {
+            Some((i, _)) if task_queue[i].task.multithreaded() => task_queue[i].clone(),
+            Some((i, _)) => task_queue.remove(i).unwrap(),
+            None => {
+                self.update_pending_priority(task_queue);
+                return None;
             }
-            return Some(task);
         };
-        None
+        task.started.store(true, Ordering::SeqCst);
+        self.update_pending_priority(task_queue);
+        Some(task)
+    }
+
+    fn update_pending_priority(&self, task_queue: &VecDeque<Arc<TaskState>>) {
+        let running_queries = InnerLocustDB::running_queries(task_queue);
+        let pending = task_queue.iter()
+            .filter(|task| !task.started.load(Ordering::SeqCst) && self.may_start(task, running_queries))
+            .map(|task| task.task.priority())
+            .max();
+        self.pending_priority.set(pending);
+    }
+
+    /// Queries are the only multithreaded tasks, the number that are executed at the same time may be limited.
+    fn may_start(&self, task: &TaskState, running_queries: usize) -> bool {
+        !task.task.multithreaded()
+            || task.started.load(Ordering::SeqCst)
+            || self.opts.max_concurrent_queries.map_or(true, |max| running_queries < max)
+    }
+
+    fn running_queries(task_queue: &VecDeque<Arc<TaskState>>) -> usize {
+        task_queue.iter()
+            .filter(|task| task.task.multithreaded() && task.started.load(Ordering::SeqCst))
+            .count()
     }
 
     pub fn schedule<T: Task + 'static>(&self, task: T) -> impl Future<Item=Trace, Error=oneshot::Canceled> {
+        self.schedule_traced(task, start_toplevel("schedule"))
+    }
+
+    /// Schedules `task` and adds the spans recorded while executing it to `trace_builder`.
+    pub fn schedule_traced<T: Task + 'static>(&self, task: T, trace_builder: TraceBuilder)
+                                              -> impl Future<Item=Trace, Error=oneshot::Canceled> {
         // This function may be entered by event loop thread so it's important it always returns quickly.
         // Since the task queue locks are never held for long, we should be fine.
-        let trace_builder = RwLock::new(Some(start_toplevel("schedule")));
+        let trace_builder = RwLock::new(Some(trace_builder));
         let (trace_sender, trace_receiver) = oneshot::channel();
-        let mut task_queue = self.task_queue.lock().unwrap();
-        task_queue.push_back(Arc::new(TaskState {
+        let task = Arc::new(TaskState {
             trace_sender: SharedSender::new(trace_sender),
             trace_builder,
             task: Box::new(task),
-        }));
-        self.idle_queue.notify_one();
+            started: AtomicBool::new(false),
+        });
+        if self.opts.threads == 0 {
+            // No worker threads, run task to completion on the calling thread.
+            // Multithreaded tasks process all of their work items when only executed once.
+            InnerLocustDB::execute_task(&task, 0);
+        } else {
+            let mut task_queue = self.task_queue.lock().unwrap();
+            task_queue.push_back(task);
+            self.update_pending_priority(&task_queue);
+            self.idle_queue.notify_one();
+        }
         trace_receiver
     }
 
     pub fn store_partition(&self, tablename: &str, partition: Vec<Arc<Column>>) {
         self.create_if_empty(tablename);
-        let tables = self.tables.read().unwrap();
-        let table = tables.get(tablename).unwrap();
+        let partition = self.add_stored_columns(tablename, partition);
         let pid = self.next_partition_id.fetch_add(1, Ordering::SeqCst) as u64;
         self.storage.store_partition(pid, tablename, &partition);
-        let (new_partition, keys) = Partition::new(pid, partition, self.lru.clone());
-        table.load_partition(new_partition);
-        for key in keys { self.lru.put(key); }
+        self.record_ingestion(&partition);
+        self.load_partition(tablename, pid, partition);
+        self.catalog_dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Stores `partitions` and adds them to `tablename` at once, so that queries see either none or all of them.
+    pub fn store_partitions(&self, tablename: &str, partitions: Vec<Vec<Arc<Column>>>) {
+        if partitions.is_empty() {
+            return;
+        }
+        self.create_if_empty(tablename);
+        let mut loaded = Vec::with_capacity(partitions.len());
+        for partition in partitions {
+            let partition = self.add_stored_columns(tablename, partition);
+            let pid = self.next_partition_id.fetch_add(1, Ordering::SeqCst) as u64;
+            self.storage.store_partition(pid, tablename, &partition);
+            self.record_ingestion(&partition);
+            loaded.push(self.new_partition(tablename, pid, partition));
+        }
+        let pids = loaded.iter().map(|partition| partition.id()).collect::<Vec<_>>();
+        self.tables.read().unwrap().get(tablename).unwrap().load_partitions(loaded);
+        for pid in pids {
+            self.lru.put(pid);
+        }
+        self.catalog_dirty.store(true, Ordering::SeqCst);
     }
 
     pub fn ingest(&self, table: &str, row: Vec<(String, RawVal)>) {
+        if let Some((buffer, wal_seq)) = self.buffer_row(table, row) {
+            self.store_buffer(table, buffer, wal_seq);
+        }
+    }
+
+    /// Appends `row` to the open partition of `table`, which is returned once it is full and has to be stored.
+    pub fn buffer_row(&self, table: &str, row: Vec<(String, RawVal)>) -> Option<(Arc<Buffer>, WalSeq)> {
         self.create_if_empty(table);
         let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest(row)
+        tables.get(table).unwrap().ingest(row, self.storage.as_ref())
+    }
+
+    /// Turns the rows buffered by `table` into a partition.
+    pub fn flush_table(&self, table: &str) {
+        let full_buffer = {
+            let tables = self.tables.read().unwrap();
+            tables.get(table).and_then(|t| t.take_buffer())
+        };
+        if let Some((buffer, wal_seq)) = full_buffer {
+            self.store_buffer(table, buffer, wal_seq);
+        }
+    }
+
+    /// Turns the rows buffered by all tables into partitions, which compresses them and removes them from the
+    /// write-ahead log.
+    pub fn flush(&self) {
+        let buffers = {
+            let tables = self.tables.read().unwrap();
+            tables.iter()
+                .filter_map(|(name, table)| table.take_buffer().map(|(buffer, wal_seq)| (name.clone(), buffer, wal_seq)))
+                .collect::<Vec<_>>()
+        };
+        for (tablename, buffer, wal_seq) in buffers {
+            self.store_buffer(&tablename, buffer, wal_seq);
+        }
+        self.save_dirty_catalog();
+    }
+
+    /// Stores the rows of a buffer taken from `tablename` as a partition, which replaces the open partition of the rows.
+    pub fn store_buffer(&self, tablename: &str, buffer: Arc<Buffer>, wal_seq: WalSeq) {
+        let partition = self.add_stored_columns(tablename, buffer.columns());
+        let pid = self.next_partition_id.fetch_add(1, Ordering::SeqCst) as u64;
+        self.storage.store_wal_partition(pid, tablename, &partition, wal_seq);
+        self.record_ingestion(&partition);
+        let new_partition = self.new_partition(tablename, pid, partition);
+        self.tables.read().unwrap().get(tablename).unwrap().load_sealed_partition(new_partition, &buffer);
+        self.lru.put(pid);
+        self.catalog_dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Adds the stored generated columns of `tablename` to the columns of a partition that is being sealed.
+    fn add_stored_columns(&self, tablename: &str, mut partition: Vec<Arc<Column>>) -> Vec<Arc<Column>> {
+        for column in self.generated_columns(tablename).iter().filter(|column| column.stored) {
+            match scan::generated_column(&partition, &column.name, &column.expr) {
+                Ok(generated) => partition.push(generated),
+                Err(err) => warn!("Failed to compute generated column {}.{}: {}", tablename, column.name, err),
+            }
+        }
+        partition
+    }
+
+    fn record_ingestion(&self, partition: &[Arc<Column>]) {
+        let rows = partition.first().map_or(0, |column| column.len());
+        let bytes = partition.iter().map(|column| column.heap_size_of_children()).sum();
+        self.metrics.record_ingestion(rows, bytes);
+    }
+
+    fn load_partition(&self, tablename: &str, pid: PartitionID, partition: Vec<Arc<Column>>) {
+        let tables = self.tables.read().unwrap();
+        let table = tables.get(tablename).unwrap();
+        let new_partition = self.new_partition(tablename, pid, partition);
+        table.load_partition(new_partition);
+        self.lru.put(pid);
+    }
+
+    fn new_partition(&self, tablename: &str, pid: PartitionID, columns: Vec<Arc<Column>>) -> Partition {
+        // Buffered rows hold lists as JSON arrays, which are parsed once when the partition is created
+        let columns = match self.schemas.read().unwrap().get(tablename) {
+            Some(schema) => columns.into_iter()
+                .map(|column| {
+                    let is_list = schema.get(column.name()).map_or(false, |c| c.column_type == ColumnType::StringList);
+                    if !is_list || column.codec().is_list() {
+                        return column;
+                    }
+                    let lists = scan::column_values(&column).ok()
+                        .and_then(|values| values.iter()
+                            .map(|value| match *value {
+                                RawVal::Str(ref value) => list::decode(value),
+                                _ => None,
+                            })
+                            .collect::<Option<Vec<_>>>());
+                    match lists {
+                        Some(lists) => Arc::new(strings::build_list_column(column.name(), &lists)),
+                        None => column,
+                    }
+                })
+                .collect(),
+            None => columns,
+        };
+        let mut encoded_columns = Vec::new();
+        let columns = match self.shared_dictionaries.read().unwrap().get(tablename) {
+            Some(dictionaries) => columns.into_iter()
+                .map(|column| {
+                    let dictionary = dictionaries.get(column.name()).cloned();
+                    let dictionary = match dictionary {
+                        Some(dictionary) if column.basic_type() == BasicType::String && !column.codec().is_list() =>
+                            dictionary,
+                        _ => return column,
+                    };
+                    let encoded = scan::column_values(&column).ok()
+                        .and_then(|values| dictionary.encode(column.name(), &values));
+                    match encoded {
+                        Some(encoded) => {
+                            encoded_columns.push((column.name().to_string(), dictionary));
+                            Arc::new(encoded)
+                        }
+                        None => column,
+                    }
+                })
+                .collect(),
+            None => columns,
+        };
+        let bloom_filter_columns = self.bloom_filter_columns.read().unwrap().get(tablename).cloned().unwrap_or_default();
+        let mut bloom_filters = Vec::new();
+        for column in &columns {
+            if bloom_filter_columns.contains(column.name()) {
+                match scan::column_values(column) {
+                    Ok(values) => {
+                        let mut bloom_filter = BloomFilter::new(values.len());
+                        for value in &values {
+                            bloom_filter.insert(value);
+                        }
+                        bloom_filters.push((column.name().to_string(), bloom_filter));
+                    }
+                    Err(err) => warn!("Failed to build bloom filter for {}.{}: {}", tablename, column.name(), err),
+                }
+            }
+        }
+        let columns = match self.block_compression.read().unwrap().get(tablename) {
+            Some(&compression) => columns.into_iter()
+                .map(|column| match Arc::try_unwrap(column) {
+                    Ok(mut column) => {
+                        column.set_block_compression(compression);
+                        Arc::new(column)
+                    }
+                    Err(column) => column,
+                })
+                .collect(),
+            None => columns,
+        };
+        let mut partition = Partition::new(pid, columns, self.lru.clone());
+        for (name, bloom_filter) in bloom_filters {
+            partition.add_bloom_filter(&name, bloom_filter);
+        }
+        for (name, dictionary) in encoded_columns {
+            partition.add_shared_dictionary(&name, dictionary);
+        }
+        partition
+    }
+
+    pub fn set_bloom_filter_columns(&self, table: &str, columns: &[&str]) {
+        self.bloom_filter_columns.write().unwrap()
+            .insert(table.to_string(), columns.iter().map(|name| name.to_string()).collect());
+    }
+
+    pub fn set_shared_dictionary_columns(&self, table: &str, columns: &[&str]) {
+        let mut shared_dictionaries = self.shared_dictionaries.write().unwrap();
+        let dictionaries = shared_dictionaries.entry(table.to_string()).or_insert_with(HashMap::new);
+        dictionaries.retain(|name, _| columns.contains(&name.as_str()));
+        for &column in columns {
+            dictionaries.entry(column.to_string()).or_insert_with(|| Arc::new(SharedDictionary::default()));
+        }
+    }
+
+    pub fn set_block_compression(&self, table: &str, compression: BlockCompression) -> Result<(), String> {
+        compression.ensure_supported()?;
+        self.block_compression.write().unwrap().insert(table.to_string(), compression);
+        Ok(())
+    }
+
+    /// Writes all partitions of all tables to the directory at `path`, after flushing buffered rows.
+    #[cfg(feature = "enable_rocksdb")]
+    pub fn write_snapshot(&self, path: &str) -> Result<(), String> {
+        use disk_store::snapshot;
+        use std::fs;
+        use std::path::Path;
+
+        self.flush();
+        let dir = Path::new(path);
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let tables = {
+            let tables = self.tables.read().unwrap();
+            tables.iter()
+                .filter(|(name, _)| !name.starts_with("_meta_"))
+                .map(|(name, table)| (name.clone(), table.snapshot()))
+                .collect::<Vec<_>>()
+        };
+        let mut index = 0;
+        for (tablename, partitions) in tables {
+            for partition in partitions {
+                let columns = partition.columns(&self.disk_read_scheduler);
+                snapshot::write_partition(dir, index, &tablename, &columns)?;
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "enable_rocksdb"))]
+    pub fn write_snapshot(&self, _: &str) -> Result<(), String> {
+        Err("Snapshots require the `enable_rocksdb` feature".to_string())
+    }
+
+    /// Adds all partitions from the snapshot at `path` to the database.
+    #[cfg(feature = "enable_rocksdb")]
+    pub fn restore_snapshot(&self, path: &str) -> Result<(), String> {
+        use disk_store::snapshot;
+        use std::path::Path;
+
+        self.ensure_writable()?;
+        for (tablename, columns) in snapshot::read_partitions(Path::new(path))? {
+            self.store_partition(&tablename, columns);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "enable_rocksdb"))]
+    pub fn restore_snapshot(&self, _: &str) -> Result<(), String> {
+        Err("Snapshots require the `enable_rocksdb` feature".to_string())
+    }
+
+    /// Loads all partitions from the snapshot at `path` into memory without adding them to storage, so the snapshot
+    /// directory is only read and can be shared with other processes.
+    #[cfg(feature = "enable_rocksdb")]
+    pub fn load_snapshot(&self, path: &str) -> Result<(), String> {
+        use disk_store::snapshot;
+        use std::path::Path;
+
+        #[cfg(feature = "enable_mmap")]
+        let partitions = if self.opts.mmap {
+            snapshot::map_partitions(Path::new(path))?
+        } else {
+            snapshot::read_partitions(Path::new(path))?
+        };
+        #[cfg(not(feature = "enable_mmap"))]
+        let partitions = snapshot::read_partitions(Path::new(path))?;
+        for (tablename, columns) in partitions {
+            self.create_if_empty(&tablename);
+            let pid = self.next_partition_id.fetch_add(1, Ordering::SeqCst) as u64;
+            self.load_partition(&tablename, pid, columns);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "enable_rocksdb"))]
+    pub fn load_snapshot(&self, _: &str) -> Result<(), String> {
+        Err("Snapshots require the `enable_rocksdb` feature".to_string())
     }
 
     pub fn restore(&self, id: PartitionID, column: Column) {
@@ -192,13 +761,365 @@ impl InnerLocustDB {
     #[allow(dead_code)]
     pub fn ingest_heterogeneous(&self, table: &str, columns: HashMap<String, Vec<RawVal>>) {
         self.create_if_empty(table);
-        let tables = self.tables.read().unwrap();
-        tables.get(table).unwrap().ingest_heterogeneous(columns)
+        let full_buffer = {
+            let tables = self.tables.read().unwrap();
+            tables.get(table).unwrap().ingest_heterogeneous(columns)
+        };
+        if let Some((buffer, wal_seq)) = full_buffer {
+            self.store_buffer(table, buffer, wal_seq);
+        }
+    }
+
+    pub fn create_table(&self, table: &str, schema: Schema) -> Result<(), String> {
+        self.ensure_writable()?;
+        if self.tables.read().unwrap().contains_key(table) {
+            return Err(format!("Table {} already exists", table));
+        }
+        if self.views.read().unwrap().contains_key(table) {
+            return Err(format!("View {} already exists", table));
+        }
+        self.schemas.write().unwrap().insert(table.to_string(), schema);
+        self.create_if_empty(table);
+        self.save_catalog();
+        Ok(())
+    }
+
+    /// Removes `table` together with all of its partitions in memory and on disk.
+    /// Queries that are already running on the table complete before its partitions are deleted.
+    pub fn drop_table(&self, table: &str) -> Result<bool, String> {
+        self.ensure_writable()?;
+        if table.starts_with("_meta_") {
+            return Err(format!("Table {} can not be dropped", table));
+        }
+        self.schemas.write().unwrap().remove(table);
+        self.retention.write().unwrap().remove(table);
+        self.bloom_filter_columns.write().unwrap().remove(table);
+        self.block_compression.write().unwrap().remove(table);
+        self.shared_dictionaries.write().unwrap().remove(table);
+        self.generated_columns.write().unwrap().remove(table);
+        let removed = self.tables.write().unwrap().remove(table);
+        match removed {
+            Some(removed) => {
+                self.epochs.retire(removed.snapshot());
+                self.storage.delete_wal(table);
+                self.save_catalog();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Adds a view, which is queried like a table by substituting the query `sql` that defines it.
+    pub fn create_view(&self, view: &str, sql: &str) -> Result<(), String> {
+        self.ensure_writable()?;
+        if self.tables.read().unwrap().contains_key(view) {
+            return Err(format!("Table {} already exists", view));
+        }
+        {
+            let mut views = self.views.write().unwrap();
+            if views.contains_key(view) {
+                return Err(format!("View {} already exists", view));
+            }
+            views.insert(view.to_string(), sql.to_string());
+        }
+        self.save_catalog();
+        Ok(())
+    }
+
+    pub fn drop_view(&self, view: &str) -> Result<bool, String> {
+        self.ensure_writable()?;
+        let removed = self.views.write().unwrap().remove(view).is_some();
+        if removed {
+            self.save_catalog();
+        }
+        Ok(removed)
+    }
+
+    /// SQL of the query that defines `view`, `None` if there is no such view.
+    pub fn view(&self, view: &str) -> Option<String> {
+        self.views.read().unwrap().get(view).cloned()
+    }
+
+    /// Sealed partitions of all tables except system tables, together with the columns of those whose id is greater
+    /// than `after` or contained in `ids`.
+    #[cfg(feature = "replication")]
+    pub fn replication_changes(&self, after: PartitionID, ids: &HashSet<PartitionID>) -> ChangeSet {
+        let _epoch = self.pin_epoch();
+        let snapshots = {
+            let tables = self.tables.read().unwrap();
+            tables.iter()
+                .filter(|&(name, _)| !name.starts_with("_meta_"))
+                .map(|(name, table)| (name.clone(), table.snapshot()))
+                .collect::<Vec<_>>()
+        };
+        let mut changes = ChangeSet { live: Vec::new(), partitions: Vec::new() };
+        for (table, mut partitions) in snapshots {
+            partitions.sort_by_key(|partition| partition.id());
+            for partition in partitions {
+                changes.live.push((table.clone(), partition.id(), partition.deleted_rows()));
+                if partition.id() > after || ids.contains(&partition.id()) {
+                    changes.partitions.push(ReplicatedPartition {
+                        id: partition.id(),
+                        table: table.clone(),
+                        columns: partition.columns(&self.disk_read_scheduler),
+                        deleted: partition.deleted_indices(),
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// Ids of the partitions in `live` that are not greater than `after` and are missing or differ in their number of
+    /// deleted rows.
+    #[cfg(feature = "replication")]
+    pub fn stale_replicas(&self, live: &[(String, PartitionID, usize)], after: PartitionID) -> Vec<PartitionID> {
+        let replicas = self.tables.read().unwrap().values()
+            .flat_map(|table| table.snapshot())
+            .map(|partition| (partition.id(), partition.deleted_rows()))
+            .collect::<HashMap<_, _>>();
+        live.iter()
+            .filter(|&&(_, id, deleted)| id <= after && replicas.get(&id) != Some(&deleted))
+            .map(|&(_, id, _)| id)
+            .collect()
+    }
+
+    /// Adds or replaces the partitions received from the leader and removes those that the leader no longer has.
+    #[cfg(feature = "replication")]
+    pub fn apply_replicated_changes(&self, changes: ChangeSet) {
+        let mut live = HashMap::<String, HashSet<PartitionID>>::new();
+        for &(ref table, id, _) in &changes.live {
+            live.entry(table.clone()).or_insert_with(HashSet::new).insert(id);
+        }
+        for replicated in changes.partitions {
+            self.create_if_empty(&replicated.table);
+            // Partitions keep the ids they have on the leader
+            let next_id = replicated.id as usize + 1;
+            while self.next_partition_id.load(Ordering::SeqCst) < next_id {
+                let current = self.next_partition_id.load(Ordering::SeqCst);
+                self.next_partition_id.compare_and_swap(current, cmp::max(current, next_id), Ordering::SeqCst);
+            }
+            self.storage.store_partition(replicated.id, &replicated.table, &replicated.columns);
+            let mut partition = self.new_partition(&replicated.table, replicated.id, replicated.columns);
+            if !replicated.deleted.is_empty() {
+                partition = partition.with_deleted_rows(&replicated.deleted).0;
+            }
+            self.tables.read().unwrap().get(&replicated.table).unwrap().load_partition(partition);
+            self.lru.put(replicated.id);
+        }
+        let tables = self.tables.read().unwrap().keys().cloned().collect::<Vec<_>>();
+        for table in tables {
+            if table.starts_with("_meta_") {
+                continue;
+            }
+            let removed = self.snapshot(&table).unwrap_or_default().into_iter()
+                .filter(|partition| !live.get(&table).map_or(false, |ids| ids.contains(&partition.id())))
+                .collect::<Vec<_>>();
+            if !removed.is_empty() {
+                self.replace_partitions(&table, &removed, None);
+            }
+        }
+        self.save_catalog();
+    }
+
+    /// Marks all rows of `table` that satisfy `filter` as deleted and returns their number.
+    /// Partitions in which the fraction of deleted rows reaches `compaction_threshold` are rewritten without them.
+    pub fn delete(&self, table: &str, filter: &Expr) -> Result<usize, QueryError> {
+        self.ensure_writable().map_err(QueryError::PermissionDenied)?;
+        let _deleting = self.deletion_lock.lock().unwrap();
+        // Buffered rows can only be deleted once they are part of a partition
+        self.flush_table(table);
+        let _epoch = self.pin_epoch();
+        let partitions = match self.snapshot(table) {
+            Some(partitions) => partitions,
+            None => bail!(QueryError::NotImplemented, "Table {} does not exist!", table),
+        };
+        let mut deleted = 0;
+        let mut updated = Vec::new();
+        for partition in partitions {
+            let rows = scan::matching_rows(&partition, filter, &self.disk_read_scheduler)?;
+            if rows.is_empty() {
+                continue;
+            }
+            let (partition, newly_deleted) = partition.with_deleted_rows(&rows);
+            deleted += newly_deleted;
+            updated.push(Arc::new(partition));
+        }
+        // Queries see either none or all of the rows deleted by the statement
+        if let Some(t) = self.tables.read().unwrap().get(table) {
+            t.update_partitions(&updated);
+        }
+        // The rows are deleted at this point, failing to compact their partitions only leaves them in memory
+        for partition in updated {
+            if partition.deleted_rows() as f64 >= self.opts.compaction_threshold * partition.len() as f64 {
+                if let Err(err) = self.rewrite(table, &[partition]) {
+                    warn!("Failed to compact partition of {}: {}", table, err);
+                }
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Replaces `partitions` of `table` with a single partition that contains all of their rows that are not deleted.
+    /// Columns are encoded from scratch, which also rebuilds their dictionaries.
+    fn rewrite(&self, table: &str, partitions: &[Arc<Partition>]) -> Result<(), QueryError> {
+        let mut buffer = Buffer::default();
+        for partition in partitions {
+            buffer.push_typed_cols(scan::live_columns(partition, &self.disk_read_scheduler)?);
+        }
+        let replacement = if buffer.len() == 0 {
+            None
+        } else {
+            let columns = buffer.into_columns();
+            let pid = self.next_partition_id.fetch_add(1, Ordering::SeqCst) as u64;
+            self.storage.store_partition(pid, table, &columns);
+            Some(self.new_partition(table, pid, columns))
+        };
+        self.replace_partitions(table, partitions, replacement);
+        Ok(())
+    }
+
+    /// Replaces `partitions` of `table` by `replacement` in memory and removes them from storage once no running query
+    /// references them.
+    fn replace_partitions(&self,
+                          table: &str,
+                          partitions: &[Arc<Partition>],
+                          replacement: Option<Partition>) {
+        let replacement_id = replacement.as_ref().map(|partition| partition.id());
+        if let Some(t) = self.tables.read().unwrap().get(table) {
+            let ids = partitions.iter().map(|partition| partition.id()).collect::<Vec<_>>();
+            t.replace_partitions(&ids, replacement);
+        }
+        if let Some(id) = replacement_id { self.lru.put(id); }
+        self.epochs.retire(partitions.to_vec());
+        self.catalog_dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Merges runs of adjacent partitions with fewer than half of `merge_partition_rows` rows into partitions of at most
+    /// `merge_partition_rows` rows and returns the number of partitions that were removed.
+    pub fn merge_small_partitions(&self) -> usize {
+        if self.ensure_writable().is_err() {
+            return 0;
+        }
+        let max_rows = self.opts.merge_partition_rows.unwrap_or(LOADER_PARTITION_ROWS);
+        let tables = self.tables.read().unwrap().keys().cloned().collect::<Vec<_>>();
+        let _deleting = self.deletion_lock.lock().unwrap();
+        let _epoch = self.pin_epoch();
+        let mut removed = 0;
+        for table in tables {
+            let mut partitions = self.snapshot(&table).unwrap_or_default();
+            // Partition ids are increasing, so partitions with consecutive ids contain adjacent rows
+            partitions.sort_by_key(|partition| partition.id());
+            let mut runs = Vec::new();
+            let mut run = Vec::new();
+            let mut run_rows = 0;
+            for partition in partitions {
+                let rows = partition.len() - partition.deleted_rows();
+                let is_small = partition.len() * 2 < max_rows;
+                if !is_small || run_rows + rows > max_rows {
+                    runs.push(mem::replace(&mut run, Vec::new()));
+                    run_rows = 0;
+                }
+                if is_small {
+                    run_rows += rows;
+                    run.push(partition);
+                }
+            }
+            runs.push(run);
+
+            for run in runs.into_iter().filter(|run| run.len() > 1) {
+                match self.rewrite(&table, &run) {
+                    Ok(()) => removed += run.len() - 1,
+                    Err(err) => warn!("Failed to merge partitions of {}: {}", table, err),
+                }
+            }
+        }
+        removed
+    }
+
+    /// Drops partitions of `table` once all values of the integer `column` are more than `max_age` seconds in the past.
+    pub fn set_retention(&self, table: &str, column: &str, max_age: Duration) -> Result<(), String> {
+        self.ensure_writable()?;
+        self.retention.write().unwrap().insert(table.to_string(), (column.to_string(), max_age));
+        self.save_catalog();
+        Ok(())
+    }
+
+    /// Drops all partitions that are older than the retention period of their table and returns their number.
+    pub fn enforce_retention(&self) -> usize {
+        if self.ensure_writable().is_err() {
+            return 0;
+        }
+        let policies = self.retention.read().unwrap().clone();
+        let now = time::now().to_timespec().sec;
+        let _deleting = self.deletion_lock.lock().unwrap();
+        let _epoch = self.pin_epoch();
+        let mut dropped = 0;
+        for (table, (column, max_age)) in policies {
+            let cutoff = now - max_age.as_secs() as i64;
+            for partition in self.snapshot(&table).unwrap_or_default() {
+                match partition.range(&column, &self.disk_read_scheduler) {
+                    Some((_, max)) if max < cutoff => {
+                        self.replace_partitions(&table, &[partition], None);
+                        dropped += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Adds a column to `table` that is computed from its other columns. References to previously generated columns
+    /// are replaced by their expressions, so that generated columns only depend on loaded columns.
+    pub fn add_generated_column(&self, table: &str, column: GeneratedColumn) -> Result<(), String> {
+        self.ensure_writable()?;
+        match self.tables.read().unwrap().get(table) {
+            Some(existing) => if existing.snapshot().iter().any(|partition| partition.col_names().contains(&column.name.as_str())) {
+                return Err(format!("Column {} of table {} already exists", column.name, table));
+            },
+            None => return Err(format!("Table {} does not exist!", table)),
+        }
+        if self.generated_columns(table).iter().any(|generated| generated.name == column.name) {
+            return Err(format!("Column {} of table {} already exists", column.name, table));
+        }
+        self.push_generated_column(table, column)?;
+        self.save_catalog();
+        Ok(())
+    }
+
+    fn push_generated_column(&self, table: &str, column: GeneratedColumn) -> Result<(), String> {
+        let mut generated_columns = self.generated_columns.write().unwrap();
+        let generated_columns = generated_columns.entry(table.to_string()).or_insert_with(Vec::new);
+        let expr = column.expr.map_colnames(&mut |name: String| Ok(
+            match generated_columns.iter().find(|generated| generated.name == name) {
+                Some(generated) => generated.expr.clone(),
+                None => Expr::ColName(name),
+            })).map_err(|err| err.to_string())?;
+        generated_columns.push(GeneratedColumn { expr, ..column });
+        Ok(())
+    }
+
+    pub fn generated_columns(&self, table: &str) -> Vec<GeneratedColumn> {
+        self.generated_columns.read().unwrap().get(table).cloned().unwrap_or_default()
+    }
+
+    pub fn table_schema(&self, table: &str) -> Option<Schema> {
+        self.schemas.read().unwrap().get(table).cloned()
+    }
+
+    /// Converts the values of `row` to the column types declared for `table`.
+    pub fn conform(&self, table: &str, row: Vec<(String, RawVal)>) -> Result<Vec<(String, RawVal)>, String> {
+        match self.schemas.read().unwrap().get(table) {
+            Some(schema) => schema.conform(row),
+            None => Ok(row),
+        }
     }
 
     pub fn drop_pending_tasks(&self) {
         let mut task_queue = self.task_queue.lock().unwrap();
         task_queue.clear();
+        self.pending_priority.set(None);
     }
 
     pub fn mem_tree(&self, depth: usize) -> Vec<MemTreeTable> {
@@ -206,15 +1127,35 @@ impl InnerLocustDB {
         tables.values().map(|table| { table.mem_tree(depth) }).collect()
     }
 
+    pub fn mem_stats(&self) -> Vec<MemStats> {
+        let tables = self.tables.read().unwrap();
+        let mut stats = tables.values().flat_map(|table| table.mem_stats()).collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.table.cmp(&b.table));
+        stats
+    }
+
     pub fn stats(&self) -> Vec<TableStats> {
         let tables = self.tables.read().unwrap();
         tables.values().map(|table| table.stats()).collect()
     }
 
+    #[cfg(feature = "colgen")]
     pub fn gen_partition(&self, opts: &GenTable, p: u64) {
+        if let Err(err) = self.ensure_writable() {
+            warn!("Not generating partition {} of {}: {}", p, opts.name, err);
+            return;
+        }
         opts.gen(&self, p);
     }
 
+    pub fn ensure_writable(&self) -> Result<(), String> {
+        if self.opts.read_only {
+            Err("Database was opened in read-only mode".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
     fn create_if_empty(&self, table: &str) {
         let exists = {
             let tables = self.tables.read().unwrap();
@@ -225,7 +1166,7 @@ impl InnerLocustDB {
                 let mut tables = self.tables.write().unwrap();
                 tables.insert(
                     table.to_string(),
-                    Table::new(1 << 20, table, self.lru.clone()));
+                    Table::new(self.opts.partition_size_rows, table, self.lru.clone()));
             }
             self.ingest("_meta_tables", vec![
                 ("timestamp".to_string(), RawVal::Int(time::now().to_timespec().sec)),
@@ -234,31 +1175,85 @@ impl InnerLocustDB {
         }
     }
 
-    fn enforce_mem_limit(ldb: &Arc<InnerLocustDB>) {
+    /// Evicts the least recently queried partitions while tables use more than `mem_size_limit_tables` bytes and
+    /// returns their number. Evicted partitions are reloaded from disk on access, so nothing is evicted without
+    /// persistent storage.
+    pub fn enforce_mem_limit(&self) -> usize {
+        let mut mem_usage_bytes = self.mem_usage_bytes();
+        if mem_usage_bytes <= self.opts.mem_size_limit_tables || self.opts.db_path.is_none() {
+            return 0;
+        }
+        info!("Evicting. mem_usage_bytes = {}", mem_usage_bytes);
+        let mut evicted = 0;
+        while mem_usage_bytes > self.opts.mem_size_limit_tables {
+            match self.lru.evict() {
+                Some(victim) => {
+                    let tables = self.tables.read().unwrap();
+                    for t in tables.values() {
+                        mem_usage_bytes = mem_usage_bytes.saturating_sub(t.evict(victim));
+                    }
+                    evicted += 1;
+                }
+                None => {
+                    if self.opts.mem_size_limit_tables > 0 {
+                        warn!("Table memory usage is {} but failed to find partition to evict!", mem_usage_bytes);
+                    }
+                    break;
+                }
+            }
+        }
+        info!("mem_usage_bytes = {}", mem_usage_bytes);
+        evicted
+    }
+
+    fn mem_usage_bytes(&self) -> usize {
+        let tables = self.tables.read().unwrap();
+        tables.values().map(|table| table.heap_size_of_children()).sum()
+    }
+
+    fn enforce_mem_limit_periodically(ldb: &Arc<InnerLocustDB>) {
+        let mut warned = false;
         while ldb.running.load(Ordering::SeqCst) {
-            let mut mem_usage_bytes: usize = {
-                let tables = ldb.tables.read().unwrap();
-                tables.values().map(|table| table.heap_size_of_children()).sum()
-            };
-            if mem_usage_bytes > ldb.opts.mem_size_limit_tables {
-                info!("Evicting. mem_usage_bytes = {}", mem_usage_bytes);
-                while mem_usage_bytes > ldb.opts.mem_size_limit_tables {
-                    match ldb.lru.evict() {
-                        Some(victim) => {
-                            let tables = ldb.tables.read().unwrap();
-                            for t in tables.values() {
-                                mem_usage_bytes -= t.evict(&victim);
-                            }
-                        }
-                        None => {
-                            if ldb.opts.mem_size_limit_tables > 0 {
-                                warn!("Table memory usage is {} but failed to find column to evict!", mem_usage_bytes);
-                            }
-                            break;
-                        }
+            if ldb.opts.db_path.is_some() {
+                ldb.enforce_mem_limit();
+            } else if !warned && ldb.mem_usage_bytes() > ldb.opts.mem_size_limit_tables {
+                warn!("Table memory usage is {} but partitions can't be evicted without a `db_path`", ldb.mem_usage_bytes());
+                warned = true;
+            }
+            thread::sleep(Duration::from_millis(1000));
+        }
+    }
+
+    fn flush_periodically(ldb: &Arc<InnerLocustDB>) {
+        let interval = Duration::from_secs(ldb.opts.flush_interval_secs);
+        let mut last_flush = Instant::now();
+        while ldb.running.load(Ordering::SeqCst) {
+            if last_flush.elapsed() >= interval {
+                ldb.flush();
+                last_flush = Instant::now();
+            }
+            // Partitions sealed by ingestion are written to the catalog at most once per second
+            ldb.save_dirty_catalog();
+            thread::sleep(Duration::from_millis(1000));
+        }
+    }
+
+    /// Drops expired partitions and merges small partitions if `merge_partition_rows` is set.
+    fn maintain_periodically(ldb: &Arc<InnerLocustDB>) {
+        let mut last_check = Instant::now();
+        while ldb.running.load(Ordering::SeqCst) {
+            if last_check.elapsed() >= Duration::from_secs(MAINTENANCE_INTERVAL_SECS) {
+                let dropped = ldb.enforce_retention();
+                if dropped > 0 {
+                    info!("Dropped {} partitions that exceeded their retention period", dropped);
+                }
+                if ldb.opts.merge_partition_rows.is_some() {
+                    let merged = ldb.merge_small_partitions();
+                    if merged > 0 {
+                        info!("Removed {} partitions by merging small partitions", merged);
                     }
                 }
-                info!("mem_usage_bytes = {}", mem_usage_bytes);
+                last_check = Instant::now();
             }
             thread::sleep(Duration::from_millis(1000));
         }
@@ -272,6 +1267,45 @@ impl InnerLocustDB {
         &self.opts
     }
 
+    pub fn access_policy(&self) -> RwLockReadGuard<AccessPolicy> {
+        self.access_policy.read().unwrap()
+    }
+
+    pub fn set_column_access(&self, role: &str, table: &str, column: &str, access: ColumnAccess) {
+        self.access_policy.write().unwrap().set(role, table, column, access);
+        self.result_cache.clear();
+    }
+
+    pub fn functions(&self) -> RwLockReadGuard<FunctionRegistry> {
+        self.functions.read().unwrap()
+    }
+
+    pub fn register_function(&self, function: ScalarFunction) {
+        self.functions.write().unwrap().register(function);
+        self.result_cache.clear();
+        self.subresult_cache.clear();
+    }
+
+    pub fn result_cache(&self) -> &Arc<ResultCache> {
+        &self.result_cache
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn query_log(&self) -> &QueryLog {
+        &self.query_log
+    }
+
+    pub fn subresult_cache(&self) -> &Arc<SubresultCache> {
+        &self.subresult_cache
+    }
+
+    pub fn pending_priority(&self) -> &Arc<PendingPriority> {
+        &self.pending_priority
+    }
+
     pub fn disk_read_scheduler(&self) -> &Arc<DiskReadScheduler> {
         &self.disk_read_scheduler
     }
@@ -279,6 +1313,7 @@ impl InnerLocustDB {
 
 impl Drop for InnerLocustDB {
     fn drop(&mut self) {
+        self.save_dirty_catalog();
         info!("Stopped");
     }
 }
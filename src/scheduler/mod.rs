@@ -1,8 +1,10 @@
 mod shared_sender;
 mod task;
+pub(crate) mod topology;
 pub(crate) mod disk_read_scheduler;
+pub(crate) mod epochs;
 pub(crate) mod inner_locustdb;
 
 pub use self::inner_locustdb::InnerLocustDB;
-pub use self::task::Task;
+pub use self::task::{PendingPriority, Priority, Task};
 pub use self::shared_sender::SharedSender;
\ No newline at end of file
@@ -1,10 +1,41 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use futures_channel::oneshot;
 use super::SharedSender;
 
+/// Order in which worker threads pick up queued tasks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Ingestion of data.
+    Background,
+    /// Queries that have been running for a long time.
+    Bulk,
+    /// Recently started queries and all other tasks.
+    Interactive,
+}
+
 pub trait Task: Sync + Send {
     fn execute(&self);
     fn completed(&self) -> bool;
     fn multithreaded(&self) -> bool;
+    /// Queued tasks with higher priority are started before tasks with lower priority.
+    fn priority(&self) -> Priority { Priority::Interactive }
+}
+
+/// Highest priority of the queued tasks that are waiting for a worker thread, which allows running multithreaded
+/// tasks to yield their threads.
+#[derive(Default)]
+pub struct PendingPriority(AtomicUsize);
+
+impl PendingPriority {
+    pub fn set(&self, priority: Option<Priority>) {
+        self.0.store(priority.map_or(0, |priority| priority as usize + 1), Ordering::SeqCst);
+    }
+
+    /// Whether a task with higher priority than `priority` is waiting for a worker thread.
+    pub fn exceeds(&self, priority: Priority) -> bool {
+        self.0.load(Ordering::SeqCst) > priority as usize + 1
+    }
 }
 
 
@@ -22,6 +53,7 @@ struct FnTask<F, T> where
     T: Send {
     fun: F,
     sender: SharedSender<T>,
+    priority: Priority,
 }
 
 impl<F, T> Task for FnTask<F, T> where
@@ -34,13 +66,20 @@ impl<F, T> Task for FnTask<F, T> where
 
     fn completed(&self) -> bool { false }
     fn multithreaded(&self) -> bool { false }
+    fn priority(&self) -> Priority { self.priority }
 }
 
 impl Task {
     pub fn from_fn<F, T>(fun: F) -> (impl Task, oneshot::Receiver<T>) where
+        F: Fn() -> T + Sync + Send + 'static,
+        T: Send {
+        Task::from_fn_with_priority(fun, Priority::Interactive)
+    }
+
+    pub fn from_fn_with_priority<F, T>(fun: F, priority: Priority) -> (impl Task, oneshot::Receiver<T>) where
         F: Fn() -> T + Sync + Send + 'static,
         T: Send {
         let (sender, receiver) = oneshot::channel();
-        (FnTask { fun, sender: SharedSender::new(sender) }, receiver)
+        (FnTask { fun, sender: SharedSender::new(sender), priority }, receiver)
     }
 }
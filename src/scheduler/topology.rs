@@ -0,0 +1,95 @@
+use std::cell::Cell;
+#[cfg(target_os = "linux")]
+use std::fs;
+
+use num_cpus;
+
+
+thread_local! {
+    static CURRENT_NODE: Cell<usize> = Cell::new(0);
+}
+
+/// CPUs of each NUMA node of the machine.
+#[derive(Clone, Debug)]
+pub struct Topology {
+    nodes: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    /// Reads the NUMA nodes of the machine, all CPUs are assigned to a single node if they can't be determined.
+    pub fn detect() -> Topology {
+        let nodes = Topology::read_nodes()
+            .into_iter()
+            .filter(|cpus| !cpus.is_empty())
+            .collect::<Vec<_>>();
+        if nodes.is_empty() {
+            Topology { nodes: vec![(0..num_cpus::get()).collect()] }
+        } else {
+            Topology { nodes }
+        }
+    }
+
+    pub fn node_count(&self) -> usize { self.nodes.len() }
+
+    /// Node and CPU that worker thread `worker` is pinned to, consecutive workers are spread across nodes.
+    pub fn worker_cpu(&self, worker: usize) -> (usize, usize) {
+        let node = worker % self.nodes.len();
+        let cpus = &self.nodes[node];
+        (node, cpus[(worker / self.nodes.len()) % cpus.len()])
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_nodes() -> Vec<Vec<usize>> {
+        let mut nodes = Vec::new();
+        while let Ok(cpulist) = fs::read_to_string(format!("/sys/devices/system/node/node{}/cpulist", nodes.len())) {
+            match parse_cpulist(cpulist.trim()) {
+                Some(cpus) => nodes.push(cpus),
+                None => return vec![],
+            }
+        }
+        nodes
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read_nodes() -> Vec<Vec<usize>> { vec![] }
+}
+
+/// Parses lists of CPUs such as `0-3,8,10-11`.
+fn parse_cpulist(cpulist: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in cpulist.split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let start = bounds.next()?.parse::<usize>().ok()?;
+        let end = match bounds.next() {
+            Some(end) => end.parse::<usize>().ok()?,
+            None => start,
+        };
+        cpus.extend(start..=end);
+    }
+    Some(cpus)
+}
+
+/// Restricts the current thread to `cpu`, which belongs to `node`. Memory allocated by the thread is then usually
+/// placed on the same node. Returns false if the thread could not be pinned.
+pub fn pin_current_thread(node: usize, cpu: usize) -> bool {
+    CURRENT_NODE.with(|current| current.set(node));
+    set_affinity(cpu)
+}
+
+/// NUMA node of the current thread, 0 unless it was pinned with `pin_current_thread`.
+pub fn current_node() -> usize {
+    CURRENT_NODE.with(|node| node.get())
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpu: usize) -> bool {
+    extern "C" {
+        fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const u64) -> i32;
+    }
+    let mut mask = vec![0u64; cpu / 64 + 1];
+    mask[cpu / 64] |= 1 << (cpu % 64);
+    unsafe { sched_setaffinity(0, mask.len() * 8, mask.as_ptr()) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_: usize) -> bool { false }
@@ -0,0 +1,267 @@
+extern crate serde_json;
+extern crate tiny_http;
+
+use std::cmp;
+use std::io::{BufRead, BufReader, Read};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+
+use futures_executor::block_on;
+use scoped_threadpool::Pool;
+use self::serde_json::{Map, Number, Value as Json};
+use self::tiny_http::{Header, Method, Request, Response};
+
+use QueryResult;
+use ingest::json_loader;
+use ingest::raw_val::RawVal;
+use locustdb::LocustDB;
+use syntax::parser;
+
+
+/// Embedded HTTP server that makes a database available to dashboards and other clients.
+///
+/// - `POST /query` runs the SQL statement in the request body and responds with the result as JSON object with
+///   fields `colnames`, `rows` and `stats`.
+/// - `POST /partial` runs the query in the request body on the shard of a cluster node, see `cluster::Cluster`.
+/// - `POST /ingest?table=name` writes the newline-delimited JSON objects in the request body to table `name`. Requires
+///   `allow_writes`.
+/// - `GET /metrics` responds with metrics in the Prometheus text format.
+/// - `GET /replication?after=id&ids=a,b` responds with the serialized `replication::ChangeSet` of the partitions with
+///   ids greater than `after` or listed in `ids`, see `replication::Follower`.
+///
+/// Errors are returned as JSON object with an `error` field and status code 400. The server is read-only by default:
+/// statements that modify the database are rejected unless enabled with `allow_writes`, `COPY` statements unless enabled
+/// with `allow_copy`.
+pub struct Server {
+    http: tiny_http::Server,
+    locustdb: Arc<LocustDB>,
+    threads: usize,
+    allow_writes: bool,
+    allow_copy: bool,
+}
+
+impl Server {
+    /// Listens on `addr`, e.g. `127.0.0.1:8080`. Port 0 picks any free port.
+    pub fn bind(locustdb: Arc<LocustDB>, addr: &str) -> Result<Server, String> {
+        let http = tiny_http::Server::http(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+        Ok(Server { http, locustdb, threads: num_cpus::get(), allow_writes: false, allow_copy: false })
+    }
+
+    /// Number of requests that are handled at the same time, the number of CPUs by default.
+    pub fn threads(mut self, threads: usize) -> Server {
+        self.threads = cmp::max(threads, 1);
+        self
+    }
+
+    /// Allows clients to insert, delete and ingest rows and to create, alter and drop tables.
+    pub fn allow_writes(mut self, allow_writes: bool) -> Server {
+        self.allow_writes = allow_writes;
+        self
+    }
+
+    /// Allows clients to write files to the `export_directory` of the database with `COPY` statements.
+    pub fn allow_copy(mut self, allow_copy: bool) -> Server {
+        self.allow_copy = allow_copy;
+        self
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.http.server_addr()
+    }
+
+    /// Handles requests on `threads` threads until the process exits.
+    pub fn run(&self) {
+        let mut pool = Pool::new(self.threads as u32);
+        pool.scoped(|scope| {
+            for _ in 0..self.threads {
+                scope.execute(move || while let Ok(request) = self.http.recv() {
+                    self.handle(request);
+                });
+            }
+        });
+    }
+
+    /// Handles requests on a new thread.
+    pub fn spawn(self) -> thread::JoinHandle<()> {
+        thread::spawn(move || self.run())
+    }
+
+    fn handle(&self, mut request: Request) {
+        let (path, params) = split_url(request.url());
+        let method = request.method().clone();
+        let mut body = String::new();
+        let read = request.as_reader().read_to_string(&mut body);
+        let result = match read {
+            Err(err) => Err(format!("Failed to read request body: {}", err)),
+            Ok(_) => match (&method, path.as_ref()) {
+                (&Method::Post, "/query") => self.query(&body),
+                (&Method::Post, "/partial") => self.partial(&body),
+                (&Method::Get, "/metrics") => {
+                    let content_type = "Content-Type: text/plain; version=0.0.4".parse::<Header>().unwrap();
+                    let response = Response::from_string(self.locustdb.metrics()).with_header(content_type);
+                    if let Err(err) = request.respond(response) {
+                        warn!("Failed to send response: {}", err);
+                    }
+                    return;
+                }
+                #[cfg(feature = "replication")]
+                (&Method::Get, "/replication") => {
+                    let response = match replication_changes(&self.locustdb, &params) {
+                        Ok(changes) => {
+                            let content_type = "Content-Type: application/octet-stream".parse::<Header>().unwrap();
+                            Response::from_data(changes).with_header(content_type)
+                        }
+                        Err(err) => json_response(&error_json(&err), 400),
+                    };
+                    if let Err(err) = request.respond(response) {
+                        warn!("Failed to send response: {}", err);
+                    }
+                    return;
+                }
+                (&Method::Post, "/ingest") if !self.allow_writes => Err("The server is read-only".to_string()),
+                (&Method::Post, "/ingest") => match params.iter().find(|&&(ref key, _)| key == "table") {
+                    Some(&(_, ref table)) => self.ingest(table, &body),
+                    None => Err("Missing parameter `table`".to_string()),
+                },
+                (method, path) => {
+                    let response = error_json(&format!("No route for {} {}", method, path));
+                    let _ = request.respond(json_response(&response, 404));
+                    return;
+                }
+            },
+        };
+        let response = match result {
+            Ok(json) => json_response(&json, 200),
+            Err(err) => json_response(&error_json(&err), 400),
+        };
+        if let Err(err) = request.respond(response) {
+            warn!("Failed to send response: {}", err);
+        }
+    }
+
+    fn query(&self, sql: &str) -> Result<Json, String> {
+        if parser::is_copy(sql) {
+            if !self.allow_copy {
+                return Err("COPY is not permitted over HTTP".to_string());
+            }
+        } else if !self.allow_writes && !parser::is_read_only(sql) {
+            return Err("The server is read-only".to_string());
+        }
+        match block_on(self.locustdb.run_query(sql, false, vec![])) {
+            Ok((result, _)) => query_json(result),
+            Err(_) => Err("Query was cancelled".to_string()),
+        }
+    }
+
+    fn partial(&self, sql: &str) -> Result<Json, String> {
+        match block_on(self.locustdb.run_partial_query(sql)) {
+            Ok((result, _)) => query_json(result),
+            Err(_) => Err("Query was cancelled".to_string()),
+        }
+    }
+
+    fn ingest(&self, table: &str, body: &str) -> Result<Json, String> {
+        let writer = self.locustdb.table_writer(table);
+        let mut inserted = 0u64;
+        for (line_num, line) in BufReader::new(body.as_bytes()).lines().enumerate() {
+            let line = line.map_err(|err| err.to_string())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = match serde_json::from_str(&line) {
+                Ok(Json::Object(fields)) => fields,
+                Ok(_) => return Err(format!("Line {} is not a JSON object", line_num + 1)),
+                Err(err) => return Err(format!("Failed to parse line {}: {}", line_num + 1, err)),
+            };
+            let row = fields.into_iter()
+                .map(|(name, value)| (name, json_loader::to_raw_val(value)))
+                .collect();
+            writer.write(row).map_err(|err| format!("Line {}: {}", line_num + 1, err))?;
+            inserted += 1;
+        }
+        writer.flush();
+        let mut response = Map::new();
+        response.insert("inserted".to_string(), Json::from(inserted));
+        Ok(Json::Object(response))
+    }
+}
+
+/// Listens on `addr` and handles requests on a background thread.
+pub fn serve(locustdb: Arc<LocustDB>, addr: &str) -> Result<SocketAddr, String> {
+    let server = Server::bind(locustdb, addr)?;
+    let addr = server.addr();
+    server.spawn();
+    Ok(addr)
+}
+
+#[cfg(feature = "replication")]
+fn replication_changes(locustdb: &LocustDB, params: &[(String, String)]) -> Result<Vec<u8>, String> {
+    let mut after = 0;
+    let mut ids = Vec::new();
+    for &(ref key, ref value) in params {
+        match key.as_ref() {
+            "after" => after = value.parse::<u64>().map_err(|_| format!("Invalid parameter `after`: {}", value))?,
+            "ids" => for id in value.split(',').filter(|id| !id.is_empty()) {
+                ids.push(id.parse::<u64>().map_err(|_| format!("Invalid partition id: {}", id))?);
+            },
+            _ => {}
+        }
+    }
+    Ok(locustdb.replication_changes(after, &ids).serialize())
+}
+
+fn query_json(result: QueryResult) -> Result<Json, String> {
+    let output = result.map_err(|err| err.to_string())?;
+    let mut json = Map::new();
+    json.insert("colnames".to_string(),
+                Json::Array(output.colnames.into_iter().map(Json::String).collect()));
+    json.insert("rows".to_string(),
+                Json::Array(output.rows.into_iter()
+                    .map(|row| Json::Array(row.into_iter().map(to_json).collect()))
+                    .collect()));
+    let mut stats = Map::new();
+    stats.insert("runtime_ns".to_string(), Json::from(output.stats.runtime_ns));
+    stats.insert("rows_scanned".to_string(), Json::from(output.stats.rows_scanned as u64));
+    stats.insert("partitions_pruned".to_string(), Json::from(output.stats.partitions_pruned as u64));
+    stats.insert("sampling_factor".to_string(), Json::from(output.stats.sampling_factor));
+    json.insert("stats".to_string(), Json::Object(stats));
+    Ok(Json::Object(json))
+}
+
+fn to_json(value: RawVal) -> Json {
+    match value {
+        RawVal::Int(i) => Json::from(i),
+        RawVal::Float(f) => Number::from_f64(f.0).map_or(Json::Null, Json::Number),
+        RawVal::Str(s) => Json::String(s),
+        RawVal::Null => Json::Null,
+    }
+}
+
+fn error_json(error: &str) -> Json {
+    let mut json = Map::new();
+    json.insert("error".to_string(), Json::String(error.to_string()));
+    Json::Object(json)
+}
+
+fn json_response(json: &Json, status: u16) -> Response<::std::io::Cursor<Vec<u8>>> {
+    let content_type = "Content-Type: application/json".parse::<Header>().unwrap();
+    Response::from_string(json.to_string())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+/// Splits `url` into path and query parameters, parameters are not percent-decoded.
+fn split_url(url: &str) -> (String, Vec<(String, String)>) {
+    let mut parts = url.splitn(2, '?');
+    let path = parts.next().unwrap_or("").to_string();
+    let params = parts.next().unwrap_or("")
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let mut kv = param.splitn(2, '=');
+            (kv.next().unwrap_or("").to_string(), kv.next().unwrap_or("").to_string())
+        })
+        .collect();
+    (path, params)
+}
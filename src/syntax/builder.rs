@@ -0,0 +1,266 @@
+use std::i64;
+
+use QueryError;
+use engine::{Aggregator, Query, SumOverflow};
+use ingest::raw_val::RawVal;
+use syntax::expression::{Expr, Func1Type, Func2Type};
+use syntax::limit::LimitClause;
+use syntax::parser::resolve_aliases;
+use syntax::sample::SampleClause;
+
+
+/// Constructs a query without going through SQL, e.g.
+/// `Query::table("t").filter(col("a").gt(5)).group_by(col("b")).aggregate(count(col("a"))).build()`.
+/// Unlike SQL queries, queries are not limited to 100 rows unless `limit` is called.
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    table: String,
+    select: Vec<(Expr, Option<String>)>,
+    group_by: Vec<(Expr, Option<String>)>,
+    aggregates: Vec<(Expr, Option<String>)>,
+    filter: Option<Expr>,
+    order_by: Vec<(Expr, bool)>,
+    limit: LimitClause,
+    distinct: bool,
+    sum_overflow: Option<SumOverflow>,
+    sample: Option<SampleClause>,
+}
+
+impl Query {
+    pub fn table(table: &str) -> QueryBuilder {
+        QueryBuilder {
+            table: table.to_string(),
+            select: vec![],
+            group_by: vec![],
+            aggregates: vec![],
+            filter: None,
+            order_by: vec![],
+            limit: LimitClause { limit: i64::MAX as u64, offset: 0 },
+            distinct: false,
+            sum_overflow: None,
+            sample: None,
+        }
+    }
+}
+
+impl QueryBuilder {
+    pub fn select(mut self, expr: Expr) -> QueryBuilder {
+        self.select.push((expr, None));
+        self
+    }
+
+    pub fn select_as(mut self, expr: Expr, alias: &str) -> QueryBuilder {
+        self.select.push((expr, Some(alias.to_string())));
+        self
+    }
+
+    /// Adds `condition` to the filter, rows have to satisfy all conditions.
+    pub fn filter(mut self, condition: Expr) -> QueryBuilder {
+        self.filter = Some(match self.filter.take() {
+            Some(filter) => filter.and(condition),
+            None => condition,
+        });
+        self
+    }
+
+    /// Groups rows by `expr`, which is selected before all aggregates.
+    pub fn group_by(mut self, expr: Expr) -> QueryBuilder {
+        self.group_by.push((expr, None));
+        self
+    }
+
+    pub fn group_by_as(mut self, expr: Expr, alias: &str) -> QueryBuilder {
+        self.group_by.push((expr, Some(alias.to_string())));
+        self
+    }
+
+    pub fn aggregate(mut self, expr: Expr) -> QueryBuilder {
+        self.aggregates.push((expr, None));
+        self
+    }
+
+    pub fn aggregate_as(mut self, expr: Expr, alias: &str) -> QueryBuilder {
+        self.aggregates.push((expr, Some(alias.to_string())));
+        self
+    }
+
+    /// Orders by `expr`, which may refer to aliases of selected expressions.
+    pub fn order_by(mut self, expr: Expr, desc: bool) -> QueryBuilder {
+        self.order_by.push((expr, desc));
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> QueryBuilder {
+        self.limit.limit = limit;
+        self
+    }
+
+    pub fn offset(mut self, offset: u64) -> QueryBuilder {
+        self.limit.offset = offset;
+        self
+    }
+
+    pub fn distinct(mut self) -> QueryBuilder {
+        self.distinct = true;
+        self
+    }
+
+    /// Overrides how sums that exceed the range of 64-bit integers are handled for this query.
+    pub fn sum_overflow(mut self, sum_overflow: SumOverflow) -> QueryBuilder {
+        self.sum_overflow = Some(sum_overflow);
+        self
+    }
+
+    /// Runs the query on a random sample of the table.
+    pub fn sample(mut self, sample: SampleClause) -> QueryBuilder {
+        self.sample = Some(sample);
+        self
+    }
+
+    /// Checks that grouping columns and selected expressions contain no aggregates and that aggregates do. Queries
+    /// without any selected expressions select all columns.
+    pub fn build(self) -> Result<Query, QueryError> {
+        for (expr, _) in self.group_by.iter().chain(self.select.iter()) {
+            if has_aggregates(expr) {
+                bail!(QueryError::ParseError, "Aggregate {:?} must be added with `aggregate`", expr);
+            }
+        }
+        if let Some((expr, _)) = self.aggregates.iter().find(|(expr, _)| !has_aggregates(expr)) {
+            bail!(QueryError::ParseError, "Expression {:?} contains no aggregate", expr);
+        }
+        // Results are grouped by all selected expressions that are not aggregates
+        if !self.aggregates.is_empty() && !self.select.is_empty() {
+            bail!(QueryError::ParseError, "Expressions selected by aggregation queries must be added with `group_by`");
+        }
+        let mut select = self.group_by.into_iter()
+            .chain(self.select.into_iter())
+            .chain(self.aggregates.into_iter())
+            .collect::<Vec<_>>();
+        if select.is_empty() {
+            select.push((col("*"), None));
+        }
+        let (select, aliases): (Vec<_>, Vec<_>) = select.into_iter().unzip();
+        let order_by = resolve_aliases(self.order_by, &select, &aliases)?;
+        Ok(Query {
+            select,
+            table: self.table,
+            filter: self.filter.unwrap_or(Expr::Const(RawVal::Int(1))),
+            order_by,
+            limit: self.limit,
+            distinct: self.distinct,
+            aliases,
+            rollup: false,
+            sum_overflow: self.sum_overflow,
+            sample: self.sample,
+        })
+    }
+}
+
+fn has_aggregates(expr: &Expr) -> bool {
+    !Query::extract_aggregators(expr, &mut vec![]).1.is_empty()
+}
+
+/// Column `name`, `*` selects all columns.
+pub fn col(name: &str) -> Expr {
+    Expr::ColName(name.to_string())
+}
+
+/// Constant `value`, e.g. `lit(5)` or `lit("a")`.
+pub fn lit<V: Into<RawVal>>(value: V) -> Expr {
+    Expr::Const(value.into())
+}
+
+pub fn count(expr: Expr) -> Expr {
+    Expr::Aggregate(Aggregator::Count, Box::new(expr))
+}
+
+pub fn sum(expr: Expr) -> Expr {
+    Expr::Aggregate(Aggregator::Sum, Box::new(expr))
+}
+
+/// Average computed from the sum and count, like `AVG` in SQL.
+pub fn avg(expr: Expr) -> Expr {
+    sum(expr.clone()).divide(count(expr))
+}
+
+pub fn not(expr: Expr) -> Expr {
+    Expr::func1(Func1Type::Not, expr)
+}
+
+/// Call to a user defined function registered with `LocustDB::register_function`.
+pub fn func(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::Func(name.to_lowercase(), args)
+}
+
+impl Expr {
+    pub fn equals<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::Equals, self, other.into()) }
+    pub fn not_equals<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::NotEquals, self, other.into()) }
+    pub fn lt<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::LT, self, other.into()) }
+    pub fn lte<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::LTE, self, other.into()) }
+    pub fn gt<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::GT, self, other.into()) }
+    pub fn gte<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::GTE, self, other.into()) }
+    pub fn and<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::And, self, other.into()) }
+    pub fn or<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::Or, self, other.into()) }
+    pub fn plus<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::Add, self, other.into()) }
+    pub fn minus<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::Subtract, self, other.into()) }
+    pub fn times<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::Multiply, self, other.into()) }
+    pub fn divide<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::Divide, self, other.into()) }
+    pub fn modulo<E: Into<Expr>>(self, other: E) -> Expr { Expr::func(Func2Type::Modulo, self, other.into()) }
+    /// Whether the string matches the regular expression `pattern`.
+    pub fn matches(self, pattern: &str) -> Expr { Expr::func(Func2Type::RegexMatch, self, lit(pattern)) }
+    pub fn is_in<V: Into<RawVal>>(self, values: Vec<V>) -> Expr {
+        Expr::In(Box::new(self), values.into_iter().map(Into::into).collect())
+    }
+    pub fn is_null(self) -> Expr { Expr::func1(Func1Type::IsNull, self) }
+    pub fn is_not_null(self) -> Expr { Expr::func1(Func1Type::IsNotNull, self) }
+}
+
+impl From<i32> for Expr {
+    fn from(value: i32) -> Expr { lit(i64::from(value)) }
+}
+
+impl From<i64> for Expr {
+    fn from(value: i64) -> Expr { lit(value) }
+}
+
+impl From<f64> for Expr {
+    fn from(value: f64) -> Expr { lit(value) }
+}
+
+impl<'a> From<&'a str> for Expr {
+    fn from(value: &'a str) -> Expr { lit(value) }
+}
+
+impl From<String> for Expr {
+    fn from(value: String) -> Expr { lit(value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    #[test]
+    fn test_matches_sql() {
+        let built = Query::table("t")
+            .filter(col("a").gt(5))
+            .filter(col("name").equals("x").or(not(col("d").is_null())))
+            .group_by(col("b"))
+            .aggregate_as(count(col("a")), "n")
+            .aggregate(avg(col("c")))
+            .order_by(col("n"), true)
+            .limit(10)
+            .build()
+            .unwrap();
+        let parsed = parser::parse_query(
+            "SELECT b, COUNT(a) AS n, AVG(c) FROM t WHERE a > 5 AND (name = 'x' OR NOT d IS NULL) ORDER BY n DESC LIMIT 10").unwrap();
+        assert_eq!(format!("{:?}", built), format!("{:?}", parsed));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Query::table("t").select(col("a")).aggregate(count(col("b"))).build().is_err());
+        assert!(Query::table("t").group_by(sum(col("a"))).build().is_err());
+        assert!(Query::table("t").aggregate(col("a")).build().is_err());
+    }
+}
@@ -1,18 +1,85 @@
 use ingest::raw_val::RawVal;
 use self::Expr::*;
+use std::cmp::Ordering;
 use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
 use engine::*;
+use udf::ScalarFunction;
+use QueryError;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum Expr {
     ColName(String),
     Const(RawVal),
     Func1(Func1Type, Box<Expr>),
     Func2(Func2Type, Box<Expr>, Box<Expr>),
     Aggregate(Aggregator, Box<Expr>),
+    /// Values of an aggregated expression in the rows that satisfy the condition given by a `FILTER` clause.
+    Filtered(Box<Expr>, Box<Expr>),
+    /// True if the expression is equal to any of the values in the list.
+    In(Box<Expr>, Vec<RawVal>),
+    /// Call to a function that is not built in, resolved to a `Udf` before query execution.
+    Func(String, Vec<Expr>),
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    Udf(Arc<ScalarFunction>, Vec<Expr>),
+    /// Window function, evaluated on the final query result.
+    Window(Box<WindowExpr>),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct WindowExpr {
+    pub function: WindowFunction,
+    pub args: Vec<Expr>,
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<(Expr, bool)>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum WindowFunction {
+    RowNumber,
+    Lag(usize),
+    Lead(usize),
+    /// Running sum over all preceding rows of the partition.
+    Sum,
+}
+
+impl WindowFunction {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            WindowFunction::RowNumber => "row_number",
+            WindowFunction::Lag(_) => "lag",
+            WindowFunction::Lead(_) => "lead",
+            WindowFunction::Sum => "sum",
+        }
+    }
+}
+
+impl WindowExpr {
+    /// All expressions that are evaluated before the window function is applied.
+    pub fn exprs(&self) -> Vec<&Expr> {
+        self.args.iter()
+            .chain(self.partition_by.iter())
+            .chain(self.order_by.iter().map(|(expr, _)| expr))
+            .collect()
+    }
+
+    fn try_map<F>(self, f: &mut F) -> Result<WindowExpr, QueryError>
+        where F: FnMut(Expr) -> Result<Expr, QueryError> {
+        Ok(WindowExpr {
+            function: self.function,
+            args: self.args.into_iter().map(|expr| f(expr)).collect::<Result<_, _>>()?,
+            partition_by: self.partition_by.into_iter().map(|expr| f(expr)).collect::<Result<_, _>>()?,
+            order_by: self.order_by.into_iter().map(|(expr, desc)| f(expr).map(|expr| (expr, desc))).collect::<Result<_, _>>()?,
+        })
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum Func2Type {
     Equals,
     NotEquals,
@@ -28,13 +95,126 @@ pub enum Func2Type {
     Divide,
     Modulo,
     RegexMatch,
+    Concat,
+    /// Left hand side if it is not null, right hand side otherwise.
+    Coalesce,
+    /// Whether the list on the left hand side, a list column or a JSON array stored as string, contains the string on the
+    /// right hand side.
+    ArrayContains,
+    /// Value on the left hand side that is selected by the key on the right hand side, only valid as argument to
+    /// `ARG_MAX` and `ARG_MIN`.
+    KeyedBy,
+}
+
+impl Func2Type {
+    /// Comparison that is equivalent to `self` with swapped operands, `None` if `self` is not a comparison.
+    pub fn flipped(self) -> Option<Func2Type> {
+        match self {
+            Func2Type::Equals | Func2Type::NotEquals => Some(self),
+            Func2Type::LT => Some(Func2Type::GT),
+            Func2Type::LTE => Some(Func2Type::GTE),
+            Func2Type::GT => Some(Func2Type::LT),
+            Func2Type::GTE => Some(Func2Type::LTE),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum Func1Type {
     Negate,
     ToYear,
     Not,
+    Hash,
+    Redact,
+    Truncate(usize),
+    Lower,
+    Upper,
+    Length,
+    /// Substring starting at the given (zero-based) character with at most the given number of characters.
+    Substr(usize, Option<usize>),
+    /// Maps a hash to the given lane of a HyperLogLog sketch.
+    HllRegisters(usize),
+    DateTrunc(TimeUnit),
+    Extract(TimeUnit),
+    /// Converts integers, floats and strings to the given type.
+    Cast(BasicType),
+    IsNull,
+    IsNotNull,
+    /// Repeats each row once for every element of a list column.
+    Unnest,
+}
+
+/// Unit of time that timestamps are truncated to or that is extracted from timestamps.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum TimeUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+    DayOfWeek,
+    DayOfYear,
+}
+
+impl TimeUnit {
+    pub fn parse(name: &str) -> Option<TimeUnit> {
+        Some(match name.to_lowercase().as_ref() {
+            "second" => TimeUnit::Second,
+            "minute" => TimeUnit::Minute,
+            "hour" => TimeUnit::Hour,
+            "day" => TimeUnit::Day,
+            "week" => TimeUnit::Week,
+            "month" => TimeUnit::Month,
+            "quarter" => TimeUnit::Quarter,
+            "year" => TimeUnit::Year,
+            "dow" => TimeUnit::DayOfWeek,
+            "doy" => TimeUnit::DayOfYear,
+            _ => return None,
+        })
+    }
+
+    /// Length of the unit in seconds, if every instance of the unit starts at a multiple of that length.
+    pub fn seconds(self) -> Option<i64> {
+        match self {
+            TimeUnit::Second => Some(1),
+            TimeUnit::Minute => Some(60),
+            TimeUnit::Hour => Some(3600),
+            TimeUnit::Day => Some(86400),
+            _ => None,
+        }
+    }
+}
+
+fn fold_integers(t: Func2Type, a: i64, b: i64) -> Option<i64> {
+    match t {
+        Func2Type::Add => a.checked_add(b),
+        Func2Type::Subtract => a.checked_sub(b),
+        Func2Type::Multiply => a.checked_mul(b),
+        Func2Type::Divide => a.checked_div(b),
+        Func2Type::Modulo => a.checked_rem(b),
+        Func2Type::And => Some((a != 0 && b != 0) as i64),
+        Func2Type::Or => Some((a != 0 || b != 0) as i64),
+        _ => fold_comparison(t, a.cmp(&b)),
+    }
+}
+
+fn fold_comparison(t: Func2Type, ordering: Ordering) -> Option<i64> {
+    let result = match t {
+        Func2Type::Equals => ordering == Ordering::Equal,
+        Func2Type::NotEquals => ordering != Ordering::Equal,
+        Func2Type::LT => ordering == Ordering::Less,
+        Func2Type::LTE => ordering != Ordering::Greater,
+        Func2Type::GT => ordering == Ordering::Greater,
+        Func2Type::GTE => ordering != Ordering::Less,
+        _ => return None,
+    };
+    Some(result as i64)
 }
 
 impl Expr {
@@ -43,16 +223,41 @@ impl Expr {
             ColName(ref name) => {
                 result.insert(name.to_string());
             }
-            Func2(_, ref expr1, ref expr2) => {
+            Func2(_, ref expr1, ref expr2) | Filtered(ref expr1, ref expr2) => {
                 expr1.add_colnames(result);
                 expr2.add_colnames(result);
             }
-            Func1(_, ref expr) => expr.add_colnames(result),
+            Func1(_, ref expr) | In(ref expr, _) => expr.add_colnames(result),
             Aggregate(_, ref expr) => expr.add_colnames(result),
+            Func(_, ref args) | Udf(_, ref args) => for arg in args {
+                arg.add_colnames(result);
+            },
+            Window(ref window) => for expr in window.exprs() {
+                expr.add_colnames(result);
+            },
             Const(_) => {}
         }
     }
 
+    /// Direct subexpressions of `self`.
+    pub fn children(&self) -> Vec<&Expr> {
+        match *self {
+            Func1(_, ref expr) | Aggregate(_, ref expr) | In(ref expr, _) => vec![&**expr],
+            Func2(_, ref expr1, ref expr2) | Filtered(ref expr1, ref expr2) => vec![&**expr1, &**expr2],
+            Func(_, ref args) | Udf(_, ref args) => args.iter().collect(),
+            Window(ref window) => window.exprs(),
+            ColName(_) | Const(_) => vec![],
+        }
+    }
+
+    /// Outermost aggregate contained in `self`, if any.
+    pub fn find_aggregate(&self) -> Option<&Expr> {
+        match *self {
+            Aggregate(_, _) => Some(self),
+            _ => self.children().into_iter().filter_map(|child| child.find_aggregate()).next(),
+        }
+    }
+
     pub fn func(ftype: Func2Type, expr1: Expr, expr2: Expr) -> Expr {
         Func2(ftype, Box::new(expr1), Box::new(expr2))
     }
@@ -60,5 +265,202 @@ impl Expr {
     pub fn func1(ftype: Func1Type, expr: Expr) -> Expr {
         Func1(ftype, Box::new(expr))
     }
+
+    /// Evaluates operations on integer and string constants, e.g. `1 = 2` becomes `0`.
+    pub fn fold_constants(self) -> Expr {
+        match self {
+            Func1(t, expr) => match (t, expr.fold_constants()) {
+                (Func1Type::Negate, Const(RawVal::Int(i))) => Const(RawVal::Int(i.wrapping_neg())),
+                (Func1Type::Not, Const(RawVal::Int(i))) => Const(RawVal::Int((i == 0) as i64)),
+                (t, expr) => Func1(t, Box::new(expr)),
+            },
+            Func2(t, expr1, expr2) => {
+                let expr1 = expr1.fold_constants();
+                let expr2 = expr2.fold_constants();
+                let folded = match (&expr1, &expr2) {
+                    (&Const(RawVal::Int(a)), &Const(RawVal::Int(b))) => fold_integers(t, a, b),
+                    (&Const(RawVal::Str(ref a)), &Const(RawVal::Str(ref b))) => fold_comparison(t, a.cmp(b)),
+                    // AND with false and OR with true are constant regardless of the other operand
+                    (&Const(RawVal::Int(0)), _) | (_, &Const(RawVal::Int(0))) if t == Func2Type::And => Some(0),
+                    (&Const(RawVal::Int(a)), _) | (_, &Const(RawVal::Int(a))) if t == Func2Type::Or && a != 0 => Some(1),
+                    _ => None,
+                };
+                if let Some(value) = folded {
+                    return Const(RawVal::Int(value));
+                }
+                match (t, expr1, expr2) {
+                    // Remaining constant operands of AND are true and those of OR are false
+                    (Func2Type::And, Const(RawVal::Int(_)), expr) | (Func2Type::And, expr, Const(RawVal::Int(_))) => expr,
+                    (Func2Type::Or, Const(RawVal::Int(_)), expr) | (Func2Type::Or, expr, Const(RawVal::Int(_))) => expr,
+                    (t, expr1, expr2) => Func2(t, Box::new(expr1), Box::new(expr2)),
+                }
+            }
+            In(expr, values) => match expr.fold_constants() {
+                Const(ref value) if *value != RawVal::Null => Const(RawVal::Int(values.contains(value) as i64)),
+                expr => In(Box::new(expr), values),
+            },
+            Func(name, args) => Func(name, args.into_iter().map(Expr::fold_constants).collect()),
+            Udf(function, args) => Udf(function, args.into_iter().map(Expr::fold_constants).collect()),
+            expr => expr,
+        }
+    }
+
+    /// Operands of a (nested) conjunction, e.g. `[a, b, c]` for `a AND (b AND c)`.
+    pub fn conjuncts(&self) -> Vec<&Expr> {
+        match *self {
+            Func2(Func2Type::And, ref lhs, ref rhs) => {
+                let mut conjuncts = lhs.conjuncts();
+                conjuncts.extend(rhs.conjuncts());
+                conjuncts
+            }
+            ref expr => vec![expr],
+        }
+    }
+
+    /// If `self` compares an expression with an integer constant, returns the expression, whether the constant is a
+    /// lower bound and the equivalent inclusive bound.
+    pub fn range_bound(&self) -> Option<(&Expr, bool, i64)> {
+        let (op, expr, bound) = match *self {
+            Func2(op, ref expr, box Const(RawVal::Int(bound))) => (op, expr, bound),
+            Func2(op, box Const(RawVal::Int(bound)), ref expr) => (op.flipped()?, expr, bound),
+            _ => return None,
+        };
+        match op {
+            Func2Type::GTE => Some((expr, true, bound)),
+            Func2Type::GT => Some((expr, true, bound.checked_add(1)?)),
+            Func2Type::LTE => Some((expr, false, bound)),
+            Func2Type::LT => Some((expr, false, bound.checked_sub(1)?)),
+            _ => None,
+        }
+    }
+
+    /// Replaces every column reference with the expression returned by `f`.
+    pub fn map_colnames<F>(self, f: &mut F) -> Result<Expr, QueryError>
+        where F: FnMut(String) -> Result<Expr, QueryError> {
+        Ok(match self {
+            ColName(name) => f(name)?,
+            Func1(t, expr) => Func1(t, Box::new(expr.map_colnames(f)?)),
+            Func2(t, expr1, expr2) => Func2(t, Box::new(expr1.map_colnames(f)?), Box::new(expr2.map_colnames(f)?)),
+            Aggregate(a, expr) => Aggregate(a, Box::new(expr.map_colnames(f)?)),
+            Filtered(expr, condition) => Filtered(Box::new(expr.map_colnames(f)?), Box::new(condition.map_colnames(f)?)),
+            In(expr, values) => In(Box::new(expr.map_colnames(f)?), values),
+            Func(name, args) => Func(name, args.into_iter().map(|arg| arg.map_colnames(f)).collect::<Result<_, _>>()?),
+            Udf(function, args) => Udf(function, args.into_iter().map(|arg| arg.map_colnames(f)).collect::<Result<_, _>>()?),
+            Window(window) => Window(Box::new(window.try_map(&mut |expr| expr.map_colnames(f))?)),
+            Const(c) => Const(c),
+        })
+    }
+
+    /// Replaces every `UNNEST(column)` with the expression returned by `f`.
+    pub fn map_unnest<F>(self, f: &mut F) -> Result<Expr, QueryError>
+        where F: FnMut(String) -> Result<Expr, QueryError> {
+        Ok(match self {
+            Func1(Func1Type::Unnest, box ColName(name)) => f(name)?,
+            Func1(t, expr) => Func1(t, Box::new(expr.map_unnest(f)?)),
+            Func2(t, expr1, expr2) => Func2(t, Box::new(expr1.map_unnest(f)?), Box::new(expr2.map_unnest(f)?)),
+            Aggregate(a, expr) => Aggregate(a, Box::new(expr.map_unnest(f)?)),
+            Filtered(expr, condition) => Filtered(Box::new(expr.map_unnest(f)?), Box::new(condition.map_unnest(f)?)),
+            In(expr, values) => In(Box::new(expr.map_unnest(f)?), values),
+            Func(name, args) => Func(name, args.into_iter().map(|arg| arg.map_unnest(f)).collect::<Result<_, _>>()?),
+            Udf(function, args) => Udf(function, args.into_iter().map(|arg| arg.map_unnest(f)).collect::<Result<_, _>>()?),
+            Window(window) => Window(Box::new(window.try_map(&mut |expr| expr.map_unnest(f))?)),
+            expr @ ColName(_) | expr @ Const(_) => expr,
+        })
+    }
+
+    /// Replaces every call to a function that is not built in with the expression returned by `f`.
+    pub fn map_functions<F>(self, f: &mut F) -> Result<Expr, QueryError>
+        where F: FnMut(String, Vec<Expr>) -> Result<Expr, QueryError> {
+        Ok(match self {
+            Func(name, args) => {
+                let args = args.into_iter().map(|arg| arg.map_functions(f)).collect::<Result<_, _>>()?;
+                f(name, args)?
+            }
+            Udf(function, args) => Udf(function, args.into_iter().map(|arg| arg.map_functions(f)).collect::<Result<_, _>>()?),
+            Func1(t, expr) => Func1(t, Box::new(expr.map_functions(f)?)),
+            Func2(t, expr1, expr2) => Func2(t, Box::new(expr1.map_functions(f)?), Box::new(expr2.map_functions(f)?)),
+            Aggregate(a, expr) => Aggregate(a, Box::new(expr.map_functions(f)?)),
+            Filtered(expr, condition) => Filtered(Box::new(expr.map_functions(f)?), Box::new(condition.map_functions(f)?)),
+            In(expr, values) => In(Box::new(expr.map_functions(f)?), values),
+            Window(window) => Window(Box::new(window.try_map(&mut |expr| expr.map_functions(f))?)),
+            expr @ ColName(_) | expr @ Const(_) => expr,
+        })
+    }
 }
 
+/// Formats the expression as SQL, used to point out expressions in error messages.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ColName(ref name) => write!(f, "{}", name),
+            Const(ref value) => write!(f, "{}", value),
+            Func1(t, ref expr) => match t {
+                Func1Type::Negate => write!(f, "-{}", expr),
+                Func1Type::ToYear => write!(f, "to_year({})", expr),
+                Func1Type::Not => write!(f, "NOT {}", expr),
+                Func1Type::IsNull => write!(f, "{} IS NULL", expr),
+                Func1Type::IsNotNull => write!(f, "{} IS NOT NULL", expr),
+                Func1Type::Cast(target) => write!(f, "CAST({} AS {:?})", expr, target),
+                Func1Type::Truncate(length) => write!(f, "truncate({}, {})", expr, length),
+                Func1Type::Substr(start, Some(length)) => write!(f, "substr({}, {}, {})", expr, start + 1, length),
+                Func1Type::Substr(start, None) => write!(f, "substr({}, {})", expr, start + 1),
+                Func1Type::DateTrunc(unit) => write!(f, "date_trunc('{:?}', {})", unit, expr),
+                Func1Type::Extract(unit) => write!(f, "extract({:?} FROM {})", unit, expr),
+                Func1Type::HllRegisters(lane) => write!(f, "hll_registers({}, {})", expr, lane),
+                _ => write!(f, "{}({})", format!("{:?}", t).to_lowercase(), expr),
+            },
+            Func2(t, ref lhs, ref rhs) => {
+                let operator = match t {
+                    Func2Type::Equals => "=",
+                    Func2Type::NotEquals => "<>",
+                    Func2Type::LT => "<",
+                    Func2Type::LTE => "<=",
+                    Func2Type::GT => ">",
+                    Func2Type::GTE => ">=",
+                    Func2Type::And => "AND",
+                    Func2Type::Or => "OR",
+                    Func2Type::Add => "+",
+                    Func2Type::Subtract => "-",
+                    Func2Type::Multiply => "*",
+                    Func2Type::Divide => "/",
+                    Func2Type::Modulo => "%",
+                    Func2Type::RegexMatch => return write!(f, "regex({}, {})", lhs, rhs),
+                    Func2Type::Concat => return write!(f, "concat({}, {})", lhs, rhs),
+                    Func2Type::Coalesce => return write!(f, "coalesce({}, {})", lhs, rhs),
+                    Func2Type::ArrayContains => return write!(f, "array_contains({}, {})", lhs, rhs),
+                    Func2Type::KeyedBy => return write!(f, "{}, {}", lhs, rhs),
+                };
+                write!(f, "({} {} {})", lhs, operator, rhs)
+            }
+            Aggregate(aggregator, ref expr) => {
+                let name = match aggregator {
+                    Aggregator::Sum => "sum",
+                    Aggregator::Count => "count",
+                    Aggregator::Min => "min",
+                    Aggregator::Max => "max",
+                    Aggregator::ApproxCountDistinct => "approx_count_distinct",
+                    Aggregator::Percentile => "percentile",
+                    Aggregator::Histogram => "histogram",
+                    Aggregator::ArgMax => "arg_max",
+                    Aggregator::ArgMin => "arg_min",
+                };
+                match **expr {
+                    Filtered(ref expr, ref condition) => write!(f, "{}({}) FILTER (WHERE {})", name, expr, condition),
+                    ref expr => write!(f, "{}({})", name, expr),
+                }
+            }
+            Filtered(ref expr, ref condition) => write!(f, "{} FILTER (WHERE {})", expr, condition),
+            In(ref expr, ref values) => {
+                let values = values.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+                write!(f, "{} IN ({})", expr, values.join(", "))
+            }
+            Func(ref name, ref args) => write!(f, "{}({})", name, join(args)),
+            Udf(ref function, ref args) => write!(f, "{}({})", function.name, join(args)),
+            Window(ref window) => write!(f, "{}({}) OVER (...)", window.function.name(), join(&window.args)),
+        }
+    }
+}
+
+fn join(exprs: &[Expr]) -> String {
+    exprs.iter().map(|expr| expr.to_string()).collect::<Vec<_>>().join(", ")
+}
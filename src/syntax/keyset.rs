@@ -0,0 +1,168 @@
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use hex;
+use seahash;
+
+use ::QueryError;
+use engine::Query;
+use engine::data_types::OrderedF64;
+use ingest::raw_val::RawVal;
+use syntax::expression::*;
+
+/// Columns that the results of a query are ordered by, used to continue the query after the last row of a page with
+/// an opaque cursor instead of an `OFFSET`.
+///
+/// Later pages are selected by a filter on the sort key, so they are as cheap as the first page and are not shifted by
+/// rows that are inserted or deleted before their start. Rows whose sort key is equal to that of the last row of a page
+/// are skipped, so the last column of the `ORDER BY` clause should be unique.
+#[derive(Debug, Clone)]
+pub struct Keyset {
+    columns: Vec<(String, bool)>,
+    /// Identifies the table and ordering of the query, so that cursors can't be used with a different query.
+    fingerprint: u64,
+}
+
+impl Keyset {
+    pub fn of(query: &Query) -> Result<Keyset, QueryError> {
+        if query.order_by.is_empty() {
+            bail!(QueryError::InvalidQuery, "Pagination with cursors requires an ORDER BY clause")
+        }
+        if query.rollup {
+            bail!(QueryError::NotImplemented, "Pagination with cursors is not supported for GROUP BY ROLLUP")
+        }
+        if query.select.iter().any(|expr| match *expr {
+            Expr::Window(_) => true,
+            _ => false,
+        }) {
+            bail!(QueryError::NotImplemented, "Pagination with cursors is not supported for window functions")
+        }
+        let mut columns = Vec::with_capacity(query.order_by.len());
+        for &(ref expr, desc) in &query.order_by {
+            match *expr {
+                Expr::ColName(ref name) => columns.push((name.clone(), desc)),
+                _ => bail!(QueryError::NotImplemented, "Pagination with cursors requires ORDER BY columns, not {}", expr),
+            }
+        }
+        let fingerprint = seahash::hash(format!("{} {:?}", query.table, columns).as_bytes());
+        Ok(Keyset { columns, fingerprint })
+    }
+
+    /// Restricts `query` to the rows that follow the position given by `cursor`.
+    pub fn seek(&self, query: &mut Query, cursor: &str) -> Result<(), QueryError> {
+        let values = self.decode(cursor)?;
+        // (a, b) > (x, y) is expanded to a > x OR (a = x AND b > y)
+        let mut after: Option<Expr> = None;
+        for (&(ref column, desc), value) in self.columns.iter().zip(values).rev() {
+            let column = Expr::ColName(column.clone());
+            let value = Expr::Const(value);
+            let op = if desc { Func2Type::LT } else { Func2Type::GT };
+            let past = Expr::func(op, column.clone(), value.clone());
+            after = Some(match after {
+                Some(after) => Expr::func(Func2Type::Or,
+                                          past,
+                                          Expr::func(Func2Type::And, Expr::func(Func2Type::Equals, column, value), after)),
+                None => past,
+            });
+        }
+        if let Some(after) = after {
+            let filter = ::std::mem::replace(&mut query.filter, Expr::Const(RawVal::Null));
+            query.filter = Expr::func(Func2Type::And, filter, after);
+        }
+        query.limit.offset = 0;
+        Ok(())
+    }
+
+    /// Cursor for the position after the last of `rows`, `None` if fewer than `limit` rows were returned and there are
+    /// no further pages.
+    pub fn cursor(&self, colnames: &[String], rows: &[Vec<RawVal>], limit: u64) -> Result<Option<String>, QueryError> {
+        let last = match rows.last() {
+            Some(last) if rows.len() as u64 >= limit => last,
+            _ => return Ok(None),
+        };
+        let mut data = Vec::new();
+        data.write_u64::<BigEndian>(self.fingerprint).unwrap();
+        for &(ref column, _) in &self.columns {
+            let value = match colnames.iter().position(|name| name == column) {
+                Some(index) => &last[index],
+                None => bail!(QueryError::InvalidQuery, "Pagination with cursors requires the ORDER BY column {} to be selected", column),
+            };
+            match *value {
+                RawVal::Int(int) => {
+                    data.push(INT);
+                    data.write_i64::<BigEndian>(int).unwrap();
+                }
+                RawVal::Float(float) => {
+                    data.push(FLOAT);
+                    data.write_f64::<BigEndian>(float.0).unwrap();
+                }
+                RawVal::Str(ref string) => {
+                    data.push(STR);
+                    data.write_u64::<BigEndian>(string.len() as u64).unwrap();
+                    data.extend_from_slice(string.as_bytes());
+                }
+                RawVal::Null => bail!(QueryError::NotImplemented, "Can't continue after a row where ORDER BY column {} is null", column),
+            }
+        }
+        Ok(Some(hex::encode(&data)))
+    }
+
+    fn decode(&self, cursor: &str) -> Result<Vec<RawVal>, QueryError> {
+        let invalid = || QueryError::InvalidQuery(format!("Invalid cursor {}", cursor));
+        let data = hex::decode(cursor).map_err(|_| invalid())?;
+        let mut reader = Cursor::new(&data[..]);
+        if reader.read_u64::<BigEndian>().map_err(|_| invalid())? != self.fingerprint {
+            bail!(QueryError::InvalidQuery, "Cursor {} was not created by a query on the same table and ORDER BY columns", cursor)
+        }
+        let mut values = Vec::with_capacity(self.columns.len());
+        for _ in &self.columns {
+            let value = match reader.read_u8().map_err(|_| invalid())? {
+                INT => RawVal::Int(reader.read_i64::<BigEndian>().map_err(|_| invalid())?),
+                FLOAT => RawVal::Float(OrderedF64(reader.read_f64::<BigEndian>().map_err(|_| invalid())?)),
+                STR => {
+                    let len = reader.read_u64::<BigEndian>().map_err(|_| invalid())? as usize;
+                    let start = reader.position() as usize;
+                    if start + len > data.len() {
+                        return Err(invalid());
+                    }
+                    reader.set_position((start + len) as u64);
+                    RawVal::Str(String::from_utf8(data[start..start + len].to_vec()).map_err(|_| invalid())?)
+                }
+                _ => return Err(invalid()),
+            };
+            values.push(value);
+        }
+        if reader.position() as usize != data.len() {
+            return Err(invalid());
+        }
+        Ok(values)
+    }
+}
+
+const INT: u8 = 0;
+const FLOAT: u8 = 1;
+const STR: u8 = 2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser::parse_query;
+
+    #[test]
+    fn test_cursor_roundtrip() {
+        let query = parse_query("SELECT name, id FROM t ORDER BY name DESC, id LIMIT 2;").unwrap();
+        let keyset = Keyset::of(&query).unwrap();
+        let colnames = vec!["name".to_string(), "id".to_string()];
+        let rows = vec![
+            vec![RawVal::Str("b".to_string()), RawVal::Int(3)],
+            vec![RawVal::Str("a".to_string()), RawVal::Int(-7)],
+        ];
+        let cursor = keyset.cursor(&colnames, &rows, 2).unwrap().unwrap();
+        assert_eq!(keyset.decode(&cursor).unwrap(), vec![RawVal::Str("a".to_string()), RawVal::Int(-7)]);
+        assert_eq!(keyset.cursor(&colnames, &rows, 3).unwrap(), None);
+
+        let other = Keyset::of(&parse_query("SELECT name, id FROM t ORDER BY id;").unwrap()).unwrap();
+        assert!(other.decode(&cursor).is_err());
+        assert!(keyset.decode(&cursor[..cursor.len() - 2]).is_err());
+    }
+}
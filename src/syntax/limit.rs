@@ -1,4 +1,5 @@
 #[derive(Clone, Debug, Hash, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct LimitClause {
     pub limit: u64,
     pub offset: u64,
@@ -1,3 +1,8 @@
+pub mod builder;
 pub mod expression;
+pub mod keyset;
 pub mod limit;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod prepared;
+pub mod sample;
+pub mod statement;
\ No newline at end of file
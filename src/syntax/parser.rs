@@ -1,16 +1,346 @@
 extern crate sqlparser;
 
+use std::i64;
+use std::sync::Arc;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use regex;
 use sqlparser::sqlparser::*;
 use sqlparser::sqlast::*;
 use engine::*;
 use syntax::expression::*;
 use ingest::raw_val::RawVal;
 use syntax::limit::*;
+use syntax::sample::{SampleClause, SampleMethod};
+use syntax::prepared;
+use syntax::statement::*;
+use ingest::schema::{ColumnSchema, ColumnType};
+use export::ExportFormat;
 use sqlparser::dialect::GenericSqlDialect;
 use QueryError;
+use engine::operators::histogram::Histogram;
+use engine::operators::quantile_sketch::QuantileSketch;
+use udf::{ScalarFunction, Signature, ValueType};
 
 // Convert sqlparser-rs `ASTNode` to LocustDB's `Query`
 pub fn parse_query(query: &str) -> Result<Query, QueryError> {
+    let (query, distinct) = strip_distinct(query);
+    let (query, sample) = extract_sample(&query)?;
+    let query = rewrite_extract(&query)?;
+    let query = rewrite_cast(&query)?;
+    let query = rewrite_filter(&query)?;
+    let query = rewrite_wildcards(&query)?;
+    let query = rewrite_aliases(&query)?;
+    let query = rewrite_between(&query)?;
+    let query = rewrite_in_and_like(&query)?;
+    let (query, windows) = extract_windows(&query)?;
+    let mut query = parse_select(&query)?;
+    query.distinct = distinct;
+    query.sample = sample;
+    if windows.is_empty() {
+        Ok(query)
+    } else {
+        resolve_windows(query, &windows)
+    }
+}
+
+/// Parses a query, `INSERT INTO`, `DELETE FROM`, `CREATE TABLE`, `CREATE VIEW`, `DROP TABLE`, `DROP VIEW`,
+/// `ALTER TABLE`, `COPY`, `SHOW TABLES` or `DESCRIBE` statement.
+pub fn parse_statement(statement: &str) -> Result<Statement, QueryError> {
+    let keyword = first_keyword(statement);
+    match keyword.as_ref() {
+        "INSERT" | "CREATE" => {
+            let create = keyword == "CREATE";
+            if create && statement.split_whitespace().nth(1).map_or(false, |word| word.eq_ignore_ascii_case("view")) {
+                return parse_create_view(statement);
+            }
+            match parse_insert_select(statement, create)? {
+                Some(insert) => Ok(Statement::InsertSelect(insert)),
+                None if create => parse_create_table(statement).map(Statement::CreateTable),
+                None => parse_insert(statement).map(Statement::Insert),
+            }
+        }
+        "DELETE" => parse_delete(statement).map(Statement::Delete),
+        "COPY" => parse_copy(statement).map(Statement::CopyTo),
+        "DROP" => parse_drop(statement),
+        "ALTER" => parse_add_column(statement),
+        "SHOW" | "DESCRIBE" | "DESC" => parse_query(&rewrite_introspection(statement)?).map(Statement::Select),
+        _ => parse_query(statement).map(Statement::Select),
+    }
+}
+
+/// Whether `statement` is a `COPY` statement, which writes files on the host of the database.
+pub fn is_copy(statement: &str) -> bool {
+    first_keyword(statement) == "COPY"
+}
+
+/// Whether `statement` neither modifies the database nor writes files.
+pub fn is_read_only(statement: &str) -> bool {
+    match first_keyword(statement).as_ref() {
+        "INSERT" | "CREATE" | "DELETE" | "COPY" | "DROP" | "ALTER" => false,
+        _ => true,
+    }
+}
+
+fn first_keyword(statement: &str) -> String {
+    statement.split_whitespace().next().unwrap_or("").to_uppercase()
+}
+
+// SHOW TABLES and DESCRIBE name are queries over the system tables `_tables` and `_columns`
+fn rewrite_introspection(statement: &str) -> Result<String, QueryError> {
+    let words = statement.trim().trim_right_matches(';').split_whitespace().collect::<Vec<_>>();
+    if words.len() == 2 && words[0].eq_ignore_ascii_case("show") && words[1].eq_ignore_ascii_case("tables") {
+        return Ok("SELECT name FROM _tables ORDER BY name LIMIT 1000000".to_string());
+    }
+    if words.len() == 2 && !words[0].eq_ignore_ascii_case("show") {
+        let table = words[1];
+        if !table.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            bail!(QueryError::ParseError, "Invalid table name {}", table)
+        }
+        return Ok(format!(
+            "SELECT column_name, data_type, encodings FROM _columns WHERE table_name = \"{}\" ORDER BY column_name LIMIT 1000000",
+            table));
+    }
+    bail!(QueryError::ParseError, "Expected SHOW TABLES or DESCRIBE name")
+}
+
+// CREATE TABLE name (column type [NOT NULL], ...)
+fn parse_create_table(statement: &str) -> Result<CreateTable, QueryError> {
+    let error = || QueryError::ParseError("Expected CREATE TABLE name (column type [NOT NULL], ...)".to_string());
+    let statement = statement.trim().trim_right_matches(';').trim_right();
+    let open = statement.find('(').ok_or_else(error)?;
+    if !statement.ends_with(')') {
+        return Err(error());
+    }
+    let head = statement[..open].split_whitespace().collect::<Vec<_>>();
+    if head.len() != 3 || !head[0].eq_ignore_ascii_case("create") || !head[1].eq_ignore_ascii_case("table") {
+        return Err(error());
+    }
+
+    let mut columns = Vec::new();
+    for definition in statement[(open + 1)..(statement.len() - 1)].split(',') {
+        let words = definition.split_whitespace().collect::<Vec<_>>();
+        if words.len() < 2 {
+            return Err(error());
+        }
+        // Lengths such as VARCHAR(255) are ignored
+        let type_name = words[1].split('(').next().unwrap();
+        let column_type = match cast_type(type_name.trim_right_matches("[]")) {
+            Some(BasicType::String) if type_name.ends_with("[]") => ColumnType::StringList,
+            _ if type_name.ends_with("[]") => bail!(QueryError::NotImplemented, "Column type {}", words[1]),
+            Some(BasicType::Integer) => ColumnType::Integer,
+            Some(BasicType::Float) => ColumnType::Float,
+            Some(BasicType::String) => ColumnType::String,
+            _ => bail!(QueryError::NotImplemented, "Column type {}", words[1]),
+        };
+        let nullable = match words[2..].join(" ").to_uppercase().as_ref() {
+            "" | "NULL" => true,
+            "NOT NULL" => false,
+            _ => return Err(error()),
+        };
+        columns.push((words[0].to_string(), ColumnSchema::new(column_type).nullable(nullable)));
+    }
+    Ok(CreateTable { table: head[2].to_string(), columns })
+}
+
+// CREATE TABLE name AS SELECT ... or INSERT INTO name [(column, ...)] SELECT ...
+fn parse_insert_select(statement: &str, create: bool) -> Result<Option<InsertSelect>, QueryError> {
+    let error = || QueryError::ParseError("Expected INSERT INTO name [(column, ...)] SELECT ...".to_string());
+    let select = match find_keyword(statement, "select") {
+        Some(select) => select,
+        None => return Ok(None),
+    };
+    let head = &statement[..select];
+    let (table, columns) = if create {
+        let words = head.split_whitespace().collect::<Vec<_>>();
+        if words.len() != 4 || !words[1].eq_ignore_ascii_case("table") || !words[3].eq_ignore_ascii_case("as") {
+            return Ok(None);
+        }
+        (words[2].to_string(), None)
+    } else {
+        if find_keyword(head, "values").is_some() {
+            return Ok(None);
+        }
+        let (name, columns) = match head.find('(') {
+            Some(open) => {
+                let close = head.rfind(')').ok_or_else(error)?;
+                let columns = head[(open + 1)..close].split(',').map(|column| column.trim().to_string()).collect::<Vec<_>>();
+                if close < open || columns.iter().any(|column| column.is_empty()) {
+                    return Err(error());
+                }
+                (&head[..open], Some(columns))
+            }
+            None => (head, None),
+        };
+        let words = name.split_whitespace().collect::<Vec<_>>();
+        if words.len() != 3 || !words[1].eq_ignore_ascii_case("into") {
+            return Err(error());
+        }
+        (words[2].to_string(), columns)
+    };
+    let query = parse_unlimited_query(&statement[select..])?;
+    Ok(Some(InsertSelect { table, columns, query, create }))
+}
+
+// COPY (SELECT ...) TO 'file'
+fn parse_copy(statement: &str) -> Result<CopyTo, QueryError> {
+    let error = || QueryError::ParseError("Expected COPY (SELECT ...) TO 'file.csv' or 'file.parquet'".to_string());
+    let statement = statement.trim().trim_right_matches(';').trim_right();
+    let open = statement.find('(').ok_or_else(error)?;
+    let close = statement.rfind(')').ok_or_else(error)?;
+    if close < open || !statement[..open].trim().eq_ignore_ascii_case("copy") {
+        return Err(error());
+    }
+    let target = statement[(close + 1)..].trim();
+    if !target.get(..2).map_or(false, |to| to.eq_ignore_ascii_case("to")) {
+        return Err(error());
+    }
+    let path = target[2..].trim();
+    if path.len() < 2 || !(path.starts_with('\'') && path.ends_with('\'') || path.starts_with('"') && path.ends_with('"')) {
+        return Err(error());
+    }
+    let path = &path[1..(path.len() - 1)];
+    let format = match ExportFormat::detect(path) {
+        Some(format) => format,
+        None => bail!(QueryError::NotImplemented, "Export to {}, supported are .csv and .parquet files", path),
+    };
+    let query = parse_unlimited_query(&statement[(open + 1)..close])?;
+    Ok(CopyTo { query, path: path.to_string(), format })
+}
+
+/// Parses a query whose result is not limited to the default number of rows unless it has a LIMIT clause.
+fn parse_unlimited_query(query: &str) -> Result<Query, QueryError> {
+    let mut parsed = parse_query(query)?;
+    if find_keyword(query, "limit").is_none() {
+        parsed.limit = LimitClause { limit: i64::MAX as u64, offset: 0 };
+    }
+    Ok(parsed)
+}
+
+// DROP TABLE|VIEW [IF EXISTS] name
+fn parse_drop(statement: &str) -> Result<Statement, QueryError> {
+    let words = statement.trim().trim_right_matches(';').split_whitespace().collect::<Vec<_>>();
+    let if_exists = words.len() == 5 && words[2].eq_ignore_ascii_case("if") && words[3].eq_ignore_ascii_case("exists");
+    let view = words.len() > 1 && words[1].eq_ignore_ascii_case("view");
+    if !(words.len() == 3 || if_exists) || !(view || words[1].eq_ignore_ascii_case("table")) {
+        bail!(QueryError::ParseError, "Expected DROP TABLE|VIEW [IF EXISTS] name")
+    }
+    let name = words[words.len() - 1].to_string();
+    if view {
+        Ok(Statement::DropView { view: name, if_exists })
+    } else {
+        Ok(Statement::DropTable { table: name, if_exists })
+    }
+}
+
+// CREATE VIEW name AS SELECT ...
+fn parse_create_view(statement: &str) -> Result<Statement, QueryError> {
+    let error = || QueryError::ParseError("Expected CREATE VIEW name AS SELECT ...".to_string());
+    let statement = statement.trim().trim_right_matches(';').trim_right();
+    let select = find_keyword(statement, "select").ok_or_else(error)?;
+    let head = statement[..select].split_whitespace().collect::<Vec<_>>();
+    if head.len() != 4 || !head[3].eq_ignore_ascii_case("as") {
+        return Err(error());
+    }
+    // Kept on a single line, so that it can be stored in the catalog
+    let sql = statement[select..].replace(|c: char| c == '\t' || c == '\n' || c == '\r', " ");
+    if ["order", "group", "limit"].iter().any(|keyword| find_keyword(&sql, keyword).is_some()) {
+        bail!(QueryError::NotImplemented, "ORDER BY, GROUP BY or LIMIT in view")
+    }
+    let query = parse_query(&sql)?;
+    if query.distinct || query.select.iter().any(|expr| !Query::extract_aggregators(expr, &mut vec![]).1.is_empty()) {
+        bail!(QueryError::NotImplemented, "Aggregation or DISTINCT in view")
+    }
+    Ok(Statement::CreateView { view: head[2].to_string(), sql })
+}
+
+// ALTER TABLE name ADD [COLUMN] column AS expr [VIRTUAL | STORED]
+fn parse_add_column(statement: &str) -> Result<Statement, QueryError> {
+    let error = || QueryError::ParseError("Expected ALTER TABLE name ADD COLUMN column AS expr [VIRTUAL | STORED]".to_string());
+    let statement = statement.trim().trim_right_matches(';').trim_right();
+    let as_keyword = find_keyword(statement, "as").ok_or_else(error)?;
+    let head = statement[..as_keyword].split_whitespace().collect::<Vec<_>>();
+    let has_column = head.len() == 6 && head[4].eq_ignore_ascii_case("column");
+    if !(head.len() == 5 || has_column)
+        || !head[0].eq_ignore_ascii_case("alter")
+        || !head[1].eq_ignore_ascii_case("table")
+        || !head[3].eq_ignore_ascii_case("add") {
+        return Err(error());
+    }
+    let mut expr = statement[(as_keyword + 2)..].trim();
+    let mut stored = false;
+    if let Some(last) = expr.rfind(char::is_whitespace) {
+        let keyword = expr[(last + 1)..].to_uppercase();
+        if keyword == "STORED" || keyword == "VIRTUAL" {
+            stored = keyword == "STORED";
+            expr = expr[..last].trim_right();
+        }
+    }
+    // Kept on a single line, so that it can be stored in the catalog
+    let sql = expr.replace(|c: char| c == '\t' || c == '\n' || c == '\r', " ");
+    let query = parse_query(&format!("SELECT {} FROM {}", sql, head[2]))?;
+    if query.select.len() != 1 {
+        return Err(error());
+    }
+    let expr = query.select.into_iter().next().unwrap();
+    if let Some(aggregate) = expr.find_aggregate() {
+        bail!(QueryError::InvalidQuery, "Aggregate {} is not allowed in generated column", aggregate)
+    }
+    if let Expr::Window(_) = expr {
+        bail!(QueryError::InvalidQuery, "Window function {} is not allowed in generated column", expr)
+    }
+    Ok(Statement::AddColumn {
+        table: head[2].to_string(),
+        column: GeneratedColumn { name: head[head.len() - 1].to_string(), expr, sql, stored },
+    })
+}
+
+fn parse_insert(statement: &str) -> Result<Insert, QueryError> {
+    let dialect = GenericSqlDialect {};
+    let ast = Parser::parse_sql(&dialect, statement.to_string())
+        .map_err(|e| match e {
+            ParserError::ParserError(e_str) => QueryError::ParseError(e_str),
+            _ => fatal!("{:?}", e),
+        })?;
+    match ast {
+        ASTNode::SQLInsert { table_name, columns, values } => {
+            if columns.is_empty() {
+                bail!(QueryError::NotImplemented, "INSERT without column list")
+            }
+            let mut rows = Vec::with_capacity(values.len());
+            for row in &values {
+                if row.len() != columns.len() {
+                    bail!(QueryError::ParseError, "INSERT has {} columns but {} values", columns.len(), row.len())
+                }
+                rows.push(row.iter().map(insert_value).collect::<Result<Vec<_>, _>>()?);
+            }
+            Ok(Insert { table: table_name, columns, rows })
+        }
+        _ => Err(QueryError::NotImplemented(format!("{:?}", ast))),
+    }
+}
+
+// DELETE FROM name [WHERE condition]
+fn parse_delete(statement: &str) -> Result<Delete, QueryError> {
+    // Parsed as the query that selects all deleted rows
+    let statement = statement.trim_left();
+    let query = parse_query(&format!("SELECT *{}", &statement["DELETE".len()..]))?;
+    if !query.order_by.is_empty() {
+        bail!(QueryError::NotImplemented, "DELETE with ORDER BY")
+    }
+    Ok(Delete { table: query.table, filter: query.filter })
+}
+
+fn insert_value(node: &ASTNode) -> Result<RawVal, QueryError> {
+    match *expr(node)? {
+        Expr::Const(value) => Ok(value),
+        Expr::Func1(Func1Type::Negate, box Expr::Const(RawVal::Int(int))) => Ok(RawVal::Int(-int)),
+        Expr::Func1(Func1Type::Negate, box Expr::Const(RawVal::Float(float))) => Ok(RawVal::Float((-float.0).into())),
+        value => bail!(QueryError::NotImplemented, "Non-constant value {:?} in INSERT", value),
+    }
+}
+
+fn parse_select(query: &str) -> Result<Query, QueryError> {
     let dialect = GenericSqlDialect {};
     let ast = Parser::parse_sql(&dialect, query.to_string())
         .map_err(|e| match e {
@@ -18,54 +348,880 @@ pub fn parse_query(query: &str) -> Result<Query, QueryError> {
             _ => fatal!("{:?}", e),
         })?;
 
-    let (projection, relation, selection, order_by, limit) = get_query_components(ast)?;
-    let projection = get_projection(projection)?;
-    let table = get_table_name(relation)?;
+    select_query(ast)
+}
+
+fn select_query(ast: ASTNode) -> Result<Query, QueryError> {
+    let (projection, relation, selection, order_by, group_by, limit) = get_query_components(ast)?;
+    let (projection, aliases): (Vec<_>, Vec<_>) = get_projection(projection)?.into_iter().unzip();
     let filter = match selection {
         Some(ref s) => *expr(s)?,
         None => Expr::Const(RawVal::Int(1)),
     };
-    let order_by = get_order_by(order_by)?;
+    let order_by = resolve_aliases(get_order_by(order_by)?, &projection, &aliases)?;
+    let rollup = get_rollup(group_by, &projection, &aliases)?;
+    if rollup && !order_by.is_empty() {
+        bail!(QueryError::NotImplemented, "ORDER BY with ROLLUP")
+    }
     let limit_clause = LimitClause { limit: get_limit(limit)?, offset: 0 };
 
+    match relation {
+        Some(box subquery @ ASTNode::SQLSelect { .. }) => {
+            let query = Query {
+                select: projection,
+                table: String::new(),
+                filter,
+                order_by,
+                limit: limit_clause,
+                distinct: false,
+                aliases,
+                rollup,
+                sum_overflow: None,
+                sample: None,
+            };
+            inline_subquery(query, subquery)
+        }
+        relation => Ok(Query {
+            select: projection,
+            table: get_table_name(relation)?,
+            filter,
+            order_by,
+            limit: limit_clause,
+            distinct: false,
+            aliases,
+            rollup,
+            sum_overflow: None,
+            sample: None,
+        }),
+    }
+}
+
+// Results are always grouped by all selected expressions that are not aggregates, so a plain GROUP BY clause does not
+// change the query. GROUP BY ROLLUP has to list the grouped expressions in the order in which they are selected.
+fn get_rollup(group_by: Option<Vec<ASTNode>>,
+              select: &[Expr],
+              aliases: &[Option<String>]) -> Result<bool, QueryError> {
+    let mut group_by = match group_by {
+        Some(group_by) => group_by,
+        None => return Ok(false),
+    };
+    let rollup = group_by.len() == 1 && match group_by[0] {
+        ASTNode::SQLFunction { ref id, .. } => id.to_uppercase() == "ROLLUP",
+        _ => false,
+    };
+    if !rollup {
+        return Ok(false);
+    }
+    if let Some(ASTNode::SQLFunction { args, .. }) = group_by.pop() {
+        group_by = args;
+    }
+    let group_by = group_by.iter()
+        .map(|node| expr(node).map(|expr| (*expr, false)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let group_by = resolve_aliases(group_by, select, aliases)?.into_iter()
+        .map(|(expr, _)| format!("{:?}", expr))
+        .collect::<Vec<_>>();
+    let grouped = select.iter()
+        .filter(|expr| Query::extract_aggregators(expr, &mut vec![]).1.is_empty())
+        .map(|expr| format!("{:?}", expr))
+        .collect::<Vec<_>>();
+    if group_by != grouped {
+        bail!(QueryError::NotImplemented, "ROLLUP columns must be selected in the same order")
+    }
+    // Subtotals are computed by adding up the aggregates of the groups they contain
+    let additive = select.iter().all(|expr| match expr {
+        Expr::Aggregate(Aggregator::Sum, _) | Expr::Aggregate(Aggregator::Count, _) => true,
+        expr => Query::extract_aggregators(expr, &mut vec![]).1.is_empty(),
+    });
+    if !additive {
+        bail!(QueryError::NotImplemented, "ROLLUP with aggregates other than sum and count")
+    }
+    Ok(true)
+}
+
+// ORDER BY may refer to selected expressions by their alias.
+pub(crate) fn resolve_aliases(order_by: Vec<(Expr, bool)>,
+                   select: &[Expr],
+                   aliases: &[Option<String>]) -> Result<Vec<(Expr, bool)>, QueryError> {
+    let mut resolve = |name: String| -> Result<Expr, QueryError> {
+        let selected = aliases.iter().position(|alias| alias.as_ref() == Some(&name));
+        Ok(match selected {
+            Some(i) => select[i].clone(),
+            None => Expr::ColName(name),
+        })
+    };
+    order_by.into_iter()
+        .map(|(expr, desc)| expr.map_colnames(&mut resolve).map(|expr| (expr, desc)))
+        .collect()
+}
+
+// Subqueries in FROM are evaluated by substituting the expressions they select into the outer query.
+fn inline_subquery(query: Query, subquery: ASTNode) -> Result<Query, QueryError> {
+    if let ASTNode::SQLSelect { ref order_by, ref group_by, ref limit, .. } = subquery {
+        if order_by.is_some() || group_by.is_some() || limit.is_some() {
+            bail!(QueryError::NotImplemented, "ORDER BY, GROUP BY or LIMIT in subquery")
+        }
+    }
+    inline_query(query, select_query(subquery)?)
+}
+
+/// Substitutes the columns selected by `inner` into `query`, which selects from a subquery or view defined by `inner`.
+pub fn inline_query(query: Query, inner: Query) -> Result<Query, QueryError> {
+    if inner.select.iter().any(|expr| !Query::extract_aggregators(expr, &mut vec![]).1.is_empty()) {
+        bail!(QueryError::NotImplemented, "Aggregation in subquery")
+    }
+    let wildcard = inner.select.iter().any(|expr| match expr {
+        Expr::ColName(name) => name == "*",
+        _ => false,
+    });
+    let columns = inner.select.iter().zip(inner.aliases.iter())
+        .filter_map(|(expr, alias)| match (expr, alias) {
+            (_, Some(alias)) => Some((alias.clone(), expr.clone())),
+            (Expr::ColName(name), None) => Some((name.clone(), expr.clone())),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut resolve = |name: String| match columns.iter().find(|(column, _)| *column == name) {
+        Some((_, expr)) => Ok(expr.clone()),
+        // Placeholders of prepared queries are bound after views are inlined
+        None if wildcard || prepared::is_param(&name) => Ok(Expr::ColName(name)),
+        None => Err(QueryError::ParseError(format!("Column {} is not selected by subquery", name))),
+    };
+
+    let mut select = Vec::with_capacity(query.select.len());
+    let mut aliases = Vec::with_capacity(query.select.len());
+    for (expr, alias) in query.select.into_iter().zip(query.aliases.into_iter()) {
+        match expr {
+            Expr::ColName(ref name) if name == "*" && !wildcard => {
+                select.extend(inner.select.iter().cloned());
+                aliases.extend(inner.aliases.iter().cloned());
+            }
+            Expr::ColName(ref name) if name == "*" => {
+                select.push(expr.clone());
+                aliases.push(alias);
+            }
+            expr => {
+                select.push(expr.map_colnames(&mut resolve)?);
+                aliases.push(alias);
+            }
+        }
+    }
+    let filter = match query.filter.map_colnames(&mut resolve)? {
+        Expr::Const(RawVal::Int(1)) => inner.filter,
+        filter => match inner.filter {
+            Expr::Const(RawVal::Int(1)) => filter,
+            inner_filter => Expr::func(Func2Type::And, inner_filter, filter),
+        },
+    };
+    let order_by = query.order_by.into_iter()
+        .map(|(expr, desc)| expr.map_colnames(&mut resolve).map(|expr| (expr, desc)))
+        .collect::<Result<Vec<_>, _>>()?;
     Ok(Query {
-        select: projection,
-        table,
+        select,
+        table: inner.table,
         filter,
         order_by,
-        limit: limit_clause,
+        limit: query.limit,
+        distinct: query.distinct,
+        aliases,
+        rollup: query.rollup,
+        sum_overflow: query.sum_overflow,
+        sample: query.sample,
     })
 }
 
+// sqlparser does not support `SELECT DISTINCT`, so the keyword is removed before parsing.
+fn strip_distinct(query: &str) -> (String, bool) {
+    let trimmed = query.trim_left();
+    let mut tokens = trimmed.splitn(3, char::is_whitespace);
+    match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(select), Some(distinct), Some(rest))
+        if select.eq_ignore_ascii_case("select") && distinct.eq_ignore_ascii_case("distinct") =>
+            (format!("{} {}", select, rest), true),
+        _ => (query.to_string(), false),
+    }
+}
+
+// sqlparser does not support sampling, so `TABLESAMPLE SYSTEM|BERNOULLI (percent) [REPEATABLE (seed)]` and
+// `SAMPLE (rows) [REPEATABLE (seed)]` following the table name are removed from the query before parsing.
+fn extract_sample(query: &str) -> Result<(String, Option<SampleClause>), QueryError> {
+    let repeatable = r"(?:\s+REPEATABLE\s*\(\s*(\d+)\s*\))?";
+    for &(keyword, syntax) in &[("tablesample", r"^\s+(\w+)\s*\(\s*(\d+(?:\.\d*)?)\s*\)"),
+                                ("sample", r"^\s*\(\s*(\d+)\s*\)")] {
+        let mut offset = 0;
+        while let Some(found) = find_keyword(&query[offset..], keyword) {
+            let start = offset + found;
+            offset = start + keyword.len();
+            if !follows_table_name(query, start) {
+                continue;
+            }
+            let error = || QueryError::ParseError(
+                format!("Invalid {} clause at position {}", keyword.to_uppercase(), start));
+            let regex = regex::Regex::new(&format!("(?i){}{}", syntax, repeatable)).unwrap();
+            let captures = regex.captures(&query[offset..]).ok_or_else(error)?;
+            let end = offset + captures.get(0).unwrap().end();
+            let seed = match captures.get(captures.len() - 1) {
+                Some(seed) => Some(seed.as_str().parse::<u64>().map_err(|_| error())?),
+                None => None,
+            };
+            let method = if keyword == "tablesample" {
+                let percent = captures[2].parse::<f64>().map_err(|_| error())?;
+                if percent > 100.0 {
+                    bail!(QueryError::ParseError, "Sample percentage {} exceeds 100", percent)
+                }
+                match captures[1].to_lowercase().as_ref() {
+                    "system" => SampleMethod::Partitions(percent / 100.0),
+                    "bernoulli" => SampleMethod::Rows(percent / 100.0),
+                    method => bail!(QueryError::NotImplemented, "TABLESAMPLE {}", method.to_uppercase()),
+                }
+            } else {
+                SampleMethod::RowCount(captures[1].parse::<u64>().map_err(|_| error())?)
+            };
+            let query = format!("{}{}", &query[..start], &query[end..]);
+            return Ok((query, Some(SampleClause { method, seed })));
+        }
+    }
+    Ok((query.to_string(), None))
+}
+
+/// Whether the keyword starting at `start` directly follows `FROM table`.
+fn follows_table_name(query: &str, start: usize) -> bool {
+    let mut words = query[..start].split_whitespace().rev();
+    match (words.next(), words.next()) {
+        (Some(table), Some(from)) => from.eq_ignore_ascii_case("from") && table.bytes().all(is_identifier_char),
+        _ => false,
+    }
+}
+
+// sqlparser does not support `EXTRACT(field FROM expr)`, which is rewritten to `__extract('field', expr)` before parsing.
+fn rewrite_extract(query: &str) -> Result<String, QueryError> {
+    let mut query = query.to_string();
+    while let Some(extract_start) = find_keyword(&query, "extract") {
+        let (field, expr_start) = {
+            let bytes = query.as_bytes();
+            let error = || QueryError::ParseError(format!("Expected EXTRACT(field FROM expr) at position {}", extract_start));
+            let mut field_start = extract_start + "extract".len();
+            while field_start < bytes.len() && (bytes[field_start] as char).is_whitespace() {
+                field_start += 1;
+            }
+            if bytes.get(field_start) != Some(&b'(') {
+                return Err(error());
+            }
+            let from = find_keyword(&query[field_start..], "from").ok_or_else(error)? + field_start;
+            let field = query[(field_start + 1)..from].trim();
+            if field.is_empty() || !field.bytes().all(is_identifier_char) {
+                return Err(error());
+            }
+            (field.to_string(), from + "from".len())
+        };
+        query = format!("{}{}('{}',{}", &query[..extract_start], EXTRACT, field, &query[expr_start..]);
+    }
+    Ok(query)
+}
+
+const EXTRACT: &str = "__extract";
+
+// sqlparser does not support `CAST(expr AS type)`, which is rewritten to `__cast('type', expr)` before parsing.
+fn rewrite_cast(query: &str) -> Result<String, QueryError> {
+    let mut query = query.to_string();
+    while let Some(cast_start) = find_keyword(&query, "cast") {
+        let (data_type, expr, cast_end) = {
+            let bytes = query.as_bytes();
+            let error = || QueryError::ParseError(format!("Expected CAST(expr AS type) at position {}", cast_start));
+            let mut open = cast_start + "cast".len();
+            while open < bytes.len() && (bytes[open] as char).is_whitespace() {
+                open += 1;
+            }
+            if bytes.get(open) != Some(&b'(') {
+                return Err(error());
+            }
+            let close = closing_paren(bytes, open).ok_or_else(error)?;
+            let inner = query[(open + 1)..close].trim_right();
+            let type_start = inner.char_indices().rev()
+                .find(|&(_, c)| !(c.is_alphanumeric() || c == '_'))
+                .map_or(0, |(i, c)| i + c.len_utf8());
+            let rest = inner[..type_start].trim_right();
+            let as_start = rest.len().saturating_sub(2);
+            if type_start == inner.len()
+                || !rest.as_bytes()[as_start..].eq_ignore_ascii_case(b"as")
+                || (as_start > 0 && is_identifier_char(rest.as_bytes()[as_start - 1]))
+                || rest[..as_start].trim().is_empty() {
+                return Err(error());
+            }
+            (inner[type_start..].to_string(), rest[..as_start].to_string(), close + 1)
+        };
+        query = format!("{}{}('{}',{}){}", &query[..cast_start], CAST, data_type, expr, &query[cast_end..]);
+    }
+    Ok(query)
+}
+
+const CAST: &str = "__cast";
+
+// sqlparser does not support `aggregate(expr) FILTER (WHERE condition)`, which is rewritten to
+// `__filter(aggregate(expr), (condition))` before parsing.
+fn rewrite_filter(query: &str) -> Result<String, QueryError> {
+    let mut query = query.to_string();
+    while let Some(filter_start) = find_keyword(&query, "filter") {
+        let (name_start, call_end, condition_start, clause_end) = {
+            let bytes = query.as_bytes();
+            let error = || QueryError::ParseError(
+                format!("Expected aggregate(expr) FILTER (WHERE condition) at position {}", filter_start));
+            let mut clause_start = filter_start + "filter".len();
+            while clause_start < bytes.len() && (bytes[clause_start] as char).is_whitespace() {
+                clause_start += 1;
+            }
+            if bytes.get(clause_start) != Some(&b'(') {
+                return Err(error());
+            }
+            let clause_end = closing_paren(bytes, clause_start).ok_or_else(error)?;
+            let where_start = find_keyword(&query[clause_start..clause_end], "where").ok_or_else(error)? + clause_start;
+            if !query[(clause_start + 1)..where_start].trim().is_empty() {
+                return Err(error());
+            }
+            let mut call_end = filter_start;
+            while call_end > 0 && (bytes[call_end - 1] as char).is_whitespace() {
+                call_end -= 1;
+            }
+            if call_end == 0 || bytes[call_end - 1] != b')' {
+                return Err(error());
+            }
+            let mut name_start = opening_paren(bytes, call_end - 1).ok_or_else(error)?;
+            while name_start > 0 && is_identifier_char(bytes[name_start - 1]) {
+                name_start -= 1;
+            }
+            (name_start, call_end, where_start + "where".len(), clause_end)
+        };
+        query = format!("{}{}({},({})){}",
+                        &query[..name_start],
+                        FILTER,
+                        &query[name_start..call_end],
+                        &query[condition_start..clause_end],
+                        &query[(clause_end + 1)..]);
+    }
+    Ok(query)
+}
+
+const FILTER: &str = "__filter";
+
+// sqlparser does not support `expr AS alias`, which is rewritten to `__alias(expr,'alias')` before parsing.
+fn rewrite_aliases(query: &str) -> Result<String, QueryError> {
+    let mut query = query.to_string();
+    while let Some(as_start) = find_keyword(&query, "as") {
+        let (expr_start, alias_end) = {
+            let bytes = query.as_bytes();
+            let error = || QueryError::ParseError(format!("Expected expr AS alias at position {}", as_start));
+            let mut alias_start = as_start + "as".len();
+            while alias_start < bytes.len() && (bytes[alias_start] as char).is_whitespace() {
+                alias_start += 1;
+            }
+            let mut alias_end = alias_start;
+            while alias_end < bytes.len() && is_identifier_char(bytes[alias_end]) {
+                alias_end += 1;
+            }
+            let expr_start = select_item_start(bytes, as_start);
+            if alias_start == alias_end || query[expr_start..as_start].trim().is_empty() {
+                return Err(error());
+            }
+            (expr_start, alias_end)
+        };
+        let alias = query[(as_start + "as".len())..alias_end].trim().to_string();
+        query = format!("{} {}({},'{}'){}",
+                        &query[..expr_start], ALIAS, query[expr_start..as_start].trim(), alias, &query[alias_end..]);
+    }
+    Ok(query)
+}
+
+const ALIAS: &str = "__alias";
+
+// Start of the item in a select list that ends at `end`.
+fn select_item_start(bytes: &[u8], end: usize) -> usize {
+    let mut depth = 0;
+    let mut quote = None;
+    for i in (0..end).rev() {
+        match (quote, bytes[i]) {
+            (Some(q), c) => if c == q { quote = None },
+            (None, c @ b'\'') | (None, c @ b'"') => quote = Some(c),
+            (None, b')') => depth += 1,
+            (None, b'(') => if depth == 0 { return i + 1 } else { depth -= 1 },
+            (None, b',') if depth == 0 => return i + 1,
+            (None, _) => if depth == 0 && i + 1 >= "select".len() {
+                let start = i + 1 - "select".len();
+                if bytes[start..(i + 1)].eq_ignore_ascii_case(b"select")
+                    && (start == 0 || !is_identifier_char(bytes[start - 1]))
+                    && !is_identifier_char(bytes[i + 1]) {
+                    return i + 1;
+                }
+            },
+        }
+    }
+    0
+}
+
+// sqlparser does not support `IN` and `LIKE`, so every `expr [NOT] IN (values)` and `expr [NOT] LIKE pattern` is
+// replaced with `expr = __in_list(values)` or `expr = __like(pattern)` (`<>` if negated) before parsing.
+// `ILIKE` is rewritten the same way into `__ilike(pattern)`.
+fn rewrite_in_and_like(query: &str) -> Result<String, QueryError> {
+    let query = rewrite_operator(query, "in", IN_LIST)?;
+    let query = rewrite_operator(&query, "like", LIKE)?;
+    rewrite_operator(&query, "ilike", ILIKE)
+}
+
+fn rewrite_operator(query: &str, keyword: &str, function: &str) -> Result<String, QueryError> {
+    let mut query = query.to_string();
+    while let Some(keyword_start) = find_keyword(&query, keyword) {
+        let (start, operand, operand_end, negated) = {
+            let bytes = query.as_bytes();
+            let error = || QueryError::ParseError(
+                format!("Invalid operand to {} at position {}", keyword.to_uppercase(), keyword_start));
+            let mut operand_start = keyword_start + keyword.len();
+            while operand_start < bytes.len() && (bytes[operand_start] as char).is_whitespace() {
+                operand_start += 1;
+            }
+            let (operand, operand_end) = match bytes.get(operand_start) {
+                Some(&b'(') => {
+                    let end = closing_paren(bytes, operand_start).ok_or_else(error)? + 1;
+                    (query[operand_start..end].to_string(), end)
+                }
+                Some(&quote) if quote == b'\'' || quote == b'"' => {
+                    let end = operand_start + 2 + bytes[(operand_start + 1)..].iter()
+                        .position(|&c| c == quote)
+                        .ok_or_else(error)?;
+                    (format!("({})", &query[operand_start..end]), end)
+                }
+                _ => return Err(error()),
+            };
+            match preceding_not(bytes, keyword_start) {
+                Some(not_start) => (not_start, operand, operand_end, true),
+                None => (keyword_start, operand, operand_end, false),
+            }
+        };
+        let operator = if negated { "<>" } else { "=" };
+        query = format!("{}{} {}{}{}", &query[..start], operator, function, operand, &query[operand_end..]);
+    }
+    Ok(query)
+}
+
+/// Start of the `NOT` that directly precedes the keyword starting at `keyword_start`, if any.
+fn preceding_not(bytes: &[u8], keyword_start: usize) -> Option<usize> {
+    let mut not_end = keyword_start;
+    while not_end > 0 && (bytes[not_end - 1] as char).is_whitespace() {
+        not_end -= 1;
+    }
+    if not_end >= 3
+        && bytes[(not_end - 3)..not_end].eq_ignore_ascii_case(b"not")
+        && (not_end == 3 || !is_identifier_char(bytes[not_end - 4])) {
+        Some(not_end - 3)
+    } else {
+        None
+    }
+}
+
+// sqlparser only supports a bare `*` in the select clause, so `prefix*` and `[prefix]* EXCEPT(a, b)` are replaced
+// with `__wildcard('pattern')` before parsing. The pattern is expanded against the columns of the table by
+// `Query::expand_wildcards`.
+fn rewrite_wildcards(query: &str) -> Result<String, QueryError> {
+    let (select_end, from_start) = match (find_keyword(query, "select"), find_keyword(query, "from")) {
+        (Some(select), Some(from)) if select < from => (select + "select".len(), from),
+        _ => return Ok(query.to_string()),
+    };
+    let mut items = Vec::new();
+    {
+        let bytes = query.as_bytes();
+        let mut item_start = select_end;
+        let mut depth = 0;
+        let mut quote = None;
+        for i in select_end..from_start {
+            match (quote, bytes[i]) {
+                (Some(q), c) => if c == q { quote = None },
+                (None, c @ b'\'') | (None, c @ b'"') => quote = Some(c),
+                (None, b'(') => depth += 1,
+                (None, b')') => depth -= 1,
+                (None, b',') if depth == 0 => {
+                    items.push((item_start, i));
+                    item_start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        items.push((item_start, from_start));
+    }
+    let mut rewritten = query[..select_end].to_string();
+    for (i, &(start, end)) in items.iter().enumerate() {
+        if i > 0 {
+            rewritten.push(',');
+        }
+        match parse_wildcard(&query[start..end])? {
+            Some(wildcard) => rewritten.push_str(&format!(" {}('{}') ", WILDCARD, wildcard)),
+            None => rewritten.push_str(&query[start..end]),
+        }
+    }
+    rewritten.push_str(&query[from_start..]);
+    Ok(rewritten)
+}
+
+/// Parses a select item of the form `prefix*` or `[prefix]* EXCEPT(a, b)`, a bare `*` is left to sqlparser.
+fn parse_wildcard(item: &str) -> Result<Option<Wildcard>, QueryError> {
+    let item = item.trim();
+    let star = match item.find('*') {
+        Some(star) => star,
+        None => return Ok(None),
+    };
+    let prefix = &item[..star];
+    if !prefix.bytes().all(is_identifier_char) {
+        return Ok(None);
+    }
+    let rest = item[(star + 1)..].trim_left();
+    let except = if rest.is_empty() {
+        if prefix.is_empty() {
+            return Ok(None);
+        }
+        vec![]
+    } else if rest.len() >= 6
+        && rest.as_bytes()[..6].eq_ignore_ascii_case(b"except")
+        && (rest.len() == 6 || !is_identifier_char(rest.as_bytes()[6])) {
+        let error = || QueryError::ParseError(format!("Invalid column list in `{}`", item));
+        let list = rest[6..].trim();
+        if !list.starts_with('(') || !list.ends_with(')') {
+            return Err(error());
+        }
+        let except = list[1..(list.len() - 1)].split(',')
+            .map(|col| col.trim().to_string())
+            .collect::<Vec<_>>();
+        if except.iter().any(|col| col.is_empty() || !col.bytes().all(is_identifier_char)) {
+            return Err(error());
+        }
+        except
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(Wildcard { prefix: prefix.to_string(), except }))
+}
+
+// sqlparser does not support `BETWEEN`, so every `expr [NOT] BETWEEN lower AND upper` is replaced with
+// `expr = __between(lower, upper)` (`<>` if negated) before parsing. The bounds must be literals, identifiers or
+// parenthesized expressions.
+fn rewrite_between(query: &str) -> Result<String, QueryError> {
+    let mut query = query.to_string();
+    while let Some(keyword_start) = find_keyword(&query, "between") {
+        let (start, lower, upper, end, negated) = {
+            let bytes = query.as_bytes();
+            let error = || QueryError::ParseError(
+                format!("Invalid operands to BETWEEN at position {}", keyword_start));
+            let (lower, lower_end) = between_operand(&query, keyword_start + "between".len()).ok_or_else(error)?;
+            let mut and_start = lower_end;
+            while and_start < bytes.len() && (bytes[and_start] as char).is_whitespace() {
+                and_start += 1;
+            }
+            let and_end = and_start + 3;
+            if and_end > bytes.len()
+                || !bytes[and_start..and_end].eq_ignore_ascii_case(b"and")
+                || (and_end < bytes.len() && is_identifier_char(bytes[and_end])) {
+                return Err(error());
+            }
+            let (upper, upper_end) = between_operand(&query, and_end).ok_or_else(error)?;
+            match preceding_not(bytes, keyword_start) {
+                Some(not_start) => (not_start, lower, upper, upper_end, true),
+                None => (keyword_start, lower, upper, upper_end, false),
+            }
+        };
+        let operator = if negated { "<>" } else { "=" };
+        query = format!("{}{} {}({}, {}){}", &query[..start], operator, BETWEEN, lower, upper, &query[end..]);
+    }
+    Ok(query)
+}
+
+/// Literal, identifier or parenthesized expression starting after `start` and the position at which it ends.
+fn between_operand(query: &str, start: usize) -> Option<(String, usize)> {
+    let bytes = query.as_bytes();
+    let mut start = start;
+    while start < bytes.len() && (bytes[start] as char).is_whitespace() {
+        start += 1;
+    }
+    let end = match *bytes.get(start)? {
+        b'(' => closing_paren(bytes, start)? + 1,
+        quote @ b'\'' | quote @ b'"' => start + 2 + bytes[(start + 1)..].iter().position(|&c| c == quote)?,
+        _ => {
+            let mut end = if bytes[start] == b'-' { start + 1 } else { start };
+            while end < bytes.len() && (is_identifier_char(bytes[end]) || bytes[end] == b'.') {
+                end += 1;
+            }
+            end
+        }
+    };
+    if end == start || (end == start + 1 && bytes[start] == b'-') {
+        return None;
+    }
+    Some((query[start..end].to_string(), end))
+}
+
+const IN_LIST: &str = "__in_list";
+const LIKE: &str = "__like";
+const ILIKE: &str = "__ilike";
+const BETWEEN: &str = "__between";
+const WILDCARD: &str = "__wildcard";
+
+struct Window {
+    function: String,
+    spec: String,
+    has_args: bool,
+}
+
+// sqlparser does not support window functions, so every `function(args) OVER (window)` is replaced with a call to
+// `__window{i}(args)` before parsing and converted into a window expression afterwards.
+fn extract_windows(query: &str) -> Result<(String, Vec<Window>), QueryError> {
+    let mut query = query.to_string();
+    let mut windows = Vec::new();
+    while let Some(over) = find_keyword(&query, "over") {
+        let (name_start, args_start, args_end, spec_start, spec_end) = {
+            let bytes = query.as_bytes();
+            let error = || QueryError::ParseError(format!("Invalid window function at position {}", over));
+            let mut spec_start = over + 4;
+            while spec_start < bytes.len() && (bytes[spec_start] as char).is_whitespace() {
+                spec_start += 1;
+            }
+            if bytes.get(spec_start) != Some(&b'(') {
+                return Err(error());
+            }
+            let spec_end = closing_paren(bytes, spec_start).ok_or_else(error)?;
+            let mut args_end = over;
+            while args_end > 0 && (bytes[args_end - 1] as char).is_whitespace() {
+                args_end -= 1;
+            }
+            if args_end == 0 || bytes[args_end - 1] != b')' {
+                return Err(error());
+            }
+            let args_start = opening_paren(bytes, args_end - 1).ok_or_else(error)?;
+            let mut name_start = args_start;
+            while name_start > 0 && is_identifier_char(bytes[name_start - 1]) {
+                name_start -= 1;
+            }
+            if name_start == args_start {
+                return Err(error());
+            }
+            (name_start, args_start, args_end, spec_start, spec_end)
+        };
+        // Calls without arguments are passed a dummy argument which is removed again in `resolve_windows`
+        let has_args = !query[(args_start + 1)..(args_end - 1)].trim().is_empty();
+        let args = if has_args { &query[args_start..args_end] } else { "(0)" };
+        windows.push(Window {
+            function: query[name_start..args_start].to_string(),
+            spec: query[(spec_start + 1)..spec_end].to_string(),
+            has_args,
+        });
+        query = format!("{}__window{}{}{}", &query[..name_start], windows.len() - 1, args, &query[(spec_end + 1)..]);
+    }
+    Ok((query, windows))
+}
+
+// Replaces the placeholder calls inserted by `extract_windows` with window expressions.
+fn resolve_windows(query: Query, windows: &[Window]) -> Result<Query, QueryError> {
+    let mut select = Vec::with_capacity(query.select.len());
+    for expr in query.select {
+        let window = match expr {
+            Expr::Func(ref name, ref args) => match window_index(name) {
+                Some(index) => {
+                    let window = &windows[index];
+                    let args = if window.has_args { args.clone() } else { vec![] };
+                    Some(parse_window(&window.function, args, &window.spec)?)
+                }
+                None => None,
+            },
+            _ => None,
+        };
+        match window {
+            Some(window) => {
+                if window.exprs().into_iter().any(contains_window) {
+                    bail!(QueryError::NotImplemented, "Nested window functions")
+                }
+                select.push(Expr::Window(Box::new(window)));
+            }
+            None => {
+                if contains_window(&expr) {
+                    bail!(QueryError::NotImplemented, "Window functions as part of other expressions")
+                }
+                select.push(expr);
+            }
+        }
+    }
+    if contains_window(&query.filter) || query.order_by.iter().any(|(expr, _)| contains_window(expr)) {
+        bail!(QueryError::NotImplemented, "Window functions outside of SELECT")
+    }
+    Ok(Query { select, ..query })
+}
+
+fn parse_window(function: &str, mut args: Vec<Expr>, spec: &str) -> Result<WindowExpr, QueryError> {
+    let (partition, order) = match find_keyword(spec, "order") {
+        Some(i) => (&spec[..i], Some(&spec[i..])),
+        None => (spec, None),
+    };
+    let partition_by = {
+        let mut tokens = partition.trim().splitn(3, char::is_whitespace);
+        match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some(""), None, None) => vec![],
+            (Some(partition), Some(by), Some(exprs))
+            if partition.eq_ignore_ascii_case("partition") && by.eq_ignore_ascii_case("by") =>
+                parse_select(&format!("SELECT {} FROM window", exprs))?.select,
+            _ => return Err(QueryError::ParseError(format!("Expected PARTITION BY or ORDER BY in window {:?}", spec))),
+        }
+    };
+    let order_by = match order {
+        Some(order) => parse_select(&format!("SELECT 0 FROM window {}", order))?.order_by,
+        None => vec![],
+    };
+    let function = match function.to_uppercase().as_ref() {
+        "ROW_NUMBER" => {
+            if !args.is_empty() {
+                return Err(QueryError::ParseError("Expected no arguments in ROW_NUMBER function".to_string()));
+            }
+            WindowFunction::RowNumber
+        }
+        name @ "LAG" | name @ "LEAD" => {
+            let offset = match args.get(1) {
+                None => 1,
+                Some(&Expr::Const(RawVal::Int(offset))) if offset >= 0 => offset as usize,
+                _ => return Err(QueryError::ParseError(
+                    format!("Expected non-negative integer constant as second argument to {}", name))),
+            };
+            if args.is_empty() || args.len() > 2 {
+                return Err(QueryError::ParseError(format!("Expected one or two arguments in {} function", name)));
+            }
+            args.truncate(1);
+            if name == "LAG" { WindowFunction::Lag(offset) } else { WindowFunction::Lead(offset) }
+        }
+        "SUM" => {
+            if args.len() != 1 {
+                return Err(QueryError::ParseError("Expected one argument in SUM function".to_string()));
+            }
+            WindowFunction::Sum
+        }
+        _ => bail!(QueryError::NotImplemented, "Window function {}", function),
+    };
+    Ok(WindowExpr { function, args, partition_by, order_by })
+}
+
+fn window_index(function: &str) -> Option<usize> {
+    if function.starts_with("__window") {
+        function["__window".len()..].parse::<usize>().ok()
+    } else {
+        None
+    }
+}
+
+fn contains_window(expr: &Expr) -> bool {
+    let mut found = false;
+    let _ = expr.clone().map_functions(&mut |name, args| {
+        found |= window_index(&name).is_some();
+        Ok(Expr::Func(name, args))
+    });
+    found
+}
+
+// Position of `keyword` as a separate word outside of any string literal.
+fn find_keyword(query: &str, keyword: &str) -> Option<usize> {
+    let bytes = query.as_bytes();
+    let mut quote = None;
+    for i in 0..bytes.len() {
+        match quote {
+            Some(q) => if bytes[i] == q { quote = None },
+            None if bytes[i] == b'\'' || bytes[i] == b'"' => quote = Some(bytes[i]),
+            None => {
+                let end = i + keyword.len();
+                if end <= bytes.len()
+                    && bytes[i..end].eq_ignore_ascii_case(keyword.as_bytes())
+                    && (i == 0 || !is_identifier_char(bytes[i - 1]))
+                    && (end == bytes.len() || !is_identifier_char(bytes[end])) {
+                    return Some(i);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn closing_paren(bytes: &[u8], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for i in open..bytes.len() {
+        match (quote, bytes[i]) {
+            (Some(q), c) => if c == q { quote = None },
+            (None, c @ b'\'') | (None, c @ b'"') => quote = Some(c),
+            (None, b'(') => depth += 1,
+            (None, b')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn opening_paren(bytes: &[u8], close: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for i in (0..(close + 1)).rev() {
+        match (quote, bytes[i]) {
+            (Some(q), c) => if c == q { quote = None },
+            (None, c @ b'\'') | (None, c @ b'"') => quote = Some(c),
+            (None, b')') => depth += 1,
+            (None, b'(') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_identifier_char(c: u8) -> bool {
+    (c as char).is_alphanumeric() || c == b'_'
+}
+
 fn get_query_components(ast: ASTNode)
                         -> Result<(
                             Vec<ASTNode>,
                             Option<Box<ASTNode>>,
                             Option<Box<ASTNode>>,
                             Option<Vec<SQLOrderByExpr>>,
+                            Option<Vec<ASTNode>>,
                             Option<Box<ASTNode>>),
                             QueryError>
 {
     match ast {
         ASTNode::SQLSelect { projection, relation, selection, order_by, group_by, having, limit } => {
-            if group_by.is_some() {
-                Err(QueryError::NotImplemented(format!("Group By")))
-            } else if having.is_some() {
+            if having.is_some() {
                 Err(QueryError::NotImplemented(format!("Having")))
             } else {
-                Ok((projection, relation, selection, order_by, limit))
+                Ok((projection, relation, selection, order_by, group_by, limit))
             }
         }
         _ => Err(QueryError::NotImplemented(format!("{:?}", ast))),
     }
 }
 
-fn get_projection(projection: Vec<ASTNode>) -> Result<Vec<Expr>, QueryError> {
-    let mut result = Vec::<Expr>::new();
+fn get_projection(projection: Vec<ASTNode>) -> Result<Vec<(Expr, Option<String>)>, QueryError> {
+    let mut result = Vec::new();
     for elem in &projection {
         match elem {
-            ASTNode::SQLWildcard => result.push(Expr::ColName('*'.to_string())),
-            _ => result.push(*expr(elem)?),
+            ASTNode::SQLWildcard => result.push((Expr::ColName('*'.to_string()), None)),
+            // Produced by `rewrite_wildcards`
+            ASTNode::SQLFunction { id, args } if id == WILDCARD => match args.first().map(|arg| expr(arg)) {
+                Some(Ok(box Expr::Const(RawVal::Str(pattern)))) if args.len() == 1 =>
+                    result.push((Expr::ColName(pattern), None)),
+                _ => return Err(QueryError::ParseError(format!("Invalid wildcard {:?}", args))),
+            },
+            // Produced by `rewrite_aliases`
+            ASTNode::SQLFunction { id, args } if id == ALIAS => match args.get(1).map(|arg| expr(arg)) {
+                Some(Ok(box Expr::Const(RawVal::Str(alias)))) if args.len() == 2 =>
+                    result.push((*expr(&args[0])?, Some(alias))),
+                _ => return Err(QueryError::ParseError(format!("Invalid alias {:?}", args))),
+            },
+            _ => result.push((*expr(elem)?, None)),
         }
     }
 
@@ -100,8 +1256,23 @@ fn get_limit(limit: Option<Box<ASTNode>>) -> Result<u64, QueryError> {
 
 fn expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
     Ok(Box::new(match node {
-        ASTNode::SQLBinaryExpr { ref left, ref op, ref right } =>
-            Expr::Func2(map_operator(op)?, expr(left)?, expr(right)?),
+        ASTNode::SQLBinaryExpr { ref left, ref op, ref right } => match (op, &**right) {
+            (SQLOperator::Eq, ASTNode::SQLFunction { id, args }) if id == IN_LIST => in_list(left, args)?,
+            (SQLOperator::NotEq, ASTNode::SQLFunction { id, args }) if id == IN_LIST =>
+                Expr::Func1(Func1Type::Not, Box::new(in_list(left, args)?)),
+            (SQLOperator::Eq, ASTNode::SQLFunction { id, args }) if id == LIKE => like(left, args, false)?,
+            (SQLOperator::NotEq, ASTNode::SQLFunction { id, args }) if id == LIKE =>
+                Expr::Func1(Func1Type::Not, Box::new(like(left, args, false)?)),
+            (SQLOperator::Eq, ASTNode::SQLFunction { id, args }) if id == ILIKE => like(left, args, true)?,
+            (SQLOperator::NotEq, ASTNode::SQLFunction { id, args }) if id == ILIKE =>
+                Expr::Func1(Func1Type::Not, Box::new(like(left, args, true)?)),
+            (SQLOperator::Eq, ASTNode::SQLFunction { id, args }) if id == BETWEEN => between(left, args)?,
+            (SQLOperator::NotEq, ASTNode::SQLFunction { id, args }) if id == BETWEEN =>
+                Expr::Func1(Func1Type::Not, Box::new(between(left, args)?)),
+            _ => Expr::Func2(map_operator(op)?, expr(left)?, expr(right)?),
+        },
+        ASTNode::SQLIsNull(ref inner) => Expr::Func1(Func1Type::IsNull, expr(inner)?),
+        ASTNode::SQLIsNotNull(ref inner) => Expr::Func1(Func1Type::IsNotNull, expr(inner)?),
         ASTNode::SQLValue(ref literal) => Expr::Const(get_raw_val(literal)?),
         ASTNode::SQLIdentifier(ref identifier) => Expr::ColName(identifier.to_string()),
         ASTNode::SQLFunction { id, args } => match id.to_uppercase().as_ref() {
@@ -112,6 +1283,136 @@ fn expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
                 }
                 Expr::Func1(Func1Type::ToYear, expr(&args[0])?)
             }
+            "HASH" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in HASH function".to_string()));
+                }
+                Expr::Func1(Func1Type::Hash, expr(&args[0])?)
+            }
+            "REDACT" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in REDACT function".to_string()));
+                }
+                Expr::Func1(Func1Type::Redact, expr(&args[0])?)
+            }
+            "TRUNCATE" => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        "Expected two arguments in TRUNCATE function".to_string()));
+                }
+                match *expr(&args[1])? {
+                    Expr::Const(RawVal::Int(length)) if length >= 0 =>
+                        Expr::Func1(Func1Type::Truncate(length as usize), expr(&args[0])?),
+                    _ => return Err(QueryError::ParseError(
+                        "Expected non-negative integer constant as second argument to TRUNCATE".to_string())),
+                }
+            }
+            name @ "LOWER" | name @ "UPPER" | name @ "LENGTH" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        format!("Expected one argument in {} function", name)));
+                }
+                let ftype = match name {
+                    "LOWER" => Func1Type::Lower,
+                    "UPPER" => Func1Type::Upper,
+                    _ => Func1Type::Length,
+                };
+                Expr::Func1(ftype, expr(&args[0])?)
+            }
+            "SUBSTR" | "SUBSTRING" => {
+                if args.len() != 2 && args.len() != 3 {
+                    return Err(QueryError::ParseError(
+                        "Expected two or three arguments in SUBSTR function".to_string()));
+                }
+                // Character positions are one-based
+                let start = match *expr(&args[1])? {
+                    Expr::Const(RawVal::Int(start)) => if start > 0 { start as usize - 1 } else { 0 },
+                    _ => return Err(QueryError::ParseError(
+                        "Expected integer constant as second argument to SUBSTR".to_string())),
+                };
+                let length = if args.len() == 3 {
+                    match *expr(&args[2])? {
+                        Expr::Const(RawVal::Int(length)) if length >= 0 => Some(length as usize),
+                        _ => return Err(QueryError::ParseError(
+                            "Expected non-negative integer constant as third argument to SUBSTR".to_string())),
+                    }
+                } else {
+                    None
+                };
+                Expr::Func1(Func1Type::Substr(start, length), expr(&args[0])?)
+            }
+            "CONCAT" => {
+                if args.is_empty() {
+                    return Err(QueryError::ParseError(
+                        "Expected at least one argument in CONCAT function".to_string()));
+                }
+                let mut concatenated = expr(&args[0])?;
+                for arg in &args[1..] {
+                    concatenated = Box::new(Expr::Func2(Func2Type::Concat, concatenated, expr(arg)?));
+                }
+                *concatenated
+            }
+            "COALESCE" => {
+                if args.is_empty() {
+                    return Err(QueryError::ParseError(
+                        "Expected at least one argument in COALESCE function".to_string()));
+                }
+                let mut coalesced = expr(&args[args.len() - 1])?;
+                for arg in args[..(args.len() - 1)].iter().rev() {
+                    coalesced = Box::new(Expr::Func2(Func2Type::Coalesce, expr(arg)?, coalesced));
+                }
+                *coalesced
+            }
+            "DATE_TRUNC" => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        "Expected two arguments in DATE_TRUNC function".to_string()));
+                }
+                match time_unit(&args[0])? {
+                    Some(TimeUnit::DayOfWeek) | Some(TimeUnit::DayOfYear) | None => return Err(QueryError::ParseError(
+                        format!("Invalid unit {:?} in DATE_TRUNC", args[0]))),
+                    Some(unit) => Expr::Func1(Func1Type::DateTrunc(unit), expr(&args[1])?),
+                }
+            }
+            // Produced by `rewrite_extract`
+            "__EXTRACT" => {
+                let unit = if args.len() == 2 { time_unit(&args[0])? } else { None };
+                match unit {
+                    Some(unit) => Expr::Func1(Func1Type::Extract(unit), expr(&args[1])?),
+                    None => return Err(QueryError::ParseError(format!("Invalid EXTRACT arguments {:?}", args))),
+                }
+            }
+            // Produced by `rewrite_cast`
+            "__CAST" => {
+                let data_type = match args.get(0).map(|arg| expr(arg)) {
+                    Some(Ok(box Expr::Const(RawVal::Str(data_type)))) => data_type,
+                    _ => return Err(QueryError::ParseError(format!("Invalid CAST arguments {:?}", args))),
+                };
+                match cast_type(&data_type) {
+                    Some(t) if args.len() == 2 => Expr::Func1(Func1Type::Cast(t), expr(&args[1])?),
+                    _ => bail!(QueryError::NotImplemented, "CAST to {}", data_type),
+                }
+            }
+            "TO_TIMESTAMP" => to_timestamp(args)?,
+            "ARRAY_CONTAINS" => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        "Expected two arguments in ARRAY_CONTAINS function".to_string()));
+                }
+                Expr::Func2(Func2Type::ArrayContains, expr(&args[0])?, expr(&args[1])?)
+            }
+            "UNNEST" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in UNNEST function".to_string()));
+                }
+                match *expr(&args[0])? {
+                    column @ Expr::ColName(_) => Expr::Func1(Func1Type::Unnest, Box::new(column)),
+                    _ => bail!(QueryError::NotImplemented, "UNNEST of expressions other than columns"),
+                }
+            }
             "REGEX" => {
                 if args.len() != 2 {
                     return Err(QueryError::ParseError(
@@ -133,12 +1434,260 @@ fn expr(node: &ASTNode) -> Result<Box<Expr>, QueryError> {
                 }
                 Expr::Aggregate(Aggregator::Sum, expr(&args[0])?)
             }
-            _ => return Err(QueryError::NotImplemented(format!("Function {:?}", id))),
+            "MIN" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in MIN function".to_string()));
+                }
+                Expr::Aggregate(Aggregator::Min, expr(&args[0])?)
+            }
+            "MAX" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in MAX function".to_string()));
+                }
+                Expr::Aggregate(Aggregator::Max, expr(&args[0])?)
+            }
+            name @ "ARG_MAX" | name @ "ARG_MIN" => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        format!("Expected two arguments in {} function", name)));
+                }
+                let aggregator = if name == "ARG_MAX" { Aggregator::ArgMax } else { Aggregator::ArgMin };
+                Expr::Aggregate(aggregator, Box::new(Expr::Func2(Func2Type::KeyedBy, expr(&args[0])?, expr(&args[1])?)))
+            }
+            "APPROX_COUNT_DISTINCT" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in APPROX_COUNT_DISTINCT function".to_string()));
+                }
+                approx_count_distinct(expr(&args[0])?)
+            }
+            "PERCENTILE" | "APPROX_QUANTILE" => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        "Expected two arguments in PERCENTILE function".to_string()));
+                }
+                match args[1] {
+                    ASTNode::SQLValue(Value::Double(q)) if q >= 0.0 && q <= 1.0 => percentile(expr(&args[0])?, q),
+                    _ => return Err(QueryError::ParseError(
+                        "Expected constant between 0 and 1 as second argument to PERCENTILE".to_string())),
+                }
+            }
+            "HISTOGRAM" => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(
+                        "Expected two arguments in HISTOGRAM function".to_string()));
+                }
+                match args[1] {
+                    ASTNode::SQLValue(Value::Long(width)) if width > 0 => histogram(expr(&args[0])?, width),
+                    _ => return Err(QueryError::ParseError(
+                        "Expected positive integer constant as second argument to HISTOGRAM".to_string())),
+                }
+            }
+            name if name == FILTER.to_uppercase() => {
+                if args.len() != 2 {
+                    return Err(QueryError::ParseError(format!("Invalid FILTER clause {:?}", args)));
+                }
+                let aggregate = *expr(&args[0])?;
+                if Query::extract_aggregators(&aggregate, &mut vec![]).1.is_empty() {
+                    return Err(QueryError::ParseError(
+                        format!("FILTER clause can only be applied to aggregates, found {:?}", aggregate)));
+                }
+                filter_aggregates(aggregate, &expr(&args[1])?)
+            }
+            "AVG" => {
+                if args.len() != 1 {
+                    return Err(QueryError::ParseError(
+                        "Expected one argument in AVG function".to_string()));
+                }
+                // Partitions are aggregated independently, so the average is computed from the merged sum and count
+                let arg = expr(&args[0])?;
+                Expr::Func2(Func2Type::Divide,
+                            Box::new(Expr::Aggregate(Aggregator::Sum, arg.clone())),
+                            Box::new(Expr::Aggregate(Aggregator::Count, arg)))
+            }
+            _ => {
+                let mut arg_exprs = Vec::with_capacity(args.len());
+                for arg in args {
+                    arg_exprs.push(*expr(arg)?);
+                }
+                Expr::Func(id.to_lowercase(), arg_exprs)
+            }
         }
         _ => return Err(QueryError::NotImplemented(format!("{:?}", node))),
     }))
 }
 
+// Restricts all aggregates in `expr` to the rows that satisfy `condition`.
+fn filter_aggregates(expr: Expr, condition: &Expr) -> Expr {
+    match expr {
+        Expr::Aggregate(aggregator, arg) =>
+            Expr::Aggregate(aggregator, Box::new(Expr::Filtered(arg, Box::new(condition.clone())))),
+        Expr::Func1(t, arg) => Expr::Func1(t, Box::new(filter_aggregates(*arg, condition))),
+        Expr::Func2(t, lhs, rhs) =>
+            Expr::Func2(t, Box::new(filter_aggregates(*lhs, condition)), Box::new(filter_aggregates(*rhs, condition))),
+        Expr::Udf(function, args) =>
+            Expr::Udf(function, args.into_iter().map(|arg| filter_aggregates(arg, condition)).collect()),
+        expr => expr,
+    }
+}
+
+// Each lane of the HyperLogLog sketch is aggregated separately and the lanes are combined into an estimate in the final pass.
+fn approx_count_distinct(arg: Box<Expr>) -> Expr {
+    let hashed = Box::new(Expr::Func1(Func1Type::Hash, arg));
+    let lanes = (0..hyperloglog::LANES)
+        .map(|lane| Expr::Aggregate(
+            Aggregator::ApproxCountDistinct,
+            Box::new(Expr::Func1(Func1Type::HllRegisters(lane), hashed.clone()))))
+        .collect();
+    let signature = Signature::new(vec![ValueType::Integer; hyperloglog::LANES], ValueType::Integer);
+    let estimate = ScalarFunction::new("approx_count_distinct", signature, |lanes| {
+        let lanes = lanes.iter()
+            .map(|lane| match *lane {
+                RawVal::Int(i) => i,
+                _ => 0,
+            })
+            .collect::<Vec<_>>();
+        RawVal::Int(hyperloglog::estimate(&lanes))
+    });
+    Expr::Udf(Arc::new(estimate), lanes)
+}
+
+// Partitions compute quantile sketches which are merged and then queried for the quantile in the final pass.
+fn percentile(arg: Box<Expr>, q: f64) -> Expr {
+    let signature = Signature::new(vec![ValueType::String], ValueType::Integer);
+    let quantile = ScalarFunction::new("percentile", signature, move |sketch| match sketch[0] {
+        RawVal::Str(ref sketch) => RawVal::Int(QuantileSketch::parse(sketch).quantile(q).unwrap_or(0)),
+        _ => RawVal::Null,
+    });
+    Expr::Udf(Arc::new(quantile), vec![Expr::Aggregate(Aggregator::Percentile, arg)])
+}
+
+// Partitions count the values in each bucket, and the merged counts are labeled with the smallest value of their bucket
+// in the final pass.
+fn histogram(arg: Box<Expr>, bucket_width: i64) -> Expr {
+    let width = || Expr::Const(RawVal::Int(bucket_width));
+    // Index of the bucket rounded towards negative infinity, computed as (x - (x % w + w) % w) / w
+    let remainder = Expr::func(Func2Type::Modulo,
+                               Expr::func(Func2Type::Add, Expr::func(Func2Type::Modulo, (*arg).clone(), width()), width()),
+                               width());
+    let bucket = Expr::func(Func2Type::Divide, Expr::func(Func2Type::Subtract, *arg, remainder), width());
+    let signature = Signature::new(vec![ValueType::String], ValueType::String);
+    let labeled = ScalarFunction::new("histogram", signature, move |histogram| match histogram[0] {
+        // Functions can not fail, so histograms that are not produced by `VecHistogram` become null
+        RawVal::Str(ref histogram) => match Histogram::parse(histogram) {
+            Ok(histogram) => RawVal::Str(histogram.labeled(bucket_width)),
+            Err(err) => {
+                error!("{}", err);
+                RawVal::Null
+            }
+        },
+        _ => RawVal::Null,
+    });
+    Expr::Udf(Arc::new(labeled), vec![Expr::Aggregate(Aggregator::Histogram, Box::new(bucket))])
+}
+
+fn time_unit(node: &ASTNode) -> Result<Option<TimeUnit>, QueryError> {
+    Ok(match *expr(node)? {
+        Expr::Const(RawVal::Str(ref unit)) => TimeUnit::parse(unit),
+        _ => None,
+    })
+}
+
+fn cast_type(name: &str) -> Option<BasicType> {
+    match name.to_lowercase().as_ref() {
+        "int" | "integer" | "bigint" => Some(BasicType::Integer),
+        "float" | "double" | "real" => Some(BasicType::Float),
+        "string" | "varchar" | "text" => Some(BasicType::String),
+        _ => None,
+    }
+}
+
+// Converts a date string constant or an epoch timestamp in the given unit to a unix timestamp in seconds.
+fn to_timestamp(args: &[ASTNode]) -> Result<Expr, QueryError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(QueryError::ParseError("Expected one or two arguments in TO_TIMESTAMP function".to_string()));
+    }
+    let unit = match args.get(1).map(|arg| expr(arg)) {
+        None => "s".to_string(),
+        Some(Ok(box Expr::Const(RawVal::Str(unit)))) => unit.to_lowercase(),
+        Some(_) => return Err(QueryError::ParseError(
+            "Expected string constant as second argument to TO_TIMESTAMP".to_string())),
+    };
+    let divisor = match unit.as_ref() {
+        "s" => 1,
+        "ms" => 1_000,
+        "us" => 1_000_000,
+        "ns" => 1_000_000_000,
+        _ => return Err(QueryError::ParseError(format!("Invalid unit {:?} in TO_TIMESTAMP", unit))),
+    };
+    match *expr(&args[0])? {
+        Expr::Const(RawVal::Str(date)) => {
+            let parsed = NaiveDateTime::parse_from_str(&date, "%Y-%m-%d %H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(&date, "%Y-%m-%dT%H:%M:%S"))
+                .or_else(|_| NaiveDate::parse_from_str(&date, "%Y-%m-%d").map(|date| date.and_hms(0, 0, 0)))
+                .map_err(|_| QueryError::ParseError(format!("Invalid date {:?} in TO_TIMESTAMP", date)))?;
+            Ok(Expr::Const(RawVal::Int(parsed.timestamp())))
+        }
+        timestamp => if divisor == 1 {
+            Ok(timestamp)
+        } else {
+            Ok(Expr::Func2(Func2Type::Divide, Box::new(timestamp), Box::new(Expr::Const(RawVal::Int(divisor)))))
+        },
+    }
+}
+
+fn in_list(node: &ASTNode, list: &[ASTNode]) -> Result<Expr, QueryError> {
+    let mut values = Vec::with_capacity(list.len());
+    for value in list {
+        match *expr(value)? {
+            Expr::Const(RawVal::Null) => return Err(QueryError::NotImplemented("NULL in IN list".to_string())),
+            Expr::Const(constant) => values.push(constant),
+            _ => return Err(QueryError::ParseError(format!("Expected constant in IN list, found {:?}", value))),
+        }
+    }
+    Ok(Expr::In(expr(node)?, values))
+}
+
+// `expr BETWEEN lower AND upper` is equivalent to `expr >= lower AND expr <= upper`, which the query planner evaluates
+// with a single range check.
+fn between(node: &ASTNode, args: &[ASTNode]) -> Result<Expr, QueryError> {
+    if args.len() != 2 {
+        bail!(QueryError::ParseError, "Expected two bounds in BETWEEN")
+    }
+    let value = expr(node)?;
+    Ok(Expr::func(Func2Type::And,
+                  Expr::Func2(Func2Type::GTE, value.clone(), expr(&args[0])?),
+                  Expr::Func2(Func2Type::LTE, value, expr(&args[1])?)))
+}
+
+// Translates the LIKE pattern into an equivalent regex. Regexes on dictionary encoded columns are evaluated once per
+// dictionary entry, so case-insensitive matching does not normalize every row.
+fn like(node: &ASTNode, args: &[ASTNode], case_insensitive: bool) -> Result<Expr, QueryError> {
+    if args.len() == 1 {
+        if let Expr::Const(RawVal::Str(pattern)) = *expr(&args[0])? {
+            let regex = Box::new(Expr::Const(RawVal::Str(like_to_regex(&pattern, case_insensitive))));
+            return Ok(Expr::Func2(Func2Type::RegexMatch, expr(node)?, regex));
+        }
+    }
+    let keyword = if case_insensitive { "ILIKE" } else { "LIKE" };
+    Err(QueryError::ParseError(format!("Expected string constant as pattern in {}", keyword)))
+}
+
+fn like_to_regex(pattern: &str, case_insensitive: bool) -> String {
+    let mut regex = if case_insensitive { "(?is)^" } else { "(?s)^" }.to_string();
+    for c in pattern.chars() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
 fn map_operator(o: &SQLOperator) -> Result<Func2Type, QueryError> {
     Ok(match o {
         SQLOperator::And => Func2Type::And,
@@ -162,6 +1711,7 @@ fn map_operator(o: &SQLOperator) -> Result<Func2Type, QueryError> {
 fn get_raw_val(constant: &Value) -> Result<RawVal, QueryError> {
     match constant {
         Value::Long(int) => Ok(RawVal::Int(*int)),
+        Value::Double(float) => Ok(RawVal::Float((*float).into())),
         Value::String(string)
         | Value::SingleQuotedString(string)
         | Value::DoubleQuotedString(string) => Ok(RawVal::Str(string.to_string())),
@@ -180,13 +1730,169 @@ mod tests {
     fn test_select_star() {
         assert_eq!(
             format!("{:?}", parse_query("select * from default")),
-            "Ok(Query { select: [ColName(\"*\")], table: \"default\", filter: Const(Int(1)), order_by: [], limit: LimitClause { limit: 100, offset: 0 } })");
+            "Ok(Query { select: [ColName(\"*\")], table: \"default\", filter: Const(Int(1)), order_by: [], limit: LimitClause { limit: 100, offset: 0 }, distinct: false, aliases: [None], rollup: false, sum_overflow: None, sample: None })");
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert_eq!(
+            format!("{:?}", parse_query("select ts, metrics_*, * except (a, b) from default").map(|query| query.select)),
+            "Ok([ColName(\"ts\"), ColName(\"metrics_*\"), ColName(\"* EXCEPT(a, b)\")])");
+        assert_eq!(
+            format!("{:?}", parse_query("select a*b, count(1) from default").map(|query| query.select)),
+            "Ok([Func2(Multiply, ColName(\"a\"), ColName(\"b\")), Aggregate(Count, Const(Int(1)))])");
+        assert!(parse_query("select * except a from default").is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(
+            format!("{:?}", parse_query("select count(0) from t tablesample bernoulli (2.5) repeatable (7) where x > 1")
+                .map(|query| (query.sample, query.filter))),
+            "Ok((Some(SampleClause { method: Rows(0.025), seed: Some(7) }), Func2(GT, ColName(\"x\"), Const(Int(1)))))");
+        assert_eq!(
+            parse_query("SELECT sample FROM t SAMPLE (1000);").map(|query| query.sample).ok(),
+            Some(Some(SampleClause { method: SampleMethod::RowCount(1000), seed: None })));
+        assert!(parse_query("select x from t tablesample system").is_err());
+    }
+
+    #[test]
+    fn test_insert() {
+        assert_eq!(
+            format!("{:?}", parse_statement("insert into events (id, name) values (1, 'a'), (-2, NULL)")),
+            "Ok(Insert(Insert { table: \"events\", columns: [\"id\", \"name\"], rows: [[Int(1), Str(\"a\")], [Int(-2), Null]] }))");
+    }
+
+    #[test]
+    fn test_delete() {
+        assert_eq!(
+            format!("{:?}", parse_statement("DELETE FROM events WHERE ts < 100;")),
+            "Ok(Delete(Delete { table: \"events\", filter: Func2(LT, ColName(\"ts\"), Const(Int(100))) }))");
+        assert_eq!(
+            format!("{:?}", parse_statement("delete from events")),
+            "Ok(Delete(Delete { table: \"events\", filter: Const(Int(1)) }))");
+    }
+
+    #[test]
+    fn test_create_table() {
+        assert_eq!(
+            format!("{:?}", parse_statement("CREATE TABLE events (id BIGINT NOT NULL, name VARCHAR(64))")),
+            "Ok(CreateTable(CreateTable { table: \"events\", columns: [(\"id\", ColumnSchema { column_type: Integer, nullable: false, encoding: Auto }), (\"name\", ColumnSchema { column_type: String, nullable: true, encoding: Auto })] }))");
+        assert_eq!(
+            format!("{:?}", parse_statement("drop table if exists events;")),
+            "Ok(DropTable { table: \"events\", if_exists: true })");
+    }
+
+    #[test]
+    fn test_add_column() {
+        assert_eq!(
+            format!("{:?}", parse_statement("ALTER TABLE events ADD COLUMN hour AS ts / 3600 STORED;")),
+            "Ok(AddColumn { table: \"events\", column: GeneratedColumn { name: \"hour\", expr: Func2(Divide, ColName(\"ts\"), Const(Int(3600))), sql: \"ts / 3600\", stored: true } })");
+        assert_eq!(
+            format!("{:?}", parse_statement("alter table events add day as (ts / 86400)")),
+            "Ok(AddColumn { table: \"events\", column: GeneratedColumn { name: \"day\", expr: Func2(Divide, ColName(\"ts\"), Const(Int(86400))), sql: \"(ts / 86400)\", stored: false } })");
+        assert!(parse_statement("ALTER TABLE events ADD COLUMN total AS sum(ts)").is_err());
+    }
+
+    #[test]
+    fn test_view() {
+        assert_eq!(
+            format!("{:?}", parse_statement("CREATE VIEW recent AS SELECT ts, name\nFROM events WHERE ts > 0;")),
+            "Ok(CreateView { view: \"recent\", sql: \"SELECT ts, name FROM events WHERE ts > 0\" })");
+        assert!(parse_statement("CREATE VIEW totals AS SELECT name, count(0) FROM events").is_err());
+        assert!(parse_statement("CREATE VIEW top AS SELECT ts FROM events ORDER BY ts LIMIT 10").is_err());
+        assert_eq!(
+            format!("{:?}", parse_statement("drop view if exists recent")),
+            "Ok(DropView { view: \"recent\", if_exists: true })");
+        let view = parse_query("SELECT ts, name AS n FROM events WHERE ts > 0").unwrap();
+        let query = parse_query("SELECT DISTINCT n FROM recent WHERE ts < 10").unwrap();
+        let inlined = inline_query(query, view).unwrap();
+        assert_eq!(
+            format!("{:?}", (&inlined.select, &inlined.table, &inlined.filter, inlined.distinct)),
+            "([ColName(\"name\")], \"events\", Func2(And, Func2(GT, ColName(\"ts\"), Const(Int(0))), Func2(LT, ColName(\"ts\"), Const(Int(10)))), true)");
     }
 
     #[test]
     fn test_to_year() {
         assert_eq!(
             format!("{:?}", parse_query("select to_year(ts) from default")),
-            "Ok(Query { select: [Func1(ToYear, ColName(\"ts\"))], table: \"default\", filter: Const(Int(1)), order_by: [], limit: LimitClause { limit: 100, offset: 0 } })");
+            "Ok(Query { select: [Func1(ToYear, ColName(\"ts\"))], table: \"default\", filter: Const(Int(1)), order_by: [], limit: LimitClause { limit: 100, offset: 0 }, distinct: false, aliases: [None], rollup: false, sum_overflow: None, sample: None })");
+    }
+
+    #[test]
+    fn test_window_function() {
+        assert_eq!(
+            format!("{:?}", parse_query("select lag(ts, 2) over (partition by tld order by ts desc) from default").map(|q| q.select)),
+            "Ok([Window(WindowExpr { function: Lag(2), args: [ColName(\"ts\")], partition_by: [ColName(\"tld\")], order_by: [(ColName(\"ts\"), true)] })])");
+    }
+
+    #[test]
+    fn test_in_list() {
+        assert_eq!(
+            format!("{:?}", parse_query("select ts from default where tld not in ('com', 'org') and num in (1, 2)").map(|q| q.filter)),
+            "Ok(Func2(And, Func1(Not, In(ColName(\"tld\"), [Str(\"com\"), Str(\"org\")])), In(ColName(\"num\"), [Int(1), Int(2)])))");
+    }
+
+    #[test]
+    fn test_like() {
+        assert_eq!(
+            format!("{:?}", parse_query("select ts from default where tld like 'c_m%'").map(|q| q.filter)),
+            "Ok(Func2(RegexMatch, ColName(\"tld\"), Const(Str(\"(?s)^c.m.*$\"))))");
+        assert_eq!(
+            format!("{:?}", parse_query("select ts from default where tld not ilike 'C_M%'").map(|q| q.filter)),
+            "Ok(Func1(Not, Func2(RegexMatch, ColName(\"tld\"), Const(Str(\"(?is)^C.M.*$\")))))");
+    }
+
+    #[test]
+    fn test_between() {
+        assert_eq!(
+            format!("{:?}", parse_query("select ts from default where num not between 1 and 5 and tld = 'a'").map(|q| q.filter)),
+            "Ok(Func2(And, Func1(Not, Func2(And, Func2(GTE, ColName(\"num\"), Const(Int(1))), \
+             Func2(LTE, ColName(\"num\"), Const(Int(5))))), Func2(Equals, ColName(\"tld\"), Const(Str(\"a\")))))");
+    }
+
+    #[test]
+    fn test_cast() {
+        assert_eq!(
+            format!("{:?}", parse_query("select cast(cast(num as string) AS int) from default").map(|q| q.select)),
+            "Ok([Func1(Cast(Integer), Func1(Cast(String), ColName(\"num\")))])");
+    }
+
+    #[test]
+    fn test_null_predicates() {
+        assert_eq!(
+            format!("{:?}", parse_query("select coalesce(a, b, 0) from default where a is null or b is not null").map(|q| (q.select, q.filter))),
+            "Ok(([Func2(Coalesce, ColName(\"a\"), Func2(Coalesce, ColName(\"b\"), Const(Int(0))))], Func2(Or, Func1(IsNull, ColName(\"a\")), Func1(IsNotNull, ColName(\"b\")))))");
+    }
+
+    #[test]
+    fn test_subquery() {
+        assert_eq!(
+            format!("{:?}", parse_query("select x, count(1) from (select a + b as x from t where c > 5) where x < 3").map(|q| (q.select, q.table, q.filter))),
+            "Ok(([Func2(Add, ColName(\"a\"), ColName(\"b\")), Aggregate(Count, Const(Int(1)))], \"t\", Func2(And, Func2(GT, ColName(\"c\"), Const(Int(5))), Func2(LT, Func2(Add, ColName(\"a\"), ColName(\"b\")), Const(Int(3))))))");
+    }
+
+    #[test]
+    fn test_group_by() {
+        assert_eq!(parse_query("select a, count(1) from t group by a").map(|q| q.rollup).ok(), Some(false));
+        assert_eq!(parse_query("select a, b, sum(c) from t group by rollup(a, b)").map(|q| q.rollup).ok(), Some(true));
+        assert_eq!(parse_query("select a, b, count(1) from t group by a").map(|q| q.rollup).ok(), Some(false));
+        assert!(parse_query("select a, b, count(1) from t group by rollup(b, a)").is_err());
+        assert!(parse_query("select a, avg(c) from t group by rollup(a)").is_err());
+    }
+
+    #[test]
+    fn test_filter_clause() {
+        assert_eq!(
+            format!("{:?}", parse_query("select count(1) filter (where x > 5) from t").map(|q| q.select)),
+            "Ok([Aggregate(Count, Filtered(Const(Int(1)), Func2(GT, ColName(\"x\"), Const(Int(5)))))])");
+        assert!(parse_query("select x filter (where x > 5) from t").is_err());
+    }
+
+    #[test]
+    fn test_select_distinct() {
+        assert_eq!(
+            format!("{:?}", parse_query("SELECT DISTINCT tld FROM default")),
+            "Ok(Query { select: [ColName(\"tld\")], table: \"default\", filter: Const(Int(1)), order_by: [], limit: LimitClause { limit: 100, offset: 0 }, distinct: true, aliases: [None], rollup: false, sum_overflow: None, sample: None })");
     }
 }
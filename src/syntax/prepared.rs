@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use QueryError;
+use engine::Query;
+use engine::query_task::CompiledQuery;
+use ingest::raw_val::RawVal;
+use syntax::expression::Expr;
+use syntax::parser;
+
+
+/// Placeholders are parsed as column names with this prefix followed by the index of the parameter.
+const PARAM_PREFIX: &str = "__param_";
+
+/// Query with `?` or `$1`, `$2`, ... placeholders that is parsed once and can be run many times with different
+/// parameters, see `LocustDB::prepare`.
+///
+/// The query is normalized with its placeholders on the first run and the normalized query is reused by later runs,
+/// which only bind their parameters into it. It is normalized again when the columns of the table change. Queries are
+/// still planned on every run, since plans depend on the encodings of the partitions that are queried.
+///
+/// Parameters are bound as values, so they are never interpreted as SQL. Placeholders can appear wherever an
+/// expression is expected, but not in `IN` lists, `LIMIT` clauses or as table name.
+#[derive(Debug, Clone)]
+pub struct PreparedQuery {
+    sql: String,
+    query: Query,
+    param_count: usize,
+    /// Normalized query together with the key of the table columns and rewrites it was compiled for.
+    compiled: Arc<Mutex<Option<(String, CompiledQuery)>>>,
+}
+
+impl PreparedQuery {
+    pub fn new(sql: &str, query: Query, param_count: usize) -> PreparedQuery {
+        PreparedQuery { sql: sql.to_string(), query, param_count, compiled: Arc::default() }
+    }
+
+    /// The query with placeholders in place of parameters.
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    pub fn param_count(&self) -> usize {
+        self.param_count
+    }
+
+    /// Names of the columns that placeholders are parsed as.
+    pub fn param_colnames(&self) -> Vec<String> {
+        (0..self.param_count).map(|index| format!("{}{}", PARAM_PREFIX, index)).collect()
+    }
+
+    /// Copy of the query with every placeholder replaced by the corresponding value of `params`.
+    pub fn bind(&self, params: &[RawVal]) -> Result<Query, QueryError> {
+        self.bind_query(self.query.clone(), params)
+    }
+
+    /// Replaces every placeholder in `query`, which was derived from the prepared query, with the corresponding value
+    /// of `params`.
+    pub fn bind_query(&self, mut query: Query, params: &[RawVal]) -> Result<Query, QueryError> {
+        self.check_param_count(params)?;
+        let mut bind = binder(params);
+        query.select = query.select.into_iter()
+            .map(|expr| expr.map_colnames(&mut bind))
+            .collect::<Result<_, _>>()?;
+        query.filter = query.filter.map_colnames(&mut bind)?;
+        query.order_by = query.order_by.into_iter()
+            .map(|(expr, desc)| expr.map_colnames(&mut bind).map(|expr| (expr, desc)))
+            .collect::<Result<_, _>>()?;
+        Ok(query)
+    }
+
+    /// Returns the normalized query with every placeholder replaced by the corresponding value of `params`. The query
+    /// is only normalized by `compile` if it hasn't been normalized for the same `key` before.
+    pub fn compile_bound<F>(&self, key: String, params: &[RawVal], compile: F) -> Result<CompiledQuery, QueryError>
+        where F: FnOnce() -> Result<CompiledQuery, QueryError> {
+        self.check_param_count(params)?;
+        let cached = match *self.compiled.lock().unwrap() {
+            Some((ref cached_key, ref compiled)) if *cached_key == key => Some(compiled.clone()),
+            _ => None,
+        };
+        let compiled = match cached {
+            Some(compiled) => compiled,
+            None => {
+                let compiled = compile()?;
+                *self.compiled.lock().unwrap() = Some((key, compiled.clone()));
+                compiled
+            }
+        };
+        compiled.map_colnames(&mut binder(params))
+    }
+
+    fn check_param_count(&self, params: &[RawVal]) -> Result<(), QueryError> {
+        if params.len() != self.param_count {
+            bail!(QueryError::ParseError, "Query has {} parameters but {} were given", self.param_count, params.len())
+        }
+        Ok(())
+    }
+}
+
+/// Replaces placeholders with the corresponding value of `params` and leaves all other column names unchanged.
+fn binder<'a>(params: &'a [RawVal]) -> impl FnMut(String) -> Result<Expr, QueryError> + 'a {
+    move |name: String| Ok(match param_index(&name) {
+        Some(index) => Expr::Const(params[index].clone()),
+        None => Expr::ColName(name),
+    })
+}
+
+/// Whether `colname` is the name of a placeholder.
+pub fn is_param(colname: &str) -> bool {
+    param_index(colname).is_some()
+}
+
+/// Parses a query that contains placeholders and returns it together with the number of parameters.
+pub fn parse(sql: &str) -> Result<(Query, usize), QueryError> {
+    let (sql, param_count) = replace_placeholders(sql)?;
+    let query = parser::parse_query(&sql)?;
+    if param_index(&query.table).is_some() {
+        bail!(QueryError::ParseError, "Table name can't be a parameter")
+    }
+    Ok((query, param_count))
+}
+
+/// Replaces placeholders outside of string literals with column names that are recognized by `param_index`.
+fn replace_placeholders(sql: &str) -> Result<(String, usize), QueryError> {
+    let mut result = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut positional = 0;
+    let mut numbered = HashSet::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, '?') => {
+                result.push_str(&format!("{}{}", PARAM_PREFIX, positional));
+                positional += 1;
+                continue;
+            }
+            (None, '$') if chars.peek().map_or(false, |c| c.is_ascii_digit()) => {
+                let mut number = 0usize;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    number = number * 10 + digit as usize;
+                    chars.next();
+                }
+                if number == 0 {
+                    bail!(QueryError::ParseError, "Parameters are numbered starting from $1")
+                }
+                result.push_str(&format!("{}{}", PARAM_PREFIX, number - 1));
+                numbered.insert(number - 1);
+                continue;
+            }
+            _ => {}
+        }
+        result.push(c);
+    }
+    if positional > 0 && !numbered.is_empty() {
+        bail!(QueryError::ParseError, "Query mixes `?` and `$n` parameters")
+    }
+    let param_count = match numbered.iter().max() {
+        Some(&max) => {
+            if numbered.len() != max + 1 {
+                bail!(QueryError::ParseError, "Parameter ${} is not used", (0..max).find(|i| !numbered.contains(i)).unwrap() + 1)
+            }
+            max + 1
+        }
+        None => positional,
+    };
+    Ok((result, param_count))
+}
+
+fn param_index(colname: &str) -> Option<usize> {
+    if colname.starts_with(PARAM_PREFIX) {
+        colname[PARAM_PREFIX.len()..].parse().ok()
+    } else {
+        None
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use mem_store::partition::Partition;
+
+/// Random subset of a table that a query is run on, given by a `TABLESAMPLE` or `SAMPLE` clause.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleClause {
+    pub method: SampleMethod,
+    /// Seed given with `REPEATABLE (seed)`, a random seed is chosen for each query if `None`.
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleMethod {
+    /// Each partition is included with the given probability (`TABLESAMPLE SYSTEM (percent)`).
+    Partitions(f64),
+    /// Each row is included with the given probability (`TABLESAMPLE BERNOULLI (percent)`).
+    Rows(f64),
+    /// Random partitions are included until they contain at least the given number of rows (`SAMPLE (rows)`).
+    RowCount(u64),
+}
+
+/// Includes each row of a partition with probability `fraction`, determined by `seed` and the position of the row.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct RowSample {
+    pub fraction: f64,
+    pub seed: u64,
+}
+
+impl SampleClause {
+    /// Selects the partitions that are sampled and returns them together with the fraction of rows they contain.
+    /// Sampling of individual rows is done during query execution and reflected in the fraction.
+    pub fn sample_partitions(&self, partitions: Vec<Arc<Partition>>, seed: u64) -> (Vec<Arc<Partition>>, f64) {
+        let total_rows = partitions.iter().map(|p| p.len()).sum::<usize>();
+        let sampled = match self.method {
+            SampleMethod::Partitions(fraction) => partitions.into_iter()
+                .filter(|p| random_fraction(seed, p.id()) < fraction)
+                .collect::<Vec<_>>(),
+            SampleMethod::Rows(fraction) => return (partitions, fraction),
+            SampleMethod::RowCount(rows) => {
+                let mut partitions = partitions;
+                partitions.sort_by_key(|p| mix(seed ^ mix(p.id())));
+                let mut sampled_rows = 0;
+                partitions.into_iter()
+                    .take_while(|p| {
+                        let take = sampled_rows < rows as usize;
+                        sampled_rows += p.len();
+                        take
+                    })
+                    .collect()
+            }
+        };
+        let sampled_rows = sampled.iter().map(|p| p.len()).sum::<usize>();
+        let factor = if total_rows == 0 { 1.0 } else { sampled_rows as f64 / total_rows as f64 };
+        (sampled, factor)
+    }
+
+    /// Sample of rows that is taken in each partition, `None` if partitions are sampled.
+    pub fn row_sample(&self) -> Option<RowSample> {
+        match self.method {
+            SampleMethod::Rows(fraction) => Some(RowSample { fraction, seed: self.seed.unwrap_or(0) }),
+            _ => None,
+        }
+    }
+}
+
+/// Pseudo-random number in `[0, 1)` that depends only on `seed` and `value`.
+pub fn random_fraction(seed: u64, value: u64) -> f64 {
+    (mix(seed ^ mix(value)) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Scrambles the bits of `x` (SplitMix64 finalizer).
+fn mix(x: u64) -> u64 {
+    let mut x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_fraction() {
+        let sampled = (0..10_000).filter(|&i| random_fraction(7, i) < 0.25).count();
+        assert!(sampled > 2_300 && sampled < 2_700, "{}", sampled);
+        assert_eq!(random_fraction(7, 42), random_fraction(7, 42));
+        assert_ne!(random_fraction(7, 42), random_fraction(8, 42));
+    }
+}
@@ -0,0 +1,103 @@
+use engine::Query;
+use export::ExportFormat;
+use ingest::raw_val::RawVal;
+use ingest::schema::{ColumnSchema, Schema};
+use syntax::expression::Expr;
+
+
+#[derive(Debug)]
+pub enum Statement {
+    Select(Query),
+    Insert(Insert),
+    InsertSelect(InsertSelect),
+    CopyTo(CopyTo),
+    Delete(Delete),
+    CreateTable(CreateTable),
+    DropTable {
+        table: String,
+        if_exists: bool,
+    },
+    AddColumn {
+        table: String,
+        column: GeneratedColumn,
+    },
+    /// `CREATE VIEW view AS sql`, where `sql` is a query without aggregation, ordering or limit.
+    CreateView {
+        view: String,
+        sql: String,
+    },
+    DropView {
+        view: String,
+        if_exists: bool,
+    },
+}
+
+/// Column added by `ALTER TABLE name ADD COLUMN column AS expr [VIRTUAL | STORED]`, whose values are computed from the
+/// other columns of the table.
+#[derive(Debug, Clone)]
+pub struct GeneratedColumn {
+    pub name: String,
+    pub expr: Expr,
+    /// SQL text of `expr`, which is kept to persist the column.
+    pub sql: String,
+    /// Stored columns are computed when partitions are sealed, virtual columns whenever they are queried.
+    pub stored: bool,
+}
+
+/// Rows removed by a `DELETE FROM` statement.
+#[derive(Debug, Clone)]
+pub struct Delete {
+    pub table: String,
+    pub filter: Expr,
+}
+
+/// Table declared by a `CREATE TABLE` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+    pub table: String,
+    pub columns: Vec<(String, ColumnSchema)>,
+}
+
+impl CreateTable {
+    pub fn schema(&self) -> Schema {
+        self.columns.iter().fold(Schema::default(), |schema, (name, column)| schema.with_column(name, *column))
+    }
+}
+
+/// Rows written by an `INSERT INTO` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<RawVal>>,
+}
+
+/// Query whose result is written to a table by `CREATE TABLE name AS SELECT ...` or
+/// `INSERT INTO name [(column, ...)] SELECT ...`.
+#[derive(Debug, Clone)]
+pub struct InsertSelect {
+    pub table: String,
+    /// Names of the columns that the selected columns are written to, the names of the selected columns by default.
+    pub columns: Option<Vec<String>>,
+    pub query: Query,
+    /// Whether the table is created by the statement.
+    pub create: bool,
+}
+
+/// Query whose result is written to a file by `COPY (SELECT ...) TO 'file'`.
+#[derive(Debug, Clone)]
+pub struct CopyTo {
+    pub query: Query,
+    pub path: String,
+    pub format: ExportFormat,
+}
+
+impl Insert {
+    /// Pairs the values of each row with their column names.
+    pub fn named_rows(self) -> Vec<Vec<(String, RawVal)>> {
+        let columns = self.columns;
+        self.rows.into_iter()
+            .map(|row| columns.iter().cloned().zip(row).collect())
+            .collect()
+    }
+}
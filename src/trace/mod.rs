@@ -54,6 +54,32 @@ macro_rules! trace_replace {
     }
 }
 
+/// Starts a span that ends when the returned guard is dropped, e.g. `let planning = trace_span!("Plan {}", id);`.
+/// Unlike `trace_start!`, the span can end before the enclosing scope.
+#[macro_export]
+#[cfg(feature = "trace")]
+macro_rules! trace_span {
+    ( $( $x:expr),* ) => (
+        $crate::_start(format!( $( $x ),* ))
+    )
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! trace_span {
+    // Drop refs to args (which is no-op) to prevent unused variable warnings.
+    ( $( $x:expr),* ) => {{
+        $(
+            #[allow(clippy::drop_ref)]
+            drop(& $x);
+        )*
+        ::trace::NoSpan
+    }}
+}
+
+/// Stands in for the guard returned by `trace_span!` when compiled without the `trace` feature.
+#[cfg(not(feature = "trace"))]
+pub struct NoSpan;
+
 #[doc(hidden)]
 #[inline]
 pub fn _start(name: String) -> SpanGuard {
@@ -18,7 +18,11 @@ pub struct SpanCollector {
 
 impl SpanCollector {
     pub fn start_span(&mut self, name: String) -> SpanGuard {
-        let parent_id = self.active_spans.last().map_or(0, |id| *id);
+        // Spans are only recorded on threads that collect them for a trace, other spans would never be collected
+        let parent_id = match self.active_spans.last() {
+            Some(&id) => id,
+            None => return SpanGuard { id: 0 },
+        };
         let span = StartSpan::new(name, parent_id);
         let id = span.id;
         self.start(span);
@@ -37,6 +37,17 @@ impl TraceBuilder {
         self.finalized_collectors.push(local_collector);
     }
 
+    /// Runs `f` and adds the spans it starts on the current thread, e.g. before a task is scheduled. Spans of other
+    /// traces that are collected on the current thread are not affected.
+    pub fn collect_spans<T, F: FnOnce() -> T>(&mut self, f: F) -> T {
+        let outer = THREAD_LOCAL_COLLECTOR.with(|sc| sc.replace(SpanCollector::default()));
+        self.activate();
+        let result = f();
+        self.collect();
+        THREAD_LOCAL_COLLECTOR.with(|sc| sc.replace(outer));
+        result
+    }
+
     pub fn finalize(self) -> Trace {
         let toplevel = self.create_toplevel();
         let id = self.id;
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use QueryError;
+use engine::*;
+use ingest::raw_val::RawVal;
+use syntax::expression::*;
+
+
+/// Type of an argument or return value of a user-defined function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+    Integer,
+    Float,
+    String,
+}
+
+impl ValueType {
+    pub(crate) fn basic_type(self) -> BasicType {
+        match self {
+            ValueType::Integer => BasicType::Integer,
+            ValueType::Float => BasicType::Float,
+            ValueType::String => BasicType::String,
+        }
+    }
+
+    pub(crate) fn encoding_type(self) -> EncodingType {
+        self.basic_type().to_encoded()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub args: Vec<ValueType>,
+    pub returns: ValueType,
+    pub dictionary_eval: bool,
+}
+
+impl Signature {
+    pub fn new(args: Vec<ValueType>, returns: ValueType) -> Signature {
+        Signature { args, returns, dictionary_eval: false }
+    }
+
+    /// Allows the function to be evaluated once per dictionary entry instead of once per row when it is applied to a
+    /// dictionary encoded string column. Only valid for pure functions that take a single string argument.
+    pub fn with_dictionary_eval(mut self) -> Signature {
+        self.dictionary_eval = self.args == [ValueType::String];
+        self
+    }
+}
+
+/// Values of an argument of a vectorized function for all rows of a batch.
+#[derive(Debug, Clone, Copy)]
+pub enum ArgSlice<'a> {
+    Integer(&'a [i64]),
+    Float(&'a [f64]),
+    String(&'a [&'a str]),
+}
+
+impl<'a> ArgSlice<'a> {
+    pub fn len(&self) -> usize {
+        match *self {
+            ArgSlice::Integer(values) => values.len(),
+            ArgSlice::Float(values) => values.len(),
+            ArgSlice::String(values) => values.len(),
+        }
+    }
+}
+
+/// Values returned by a vectorized function, one for each row of the batch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResultVec {
+    Integer(Vec<i64>),
+    Float(Vec<f64>),
+    String(Vec<String>),
+}
+
+impl ResultVec {
+    pub fn len(&self) -> usize {
+        match *self {
+            ResultVec::Integer(ref values) => values.len(),
+            ResultVec::Float(ref values) => values.len(),
+            ResultVec::String(ref values) => values.len(),
+        }
+    }
+
+    pub fn get(&self, i: usize) -> RawVal {
+        match *self {
+            ResultVec::Integer(ref values) => values.get(i).map_or(RawVal::Null, |&v| RawVal::Int(v)),
+            ResultVec::Float(ref values) => values.get(i).map_or(RawVal::Null, |&v| RawVal::Float(OrderedF64(v))),
+            ResultVec::String(ref values) => values.get(i).map_or(RawVal::Null, |v| RawVal::Str(v.clone())),
+        }
+    }
+}
+
+enum Implementation {
+    /// Called once for each row.
+    Row(Box<Fn(&[RawVal]) -> RawVal + Send + Sync>),
+    /// Called once for each batch of rows.
+    Vectorized(Box<Fn(&[ArgSlice]) -> ResultVec + Send + Sync>),
+}
+
+pub struct ScalarFunction {
+    pub name: String,
+    pub signature: Signature,
+    function: Implementation,
+}
+
+impl ScalarFunction {
+    pub fn new<F>(name: &str, signature: Signature, function: F) -> ScalarFunction
+        where F: Fn(&[RawVal]) -> RawVal + Send + Sync + 'static {
+        ScalarFunction {
+            name: name.to_lowercase(),
+            signature,
+            function: Implementation::Row(Box::new(function)),
+        }
+    }
+
+    /// Function that is called with the values of its arguments for a batch of rows and returns one value per row.
+    pub fn vectorized<F>(name: &str, signature: Signature, function: F) -> ScalarFunction
+        where F: Fn(&[ArgSlice]) -> ResultVec + Send + Sync + 'static {
+        ScalarFunction {
+            name: name.to_lowercase(),
+            signature,
+            function: Implementation::Vectorized(Box::new(function)),
+        }
+    }
+
+    pub fn is_vectorized(&self) -> bool {
+        match self.function {
+            Implementation::Vectorized(_) => true,
+            Implementation::Row(_) => false,
+        }
+    }
+
+    pub fn call(&self, args: &[RawVal]) -> RawVal {
+        match self.function {
+            Implementation::Row(ref function) => function(args),
+            Implementation::Vectorized(ref function) => {
+                let ints = args.iter()
+                    .map(|arg| match *arg { RawVal::Int(i) => i, _ => 0 })
+                    .collect::<Vec<_>>();
+                let floats = args.iter()
+                    .map(|arg| match *arg {
+                        RawVal::Float(f) => f.0,
+                        RawVal::Int(i) => i as f64,
+                        _ => ::std::f64::NAN,
+                    })
+                    .collect::<Vec<_>>();
+                let strings = args.iter()
+                    .map(|arg| match *arg { RawVal::Str(ref s) => s.as_str(), _ => "" })
+                    .collect::<Vec<_>>();
+                let slices = self.signature.args.iter().enumerate()
+                    .map(|(i, t)| match *t {
+                        ValueType::Integer => ArgSlice::Integer(&ints[i..i + 1]),
+                        ValueType::Float => ArgSlice::Float(&floats[i..i + 1]),
+                        ValueType::String => ArgSlice::String(&strings[i..i + 1]),
+                    })
+                    .collect::<Vec<_>>();
+                function(&slices).get(0)
+            }
+        }
+    }
+
+    /// Evaluates a vectorized function on `args`, which must contain the same number of rows.
+    pub fn call_vectorized(&self, args: &[ArgSlice]) -> ResultVec {
+        match self.function {
+            Implementation::Vectorized(ref function) => function(args),
+            Implementation::Row(_) => panic!("Function {} is not vectorized", self.name),
+        }
+    }
+}
+
+impl fmt::Debug for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{:?} -> {:?}", self.name, self.signature.args, self.signature.returns)
+    }
+}
+
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Arc<ScalarFunction>>,
+}
+
+impl FunctionRegistry {
+    pub fn register(&mut self, function: ScalarFunction) {
+        self.functions.insert(function.name.clone(), Arc::new(function));
+    }
+
+    /// Replaces all calls to functions that are not built in with the corresponding user-defined function.
+    pub fn resolve(&self, query: Query) -> Result<Query, QueryError> {
+        let select = query.select.into_iter()
+            .map(|expr| self.resolve_expr(expr))
+            .collect::<Result<Vec<_>, _>>()?;
+        let filter = self.resolve_expr(query.filter)?;
+        let order_by = query.order_by.into_iter()
+            .map(|(expr, desc)| self.resolve_expr(expr).map(|expr| (expr, desc)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Query {
+            select,
+            table: query.table,
+            filter,
+            order_by,
+            limit: query.limit,
+            distinct: query.distinct,
+            aliases: query.aliases,
+            rollup: query.rollup,
+            sum_overflow: query.sum_overflow,
+            sample: query.sample,
+        })
+    }
+
+    pub fn resolve_expr(&self, expr: Expr) -> Result<Expr, QueryError> {
+        expr.map_functions(&mut |name: String, args: Vec<Expr>| match self.functions.get(&name) {
+            Some(function) => Ok(Expr::Udf(function.clone(), args)),
+            None => Err(QueryError::NotImplemented(format!("Function {:?}", name))),
+        })
+    }
+}
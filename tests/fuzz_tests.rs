@@ -0,0 +1,407 @@
+extern crate env_logger;
+extern crate futures_executor;
+extern crate locustdb;
+
+use std::collections::BTreeMap;
+use std::env;
+
+use futures_executor::block_on;
+use locustdb::builder::*;
+use locustdb::*;
+use locustdb::Value::*;
+
+/// Runs random queries on random tables and compares the results of LocustDB to those of a naive reference
+/// interpreter. `LOCUSTDB_FUZZ_CASES` sets the number of tables and `LOCUSTDB_FUZZ_SEED` the seed, which is printed
+/// together with the query for any mismatch so that it can be reproduced.
+#[test]
+fn test_fuzz_queries() {
+    let _ = env_logger::try_init();
+    let cases = env::var("LOCUSTDB_FUZZ_CASES").ok().and_then(|cases| cases.parse().ok()).unwrap_or(40);
+    let seed = env::var("LOCUSTDB_FUZZ_SEED").ok().and_then(|seed| seed.parse().ok()).unwrap_or(0x10c5_7db);
+    for case in 0..cases {
+        run_case(seed + case);
+    }
+}
+
+fn run_case(seed: u64) {
+    let mut rng = Rng::new(seed);
+    let table = TableSpec::generate(&mut rng);
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(table.partition_size)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("fuzz");
+    writer.write_all(table.rows.iter().map(|row| {
+        COLUMNS.iter().map(|&column| (column.name().to_string(), row[column as usize].clone())).collect()
+    })).unwrap();
+    writer.flush();
+
+    for _ in 0..QUERIES_PER_CASE {
+        let query = FuzzQuery::generate(&mut rng, &table);
+        let expected = match query.reference(&table.rows) {
+            Some(expected) => expected,
+            None => continue,
+        };
+        let mut actual = match block_on(locustdb.run(query.build())).unwrap().0 {
+            Ok(output) => output.rows,
+            Err(err) => panic!("seed {}: {:?} failed: {:?}", seed, query, err),
+        };
+        if !query.is_ordered() {
+            actual.sort();
+        }
+        assert_eq!(actual, expected, "seed {}: {:?}", seed, query);
+    }
+}
+
+const QUERIES_PER_CASE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column {
+    Id,
+    /// Small nullable integers.
+    A,
+    /// Integers with a random range that may include negative and large values.
+    B,
+    /// Low cardinality integers with a random offset.
+    C,
+    /// Low cardinality strings.
+    S,
+}
+
+const COLUMNS: [Column; 5] = [Column::Id, Column::A, Column::B, Column::C, Column::S];
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::A => "a",
+            Column::B => "b",
+            Column::C => "c",
+            Column::S => "s",
+        }
+    }
+}
+
+struct TableSpec {
+    rows: Vec<Vec<Value>>,
+    partition_size: usize,
+    b_range: i64,
+    c_offset: i64,
+    c_cardinality: i64,
+    strings: Vec<&'static str>,
+}
+
+impl TableSpec {
+    fn generate(rng: &mut Rng) -> TableSpec {
+        let len = 1 + rng.below(400);
+        let partition_size = *rng.pick(&[8, 33, 100, 1000]);
+        let null_chance = *rng.pick(&[0.0, 0.3]);
+        let b_range = *rng.pick(&[10i64, 1000, 1 << 40]);
+        let c_offset = *rng.pick(&[0i64, -3, 1_000_000, 1 << 33]);
+        let c_cardinality = 1 + rng.below(8) as i64;
+        let mut strings = vec!["a", "alpha", "beta", "gamma", "delta epsilon", "ünïcödé"];
+        rng.shuffle(&mut strings);
+        strings.truncate(1 + rng.below(6));
+
+        let rows = (0..len)
+            .map(|id| {
+                let a = if rng.chance(null_chance) { Null } else { Int(rng.below(20) as i64) };
+                let b = Int(rng.range(-b_range, b_range));
+                let c = Int(c_offset + rng.below(c_cardinality as usize) as i64);
+                let s = Str(rng.pick(&strings).to_string());
+                vec![Int(id as i64), a, b, c, s]
+            })
+            .collect();
+        TableSpec { rows, partition_size, b_range, c_offset, c_cardinality, strings }
+    }
+
+    /// Constant to compare `column` with, which is likely but not guaranteed to occur in the column.
+    fn constant(&self, rng: &mut Rng, column: Column) -> Value {
+        if rng.chance(0.5) {
+            match self.rows[rng.below(self.rows.len())][column as usize] {
+                Null => {}
+                ref value => return value.clone(),
+            }
+        }
+        match column {
+            Column::Id => Int(rng.range(-1, self.rows.len() as i64 + 1)),
+            Column::A => Int(rng.range(-1, 21)),
+            Column::B => Int(rng.range(-self.b_range - 1, self.b_range + 1)),
+            Column::C => Int(self.c_offset + rng.range(-1, self.c_cardinality + 1)),
+            Column::S => if rng.chance(0.8) { Str(rng.pick(&self.strings).to_string()) } else { Str("missing".to_string()) },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Equals,
+    NotEquals,
+    LT,
+    LTE,
+    GT,
+    GTE,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Compare(Column, Op, Value),
+    IsNull(Column),
+    IsNotNull(Column),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    fn generate(rng: &mut Rng, table: &TableSpec, depth: usize) -> Condition {
+        if depth > 0 && rng.chance(0.4) {
+            let lhs = Box::new(Condition::generate(rng, table, depth - 1));
+            let rhs = Box::new(Condition::generate(rng, table, depth - 1));
+            return if rng.chance(0.5) { Condition::And(lhs, rhs) } else { Condition::Or(lhs, rhs) };
+        }
+        let column = *rng.pick(&COLUMNS);
+        match column {
+            Column::S => {
+                let op = *rng.pick(&[Op::Equals, Op::NotEquals]);
+                Condition::Compare(column, op, table.constant(rng, column))
+            }
+            Column::A if rng.chance(0.3) => if rng.chance(0.5) {
+                Condition::IsNull(column)
+            } else {
+                Condition::IsNotNull(column)
+            },
+            _ => {
+                let op = *rng.pick(&[Op::Equals, Op::NotEquals, Op::LT, Op::LTE, Op::GT, Op::GTE]);
+                Condition::Compare(column, op, table.constant(rng, column))
+            }
+        }
+    }
+
+    fn expr(&self) -> Expr {
+        match *self {
+            Condition::Compare(column, op, ref value) => {
+                let (lhs, rhs) = (col(column.name()), Expr::Const(value.clone()));
+                match op {
+                    Op::Equals => lhs.equals(rhs),
+                    Op::NotEquals => lhs.not_equals(rhs),
+                    Op::LT => lhs.lt(rhs),
+                    Op::LTE => lhs.lte(rhs),
+                    Op::GT => lhs.gt(rhs),
+                    Op::GTE => lhs.gte(rhs),
+                }
+            }
+            Condition::IsNull(column) => col(column.name()).is_null(),
+            Condition::IsNotNull(column) => col(column.name()).is_not_null(),
+            Condition::And(ref lhs, ref rhs) => lhs.expr().and(rhs.expr()),
+            Condition::Or(ref lhs, ref rhs) => lhs.expr().or(rhs.expr()),
+        }
+    }
+
+    /// Whether `row` satisfies the condition. Comparisons with null are never satisfied, which is equivalent to three
+    /// valued logic since conditions contain no negations.
+    fn eval(&self, row: &[Value]) -> bool {
+        match *self {
+            Condition::Compare(column, op, ref value) => {
+                let lhs = &row[column as usize];
+                if *lhs == Null {
+                    return false;
+                }
+                match op {
+                    Op::Equals => lhs == value,
+                    Op::NotEquals => lhs != value,
+                    Op::LT => lhs < value,
+                    Op::LTE => lhs <= value,
+                    Op::GT => lhs > value,
+                    Op::GTE => lhs >= value,
+                }
+            }
+            Condition::IsNull(column) => row[column as usize] == Null,
+            Condition::IsNotNull(column) => row[column as usize] != Null,
+            Condition::And(ref lhs, ref rhs) => lhs.eval(row) && rhs.eval(row),
+            Condition::Or(ref lhs, ref rhs) => lhs.eval(row) || rhs.eval(row),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Aggregate {
+    CountRows,
+    Count(Column),
+    Sum(Column),
+}
+
+#[derive(Debug)]
+enum Shape {
+    Select(Vec<Column>),
+    /// Selects `b`, `id` and `columns`, ordered by `b` and then `id`.
+    TopN { columns: Vec<Column>, desc: bool, limit: usize },
+    Aggregate { group_by: Vec<Column>, aggregates: Vec<Aggregate> },
+}
+
+#[derive(Debug)]
+struct FuzzQuery {
+    filter: Option<Condition>,
+    shape: Shape,
+}
+
+impl FuzzQuery {
+    fn generate(rng: &mut Rng, table: &TableSpec) -> FuzzQuery {
+        let filter = if rng.chance(0.3) { None } else { Some(Condition::generate(rng, table, 2)) };
+        let shape = match rng.below(3) {
+            0 => Shape::Select(rng.subset(&COLUMNS, 1)),
+            1 => Shape::TopN {
+                columns: rng.subset(&[Column::A, Column::C, Column::S], 0),
+                desc: rng.chance(0.5),
+                limit: 1 + rng.below(20),
+            },
+            _ => Shape::Aggregate {
+                group_by: rng.subset(&[Column::C, Column::S], 0),
+                aggregates: rng.subset(&[
+                    Aggregate::CountRows,
+                    Aggregate::Count(Column::A),
+                    Aggregate::Sum(Column::B),
+                    Aggregate::Sum(Column::C),
+                ], 1),
+            },
+        };
+        FuzzQuery { filter, shape }
+    }
+
+    fn build(&self) -> Query {
+        let mut query = Query::table("fuzz");
+        if let Some(ref filter) = self.filter {
+            query = query.filter(filter.expr());
+        }
+        query = match self.shape {
+            Shape::Select(ref columns) => columns.iter().fold(query, |query, column| query.select(col(column.name()))),
+            Shape::TopN { ref columns, desc, limit } => {
+                [Column::B, Column::Id].iter().chain(columns.iter())
+                    .fold(query, |query, column| query.select(col(column.name())))
+                    .order_by(col("b"), desc)
+                    .order_by(col("id"), false)
+                    .limit(limit as u64)
+            }
+            Shape::Aggregate { ref group_by, ref aggregates } => {
+                let query = group_by.iter().fold(query, |query, column| query.group_by(col(column.name())));
+                aggregates.iter().fold(query, |query, &aggregate| query.aggregate(match aggregate {
+                    Aggregate::CountRows => count(Expr::Const(Int(0))),
+                    Aggregate::Count(column) => count(col(column.name())),
+                    Aggregate::Sum(column) => sum(col(column.name())),
+                }))
+            }
+        };
+        query.build().unwrap()
+    }
+
+    fn is_ordered(&self) -> bool {
+        match self.shape {
+            Shape::TopN { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Result of the query computed row by row, sorted unless the query is ordered. `None` if the result of the query
+    /// is not well defined.
+    fn reference(&self, rows: &[Vec<Value>]) -> Option<Vec<Vec<Value>>> {
+        let rows = rows.iter()
+            .filter(|row| self.filter.as_ref().map_or(true, |filter| filter.eval(row)))
+            .collect::<Vec<_>>();
+        let project = |row: &[Value], columns: &[Column]| columns.iter()
+            .map(|&column| row[column as usize].clone())
+            .collect::<Vec<_>>();
+        let mut result = match self.shape {
+            Shape::Select(ref columns) => rows.iter().map(|row| project(row, columns)).collect::<Vec<_>>(),
+            Shape::TopN { ref columns, desc, limit } => {
+                let mut rows = rows.clone();
+                rows.sort_by(|lhs, rhs| {
+                    let (lhs_b, rhs_b) = (&lhs[Column::B as usize], &rhs[Column::B as usize]);
+                    let by_b = if desc { rhs_b.cmp(lhs_b) } else { lhs_b.cmp(rhs_b) };
+                    by_b.then_with(|| lhs[Column::Id as usize].cmp(&rhs[Column::Id as usize]))
+                });
+                let mut selected = vec![Column::B, Column::Id];
+                selected.extend(columns.iter().cloned());
+                return Some(rows.iter().take(limit).map(|row| project(row, &selected)).collect());
+            }
+            Shape::Aggregate { ref group_by, ref aggregates } => {
+                // Aggregating zero rows without grouping may or may not produce a row
+                if group_by.is_empty() && rows.is_empty() {
+                    return None;
+                }
+                let mut groups = BTreeMap::<Vec<Value>, Vec<i64>>::new();
+                for row in &rows {
+                    let accumulators = groups.entry(project(row, group_by)).or_insert_with(|| vec![0; aggregates.len()]);
+                    for (accumulator, &aggregate) in accumulators.iter_mut().zip(aggregates.iter()) {
+                        match aggregate {
+                            Aggregate::CountRows => *accumulator += 1,
+                            Aggregate::Count(column) => if row[column as usize] != Null { *accumulator += 1 },
+                            Aggregate::Sum(column) => if let Int(value) = row[column as usize] { *accumulator += value },
+                        }
+                    }
+                }
+                groups.into_iter()
+                    .map(|(mut group, accumulators)| {
+                        group.extend(accumulators.into_iter().map(Int));
+                        group
+                    })
+                    .collect()
+            }
+        };
+        result.sort();
+        Some(result)
+    }
+}
+
+/// Xorshift generator, so that failures can be reproduced from the seed on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // Avoid the all zero state and weak initial outputs for small seeds
+        let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+        for _ in 0..4 {
+            rng.next();
+        }
+        rng
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Random integer in `[min, max)`.
+    fn range(&mut self, min: i64, max: i64) -> i64 {
+        min + (self.next() % (max - min) as u64) as i64
+    }
+
+    fn chance(&mut self, p: f64) -> bool {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64 < p
+    }
+
+    fn pick<'a, T>(&mut self, values: &'a [T]) -> &'a T {
+        &values[self.below(values.len())]
+    }
+
+    fn shuffle<T>(&mut self, values: &mut [T]) {
+        for i in (1..values.len()).rev() {
+            let j = self.below(i + 1);
+            values.swap(i, j);
+        }
+    }
+
+    /// Random selection of at least `min` of `values`, in random order.
+    fn subset<T: Clone>(&mut self, values: &[T], min: usize) -> Vec<T> {
+        let mut subset = values.to_vec();
+        self.shuffle(&mut subset);
+        let len = min + self.below(values.len() - min + 1);
+        subset.truncate(len);
+        subset
+    }
+}
@@ -164,6 +164,135 @@ fn lt_filter_on_offset_encoded_column() {
     )
 }
 
+#[test]
+fn test_between() {
+    test_query("select count(1) from default where num between 1 and 3;", &[vec![84.into()]]);
+    test_query("select count(1) from default where num not between 1 and 3;", &[vec![16.into()]]);
+    test_query("select count(1) from default where num < 5 and ts > 0 and 2 <= num;", &[vec![40.into()]]);
+    test_query_ec(
+        "select u8_offset_encoded from default where u8_offset_encoded between 200 and 256;",
+        &[vec![256.into()]],
+    );
+}
+
+#[test]
+fn test_invalid_queries() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder().threads(0).build().unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..10).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+
+    let error = |query: &str| match block_on(locustdb.run_query(query, false, vec![])).unwrap().0 {
+        Err(err) => err,
+        Ok(output) => panic!("Expected error, got {:?}", output.rows),
+    };
+    assert_eq!(error("SELECT sum(count(1)) FROM items;").to_string(),
+               "Invalid query: Aggregate count(1) is nested inside aggregate sum(count(1))");
+    assert_eq!(error("SELECT id FROM items WHERE count(1) > 2;").to_string(),
+               "Invalid query: Aggregate count(1) is not allowed in WHERE clause");
+    let unknown = error("SELECT id FROM items ORDER BY idd + 1;");
+    match unknown.kind() {
+        QueryError::UnknownColumn { column, suggestion, .. } => {
+            assert_eq!(column, "idd");
+            assert_eq!(suggestion, &Suggestion(Some("id".to_string())));
+        }
+        err => panic!("Expected unknown column, got {:?}", err),
+    }
+    assert_eq!(unknown.span().map(|span| span.offset), Some(30));
+    assert_eq!(unknown.to_string(), "Column idd does not exist in table items, did you mean id?\n  \
+               SELECT id FROM items ORDER BY idd + 1;\n                                ^^^");
+}
+
+#[test]
+fn test_wildcards() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder().threads(0).build().unwrap();
+    let writer = locustdb.table_writer("events");
+    writer.write_all((0..3).map(|i| vec![
+        ("ts".to_string(), Int(i)),
+        ("metrics_cpu".to_string(), Int(i * 10)),
+        ("metrics_mem".to_string(), Int(i * 100)),
+        ("user".to_string(), Str("a")),
+    ])).unwrap();
+    writer.flush();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0;
+    let output = run("SELECT ts, metrics_* FROM events WHERE ts = 1;").unwrap();
+    assert_eq!(output.colnames, vec!["ts", "metrics_cpu", "metrics_mem"]);
+    assert_eq!(output.rows, vec![vec![Int(1), Int(10), Int(100)]]);
+    let output = run("SELECT * EXCEPT(metrics_mem, user) FROM events WHERE ts = 2;").unwrap();
+    assert_eq!(output.colnames, vec!["metrics_cpu", "ts"]);
+    assert_eq!(output.rows, vec![vec![Int(20), Int(2)]]);
+    let output = run("SELECT metrics_* except (metrics_cpu) FROM events WHERE ts = 2;").unwrap();
+    assert_eq!(output.colnames, vec!["metrics_mem"]);
+    assert!(run("SELECT * EXCEPT(metrics_gpu) FROM events;").is_err());
+    assert!(run("SELECT latency_* FROM events;").is_err());
+}
+
+#[test]
+fn test_sample() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap();
+
+    let full = run("SELECT count(0) FROM default TABLESAMPLE SYSTEM (100);");
+    assert_eq!(full.rows, vec![vec![Value::Int(100)]]);
+    assert_eq!(full.stats.sampling_factor, 1.0);
+
+    let rows = run("SELECT count(0) FROM default TABLESAMPLE BERNOULLI (50) REPEATABLE (3) WHERE num >= 0;");
+    assert_eq!(rows.stats.sampling_factor, 0.5);
+    match rows.rows[0][0] {
+        Value::Int(count) => assert!(count > 20 && count < 80, "{}", count),
+        ref value => panic!("Expected count, got {:?}", value),
+    }
+    let repeated = run("SELECT count(0) FROM default TABLESAMPLE BERNOULLI (50) REPEATABLE (3) WHERE num >= 0;");
+    assert_eq!(rows.rows, repeated.rows);
+
+    // Partitions contain 40, 40 and 20 rows, so two of them are needed for 50 rows
+    let partitions = run("SELECT count(0) FROM default SAMPLE (50);");
+    match partitions.rows[0][0] {
+        Value::Int(count) => {
+            assert!(count == 60 || count == 80, "{}", count);
+            assert_eq!(partitions.stats.sampling_factor, count as f64 / 100.0);
+        }
+        ref value => panic!("Expected count, got {:?}", value),
+    }
+    assert!(block_on(locustdb.run_query("SELECT count(0) FROM default TABLESAMPLE SYSTEM (150);", false, vec![]))
+        .unwrap().0.is_err());
+}
+
+#[test]
+fn test_merge_spill() {
+    let _ = env_logger::try_init();
+    let load = |locustdb: LocustDB| {
+        let _ = block_on(locustdb.load_csv(
+            LoadOptions::new("test_data/tiny.csv", "default")
+                .with_partition_size(10)));
+        locustdb
+    };
+    let run = |locustdb: &LocustDB, query: &str|
+        block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    let default = load(LocustDB::memory_only());
+    // Every partial result is written to disk before it is merged
+    let spilling = load(LocustDB::builder()
+        .merge_fan_in(3)
+        .merge_spill(0, None)
+        .build()
+        .unwrap());
+    for query in &[
+        "SELECT tld, count(0), sum(num) FROM default;",
+        "SELECT first_name, ts FROM default ORDER BY ts DESC LIMIT 7;",
+        "SELECT num, count(0) FROM default WHERE num > 2;",
+    ] {
+        assert_eq!(run(&spilling, query), run(&default, query), "{}", query);
+    }
+    assert!(LocustDB::builder().merge_fan_in(1).build().is_err());
+}
+
 #[test]
 fn test_group_by_limit() {
     use Value::*;
@@ -176,6 +305,53 @@ fn test_group_by_limit() {
     )
 }
 
+#[test]
+fn test_min_max() {
+    use Value::*;
+    test_query_ec(
+        "SELECT enum, min(id), max(id) FROM default;",
+        &[
+            vec![Str("aa".to_string()), Int(0), Int(7)],
+            vec![Str("bb".to_string()), Int(3), Int(9)],
+            vec![Str("cc".to_string()), Int(6), Int(8)],
+        ],
+    );
+    test_query_ec("SELECT min(id), max(id) FROM default WHERE id > 2;", &[vec![Int(3), Int(9)]]);
+}
+
+#[test]
+fn test_aggregates_from_statistics() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let output = block_on(locustdb.run_query(
+        "SELECT count(1), min(ts) AS first, max(ts) FROM default;", false, vec![])).unwrap().0.unwrap();
+    assert_eq!(output.colnames, vec!["count_0", "first", "max_2"]);
+    assert_eq!(output.rows, vec![vec![Value::Int(100), Value::Int(1456591230), Value::Int(1487173444)]]);
+    assert_eq!(output.stats.rows_scanned, 0);
+
+    // Filtered queries scan the table
+    let output = block_on(locustdb.run_query(
+        "SELECT count(1), max(ts) FROM default WHERE ts > 0;", false, vec![])).unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![vec![Value::Int(100), Value::Int(1487173444)]]);
+    assert!(output.stats.rows_scanned > 0);
+}
+
+#[test]
+fn test_group_by_dictionary() {
+    use Value::*;
+    test_query_ec(
+        "SELECT enum, count(0), sum(id) FROM default WHERE id > 1;",
+        &[
+            vec![Str("aa".to_string()), Int(3), Int(14)],
+            vec![Str("bb".to_string()), Int(3), Int(16)],
+            vec![Str("cc".to_string()), Int(2), Int(14)],
+        ],
+    )
+}
+
 #[test]
 fn group_by_string_filter_string_eq() {
     test_query(
@@ -313,6 +489,27 @@ fn test_order_by_aggregate() {
     )
 }
 
+#[test]
+fn test_top_n_groups_by_aggregate() {
+    test_query(
+        "SELECT tld, count(0) FROM default ORDER BY count(0) DESC, tld LIMIT 4;",
+        &[
+            vec![Str("name"), Int(17)],
+            vec![Str("edu"), Int(13)],
+            vec![Str("info"), Int(13)],
+            vec![Str("mil"), Int(11)],
+        ],
+    );
+    test_query(
+        "SELECT tld, length(first_name), count(0) FROM default ORDER BY count(0) DESC, tld LIMIT 3;",
+        &[
+            vec![Str("name"), Int(5), Int(6)],
+            vec![Str("info"), Int(5), Int(5)],
+            vec![Str("name"), Int(7), Int(5)],
+        ],
+    );
+}
+
 
 #[test]
 fn test_groupless_aggregate() {
@@ -332,6 +529,287 @@ fn test_groupless_aggregate() {
     );
 }
 
+#[test]
+fn test_avg() {
+    test_query(
+        "SELECT avg(ts) FROM default;",
+        &[vec![Int(1471343196)]],
+    );
+    test_query(
+        "SELECT avg(ts), count(0) FROM default WHERE tld = 'biz';",
+        &[vec![Int(1466543175), Int(10)]],
+    );
+}
+
+#[test]
+fn test_approx_count_distinct() {
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let result = block_on(locustdb.run_query(
+        "SELECT approx_count_distinct(first_name), approx_count_distinct(ts / 10000000) FROM default;", false, vec![])).unwrap();
+    let rows = result.0.unwrap().rows;
+    match (&rows[0][0], &rows[0][1]) {
+        (&Value::Int(first_names), &Value::Int(timestamps)) => {
+            assert!(first_names >= 60 && first_names <= 100, "first_names = {}", first_names);
+            assert!(timestamps >= 3 && timestamps <= 5, "timestamps = {}", timestamps);
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_percentile() {
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let result = block_on(locustdb.run_query(
+        "SELECT percentile(ts, 0.5), percentile(ts, 1.0) FROM default;", false, vec![])).unwrap();
+    let rows = result.0.unwrap().rows;
+    match (&rows[0][0], &rows[0][1]) {
+        (&Value::Int(median), &Value::Int(max)) => {
+            assert!((median - 1471137927).abs() < 14711379, "median = {}", median);
+            assert!((max - 1487173444).abs() < 14871734, "max = {}", max);
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn test_histogram() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(30)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("latencies");
+    writer.write_all((0..100).map(|i| vec![("v".to_string(), Int(i - 20)), ("g".to_string(), Int(i % 2))])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+
+    assert_eq!(run("SELECT histogram(v, 25) FROM latencies;"), vec![vec![Str("-25:20,0:25,25:25,50:25,75:5")]]);
+    assert_eq!(
+        run("SELECT g, histogram(v, 50), count(0) FROM latencies ORDER BY g;"),
+        vec![
+            vec![Int(0), Str("-50:10,0:25,50:15"), Int(50)],
+            vec![Int(1), Str("-50:10,0:25,50:15"), Int(50)],
+        ]
+    );
+}
+
+#[test]
+fn test_arg_max() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(16)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("readings");
+    writer.write_all((0..60).map(|i| vec![
+        ("device".to_string(), Str(["a", "b", "c"][i as usize % 3])),
+        ("ts".to_string(), Int(i * 7 % 60)),
+        ("value".to_string(), Int(i)),
+        ("label".to_string(), Str(&format!("v{}", i))),
+        ("score".to_string(), Float(i as f64 / 2.0)),
+        ("odd".to_string(), if i % 2 == 1 { Int(i) } else { Null }),
+        ("late".to_string(), if i < 50 { Int(i * 7 % 60) } else { Null }),
+    ])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+
+    assert_eq!(
+        run("SELECT device, arg_max(value, ts), arg_min(value, ts) FROM readings ORDER BY device;"),
+        vec![
+            vec![Str("a"), Int(51), Int(0)],
+            vec![Str("b"), Int(34), Int(43)],
+            vec![Str("c"), Int(17), Int(26)],
+        ]
+    );
+    assert_eq!(run("SELECT arg_max(label, ts), count(0) FROM readings;"), vec![vec![Str("v17"), Int(60)]]);
+    assert_eq!(
+        run("SELECT device, arg_max(value, ts) FROM readings ORDER BY arg_max(value, ts);"),
+        vec![vec![Str("c"), Int(17)], vec![Str("b"), Int(34)], vec![Str("a"), Int(51)]]
+    );
+    assert_eq!(
+        run("SELECT device, arg_max(value, ts) + 1 FROM readings ORDER BY device;"),
+        vec![vec![Str("a"), Int(52)], vec![Str("b"), Int(35)], vec![Str("c"), Int(18)]]
+    );
+    assert_eq!(
+        run("SELECT device, arg_max(score, ts), arg_max(odd, ts) FROM readings ORDER BY device;"),
+        vec![
+            vec![Str("a"), Float(25.5), Int(51)],
+            vec![Str("b"), Float(17.0), Null],
+            vec![Str("c"), Float(8.5), Int(17)],
+        ]
+    );
+    // Rows with a null key are never selected
+    assert_eq!(
+        run("SELECT device, arg_max(value, late), arg_min(value, late) FROM readings ORDER BY device;"),
+        vec![
+            vec![Str("a"), Int(42), Int(0)],
+            vec![Str("b"), Int(34), Int(43)],
+            vec![Str("c"), Int(17), Int(26)],
+        ]
+    );
+}
+
+#[test]
+fn test_distinct() {
+    test_query(
+        "SELECT DISTINCT ts / 10000000 FROM default ORDER BY ts / 10000000 DESC LIMIT 3;",
+        &[vec![Int(148)], vec![Int(147)], vec![Int(146)]],
+    );
+}
+
+#[test]
+fn test_in_list() {
+    test_query(
+        "SELECT tld, COUNT(0) FROM default WHERE tld IN ('gov', \"mil\", 'xyz') ORDER BY tld;",
+        &[vec![Str("gov"), Int(5)], vec![Str("mil"), Int(11)]],
+    );
+}
+
+#[test]
+fn test_like() {
+    test_query(
+        "SELECT COUNT(0) FROM default WHERE tld LIKE '%o%';",
+        &[vec![Int(35)]],
+    );
+    test_query(
+        "SELECT COUNT(0) FROM default WHERE first_name LIKE 'A___' OR first_name NOT LIKE 'A%';",
+        &[vec![Int(94)]],
+    );
+}
+
+#[test]
+fn test_case_insensitive() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder().threads(0).build().unwrap();
+    let writer = locustdb.table_writer("langs");
+    let names = ["Rust", "rust", "RUST", "Go", "go", "Zig"];
+    writer.write_all((0..60).map(|i| vec![("name".to_string(), Str(names[i % names.len()]))])).unwrap();
+    writer.flush();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT COUNT(0) FROM langs WHERE name ILIKE 'rust';"), vec![vec![Int(30)]]);
+    assert_eq!(run("SELECT COUNT(0) FROM langs WHERE name NOT ILIKE 'G%';"), vec![vec![Int(40)]]);
+    assert_eq!(
+        run("SELECT LOWER(name), COUNT(0) FROM langs ORDER BY LOWER(name);"),
+        vec![vec![Str("go"), Int(20)], vec![Str("rust"), Int(30)], vec![Str("zig"), Int(10)]],
+    );
+    assert_eq!(
+        run("SELECT UPPER(name), COUNT(0) FROM langs WHERE UPPER(name) = 'GO';"),
+        vec![vec![Str("GO"), Int(20)]],
+    );
+}
+
+#[test]
+fn test_string_functions() {
+    test_query(
+        "SELECT ts, LOWER(first_name), LENGTH(last_name), CONCAT(UPPER(SUBSTR(first_name, 2, 3)), '-', last_name) \
+         FROM default ORDER BY ts LIMIT 2;",
+        &[
+            vec![Int(1456591230), Str("charles"), Int(4), Str("HAR-Dunn")],
+            vec![Int(1456633115), Str("paula"), Int(5), Str("AUL-Lopez")],
+        ],
+    );
+    test_query(
+        "SELECT UPPER(tld), COUNT(0) FROM default WHERE tld IN ('com', 'org') ORDER BY UPPER(tld);",
+        &[vec![Str("COM"), Int(9)], vec![Str("ORG"), Int(8)]],
+    );
+}
+
+#[test]
+fn test_time_functions() {
+    test_query(
+        "SELECT ts, DATE_TRUNC('hour', ts), DATE_TRUNC('month', TO_TIMESTAMP(ts * 1000, 'ms')), \
+         EXTRACT(hour FROM ts), EXTRACT(dow FROM ts) FROM default ORDER BY ts LIMIT 1;",
+        &[vec![Int(1456591230), Int(1456588800), Int(1454284800), Int(16), Int(6)]],
+    );
+    test_query(
+        "SELECT EXTRACT(year FROM ts), COUNT(0) FROM default ORDER BY EXTRACT(year FROM ts);",
+        &[vec![Int(2016), Int(89)], vec![Int(2017), Int(11)]],
+    );
+    test_query(
+        "SELECT COUNT(0) FROM default WHERE ts < TO_TIMESTAMP('2016-03-01');",
+        &[vec![Int(2)]],
+    );
+}
+
+#[test]
+fn test_window_functions() {
+    test_query(
+        "SELECT ts, ROW_NUMBER() OVER (PARTITION BY ts / 10000000 ORDER BY ts DESC), LAG(ts) OVER (ORDER BY ts), \
+         SUM(ts / 1000000000) OVER (ORDER BY ts) FROM default ORDER BY ts LIMIT 3;",
+        &[
+            vec![Int(1456591230), Int(11), Null, Int(1)],
+            vec![Int(1456633115), Int(10), Int(1456591230), Int(2)],
+            vec![Int(1457460805), Int(9), Int(1456633115), Int(3)],
+        ],
+    );
+}
+
+#[test]
+fn test_float_columns() {
+    test_query_ec(
+        "SELECT sum(fractional), avg(fractional) FROM default;",
+        &[vec![Float(24.875), Float(2.4875)]],
+    );
+    test_query_ec(
+        "SELECT id, fractional * 2 FROM default WHERE fractional > 4 ORDER BY id;",
+        &[
+            vec![Int(4), Float(20.0)],
+            vec![Int(6), Float(9.0)],
+            vec![Int(8), Float(14.5)],
+        ],
+    );
+    test_query_ec(
+        "SELECT id FROM default ORDER BY fractional LIMIT 3;",
+        &[vec![Int(2)], vec![Int(7)], vec![Int(5)]],
+    );
+}
+
+#[test]
+fn test_cast() {
+    test_query_ec(
+        "SELECT id, CAST(fractional AS INT), CAST(id AS FLOAT) / 4, CAST(negative AS STRING) FROM default WHERE id < 3 ORDER BY id;",
+        &[
+            vec![Int(0), Int(0), Float(0.0), Str("-199")],
+            vec![Int(1), Int(1), Float(0.25), Str("39")],
+            vec![Int(2), Int(-3), Float(0.5), Str("-100")],
+        ],
+    );
+    test_query_ec(
+        "SELECT CAST(CAST(negative AS VARCHAR) AS BIGINT) + CAST('12' AS INT), CAST('2.5' AS DOUBLE) * id FROM default WHERE id = 2;",
+        &[vec![Int(-88), Float(5.0)]],
+    );
+}
+
+#[test]
+fn test_null_semantics() {
+    test_query_ec(
+        "SELECT id FROM default WHERE nullable_int IS NULL AND nullable_int2 IS NOT NULL ORDER BY id;",
+        &[vec![Int(3)], vec![Int(5)], vec![Int(8)]],
+    );
+    test_query_ec(
+        "SELECT id, COALESCE(nullable_int, nullable_int2, -1) FROM default WHERE id < 4 ORDER BY id;",
+        &[
+            vec![Int(0), Int(-1)],
+            vec![Int(1), Int(-40)],
+            vec![Int(2), Int(-1)],
+            vec![Int(3), Int(0)],
+        ],
+    );
+    test_query_ec(
+        "SELECT COUNT(nullable_int), SUM(nullable_int), COUNT(0) FROM default;",
+        &[vec![Int(5), Int(2), Int(10)]],
+    );
+}
+
 #[test]
 fn test_order_by_grouping() {
     test_query_nyc(
@@ -476,6 +954,17 @@ fn test_group_by_trip_id() {
     )
 }
 
+#[test]
+fn test_subquery_in_from() {
+    test_query_ec(
+        "SELECT x, count(0) FROM (SELECT non_dense_ints % 2 AS x, id FROM default WHERE id > 2) WHERE id < 100;",
+        &[
+            vec![Int(0), Int(4)],
+            vec![Int(1), Int(3)],
+        ],
+    )
+}
+
 #[test]
 fn test_group_by_negative_expression() {
     test_query_ec(
@@ -488,6 +977,53 @@ fn test_group_by_negative_expression() {
     )
 }
 
+// Delta encoded columns are not order preserving
+#[cfg(feature = "enable_lz4")]
+#[test]
+fn test_group_by_multiple_not_order_preserving() {
+    test_query_ec(
+        "SELECT id, enum, count(0) FROM default;",
+        &[
+            vec![Int(0), Str("aa"), Int(1)],
+            vec![Int(1), Str("aa"), Int(1)],
+            vec![Int(2), Str("aa"), Int(1)],
+            vec![Int(3), Str("bb"), Int(1)],
+            vec![Int(4), Str("bb"), Int(1)],
+            vec![Int(5), Str("aa"), Int(1)],
+            vec![Int(6), Str("cc"), Int(1)],
+            vec![Int(7), Str("aa"), Int(1)],
+            vec![Int(8), Str("cc"), Int(1)],
+            vec![Int(9), Str("bb"), Int(1)],
+        ],
+    )
+}
+
+#[test]
+fn test_group_by_computed_expressions() {
+    test_query(
+        "SELECT length(first_name), count(0) FROM default;",
+        &[
+            vec![Int(3), Int(3)],
+            vec![Int(4), Int(19)],
+            vec![Int(5), Int(22)],
+            vec![Int(6), Int(17)],
+            vec![Int(7), Int(22)],
+            vec![Int(8), Int(10)],
+            vec![Int(9), Int(6)],
+            vec![Int(10), Int(1)],
+        ],
+    );
+    test_query_ec(
+        "SELECT negative % 3, count(0) FROM default;",
+        &[
+            vec![Int(-1), Int(4)],
+            vec![Int(0), Int(2)],
+            vec![Int(1), Int(1)],
+            vec![Int(2), Int(3)],
+        ],
+    );
+}
+
 #[test]
 fn test_order_by_expression() {
     test_query_ec(
@@ -507,6 +1043,20 @@ fn test_order_by_expression() {
     )
 }
 
+#[test]
+fn test_order_by_multiple_columns_limit() {
+    test_query(
+        "SELECT tld, first_name FROM default ORDER BY tld DESC, first_name LIMIT 5;",
+        &[
+            vec![Str("org"), Str("Amy")],
+            vec![Str("org"), Str("Carolyn")],
+            vec![Str("org"), Str("Christina")],
+            vec![Str("org"), Str("Fred")],
+            vec![Str("org"), Str("Lisa")],
+        ],
+    );
+}
+
 #[test]
 fn test_order_by_multiple() {
     test_query_ec(
@@ -653,15 +1203,240 @@ fn test_column_with_null_partitions() {
     assert_eq!(result.rows.iter().filter(|&x| x == &[Str("B".to_string())]).count(), 2);
 }
 
-// TODO(clemens): enable once unused query plans get eliminated
-// #[test]
-fn test_group_by_string() {
-    use value_syntax::*;
+#[test]
+fn test_inline_scheduler() {
+    use Value::*;
     let _ = env_logger::try_init();
+    let mut opts = Options::default();
+    opts.threads = 0;
+    let locustdb = LocustDB::new(&opts);
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let query = "select num, count(1) from default where num < 2;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Int(0), Int(8)],
+        vec![Int(1), Int(49)],
+    ]);
+}
+
+#[test]
+fn test_builder() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    assert!(LocustDB::builder().read_only(true).build().is_err());
+    assert!(LocustDB::builder().readahead(2).mem_size_limit_tables(1).build().is_err());
+
+    let queries = Arc::new(AtomicUsize::new(0));
+    let counter = queries.clone();
+    let locustdb = LocustDB::builder()
+        .threads(2)
+        .on_query(move |_, result| if result.is_ok() { counter.fetch_add(1, Ordering::SeqCst); })
+        .build()
+        .unwrap();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    block_on(locustdb.run_query("select count(0) from default;", false, vec![])).unwrap();
+    block_on(locustdb.run_query("select count(0) from missing;", false, vec![])).unwrap();
+    assert_eq!(queries.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_column_access() {
     let locustdb = LocustDB::memory_only();
-    let _ = block_on(locustdb.gen_table(
-        locustdb::colgen::GenTable {
-            name: "test".to_string(),
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    locustdb.set_column_access("analyst", "default", "last_name", ColumnAccess::Deny);
+    locustdb.set_column_access("analyst", "default", "first_name", ColumnAccess::Mask(Masking::Truncate(1)));
+    locustdb.set_column_access("analyst", "default", "guid", ColumnAccess::Mask(Masking::Redact));
+
+    let denied = block_on(locustdb.run_query_as("analyst", "select last_name from default;", false, vec![])).unwrap();
+    assert!(denied.0.is_err());
+    let star = block_on(locustdb.run_query_as("analyst", "select * from default limit 1;", false, vec![])).unwrap();
+    assert!(!star.0.unwrap().colnames.iter().any(|c| c == "last_name"));
+
+    let truncated = block_on(locustdb.run_query_as(
+        "analyst", "select first_name from default order by first_name limit 2;", false, vec![])).unwrap();
+    assert_eq!(truncated.0.unwrap().rows, vec![vec!["A".into()], vec!["A".into()]]);
+    let redacted = block_on(locustdb.run_query_as(
+        "analyst", "select guid from default limit 1;", false, vec![])).unwrap();
+    assert_eq!(redacted.0.unwrap().rows, vec![vec!["***".into()]]);
+    let unrestricted = block_on(locustdb.run_query("select count(0) from default where last_name = 'Webb';", false, vec![])).unwrap();
+    assert!(unrestricted.0.is_ok());
+}
+
+#[test]
+fn test_user_defined_function() {
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    locustdb.register_function("double", Signature::new(vec![ValueType::Integer], ValueType::Integer), |args| match args[0] {
+        Value::Int(i) => Value::Int(2 * i),
+        _ => Value::Null,
+    });
+    locustdb.register_function(
+        "initial",
+        Signature::new(vec![ValueType::String], ValueType::String).with_dictionary_eval(),
+        |args| match args[0] {
+            Value::Str(ref s) => Value::Str(s.chars().take(1).collect()),
+            _ => Value::Null,
+        });
+
+    let result = block_on(locustdb.run_query(
+        "select double(ts), initial(first_name) from default where ts = 1472763607;", false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Int(2945527214), Str("V")]]);
+    let missing = block_on(locustdb.run_query("select triple(ts) from default;", false, vec![])).unwrap();
+    assert!(missing.0.is_err());
+}
+
+#[test]
+fn test_vectorized_user_defined_function() {
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let signature = Signature::new(vec![ValueType::Integer, ValueType::Integer], ValueType::Integer);
+    locustdb.register_udf("bucket", signature, |args| match (args[0], args[1]) {
+        (ArgSlice::Integer(values), ArgSlice::Integer(widths)) =>
+            ResultVec::Integer(values.iter().zip(widths).map(|(v, w)| v / w).collect()),
+        _ => panic!("Unexpected arguments {:?}", args),
+    });
+    let signature = Signature::new(vec![ValueType::String, ValueType::Integer], ValueType::String);
+    locustdb.register_udf("label", signature, |args| match (args[0], args[1]) {
+        (ArgSlice::String(names), ArgSlice::Integer(ids)) =>
+            ResultVec::String(names.iter().zip(ids).map(|(name, id)| format!("{}-{}", name, id)).collect()),
+        _ => panic!("Unexpected arguments {:?}", args),
+    });
+
+    let result = block_on(locustdb.run_query(
+        "select bucket(ts, 1000), label(first_name, 7) from default where ts = 1472763607;", false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Int(1472763), Str("Victor-7")]]);
+    let count = block_on(locustdb.run_query(
+        "select count(0) from default where bucket(ts, 1) = ts;", false, vec![])).unwrap();
+    assert_eq!(count.0.unwrap().rows, vec![vec![Int(100)]]);
+}
+
+#[test]
+fn test_integer_comparisons_and_sums() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(700)
+        .build()
+        .unwrap();
+    // Column ranges select 8, 16, 32 and 64 bit encodings, partitions have lengths that aren't multiples of the
+    // SIMD vector widths
+    let value = |col: usize, i: i64| match col {
+        0 => i % 200,
+        1 => i * 61 % 60_000,
+        2 => i * 4_000_037 % 4_000_000_000,
+        _ => i * 1_000_000_000_007 % 9_000_000_000_000 - 4_000_000_000_000,
+    };
+    let writer = locustdb.table_writer("numbers");
+    writer.write_all((0..1000).map(|i| (0..4).map(|col| (format!("x{}", col), Int(value(col, i)))).collect())).unwrap();
+    writer.flush();
+
+    let run = |query: &str| match block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows[0][0] {
+        Int(i) => i,
+        ref other => panic!("Unexpected result {:?}", other),
+    };
+    for col in 0..4 {
+        let values = (0..1000).map(|i| value(col, i)).collect::<Vec<_>>();
+        let pivot = *values[317..].iter().find(|&&v| v >= 0).unwrap();
+        for &(op, rhs) in &[("<", pivot), ("<=", pivot), ("=", pivot), ("<>", pivot), ("<", 0), ("=", i64::max_value())] {
+            let expected = values.iter().filter(|&&v| match op {
+                "<" => v < rhs,
+                "<=" => v <= rhs,
+                "=" => v == rhs,
+                _ => v != rhs,
+            }).count() as i64;
+            assert_eq!(run(&format!("SELECT count(0) FROM numbers WHERE x{} {} {};", col, op, rhs)), expected,
+                       "x{} {} {}", col, op, rhs);
+        }
+        assert_eq!(run(&format!("SELECT sum(x{}) FROM numbers;", col)), values.iter().sum::<i64>(), "sum(x{})", col);
+    }
+}
+
+#[test]
+fn test_column_aliases() {
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    let result = block_on(locustdb.run_query(
+        "SELECT count(0) AS visits, tld AS domain FROM default ORDER BY visits DESC LIMIT 1;", false, vec![])).unwrap();
+    let output = result.0.unwrap();
+    assert_eq!(output.colnames, vec!["domain".to_string(), "visits".to_string()]);
+    assert_eq!(output.rows, vec![vec![Str("name"), Int(17)]]);
+}
+
+#[test]
+fn test_group_by_rollup() {
+    test_query_ec(
+        "SELECT enum, non_dense_ints, count(0), sum(negative) FROM default GROUP BY ROLLUP(enum, non_dense_ints);",
+        &[
+            vec![Str("aa"), Int(0), Int(2), Int(-167)],
+            vec![Str("aa"), Int(1), Int(1), Int(-120)],
+            vec![Str("aa"), Int(2), Int(1), Int(39)],
+            vec![Str("aa"), Int(3), Int(1), Int(-100)],
+            vec![Str("aa"), Null, Int(5), Int(-348)],
+            vec![Str("bb"), Int(1), Int(1), Int(34)],
+            vec![Str("bb"), Int(3), Int(1), Int(-40)],
+            vec![Str("bb"), Int(4), Int(1), Int(4031)],
+            vec![Str("bb"), Null, Int(3), Int(4025)],
+            vec![Str("cc"), Int(2), Int(2), Int(3880)],
+            vec![Str("cc"), Null, Int(2), Int(3880)],
+            vec![Null, Null, Int(10), Int(7557)],
+        ],
+    );
+}
+
+#[test]
+fn test_aggregate_filter_clause() {
+    test_query_ec(
+        "SELECT enum, count(0) FILTER (WHERE negative > 0), sum(non_dense_ints) FILTER (WHERE negative < 0), \
+         count(0) FILTER (WHERE negative > 1000), count(0) FROM default;",
+        &[
+            vec![Str("aa"), Int(2), Int(4), Int(0), Int(5)],
+            vec![Str("bb"), Int(2), Int(3), Int(1), Int(3)],
+            vec![Str("cc"), Int(1), Int(2), Int(1), Int(2)],
+        ],
+    );
+}
+
+#[test]
+fn test_arithmetic_on_aggregates() {
+    test_query_ec(
+        "SELECT enum, sum(non_dense_ints) * 100 / count(0), sum(negative) / count(0), count(0) / sum(constant0) FROM default;",
+        &[
+            vec![Str("aa"), Int(120), Int(-69), Int(0)],
+            vec![Str("bb"), Int(266), Int(1341), Int(0)],
+            vec![Str("cc"), Int(200), Int(1940), Int(0)],
+        ],
+    );
+    test_query_ec(
+        "SELECT enum, sum(fractional) / count(0) FROM default;",
+        &[
+            vec![Str("aa"), Float(-0.525)],
+            vec![Str("bb"), Float(5.25)],
+            vec![Str("cc"), Float(5.875)],
+        ],
+    );
+}
+
+// TODO(clemens): enable once unused query plans get eliminated
+// #[test]
+fn test_group_by_string() {
+    use value_syntax::*;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.gen_table(
+        locustdb::colgen::GenTable {
+            name: "test".to_string(),
             partitions: 3,
             partition_size: 4096 + 100,
             columns: vec![
@@ -754,3 +1529,2055 @@ fn test_restore_from_disk() {
     ]);
 }
 
+
+#[cfg(feature = "enable_rocksdb")]
+#[test]
+fn test_restore_ingested_rows() {
+    use std::{thread, time};
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("rocks").unwrap();
+    let mut opts = Options::default();
+    opts.db_path = Some(tmp_dir.path().to_str().unwrap().to_string());
+    {
+        // Creating the table ingests a row into `_meta_tables`
+        let locustdb = LocustDB::new(&opts);
+        let load = block_on(locustdb.load_csv(
+            LoadOptions::new("test_data/tiny.csv", "default")
+                .with_partition_size(40)));
+        load.unwrap().ok();
+    }
+    thread::sleep(time::Duration::from_millis(2000));
+    let locustdb = LocustDB::new(&opts);
+    locustdb.flush();
+    let result = block_on(locustdb.run_query("SELECT name FROM _meta_tables;", false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Str("default")]]);
+}
+
+#[cfg(feature = "enable_rocksdb")]
+#[test]
+fn test_evict_partitions() {
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    // Four partitions of the same size that are identified by `p`
+    let rows = (0..160).map(|i| vec![("p".to_string(), Int(i / 40)), ("x".to_string(), Int(i % 40))]).collect::<Vec<_>>();
+    let load = |locustdb: &LocustDB| {
+        let writer = locustdb.table_writer("items");
+        writer.write_all(rows.clone()).unwrap();
+        writer.flush();
+    };
+
+    // The memory usage of the same data without eviction determines a limit that only fits two and a half partitions
+    let memory_only = LocustDB::builder().threads(0).partition_size_rows(40).build().unwrap();
+    load(&memory_only);
+    let table_bytes = memory_only.metrics().lines()
+        .filter(|line| line.starts_with("locustdb_table_memory_bytes{"))
+        .map(|line| (line.starts_with("locustdb_table_memory_bytes{table=\"items\"}"), line.rsplit(' ').next().unwrap().parse::<usize>().unwrap()))
+        .collect::<Vec<_>>();
+    let total_bytes = table_bytes.iter().map(|&(_, bytes)| bytes).sum::<usize>();
+    let partition_bytes = table_bytes.iter().find(|&&(items, _)| items).unwrap().1 / 4;
+
+    let tmp_dir = TempDir::new("rocks").unwrap();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(40)
+        .db_path(tmp_dir.path().to_str().unwrap())
+        .mem_size_limit_tables(total_bytes - partition_bytes - partition_bytes / 2)
+        .build()
+        .unwrap();
+    load(&locustdb);
+    let run = |query: &str| block_on(locustdb.run_query_uncached(query, false, vec![])).unwrap().0.unwrap().rows;
+    let query = "SELECT p, count(0), sum(x) FROM items;";
+    let expected = run(query);
+    // The partitions with p < 2 are pruned by the filter and become the least recently queried ones
+    run("SELECT p, sum(x) FROM items WHERE p >= 2;");
+    assert_eq!(locustdb.enforce_mem_limit(), 2);
+    let evicted = run("SELECT encodings FROM _partitions WHERE table_name = \"items\" ORDER BY partition_id;")
+        .iter()
+        .map(|row| match row[0] {
+            Value::Str(ref encodings) => encodings.contains("<nonresident>"),
+            ref value => panic!("Unexpected encodings {:?}", value),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(evicted, vec![true, true, false, false]);
+    assert_eq!(locustdb.enforce_mem_limit(), 0);
+
+    // Evicted partitions are reloaded from disk
+    assert_eq!(run(query), expected);
+}
+
+#[cfg(feature = "enable_rocksdb")]
+#[test]
+fn test_snapshot_restore() {
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("snapshot").unwrap();
+    let path = tmp_dir.path().to_str().unwrap();
+    let query = "SELECT tld, count(0), sum(num) FROM default;";
+
+    let locustdb = LocustDB::memory_only();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    load.unwrap().ok();
+    block_on(locustdb.snapshot(path)).unwrap().unwrap();
+    let expected = block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+
+    let restored = LocustDB::memory_only();
+    block_on(restored.restore(path)).unwrap().unwrap();
+    let actual = block_on(restored.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(actual, expected);
+}
+
+#[cfg(feature = "enable_rocksdb")]
+#[test]
+fn test_open_read_only() {
+    use std::time::Duration;
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("shared").unwrap();
+    let path = tmp_dir.path().to_str().unwrap();
+    let query = "SELECT tld, count(0), sum(num) FROM default;";
+
+    let locustdb = LocustDB::memory_only();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    load.unwrap().ok();
+    block_on(locustdb.snapshot(path)).unwrap().unwrap();
+    let expected = block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+
+    // Several readers can open the same directory at once
+    let first = LocustDB::open_read_only(path).unwrap();
+    let second = LocustDB::open_read_only(path).unwrap();
+    for reader in &[first, second] {
+        let actual = block_on(reader.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+        assert_eq!(actual, expected);
+        let insert = block_on(reader.run_query("INSERT INTO default (num) VALUES (1);", false, vec![])).unwrap().0;
+        assert!(insert.is_err());
+        assert!(reader.set_retention("default", "num", Duration::from_secs(1)).is_err());
+        assert_eq!(reader.enforce_retention(), 0);
+    }
+    assert!(LocustDB::open_read_only(&format!("{}/missing", path)).is_err());
+}
+
+#[cfg(feature = "enable_mmap")]
+#[test]
+fn test_mmap_snapshot() {
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("mmap").unwrap();
+    let path = tmp_dir.path().to_str().unwrap();
+    let queries = [
+        "SELECT tld, count(0), sum(num) FROM default;",
+        "SELECT first_name, num FROM default WHERE num > 5 ORDER BY num DESC, first_name LIMIT 10;",
+    ];
+
+    let locustdb = LocustDB::memory_only();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)));
+    load.unwrap().ok();
+    block_on(locustdb.snapshot(path)).unwrap().unwrap();
+
+    let mapped = LocustDB::builder().mmap(true).open_read_only(path).unwrap();
+    for query in &queries {
+        let expected = block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+        let actual = block_on(mapped.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+        assert_eq!(actual, expected);
+    }
+}
+
+#[cfg(feature = "ingest_json")]
+#[test]
+fn test_load_json() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let load = block_on(locustdb.load_json(
+        JsonLoadOptions::new("test_data/events.ndjson", "events")
+            .with_partition_size(2)));
+    load.unwrap().unwrap();
+    let query = "SELECT id, user, latency FROM events WHERE id < 3 ORDER BY id;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Int(1), Str("alice"), Float(12.0)],
+        vec![Int(2), Str("bob"), Float(3.5)],
+    ]);
+    let query = "SELECT status, count(0) FROM events WHERE id > 2;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Str("error"), Int(1)],
+        vec![Str("ok"), Int(1)],
+    ]);
+
+    let strict = block_on(locustdb.load_json(
+        JsonLoadOptions::new("test_data/events.ndjson", "events_strict")
+            .with_type_inference(TypeInference::Strict)));
+    assert!(strict.unwrap().is_err());
+}
+
+#[cfg(feature = "enable_arrow")]
+#[test]
+fn test_arrow_roundtrip() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/edge_cases.csv", "default")
+            .with_partition_size(3)
+            .allow_nulls()));
+    load.unwrap().ok();
+    let query = "SELECT id, enum, fractional FROM default ORDER BY id LIMIT 100;";
+    let expected = block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap();
+    let batch = expected.to_record_batch();
+    assert_eq!(batch.num_rows(), expected.rows.len());
+    assert_eq!(batch.num_columns(), 3);
+    let batches = expected.to_record_batches(4);
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), expected.rows.len());
+    for b in &batches {
+        assert!(b.num_rows() <= 4);
+        for i in 0..batch.num_columns() {
+            assert_eq!(b.schema().field(i).data_type(), batch.schema().field(i).data_type());
+        }
+    }
+
+    block_on(locustdb.ingest_arrow("arrow", vec![batch])).unwrap().unwrap();
+    let query = "SELECT id, enum, fractional FROM arrow ORDER BY id LIMIT 100;";
+    let actual = block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap();
+    assert_eq!(actual.rows, expected.rows);
+}
+
+#[test]
+fn test_table_writer() {
+    let _ = env_logger::try_init();
+    // Without worker threads full partitions are sealed before `write` returns
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(3)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("stream");
+    writer.write_all((0..10).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("parity".to_string(), Str(if i % 2 == 0 { "even" } else { "odd" })),
+    ])).unwrap();
+    // The last row has not been sealed yet and is queried through the open partition
+    let query = "SELECT count(0), sum(id) FROM stream;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Int(10), Int(45)]]);
+    writer.write(vec![("id".to_string(), Int(10)), ("parity".to_string(), Str("even"))]).unwrap();
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Int(11), Int(55)]]);
+
+    writer.flush();
+    let query = "SELECT parity, count(0) FROM stream;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Str("even"), Int(6)],
+        vec![Str("odd"), Int(5)],
+    ]);
+}
+
+#[test]
+fn test_generated_columns() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder().threads(0).build().unwrap();
+    let writer = locustdb.table_writer("events");
+    writer.write_all([0, 3600, 7200].iter().map(|&ts| vec![("ts".to_string(), Int(ts))])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0;
+
+    run("ALTER TABLE events ADD COLUMN hour AS ts / 3600;").unwrap();
+    run("ALTER TABLE events ADD COLUMN day AS hour / 24 STORED;").unwrap();
+    assert!(run("ALTER TABLE events ADD COLUMN ts AS 1;").is_err());
+    assert!(run("ALTER TABLE events ADD COLUMN hour AS ts;").is_err());
+    writer.write_all([86400, 90000].iter().map(|&ts| vec![("ts".to_string(), Int(ts))])).unwrap();
+    writer.flush();
+
+    let output = run("SELECT ts, hour, day FROM events ORDER BY ts;").unwrap();
+    assert_eq!(output.colnames, vec!["ts", "hour", "day"]);
+    assert_eq!(output.rows, vec![
+        vec![Int(0), Int(0), Int(0)],
+        vec![Int(3600), Int(1), Int(0)],
+        vec![Int(7200), Int(2), Int(0)],
+        vec![Int(86400), Int(24), Int(1)],
+        vec![Int(90000), Int(25), Int(1)],
+    ]);
+    assert_eq!(run("SELECT count(0) FROM events WHERE hour > 1;").unwrap().rows, vec![vec![Int(3)]]);
+    // Only stored columns are added to partitions
+    let columns = run("DESCRIBE events;").unwrap().rows.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>();
+    assert_eq!(columns, vec![Str("day"), Str("ts")]);
+}
+
+#[test]
+fn test_csv_loader_options() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/nulls.tsv", "subset")
+            .with_delimiter(b'\t')
+            .with_null_tokens(&["\\N", "null"])
+            .with_columns(&["name", "score"])));
+    load.unwrap().unwrap();
+    let query = "SELECT name, score FROM subset ORDER BY name;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Str("alice"), Int(10)],
+        vec![Str("bob"), Null],
+        vec![Str("carol"), Null],
+    ]);
+
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/nulls.tsv", "tsv")
+            .with_delimiter(b'\t')));
+    load.unwrap().unwrap();
+    let query = "SELECT note FROM tsv WHERE name = 'alice';";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Str("a\tb")]]);
+
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/nulls.tsv", "missing")
+            .with_delimiter(b'\t')
+            .with_columns(&["name", "age"])));
+    assert!(load.unwrap().is_err());
+}
+
+#[test]
+fn test_regex_transform() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let url = RegexTransform::new("url", r"^(?P<host>[^/?]+)(?P<path>/[^?]*)?(\?(?P<query>.*))?$").unwrap();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/urls.csv", "urls")
+            .with_regex_transform(url)
+            .with_ignore_cols(&["url".to_string()])));
+    load.unwrap().unwrap();
+    let query = "SELECT host, path, query, status FROM urls ORDER BY status, host;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Str("example.com"), Str("/a/b"), Str("x=1"), Int(200)],
+        vec![Str("locustdb.io"), Str(""), Str("q=2"), Int(200)],
+        vec![Str("example.com"), Str("/c"), Str(""), Int(404)],
+        vec![Str(""), Str(""), Str(""), Int(500)],
+    ]);
+    let result = block_on(locustdb.run_query("SELECT url FROM urls;", false, vec![])).unwrap();
+    assert!(result.0.is_err());
+
+    let status = RegexTransform::new("status", "(?P<url>.*)").unwrap();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/urls.csv", "collision")
+            .with_regex_transform(status)));
+    assert!(load.unwrap().is_err());
+}
+
+#[test]
+fn test_load_transaction() {
+    use std::fs;
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("load").unwrap();
+    let ragged = tmp_dir.path().join("ragged.csv");
+    fs::write(&ragged, "id,name\n1,a\n2\n").unwrap();
+    let locustdb = LocustDB::memory_only();
+    let count = || block_on(locustdb.run_query("SELECT count(0) FROM staged;", false, vec![])).unwrap().0
+        .map(|output| output.rows);
+
+    // The file that fails to parse rolls back the file loaded before it
+    let transaction = locustdb.begin_load("staged");
+    let load = block_on(transaction.load_csv(LoadOptions::new("test_data/tiny.csv", "staged")));
+    load.unwrap().unwrap();
+    let load = block_on(transaction.load_csv(LoadOptions::new(ragged.to_str().unwrap(), "staged")));
+    assert!(load.unwrap().is_err());
+    assert!(transaction.commit().is_err());
+    assert!(count().is_err());
+
+    let transaction = locustdb.begin_load("staged");
+    for _ in 0..2 {
+        let load = block_on(transaction.load_csv(LoadOptions::new("test_data/tiny.csv", "staged")));
+        load.unwrap().unwrap();
+    }
+    let load = block_on(transaction.load_csv(LoadOptions::new("test_data/tiny.csv", "other")));
+    assert!(load.unwrap().is_err());
+    assert!(count().is_err());
+    assert!(transaction.commit().is_err());
+
+    let transaction = locustdb.begin_load("staged");
+    for _ in 0..2 {
+        let load = block_on(transaction.load_csv(LoadOptions::new("test_data/tiny.csv", "staged")));
+        load.unwrap().unwrap();
+    }
+    assert!(count().is_err());
+    transaction.commit().unwrap();
+    assert_eq!(count().unwrap(), vec![vec![Int(200)]]);
+}
+
+#[test]
+fn test_load_progress() {
+    use std::sync::{Arc, Mutex};
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let progress = reports.clone();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)
+            .with_max_in_flight_partitions(0)
+            .with_progress(move |p: &LoadProgress| progress.lock().unwrap().push(p.clone()))));
+    load.unwrap().unwrap();
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.iter().map(|p| (p.rows_ingested, p.partitions_sealed)).collect::<Vec<_>>(),
+               vec![(40, 1), (80, 2), (100, 3)]);
+    assert_eq!(reports[2].bytes_read, 53573);
+}
+
+#[cfg(feature = "enable_zstd")]
+#[test]
+fn test_load_zstd_csv() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "plain")
+            .with_partition_size(40)));
+    load.unwrap().unwrap();
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv.zst", "zstd")
+            .with_partition_size(40)));
+    load.unwrap().unwrap();
+    let query = |table: &str| format!("SELECT tld, count(0), sum(num) FROM {};", table);
+    let expected = block_on(locustdb.run_query(&query("plain"), false, vec![])).unwrap().0.unwrap().rows;
+    let actual = block_on(locustdb.run_query(&query("zstd"), false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_csv_schema() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let schema = Schema::default()
+        .with_column("num", ColumnSchema::new(ColumnType::String))
+        .with_column("hash", ColumnSchema::new(ColumnType::String).encoding(EncodingHint::NoHex));
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "default")
+            .with_partition_size(40)
+            .with_schema(schema)));
+    load.unwrap().unwrap();
+    let query = "SELECT num FROM default WHERE num = '8';";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Str("8")]]);
+
+    let schema = Schema::default().with_column("tld", ColumnSchema::new(ColumnType::Integer));
+    let load = block_on(locustdb.load_csv(
+        LoadOptions::new("test_data/tiny.csv", "invalid")
+            .with_schema(schema)));
+    assert!(load.unwrap().is_err());
+}
+
+#[test]
+fn test_added_column() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    for file in &["test_data/schema_v1.csv", "test_data/schema_v2.csv"] {
+        let load = block_on(locustdb.load_csv(LoadOptions::new(file, "evolving")));
+        load.unwrap().unwrap();
+    }
+    let query = "SELECT id, score FROM evolving ORDER BY id;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Int(1), Null],
+        vec![Int(2), Null],
+        vec![Int(3), Int(30)],
+        vec![Int(4), Int(40)],
+    ]);
+}
+
+#[test]
+fn test_insert_into() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let insert = "INSERT INTO fixture (id, name, score) VALUES (1, 'a', 1.5), (2, 'b', -3.0);";
+    let result = block_on(locustdb.run_query(insert, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Int(2)]]);
+    let insert = "INSERT INTO fixture (id, name) VALUES (3, 'c');";
+    block_on(locustdb.run_query(insert, false, vec![])).unwrap().0.unwrap();
+
+    let query = "SELECT id, name FROM fixture ORDER BY id;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![
+        vec![Int(1), Str("a")],
+        vec![Int(2), Str("b")],
+        vec![Int(3), Str("c")],
+    ]);
+    let query = "SELECT score FROM fixture WHERE id = 2;";
+    let result = block_on(locustdb.run_query(query, false, vec![])).unwrap();
+    assert_eq!(result.0.unwrap().rows, vec![vec![Float(-3.0)]]);
+}
+
+#[test]
+fn test_insert_select() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0;
+    let values = (0..150).map(|i| format!("({}, '{}')", i, ["a", "b", "c"][i % 3])).collect::<Vec<_>>();
+    run(&format!("INSERT INTO events (id, name) VALUES {};", values.join(", "))).unwrap();
+
+    // Results are not limited to the default of 100 rows
+    assert_eq!(run("CREATE TABLE copy AS SELECT id, name FROM events;").unwrap().rows, vec![vec![Int(150)]]);
+    assert_eq!(run("SELECT COUNT(0), SUM(id) FROM copy;").unwrap().rows, vec![vec![Int(150), Int(11175)]]);
+    assert!(run("CREATE TABLE copy AS SELECT id FROM events;").is_err());
+
+    run("CREATE TABLE totals AS SELECT name, COUNT(0) AS n, SUM(id) AS total FROM events WHERE id < 6;").unwrap();
+    run("INSERT INTO totals (name, n, total) SELECT name, COUNT(0), SUM(id) / 10 FROM events WHERE name = 'a' LIMIT 1;").unwrap();
+    assert_eq!(run("SELECT name, n, total FROM totals ORDER BY name, total;").unwrap().rows, vec![
+        vec![Str("a"), Int(2), Int(3)],
+        vec![Str("a"), Int(50), Int(367)],
+        vec![Str("b"), Int(2), Int(5)],
+        vec![Str("c"), Int(2), Int(7)],
+    ]);
+    assert!(run("INSERT INTO totals (name) SELECT name, COUNT(0) FROM events;").is_err());
+}
+
+#[test]
+fn test_query_builder() {
+    use locustdb::builder::*;
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(LoadOptions::new("test_data/tiny.csv", "default").with_partition_size(40)));
+    let run = |query: Query| block_on(locustdb.run(query)).unwrap().0.unwrap().rows;
+    let sql = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+
+    let query = Query::table("default")
+        .filter(col("num").gt(2))
+        .group_by(col("tld"))
+        .aggregate_as(count(col("num")), "n")
+        .aggregate(sum(col("num")))
+        .order_by(col("n"), true)
+        .order_by(col("tld"), false)
+        .limit(5)
+        .build()
+        .unwrap();
+    assert_eq!(run(query),
+               sql("SELECT tld, COUNT(num) AS n, SUM(num) FROM default WHERE num > 2 ORDER BY n DESC, tld LIMIT 5;"));
+
+    // Unlike SQL queries, built queries return all rows by default
+    let query = Query::table("default").select(col("first_name")).build().unwrap();
+    assert_eq!(vec![vec![Int(run(query).len() as i64)]], sql("SELECT COUNT(0) FROM default;"));
+    assert!(Query::table("default").select(col("tld")).aggregate(count(col("num"))).build().is_err());
+}
+
+#[test]
+fn test_metrics() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let _ = block_on(locustdb.load_csv(LoadOptions::new("test_data/tiny.csv", "default").with_partition_size(40)));
+    block_on(locustdb.run_query("SELECT COUNT(0) FROM default;", false, vec![])).unwrap().0.unwrap();
+    block_on(locustdb.run_query("SELECT nonexistent FROM missing;", false, vec![])).unwrap().0.unwrap_err();
+
+    let metrics = locustdb.metrics();
+    assert!(metrics.contains("locustdb_queries_total 2\n"), "{}", metrics);
+    assert!(metrics.contains("locustdb_query_errors_total 1\n"), "{}", metrics);
+    assert!(metrics.contains("locustdb_rows_ingested_total 100\n"), "{}", metrics);
+    assert!(metrics.contains("locustdb_table_partitions{table=\"default\"} 3\n"), "{}", metrics);
+    assert!(metrics.contains("locustdb_query_duration_seconds_count 2\n"), "{}", metrics);
+}
+
+#[test]
+fn test_sum_overflow() {
+    let _ = env_logger::try_init();
+    for &partition_size in &[2, 1000] {
+        let locustdb = LocustDB::builder()
+            .threads(0)
+            .partition_size_rows(partition_size)
+            .build()
+            .unwrap();
+        let writer = locustdb.table_writer("large");
+        writer.write_all((0..3).map(|i| vec![("x".to_string(), Int(4_000_000_000_000_000_000 + i))])).unwrap();
+        writer.flush();
+
+        match block_on(locustdb.run_query("SELECT SUM(x) FROM large;", false, vec![])).unwrap().0 {
+            Err(QueryError::Overflow(_)) => {}
+            result => panic!("Expected overflow, got {:?}", result),
+        }
+        let query = Query::table("large")
+            .aggregate(builder::sum(builder::col("x")))
+            .sum_overflow(SumOverflow::Float)
+            .build()
+            .unwrap();
+        let rows = block_on(locustdb.run(query)).unwrap().0.unwrap().rows;
+        assert_eq!(rows, vec![vec![Float(1.2e19)]]);
+    }
+}
+
+#[test]
+fn test_query_log() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(50)
+        .query_log_size(2)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0;
+    run("SELECT COUNT(0) FROM items;").unwrap();
+    run("SELECT id FROM items WHERE id > 80;").unwrap();
+    run("SELECT id FROM missing;").unwrap_err();
+    let log = run("SELECT normalized_query, rows_scanned, rows_returned, partitions_pruned, error FROM _query_log;")
+        .unwrap()
+        .rows;
+    assert_eq!(log[0], vec![Str("SELECT id FROM items WHERE id > ?;"), Int(50), Int(19), Int(1), Null]);
+    assert_eq!(log[1][0], Str("SELECT id FROM missing;"));
+    assert!(log[1][4] != Null);
+    assert_eq!(log.len(), 2);
+}
+
+#[test]
+fn test_copy_to() {
+    use std::fs;
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("copy").unwrap();
+    let locustdb = LocustDB::builder()
+        .export_directory(tmp_dir.path().to_str().unwrap())
+        .build()
+        .unwrap();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0;
+    let values = (0..150).map(|i| format!("({}, '{}')", i, ["a", "b,c", "d\"e"][i % 3])).collect::<Vec<_>>();
+    run(&format!("INSERT INTO events (id, name) VALUES {};", values.join(", "))).unwrap();
+
+    let path = tmp_dir.path().join("events.csv");
+    assert_eq!(run("COPY (SELECT id, name FROM events ORDER BY id) TO 'events.csv';").unwrap().rows, vec![vec![Int(150)]]);
+    let csv = fs::read_to_string(&path).unwrap();
+    assert_eq!(csv.lines().count(), 151);
+    assert_eq!(csv.lines().take(4).collect::<Vec<_>>(), vec!["id,name", "0,a", "1,\"b,c\"", "2,\"d\"\"e\""]);
+
+    let path = tmp_dir.path().join("totals.csv");
+    run("COPY (SELECT name, SUM(id) AS total FROM events WHERE id < 3) TO \"totals.csv\"").unwrap();
+    let csv = fs::read_to_string(&path).unwrap();
+    assert_eq!(csv.lines().next(), Some("name,total"));
+    assert_eq!(csv.lines().count(), 4);
+
+    assert!(run("COPY (SELECT id FROM events) TO 'events.txt';").is_err());
+    assert!(run("COPY events TO 'events.csv';").is_err());
+    if !cfg!(feature = "enable_parquet") {
+        let path = tmp_dir.path().join("events.parquet");
+        assert!(run("COPY (SELECT id FROM events) TO 'events.parquet';").is_err());
+        assert!(!path.exists());
+    }
+
+    // Files can only be written to the export directory, which has to be set explicitly
+    let outside = tmp_dir.path().join("outside.csv");
+    assert!(run(&format!("COPY (SELECT id FROM events) TO '{}';", outside.display())).is_err());
+    assert!(run("COPY (SELECT id FROM events) TO '../outside.csv';").is_err());
+    assert!(!outside.exists());
+    let locustdb = LocustDB::memory_only();
+    assert!(block_on(locustdb.run_query("COPY (SELECT id FROM events) TO 'events.csv';", false, vec![])).unwrap().0.is_err());
+}
+
+#[test]
+fn test_create_and_drop_table() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0;
+    run("CREATE TABLE declared (id INT NOT NULL, name VARCHAR(16));").unwrap();
+    assert!(run("CREATE TABLE declared (id INT);").is_err());
+    // Tables without rows have no partitions
+    let empty = run("SELECT id, name FROM declared;").unwrap();
+    assert_eq!(empty.colnames, vec!["id".to_string(), "name".to_string()]);
+    assert!(empty.rows.is_empty());
+    assert!(run("SELECT name, count(0) FROM declared;").unwrap().rows.is_empty());
+    run("INSERT INTO declared (id, name) VALUES ('7', 42), (NULL, 'x');").unwrap();
+    assert!(run("INSERT INTO declared (id) VALUES ('seven');").is_err());
+    assert_eq!(run("SELECT id, name FROM declared ORDER BY id;").unwrap().rows, vec![
+        vec![Int(0), Str("x")],
+        vec![Int(7), Str("42")],
+    ]);
+
+    run("DROP TABLE declared;").unwrap();
+    assert!(run("SELECT id FROM declared;").is_err());
+    assert!(run("DROP TABLE declared;").is_err());
+    run("DROP TABLE IF EXISTS declared;").unwrap();
+}
+
+#[test]
+fn test_delete() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0.unwrap().rows;
+    // Each statement creates a separate partition
+    run("INSERT INTO events (id, name) VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd');");
+    run("INSERT INTO events (id, name) VALUES (5, 'e'), (6, 'f'), (7, 'g'), (8, 'h');");
+
+    assert_eq!(run("DELETE FROM events WHERE id = 1;"), vec![vec![Int(1)]]);
+    assert_eq!(run("SELECT count(0), sum(id) FROM events;"), vec![vec![Int(7), Int(35)]]);
+    assert_eq!(run("SELECT id FROM events ORDER BY id LIMIT 3;"), vec![vec![Int(2)], vec![Int(3)], vec![Int(5)]]);
+
+    // Deleting half of the rows of the first partition compacts it
+    assert_eq!(run("DELETE FROM events WHERE id < 3 OR id = 8;"), vec![vec![Int(2)]]);
+    assert_eq!(run("DELETE FROM events WHERE id = 42;"), vec![vec![Int(0)]]);
+    assert_eq!(run("SELECT id, name FROM events ORDER BY id;"), vec![
+        vec![Int(3), Str("c")],
+        vec![Int(4), Str("d")],
+        vec![Int(5), Str("e")],
+        vec![Int(6), Str("f")],
+        vec![Int(7), Str("g")],
+    ]);
+}
+
+#[test]
+fn test_retention() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0.unwrap().rows;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    run("INSERT INTO logs (ts, message) VALUES (1000, 'old'), (2000, 'older');");
+    run(&format!("INSERT INTO logs (ts, message) VALUES ({}, 'old'), ({}, 'recent');", now - 7200, now - 60));
+    run(&format!("INSERT INTO logs (ts, message) VALUES ({}, 'recent');", now));
+
+    locustdb.set_retention("logs", "ts", Duration::from_secs(3600)).unwrap();
+    assert_eq!(locustdb.enforce_retention(), 1);
+    assert_eq!(run("SELECT message, count(0) FROM logs;"), vec![
+        vec![Str("old"), Int(1)],
+        vec![Str("recent"), Int(2)],
+    ]);
+    assert_eq!(locustdb.enforce_retention(), 0);
+}
+
+#[test]
+fn test_partition_pruning() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0.unwrap();
+    run("INSERT INTO ranges (id, name) VALUES (1, 'a'), (2, 'b'), (3, 'c'), (4, 'd');");
+    run("INSERT INTO ranges (id, name) VALUES (5, 'e'), (6, 'f'), (7, 'g'), (8, 'h');");
+
+    let result = run("SELECT count(0) FROM ranges WHERE id > 5;");
+    assert_eq!(result.rows, vec![vec![Int(3)]]);
+    assert_eq!(result.stats.rows_scanned, 4);
+    let result = run("SELECT name FROM ranges WHERE 2 >= id;");
+    assert_eq!(result.rows, vec![vec![Str("a")], vec![Str("b")]]);
+    assert_eq!(result.stats.rows_scanned, 4);
+    assert_eq!(run("SELECT name FROM ranges WHERE id = 3 OR id = 7;").stats.rows_scanned, 8);
+    assert_eq!(run("SELECT name FROM ranges WHERE id > 100;").rows.len(), 0);
+}
+
+#[test]
+fn test_bloom_filter() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::memory_only();
+    locustdb.set_bloom_filter_columns("requests", &["request_id"]);
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0.unwrap();
+    run("INSERT INTO requests (request_id, status) VALUES ('f3a1', 200), ('09bc', 404), ('77de', 200);");
+    run("INSERT INTO requests (request_id, status) VALUES ('c2e8', 500), ('5b10', 200), ('e6f4', 200);");
+
+    let result = run("SELECT status FROM requests WHERE request_id = '5b10';");
+    assert_eq!(result.rows, vec![vec![Int(200)]]);
+    assert_eq!(result.stats.rows_scanned, 3);
+    let result = run("SELECT count(0) FROM requests WHERE request_id IN ('09bc', 'e6f4');");
+    assert_eq!(result.rows, vec![vec![Int(2)]]);
+    assert_eq!(result.stats.rows_scanned, 6);
+}
+
+#[test]
+fn test_merge_small_partitions() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(4)
+        .merge_partition_rows(4)
+        .build()
+        .unwrap();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0.unwrap().rows;
+    for i in 0..5 {
+        run(&format!("INSERT INTO tiny (id, name) VALUES ({}, 'row{}');", i, i));
+    }
+    run("INSERT INTO tiny (id, tag) VALUES (5, 7);");
+    run("INSERT INTO tiny (id, tag) VALUES (6, 'seven');");
+    run("DELETE FROM tiny WHERE id = 4;");
+    let batches = |locustdb: &LocustDB| block_on(locustdb.table_stats()).unwrap()
+        .into_iter()
+        .find(|table| table.name == "tiny")
+        .unwrap()
+        .batches;
+    assert_eq!(batches(&locustdb), 6);
+
+    // Partitions are merged into partitions of at most 4 rows, integer and string values of `tag` end up in one column
+    assert_eq!(locustdb.merge_small_partitions(), 4);
+    assert_eq!(batches(&locustdb), 2);
+    assert_eq!(run("SELECT id, name, tag FROM tiny ORDER BY id;"), vec![
+        vec![Int(0), Str("row0"), Null],
+        vec![Int(1), Str("row1"), Null],
+        vec![Int(2), Str("row2"), Null],
+        vec![Int(3), Str("row3"), Null],
+        vec![Int(5), Null, Int(7)],
+        vec![Int(6), Null, Str("seven")],
+    ]);
+}
+
+#[test]
+fn test_run_length_and_delta_encoding() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(100)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("encodings");
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("status".to_string(), Int(200 + 100 * (i / 40))),
+        ("counter".to_string(), Int(1000 * i)),
+    ])).unwrap();
+    writer.flush();
+    let tables = block_on(locustdb.mem_tree(2)).unwrap();
+    let table = tables.iter().find(|table| table.name == "encodings").unwrap();
+    let codecs = |column: &str| table.columns[column].encodings.keys().cloned().collect::<Vec<_>>().join(",");
+    assert!(codecs("status").contains("RunLength"), "{}", codecs("status"));
+    assert!(codecs("counter").contains("Delta"), "{}", codecs("counter"));
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT count(0) FROM encodings WHERE status >= 300;"), vec![vec![Int(60)]]);
+    assert_eq!(run("SELECT count(0) FROM encodings WHERE 300 = status;"), vec![vec![Int(40)]]);
+    assert_eq!(run("SELECT sum(id) FROM encodings WHERE status < 300;"), vec![vec![Int(780)]]);
+    assert_eq!(run("SELECT status, count(0) FROM encodings;"), vec![
+        vec![Int(200), Int(40)],
+        vec![Int(300), Int(40)],
+        vec![Int(400), Int(20)],
+    ]);
+    assert_eq!(run("SELECT counter FROM encodings WHERE id = 42;"), vec![vec![Int(42000)]]);
+}
+
+#[test]
+fn test_dictionary_range_filter() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(100)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("tiers");
+    let tiers = ["bronze", "gold", "silver"];
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(tiers[i as usize % 3])),
+    ])).unwrap();
+    writer.flush();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier < \"h\";"), vec![vec![Int(67)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier >= \"gold\";"), vec![vec![Int(66)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier <= \"gold\";"), vec![vec![Int(67)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE \"h\" < tier;"), vec![vec![Int(33)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier > \"a\";"), vec![vec![Int(100)]]);
+    assert_eq!(run("SELECT tier, count(0) FROM tiers;"), vec![
+        vec![Str("bronze"), Int(34)],
+        vec![Str("gold"), Int(33)],
+        vec![Str("silver"), Int(33)],
+    ]);
+}
+
+#[test]
+fn test_shared_dictionary() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(50)
+        .build()
+        .unwrap();
+    locustdb.set_shared_dictionary_columns("tiers", &["tier"]);
+    let writer = locustdb.table_writer("tiers");
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(if i < 50 { ["silver", "gold"][i as usize % 2] } else { ["gold", "bronze"][i as usize % 2] })),
+    ])).unwrap();
+    writer.flush();
+    let tables = block_on(locustdb.mem_tree(2)).unwrap();
+    let table = tables.iter().find(|table| table.name == "tiers").unwrap();
+    let codecs = table.columns["tier"].encodings.keys().cloned().collect::<Vec<_>>().join(",");
+    assert!(codecs.contains("SharedDict"), "{}", codecs);
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier = \"gold\";"), vec![vec![Int(50)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier < \"h\";"), vec![vec![Int(75)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier >= \"gold\";"), vec![vec![Int(75)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE \"gold\" >= tier;"), vec![vec![Int(75)]]);
+    assert_eq!(run("SELECT count(0) FROM tiers WHERE tier > \"gold\";"), vec![vec![Int(25)]]);
+    // Range comparisons are evaluated on the dictionary instead of decoding every row
+    let output = block_on(locustdb.run_query("SELECT count(0) FROM tiers WHERE tier <= \"gold\";", true, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![vec![Int(75)]]);
+    assert!(output.query_plans.keys().any(|plan| plan.contains("LTE")), "{:?}", output.query_plans);
+    assert_eq!(run("SELECT tier, count(0) FROM tiers;"), vec![
+        vec![Str("bronze"), Int(25)],
+        vec![Str("gold"), Int(50)],
+        vec![Str("silver"), Int(25)],
+    ]);
+}
+
+#[test]
+fn test_shared_dictionary_group_by() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(20)
+        .build()
+        .unwrap();
+    locustdb.set_shared_dictionary_columns("tiers", &["tier"]);
+    let writer = locustdb.table_writer("tiers");
+    // Later partitions add strings that sort before the ones that are assigned the first codes
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(if i < 40 {
+            ["silver", "gold"][i as usize % 2]
+        } else if i < 80 {
+            ["gold", "bronze"][i as usize % 2]
+        } else {
+            ["bronze", "basic", "silver", "gold"][i as usize % 4]
+        })),
+    ])).unwrap();
+    writer.flush();
+
+    let output = block_on(locustdb.run_query("SELECT tier, count(0), sum(id) FROM tiers;", true, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![
+        vec![Str("basic"), Int(5), Int(445)],
+        vec![Str("bronze"), Int(25), Int(1640)],
+        vec![Str("gold"), Int(45), Int(2035)],
+        vec![Str("silver"), Int(25), Int(830)],
+    ]);
+    // Groups of every partition are identified by codes and the strings are only looked up for the merged result
+    for plan in &output.plan_graphs {
+        for op in plan.operators() {
+            assert!(!op.operator.starts_with("DictLookup") && !op.operator.starts_with("DictionaryEntries"), "{}", plan);
+        }
+    }
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT tier, count(0) FROM tiers WHERE id >= 40 LIMIT 2;"), vec![
+        vec![Str("basic"), Int(5)],
+        vec![Str("bronze"), Int(25)],
+    ]);
+    assert_eq!(run("SELECT tier, count(0) AS c FROM tiers ORDER BY c DESC LIMIT 1;"), vec![
+        vec![Str("gold"), Int(45)],
+    ]);
+}
+
+#[test]
+fn test_string_list() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("posts");
+    writer.write_all((0..30).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tags".to_string(), Str(["[\"a\",\"b\"]", "[\"b\"]", "[]"][i as usize % 3])),
+    ])).unwrap();
+    writer.flush();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT count(0) FROM posts WHERE ARRAY_CONTAINS(tags, \"a\");"), vec![vec![Int(10)]]);
+    assert_eq!(run("SELECT count(0) FROM posts WHERE ARRAY_CONTAINS(tags, \"b\");"), vec![vec![Int(20)]]);
+    assert_eq!(run("SELECT UNNEST(tags), count(0) FROM posts;"), vec![
+        vec![Str("a"), Int(10)],
+        vec![Str("b"), Int(20)],
+    ]);
+
+    // Declared lists are stored in a list column
+    run("CREATE TABLE typed (id INT, tags string[]);");
+    let writer = locustdb.table_writer("typed");
+    writer.write_all((0..30).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tags".to_string(), Str(["[\"a\",\"b\"]", "[\"b\"]", "[]"][i as usize % 3])),
+    ])).unwrap();
+    writer.flush();
+    let tables = block_on(locustdb.mem_tree(2)).unwrap();
+    let table = tables.iter().find(|table| table.name == "typed").unwrap();
+    let codecs = table.columns["tags"].encodings.keys().cloned().collect::<Vec<_>>().join(",");
+    assert!(codecs.contains("List"), "{}", codecs);
+
+    let output = block_on(locustdb.run_query("SELECT count(0) FROM typed WHERE ARRAY_CONTAINS(tags, \"a\");", true, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![vec![Int(10)]]);
+    for plan in &output.plan_graphs {
+        for op in plan.operators() {
+            assert!(!op.operator.starts_with("EncodeLists"), "{}", plan);
+        }
+    }
+    assert_eq!(run("SELECT count(0) FROM typed WHERE ARRAY_CONTAINS(tags, \"b\");"), vec![vec![Int(20)]]);
+    assert_eq!(run("SELECT count(0) FROM typed WHERE ARRAY_CONTAINS(tags, \"c\");"), vec![vec![Int(0)]]);
+    assert_eq!(run("SELECT UNNEST(tags), count(0) FROM typed;"), vec![
+        vec![Str("a"), Int(10)],
+        vec![Str("b"), Int(20)],
+    ]);
+    assert_eq!(run("SELECT tags FROM typed WHERE id < 3 ORDER BY id;"), vec![
+        vec![Str("[\"a\",\"b\"]")],
+        vec![Str("[\"b\"]")],
+        vec![Str("[]")],
+    ]);
+}
+
+#[test]
+fn test_introspection() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(50)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(["bronze", "gold"][i as usize % 2])),
+    ])).unwrap();
+    writer.flush();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SHOW TABLES;"), vec![vec![Str("_meta_tables")], vec![Str("items")]]);
+    let columns = run("DESCRIBE items;");
+    assert_eq!(columns.iter().map(|row| row[..2].to_vec()).collect::<Vec<_>>(), vec![
+        vec![Str("id"), Str("Integer")],
+        vec![Str("tier"), Str("String")],
+    ]);
+    assert_eq!(run("SELECT count(0), sum(row_count) FROM _partitions WHERE table_name = \"items\";"),
+               vec![vec![Int(2), Int(100)]]);
+}
+
+#[test]
+fn test_mem_stats() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(50)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(["bronze", "gold"][i as usize % 2])),
+    ])).unwrap();
+    writer.flush();
+
+    let stats = block_on(locustdb.mem_stats()).unwrap();
+    let items = stats.iter().filter(|s| s.table == "items").collect::<Vec<_>>();
+    assert_eq!(items.iter().filter(|s| s.column == "tier").map(|s| s.rows).sum::<usize>(), 100);
+    assert!(items.iter().all(|s| s.size_bytes > 0));
+    let total = items.iter().map(|s| s.size_bytes as i64).sum::<i64>();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT sum(size_bytes) FROM _mem_stats WHERE table_name = \"items\";"), vec![vec![Int(total)]]);
+}
+
+#[test]
+fn test_column_stats() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(50)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(["bronze", "silver", "gold"][i as usize % 3])),
+    ])).unwrap();
+    writer.flush();
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT column_name, min, max, null_count, distinct_values FROM _column_stats \
+                    WHERE table_name = \"items\" ORDER BY column_name, min;"), vec![
+        vec![Str("id"), Int(0), Int(49), Int(0), Int(50)],
+        vec![Str("id"), Int(50), Int(99), Int(0), Int(50)],
+        vec![Str("tier"), Null, Null, Int(0), Int(3)],
+        vec![Str("tier"), Null, Null, Int(0), Int(3)],
+    ]);
+    assert_eq!(run("SELECT count(0) FROM _column_stats WHERE size_bytes > 0 AND table_name = \"items\";"),
+               vec![vec![Int(4)]]);
+}
+
+#[test]
+fn test_streaming_results() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(50)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+
+    let batches = |query: &str| futures_executor::block_on_stream(locustdb.run_query_streaming(query))
+        .map(|batch| batch.unwrap())
+        .collect::<Vec<_>>();
+    let scan = batches("SELECT id FROM items LIMIT 1000;");
+    assert_eq!(scan.len(), 2);
+    let mut ids = scan.iter().flat_map(|b| b.rows.iter().map(|row| row[0].clone())).collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, (0..100).map(Int).collect::<Vec<_>>());
+    assert_eq!(scan[1].stats.rows_scanned, 100);
+
+    let limited = batches("SELECT id FROM items LIMIT 30;");
+    assert_eq!(limited.iter().map(|b| b.rows.len()).sum::<usize>(), 30);
+
+    let aggregate = batches("SELECT count(0) FROM items;");
+    assert_eq!(aggregate.len(), 1);
+    assert_eq!(aggregate[0].rows, vec![vec![Int(100)]]);
+}
+
+#[test]
+fn test_cancel_query() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(1)
+        .partition_size_rows(50)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+
+    // Blocks the query in the first partition until the test has cancelled it
+    let (release, blocked) = std::sync::mpsc::channel::<()>();
+    let blocked = std::sync::Mutex::new(blocked);
+    locustdb.register_function("wait", Signature::new(vec![ValueType::Integer], ValueType::Integer), move |args| {
+        let _ = blocked.lock().unwrap().recv();
+        args[0].clone()
+    });
+    let query = locustdb.run_query("SELECT wait(id) FROM items LIMIT 1000;", false, vec![]);
+    query.cancel();
+    drop(release);
+    match block_on(query).unwrap().0 {
+        Err(QueryError::Cancelled) => {}
+        result => panic!("Expected query to be cancelled, got {:?}", result.map(|output| output.rows)),
+    }
+    assert_eq!(block_on(locustdb.run_query("SELECT count(0) FROM items;", false, vec![])).unwrap().0.unwrap().rows,
+               vec![vec![Int(100)]]);
+}
+
+#[test]
+fn test_query_limits() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(50)
+        .query_timeout(std::time::Duration::from_millis(20))
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..200).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    locustdb.register_function("slow", Signature::new(vec![ValueType::Integer], ValueType::Integer), |args| {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        args[0].clone()
+    });
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0;
+    assert_eq!(run("SELECT count(0) FROM items;").unwrap().rows, vec![vec![Int(200)]]);
+    match run("SELECT slow(id) FROM items LIMIT 1000;") {
+        Err(QueryError::ResourceExhausted(_)) => {}
+        result => panic!("Expected timeout, got {:?}", result.map(|output| output.rows)),
+    }
+
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(1000)
+        .query_memory_limit(4000)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..1000).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0;
+    match run("SELECT id FROM items ORDER BY id LIMIT 1000;") {
+        Err(QueryError::ResourceExhausted(_)) => {}
+        result => panic!("Expected memory limit to be exceeded, got {:?}", result.map(|output| output.rows)),
+    }
+
+    // The limit applies to the partial results of all partitions, not to each partition
+    let locustdb = LocustDB::builder()
+        .threads(2)
+        .partition_size_rows(100)
+        .query_memory_limit(6000)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..2000).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0;
+    assert_eq!(run("SELECT id + 1 FROM items WHERE id = 7;").unwrap().rows, vec![vec![Int(8)]]);
+    match run("SELECT id, id + 1 FROM items LIMIT 2000;") {
+        Err(QueryError::ResourceExhausted(_)) => {}
+        result => panic!("Expected memory limit to be exceeded, got {:?}", result.map(|output| output.rows.len())),
+    }
+}
+
+#[test]
+fn test_prepared_query() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(["bronze", "gold"][i as usize % 2])),
+    ])).unwrap();
+    writer.flush();
+
+    let query = locustdb.prepare("SELECT count(0) FROM items WHERE tier = ? AND id < ?;").unwrap();
+    assert_eq!(query.param_count(), 2);
+    let run = |params: &[Value]| block_on(locustdb.run_prepared(&query, params)).unwrap().0;
+    assert_eq!(run(&[Str("gold"), Int(10)]).unwrap().rows, vec![vec![Int(5)]]);
+    assert_eq!(run(&[Str("bronze"), Int(100)]).unwrap().rows, vec![vec![Int(50)]]);
+    assert_eq!(run(&[Str("' OR 1 = 1 --"), Int(100)]).unwrap().rows, vec![vec![Int(0)]]);
+    assert!(run(&[Str("gold")]).is_err());
+
+    // Later runs only bind their parameters into the query that was normalized by the first run
+    let normalizations = || locustdb.metrics().lines()
+        .find(|line| line.starts_with("locustdb_queries_normalized_total "))
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|count| count.parse::<usize>().ok())
+        .unwrap();
+    let before = normalizations();
+    for i in 0..10 {
+        assert_eq!(run(&[Str("gold"), Int(2 * i)]).unwrap().rows, vec![vec![Int(i)]]);
+    }
+    assert_eq!(normalizations(), before);
+    // New columns invalidate the normalized query
+    writer.write_all(vec![vec![
+        ("id".to_string(), Int(100)),
+        ("tier".to_string(), Str("gold")),
+        ("score".to_string(), Int(1)),
+    ]]).unwrap();
+    writer.flush();
+    assert_eq!(run(&[Str("gold"), Int(200)]).unwrap().rows, vec![vec![Int(51)]]);
+    assert_eq!(run(&[Str("gold"), Int(10)]).unwrap().rows, vec![vec![Int(5)]]);
+    assert_eq!(normalizations(), before + 1);
+
+    let numbered = locustdb.prepare("SELECT id FROM items WHERE id >= $1 AND id < $1 + $2 AND tier <> '$3?' ORDER BY id;").unwrap();
+    assert_eq!(numbered.param_count(), 2);
+    assert_eq!(block_on(locustdb.run_prepared(&numbered, &[Int(40), Int(3)])).unwrap().0.unwrap().rows,
+               vec![vec![Int(40)], vec![Int(41)], vec![Int(42)]]);
+    assert!(locustdb.prepare("SELECT id FROM items WHERE id = $2;").is_err());
+}
+
+#[test]
+fn test_limit_stops_scan() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(4)
+        .partition_size_rows(10)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..2000).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    // Counts evaluations to determine the number of scanned rows
+    let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = calls.clone();
+    locustdb.register_function("tally", Signature::new(vec![ValueType::Integer], ValueType::Integer), move |args| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        args[0].clone()
+    });
+
+    // Every partition contains a single matching row
+    let result = block_on(locustdb.run_query("SELECT id FROM items WHERE tally(id) % 10 = 0 LIMIT 20;", false, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(result.rows.len(), 20);
+    assert!(result.rows.iter().all(|row| match row[0] { Int(i) => i % 10 == 0, _ => false }));
+    // At most one partition per thread is scanned after the limit has been reached
+    assert!(calls.load(std::sync::atomic::Ordering::SeqCst) < 300);
+}
+
+#[test]
+fn test_result_cache() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .result_cache_bytes(1 << 20)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..10).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    // Counts evaluations to tell cached results apart from computed ones
+    let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = calls.clone();
+    locustdb.register_function("tally", Signature::new(vec![ValueType::Integer], ValueType::Integer), move |args| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        args[0].clone()
+    });
+    let calls = || calls.load(std::sync::atomic::Ordering::SeqCst);
+
+    let query = "SELECT tally(id) FROM items WHERE id < 2 ORDER BY id;";
+    let run = || block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run(), vec![vec![Int(0)], vec![Int(1)]]);
+    let computed = calls();
+    assert!(computed > 0);
+    assert_eq!(run(), vec![vec![Int(0)], vec![Int(1)]]);
+    assert_eq!(calls(), computed);
+
+    let uncached = block_on(locustdb.run_query_uncached(query, false, vec![])).unwrap().0.unwrap();
+    assert_eq!(uncached.rows, vec![vec![Int(0)], vec![Int(1)]]);
+    assert!(calls() > computed);
+
+    let computed = calls();
+    writer.write(vec![("id".to_string(), Int(-1))]).unwrap();
+    writer.flush();
+    assert_eq!(run(), vec![vec![Int(-1)], vec![Int(0)], vec![Int(1)]]);
+    assert!(calls() > computed);
+}
+
+#[test]
+fn test_subresult_cache() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(10)
+        .subresult_cache_bytes(1 << 20)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..30).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    // Counts evaluations to tell cached partitions apart from aggregated ones
+    let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = calls.clone();
+    locustdb.register_function("tally", Signature::new(vec![ValueType::Integer], ValueType::Integer), move |args| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        args[0].clone()
+    });
+    let calls = || calls.load(std::sync::atomic::Ordering::SeqCst);
+
+    let run = || block_on(locustdb.run_query("SELECT sum(tally(id)) FROM items;", false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run(), vec![vec![Int(435)]]);
+    let computed = calls();
+    assert!(computed > 0);
+    assert_eq!(run(), vec![vec![Int(435)]]);
+    assert_eq!(calls(), computed);
+
+    writer.write_all((30..40).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    assert_eq!(run(), vec![vec![Int(780)]]);
+    assert!(calls() > computed && calls() < 2 * computed);
+
+    let computed = calls();
+    assert_eq!(block_on(locustdb.run_query("DELETE FROM items WHERE id = 5;", false, vec![])).unwrap().0.unwrap().rows,
+               vec![vec![Int(1)]]);
+    assert_eq!(run(), vec![vec![Int(775)]]);
+    assert!(calls() > computed);
+}
+
+#[test]
+fn test_order_by_limit_merge() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(4)
+        .partition_size_rows(10)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..1000).map(|i| {
+        let score = (i * 7919) % 1000;
+        vec![("id".to_string(), Int(i)),
+             ("score".to_string(), Int(score)),
+             ("name".to_string(), Str(&format!("item{}", score)))]
+    })).unwrap();
+    writer.flush();
+
+    let result = block_on(locustdb.run_query("SELECT id, name FROM items ORDER BY score DESC LIMIT 5;", false, vec![]))
+        .unwrap().0.unwrap();
+    let mut expected = (0..1000).map(|i| ((i * 7919) % 1000, i)).collect::<Vec<_>>();
+    expected.sort_by(|a, b| b.cmp(a));
+    let expected = expected.into_iter()
+        .take(5)
+        .map(|(score, id)| vec![Int(id), Str(&format!("item{}", score))])
+        .collect::<Vec<_>>();
+    assert_eq!(result.rows, expected);
+}
+
+#[test]
+fn test_parallel_merge() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(8)
+        .partition_size_rows(10)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..5000).map(|i| vec![("id".to_string(), Int(i)), ("bucket".to_string(), Int(i % 7))])).unwrap();
+    writer.flush();
+
+    let result = block_on(locustdb.run_query("SELECT bucket, count(0), sum(id) FROM items;", false, vec![]))
+        .unwrap().0.unwrap();
+    let mut rows = result.rows;
+    rows.sort();
+    let expected = (0..7)
+        .map(|b| {
+            let ids = (0..5000).filter(|i| i % 7 == b).collect::<Vec<i64>>();
+            vec![Int(b), Int(ids.len() as i64), Int(ids.iter().sum())]
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rows, expected);
+}
+
+#[test]
+fn test_common_subexpression_elimination() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    // Counts evaluations to determine how often the shared expression is computed
+    let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = calls.clone();
+    locustdb.register_function("tally", Signature::new(vec![ValueType::Integer], ValueType::Integer), move |args| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        args[0].clone()
+    });
+
+    let result = block_on(locustdb.run_query(
+        "SELECT tally(id) / 10, count(0) FROM items WHERE tally(id) / 10 > 7;", false, vec![]))
+        .unwrap().0.unwrap();
+    let mut rows = result.rows;
+    rows.sort();
+    assert_eq!(rows, vec![vec![Int(8), Int(10)], vec![Int(9), Int(10)]]);
+    // Grouping reuses the values computed for the filter
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 100);
+}
+
+#[test]
+fn test_constant_filters() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..10).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    let query = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+
+    assert_eq!(query("SELECT id FROM items WHERE 2 * 3 = 7;"), Vec::<Vec<Value>>::new());
+    assert_eq!(query("SELECT id % 2, count(0) FROM items WHERE 1 > 2;"), Vec::<Vec<Value>>::new());
+    assert_eq!(query("SELECT id FROM items WHERE 10 / 5 = 2 AND id < 3;"), vec![vec![Int(0)], vec![Int(1)], vec![Int(2)]]);
+    assert_eq!(query("SELECT id FROM items WHERE id < 3 OR 'a' = 'a';").len(), 10);
+    assert_eq!(query("SELECT id FROM items WHERE id > 7 OR 1 < 0;"), vec![vec![Int(8)], vec![Int(9)]]);
+}
+
+#[test]
+fn test_adaptive_grouping() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(10_000)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("events");
+    writer.write_all((0..10_000).map(|i| vec![("key".to_string(), Int(i)), ("value".to_string(), Int(i % 3))])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, true, vec![])).unwrap().0.unwrap();
+
+    // Most keys occur, so grouping directly by key is cheaper
+    let output = run("SELECT key, sum(value) FROM events WHERE value < 2;");
+    assert_eq!(output.rows.len(), 6667);
+    assert!(output.query_plans.keys().all(|plan| !plan.contains("hashmap_grouping")), "{:?}", output.query_plans);
+
+    // The filter only selects a small fraction of keys
+    let output = run("SELECT key, sum(value) FROM events WHERE key < 10 OR key >= 9990;");
+    let mut rows = output.rows;
+    rows.sort();
+    let expected = (0..10).chain(9990..10_000).map(|i| vec![Int(i), Int(i % 3)]).collect::<Vec<_>>();
+    assert_eq!(rows, expected);
+    assert!(output.query_plans.keys().any(|plan| plan.contains("hashmap_grouping")), "{:?}", output.query_plans);
+}
+
+#[test]
+fn test_radix_grouping() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("events");
+    writer.write_all((0..150_000).map(|i| vec![("key".to_string(), Int(i % 75_000 * 1000)), ("i".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, true, vec![])).unwrap().0.unwrap();
+
+    let output = run("SELECT key, count(0), sum(i) FROM events ORDER BY key DESC LIMIT 2;");
+    assert_eq!(output.rows, vec![
+        vec![Int(74_999_000), Int(2), Int(2 * 74_999 + 75_000)],
+        vec![Int(74_998_000), Int(2), Int(2 * 74_998 + 75_000)],
+    ]);
+    assert!(output.query_plans.keys().any(|plan| plan.contains("radix_hashmap_grouping")), "{:?}", output.query_plans);
+    let output = run("SELECT key, count(0), sum(i) FROM events ORDER BY key LIMIT 2;");
+    assert_eq!(output.rows, vec![vec![Int(0), Int(2), Int(75_000)], vec![Int(1000), Int(2), Int(75_002)]]);
+}
+
+#[test]
+fn test_pagination() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(100)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("events");
+    writer.write_all((0..1000).map(|i| vec![("id".to_string(), Int(i)), ("g".to_string(), Str(&format!("g{}", i % 7)))])).unwrap();
+    writer.flush();
+
+    let query = "SELECT g, id FROM events ORDER BY g DESC, id LIMIT 150;";
+    let mut paged = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = block_on(locustdb.run_page(query, cursor.as_ref().map(String::as_str))).unwrap().unwrap();
+        assert!(page.output.rows.len() <= 150);
+        paged.extend(page.output.rows);
+        cursor = page.cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    let all = block_on(locustdb.run_query("SELECT g, id FROM events ORDER BY g DESC, id LIMIT 1000;", false, vec![]))
+        .unwrap().0.unwrap().rows;
+    assert_eq!(paged.len(), 1000);
+    assert_eq!(paged, all);
+
+    assert!(block_on(locustdb.run_page("SELECT id FROM events ORDER BY g LIMIT 10;", None)).unwrap().is_err());
+    assert!(block_on(locustdb.run_page(query, Some("invalid"))).unwrap().is_err());
+
+    let single = LocustDB::builder().threads(0).build().unwrap();
+    let writer = single.table_writer("events");
+    writer.write_all((0..1000).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    let output = block_on(single.run_query("SELECT id FROM events ORDER BY id DESC LIMIT 3 OFFSET 5;", false, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![vec![Int(994)], vec![Int(993)], vec![Int(992)]]);
+    let output = block_on(single.run_query("SELECT id FROM events ORDER BY id LIMIT 3 OFFSET 998;", false, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![vec![Int(998)], vec![Int(999)]]);
+}
+
+#[test]
+fn test_predicate_reordering() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(10_000)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("events");
+    writer.write_all((0..10_000).map(|i| {
+        let mut row = vec![("id".to_string(), Int(i)), ("val".to_string(), Int(i % 10))];
+        if i % 2 == 0 {
+            row.push(("opt".to_string(), Int(i)));
+        }
+        row
+    })).unwrap();
+    writer.flush();
+    let run = |query: &str| {
+        let mut rows = block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+        rows.sort();
+        rows
+    };
+
+    assert_eq!(run("SELECT count(0) FROM events WHERE val = 3 AND id < 100;"), vec![vec![Int(10)]]);
+    assert_eq!(run("SELECT count(0) FROM events WHERE val < 5 AND opt >= 9000 AND id < 9500;"), vec![vec![Int(150)]]);
+    assert_eq!(run("SELECT id FROM events WHERE id > 9990 AND val = 5;"), vec![vec![Int(9995)]]);
+    assert_eq!(run("SELECT val, count(0) FROM events WHERE id < 30 AND val < 2;"),
+               vec![vec![Int(0), Int(3)], vec![Int(1), Int(3)]]);
+    let output = block_on(locustdb.run_query(
+        "SELECT id FROM events WHERE val = 7 AND id < 50 ORDER BY id DESC LIMIT 2;", false, vec![])).unwrap().0.unwrap();
+    assert_eq!(output.rows, vec![vec![Int(47)], vec![Int(37)]]);
+}
+
+#[test]
+fn test_batch_size() {
+    let _ = env_logger::try_init();
+    let queries = [
+        "SELECT id, name FROM events WHERE id % 7 = 3 ORDER BY id DESC LIMIT 5;",
+        "SELECT name, count(0), sum(id) FROM events WHERE id > 100;",
+        "SELECT count(0) FROM events WHERE name = \"b\";",
+    ];
+    let mut results = Vec::new();
+    for &batch_size in &[1, 7, 1 << 16] {
+        let locustdb = LocustDB::builder()
+            .threads(0)
+            .batch_size_rows(batch_size)
+            .build()
+            .unwrap();
+        let writer = locustdb.table_writer("events");
+        writer.write_all((0..5000).map(|i| vec![
+            ("id".to_string(), Int(i)),
+            ("name".to_string(), Str(["a", "b", "c"][i as usize % 3])),
+        ])).unwrap();
+        writer.flush();
+        let rows = queries.iter()
+            .map(|query| {
+                let mut rows = block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+                rows.sort();
+                rows
+            })
+            .collect::<Vec<_>>();
+        results.push(rows);
+    }
+    assert_eq!(results[0][2], vec![vec![Int(1667)]]);
+    assert_eq!(results[0], results[1]);
+    assert_eq!(results[0], results[2]);
+    assert!(LocustDB::builder().batch_size_rows(0).build().is_err());
+}
+
+#[test]
+fn test_query_priorities() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(2)
+        .partition_size_rows(10)
+        .max_concurrent_queries(2)
+        .bulk_query_threshold(std::time::Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..500).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    locustdb.register_function("slow", Signature::new(vec![ValueType::Integer], ValueType::Integer), |args| {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        args[0].clone()
+    });
+
+    // Only one further query runs at a time, and it preempts the slow query once that has become a bulk query
+    let bulk = locustdb.run_query("SELECT sum(slow(id)) FROM items;", false, vec![]);
+    let queries = (0..4)
+        .map(|i| locustdb.run_query(&format!("SELECT count(0) FROM items WHERE id < {};", (i + 1) * 100), false, vec![]))
+        .collect::<Vec<_>>();
+    for (i, query) in queries.into_iter().enumerate() {
+        assert_eq!(block_on(query).unwrap().0.unwrap().rows, vec![vec![Int((i as i64 + 1) * 100)]]);
+    }
+    assert_eq!(block_on(bulk).unwrap().0.unwrap().rows, vec![vec![Int(124_750)]]);
+    assert!(LocustDB::builder().max_concurrent_queries(0).build().is_err());
+}
+
+#[test]
+fn test_pinned_threads() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(4)
+        .pin_threads(true)
+        .partition_size_rows(10)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..1000).map(|i| vec![("id".to_string(), Int(i)), ("bucket".to_string(), Int(i % 3))])).unwrap();
+    writer.flush();
+    let result = block_on(locustdb.run_query("SELECT bucket, count(0) FROM items;", false, vec![]))
+        .unwrap().0.unwrap();
+    let mut rows = result.rows;
+    rows.sort();
+    assert_eq!(rows, vec![vec![Int(0), Int(334)], vec![Int(1), Int(333)], vec![Int(2), Int(333)]]);
+}
+
+#[test]
+fn test_plan_graph() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("tier".to_string(), Str(["bronze", "gold"][i as usize % 2])),
+    ])).unwrap();
+    writer.flush();
+
+    let output = block_on(locustdb.run_query("SELECT tier, count(0) FROM items WHERE id < 50;", true, vec![]))
+        .unwrap().0.unwrap();
+    assert_eq!(output.plan_graphs.len(), output.query_plans.len());
+    let plan = &output.plan_graphs[0];
+    assert!(output.query_plans.contains_key(&format!("{}", plan)));
+    let operators = plan.operators().collect::<Vec<_>>();
+    assert!(!operators.is_empty());
+    for edge in plan.edges() {
+        assert!(plan.buffers.iter().any(|b| b.id == edge.buffer));
+        assert!(operators.iter().any(|op| op.id == edge.to && op.inputs.contains(&edge.buffer)));
+    }
+    assert!(plan.to_json().starts_with("{\"stages\":["));
+    assert!(plan.to_dot().starts_with("digraph plan {"));
+}
+
+#[test]
+fn test_load_from_url() {
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    let _ = env_logger::try_init();
+    // Serves test_data/tiny.csv and answers requests for byte ranges
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let data = fs::read("test_data/tiny.csv").unwrap();
+    thread::spawn(move || for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let mut request = vec![];
+        for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+            let line = line.unwrap();
+            if line.is_empty() { break; }
+            request.push(line);
+        }
+        let range = request.iter()
+            .find(|line| line.to_lowercase().starts_with("range: bytes="))
+            .map(|line| {
+                let bounds = line["range: bytes=".len()..].split('-').map(|b| b.parse::<usize>().unwrap()).collect::<Vec<_>>();
+                (bounds[0], bounds[1] + 1)
+            });
+        let body = match range {
+            Some((start, end)) => &data[start..end],
+            None => &data[..],
+        };
+        write!(stream, "HTTP/1.0 {}\r\nContent-Length: {}\r\n\r\n", if range.is_some() { "206 Partial Content" } else { "200 OK" }, body.len()).unwrap();
+        if request[0].starts_with("GET") {
+            stream.write_all(body).unwrap();
+        }
+    });
+
+    let locustdb = LocustDB::memory_only();
+    let url = format!("http://{}/tiny.csv", addr);
+    block_on(locustdb.load_csv(LoadOptions::new(&url, "remote").with_partition_size(40))).unwrap().unwrap();
+    let _ = block_on(locustdb.load_csv(LoadOptions::new("test_data/tiny.csv", "local").with_partition_size(40)));
+    let count = |table: &str| block_on(locustdb.run_query(&format!("SELECT COUNT(0), SUM(num) FROM {};", table), false, vec![]))
+        .unwrap().0.unwrap().rows;
+    assert_eq!(count("remote"), count("local"));
+    let missing = format!("http://{}/missing.csv", "127.0.0.1:1");
+    assert!(block_on(locustdb.load_csv(LoadOptions::new(&missing, "missing"))).unwrap().is_err());
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_http_server() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use locustdb::server::Server;
+    use tempdir::TempDir;
+
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("http_export").unwrap();
+    let locustdb = Arc::new(LocustDB::builder()
+        .threads(0)
+        .export_directory(tmp_dir.path().to_str().unwrap())
+        .build()
+        .unwrap());
+    let read_only = locustdb::server::serve(locustdb.clone(), "127.0.0.1:0").unwrap();
+    let server = Server::bind(locustdb, "127.0.0.1:0").unwrap().threads(2).allow_writes(true);
+    let addr = server.addr();
+    server.spawn();
+    let post_to = |addr: std::net::SocketAddr, path: &str, body: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+               path, body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let post = |path: &str, body: &str| post_to(addr, path, body);
+
+    let response = post("/ingest?table=events", "{\"id\": 1, \"kind\": \"a\"}\n{\"id\": 2, \"kind\": \"b\"}\n");
+    assert!(response.ends_with("{\"inserted\":2}"), "{}", response);
+    let response = post("/query", "SELECT kind FROM events WHERE id = 2;");
+    assert!(response.contains("\"rows\":[[\"b\"]]"), "{}", response);
+    let response = post("/query", "SELECT kind FROM missing;");
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    // Clients can't write files unless the server allows it
+    let response = post("/query", "COPY (SELECT id FROM events) TO 'events.csv';");
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    assert!(!tmp_dir.path().join("events.csv").exists());
+
+    // Servers are read-only by default
+    let response = post_to(read_only, "/query", "SELECT kind FROM events WHERE id = 2;");
+    assert!(response.contains("\"rows\":[[\"b\"]]"), "{}", response);
+    for &(path, body) in &[("/query", "DELETE FROM events WHERE id = 1;"),
+                           ("/query", "DROP TABLE events;"),
+                           ("/ingest?table=events", "{\"id\": 3}\n")] {
+        let response = post_to(read_only, path, body);
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+    }
+    let response = post("/query", "SELECT COUNT(0) FROM events;");
+    assert!(response.contains("\"rows\":[[2]]"), "{}", response);
+}
+
+#[cfg(feature = "cluster")]
+#[test]
+fn test_cluster() {
+    use std::sync::Arc;
+    use locustdb::cluster::Cluster;
+
+    let _ = env_logger::try_init();
+    let kinds = ["a", "b", "c"];
+    let row = |i: i64| vec![("id".to_string(), Int(i)), ("kind".to_string(), Str(kinds[i as usize % 3]))];
+    let single = LocustDB::builder().threads(0).build().unwrap();
+    single.table_writer("events").write_all((0..100).map(row)).unwrap();
+    single.flush();
+    let mut nodes = vec![];
+    for shard in 0..2 {
+        let locustdb = Arc::new(LocustDB::builder().threads(0).build().unwrap());
+        let writer = locustdb.table_writer("events");
+        writer.write_all((0..100).filter(|i| i % 2 == shard).map(row)).unwrap();
+        writer.flush();
+        nodes.push(locustdb::server::serve(locustdb, "127.0.0.1:0").unwrap().to_string());
+    }
+    let cluster = Cluster::new(nodes);
+
+    for query in &["SELECT kind, COUNT(0), SUM(id) FROM events ORDER BY kind;",
+                   "SELECT kind, SUM(id) FROM events ORDER BY SUM(id) DESC LIMIT 2;",
+                   "SELECT id FROM events WHERE id > 90 ORDER BY id DESC LIMIT 3;",
+                   "SELECT DISTINCT kind FROM events ORDER BY kind;",
+                   "SELECT kind, MIN(id), MAX(id) FROM events ORDER BY MAX(id) LIMIT 2;"] {
+        let expected = block_on(single.run_query(query, false, vec![])).unwrap().0.unwrap();
+        let result = cluster.run_query(query).unwrap();
+        assert_eq!(result.colnames, expected.colnames, "{}", query);
+        assert_eq!(result.rows, expected.rows, "{}", query);
+        assert_eq!(result.stats.rows_scanned, expected.stats.rows_scanned, "{}", query);
+    }
+    assert!(cluster.run_query("SELECT SUM(id) / COUNT(0) FROM events;").is_err());
+    assert!(cluster.run_query("SELECT AVG(id) FROM events;").is_err());
+    assert!(cluster.run_query("SELECT kind FROM events ORDER BY id LIMIT 3;").is_err());
+    assert!(cluster.run_query("SELECT kind, SUM(id) FROM events ORDER BY COUNT(0) LIMIT 1;").is_err());
+}
+
+#[cfg(feature = "replication")]
+#[test]
+fn test_replication() {
+    use std::sync::Arc;
+    use locustdb::replication::Follower;
+
+    let _ = env_logger::try_init();
+    let count = |locustdb: &LocustDB| {
+        let query = "SELECT COUNT(0), SUM(id) FROM events;";
+        block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows
+    };
+    let leader = Arc::new(LocustDB::builder().threads(0).build().unwrap());
+    let writer = leader.table_writer("events");
+    writer.write_all((0..100).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    let addr = locustdb::server::serve(leader.clone(), "127.0.0.1:0").unwrap();
+    let replica = Arc::new(LocustDB::builder().threads(0).build().unwrap());
+    let mut follower = Follower::new(&addr.to_string(), replica.clone());
+
+    assert_eq!(follower.sync().unwrap(), 1);
+    assert_eq!(count(&replica), count(&leader));
+    assert_eq!(follower.sync().unwrap(), 0);
+
+    block_on(leader.run_query("DELETE FROM events WHERE id < 10;", false, vec![])).unwrap().0.unwrap();
+    writer.write_all((100..150).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    assert_eq!(follower.sync().unwrap(), 2);
+    assert_eq!(count(&replica), vec![vec![Int(140), Int(11130)]]);
+
+    // Merged partitions replace the partitions they were merged from
+    assert!(leader.merge_small_partitions() > 0);
+    follower.sync().unwrap();
+    assert_eq!(count(&replica), count(&leader));
+    let batches = |locustdb: &LocustDB| {
+        block_on(locustdb.table_stats()).unwrap().into_iter().find(|stats| stats.name == "events").unwrap().batches
+    };
+    assert_eq!(batches(&replica), batches(&leader));
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+fn test_postgres_wire_protocol() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    fn read_until_ready(stream: &mut TcpStream) -> Vec<(u8, Vec<u8>)> {
+        let mut messages = Vec::new();
+        loop {
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).unwrap();
+            let len = ((header[1] as usize) << 24) | ((header[2] as usize) << 16) | ((header[3] as usize) << 8) | header[4] as usize;
+            let mut body = vec![0; len - 4];
+            stream.read_exact(&mut body).unwrap();
+            messages.push((header[0], body));
+            if header[0] == b'Z' {
+                return messages;
+            }
+        }
+    }
+
+    let _ = env_logger::try_init();
+    let locustdb = Arc::new(LocustDB::builder().threads(0).build().unwrap());
+    let writer = locustdb.table_writer("events");
+    writer.write_all((0..3).map(|i| vec![("id".to_string(), Int(i)), ("kind".to_string(), Str("a"))])).unwrap();
+    writer.flush();
+    let addr = locustdb::postgres::serve(locustdb, "127.0.0.1:0").unwrap();
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut startup = vec![0, 0, 0, 0, 0, 3, 0, 0];
+    startup.extend_from_slice(b"user\0test\0\0");
+    let len = startup.len() as u8;
+    startup[3] = len;
+    stream.write_all(&startup).unwrap();
+    let messages = read_until_ready(&mut stream);
+    assert_eq!(messages[0], (b'R', vec![0, 0, 0, 0]));
+
+    let query = b"SELECT id, kind FROM events WHERE id = 2;\0";
+    stream.write_all(&[b'Q', 0, 0, 0, query.len() as u8 + 4]).unwrap();
+    stream.write_all(query).unwrap();
+    let messages = read_until_ready(&mut stream);
+    let tags = messages.iter().map(|m| m.0).collect::<Vec<_>>();
+    assert_eq!(tags, vec![b'T', b'D', b'C', b'Z']);
+    assert_eq!(messages[1].1, vec![0, 2, 0, 0, 0, 1, b'2', 0, 0, 0, 1, b'a']);
+    assert_eq!(messages[2].1, b"SELECT 1\0".to_vec());
+
+    // Column types don't depend on the rows of the result
+    let query = b"SELECT id FROM events WHERE id > 100;\0";
+    stream.write_all(&[b'Q', 0, 0, 0, query.len() as u8 + 4]).unwrap();
+    stream.write_all(query).unwrap();
+    let messages = read_until_ready(&mut stream);
+    assert_eq!(messages[0].0, b'T');
+    assert_eq!(messages[0].1[11..15].to_vec(), vec![0, 0, 0, 20]);
+
+    let query = b"SELECT id FROM missing;\0";
+    stream.write_all(&[b'Q', 0, 0, 0, query.len() as u8 + 4]).unwrap();
+    stream.write_all(query).unwrap();
+    let tags = read_until_ready(&mut stream).iter().map(|m| m.0).collect::<Vec<_>>();
+    assert_eq!(tags, vec![b'E', b'Z']);
+
+    // Messages that exceed the maximum length close the connection
+    stream.write_all(&[b'Q', 0x7f, 0xff, 0xff, 0xff]).unwrap();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[cfg(feature = "enable_zstd")]
+#[test]
+fn test_block_compression() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(0)
+        .partition_size_rows(1000)
+        .build()
+        .unwrap();
+    locustdb.set_block_compression("archive", BlockCompression::Zstd).unwrap();
+    let writer = locustdb.table_writer("archive");
+    writer.write_all((0..1000).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("value".to_string(), Int((i % 7) * 1000)),
+    ])).unwrap();
+    writer.flush();
+    let tables = block_on(locustdb.mem_tree(2)).unwrap();
+    let table = tables.iter().find(|table| table.name == "archive").unwrap();
+    assert!(table.columns["value"].encodings.keys().any(|codec| codec.contains("Zstd")));
+
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+    assert_eq!(run("SELECT count(0), sum(value) FROM archive WHERE value = 3000;"), vec![vec![Int(143), Int(429000)]]);
+    assert_eq!(run("SELECT value FROM archive WHERE id = 10;"), vec![vec![Int(3000)]]);
+}
+
+#[test]
+fn test_catalog_restart() {
+    use std::time::Duration;
+    use tempdir::TempDir;
+    let _ = env_logger::try_init();
+    let tmp_dir = TempDir::new("catalog").unwrap();
+    let path = tmp_dir.path().join("catalog");
+    {
+        let locustdb = LocustDB::builder().threads(0).catalog_path(path.to_str().unwrap()).build().unwrap();
+        let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0.unwrap().rows;
+        run("CREATE TABLE events (ts BIGINT NOT NULL, name VARCHAR);");
+        run("CREATE TABLE empty (id BIGINT);");
+        run("ALTER TABLE events ADD COLUMN hour AS ts / 3600;");
+        locustdb.set_retention("events", "ts", Duration::from_secs(86400)).unwrap();
+        run("INSERT INTO events (ts, name) VALUES (3600, 'a');");
+        run("CREATE VIEW late AS SELECT ts, hour AS h FROM events\nWHERE ts > 3600;");
+        locustdb.flush();
+
+        let catalog = Catalog::read(&path).unwrap();
+        let events = &catalog.tables["events"];
+        assert_eq!(events.retention, Some(("ts".to_string(), 86400)));
+        assert_eq!(events.generated, vec![GeneratedEntry { name: "hour".to_string(), sql: "ts / 3600".to_string(), stored: false }]);
+        assert_eq!(events.partitions.len(), 1);
+        assert_eq!(events.partitions[0].len, 1);
+        assert!(catalog.tables["empty"].partitions.is_empty());
+        assert_eq!(catalog.views["late"], "SELECT ts, hour AS h FROM events WHERE ts > 3600");
+    }
+
+    let locustdb = LocustDB::builder().threads(0).catalog_path(path.to_str().unwrap()).build().unwrap();
+    let run = |statement: &str| block_on(locustdb.run_query(statement, false, vec![])).unwrap().0.unwrap().rows;
+    let tables = run("SHOW TABLES;");
+    assert!(tables.contains(&vec![Str("empty")]) && tables.contains(&vec![Str("events")]));
+    assert!(run("SELECT id FROM empty;").is_empty());
+    // Values are converted to the declared column types
+    run("INSERT INTO events (ts) VALUES ('7200');");
+    assert_eq!(run("SELECT hour FROM events;"), vec![vec![Int(2)]]);
+    assert_eq!(run("SELECT h FROM late WHERE ts < 10000;"), vec![vec![Int(2)]]);
+    run("DROP VIEW late;");
+    assert!(block_on(locustdb.run_query("SELECT h FROM late;", false, vec![])).unwrap().0.is_err());
+    assert!(Catalog::read(&path).unwrap().views.is_empty());
+}
+
+#[test]
+fn test_deterministic() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder().threads(4).partition_size_rows(10).deterministic(true).build().unwrap();
+    let writer = locustdb.table_writer("items");
+    let names = ["zeta", "beta", "alpha"];
+    writer.write_all((0..100).map(|i| vec![
+        ("id".to_string(), Int(i)),
+        ("g".to_string(), Int(i % 3)),
+        ("name".to_string(), Str(names[i as usize % 3])),
+    ])).unwrap();
+    writer.flush();
+    let run = |query: &str| block_on(locustdb.run_query(query, false, vec![])).unwrap().0.unwrap().rows;
+
+    // Ties keep the order in which rows were inserted
+    let expected = (0..20).map(|i| vec![Int(3 * i)]).collect::<Vec<_>>();
+    for _ in 0..5 {
+        assert_eq!(run("SELECT id FROM items ORDER BY g LIMIT 20;"), expected);
+    }
+    // Groups are ordered by their values
+    assert_eq!(run("SELECT name, count(0) FROM items LIMIT 2;"),
+               vec![vec![Str("alpha"), Int(33)], vec![Str("beta"), Int(33)]]);
+    assert_eq!(run("SELECT g, count(0) FROM items ORDER BY count(0) LIMIT 2;"),
+               vec![vec![Int(1), Int(33)], vec![Int(2), Int(33)]]);
+}
+
+#[test]
+fn test_deterministic_under_load() {
+    let _ = env_logger::try_init();
+    let locustdb = LocustDB::builder()
+        .threads(2)
+        .partition_size_rows(10)
+        .bulk_query_threshold(std::time::Duration::from_millis(0))
+        .deterministic(true)
+        .build()
+        .unwrap();
+    let writer = locustdb.table_writer("items");
+    writer.write_all((0..500).map(|i| vec![("id".to_string(), Int(i))])).unwrap();
+    writer.flush();
+    locustdb.register_function("slow", Signature::new(vec![ValueType::Integer], ValueType::Integer), |args| {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        args[0].clone()
+    });
+
+    // The slow query is never preempted by the queries started after it and completes
+    let slow = locustdb.run_query("SELECT sum(slow(id)) FROM items;", false, vec![]);
+    let queries = (0..8)
+        .map(|i| locustdb.run_query(&format!("SELECT count(0) FROM items WHERE id < {};", (i + 1) * 50), false, vec![]))
+        .collect::<Vec<_>>();
+    for (i, query) in queries.into_iter().enumerate() {
+        assert_eq!(block_on(query).unwrap().0.unwrap().rows, vec![vec![Int((i as i64 + 1) * 50)]]);
+    }
+    assert_eq!(block_on(slow).unwrap().0.unwrap().rows, vec![vec![Int(124_750)]]);
+}